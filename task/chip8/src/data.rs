@@ -72,7 +72,7 @@ impl Address {
         Self(value % Self::DOMAIN_SIZE as u16)
     }
 
-    pub fn as_usize(self) -> usize {
+    pub const fn as_usize(self) -> usize {
         self.0 as usize
     }
 }
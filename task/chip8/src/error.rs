@@ -16,6 +16,14 @@ pub enum Error {
     InvalidKey(Word),
     #[error("invalid sprite: address {0}, size {1}")]
     InvalidSprite(Address, Nibble),
+    #[error("failed to access flag storage")]
+    FlagStorage,
+    #[error("failed to render frame")]
+    Render,
+    #[error("memory access out of bounds: {0:#06x}")]
+    OutOfBounds(usize),
+    #[error("can't fetch an instruction from memory-mapped I/O at {0}")]
+    MmioFetch(Address),
     #[error("the interpreter has crashed and is now unrecoverable")]
     Crashed,
 }
@@ -0,0 +1,115 @@
+use crate::{data::Word, error::Result};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Amount of RPL user flags available to `Fx75` and `Fx85`.
+pub const FLAGS_AMOUNT: usize = 8;
+
+/// Persistent storage of RPL user flags.
+///
+/// The store outlives the interpreter, so flags saved by one program can be loaded
+/// by another one, as on the HP-48 calculators SCHIP originates from.
+pub trait FlagStore {
+    fn load(&self) -> Result<[Word; FLAGS_AMOUNT]>;
+
+    /// Overwrites the first `flags.len()` flags, keeping the rest intact.
+    fn save(&mut self, flags: &[Word]) -> Result<()>;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InMemoryFlagStore {
+    flags: [Word; FLAGS_AMOUNT],
+}
+
+impl InMemoryFlagStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FlagStore for InMemoryFlagStore {
+    fn load(&self) -> Result<[Word; FLAGS_AMOUNT]> {
+        Ok(self.flags)
+    }
+
+    fn save(&mut self, flags: &[Word]) -> Result<()> {
+        self.flags[..flags.len()].copy_from_slice(flags);
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "std")]
+pub use file::FileFlagStore;
+
+#[cfg(feature = "std")]
+mod file {
+    use super::{FlagStore, FLAGS_AMOUNT};
+    use crate::{data::Word, error::Result, Error};
+
+    use std::{
+        boxed::Box,
+        fs,
+        io::ErrorKind,
+        path::{Path, PathBuf},
+    };
+
+    type WarningHook = dyn Fn(&Path) + Send;
+
+    /// Stores flags in a binary file of exactly `FLAGS_AMOUNT` bytes.
+    ///
+    /// A missing file loads as zeros. A file of a wrong size is considered corrupted:
+    /// it loads as zeros too, but the warning hook is called first.
+    pub struct FileFlagStore {
+        path: PathBuf,
+        on_corrupted: Option<Box<WarningHook>>,
+    }
+
+    impl FileFlagStore {
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            Self {
+                path: path.into(),
+                on_corrupted: None,
+            }
+        }
+
+        pub fn with_warning_hook(mut self, hook: impl Fn(&Path) + Send + 'static) -> Self {
+            self.on_corrupted = Some(Box::new(hook));
+            self
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl FlagStore for FileFlagStore {
+        fn load(&self) -> Result<[Word; FLAGS_AMOUNT]> {
+            let data = match fs::read(&self.path) {
+                Ok(data) => data,
+                Err(error) if error.kind() == ErrorKind::NotFound => return Ok([0; FLAGS_AMOUNT]),
+                Err(_) => return Err(Error::FlagStorage),
+            };
+
+            match <[Word; FLAGS_AMOUNT]>::try_from(data.as_slice()) {
+                Ok(flags) => Ok(flags),
+                Err(_) => {
+                    if let Some(hook) = &self.on_corrupted {
+                        hook(&self.path);
+                    }
+                    Ok([0; FLAGS_AMOUNT])
+                }
+            }
+        }
+
+        fn save(&mut self, flags: &[Word]) -> Result<()> {
+            let mut stored = self.load()?;
+            stored[..flags.len()].copy_from_slice(flags);
+
+            fs::write(&self.path, stored).map_err(|_| Error::FlagStorage)
+        }
+    }
+}
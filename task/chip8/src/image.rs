@@ -26,7 +26,8 @@ impl<T: AsRef<[u8]>> Ch8Image<T> {
     const BASE_ADDRESS: Address = Address::new(0x200);
 
     pub fn new(data: T) -> Result<Self, Ch8ImageError> {
-        if data.as_ref().len() > Address::DOMAIN_SIZE {
+        // The image is loaded at the base address, not at the start of memory.
+        if data.as_ref().len() > Address::DOMAIN_SIZE - Self::BASE_ADDRESS.as_usize() {
             return Err(Ch8ImageError::TooBig);
         }
 
@@ -1,10 +1,22 @@
 use crate::{
     data::{Address, Nibble, OpCode, RegisterIndex, Word},
+    flag_store::FLAGS_AMOUNT,
     image::Image,
+    mmio::MmioMap,
     platform::{Platform, Point, Sprite},
+    quirks::Quirks,
     Error, Offset, Result,
 };
 
+#[cfg(feature = "std")]
+use crate::mmio::{MmioError, MmioHandler};
+
+#[cfg(feature = "std")]
+use core::ops::Range;
+
+#[cfg(feature = "std")]
+use std::boxed::Box;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 pub const SCREEN_WIDTH: usize = 64;
@@ -44,13 +56,17 @@ pub struct Interpreter<P: Platform> {
     registers: Registers,
     memory: Memory,
     stack: Stack,
+    quirks: Quirks,
 }
 
 impl<P: Platform> Interpreter<P> {
-    pub fn new(image: impl Image, platform: P) -> Self {
+    pub fn new(image: impl Image, platform: P, quirks: Quirks) -> Self {
         let stack = Stack::default();
 
         let mut memory = Memory::default();
+        let font_start = FONT_ADDRESS.as_usize();
+        memory.locations[font_start..font_start + FONT_SPRITES.len()]
+            .copy_from_slice(&FONT_SPRITES);
         image.load_into_memory(&mut memory.locations);
 
         Self {
@@ -59,6 +75,7 @@ impl<P: Platform> Interpreter<P> {
             registers: Registers::new(),
             memory,
             stack,
+            quirks,
         }
     }
 
@@ -70,8 +87,48 @@ impl<P: Platform> Interpreter<P> {
         &mut self.platform
     }
 
+    pub fn into_platform(self) -> P {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Maps the addresses of `range` to the handler, see `MmioHandler`. The range must not
+    /// overlap with the ones registered before, nor cover `PROTECTED_RANGES`.
+    #[cfg(feature = "std")]
+    pub fn register_mmio(
+        &mut self,
+        range: Range<usize>,
+        handler: Box<dyn MmioHandler>,
+    ) -> core::result::Result<(), MmioError> {
+        self.memory.mmio.register(range, handler, false)
+    }
+
+    /// Same as `register_mmio`, but allows the range to cover `PROTECTED_RANGES`.
+    #[cfg(feature = "std")]
+    pub fn register_mmio_forced(
+        &mut self,
+        range: Range<usize>,
+        handler: Box<dyn MmioHandler>,
+    ) -> core::result::Result<(), MmioError> {
+        self.memory.mmio.register(range, handler, true)
+    }
+
+    pub(crate) fn map_platform<Q: Platform>(self, f: impl FnOnce(P) -> Q) -> Interpreter<Q> {
+        Interpreter {
+            platform: f(self.platform),
+            index_register: self.index_register,
+            registers: self.registers,
+            memory: self.memory,
+            stack: self.stack,
+            quirks: self.quirks,
+        }
+    }
+
     pub fn run_next_instruction(&mut self) -> Result<()> {
-        let opcode = self.memory.get_next_opcode();
+        let opcode = self.memory.get_next_opcode()?;
 
         let operation = Operation::try_from(opcode)?;
 
@@ -80,7 +137,7 @@ impl<P: Platform> Interpreter<P> {
             Operation::Jump(address) => self.jump(address),
             Operation::SetRegister(register_index, word) => self.set_register(register_index, word),
             Operation::SetIndexRegister(address) => self.index_register = address,
-            Operation::Draw(x, y, n) => self.draw(x, y, n),
+            Operation::Draw(x, y, n) => self.draw(x, y, n)?,
             Operation::AddValue(register_index, word) => self.add_value(register_index, word),
             Operation::SkipIfEqual(register_index, word) => {
                 self.skip_if_equal(register_index, word)
@@ -124,9 +181,11 @@ impl<P: Platform> Interpreter<P> {
             Operation::IncrementIndexRegister(register_index) => {
                 self.increment_index_register(register_index)
             }
-            Operation::ToDecimal(register_index) => self.execute_to_decimal(register_index),
-            Operation::WriteMemory(register_index) => self.write_memory(register_index),
-            Operation::ReadMemory(register_index) => self.read_memory(register_index),
+            Operation::ToDecimal(register_index) => self.execute_to_decimal(register_index)?,
+            Operation::WriteMemory(register_index) => self.write_memory(register_index)?,
+            Operation::ReadMemory(register_index) => self.read_memory(register_index)?,
+            Operation::SaveFlags(register_index) => self.save_flags(register_index)?,
+            Operation::LoadFlags(register_index) => self.load_flags(register_index)?,
             Operation::Return => self.return_()?,
             Operation::Call(address) => self.call(address)?,
             Operation::SkipIfKeyDown(register_index) => self.skip_if_key_down(register_index),
@@ -136,8 +195,12 @@ impl<P: Platform> Interpreter<P> {
             Operation::GetDelayTimer(register_index) => self.get_delay_timer(register_index),
             Operation::WaitForKey(register_index) => self.wait_for_key(register_index),
             Operation::JumpV0(address) => self.jump_v0(address),
-            Operation::SetToRandom(_, _) => todo!(),
-            Operation::SetIndexRegisterToSprite(_) => todo!(),
+            Operation::SetToRandom(register_index, word) => {
+                self.set_to_random(register_index, word)
+            }
+            Operation::SetIndexRegisterToSprite(register_index) => {
+                self.set_index_register_to_sprite(register_index)
+            }
         }
 
         Ok(())
@@ -152,9 +215,14 @@ impl<P: Platform> Interpreter<P> {
     }
 
     fn jump_v0(&mut self, address: Address) {
-        let v0_value = self.registers.get(Nibble(0));
+        let register_index = if self.quirks.jump_with_vx {
+            Nibble((address.as_usize() >> 8) as u8)
+        } else {
+            Nibble(0)
+        };
+        let offset = self.registers.get(register_index);
 
-        self.memory.instruction_pointer = address + v0_value.into();
+        self.memory.instruction_pointer = address + offset.into();
     }
 
     fn set_register(&mut self, register_index: Nibble, word: u8) {
@@ -168,16 +236,54 @@ impl<P: Platform> Interpreter<P> {
         )
     }
 
-    fn draw(&mut self, x: Nibble, y: Nibble, n: Nibble) {
+    fn set_to_random(&mut self, register_index: Nibble, mask: u8) {
+        self.registers
+            .set(register_index, self.platform.get_random_word() & mask)
+    }
+
+    fn draw(&mut self, x: Nibble, y: Nibble, n: Nibble) -> Result<()> {
         let point = Point {
             x: self.registers.get(x),
             y: self.registers.get(y),
         };
 
-        let sprite = Sprite::new(self.memory.get_slice(self.index_register, n.as_usize()));
+        let mut rows = [0; Nibble::DOMAIN_SIZE];
+        let rows = &mut rows[..n.as_usize()];
+        for (i, row) in rows.iter_mut().enumerate() {
+            *row = self.memory.read(self.index_register.as_usize() + i)?;
+        }
 
-        let had_pixels_flipped = self.platform.draw_sprite(point, sprite);
+        let had_pixels_flipped = if self.quirks.draw_wraps {
+            self.draw_wrapping(point, rows)
+        } else {
+            self.platform.draw_sprite(point, Sprite::new(rows))
+        };
         self.set_register_f(had_pixels_flipped);
+
+        Ok(())
+    }
+
+    /// Platforms clip sprites at the edges of the screen, so the sprite is drawn row by
+    /// row, and the pixels of a row past the right edge are drawn once more from the left.
+    fn draw_wrapping(&mut self, point: Point, rows: &[u8]) -> bool {
+        let x = point.x as usize % SCREEN_WIDTH;
+        let past_right_edge = (x + 8).saturating_sub(SCREEN_WIDTH);
+
+        let mut had_pixels_flipped = false;
+        for (i, row) in rows.iter().enumerate() {
+            let y = ((point.y as usize + i) % SCREEN_HEIGHT) as u8;
+
+            let start = Point { x: x as u8, y };
+            had_pixels_flipped |= self.platform.draw_sprite(start, Sprite::new(&[*row]));
+
+            if past_right_edge > 0 {
+                let wrapped_row = [*row << (8 - past_right_edge)];
+                let start = Point { x: 0, y };
+                had_pixels_flipped |= self.platform.draw_sprite(start, Sprite::new(&wrapped_row));
+            }
+        }
+
+        had_pixels_flipped
     }
 
     fn set_register_f(&mut self, value: bool) {
@@ -230,7 +336,7 @@ impl<P: Platform> Interpreter<P> {
             self.registers.get(register_index_first) | self.registers.get(register_index_second);
 
         self.registers.set(register_index_first, word);
-        self.set_register_f(false)
+        self.reset_register_f_on_logic()
     }
 
     fn and(&mut self, register_index_first: Nibble, register_index_second: Nibble) {
@@ -238,7 +344,7 @@ impl<P: Platform> Interpreter<P> {
             self.registers.get(register_index_first) & self.registers.get(register_index_second);
 
         self.registers.set(register_index_first, word);
-        self.set_register_f(false)
+        self.reset_register_f_on_logic()
     }
 
     fn xor(&mut self, register_index_first: Nibble, register_index_second: Nibble) {
@@ -246,7 +352,13 @@ impl<P: Platform> Interpreter<P> {
             self.registers.get(register_index_first) ^ self.registers.get(register_index_second);
 
         self.registers.set(register_index_first, word);
-        self.set_register_f(false)
+        self.reset_register_f_on_logic()
+    }
+
+    fn reset_register_f_on_logic(&mut self) {
+        if self.quirks.vf_reset_on_logic {
+            self.set_register_f(false)
+        }
     }
 
     fn add_register(&mut self, register_index_first: Nibble, register_index_second: Nibble) {
@@ -270,7 +382,9 @@ impl<P: Platform> Interpreter<P> {
     }
 
     fn shift_right(&mut self, register_index_first: Nibble, register_index_second: Nibble) {
-        let word = self.registers.get(register_index_second);
+        let word = self
+            .registers
+            .get(self.shift_source(register_index_first, register_index_second));
 
         let shifted_word = word >> 1;
         let is_shifted_out = (word & 0b1) != 0;
@@ -280,7 +394,9 @@ impl<P: Platform> Interpreter<P> {
     }
 
     fn shift_left(&mut self, register_index_first: Nibble, register_index_second: Nibble) {
-        let word = self.registers.get(register_index_second);
+        let word = self
+            .registers
+            .get(self.shift_source(register_index_first, register_index_second));
 
         let shifted_word = word << 1;
         let is_shifted_out = (word & 0b10000000) != 0;
@@ -289,6 +405,14 @@ impl<P: Platform> Interpreter<P> {
         self.set_register_f(is_shifted_out);
     }
 
+    fn shift_source(&self, register_index_first: Nibble, register_index_second: Nibble) -> Nibble {
+        if self.quirks.shift_uses_vy {
+            register_index_second
+        } else {
+            register_index_first
+        }
+    }
+
     fn sub_register_reversed(
         &mut self,
         register_index_first: Nibble,
@@ -307,7 +431,14 @@ impl<P: Platform> Interpreter<P> {
         self.index_register += self.registers.get(register_index) as Offset
     }
 
-    fn execute_to_decimal(&mut self, register_index: Nibble) {
+    /// Only the low nibble of the register picks the digit.
+    fn set_index_register_to_sprite(&mut self, register_index: Nibble) {
+        let digit = (self.registers.get(register_index) & 0xf) as Offset;
+
+        self.index_register = FONT_ADDRESS + digit * FONT_HEIGHT;
+    }
+
+    fn execute_to_decimal(&mut self, register_index: Nibble) -> Result<()> {
         let word = self.registers.get(register_index);
 
         let hundreds = word / 100;
@@ -316,29 +447,58 @@ impl<P: Platform> Interpreter<P> {
 
         let base_index = self.index_register.as_usize();
 
-        self.memory.locations[base_index] = hundreds;
-        self.memory.locations[base_index + 1] = tens;
-        self.memory.locations[base_index + 2] = units;
+        self.memory.write(base_index, hundreds)?;
+        self.memory.write(base_index + 1, tens)?;
+        self.memory.write(base_index + 2, units)
     }
 
-    fn write_memory(&mut self, register_index: Nibble) {
+    fn write_memory(&mut self, register_index: Nibble) -> Result<()> {
         for i in 0..=register_index.as_u8() {
-            self.memory.locations[self.index_register.as_usize() + usize::from(i)] =
-                self.registers.get(Nibble(i))
+            self.memory.write(
+                self.index_register.as_usize() + usize::from(i),
+                self.registers.get(Nibble(i)),
+            )?;
+        }
+
+        if self.quirks.memory_increments_i {
+            self.index_register += register_index.as_offset() + 1;
         }
 
-        self.index_register += register_index.as_offset() + 1;
+        Ok(())
     }
 
-    fn read_memory(&mut self, register_index: Nibble) {
+    fn read_memory(&mut self, register_index: Nibble) -> Result<()> {
         for i in 0..=register_index.as_u8() {
-            self.registers.set(
-                Nibble(i),
-                self.memory.locations[self.index_register.as_usize() + usize::from(i)],
-            );
+            let word = self
+                .memory
+                .read(self.index_register.as_usize() + usize::from(i))?;
+            self.registers.set(Nibble(i), word);
         }
 
-        self.index_register += register_index.as_offset() + 1;
+        if self.quirks.memory_increments_i {
+            self.index_register += register_index.as_offset() + 1;
+        }
+
+        Ok(())
+    }
+
+    fn flags_count(register_index: Nibble) -> usize {
+        register_index.as_usize().min(FLAGS_AMOUNT - 1) + 1
+    }
+
+    fn save_flags(&mut self, register_index: Nibble) -> Result<()> {
+        let count = Self::flags_count(register_index);
+
+        self.platform.save_flags(&self.registers.words[..count])
+    }
+
+    fn load_flags(&mut self, register_index: Nibble) -> Result<()> {
+        let count = Self::flags_count(register_index);
+        let flags = self.platform.load_flags()?;
+
+        self.registers.words[..count].copy_from_slice(&flags[..count]);
+
+        Ok(())
     }
 
     fn return_(&mut self) -> Result<()> {
@@ -428,6 +588,7 @@ impl Registers {
 pub struct Memory {
     locations: [u8; Address::DOMAIN_SIZE],
     instruction_pointer: Address,
+    mmio: MmioMap,
 }
 
 impl Default for Memory {
@@ -441,18 +602,53 @@ impl Memory {
         Self {
             locations: [0; Address::DOMAIN_SIZE],
             instruction_pointer: ENTRY_POINT_ADDRESS,
+            mmio: MmioMap::new(),
         }
     }
 
-    fn get_next_opcode(&mut self) -> OpCode {
+    /// Instructions are never fetched from memory-mapped I/O.
+    fn get_next_opcode(&mut self) -> Result<OpCode> {
         let ipa = self.instruction_pointer.as_usize();
+        if self.mmio.contains(ipa) || self.mmio.contains(ipa + 1) {
+            return Err(Error::MmioFetch(self.instruction_pointer));
+        }
         self.increment_instruction_pointer();
 
-        OpCode::from_bytes(self.locations[ipa], self.locations[ipa + 1])
+        Ok(OpCode::from_bytes(
+            self.read_location(ipa)?,
+            self.read_location(ipa + 1)?,
+        ))
+    }
+
+    fn read_location(&self, address: usize) -> Result<u8> {
+        self.locations
+            .get(address)
+            .copied()
+            .ok_or(Error::OutOfBounds(address))
     }
 
-    fn get_slice(&self, start: Address, size: usize) -> &[u8] {
-        &self.locations[start.as_usize()..start.as_usize() + size]
+    /// Goes to the handler if the address is memory-mapped.
+    fn read(&mut self, address: usize) -> Result<u8> {
+        match self.mmio.handler(address) {
+            Some((handler, offset)) => Ok(handler.read(offset)),
+            None => self.read_location(address),
+        }
+    }
+
+    /// Goes to the handler if the address is memory-mapped.
+    fn write(&mut self, address: usize, value: u8) -> Result<()> {
+        if let Some((handler, offset)) = self.mmio.handler(address) {
+            handler.write(offset, value);
+            return Ok(());
+        }
+
+        let location = self
+            .locations
+            .get_mut(address)
+            .ok_or(Error::OutOfBounds(address))?;
+        *location = value;
+
+        Ok(())
     }
 
     fn increment_instruction_pointer(&mut self) {
@@ -464,8 +660,10 @@ impl Memory {
     }
 }
 
-struct Stack {
+/// Return addresses of `2nnn`, all `STACK_SIZE` levels deep.
+pub struct Stack {
     stack: [Address; STACK_SIZE],
+    /// The first free slot, which is also the depth.
     pointer: usize,
 }
 
@@ -476,33 +674,36 @@ impl Default for Stack {
 }
 
 impl Stack {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             stack: [Address::new(0); STACK_SIZE],
             pointer: 0,
         }
     }
 
-    fn push(&mut self, value: Address) -> Result<()> {
-        if self.pointer + 1 == STACK_SIZE {
+    pub fn depth(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn push(&mut self, value: Address) -> Result<()> {
+        if self.pointer == STACK_SIZE {
             return Err(Error::StackOverflow);
         }
 
-        self.pointer += 1;
         self.stack[self.pointer] = value;
+        self.pointer += 1;
 
         Ok(())
     }
 
-    fn pop(&mut self) -> Result<Address> {
+    pub fn pop(&mut self) -> Result<Address> {
         if self.pointer == 0 {
             return Err(Error::StackUnderflow);
         }
 
         self.pointer -= 1;
-        let value = self.stack[self.pointer + 1];
 
-        Ok(value)
+        Ok(self.stack[self.pointer])
     }
 }
 
@@ -530,20 +731,22 @@ pub enum Operation {
     ShiftLeft(RegisterIndex, RegisterIndex),               // 8xyE
     SkipIfRegistersNotEqual(RegisterIndex, RegisterIndex), // 9xy0
     SetIndexRegister(Address),                             // Annn
-    JumpV0(Address),
-    SetToRandom(RegisterIndex, Word),
-    Draw(RegisterIndex, RegisterIndex, Nibble), // Dxyn
-    SkipIfKeyDown(RegisterIndex),               // Ex9E
-    SkipIfKeyUp(RegisterIndex),                 // ExA1
-    GetDelayTimer(RegisterIndex),               // Fx07
-    WaitForKey(RegisterIndex),                  // Fx0A
-    SetDelayTimer(RegisterIndex),               // Fx15
-    SetSoundTimer(RegisterIndex),               // Fx18
-    IncrementIndexRegister(RegisterIndex),      // Fx1E
-    SetIndexRegisterToSprite(Nibble),
-    ToDecimal(RegisterIndex), // Fx33
-    WriteMemory(Nibble),      // Fx55
-    ReadMemory(Nibble),       // Fx65
+    JumpV0(Address),                                       // Bnnn
+    SetToRandom(RegisterIndex, Word),                      // Cxnn
+    Draw(RegisterIndex, RegisterIndex, Nibble),            // Dxyn
+    SkipIfKeyDown(RegisterIndex),                          // Ex9E
+    SkipIfKeyUp(RegisterIndex),                            // ExA1
+    GetDelayTimer(RegisterIndex),                          // Fx07
+    WaitForKey(RegisterIndex),                             // Fx0A
+    SetDelayTimer(RegisterIndex),                          // Fx15
+    SetSoundTimer(RegisterIndex),                          // Fx18
+    IncrementIndexRegister(RegisterIndex),                 // Fx1E
+    SetIndexRegisterToSprite(RegisterIndex),               // Fx29
+    ToDecimal(RegisterIndex),                              // Fx33
+    WriteMemory(Nibble),                                   // Fx55
+    ReadMemory(Nibble),                                    // Fx65
+    SaveFlags(RegisterIndex),                              // Fx75
+    LoadFlags(RegisterIndex),                              // Fx85
 }
 
 impl TryFrom<OpCode> for Operation {
@@ -588,6 +791,7 @@ impl TryFrom<OpCode> for Operation {
                 ),
                 0xa => Self::SetIndexRegister(op_code.extract_address()),
                 0xb => Self::JumpV0(op_code.extract_address()),
+                0xc => Self::SetToRandom(op_code.extract_nibble(1), op_code.extract_word(1)),
                 0xd => Self::Draw(
                     op_code.extract_nibble(1),
                     op_code.extract_nibble(2),
@@ -604,9 +808,12 @@ impl TryFrom<OpCode> for Operation {
                     0x15 => Self::SetDelayTimer(op_code.extract_nibble(1)),
                     0x18 => Self::SetSoundTimer(op_code.extract_nibble(1)),
                     0x1e => Self::IncrementIndexRegister(op_code.extract_nibble(1)),
+                    0x29 => Self::SetIndexRegisterToSprite(op_code.extract_nibble(1)),
                     0x33 => Self::ToDecimal(op_code.extract_nibble(1)),
                     0x55 => Self::WriteMemory(op_code.extract_nibble(1)),
                     0x65 => Self::ReadMemory(op_code.extract_nibble(1)),
+                    0x75 => Self::SaveFlags(op_code.extract_nibble(1)),
+                    0x85 => Self::LoadFlags(op_code.extract_nibble(1)),
                     _ => return unknown_op_code_error,
                 },
                 _ => return unknown_op_code_error,
@@ -1,16 +1,31 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 mod data;
 mod error;
+mod flag_store;
 mod image;
 mod interpreter;
 mod managed_interpreter;
+mod mmio;
 mod platform;
+mod quirks;
+mod render;
+#[cfg(feature = "web")]
+mod web;
 
 pub use data::*;
 pub use error::*;
+pub use flag_store::*;
 pub use image::*;
 pub use interpreter::*;
 pub use managed_interpreter::*;
+pub use mmio::*;
 pub use platform::*;
+pub use quirks::*;
+pub use render::*;
+#[cfg(feature = "web")]
+pub use web::*;
@@ -1,18 +1,32 @@
 use crate::{
     data::Word,
     error::Result,
+    flag_store::{FlagStore, InMemoryFlagStore, FLAGS_AMOUNT},
     image::Image,
     interpreter::{Interpreter, SCREEN_HEIGHT, SCREEN_WIDTH},
     platform::{Key, Platform, Point, Sprite},
+    quirks::Quirks,
     Error, KeyEventKind, Nibble,
 };
 
 use core::time::Duration;
 
+#[cfg(feature = "std")]
+use crate::mmio::{MmioError, MmioHandler};
+
+#[cfg(feature = "std")]
+use core::ops::Range;
+
+#[cfg(feature = "std")]
+use std::boxed::Box;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 pub const KEYPAD_SIZE: usize = 16;
 
+/// Size of `FrameBuffer::to_packed`, a bit per pixel.
+pub const PACKED_FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 8;
+
 pub struct FrameBuffer([[bool; SCREEN_WIDTH]; SCREEN_HEIGHT]);
 
 impl Default for FrameBuffer {
@@ -26,6 +40,18 @@ impl FrameBuffer {
         self.0.iter()
     }
 
+    /// Pixels row by row, eight per byte with the leftmost one in the highest bit, as
+    /// in sprites.
+    pub fn to_packed(&self) -> [u8; PACKED_FRAME_SIZE] {
+        let mut packed = [0; PACKED_FRAME_SIZE];
+        for (i, pixel) in self.0.iter().flatten().enumerate() {
+            if *pixel {
+                packed[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        packed
+    }
+
     pub fn clear(&mut self) {
         for row in self.0.iter_mut() {
             for element in row.iter_mut() {
@@ -60,15 +86,16 @@ impl<R: FnMut() -> Word> RandomNumberGenerator for R {}
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Default)]
-struct ManagedPlatform<R: RandomNumberGenerator> {
+struct ManagedPlatform<R: RandomNumberGenerator, S: FlagStore> {
     rand: R,
     frame_buffer: FrameBuffer,
     delay_timer: Word,
     sound_timer: Word,
     keypad: ManagedKeypad,
+    flag_store: S,
 }
 
-impl<R: RandomNumberGenerator> Platform for ManagedPlatform<R> {
+impl<R: RandomNumberGenerator, S: FlagStore> Platform for ManagedPlatform<R, S> {
     fn draw_sprite(&mut self, pos: Point, sprite: Sprite) -> bool {
         let wrapped_pos = wrap_point_within_screen(pos);
 
@@ -107,6 +134,14 @@ impl<R: RandomNumberGenerator> Platform for ManagedPlatform<R> {
     fn get_random_word(&mut self) -> Word {
         (self.rand)()
     }
+
+    fn load_flags(&mut self) -> Result<[Word; FLAGS_AMOUNT]> {
+        self.flag_store.load()
+    }
+
+    fn save_flags(&mut self, flags: &[Word]) -> Result<()> {
+        self.flag_store.save(flags)
+    }
 }
 
 fn wrap_point_within_screen(point: Point) -> Point {
@@ -116,7 +151,7 @@ fn wrap_point_within_screen(point: Point) -> Point {
     }
 }
 
-impl<R: RandomNumberGenerator> ManagedPlatform<R> {
+impl<R: RandomNumberGenerator> ManagedPlatform<R, InMemoryFlagStore> {
     fn new(rand: R) -> Self {
         Self {
             rand,
@@ -124,6 +159,20 @@ impl<R: RandomNumberGenerator> ManagedPlatform<R> {
             delay_timer: 0,
             sound_timer: 0,
             keypad: ManagedKeypad::default(),
+            flag_store: InMemoryFlagStore::new(),
+        }
+    }
+}
+
+impl<R: RandomNumberGenerator, S: FlagStore> ManagedPlatform<R, S> {
+    fn with_flag_store<T: FlagStore>(self, flag_store: T) -> ManagedPlatform<R, T> {
+        ManagedPlatform {
+            rand: self.rand,
+            frame_buffer: self.frame_buffer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keypad: self.keypad,
+            flag_store,
         }
     }
 }
@@ -189,8 +238,8 @@ impl ManagedKeypad {
 
 ////////////////////////////////////////////////////////////////////////////////
 
-pub struct ManagedInterpreter<R: RandomNumberGenerator> {
-    inner: Interpreter<ManagedPlatform<R>>,
+pub struct ManagedInterpreter<R: RandomNumberGenerator, S: FlagStore = InMemoryFlagStore> {
+    inner: Interpreter<ManagedPlatform<R, S>>,
     operation_duration: Duration,
     delay_tick_duration: Duration,
     sound_tick_duration: Duration,
@@ -211,20 +260,91 @@ impl<R: RandomNumberGenerator> ManagedInterpreter<R> {
         )
     }
 
+    pub fn new_with_quirks(image: impl Image, rand: R, quirks: Quirks) -> Self {
+        Self::new_with_durations_and_quirks(
+            image,
+            rand,
+            Self::DEFAULT_OPERATION_DURATION,
+            Self::DEFAULT_DELAY_TICK_DURATION,
+            Self::DEFAULT_SOUND_TICK_DURATION,
+            quirks,
+        )
+    }
+
     pub fn new_with_durations(
         image: impl Image,
         rand: R,
         operation_duration: Duration,
         delay_tick_duration: Duration,
         sound_tick_duration: Duration,
+    ) -> Self {
+        Self::new_with_durations_and_quirks(
+            image,
+            rand,
+            operation_duration,
+            delay_tick_duration,
+            sound_tick_duration,
+            Quirks::default(),
+        )
+    }
+
+    pub fn new_with_durations_and_quirks(
+        image: impl Image,
+        rand: R,
+        operation_duration: Duration,
+        delay_tick_duration: Duration,
+        sound_tick_duration: Duration,
+        quirks: Quirks,
     ) -> Self {
         Self {
-            inner: Interpreter::new(image, ManagedPlatform::new(rand)),
+            inner: Interpreter::new(image, ManagedPlatform::new(rand), quirks),
             operation_duration,
             delay_tick_duration,
             sound_tick_duration,
         }
     }
+}
+
+impl<R: RandomNumberGenerator, S: FlagStore> ManagedInterpreter<R, S> {
+    /// Replaces the in-memory flag store, which is used by default.
+    pub fn with_flag_store<T: FlagStore>(self, flag_store: T) -> ManagedInterpreter<R, T> {
+        ManagedInterpreter {
+            inner: self
+                .inner
+                .map_platform(|platform| platform.with_flag_store(flag_store)),
+            operation_duration: self.operation_duration,
+            delay_tick_duration: self.delay_tick_duration,
+            sound_tick_duration: self.sound_tick_duration,
+        }
+    }
+
+    pub fn flag_store(&self) -> &S {
+        &self.inner.platform().flag_store
+    }
+
+    pub fn into_flag_store(self) -> S {
+        self.inner.into_platform().flag_store
+    }
+
+    /// See `Interpreter::register_mmio`.
+    #[cfg(feature = "std")]
+    pub fn register_mmio(
+        &mut self,
+        range: Range<usize>,
+        handler: Box<dyn MmioHandler>,
+    ) -> core::result::Result<(), MmioError> {
+        self.inner.register_mmio(range, handler)
+    }
+
+    /// See `Interpreter::register_mmio_forced`.
+    #[cfg(feature = "std")]
+    pub fn register_mmio_forced(
+        &mut self,
+        range: Range<usize>,
+        handler: Box<dyn MmioHandler>,
+    ) -> core::result::Result<(), MmioError> {
+        self.inner.register_mmio_forced(range, handler)
+    }
 
     pub fn simulate_one_instruction(&mut self) -> Result<()> {
         self.inner.run_next_instruction()
@@ -259,6 +379,11 @@ impl<R: RandomNumberGenerator> ManagedInterpreter<R> {
         &self.inner.platform().frame_buffer
     }
 
+    /// Whether the sound timer is running, which is when the buzzer should sound.
+    pub fn is_beeping(&self) -> bool {
+        self.inner.platform().sound_timer > 0
+    }
+
     pub fn set_key_down(&mut self, key: Key, is_down: bool) {
         let event_kind = if is_down {
             KeyEventKind::Pressed
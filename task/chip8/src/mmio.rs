@@ -0,0 +1,125 @@
+use crate::interpreter::{ENTRY_POINT_ADDRESS, FONT_ADDRESS, FONT_SPRITES};
+
+use core::ops::Range;
+
+use thiserror_no_std::Error;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A memory-mapped peripheral, see `Interpreter::register_mmio`.
+///
+/// Every read and write the program makes within the registered range goes to the
+/// handler instead of the memory. Instructions can't be fetched from the range.
+pub trait MmioHandler {
+    /// Offsets are relative to the start of the registered range.
+    fn read(&mut self, offset: usize) -> u8;
+    fn write(&mut self, offset: usize, value: u8);
+}
+
+/// Areas which a range may cover only if the registration is forced: the font sprites
+/// and the first instruction of the program.
+pub const PROTECTED_RANGES: [Range<usize>; 2] = [
+    FONT_ADDRESS.as_usize()..FONT_ADDRESS.as_usize() + FONT_SPRITES.len(),
+    ENTRY_POINT_ADDRESS.as_usize()..ENTRY_POINT_ADDRESS.as_usize() + 2,
+];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MmioError {
+    #[error("range {0:?} is empty or out of memory")]
+    InvalidRange(Range<usize>),
+    #[error("range {0:?} overlaps with the registered range {1:?}")]
+    Overlap(Range<usize>, Range<usize>),
+    #[error("range {0:?} covers the font or the entry point, the registration must be forced")]
+    Protected(Range<usize>),
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "std")]
+pub(crate) use map::MmioMap;
+
+#[cfg(feature = "std")]
+mod map {
+    use super::{MmioError, MmioHandler, PROTECTED_RANGES};
+    use crate::data::Address;
+
+    use core::ops::Range;
+
+    use std::{boxed::Box, vec::Vec};
+
+    fn overlaps(lhs: &Range<usize>, rhs: &Range<usize>) -> bool {
+        lhs.start < rhs.end && rhs.start < lhs.end
+    }
+
+    pub(crate) struct MmioMap {
+        regions: Vec<(Range<usize>, Box<dyn MmioHandler>)>,
+    }
+
+    impl MmioMap {
+        pub(crate) fn new() -> Self {
+            Self {
+                regions: Vec::new(),
+            }
+        }
+
+        pub(crate) fn register(
+            &mut self,
+            range: Range<usize>,
+            handler: Box<dyn MmioHandler>,
+            force: bool,
+        ) -> Result<(), MmioError> {
+            if range.is_empty() || range.end > Address::DOMAIN_SIZE {
+                return Err(MmioError::InvalidRange(range));
+            }
+            if let Some((registered, _)) = self
+                .regions
+                .iter()
+                .find(|(registered, _)| overlaps(registered, &range))
+            {
+                return Err(MmioError::Overlap(range, registered.clone()));
+            }
+            if !force && PROTECTED_RANGES.iter().any(|p| overlaps(p, &range)) {
+                return Err(MmioError::Protected(range));
+            }
+
+            self.regions.push((range, handler));
+            Ok(())
+        }
+
+        pub(crate) fn contains(&self, address: usize) -> bool {
+            self.regions
+                .iter()
+                .any(|(range, _)| range.contains(&address))
+        }
+
+        /// Returns the handler of the address with the offset within its range.
+        pub(crate) fn handler(&mut self, address: usize) -> Option<(&mut dyn MmioHandler, usize)> {
+            self.regions
+                .iter_mut()
+                .find(|(range, _)| range.contains(&address))
+                .map(|(range, handler)| {
+                    let handler: &mut dyn MmioHandler = handler.as_mut();
+                    (handler, address - range.start)
+                })
+        }
+    }
+}
+
+/// Ranges can't be registered without `std`, as handlers are boxed.
+#[cfg(not(feature = "std"))]
+pub(crate) struct MmioMap;
+
+#[cfg(not(feature = "std"))]
+impl MmioMap {
+    pub(crate) fn new() -> Self {
+        Self
+    }
+
+    pub(crate) fn contains(&self, _address: usize) -> bool {
+        false
+    }
+
+    pub(crate) fn handler(&mut self, _address: usize) -> Option<(&mut dyn MmioHandler, usize)> {
+        None
+    }
+}
@@ -1,6 +1,10 @@
 use core::ops::Add;
 
-use crate::data::{Nibble, Word};
+use crate::{
+    data::{Nibble, Word},
+    error::Result,
+    flag_store::FLAGS_AMOUNT,
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -69,4 +73,6 @@ pub trait Platform {
     fn is_key_down(&self, key: Key) -> bool;
     fn consume_key_press(&mut self) -> Option<Key>;
     fn get_random_word(&mut self) -> Word;
+    fn load_flags(&mut self) -> Result<[Word; FLAGS_AMOUNT]>;
+    fn save_flags(&mut self, flags: &[Word]) -> Result<()>;
 }
@@ -0,0 +1,30 @@
+////////////////////////////////////////////////////////////////////////////////
+
+/// Behaviours the interpreters of chip8 disagree on, see the `quirks` test image. The
+/// default is the original COSMAC VIP one, the test passes with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6` and `8xyE` shift `vy` into `vx`, instead of shifting `vx` in place.
+    pub shift_uses_vy: bool,
+    /// `Fx55` and `Fx65` leave `I` past the last register.
+    pub memory_increments_i: bool,
+    /// `Bxnn` jumps to `xnn + vx`, instead of `Bnnn` jumping to `nnn + v0`.
+    pub jump_with_vx: bool,
+    /// `Dxyn` wraps the pixels past the edges of the screen around, instead of clipping
+    /// them. The top left corner of a sprite is wrapped either way.
+    pub draw_wraps: bool,
+    /// `8xy1`, `8xy2` and `8xy3` set `vf` to 0.
+    pub vf_reset_on_logic: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: true,
+            memory_increments_i: true,
+            jump_with_vx: false,
+            draw_wraps: false,
+            vf_reset_on_logic: true,
+        }
+    }
+}
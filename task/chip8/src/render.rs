@@ -0,0 +1,172 @@
+use crate::{error::Result, managed_interpreter::FrameBuffer};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Draws frame buffers somewhere, so that frontends don't have to reimplement it.
+pub trait Renderer {
+    fn render(&mut self, fb: &FrameBuffer) -> Result<()>;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "std")]
+pub use text::{AnsiTerminalRenderer, TextRenderer};
+
+#[cfg(feature = "std")]
+mod text {
+    use super::Renderer;
+    use crate::{
+        error::Result, interpreter::SCREEN_WIDTH, managed_interpreter::FrameBuffer, Error,
+    };
+
+    use std::{io::Write, string::String, vec::Vec};
+
+    const CURSOR_HOME: &[u8] = b"\x1b[H";
+    const RESET_COLORS: &[u8] = b"\x1b[0m";
+    const UPPER_HALF_BLOCK: &str = "▀";
+
+    type Row = [bool; SCREEN_WIDTH];
+
+    /// Renders to any writer with ANSI escape sequences, assuming nothing about what is
+    /// on the other end. Every terminal row shows two rows of pixels: the upper one as
+    /// the foreground of `▀` and the lower one as its background.
+    ///
+    /// The previous frame is remembered, and only the terminal rows that have changed
+    /// since then are redrawn.
+    pub struct AnsiTerminalRenderer<W: Write> {
+        writer: W,
+        partial_redraw: bool,
+        previous: Option<Vec<Row>>,
+    }
+
+    impl<W: Write> AnsiTerminalRenderer<W> {
+        pub fn new(writer: W) -> Self {
+            Self {
+                writer,
+                partial_redraw: true,
+                previous: None,
+            }
+        }
+
+        /// Partial redraw is on by default. When off, every frame is drawn in full.
+        pub fn with_partial_redraw(mut self, partial_redraw: bool) -> Self {
+            self.partial_redraw = partial_redraw;
+            self
+        }
+
+        /// Makes the next frame be drawn in full, e.g. if the terminal was cleared.
+        pub fn invalidate(&mut self) {
+            self.previous = None;
+        }
+
+        pub fn writer_mut(&mut self) -> &mut W {
+            &mut self.writer
+        }
+
+        pub fn into_writer(self) -> W {
+            self.writer
+        }
+    }
+
+    impl<W: Write> Renderer for AnsiTerminalRenderer<W> {
+        fn render(&mut self, fb: &FrameBuffer) -> Result<()> {
+            let rows = fb.iter_rows().copied().collect::<Vec<_>>();
+
+            let mut output = Vec::new();
+            match self.previous.as_ref().filter(|_| self.partial_redraw) {
+                Some(previous) => {
+                    let changed = rows.chunks(2).zip(previous.chunks(2)).enumerate();
+                    for (i, (pair, previous_pair)) in changed {
+                        if pair != previous_pair {
+                            // Terminal rows are numbered from 1.
+                            output.extend(std::format!("\x1b[{};1H", i + 1).as_bytes());
+                            write_row(&mut output, pair);
+                        }
+                    }
+                }
+                None => {
+                    output.extend(CURSOR_HOME);
+                    for (i, pair) in rows.chunks(2).enumerate() {
+                        if i > 0 {
+                            output.extend(b"\r\n");
+                        }
+                        write_row(&mut output, pair);
+                    }
+                }
+            }
+
+            self.writer
+                .write_all(&output)
+                .and_then(|_| self.writer.flush())
+                .map_err(|_| Error::Render)?;
+            self.previous = Some(rows);
+            Ok(())
+        }
+    }
+
+    /// Writes a pair of pixel rows, emitting colors only where they change.
+    fn write_row(output: &mut Vec<u8>, pair: &[Row]) {
+        let mut colors = None;
+        for x in 0..SCREEN_WIDTH {
+            let upper = pair[0][x];
+            let lower = pair.get(1).is_some_and(|row| row[x]);
+            if colors != Some((upper, lower)) {
+                colors = Some((upper, lower));
+                let foreground = if upper { 37 } else { 30 };
+                let background = if lower { 47 } else { 40 };
+                output.extend(std::format!("\x1b[{foreground};{background}m").as_bytes());
+            }
+            output.extend(UPPER_HALF_BLOCK.as_bytes());
+        }
+        output.extend(RESET_COLORS);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+
+    /// Renders to plain text, a character per pixel and a line per row, lines being
+    /// separated by `\n`.
+    pub struct TextRenderer {
+        on: char,
+        off: char,
+        text: String,
+    }
+
+    impl Default for TextRenderer {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl TextRenderer {
+        pub fn new() -> Self {
+            Self::with_chars('▓', ' ')
+        }
+
+        pub fn with_chars(on: char, off: char) -> Self {
+            Self {
+                on,
+                off,
+                text: String::new(),
+            }
+        }
+
+        /// The last rendered frame.
+        pub fn text(&self) -> &str {
+            &self.text
+        }
+    }
+
+    impl Renderer for TextRenderer {
+        fn render(&mut self, fb: &FrameBuffer) -> Result<()> {
+            self.text.clear();
+            for (i, row) in fb.iter_rows().enumerate() {
+                if i > 0 {
+                    self.text.push('\n');
+                }
+                self.text
+                    .extend(row.iter().map(|&on| if on { self.on } else { self.off }));
+            }
+            Ok(())
+        }
+    }
+}
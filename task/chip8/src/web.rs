@@ -0,0 +1,119 @@
+//! Bindings for running the interpreter in a browser. Build them with
+//! `cargo rustc --release --target wasm32-unknown-unknown --features web --crate-type cdylib`
+//! and generate the JS glue with `wasm-bindgen --target web`:
+//!
+//! ```js
+//! import init, { WebChip8 } from "./chip8.js";
+//!
+//! await init();
+//! const chip8 = new WebChip8(rom, Math.random() * 2 ** 32);
+//! document.addEventListener("keydown", (event) => chip8.key_down(KEYS[event.code]));
+//! document.addEventListener("keyup", (event) => chip8.key_up(KEYS[event.code]));
+//! setInterval(() => {
+//!     chip8.run_ms(16);
+//!     draw(chip8.frame_buffer_packed(), chip8.beeping());
+//! }, 16);
+//! ```
+//!
+//! Errors are thrown as JS `Error`s with the messages of `WebError`.
+
+use crate::{
+    data::{Nibble, Word},
+    image::{Ch8Image, Ch8ImageError},
+    managed_interpreter::ManagedInterpreter,
+};
+
+use core::time::Duration;
+
+use std::{boxed::Box, string::ToString, vec::Vec};
+
+use thiserror_no_std::Error;
+use wasm_bindgen::prelude::*;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Error, Debug)]
+pub enum WebError {
+    #[error("invalid ROM: {0}")]
+    Rom(Ch8ImageError),
+    #[error("invalid key: {0:#04x}, expected 0x0 to 0xf")]
+    InvalidKey(u8),
+    #[error("{0}")]
+    Interpreter(crate::Error),
+}
+
+fn to_js_error(error: WebError) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+type Random = Box<dyn FnMut() -> Word>;
+
+/// A xorshift, so that the same seed plays the same game.
+fn seeded_random(seed: u32) -> Random {
+    // Zero is the fixed point of xorshift.
+    let mut state = seed.max(1);
+    Box::new(move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 24) as Word
+    })
+}
+
+#[wasm_bindgen]
+pub struct WebChip8 {
+    interpreter: ManagedInterpreter<Random>,
+}
+
+#[wasm_bindgen]
+impl WebChip8 {
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], seed: u32) -> Result<WebChip8, JsError> {
+        Self::try_new(rom, seed).map_err(to_js_error)
+    }
+
+    pub fn run_ms(&mut self, ms: u32) -> Result<(), JsError> {
+        self.try_run_ms(ms).map_err(to_js_error)
+    }
+
+    /// See `FrameBuffer::to_packed`.
+    pub fn frame_buffer_packed(&self) -> Vec<u8> {
+        self.interpreter.frame_buffer().to_packed().to_vec()
+    }
+
+    pub fn key_down(&mut self, key: u8) -> Result<(), JsError> {
+        self.try_set_key_down(key, true).map_err(to_js_error)
+    }
+
+    pub fn key_up(&mut self, key: u8) -> Result<(), JsError> {
+        self.try_set_key_down(key, false).map_err(to_js_error)
+    }
+
+    pub fn beeping(&self) -> bool {
+        self.interpreter.is_beeping()
+    }
+}
+
+/// The same as the bindings, but with Rust errors: `JsError` can only be created on wasm.
+impl WebChip8 {
+    pub fn try_new(rom: &[u8], seed: u32) -> Result<Self, WebError> {
+        let image = Ch8Image::new(rom).map_err(WebError::Rom)?;
+        Ok(Self {
+            interpreter: ManagedInterpreter::new(image, seeded_random(seed)),
+        })
+    }
+
+    pub fn try_run_ms(&mut self, ms: u32) -> Result<(), WebError> {
+        self.interpreter
+            .simulate_duration(Duration::from_millis(ms.into()))
+            .map_err(WebError::Interpreter)
+    }
+
+    pub fn try_set_key_down(&mut self, key: u8, is_down: bool) -> Result<(), WebError> {
+        let key = Nibble::try_from(key).map_err(|()| WebError::InvalidKey(key))?;
+        self.interpreter.set_key_down(key, is_down);
+        Ok(())
+    }
+}
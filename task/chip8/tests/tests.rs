@@ -1,6 +1,22 @@
 use core::time::Duration;
 
-use chip8::{Ch8Image, FrameBuffer, ManagedInterpreter, Nibble};
+use chip8::{
+    Address, AnsiTerminalRenderer, Ch8Image, Error, FileFlagStore, FlagStore, FrameBuffer,
+    InMemoryFlagStore, ManagedInterpreter, MmioError, MmioHandler, Nibble, Point, Quirks,
+    RandomNumberGenerator, Renderer, Stack, TextRenderer, Word, FLAGS_AMOUNT, PACKED_FRAME_SIZE,
+    STACK_SIZE,
+};
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -39,6 +55,19 @@ fn test_by_instruction_count(image: &[u8], instruction_count: usize, expected_di
     check_display(inter.frame_buffer(), expected_display);
 }
 
+fn run_with_flag_store<S: FlagStore>(image: &[u8], flag_store: S) -> S {
+    let mut inter = ManagedInterpreter::new(Ch8Image::new(image).unwrap(), rand::random)
+        .with_flag_store(flag_store);
+    for _ in 0..image.len() / 2 {
+        inter.simulate_one_instruction().unwrap();
+    }
+    inter.into_flag_store()
+}
+
+fn temp_flags_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("chip8-{}-{name}.flags", std::process::id()))
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[test]
@@ -315,3 +344,809 @@ fn test_keypad() {
         ",
     );
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn opcode_interpreter<R: RandomNumberGenerator>(image: &[u8], rand: R) -> ManagedInterpreter<R> {
+    ManagedInterpreter::new(Ch8Image::new(image).unwrap(), rand)
+}
+
+fn simulate_instructions<R: RandomNumberGenerator>(
+    inter: &mut ManagedInterpreter<R>,
+    instruction_count: usize,
+) {
+    for _ in 0..instruction_count {
+        inter.simulate_one_instruction().unwrap();
+    }
+}
+
+/// The registers the image has saved with `Fx75`, registers aren't visible otherwise.
+fn saved_registers<R: RandomNumberGenerator>(
+    inter: &ManagedInterpreter<R>,
+) -> [Word; FLAGS_AMOUNT] {
+    inter.flag_store().load().unwrap()
+}
+
+/// The top left corner of the display, `width` pixels wide.
+fn display_corner(fb: &FrameBuffer, width: usize, height: usize) -> Vec<String> {
+    fb.iter_rows()
+        .take(height)
+        .map(|row| {
+            row[..width]
+                .iter()
+                .map(|&on| if on { '#' } else { '.' })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_random_is_masked() {
+    // V0..=V3 = rand & 0x0f, 0xf0, 0xff, 0x00, save V0..=V3.
+    let image = [0xc0, 0x0f, 0xc1, 0xf0, 0xc2, 0xff, 0xc3, 0x00, 0xf3, 0x75];
+    let mut inter = opcode_interpreter(&image, || 0xab);
+    simulate_instructions(&mut inter, 5);
+    assert_eq!(
+        saved_registers(&inter),
+        [0x0b, 0xa0, 0xab, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+
+    // A number is drawn for every instruction.
+    let image = [0xc0, 0xff, 0xc1, 0xff, 0xf1, 0x75];
+    let mut next = 0x10;
+    let mut inter = opcode_interpreter(&image, move || {
+        next += 1;
+        next
+    });
+    simulate_instructions(&mut inter, 3);
+    assert_eq!(saved_registers(&inter)[..2], [0x11, 0x12]);
+}
+
+#[test]
+fn test_font_sprite() {
+    // I = sprite of V0 = 0x1a, draw at (0, 0); I = sprite of V2 = 7, draw at (8, 0).
+    let image = [
+        0x60, 0x1a, 0xf0, 0x29, 0x61, 0x00, 0xd1, 0x15, 0x62, 0x07, 0xf2, 0x29, 0x63, 0x08, 0xd3,
+        0x15,
+    ];
+    let mut inter = opcode_interpreter(&image, rand::random);
+    simulate_instructions(&mut inter, 8);
+    assert_eq!(
+        display_corner(inter.frame_buffer(), 12, 6),
+        [
+            "####....####",
+            "#..#.......#",
+            "####......#.",
+            "#..#.....#..",
+            "#..#.....#..",
+            "............",
+        ]
+    );
+}
+
+#[test]
+fn test_timers() {
+    // V0 = 42, delay = sound = V0, then V1 = delay and save V0..=V1 forever.
+    let image = [
+        0x60, 0x2a, 0xf0, 0x15, 0xf0, 0x18, 0xf1, 0x07, 0xf1, 0x75, 0x12, 0x06,
+    ];
+    let mut inter = opcode_interpreter(&image, rand::random);
+    simulate_instructions(&mut inter, 2);
+    assert!(!inter.is_beeping());
+    simulate_instructions(&mut inter, 3);
+    assert!(inter.is_beeping());
+    assert_eq!(saved_registers(&inter)[..2], [0x2a, 0x2a]);
+
+    inter.simulate_duration(Duration::from_secs(1)).unwrap();
+    assert!(!inter.is_beeping());
+    assert_eq!(saved_registers(&inter)[..2], [0x2a, 0x00]);
+}
+
+#[test]
+fn test_key_skips() {
+    // V0 = 5, skip V1 = 1 if key V0 is down, skip V2 = 1 if it's up, save V0..=V2.
+    let image = [
+        0x60, 0x05, 0xe0, 0x9e, 0x61, 0x01, 0xe0, 0xa1, 0x62, 0x01, 0xf2, 0x75,
+    ];
+    for (is_down, expected) in [(true, [0x05, 0x00, 0x01]), (false, [0x05, 0x01, 0x00])] {
+        let mut inter = opcode_interpreter(&image, rand::random);
+        inter.set_key_down(Nibble::try_from(5).unwrap(), is_down);
+        simulate_instructions(&mut inter, 5);
+        assert_eq!(saved_registers(&inter)[..3], expected);
+    }
+}
+
+#[test]
+fn test_wait_for_key() {
+    // V0 = key, V1 = 1, save V0..=V1.
+    let image = [0xf0, 0x0a, 0x61, 0x01, 0xf1, 0x75];
+    let mut inter = opcode_interpreter(&image, rand::random);
+    simulate_instructions(&mut inter, 3);
+    assert_eq!(saved_registers(&inter), [0; FLAGS_AMOUNT]);
+
+    // A key counts once it's released.
+    inter.set_key_down(Nibble::try_from(0xb).unwrap(), true);
+    simulate_instructions(&mut inter, 3);
+    assert_eq!(saved_registers(&inter), [0; FLAGS_AMOUNT]);
+
+    inter.set_key_down(Nibble::try_from(0xb).unwrap(), false);
+    simulate_instructions(&mut inter, 3);
+    assert_eq!(saved_registers(&inter)[..2], [0x0b, 0x01]);
+}
+
+#[test]
+fn test_jump_v0() {
+    // V0 = 4, jump to 0x206 + V0, skipping every V1 = ..., then V2 = 1 and save V0..=V2.
+    let image = [
+        0x60, 0x04, 0xb2, 0x06, 0x61, 0x01, 0x61, 0x02, 0x61, 0x03, 0x62, 0x01, 0xf2, 0x75,
+    ];
+    let mut inter = opcode_interpreter(&image, rand::random);
+    simulate_instructions(&mut inter, 4);
+    assert_eq!(saved_registers(&inter)[..3], [0x04, 0x00, 0x01]);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Runs the image with the default quirks but one, and returns the registers it has
+/// saved, see `saved_registers`.
+fn run_with_quirks(image: &[u8], instruction_count: usize, quirks: Quirks) -> [Word; FLAGS_AMOUNT] {
+    let mut inter =
+        ManagedInterpreter::new_with_quirks(Ch8Image::new(image).unwrap(), rand::random, quirks);
+    simulate_instructions(&mut inter, instruction_count);
+    saved_registers(&inter)
+}
+
+/// V0 = 3, V1 = 0x80, V0 = V? >> 1, V2 = VF; V3 = 0x81, V4 = 1, V3 = V? << 1, V5 = VF;
+/// save V0..=V5.
+const SHIFT_IMAGE: [u8; 18] = [
+    0x60, 0x03, 0x61, 0x80, 0x80, 0x16, 0x82, 0xf0, 0x63, 0x81, 0x64, 0x01, 0x83, 0x4e, 0x85, 0xf0,
+    0xf5, 0x75,
+];
+
+#[test]
+fn test_quirk_shift_uses_vy() {
+    let quirks = Quirks {
+        shift_uses_vy: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_quirks(&SHIFT_IMAGE, 9, quirks)[..6],
+        [0x40, 0x80, 0x00, 0x02, 0x01, 0x00]
+    );
+}
+
+#[test]
+fn test_quirk_shift_in_place() {
+    let quirks = Quirks {
+        shift_uses_vy: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_quirks(&SHIFT_IMAGE, 9, quirks)[..6],
+        [0x01, 0x80, 0x01, 0x02, 0x01, 0x01]
+    );
+}
+
+/// I = 0x300, V0 = 0xaa, store V0, V0 = 0xbb, store V0; I = 0x300, load and save V0..=V1.
+const MEMORY_IMAGE: [u8; 16] = [
+    0xa3, 0x00, 0x60, 0xaa, 0xf0, 0x55, 0x60, 0xbb, 0xf0, 0x55, 0xa3, 0x00, 0xf1, 0x65, 0xf1, 0x75,
+];
+
+#[test]
+fn test_quirk_memory_increments_i() {
+    let quirks = Quirks {
+        memory_increments_i: true,
+        ..Default::default()
+    };
+    assert_eq!(run_with_quirks(&MEMORY_IMAGE, 8, quirks)[..2], [0xaa, 0xbb]);
+}
+
+#[test]
+fn test_quirk_memory_keeps_i() {
+    let quirks = Quirks {
+        memory_increments_i: false,
+        ..Default::default()
+    };
+    assert_eq!(run_with_quirks(&MEMORY_IMAGE, 8, quirks)[..2], [0xbb, 0x00]);
+}
+
+/// V0 = 4, V2 = 2, jump to 0x206 + V0 or V2; at 0x208 V1 = 2; at 0x20a save V0..=V2.
+const JUMP_IMAGE: [u8; 12] = [
+    0x60, 0x04, 0x62, 0x02, 0xb2, 0x06, 0x61, 0x01, 0x61, 0x02, 0xf2, 0x75,
+];
+
+#[test]
+fn test_quirk_jump_with_v0() {
+    let quirks = Quirks {
+        jump_with_vx: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_quirks(&JUMP_IMAGE, 4, quirks)[..3],
+        [0x04, 0x00, 0x02]
+    );
+}
+
+#[test]
+fn test_quirk_jump_with_vx() {
+    let quirks = Quirks {
+        jump_with_vx: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_quirks(&JUMP_IMAGE, 5, quirks)[..3],
+        [0x04, 0x02, 0x02]
+    );
+}
+
+/// Draws the font sprite of 8 at (62, 30).
+fn lit_pixels_after_draw(quirks: Quirks) -> Vec<(usize, usize)> {
+    let image = [0x60, 0x3e, 0x61, 0x1e, 0x62, 0x08, 0xf2, 0x29, 0xd0, 0x15];
+    let mut inter =
+        ManagedInterpreter::new_with_quirks(Ch8Image::new(image).unwrap(), rand::random, quirks);
+    simulate_instructions(&mut inter, 5);
+    inter
+        .frame_buffer()
+        .iter_rows()
+        .enumerate()
+        .flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|&(_, &on)| on)
+                .map(move |(x, _)| (x, y))
+        })
+        .collect()
+}
+
+#[test]
+fn test_quirk_draw_clips() {
+    let quirks = Quirks {
+        draw_wraps: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        lit_pixels_after_draw(quirks),
+        [(62, 30), (63, 30), (62, 31)]
+    );
+}
+
+#[test]
+fn test_quirk_draw_wraps() {
+    let quirks = Quirks {
+        draw_wraps: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        lit_pixels_after_draw(quirks),
+        [
+            (0, 0),
+            (1, 0),
+            (62, 0),
+            (63, 0),
+            (1, 1),
+            (62, 1),
+            (0, 2),
+            (1, 2),
+            (62, 2),
+            (63, 2),
+            (0, 30),
+            (1, 30),
+            (62, 30),
+            (63, 30),
+            (1, 31),
+            (62, 31),
+        ]
+    );
+}
+
+/// V0 = 0x0f, V1 = 0xf0, then VF = 1 before V0 |= V1, V0 &= V1 and V0 ^= V1, with VF
+/// copied into V2, V3 and V4 after each; save V0..=V4.
+const LOGIC_IMAGE: [u8; 24] = [
+    0x60, 0x0f, 0x61, 0xf0, 0x6f, 0x01, 0x80, 0x11, 0x82, 0xf0, 0x6f, 0x01, 0x80, 0x12, 0x83, 0xf0,
+    0x6f, 0x01, 0x80, 0x13, 0x84, 0xf0, 0xf4, 0x75,
+];
+
+#[test]
+fn test_quirk_vf_reset_on_logic() {
+    let quirks = Quirks {
+        vf_reset_on_logic: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_quirks(&LOGIC_IMAGE, 12, quirks)[..5],
+        [0x00, 0xf0, 0x00, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn test_quirk_vf_kept_on_logic() {
+    let quirks = Quirks {
+        vf_reset_on_logic: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_quirks(&LOGIC_IMAGE, 12, quirks)[..5],
+        [0x00, 0xf0, 0x01, 0x01, 0x01]
+    );
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_stack_is_lifo() {
+    let mut stack = Stack::new();
+    for i in 0..STACK_SIZE {
+        stack.push(Address::new(0x200 + 2 * i as u16)).unwrap();
+        assert_eq!(stack.depth(), i + 1);
+    }
+    for i in (0..STACK_SIZE).rev() {
+        assert_eq!(stack.pop().unwrap().as_usize(), 0x200 + 2 * i);
+        assert_eq!(stack.depth(), i);
+    }
+}
+
+#[test]
+fn test_stack_overflow() {
+    let mut stack = Stack::new();
+    for _ in 0..STACK_SIZE {
+        stack.push(Address::new(0x200)).unwrap();
+    }
+    assert!(matches!(
+        stack.push(Address::new(0x202)),
+        Err(Error::StackOverflow)
+    ));
+    assert_eq!(stack.depth(), STACK_SIZE);
+
+    // The failed push hasn't overwritten anything.
+    assert_eq!(stack.pop().unwrap().as_usize(), 0x200);
+}
+
+#[test]
+fn test_stack_underflow() {
+    let mut stack = Stack::new();
+    assert!(matches!(stack.pop(), Err(Error::StackUnderflow)));
+
+    stack.push(Address::new(0x200)).unwrap();
+    stack.pop().unwrap();
+    assert!(matches!(stack.pop(), Err(Error::StackUnderflow)));
+    assert_eq!(stack.depth(), 0);
+}
+
+/// V0 = 0, call 0x206, save V0. At 0x206: V0 += 1, call 0x206 again unless V0 == `depth`,
+/// return.
+fn nested_calls_image(depth: u8) -> [u8; 14] {
+    [
+        0x60, 0x00, 0x22, 0x06, 0xf0, 0x75, 0x70, 0x01, 0x30, depth, 0x22, 0x06, 0x00, 0xee,
+    ]
+}
+
+#[test]
+fn test_nested_calls() {
+    let depth = STACK_SIZE as u8;
+    let mut inter = opcode_interpreter(&nested_calls_image(depth), rand::random);
+    // The outer call, two instructions and a call on every level but the deepest one,
+    // a return from every level.
+    let instruction_count = 2 + 2 * STACK_SIZE + (STACK_SIZE - 1) + STACK_SIZE + 1;
+    simulate_instructions(&mut inter, instruction_count);
+    assert_eq!(saved_registers(&inter)[0], depth);
+
+    let mut inter = opcode_interpreter(&nested_calls_image(depth + 1), rand::random);
+    let error = (0..instruction_count)
+        .find_map(|_| inter.simulate_one_instruction().err())
+        .unwrap();
+    assert!(matches!(error, Error::StackOverflow));
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_flags_survive_reset() {
+    // V0 = 0x11, V1 = 0x22, V2 = 0x33, save V0..=V2.
+    let save = [0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xf2, 0x75];
+    let store = run_with_flag_store(&save, InMemoryFlagStore::new());
+    assert_eq!(
+        store.load().unwrap(),
+        [0x11, 0x22, 0x33, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+
+    // Load V0..=V1, V0 += 1, save V0 only.
+    let update = [0xf1, 0x85, 0x70, 0x01, 0xf0, 0x75];
+    let store = run_with_flag_store(&update, store);
+    assert_eq!(
+        store.load().unwrap(),
+        [0x12, 0x22, 0x33, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn test_flags_register_index_is_clamped() {
+    // V7 = 0xee, V8 = 0xff, save and load V0..=VF.
+    let image = [0x67, 0xee, 0x68, 0xff, 0xff, 0x75, 0xff, 0x85];
+    let store = run_with_flag_store(&image, InMemoryFlagStore::new());
+    assert_eq!(
+        store.load().unwrap(),
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xee]
+    );
+}
+
+#[test]
+fn test_file_flag_store() {
+    let path = temp_flags_path("persist");
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(FileFlagStore::new(&path).load().unwrap(), [0; 8]);
+
+    let save = [0x60, 0x2a, 0x61, 0x07, 0xf1, 0x75];
+    run_with_flag_store(&save, FileFlagStore::new(&path));
+    assert_eq!(fs::read(&path).unwrap(), [0x2a, 0x07, 0, 0, 0, 0, 0, 0]);
+
+    // Load V0, save V0 to the second store; V1 must be kept.
+    let copy = [0xf0, 0x85, 0x70, 0x01, 0xf0, 0x75];
+    let store = run_with_flag_store(&copy, FileFlagStore::new(&path));
+    assert_eq!(store.load().unwrap(), [0x2b, 0x07, 0, 0, 0, 0, 0, 0]);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_file_flag_store_corrupted() {
+    let path = temp_flags_path("corrupted");
+    fs::write(&path, [1, 2, 3]).unwrap();
+
+    let warned = Arc::new(AtomicBool::new(false));
+    let store = FileFlagStore::new(&path).with_warning_hook({
+        let warned = warned.clone();
+        move |_| warned.store(true, Ordering::SeqCst)
+    });
+
+    assert_eq!(store.load().unwrap(), [0; 8]);
+    assert!(warned.load(Ordering::SeqCst));
+
+    let image = [0x63, 0x05, 0xf3, 0x75];
+    run_with_flag_store(&image, store);
+    assert_eq!(fs::read(&path).unwrap(), [0, 0, 0, 5, 0, 0, 0, 0]);
+
+    fs::remove_file(&path).unwrap();
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Eq)]
+enum Access {
+    Read(usize),
+    Write(usize, u8),
+}
+
+/// Logs every access and answers reads with `0xa0 + offset`.
+struct ScriptedDevice {
+    log: Rc<RefCell<Vec<Access>>>,
+}
+
+impl ScriptedDevice {
+    fn new() -> (Box<Self>, Rc<RefCell<Vec<Access>>>) {
+        let log = Rc::new(RefCell::new(vec![]));
+        (Box::new(Self { log: log.clone() }), log)
+    }
+}
+
+impl MmioHandler for ScriptedDevice {
+    fn read(&mut self, offset: usize) -> u8 {
+        self.log.borrow_mut().push(Access::Read(offset));
+        0xa0 + offset as u8
+    }
+
+    fn write(&mut self, offset: usize, value: u8) {
+        self.log.borrow_mut().push(Access::Write(offset, value));
+    }
+}
+
+fn new_interpreter(image: &[u8]) -> ManagedInterpreter<fn() -> u8, InMemoryFlagStore> {
+    ManagedInterpreter::new(Ch8Image::new(image).unwrap(), rand::random as fn() -> u8)
+        .with_flag_store(InMemoryFlagStore::new())
+}
+
+#[test]
+fn test_mmio_read_write() {
+    let image = [
+        // I = 0xf00, V0 = 0x11, V1 = 0x22, V2 = 0x33, store V0..=V2.
+        0xaf, 0x00, 0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xf2, 0x55,
+        // I = 0xf00, load V0..=V2, save them as flags.
+        0xaf, 0x00, 0xf2, 0x65, 0xf2, 0x75,
+        // V3 = 123, I = 0xf08, store its decimal digits.
+        0x63, 0x7b, 0xaf, 0x08, 0xf3, 0x33,
+    ];
+    let mut inter = new_interpreter(&image);
+    let (device, log) = ScriptedDevice::new();
+    inter.register_mmio(0xf00..0xf10, device).unwrap();
+    for _ in 0..image.len() / 2 {
+        inter.simulate_one_instruction().unwrap();
+    }
+
+    assert_eq!(
+        *log.borrow(),
+        [
+            Access::Write(0, 0x11),
+            Access::Write(1, 0x22),
+            Access::Write(2, 0x33),
+            Access::Read(0),
+            Access::Read(1),
+            Access::Read(2),
+            Access::Write(8, 1),
+            Access::Write(9, 2),
+            Access::Write(10, 3),
+        ]
+    );
+    assert_eq!(
+        inter.flag_store().load().unwrap(),
+        [0xa0, 0xa1, 0xa2, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn test_mmio_registration() {
+    let mut inter = new_interpreter(&[0x00, 0xe0]);
+    let mut register = |range| inter.register_mmio(range, ScriptedDevice::new().0);
+
+    assert_eq!(register(0xf00..0xf10), Ok(()));
+    assert_eq!(
+        register(0xf08..0xf20),
+        Err(MmioError::Overlap(0xf08..0xf20, 0xf00..0xf10))
+    );
+    assert_eq!(
+        register(0xe00..0xf01),
+        Err(MmioError::Overlap(0xe00..0xf01, 0xf00..0xf10))
+    );
+    assert_eq!(register(0xf10..0xf20), Ok(()));
+    assert_eq!(
+        register(0xe00..0xe00),
+        Err(MmioError::InvalidRange(0xe00..0xe00))
+    );
+    assert_eq!(
+        register(0xff0..0x1001),
+        Err(MmioError::InvalidRange(0xff0..0x1001))
+    );
+
+    // The font and the entry point.
+    assert_eq!(register(0x40..0x60), Err(MmioError::Protected(0x40..0x60)));
+    assert_eq!(
+        register(0x1ff..0x201),
+        Err(MmioError::Protected(0x1ff..0x201))
+    );
+    assert_eq!(register(0x50..0x1ff), Ok(()));
+    assert_eq!(
+        inter.register_mmio_forced(0x40..0x50, ScriptedDevice::new().0),
+        Ok(())
+    );
+    assert_eq!(
+        inter.register_mmio_forced(0x40..0x60, ScriptedDevice::new().0),
+        Err(MmioError::Overlap(0x40..0x60, 0x50..0x1ff))
+    );
+}
+
+#[test]
+fn test_mmio_fetch_is_forbidden() {
+    // Jump to 0xf00.
+    let mut inter = new_interpreter(&[0x1f, 0x00]);
+    let (device, log) = ScriptedDevice::new();
+    inter.register_mmio(0xf00..0xf10, device).unwrap();
+    inter.simulate_one_instruction().unwrap();
+    assert!(matches!(
+        inter.simulate_one_instruction(),
+        Err(Error::MmioFetch(_))
+    ));
+
+    // Jump to 0xeff, the second byte of the instruction is mapped.
+    let mut inter = new_interpreter(&[0x1e, 0xff]);
+    inter
+        .register_mmio(0xf00..0xf01, ScriptedDevice::new().0)
+        .unwrap();
+    inter.simulate_one_instruction().unwrap();
+    assert!(matches!(
+        inter.simulate_one_instruction(),
+        Err(Error::MmioFetch(_))
+    ));
+
+    let mut inter = new_interpreter(&[0x00, 0xe0]);
+    inter
+        .register_mmio_forced(0x200..0x202, ScriptedDevice::new().0)
+        .unwrap();
+    assert!(matches!(
+        inter.simulate_one_instruction(),
+        Err(Error::MmioFetch(_))
+    ));
+
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn test_mmio_untouched() {
+    let image = include_bytes!("../images/tests/2-ibm-logo.ch8");
+    let mut plain = new_interpreter(image);
+    let mut mapped = new_interpreter(image);
+    let (device, log) = ScriptedDevice::new();
+    mapped.register_mmio(0xe00..0xf00, device).unwrap();
+
+    for _ in 0..100 {
+        plain.simulate_one_instruction().unwrap();
+        mapped.simulate_one_instruction().unwrap();
+        assert!(plain
+            .frame_buffer()
+            .iter_rows()
+            .eq(mapped.frame_buffer().iter_rows()));
+    }
+    assert!(log.borrow().is_empty());
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Draws a pattern of `#` and `.` at the top left corner.
+fn frame_buffer_with(pattern: &[&str]) -> FrameBuffer {
+    let mut fb = FrameBuffer::default();
+    for (y, row) in pattern.iter().enumerate() {
+        for (x, pixel) in row.chars().enumerate() {
+            if pixel == '#' {
+                let point = Point {
+                    x: x as u8,
+                    y: y as u8,
+                };
+                fb.flip(point, Point { x: 0, y: 0 });
+            }
+        }
+    }
+    fb
+}
+
+const PATTERN: &[&str] = &["##..##..", "#.#.#.#.", "........", "########"];
+
+#[test]
+fn test_text_renderer() {
+    let mut renderer = TextRenderer::with_chars('#', '.');
+    renderer.render(&frame_buffer_with(PATTERN)).unwrap();
+
+    let lines = renderer.text().split('\n').collect::<Vec<_>>();
+    assert_eq!(lines.len(), 32);
+    for (line, pattern) in lines.iter().zip(PATTERN) {
+        assert_eq!(*line, format!("{pattern}{}", ".".repeat(56)));
+    }
+    assert!(lines[4..].iter().all(|line| *line == ".".repeat(64)));
+
+    // The previous frame is replaced, and the default characters are the ones of
+    // `check_display`.
+    let mut renderer = TextRenderer::new();
+    renderer.render(&frame_buffer_with(PATTERN)).unwrap();
+    renderer.render(&frame_buffer_with(&["#"])).unwrap();
+    let mut expected = vec![" ".repeat(64); 32];
+    expected[0] = format!("▓{}", " ".repeat(63));
+    assert_eq!(renderer.text(), expected.join("\n"));
+}
+
+#[test]
+fn test_ansi_terminal_renderer() {
+    let mut renderer = AnsiTerminalRenderer::new(Vec::new());
+    renderer.render(&frame_buffer_with(PATTERN)).unwrap();
+
+    let blocks = |count| "▀".repeat(count);
+    let mut expected = String::from("\x1b[H");
+    expected += "\x1b[37;47m▀\x1b[37;40m▀\x1b[30;47m▀\x1b[30;40m▀";
+    expected += "\x1b[37;47m▀\x1b[37;40m▀\x1b[30;47m▀\x1b[30;40m▀";
+    expected += &format!("{}\x1b[0m\r\n", blocks(56));
+    expected += &format!("\x1b[30;47m{}\x1b[30;40m{}\x1b[0m", blocks(8), blocks(56));
+    for _ in 2..16 {
+        expected += &format!("\r\n\x1b[30;40m{}\x1b[0m", blocks(64));
+    }
+
+    let output = renderer.into_writer();
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
+
+#[test]
+fn test_ansi_terminal_renderer_partial_redraw() {
+    let mut fb = frame_buffer_with(PATTERN);
+    let mut renderer = AnsiTerminalRenderer::new(Vec::new());
+    renderer.render(&fb).unwrap();
+    let full_output = std::mem::take(renderer.writer_mut());
+
+    renderer.render(&fb).unwrap();
+    assert!(renderer.writer_mut().is_empty());
+
+    fb.flip(Point { x: 3, y: 5 }, Point { x: 0, y: 0 });
+    fb.flip(Point { x: 0, y: 31 }, Point { x: 0, y: 0 });
+    renderer.render(&fb).unwrap();
+    let expected = format!(
+        "\x1b[3;1H\x1b[30;40m▀▀▀\x1b[30;47m▀\x1b[30;40m{}\x1b[0m\
+         \x1b[16;1H\x1b[30;47m▀\x1b[30;40m{}\x1b[0m",
+        "▀".repeat(60),
+        "▀".repeat(63),
+    );
+    let output = std::mem::take(renderer.writer_mut());
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+    // Back to the first frame, after a full redraw.
+    fb.flip(Point { x: 3, y: 5 }, Point { x: 0, y: 0 });
+    fb.flip(Point { x: 0, y: 31 }, Point { x: 0, y: 0 });
+    renderer.invalidate();
+    renderer.render(&fb).unwrap();
+    assert_eq!(*renderer.writer_mut(), full_output);
+
+    let mut renderer = AnsiTerminalRenderer::new(Vec::new()).with_partial_redraw(false);
+    renderer.render(&fb).unwrap();
+    renderer.render(&fb).unwrap();
+    assert_eq!(
+        renderer.into_writer(),
+        [&full_output[..], &full_output[..]].concat()
+    );
+}
+
+#[test]
+fn test_frame_buffer_packed() {
+    let mut fb = frame_buffer_with(PATTERN);
+    fb.flip(Point { x: 63, y: 31 }, Point { x: 0, y: 0 });
+    fb.flip(Point { x: 9, y: 0 }, Point { x: 0, y: 0 });
+
+    let packed = fb.to_packed();
+    assert_eq!(packed.len(), PACKED_FRAME_SIZE);
+    let mut expected = [0; PACKED_FRAME_SIZE];
+    expected[0] = 0b1100_1100;
+    expected[1] = 0b0100_0000;
+    expected[8] = 0b1010_1010;
+    expected[24] = 0b1111_1111;
+    expected[PACKED_FRAME_SIZE - 1] = 0b0000_0001;
+    assert_eq!(packed, expected);
+
+    assert_eq!(FrameBuffer::default().to_packed(), [0; PACKED_FRAME_SIZE]);
+}
+
+#[test]
+fn test_image_too_big() {
+    assert!(Ch8Image::new([0; 0x1000 - 0x200]).is_ok());
+    assert!(Ch8Image::new([0; 0x1000 - 0x200 + 1]).is_err());
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Run with `cargo test --features web`.
+#[cfg(feature = "web")]
+mod web {
+    use chip8::{WebChip8, WebError, PACKED_FRAME_SIZE};
+
+    // Draws the sprite of a `0` at (0, 0), then loops.
+    const DRAW_ZERO: &[u8] = &[
+        0xA2, 0x06, 0xD0, 0x05, 0x12, 0x04, 0xF0, 0x90, 0x90, 0x90, 0xF0,
+    ];
+
+    #[test]
+    fn test_web_run() {
+        let mut chip8 = WebChip8::try_new(DRAW_ZERO, 42).unwrap();
+        assert_eq!(chip8.frame_buffer_packed(), vec![0; PACKED_FRAME_SIZE]);
+        chip8.try_run_ms(10).unwrap();
+
+        let packed = chip8.frame_buffer_packed();
+        assert_eq!(packed.len(), PACKED_FRAME_SIZE);
+        let rows = [0xF0, 0x90, 0x90, 0x90, 0xF0];
+        for (y, row) in rows.iter().enumerate() {
+            assert_eq!(packed[y * 8], *row);
+        }
+        assert_eq!(packed.iter().filter(|byte| **byte != 0).count(), 5);
+        assert!(!chip8.beeping());
+
+        chip8.try_set_key_down(0xf, true).unwrap();
+        chip8.try_set_key_down(0xf, false).unwrap();
+    }
+
+    #[test]
+    fn test_web_errors() {
+        let error = WebChip8::try_new(&[0; 0x1000], 0).err().unwrap();
+        assert!(matches!(error, WebError::Rom(_)));
+        assert_eq!(error.to_string(), "invalid ROM: image is too big");
+
+        let mut chip8 = WebChip8::try_new(DRAW_ZERO, 0).unwrap();
+        let error = chip8.try_set_key_down(0x10, true).unwrap_err();
+        assert_eq!(error.to_string(), "invalid key: 0x10, expected 0x0 to 0xf");
+
+        // 8xyF is not an instruction.
+        let mut chip8 = WebChip8::try_new(&[0x80, 0x0F], 0).unwrap();
+        let error = chip8.try_run_ms(10).unwrap_err();
+        assert!(matches!(error, WebError::Interpreter(_)));
+        assert!(error.to_string().starts_with("unknown opcode"), "{error}");
+    }
+}
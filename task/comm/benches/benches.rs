@@ -1,14 +1,19 @@
 use std::{
     io::{self, BufWriter, Write},
     iter,
+    path::Path,
     process::Command,
 };
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{
+    black_box, criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, Criterion,
+};
 use rand::{distributions::Alphanumeric, seq::SliceRandom, thread_rng, Rng};
 use tempfile::{NamedTempFile, TempPath};
 
 const RUST_BINARY_PATH: &str = "../../target/release/comm";
+/// Built with the `large-buffers` feature by `cargo xtask bench`, skipped if missing.
+const RUST_LARGE_BUFFERS_BINARY_PATH: &str = "../../target/release/comm_large_buffers";
 const CPP_BINARY_PATH: &str = "../../target/release/comm_cpp";
 
 fn create_tempfile(data: &[String]) -> io::Result<TempPath> {
@@ -35,6 +40,30 @@ fn run_comm(path: &str, first: &TempPath, second: &TempPath) {
     assert!(output.status.success(), "comm process failed");
 }
 
+fn bench_binaries(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    first_path: &TempPath,
+    second_path: &TempPath,
+) {
+    group.bench_function("rust", |b| {
+        b.iter(|| black_box(run_comm(RUST_BINARY_PATH, first_path, second_path)))
+    });
+    if Path::new(RUST_LARGE_BUFFERS_BINARY_PATH).exists() {
+        group.bench_function("rust_large_buffers", |b| {
+            b.iter(|| {
+                black_box(run_comm(
+                    RUST_LARGE_BUFFERS_BINARY_PATH,
+                    first_path,
+                    second_path,
+                ))
+            })
+        });
+    }
+    group.bench_function("cpp", |b| {
+        b.iter(|| black_box(run_comm(CPP_BINARY_PATH, first_path, second_path)))
+    });
+}
+
 fn generate_input(
     common: usize,
     left_unique: usize,
@@ -74,13 +103,7 @@ fn bench_50k_50k(c: &mut Criterion) {
     let (first, second) = generate_input(50_000, 50_000, 50_000);
     let (first_path, second_path) =
         create_tempfiles(&first, &second).expect("failed to create tempfiles");
-
-    group.bench_function("rust", |b| {
-        b.iter(|| black_box(run_comm(RUST_BINARY_PATH, &first_path, &second_path)))
-    });
-    group.bench_function("cpp", |b| {
-        b.iter(|| black_box(run_comm(CPP_BINARY_PATH, &first_path, &second_path)))
-    });
+    bench_binaries(&mut group, &first_path, &second_path);
 }
 
 fn bench_0_100k(c: &mut Criterion) {
@@ -92,13 +115,7 @@ fn bench_0_100k(c: &mut Criterion) {
     let (first, second) = generate_input(0, 100_000, 100_000);
     let (first_path, second_path) =
         create_tempfiles(&first, &second).expect("failed to create tempfiles");
-
-    group.bench_function("rust", |b| {
-        b.iter(|| black_box(run_comm(RUST_BINARY_PATH, &first_path, &second_path)))
-    });
-    group.bench_function("cpp", |b| {
-        b.iter(|| black_box(run_comm(CPP_BINARY_PATH, &first_path, &second_path)))
-    });
+    bench_binaries(&mut group, &first_path, &second_path);
 }
 
 criterion_group!(benches, bench_50k_50k, bench_0_100k);
@@ -1,31 +1,164 @@
+mod parallel;
+mod watch;
+
 use std::{
-    collections::HashSet,
     env::args,
     fs::File,
-    io::{stdout, BufRead, BufReader, BufWriter, Result, Write},
+    io::{self, stdout, BufRead, BufReader, BufWriter, Read, Result, Write},
+    num::NonZero,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use parallel::{
+    chunk_lines, for_each_chunk, hash_line, random_seed, read_line_hashes, strip_newline,
+    CHUNK_SIZE,
 };
+use watch::{format_time, ChangeDetector, Sample, Status};
+
+/// With the `large-buffers` feature, the size of the read buffer of the second file and
+/// of the output buffer, so that both take few syscalls.
+const LARGE_BUFFER_SIZE: usize = 2 << 20;
+
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    first_path: String,
+    second_path: String,
+    /// Set by `--watch`, how often to poll the files, see `watch`.
+    watch_interval: Option<Duration>,
+}
+
+/// `None` if the arguments don't make sense, flags may go anywhere.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Option<Options> {
+    let mut paths = vec![];
+    let mut watch = false;
+    let mut interval = DEFAULT_WATCH_INTERVAL;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--watch" => watch = true,
+            "--watch-interval-ms" => {
+                let millis = args.next()?.parse().ok().filter(|&millis| millis > 0)?;
+                interval = Duration::from_millis(millis);
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let [first_path, second_path] = <[String; 2]>::try_from(paths).ok()?;
+    Some(Options {
+        first_path,
+        second_path,
+        watch_interval: watch.then_some(interval),
+    })
+}
 
 fn main() -> Result<()> {
     let args = args().collect::<Vec<String>>();
-    if args.len() < 3 {
-        eprintln!("usage: {} [file_1] [file_2]", args[0]);
+    let Some(options) = parse_args(args.iter().skip(1).cloned()) else {
+        eprintln!(
+            "usage: {} [--watch] [--watch-interval-ms N] [file_1] [file_2]",
+            args[0]
+        );
         return Ok(());
+    };
+
+    let paths = [&options.first_path, &options.second_path];
+    let samples = paths.map(|path| Sample::of(Path::new(path)));
+    compare_files(paths)?;
+    if let Some(interval) = options.watch_interval {
+        watch(paths, samples, interval)?;
     }
+    Ok(())
+}
 
-    let mut first_file_lines = read_file(&args[1])?;
+/// Prints the lines common to both files to stdout.
+fn compare_files([first_path, second_path]: [&String; 2]) -> Result<()> {
+    let first_file = File::open(first_path)?;
+    let threads = thread::available_parallelism().map_or(1, NonZero::get);
 
-    let second_file = File::open(&args[2])?;
-    let reader = BufReader::new(second_file);
-    let mut writer = BufWriter::new(stdout());
+    if cfg!(feature = "large-buffers") {
+        let second_file = File::open(second_path)?;
+        let writer = BufWriter::with_capacity(LARGE_BUFFER_SIZE, stdout());
+        return comm_large_buffers(first_file, second_file, writer, threads, LARGE_BUFFER_SIZE);
+    }
 
-    for line in reader.lines() {
-        let line = line?;
+    let second_file = BufReader::new(File::open(second_path)?);
+    let writer = BufWriter::new(stdout());
+    comm(first_file, second_file, writer, threads)
+}
 
-        if first_file_lines.contains(&line) {
-            writer.write_all(line.as_bytes())?;
-            writer.write_all(b"\n")?;
+/// Reruns `compare_files` every time either file settles in a new state, see
+/// `ChangeDetector`, until Ctrl-C. The `samples` are the ones taken before the first run.
+///
+/// The output of every rerun follows a separator line telling when and which file has
+/// changed. Errors, e.g. a file removed for good, are reported and watching goes on.
+fn watch(paths: [&String; 2], samples: [Sample; 2], interval: Duration) -> Result<()> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    ctrlc::set_handler({
+        let interrupted = interrupted.clone();
+        move || interrupted.store(true, Ordering::Relaxed)
+    })
+    .map_err(io::Error::other)?;
+
+    let mut detectors = samples.map(ChangeDetector::new);
+    loop {
+        thread::sleep(interval);
+        if interrupted.load(Ordering::Relaxed) {
+            return Ok(());
+        }
 
-            first_file_lines.take(&line);
+        let mut changed = vec![];
+        for (path, detector) in paths.iter().zip(&mut detectors) {
+            if detector.poll(Sample::of(Path::new(path))) == Status::Changed {
+                changed.push(path.as_str());
+            }
+        }
+        if changed.is_empty() {
+            continue;
+        }
+
+        println!(
+            "==> {}: {} changed <==",
+            format_time(SystemTime::now()),
+            changed.join(", ")
+        );
+        if let Err(err) = compare_files(paths) {
+            eprintln!("failed to compare the files: {err}");
+        }
+    }
+}
+
+/// Lines are compared as bytes and may be in any encoding. Only `\n` ends a line.
+///
+/// Lines of the first file are hashed in parallel, see `parallel::read_line_hashes`,
+/// then the second file is streamed and probes the hashes.
+fn comm(
+    first: impl Read,
+    mut second: impl BufRead,
+    mut writer: impl Write,
+    threads: usize,
+) -> Result<()> {
+    let seed = random_seed();
+    let mut first_file_lines = read_line_hashes(first, seed, threads, CHUNK_SIZE)?;
+
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if second.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let line = strip_newline(&line);
+
+        if first_file_lines.remove(&hash_line(line, seed)) {
+            writer.write_all(line)?;
+            writer.write_all(b"\n")?;
         }
     }
 
@@ -34,15 +167,227 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn read_file(path: &String) -> Result<HashSet<String>> {
-    let mut lines = HashSet::new();
+/// Same as `comm`, but the second file is read by chunks of about `buffer_size` bytes,
+/// see `parallel::for_each_chunk`, and its lines are probed right in the chunks instead
+/// of being copied out of a `BufReader` one by one.
+fn comm_large_buffers(
+    first: impl Read,
+    second: impl Read,
+    mut writer: impl Write,
+    threads: usize,
+    buffer_size: usize,
+) -> Result<()> {
+    let seed = random_seed();
+    let mut first_file_lines = read_line_hashes(first, seed, threads, CHUNK_SIZE)?;
+
+    // Chunks are passed to an infallible callback, so the first write error is kept
+    // and the rest of the input is skipped.
+    let mut write_result = Ok(());
+    for_each_chunk(second, buffer_size, |chunk| {
+        if write_result.is_err() {
+            return;
+        }
+        write_result = chunk_lines(&chunk)
+            .filter(|line| first_file_lines.remove(&hash_line(line, seed)))
+            .try_for_each(|line| {
+                writer.write_all(line)?;
+                writer.write_all(b"\n")
+            });
+    })?;
+    write_result?;
+
+    writer.flush()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{collections::HashSet, io::Seek, time::Instant};
 
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
-    for line in reader.lines() {
-        lines.insert(line?);
+    /// The original single-threaded implementation, comparing bytes instead of strings.
+    fn comm_single_threaded(
+        first: impl BufRead,
+        second: impl BufRead,
+        mut writer: impl Write,
+    ) -> Result<()> {
+        let mut first_file_lines = HashSet::new();
+        for line in first.split(b'\n') {
+            first_file_lines.insert(line?);
+        }
+
+        for line in second.split(b'\n') {
+            let line = line?;
+
+            if first_file_lines.contains(&line) {
+                writer.write_all(&line)?;
+                writer.write_all(b"\n")?;
+
+                first_file_lines.take(&line);
+            }
+        }
+
+        writer.flush()
+    }
+
+    fn sorted_lines(output: &[u8]) -> Vec<&[u8]> {
+        let mut lines = parallel::chunk_lines(output).collect::<Vec<_>>();
+        lines.sort_unstable();
+        lines
+    }
+
+    fn random_input(rng: &mut StdRng) -> Vec<u8> {
+        const ALPHABET: &[u8] = b"ab\n\n\xff\xd0\r ";
+        let len = rng.gen_range(0..200);
+        (0..len)
+            .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())])
+            .collect()
+    }
+
+    #[test]
+    fn same_as_single_threaded() {
+        let mut rng = StdRng::seed_from_u64(4518);
+        for _ in 0..2000 {
+            let first = random_input(&mut rng);
+            let second = random_input(&mut rng);
+            let threads = rng.gen_range(1..5);
+
+            let mut expected = vec![];
+            comm_single_threaded(&first[..], &second[..], &mut expected).unwrap();
+            let mut output = vec![];
+            comm(&first[..], &second[..], &mut output, threads).unwrap();
+
+            assert_eq!(
+                sorted_lines(&output),
+                sorted_lines(&expected),
+                "first: {first:?}, second: {second:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn lines_spanning_chunks() {
+        let mut first = vec![];
+        for i in 0..20_000 {
+            writeln!(first, "{}", "x".repeat(i % 100)).unwrap();
+        }
+        let hashes = read_line_hashes(&first[..], random_seed(), 3, 1000).unwrap();
+        assert_eq!(hashes.len(), 100);
+
+        let mut output = vec![];
+        comm(&first[..], &b"xxx\ny"[..], &mut output, 3).unwrap();
+        assert_eq!(output, b"xxx\n");
+    }
+
+    #[test]
+    fn large_buffers_same_output() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            b"\n",
+            b"\n\n\n",
+            b"foo",
+            b"foo\nbar",
+            b"foo\n\nbar\n",
+            "привет\nмир\n\n日本語\nfoo".as_bytes(),
+            "мир\n\nпривет\n日本\n日本語".as_bytes(),
+            b"\xd0\n\xd0\xbf\r\n\xff",
+        ];
+        for first in inputs {
+            for second in inputs {
+                let mut expected = vec![];
+                comm(*first, *second, &mut expected, 2).unwrap();
+                for buffer_size in [1, 3, 8, LARGE_BUFFER_SIZE] {
+                    let mut output = vec![];
+                    comm_large_buffers(*first, *second, &mut output, 2, buffer_size).unwrap();
+                    assert_eq!(
+                        output, expected,
+                        "first: {first:?}, second: {second:?}, buffer size {buffer_size}"
+                    );
+                }
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(4536);
+        for _ in 0..500 {
+            let first = random_input(&mut rng);
+            let second = random_input(&mut rng);
+            let buffer_size = rng.gen_range(1..50);
+
+            let mut expected = vec![];
+            comm(&first[..], &second[..], &mut expected, 1).unwrap();
+            let mut output = vec![];
+            comm_large_buffers(&first[..], &second[..], &mut output, 3, buffer_size).unwrap();
+            assert_eq!(output, expected, "first: {first:?}, second: {second:?}");
+        }
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
     }
 
-    Ok(lines)
+    #[test]
+    fn watch_args() {
+        let options = parse_args(strings(&["a", "b"])).unwrap();
+        assert_eq!(options.first_path, "a");
+        assert_eq!(options.second_path, "b");
+        assert_eq!(options.watch_interval, None);
+
+        let options = parse_args(strings(&["a", "--watch", "b"])).unwrap();
+        assert_eq!(options.watch_interval, Some(DEFAULT_WATCH_INTERVAL));
+        let options = parse_args(strings(&[
+            "--watch-interval-ms",
+            "100",
+            "a",
+            "b",
+            "--watch",
+        ]))
+        .unwrap();
+        assert_eq!(options.watch_interval, Some(Duration::from_millis(100)));
+
+        for args in [
+            &["a"][..],
+            &["a", "b", "c"],
+            &["a", "b", "--watch-interval-ms"],
+            &["a", "b", "--watch-interval-ms", "0"],
+            &["a", "b", "--watch-interval-ms", "soon"],
+        ] {
+            assert_eq!(parse_args(strings(args)), None, "{args:?}");
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn parallel_speedup() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut file = tempfile::tempfile().unwrap();
+        {
+            let mut writer = BufWriter::new(&mut file);
+            for _ in 0..10_000_000 {
+                writeln!(writer, "{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>()).unwrap();
+            }
+        }
+        let second = b"0\n1\n";
+
+        let mut measure = |f: &mut dyn FnMut(BufReader<&File>)| {
+            file.rewind().unwrap();
+            let start = Instant::now();
+            f(BufReader::new(&file));
+            start.elapsed()
+        };
+        let single = measure(&mut |first| {
+            comm_single_threaded(first, &second[..], std::io::sink()).unwrap()
+        });
+        let threads = thread::available_parallelism().map_or(1, NonZero::get);
+        let parallel =
+            measure(&mut |first| comm(first, &second[..], std::io::sink(), threads).unwrap());
+
+        println!(
+            "single-threaded: {single:?}, {threads} threads: {parallel:?}, speedup: {:.2}",
+            single.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
 }
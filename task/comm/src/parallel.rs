@@ -0,0 +1,289 @@
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    hash::{BuildHasher, BuildHasherDefault, Hasher},
+    io::{Read, Result},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const CHUNK_SIZE: usize = 4 << 20;
+
+/// Lines are identified by 128-bit hashes instead of being stored. Collisions are not
+/// handled: among n distinct lines one happens with probability about n² / 2¹²⁹,
+/// i.e. never for any input that fits on a disk. The seed is random for every run,
+/// so even a crafted input can't collide reliably.
+pub type LineHashSet = HashSet<u128, BuildHasherDefault<PrehashedHasher>>;
+
+/// Keys of a `LineHashSet` are hashes already, so there is no point hashing them again.
+#[derive(Default)]
+pub struct PrehashedHasher(u64);
+
+impl Hasher for PrehashedHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    fn write_u128(&mut self, hash: u128) {
+        self.0 = hash as u64;
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+const PRIME_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME_3: u64 = 0x1656_67b1_9e37_79f9;
+
+fn avalanche(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^ (hash >> 32)
+}
+
+/// A two-lane hash in the style of xxHash64, one lane per half of the result.
+pub fn hash_line(line: &[u8], seed: u64) -> u128 {
+    let mut low = seed.wrapping_add(PRIME_1);
+    let mut high = seed.rotate_left(32).wrapping_add(PRIME_2);
+    let mut mix = |word: u64| {
+        low = (low ^ word.wrapping_mul(PRIME_2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME_1);
+        high = high
+            .wrapping_add(word.wrapping_mul(PRIME_3))
+            .rotate_left(27)
+            .wrapping_mul(PRIME_2);
+    };
+
+    let mut words = line.chunks_exact(8);
+    for word in &mut words {
+        mix(u64::from_le_bytes(word.try_into().unwrap()));
+    }
+    let mut tail = [0; 8];
+    tail[..words.remainder().len()].copy_from_slice(words.remainder());
+    mix(u64::from_le_bytes(tail));
+    mix(line.len() as u64);
+
+    let low = avalanche(low ^ high.rotate_left(17));
+    let high = avalanche(high ^ low);
+    (u128::from(high) << 64) | u128::from(low)
+}
+
+/// Splits off the line terminator, `\n` only: the bytes of a line are kept exactly.
+pub fn strip_newline(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\n").unwrap_or(line)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reads `reader` by about `chunk_size` bytes and passes them to `f` split on line
+/// boundaries: every chunk consists of complete lines, each ending with `\n`, except
+/// the last one if the input has no trailing newline. A line longer than `chunk_size`
+/// makes a longer chunk. Concatenated chunks give the input back.
+pub fn for_each_chunk(
+    mut reader: impl Read,
+    chunk_size: usize,
+    mut f: impl FnMut(Vec<u8>),
+) -> Result<()> {
+    let mut carry = vec![];
+    loop {
+        let mut chunk = std::mem::take(&mut carry);
+        let start = chunk.len();
+        chunk.reserve(chunk_size);
+        let read = (&mut reader)
+            .take(chunk_size as u64)
+            .read_to_end(&mut chunk)?;
+
+        if read == 0 {
+            if !chunk.is_empty() {
+                f(chunk);
+            }
+            return Ok(());
+        }
+
+        match chunk[start..].iter().rposition(|&byte| byte == b'\n') {
+            Some(i) => {
+                carry = chunk.split_off(start + i + 1);
+                f(chunk);
+            }
+            None => carry = chunk,
+        }
+    }
+}
+
+pub fn chunk_lines(chunk: &[u8]) -> impl Iterator<Item = &[u8]> {
+    chunk
+        .split_inclusive(|&byte| byte == b'\n')
+        .map(strip_newline)
+}
+
+/// Hashes the lines of `reader` on `threads` workers, while the current thread reads.
+pub fn read_line_hashes(
+    reader: impl Read,
+    seed: u64,
+    threads: usize,
+    chunk_size: usize,
+) -> Result<LineHashSet> {
+    let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(threads * 2);
+    let receiver = Mutex::new(receiver);
+
+    let (read_result, mut sets) = thread::scope(|scope| {
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut hashes = LineHashSet::default();
+                    loop {
+                        // Not in `while let`, so that the lock is not held while hashing.
+                        let received = receiver.lock().unwrap().recv();
+                        let Ok(chunk) = received else {
+                            return hashes;
+                        };
+                        hashes.extend(chunk_lines(&chunk).map(|line| hash_line(line, seed)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let read_result = for_each_chunk(reader, chunk_size, |chunk| {
+            // Workers never stop first, so sending can't fail.
+            sender.send(chunk).unwrap();
+        });
+        drop(sender);
+
+        let sets = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>();
+        (read_result, sets)
+    });
+    read_result?;
+
+    // Merge into the largest set to move the fewest hashes.
+    sets.sort_by_key(|set| std::cmp::Reverse(set.len()));
+    let mut sets = sets.into_iter();
+    let mut merged = sets.next().unwrap_or_default();
+    for set in sets {
+        merged.extend(set);
+    }
+    Ok(merged)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(input: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+        let mut chunks = vec![];
+        for_each_chunk(input, chunk_size, |chunk| chunks.push(chunk)).unwrap();
+        chunks
+    }
+
+    fn check_chunks(input: &[u8], chunk_size: usize) {
+        let chunks = chunks(input, chunk_size);
+        assert_eq!(chunks.concat(), input, "chunk size {chunk_size}");
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(!chunk.is_empty());
+            if i + 1 < chunks.len() {
+                assert!(chunk.ends_with(b"\n"), "chunk #{i} of {chunks:?}");
+            }
+        }
+
+        let expected = input
+            .split_inclusive(|&byte| byte == b'\n')
+            .map(strip_newline)
+            .collect::<Vec<_>>();
+        let lines = chunks
+            .iter()
+            .flat_map(|chunk| chunk_lines(chunk))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, expected, "chunk size {chunk_size}");
+    }
+
+    #[test]
+    fn chunks_simple() {
+        assert_eq!(chunks(b"", 4), Vec::<Vec<u8>>::new());
+        assert_eq!(chunks(b"ab\ncd\n", 4), [b"ab\n".to_vec(), b"cd\n".to_vec()]);
+        assert_eq!(chunks(b"ab\ncd\n", 100), [b"ab\ncd\n".to_vec()]);
+        assert_eq!(
+            chunks(b"abcdef\ng", 2),
+            [b"abcdef\n".to_vec(), b"g".to_vec()]
+        );
+    }
+
+    #[test]
+    fn chunks_without_trailing_newline() {
+        for chunk_size in 1..10 {
+            check_chunks(b"foo", chunk_size);
+            check_chunks(b"foo\nbar", chunk_size);
+            check_chunks(b"\n\nbar", chunk_size);
+        }
+        assert_eq!(chunks(b"foo\nbar", 4), [b"foo\n".to_vec(), b"bar".to_vec()]);
+    }
+
+    #[test]
+    fn lines_spanning_chunk_boundaries() {
+        let input = b"a\nbb\n\nccccccccccc\nd\nee\xff\xfe\r\n\n\nfff fff\n\xd0\xbf\n";
+        for chunk_size in 1..=input.len() + 1 {
+            check_chunks(input, chunk_size);
+        }
+    }
+
+    #[test]
+    fn empty_lines() {
+        assert_eq!(chunk_lines(b"\n\n").collect::<Vec<_>>(), [b"", b""]);
+        assert_eq!(chunk_lines(b"").count(), 0);
+        for chunk_size in 1..4 {
+            check_chunks(b"\n", chunk_size);
+            check_chunks(b"\n\n\n", chunk_size);
+        }
+    }
+
+    #[test]
+    fn hashes_depend_on_all_bytes() {
+        let lines: &[&[u8]] = &[
+            b"",
+            b"\0",
+            b"\0\0",
+            b"a",
+            b"a\0",
+            b"abcdefgh",
+            b"abcdefgh\0",
+            b"abcdefgi",
+            b"bbcdefgh",
+        ];
+        let hashes = lines
+            .iter()
+            .map(|line| hash_line(line, 1))
+            .collect::<HashSet<_>>();
+        assert_eq!(hashes.len(), lines.len());
+        assert_ne!(hash_line(b"a", 1), hash_line(b"a", 2));
+    }
+
+    #[test]
+    fn hashes_of_all_threads_are_merged() {
+        let input = (0..10_000)
+            .map(|i| format!("line {}\n", i % 3_000))
+            .collect::<String>();
+        for threads in [1, 2, 7] {
+            let hashes = read_line_hashes(input.as_bytes(), 42, threads, 64).unwrap();
+            assert_eq!(hashes.len(), 3_000);
+            assert!(hashes.contains(&hash_line(b"line 2999", 42)));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Polling the input files for changes in `--watch` mode, see `ChangeDetector`.
+
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What a poll tells about a file. Missing files are all the same sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub mtime: SystemTime,
+    pub size: u64,
+    pub exists: bool,
+}
+
+impl Sample {
+    pub const MISSING: Self = Self {
+        mtime: UNIX_EPOCH,
+        size: 0,
+        exists: false,
+    };
+
+    /// A file that can't be inspected at all counts as missing, its rerun reports why.
+    pub fn of(path: &Path) -> Self {
+        match fs::metadata(path) {
+            Ok(metadata) => Self {
+                mtime: metadata.modified().unwrap_or(UNIX_EPOCH),
+                size: metadata.len(),
+                exists: true,
+            },
+            Err(_) => Self::MISSING,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Unchanged,
+    /// The file has changed, but is still being written, or is missing for now.
+    Settling,
+    /// The file has settled in a state other than the one of the last run.
+    Changed,
+}
+
+/// Tells when a file polled with `poll` has changed since the last run and is safe to
+/// read again.
+///
+/// A change is only reported once two polls in a row see the same sample, so that a file
+/// is not read in the middle of a write. Editors often save by removing the file and
+/// writing it anew, so a missing file has to stay missing for `MISSING_GRACE_POLLS`
+/// polls in a row before it's reported.
+#[derive(Debug)]
+pub struct ChangeDetector {
+    /// The sample the last run has seen.
+    current: Sample,
+    /// The last sample, if it differs from `current`.
+    pending: Option<Sample>,
+    /// How many polls in a row have seen `pending`, besides the first one.
+    stable_polls: u32,
+}
+
+impl ChangeDetector {
+    pub const MISSING_GRACE_POLLS: u32 = 4;
+
+    pub fn new(initial: Sample) -> Self {
+        Self {
+            current: initial,
+            pending: None,
+            stable_polls: 0,
+        }
+    }
+
+    pub fn poll(&mut self, sample: Sample) -> Status {
+        if self.pending != Some(sample) {
+            if sample == self.current {
+                // Changed back before settling, e.g. recreated with the same mtime.
+                self.pending = None;
+                return Status::Unchanged;
+            }
+            self.pending = Some(sample);
+            self.stable_polls = 0;
+            return Status::Settling;
+        }
+
+        self.stable_polls += 1;
+        let required = if sample.exists {
+            1
+        } else {
+            Self::MISSING_GRACE_POLLS
+        };
+        if self.stable_polls < required {
+            return Status::Settling;
+        }
+        self.current = sample;
+        self.pending = None;
+        Status::Changed
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// `HH:MM:SS` of the time of day in UTC, there is no time zone database at hand.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use Status::*;
+
+    fn sample(mtime_secs: u64, size: u64) -> Sample {
+        Sample {
+            mtime: UNIX_EPOCH + Duration::from_secs(mtime_secs),
+            size,
+            exists: true,
+        }
+    }
+
+    fn statuses(detector: &mut ChangeDetector, samples: &[Sample]) -> Vec<Status> {
+        samples.iter().map(|&s| detector.poll(s)).collect()
+    }
+
+    #[test]
+    fn no_change() {
+        let mut detector = ChangeDetector::new(sample(1, 10));
+        assert_eq!(statuses(&mut detector, &[sample(1, 10); 5]), [Unchanged; 5]);
+
+        let mut detector = ChangeDetector::new(Sample::MISSING);
+        assert_eq!(
+            statuses(&mut detector, &[Sample::MISSING; 10]),
+            [Unchanged; 10]
+        );
+    }
+
+    #[test]
+    fn single_write() {
+        let mut detector = ChangeDetector::new(sample(1, 10));
+        assert_eq!(
+            statuses(
+                &mut detector,
+                &[sample(1, 10), sample(2, 12), sample(2, 12), sample(2, 12)]
+            ),
+            [Unchanged, Settling, Changed, Unchanged]
+        );
+    }
+
+    #[test]
+    fn rapid_successive_writes() {
+        // The file grows on every poll, only the final state is worth a rerun.
+        let mut detector = ChangeDetector::new(sample(1, 10));
+        assert_eq!(
+            statuses(
+                &mut detector,
+                &[sample(2, 20), sample(2, 30), sample(3, 30), sample(3, 30)]
+            ),
+            [Settling, Settling, Settling, Changed]
+        );
+
+        // Written back to what it was before settling.
+        assert_eq!(
+            statuses(
+                &mut detector,
+                &[sample(4, 40), sample(3, 30), sample(3, 30)]
+            ),
+            [Settling, Unchanged, Unchanged]
+        );
+    }
+
+    #[test]
+    fn deletion_then_recreation() {
+        // Saved by removing and writing anew within the grace polls: a single change.
+        let mut detector = ChangeDetector::new(sample(1, 10));
+        assert_eq!(
+            statuses(
+                &mut detector,
+                &[
+                    Sample::MISSING,
+                    Sample::MISSING,
+                    Sample::MISSING,
+                    sample(2, 10),
+                    sample(2, 10),
+                    sample(2, 10),
+                ]
+            ),
+            [Settling, Settling, Settling, Settling, Changed, Unchanged]
+        );
+
+        // Removed for good, then recreated much later.
+        let mut polls = vec![Sample::MISSING; ChangeDetector::MISSING_GRACE_POLLS as usize + 3];
+        polls.extend([sample(3, 5), sample(3, 5)]);
+        let mut expected = vec![Settling; ChangeDetector::MISSING_GRACE_POLLS as usize];
+        expected.extend([Changed, Unchanged, Unchanged, Settling, Changed]);
+        assert_eq!(statuses(&mut detector, &polls), expected);
+    }
+
+    #[test]
+    fn time_of_day() {
+        let time = UNIX_EPOCH + Duration::from_secs(3 * 24 * 3600 + 13 * 3600 + 5 * 60 + 9);
+        assert_eq!(format_time(time), "13:05:09 UTC");
+    }
+}
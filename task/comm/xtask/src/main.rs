@@ -28,6 +28,12 @@ fn bench() -> Result<()> {
     let repo_path = get_cwd_repo_path()?;
     let sh = Shell::new().context("failed to create shell")?;
 
+    // The same binary, first with the `large-buffers` feature and then without it.
+    cmd!(sh, "cargo build --release --features large-buffers").run()?;
+    sh.copy_file(
+        repo_path.join("target/release/comm"),
+        repo_path.join("target/release/comm_large_buffers"),
+    )?;
     cmd!(sh, "cargo build --release").run()?;
     cmd!(
         sh,
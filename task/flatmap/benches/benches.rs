@@ -114,10 +114,51 @@ fn bench_100k_random_lookup_misses(c: &mut Criterion) {
     });
 }
 
+fn bench_100k_random_bulk_construction(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(8234923402);
+    let insertions: Vec<_> = (0..100_000)
+        .map(|_| (rng.gen::<i64>(), rng.gen::<i64>()))
+        .collect();
+    let (head, tail) = insertions.split_at(insertions.len() / 2);
+
+    let mut group = c.benchmark_group("100k_random_bulk_construction");
+
+    group.bench_function("flat_map", |b| {
+        b.iter(|| {
+            black_box({
+                let mut map = FlatMap::from_iter(head.iter().copied());
+                map.extend(tail.iter().copied());
+                map
+            })
+        })
+    });
+
+    group.bench_function("btree_map", |b| {
+        b.iter(|| {
+            black_box({
+                let mut map = BTreeMap::from_iter(head.iter().copied());
+                map.extend(tail.iter().copied());
+                map
+            })
+        })
+    });
+
+    group.bench_function("hash_map", |b| {
+        b.iter(|| {
+            black_box({
+                let mut map = HashMap::<_, _, RandomState>::from_iter(head.iter().copied());
+                map.extend(tail.iter().copied());
+                map
+            })
+        })
+    });
+}
+
 criterion_group!(
     benches,
     bench_100k_random_lookup_hits,
     bench_100k_random_lookup_misses,
+    bench_100k_random_bulk_construction,
 );
 
 criterion_main!(benches);
@@ -1,6 +1,12 @@
 #![forbid(unsafe_code)]
 
-use std::{borrow::Borrow, iter::FromIterator, ops::Index};
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    iter::{self, FromIterator, FusedIterator},
+    ops::{Bound, Index, RangeBounds},
+    slice,
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -28,6 +34,55 @@ impl<K: Ord, V> FlatMap<K, V> {
         &self.0
     }
 
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter(self.0.iter())
+    }
+
+    /// Only values are mutable, so the keys stay sorted.
+    ///
+    /// ```compile_fail
+    /// let mut map = flatmap::FlatMap::from(vec![(1, 10), (2, 20)]);
+    /// for (key, _) in map.iter_mut() {
+    ///     *key = 3;
+    /// }
+    /// ```
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut(self.0.iter_mut())
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut V> + ExactSizeIterator {
+        self.iter_mut().map(|(_, v)| v)
+    }
+
+    /// Returns the entries with keys in `range`, which is empty if the range is inverted.
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.find(key).unwrap_or_else(|index| index),
+            Bound::Excluded(key) => self.find(key).map_or_else(|index| index, |index| index + 1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.find(key).map_or_else(|index| index, |index| index + 1),
+            Bound::Excluded(key) => self.find(key).unwrap_or_else(|index| index),
+            Bound::Unbounded => self.0.len(),
+        };
+
+        Iter(self.0[start.min(end)..end].iter())
+    }
+
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         match self.find(&key) {
             Ok(index) => {
@@ -43,6 +98,20 @@ impl<K: Ord, V> FlatMap<K, V> {
         }
     }
 
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.find(&key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry {
+                entries: &mut self.0,
+                index,
+            }),
+            Err(index) => Entry::Vacant(VacantEntry {
+                entries: &mut self.0,
+                index,
+                key,
+            }),
+        }
+    }
+
     pub fn get<Q>(&self, key: &Q) -> Option<&V>
     where
         K: Borrow<Q>,
@@ -67,6 +136,192 @@ impl<K: Ord, V> FlatMap<K, V> {
         self.find(key).ok().map(|index| self.0.remove(index))
     }
 
+    /// Appends the entry in O(1) if its key is greater than all keys of the map, as
+    /// for increasing timestamps. Otherwise gives it back, e.g. to be `insert`ed.
+    pub fn push_max(&mut self, key: K, value: V) -> Result<(), (K, V)> {
+        if self.0.last().is_some_and(|(last, _)| *last >= key) {
+            return Err((key, value));
+        }
+        self.0.push((key, value));
+        Ok(())
+    }
+
+    /// Same as `extend`, but pushes entries as `push_max` while their keys increase,
+    /// only merging the rest from the first entry out of order.
+    pub fn append_sorted(&mut self, iter: impl IntoIterator<Item = (K, V)>) {
+        let mut iter = iter.into_iter();
+        while let Some((key, value)) = iter.next() {
+            if let Err(entry) = self.push_max(key, value) {
+                self.merge(Self::sorted_dedup(iter::once(entry).chain(iter)));
+                break;
+            }
+        }
+        self.debug_assert_sorted();
+    }
+
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        let index = self.0.len().checked_sub(1)?;
+        Some(OccupiedEntry {
+            entries: &mut self.0,
+            index,
+        })
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        self.0.pop()
+    }
+
+    /// Moves all entries of `other` into `self`, values of `other` win on equal keys.
+    pub fn append(&mut self, other: FlatMap<K, V>) {
+        self.merge(other.0);
+    }
+
+    /// Entries with keys present in both maps, in a single linear pass over both.
+    pub fn iter_intersection<'a, V2>(
+        &'a self,
+        other: &'a FlatMap<K, V2>,
+    ) -> Intersection<'a, K, V, V2> {
+        Intersection {
+            lhs: &self.0,
+            rhs: &other.0,
+        }
+    }
+
+    /// Entries with keys present in `self` but not in `other`, in a single linear pass
+    /// over both.
+    pub fn iter_difference<'a, V2>(
+        &'a self,
+        other: &'a FlatMap<K, V2>,
+    ) -> Difference<'a, K, V, V2> {
+        Difference {
+            lhs: &self.0,
+            rhs: &other.0,
+        }
+    }
+
+    /// Combines values of the keys present in both maps with `f`.
+    pub fn intersection_with<'a, V2, R, F>(
+        &'a self,
+        other: &'a FlatMap<K, V2>,
+        mut f: F,
+    ) -> FlatMap<K, R>
+    where
+        K: Clone,
+        F: FnMut(&K, &V, &V2) -> R,
+    {
+        let entries = self
+            .iter_intersection(other)
+            .map(|(key, lhs, rhs)| (key.clone(), f(key, lhs, rhs)))
+            .collect();
+
+        // Already sorted and deduplicated, as are both maps.
+        FlatMap(entries)
+    }
+
+    pub fn difference<V2>(&self, other: &FlatMap<K, V2>) -> FlatMap<K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let entries = self
+            .iter_difference(other)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        FlatMap(entries)
+    }
+
+    /// Entries of both maps, values of the keys present in both are combined with `f`.
+    pub fn union_with<F>(&self, other: &FlatMap<K, V>, mut f: F) -> FlatMap<K, V>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut(&K, &V, &V) -> V,
+    {
+        let mut entries = Vec::with_capacity(self.len().max(other.len()));
+
+        let (mut lhs, mut rhs) = (self.0.as_slice(), other.0.as_slice());
+        while let (Some((lhs_key, lhs_value)), Some((rhs_key, rhs_value))) =
+            (lhs.first(), rhs.first())
+        {
+            match lhs_key.cmp(rhs_key) {
+                Ordering::Less => {
+                    entries.push((lhs_key.clone(), lhs_value.clone()));
+                    lhs = &lhs[1..];
+                }
+                Ordering::Equal => {
+                    entries.push((lhs_key.clone(), f(lhs_key, lhs_value, rhs_value)));
+                    lhs = &lhs[1..];
+                    rhs = &rhs[1..];
+                }
+                Ordering::Greater => {
+                    entries.push((rhs_key.clone(), rhs_value.clone()));
+                    rhs = &rhs[1..];
+                }
+            }
+        }
+        entries.extend_from_slice(lhs);
+        entries.extend_from_slice(rhs);
+
+        FlatMap(entries)
+    }
+
+    /// Sorts the entries and keeps the last one of each key, as sequential `insert`s would.
+    fn sorted_dedup(iter: impl IntoIterator<Item = (K, V)>) -> Vec<(K, V)> {
+        let mut entries = iter.into_iter().collect::<Vec<_>>();
+
+        // The sort is stable, so equal keys keep their insertion order.
+        entries.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        entries.dedup_by(|next, prev| {
+            if next.0 == prev.0 {
+                std::mem::swap(next, prev);
+                true
+            } else {
+                false
+            }
+        });
+
+        entries
+    }
+
+    /// Merges sorted and deduplicated `entries` in a single linear pass.
+    fn merge(&mut self, entries: Vec<(K, V)>) {
+        if entries.is_empty() {
+            return;
+        }
+        if self.0.is_empty() {
+            self.0 = entries;
+            return;
+        }
+
+        let old = std::mem::take(&mut self.0);
+        self.0.reserve(old.len() + entries.len());
+
+        let mut old = old.into_iter().peekable();
+        let mut new = entries.into_iter().peekable();
+        while let (Some((old_key, _)), Some((new_key, _))) = (old.peek(), new.peek()) {
+            match old_key.cmp(new_key) {
+                Ordering::Less => self.0.extend(old.next()),
+                Ordering::Equal => {
+                    old.next();
+                    self.0.extend(new.next());
+                }
+                Ordering::Greater => self.0.extend(new.next()),
+            }
+        }
+        self.0.extend(old);
+        self.0.extend(new);
+        self.debug_assert_sorted();
+    }
+
+    /// Checks the whole map after bulk operations, in debug builds only.
+    fn debug_assert_sorted(&self) {
+        debug_assert!(
+            self.0.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "keys of the map aren't strictly increasing"
+        );
+    }
+
     fn find<Q>(&self, key: &Q) -> Result<usize, usize>
     where
         K: Borrow<Q>,
@@ -78,6 +333,111 @@ impl<K: Ord, V> FlatMap<K, V> {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        self.or_insert_with_key(|_| default())
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+pub struct OccupiedEntry<'a, K, V> {
+    entries: &'a mut Vec<(K, V)>,
+    index: usize,
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.entries[self.index].0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.entries[self.index].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.entries[self.index].1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.entries[self.index].1
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        self.entries.remove(self.index)
+    }
+}
+
+pub struct VacantEntry<'a, K, V> {
+    entries: &'a mut Vec<(K, V)>,
+    index: usize,
+    key: K,
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts at the position found by `FlatMap::entry`, without searching again.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.entries.insert(self.index, (self.key, value));
+        &mut self.entries[self.index].1
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 impl<K, V, Q> Index<&Q> for FlatMap<K, V>
 where
     K: Ord + Borrow<Q>,
@@ -93,14 +453,7 @@ where
 
 impl<K: Ord, V> Extend<(K, V)> for FlatMap<K, V> {
     fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
-        let iter = iter.into_iter();
-
-        let (count, _) = iter.size_hint();
-        self.0.reserve(count);
-
-        iter.for_each(|(k, v)| {
-            self.insert(k, v);
-        })
+        self.merge(Self::sorted_dedup(iter));
     }
 }
 
@@ -118,17 +471,7 @@ impl<K, V> From<FlatMap<K, V>> for Vec<(K, V)> {
 
 impl<K: Ord, V> FromIterator<(K, V)> for FlatMap<K, V> {
     fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
-        let mut result = Self::new();
-        let iter = iter.into_iter();
-
-        let (count, _) = iter.size_hint();
-        result.0.reserve(count);
-
-        iter.for_each(|(k, v)| {
-            result.insert(k, v);
-        });
-
-        result
+        Self(Self::sorted_dedup(iter))
     }
 }
 
@@ -141,3 +484,233 @@ impl<K, V> IntoIterator for FlatMap<K, V> {
         self.0.into_iter()
     }
 }
+
+impl<'a, K, V> IntoIterator for &'a FlatMap<K, V> {
+    type Item = (&'a K, &'a V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter(self.0.iter())
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut FlatMap<K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IterMut(self.0.iter_mut())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+pub struct Iter<'a, K, V>(slice::Iter<'a, (K, V)>);
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+#[derive(Debug)]
+pub struct IterMut<'a, K, V>(slice::IterMut<'a, (K, V)>);
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, v)| (&*k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// See `FlatMap::iter_intersection`.
+#[derive(Debug)]
+pub struct Intersection<'a, K, V, V2> {
+    lhs: &'a [(K, V)],
+    rhs: &'a [(K, V2)],
+}
+
+impl<'a, K: Ord, V, V2> Iterator for Intersection<'a, K, V, V2> {
+    type Item = (&'a K, &'a V, &'a V2);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (lhs_key, lhs_value) = self.lhs.first()?;
+            let (rhs_key, rhs_value) = self.rhs.first()?;
+            match lhs_key.cmp(rhs_key) {
+                Ordering::Less => self.lhs = &self.lhs[1..],
+                Ordering::Equal => {
+                    self.lhs = &self.lhs[1..];
+                    self.rhs = &self.rhs[1..];
+                    return Some((lhs_key, lhs_value, rhs_value));
+                }
+                Ordering::Greater => self.rhs = &self.rhs[1..],
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.lhs.len().min(self.rhs.len())))
+    }
+}
+
+impl<K: Ord, V, V2> FusedIterator for Intersection<'_, K, V, V2> {}
+
+impl<K, V, V2> Clone for Intersection<'_, K, V, V2> {
+    fn clone(&self) -> Self {
+        Self {
+            lhs: self.lhs,
+            rhs: self.rhs,
+        }
+    }
+}
+
+/// See `FlatMap::iter_difference`.
+#[derive(Debug)]
+pub struct Difference<'a, K, V, V2> {
+    lhs: &'a [(K, V)],
+    rhs: &'a [(K, V2)],
+}
+
+impl<'a, K: Ord, V, V2> Iterator for Difference<'a, K, V, V2> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (lhs_key, lhs_value) = self.lhs.first()?;
+            let Some((rhs_key, _)) = self.rhs.first() else {
+                self.lhs = &self.lhs[1..];
+                return Some((lhs_key, lhs_value));
+            };
+            match lhs_key.cmp(rhs_key) {
+                Ordering::Less => {
+                    self.lhs = &self.lhs[1..];
+                    return Some((lhs_key, lhs_value));
+                }
+                Ordering::Equal => {
+                    self.lhs = &self.lhs[1..];
+                    self.rhs = &self.rhs[1..];
+                }
+                Ordering::Greater => self.rhs = &self.rhs[1..],
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.lhs.len();
+        (len.saturating_sub(self.rhs.len()), Some(len))
+    }
+}
+
+impl<K: Ord, V, V2> FusedIterator for Difference<'_, K, V, V2> {}
+
+impl<K, V, V2> Clone for Difference<'_, K, V, V2> {
+    fn clone(&self) -> Self {
+        Self {
+            lhs: self.lhs,
+            rhs: self.rhs,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::FlatMap;
+
+    use serde::{
+        de::{MapAccess, Visitor},
+        ser::SerializeMap,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use std::{fmt, marker::PhantomData};
+
+    impl<K: Serialize, V: Serialize> Serialize for FlatMap<K, V> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for (key, value) in &self.0 {
+                map.serialize_entry(key, value)?;
+            }
+            map.end()
+        }
+    }
+
+    impl<'de, K, V> Deserialize<'de> for FlatMap<K, V>
+    where
+        K: Ord + Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(FlatMapVisitor(PhantomData))
+        }
+    }
+
+    const MAX_PREALLOCATED_ENTRIES: usize = 4096;
+
+    struct FlatMapVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for FlatMapVisitor<K, V>
+    where
+        K: Ord + Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        type Value = FlatMap<K, V>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+            // The hint comes from the input, so don't let it preallocate unbounded memory.
+            let capacity = access
+                .size_hint()
+                .unwrap_or(0)
+                .min(MAX_PREALLOCATED_ENTRIES);
+            let mut entries = Vec::with_capacity(capacity);
+            while let Some(entry) = access.next_entry()? {
+                entries.push(entry);
+            }
+
+            // Sorting once keeps the last value of duplicate keys, as inserts would.
+            Ok(FlatMap::from_iter(entries))
+        }
+    }
+}
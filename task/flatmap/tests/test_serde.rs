@@ -0,0 +1,106 @@
+#![cfg(feature = "serde")]
+
+use flatmap::FlatMap;
+
+use pretty_assertions::assert_eq;
+
+#[test]
+fn test_empty() {
+    let map = FlatMap::<String, u64>::new();
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, "{}");
+
+    let map: FlatMap<String, u64> = serde_json::from_str(&json).unwrap();
+    assert!(map.is_empty());
+}
+
+#[test]
+fn test_round_trip() {
+    let map = FlatMap::from(vec![
+        ("foo".to_string(), 3u64),
+        ("bar".to_string(), 1),
+        ("baz".to_string(), 2),
+    ]);
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, r#"{"bar":1,"baz":2,"foo":3}"#);
+
+    let restored: FlatMap<String, u64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, map);
+}
+
+#[test]
+fn test_integer_keys() {
+    let map = FlatMap::from(vec![(10, "ten"), (-1, "minus one"), (2, "two")]);
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, r#"{"-1":"minus one","2":"two","10":"ten"}"#);
+
+    let restored: FlatMap<i32, String> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        restored.as_slice(),
+        &[
+            (-1, "minus one".to_string()),
+            (2, "two".to_string()),
+            (10, "ten".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_unsorted_and_duplicate_keys() {
+    let json = r#"{"c": 1, "a": 2, "b": 3, "a": 4, "c": 5}"#;
+    let map: FlatMap<String, u64> = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        map.as_slice(),
+        &[
+            ("a".to_string(), 4),
+            ("b".to_string(), 3),
+            ("c".to_string(), 5),
+        ]
+    );
+}
+
+#[test]
+fn test_not_a_map() {
+    assert!(serde_json::from_str::<FlatMap<String, u64>>("[1, 2]").is_err());
+}
+
+#[test]
+fn test_huge_size_hint() {
+    use serde::de::{
+        value::{Error, MapAccessDeserializer},
+        Deserialize, DeserializeSeed, IntoDeserializer, MapAccess,
+    };
+
+    // Claims far more entries than it has, as a malicious input could.
+    struct LyingAccess(Vec<(u64, u64)>);
+
+    impl<'de> MapAccess<'de> for LyingAccess {
+        type Error = Error;
+
+        fn next_key_seed<S: DeserializeSeed<'de>>(
+            &mut self,
+            seed: S,
+        ) -> Result<Option<S::Value>, Self::Error> {
+            match self.0.first() {
+                Some(&(key, _)) => seed.deserialize(key.into_deserializer()).map(Some),
+                None => Ok(None),
+            }
+        }
+
+        fn next_value_seed<S: DeserializeSeed<'de>>(
+            &mut self,
+            seed: S,
+        ) -> Result<S::Value, Self::Error> {
+            let (_, value) = self.0.remove(0);
+            seed.deserialize(value.into_deserializer())
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(usize::MAX)
+        }
+    }
+
+    let access = LyingAccess(vec![(2, 20), (1, 10)]);
+    let map = FlatMap::<u64, u64>::deserialize(MapAccessDeserializer::new(access)).unwrap();
+    assert_eq!(map.as_slice(), &[(1, 10), (2, 20)]);
+}
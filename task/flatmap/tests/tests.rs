@@ -1,4 +1,4 @@
-use flatmap::FlatMap;
+use flatmap::{Entry, FlatMap};
 
 use pretty_assertions::assert_eq;
 use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
@@ -198,3 +198,490 @@ fn test_random_removals_big() {
         }
     }
 }
+
+#[test]
+fn test_iter() {
+    let mut map = FlatMap::from(vec![(3, 30), (1, 10), (2, 20)]);
+
+    assert_eq!(
+        map.iter().collect::<Vec<_>>(),
+        vec![(&1, &10), (&2, &20), (&3, &30)]
+    );
+    assert_eq!(map.iter().next_back(), Some((&3, &30)));
+    assert_eq!(map.iter().len(), 3);
+    assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(map.values().copied().collect::<Vec<_>>(), vec![10, 20, 30]);
+
+    for (key, value) in map.iter_mut() {
+        *value += key;
+    }
+    for value in map.values_mut() {
+        *value *= 2;
+    }
+    assert_eq!(map.as_slice(), &[(1, 22), (2, 44), (3, 66)]);
+
+    for (_, value) in &mut map {
+        *value = -*value;
+    }
+    let mut keys = vec![];
+    for (key, value) in &map {
+        assert_eq!(*value, -22 * key);
+        keys.push(*key);
+    }
+    assert_eq!(keys, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_range() {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    let map = FlatMap::from_iter((0..10).map(|i| (i * 2, i)));
+    let keys = |iter: flatmap::Iter<'_, i32, i32>| iter.map(|(k, _)| *k).collect::<Vec<_>>();
+
+    assert_eq!(
+        keys(map.range(..)),
+        (0..10).map(|i| i * 2).collect::<Vec<_>>()
+    );
+    assert_eq!(keys(map.range(4..8)), vec![4, 6]);
+    assert_eq!(keys(map.range(4..=8)), vec![4, 6, 8]);
+    assert_eq!(keys(map.range(3..9)), vec![4, 6, 8]);
+    assert_eq!(keys(map.range(..3)), vec![0, 2]);
+    assert_eq!(keys(map.range(15..)), vec![16, 18]);
+    assert_eq!(keys(map.range((Excluded(4), Included(10)))), vec![6, 8, 10]);
+    assert_eq!(keys(map.range((Excluded(5), Excluded(10)))), vec![6, 8]);
+    assert_eq!(keys(map.range((Excluded(16), Unbounded))), vec![18]);
+    assert_eq!(keys(map.range(20..)), vec![]);
+    assert_eq!(keys(map.range(-5..0)), vec![]);
+    assert_eq!(keys(map.range((Excluded(4), Excluded(4)))), vec![]);
+    assert_eq!(keys(map.range((Included(8), Excluded(4)))), vec![]);
+
+    let map = FlatMap::from(vec![
+        ("apple".to_string(), 1),
+        ("banana".to_string(), 2),
+        ("cherry".to_string(), 3),
+    ]);
+    assert_eq!(
+        map.range::<str, _>((Excluded("apple"), Included("cherry")))
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+}
+
+#[test]
+fn test_entry() {
+    let mut map = FlatMap::from(vec![(1, 10), (3, 30)]);
+
+    assert_eq!(*map.entry(1).and_modify(|v| *v += 1).or_insert(0), 11);
+    assert_eq!(*map.entry(2).and_modify(|v| *v += 1).or_insert(20), 20);
+    assert_eq!(*map.entry(0).or_insert_with(|| 5), 5);
+    assert_eq!(*map.entry(3).or_insert_with(|| unreachable!()), 30);
+    assert_eq!(*map.entry(4).or_default(), 0);
+    assert_eq!(map.entry(7).key(), &7);
+    assert_eq!(map.as_slice(), &[(0, 5), (1, 11), (2, 20), (3, 30), (4, 0)]);
+
+    match map.entry(2) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), &2);
+            assert_eq!(entry.insert(21), 20);
+            assert_eq!(entry.get(), &21);
+            assert_eq!(entry.remove_entry(), (2, 21));
+        }
+        Entry::Vacant(_) => panic!("key 2 must be occupied"),
+    }
+    match map.entry(2) {
+        Entry::Occupied(_) => panic!("key 2 must be vacant"),
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), &2);
+            *entry.insert(1) += 1;
+        }
+    }
+    assert_eq!(map.as_slice(), &[(0, 5), (1, 11), (2, 2), (3, 30), (4, 0)]);
+}
+
+#[test]
+fn test_entry_counting() {
+    let mut rng = StdRng::seed_from_u64(8347592034);
+    let mut flat_map = FlatMap::new();
+    let mut hash_map = HashMap::new();
+
+    for _ in 0..10000 {
+        let key = rng.gen_range(-100..100);
+        *flat_map.entry(key).or_insert(0) += 1;
+        *hash_map.entry(key).or_insert(0) += 1;
+    }
+
+    assert_eq!(flat_map.len(), hash_map.len());
+    assert!(flat_map.as_slice().windows(2).all(|w| w[0].0 < w[1].0));
+    for (key, value) in &hash_map {
+        assert_eq!(flat_map.get(key), Some(value));
+    }
+}
+
+#[test]
+fn test_append() {
+    let mut map = FlatMap::from(vec![(1, 10), (3, 30), (5, 50)]);
+    map.append(FlatMap::from(vec![(0, 0), (3, 33), (6, 60)]));
+    assert_eq!(
+        map.as_slice(),
+        &[(0, 0), (1, 10), (3, 33), (5, 50), (6, 60)]
+    );
+
+    map.append(FlatMap::new());
+    assert_eq!(map.len(), 5);
+
+    let mut empty = FlatMap::new();
+    empty.append(map);
+    assert_eq!(
+        empty.as_slice(),
+        &[(0, 0), (1, 10), (3, 33), (5, 50), (6, 60)]
+    );
+
+    let mut map = FlatMap::from(vec![(2, 20)]);
+    map.extend(vec![(2, 21), (1, 10), (2, 22), (3, 30)]);
+    assert_eq!(map.as_slice(), &[(1, 10), (2, 22), (3, 30)]);
+}
+
+#[test]
+fn test_bulk_same_as_sequential_insert() {
+    let mut rng = StdRng::seed_from_u64(9823472340);
+    for _ in 0..100 {
+        let initial: Vec<(i32, i32)> = (0..rng.gen_range(0..50))
+            .map(|_| (rng.gen_range(-30..30), rng.gen()))
+            .collect();
+        let extension: Vec<(i32, i32)> = (0..rng.gen_range(0..50))
+            .map(|_| (rng.gen_range(-30..30), rng.gen()))
+            .collect();
+
+        let mut expected = FlatMap::new();
+        for &(key, value) in initial.iter().chain(extension.iter()) {
+            expected.insert(key, value);
+        }
+
+        let mut extended = FlatMap::from_iter(initial.iter().copied());
+        extended.extend(extension.iter().copied());
+        assert_eq!(extended, expected);
+
+        let mut appended = FlatMap::from(initial.clone());
+        appended.append(FlatMap::from(extension.clone()));
+        assert_eq!(appended, expected);
+
+        let mut appended_sorted = FlatMap::from(initial.clone());
+        appended_sorted.append_sorted(extension.iter().copied());
+        assert_eq!(appended_sorted, expected);
+    }
+}
+
+#[test]
+fn test_push_max() {
+    let mut map = FlatMap::new();
+    assert_eq!(map.push_max(10, "a"), Ok(()));
+    assert_eq!(map.push_max(20, "b"), Ok(()));
+    assert_eq!(map.push_max(20, "c"), Err((20, "c")));
+    assert_eq!(map.push_max(15, "d"), Err((15, "d")));
+    assert_eq!(map.push_max(-5, "e"), Err((-5, "e")));
+    assert_eq!(map.as_slice(), &[(10, "a"), (20, "b")]);
+
+    assert_eq!(map.push_max(21, "f"), Ok(()));
+    assert_eq!(map.as_slice(), &[(10, "a"), (20, "b"), (21, "f")]);
+}
+
+#[test]
+fn test_push_max_with_insert() {
+    let mut rng = StdRng::seed_from_u64(3409823471);
+    let mut map = FlatMap::new();
+    let mut expected = HashMap::new();
+    for _ in 0..1000 {
+        let key = rng.gen_range(0..2000);
+        let value = rng.gen::<i32>();
+        if rng.gen_bool(0.5) {
+            if let Err((key, value)) = map.push_max(key, value) {
+                assert!(map.keys().next_back().is_some_and(|last| *last >= key));
+                map.insert(key, value);
+            }
+        } else {
+            map.insert(key, value);
+        }
+        expected.insert(key, value);
+
+        assert!(map.as_slice().windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    let mut expected = Vec::from_iter(expected);
+    expected.sort();
+    assert_eq!(map.as_slice(), expected.as_slice());
+}
+
+#[test]
+fn test_append_sorted() {
+    let mut map = FlatMap::from(vec![(1, 10), (5, 50)]);
+    map.append_sorted(vec![(6, 60), (8, 80), (9, 90)]);
+    assert_eq!(
+        map.as_slice(),
+        &[(1, 10), (5, 50), (6, 60), (8, 80), (9, 90)]
+    );
+
+    // Sorted up to (3, 31), which goes to the merge with the rest of the batch.
+    map.append_sorted(vec![
+        (10, 100),
+        (12, 120),
+        (3, 31),
+        (12, 121),
+        (5, 51),
+        (11, 110),
+    ]);
+    assert_eq!(
+        map.as_slice(),
+        &[
+            (1, 10),
+            (3, 31),
+            (5, 51),
+            (6, 60),
+            (8, 80),
+            (9, 90),
+            (10, 100),
+            (11, 110),
+            (12, 121)
+        ]
+    );
+
+    // An equal key isn't greater either.
+    map.append_sorted(vec![(12, 122), (13, 130)]);
+    assert_eq!(map.len(), 10);
+    assert_eq!(map[&12], 122);
+    assert_eq!(map[&13], 130);
+
+    let mut empty = FlatMap::new();
+    empty.append_sorted(vec![(2, 20), (1, 10), (2, 21)]);
+    assert_eq!(empty.as_slice(), &[(1, 10), (2, 21)]);
+    empty.append_sorted(std::iter::empty());
+    assert_eq!(empty.len(), 2);
+}
+
+#[test]
+fn test_last() {
+    let mut map = FlatMap::<i32, i32>::new();
+    assert!(map.last_entry().is_none());
+    assert_eq!(map.pop_last(), None);
+
+    map.insert(1, 10);
+    {
+        let mut last = map.last_entry().unwrap();
+        assert_eq!(last.key(), &1);
+        *last.get_mut() += 1;
+    }
+    assert_eq!(map[&1], 11);
+    assert_eq!(map.last_entry().unwrap().remove_entry(), (1, 11));
+    assert!(map.is_empty());
+
+    map.insert(1, 10);
+    assert_eq!(map.pop_last(), Some((1, 10)));
+    assert_eq!(map.pop_last(), None);
+    assert!(map.last_entry().is_none());
+
+    map.extend([(3, 30), (1, 10), (2, 20)]);
+    assert_eq!(map.pop_last(), Some((3, 30)));
+    assert_eq!(map.last_entry().unwrap().get(), &20);
+    assert_eq!(map.push_max(2, 21), Err((2, 21)));
+    assert_eq!(map.push_max(3, 31), Ok(()));
+}
+
+#[test]
+fn test_bulk_100k() {
+    let mut rng = StdRng::seed_from_u64(2394823049);
+    let entries: Vec<(i64, i64)> = (0..100_000).map(|_| (rng.gen(), rng.gen())).collect();
+
+    let mut map = FlatMap::from_iter(entries.iter().copied().take(50_000));
+    map.extend(entries.iter().copied().skip(50_000));
+
+    assert_eq!(map.len(), 100_000);
+    assert!(map.as_slice().windows(2).all(|w| w[0].0 < w[1].0));
+    for (key, value) in entries.iter() {
+        assert_eq!(map.get(key), Some(value));
+    }
+}
+
+#[test]
+fn test_set_ops_disjoint() {
+    let lhs = FlatMap::from(vec![(1, 10), (3, 30), (5, 50)]);
+    let rhs = FlatMap::from(vec![(2, "b"), (4, "d")]);
+
+    assert_eq!(lhs.iter_intersection(&rhs).count(), 0);
+    assert!(lhs.intersection_with(&rhs, |_, _, _| ()).is_empty());
+    assert_eq!(lhs.difference(&rhs), lhs);
+    assert_eq!(
+        lhs.iter_difference(&rhs).collect::<Vec<_>>(),
+        vec![(&1, &10), (&3, &30), (&5, &50)]
+    );
+
+    let other = FlatMap::from(vec![(6, 60), (0, 0)]);
+    let union = lhs.union_with(&other, |_, _, _| unreachable!());
+    assert_eq!(
+        union.as_slice(),
+        &[(0, 0), (1, 10), (3, 30), (5, 50), (6, 60)]
+    );
+
+    let empty = FlatMap::new();
+    assert_eq!(lhs.union_with(&empty, |_, _, _| unreachable!()), lhs);
+    assert_eq!(empty.union_with(&lhs, |_, _, _| unreachable!()), lhs);
+    assert_eq!(lhs.difference(&empty), lhs);
+    assert!(empty.difference(&lhs).is_empty());
+}
+
+#[test]
+fn test_set_ops_identical() {
+    let map = FlatMap::from(vec![(1, 10), (2, 20), (3, 30)]);
+
+    assert_eq!(
+        map.iter_intersection(&map).collect::<Vec<_>>(),
+        vec![(&1, &10, &10), (&2, &20, &20), (&3, &30, &30)]
+    );
+    assert_eq!(
+        map.intersection_with(&map, |key, lhs, rhs| key + lhs + rhs)
+            .as_slice(),
+        &[(1, 21), (2, 42), (3, 63)]
+    );
+    assert!(map.difference(&map).is_empty());
+    assert_eq!(map.iter_difference(&map).count(), 0);
+    assert_eq!(
+        map.union_with(&map, |_, lhs, rhs| lhs * rhs).as_slice(),
+        &[(1, 100), (2, 400), (3, 900)]
+    );
+}
+
+#[test]
+fn test_set_ops_interleaved() {
+    let lhs = FlatMap::from(vec![(1, 10), (2, 20), (4, 40), (7, 70), (8, 80)]);
+    let rhs = FlatMap::from(vec![(0, "a"), (2, "c"), (3, "d"), (7, "h"), (9, "j")]);
+
+    assert_eq!(
+        lhs.intersection_with(&rhs, |_, &value, &name| format!("{name}{value}"))
+            .as_slice(),
+        &[(2, "c20".to_string()), (7, "h70".to_string())]
+    );
+    assert_eq!(
+        lhs.difference(&rhs).as_slice(),
+        &[(1, 10), (4, 40), (8, 80)]
+    );
+    assert_eq!(
+        rhs.difference(&lhs).as_slice(),
+        &[(0, "a"), (3, "d"), (9, "j")]
+    );
+
+    // "Apply B's values over A for common keys".
+    let updates = FlatMap::from(vec![(2, 22), (5, 55), (8, 88)]);
+    assert_eq!(
+        lhs.union_with(&updates, |_, _, new| *new).as_slice(),
+        &[(1, 10), (2, 22), (4, 40), (5, 55), (7, 70), (8, 88)]
+    );
+
+    let mut rng = StdRng::seed_from_u64(4350983450);
+    for _ in 0..100 {
+        let lhs: FlatMap<i32, i32> = (0..rng.gen_range(0..50))
+            .map(|_| (rng.gen_range(-30..30), rng.gen()))
+            .collect();
+        let rhs: FlatMap<i32, i32> = (0..rng.gen_range(0..50))
+            .map(|_| (rng.gen_range(-30..30), rng.gen()))
+            .collect();
+
+        let expected: Vec<_> = lhs
+            .iter()
+            .filter_map(|(key, value)| rhs.get(key).map(|other| (*key, value ^ other)))
+            .collect();
+        let intersection = lhs.intersection_with(&rhs, |_, lhs, rhs| lhs ^ rhs);
+        assert_eq!(intersection.as_slice(), expected.as_slice());
+
+        let expected: Vec<_> = lhs
+            .iter()
+            .filter(|(key, _)| rhs.get(key).is_none())
+            .map(|(key, value)| (*key, *value))
+            .collect();
+        assert_eq!(lhs.difference(&rhs).as_slice(), expected.as_slice());
+
+        let mut expected = FlatMap::new();
+        for (key, value) in lhs.iter().chain(rhs.iter()) {
+            *expected.entry(*key).or_insert(0) ^= value;
+        }
+        assert_eq!(lhs.union_with(&rhs, |_, lhs, rhs| lhs ^ rhs), expected);
+    }
+}
+
+#[test]
+fn test_set_ops_combiner_calls() {
+    let lhs = FlatMap::from_iter((0..100).map(|i| (i * 2, i)));
+    let rhs = FlatMap::from_iter((0..100).map(|i| (i * 3, i)));
+
+    let mut calls = Vec::new();
+    let intersection = lhs.intersection_with(&rhs, |key, _, _| calls.push(*key));
+    assert_eq!(intersection.len(), 34);
+    assert_eq!(calls, (0..34).map(|i| i * 6).collect::<Vec<_>>());
+
+    let mut calls = 0;
+    let union = lhs.union_with(&rhs, |_, lhs, _| {
+        calls += 1;
+        *lhs
+    });
+    assert_eq!(calls, 34);
+    assert_eq!(union.len(), 166);
+}
+
+thread_local! {
+    static COMPARISONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CountingKey(u32);
+
+impl PartialOrd for CountingKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CountingKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        COMPARISONS.with(|count| count.set(count.get() + 1));
+        self.0.cmp(&other.0)
+    }
+}
+
+fn count_comparisons(f: impl FnOnce()) -> usize {
+    COMPARISONS.with(|count| count.set(0));
+    f();
+    COMPARISONS.with(|count| count.get())
+}
+
+#[test]
+fn test_set_ops_linear() {
+    let mut rng = StdRng::seed_from_u64(7098234502);
+    for (n, m) in [(10_000, 10_000), (10_000, 100), (100, 10_000), (0, 1000)] {
+        let lhs: FlatMap<_, _> = (0..n)
+            .map(|_| (CountingKey(rng.gen_range(0..20_000)), ()))
+            .collect();
+        let rhs: FlatMap<_, _> = (0..m)
+            .map(|_| (CountingKey(rng.gen_range(0..20_000)), ()))
+            .collect();
+        let bound = lhs.len() + rhs.len();
+
+        let comparisons = count_comparisons(|| {
+            lhs.iter_intersection(&rhs).count();
+        });
+        assert!(comparisons <= bound, "{comparisons} > {bound}");
+        let comparisons = count_comparisons(|| {
+            lhs.iter_difference(&rhs).count();
+        });
+        assert!(comparisons <= bound, "{comparisons} > {bound}");
+        let comparisons = count_comparisons(|| {
+            lhs.intersection_with(&rhs, |_, _, _| ());
+        });
+        assert!(comparisons <= bound, "{comparisons} > {bound}");
+        let comparisons = count_comparisons(|| {
+            lhs.difference(&rhs);
+        });
+        assert!(comparisons <= bound, "{comparisons} > {bound}");
+        let comparisons = count_comparisons(|| {
+            lhs.union_with(&rhs, |_, _, _| ());
+        });
+        assert!(comparisons <= bound, "{comparisons} > {bound}");
+    }
+}
@@ -0,0 +1,115 @@
+//! Common aggregate walks built on top of [`Walker`].
+//!
+//! None of them reads file contents. Errors on entries under the given path, like
+//! unreadable directories or metadata that cannot be queried, are skipped and returned
+//! next to the result, while an error on the path itself is returned as is.
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::{self, Result},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{ErrorAction, Handle, SkippedErrors, Walker};
+
+/// Returns a walker skipping all the errors except the ones on `root`.
+fn skipping_walker(root: &Path) -> Walker<'_> {
+    Walker::new().on_error(move |path, _| {
+        if path == root {
+            ErrorAction::Abort
+        } else {
+            ErrorAction::Skip
+        }
+    })
+}
+
+/// Returns the total size in bytes of all files under `path`.
+pub fn dir_size<P: AsRef<Path>>(path: P) -> Result<(u64, SkippedErrors)> {
+    let path = path.as_ref();
+    let mut size = 0;
+    let mut metadata_errors = Vec::new();
+
+    let mut skipped = {
+        let mut walker = skipping_walker(path);
+        walker.add_callback(|handle| match handle {
+            Handle::Dir(dir_handle) => dir_handle.descend(),
+            Handle::File(file_handle) => match file_handle.metadata() {
+                Ok(metadata) => size += metadata.len(),
+                Err(error) => skip_entry(&mut metadata_errors, file_handle.path(), error),
+            },
+            Handle::Content { .. } => {}
+        });
+        walker.walk(path)?
+    };
+
+    skipped.append(&mut metadata_errors);
+    Ok((size, skipped))
+}
+
+/// Counts files under `path` by their extension.
+///
+/// Files without an extension, including dotfiles like `.gitignore`, are counted
+/// under the empty key.
+pub fn count_by_extension<P: AsRef<Path>>(
+    path: P,
+) -> Result<(HashMap<OsString, u64>, SkippedErrors)> {
+    let path = path.as_ref();
+    let mut counts = HashMap::new();
+
+    let skipped = {
+        let mut walker = skipping_walker(path);
+        walker.add_callback(|handle| match handle {
+            Handle::Dir(dir_handle) => dir_handle.descend(),
+            Handle::File(file_handle) => {
+                let extension = file_handle.path().extension().unwrap_or_default();
+                *counts.entry(extension.to_owned()).or_default() += 1;
+            }
+            Handle::Content { .. } => {}
+        });
+        walker.walk(path)?
+    };
+
+    Ok((counts, skipped))
+}
+
+/// Returns the most recently modified file under `path` with its modification time,
+/// or `None` if there are no files.
+pub fn newest_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<(Option<(PathBuf, SystemTime)>, SkippedErrors)> {
+    let path = path.as_ref();
+    let mut newest: Option<(PathBuf, SystemTime)> = None;
+    let mut metadata_errors = Vec::new();
+
+    let mut skipped = {
+        let mut walker = skipping_walker(path);
+        walker.add_callback(|handle| match handle {
+            Handle::Dir(dir_handle) => dir_handle.descend(),
+            Handle::File(file_handle) => {
+                match file_handle
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                {
+                    Ok(modified) => {
+                        if newest.as_ref().is_none_or(|(_, newest)| modified > *newest) {
+                            newest = Some((file_handle.path().to_owned(), modified));
+                        }
+                    }
+                    Err(error) => skip_entry(&mut metadata_errors, file_handle.path(), error),
+                }
+            }
+            Handle::Content { .. } => {}
+        });
+        walker.walk(path)?
+    };
+
+    skipped.append(&mut metadata_errors);
+    Ok((newest, skipped))
+}
+
+fn skip_entry(skipped: &mut SkippedErrors, path: &Path, error: io::Error) {
+    log::warn!("skipping {path:?}: {error}");
+    skipped.push((path.to_owned(), error));
+}
@@ -1,27 +1,104 @@
 #![forbid(unsafe_code)]
 
+pub mod convenience;
+
 use std::{
     fs,
-    io::{self, Result},
-    path::Path,
+    io::{self, Read, Result},
+    path::{Path, PathBuf},
 };
 
 ////////////////////////////////////////////////////////////////////////////////
 
 type Callback<'a> = dyn FnMut(&mut Handle) + 'a;
+type ErrorHandler<'a> = dyn FnMut(&Path, &io::Error) -> ErrorAction + 'a;
+
+/// What to do on an I/O error, see `Walker::on_error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Skip the entry where the error happened and continue the walk.
+    Skip,
+    /// Stop the walk and return the error.
+    Abort,
+}
+
+/// Errors skipped during a walk with the paths where they happened.
+pub type SkippedErrors = Vec<(PathBuf, io::Error)>;
 
-#[derive(Default)]
 pub struct Walker<'a> {
     callbacks: Vec<Box<Callback<'a>>>,
+    error_handler: Option<Box<ErrorHandler<'a>>>,
+    skipped_errors: SkippedErrors,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    sort_entries: bool,
+    skip_hidden: bool,
+    /// Canonical paths of the directories being walked, used to detect symlink loops.
+    ancestors: Vec<PathBuf>,
+}
+
+impl Default for Walker<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Walker<'a> {
     pub fn new() -> Self {
         Self {
             callbacks: Vec::new(),
+            error_handler: None,
+            skipped_errors: Vec::new(),
+            max_depth: None,
+            follow_symlinks: true,
+            sort_entries: false,
+            skip_hidden: false,
+            ancestors: Vec::new(),
         }
     }
 
+    /// Entries deeper than `depth` are not visited. The path given to `walk` has depth 0,
+    /// so with `max_depth(0)` callbacks see only it.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Symlinks are followed by default. A symlink to a directory that is being walked
+    /// already is skipped with a warning, so a loop can't make the walk infinite.
+    ///
+    /// When disabled, symlinks to directories are skipped, while symlinks to files are
+    /// still visited as files. The path given to `walk` is followed anyway.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Visits the entries of every directory ordered by their file names, instead
+    /// of the order given by the OS.
+    pub fn sort_entries(mut self, sort: bool) -> Self {
+        self.sort_entries = sort;
+        self
+    }
+
+    /// Skips hidden entries, see `is_hidden`, before any callback sees them, so hidden
+    /// directories are never descended into. The path given to `walk` is visited anyway.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Decides what to do on every I/O error of the walk, including the errors of
+    /// reading directories and files requested by callbacks. Without a handler the
+    /// walk is aborted on the first error.
+    pub fn on_error<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&Path, &io::Error) -> ErrorAction + 'a,
+    {
+        self.error_handler = Some(Box::new(handler));
+        self
+    }
+
     pub fn add_callback<F>(&mut self, callback: F)
     where
         F: FnMut(&mut Handle) + 'a,
@@ -29,24 +106,55 @@ impl<'a> Walker<'a> {
         self.callbacks.push(Box::new(callback))
     }
 
-    pub fn walk<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        self.walk_recursive(path.as_ref(), self.callbacks.len())
+    /// Returns the errors skipped by the handler given to `on_error` if the walk was
+    /// completed, or the error that aborted it.
+    pub fn walk<P: AsRef<Path>>(&mut self, path: P) -> Result<SkippedErrors> {
+        self.ancestors.clear();
+        self.skipped_errors.clear();
+        self.walk_recursive(path.as_ref(), 0, self.callbacks.len())?;
+        Ok(std::mem::take(&mut self.skipped_errors))
     }
 
-    fn walk_recursive(&mut self, path: &Path, remaining_callbacks: usize) -> Result<()> {
+    fn handle_error(&mut self, path: &Path, error: io::Error) -> Result<()> {
+        let action = match &mut self.error_handler {
+            Some(handler) => handler(path, &error),
+            None => ErrorAction::Abort,
+        };
+        match action {
+            ErrorAction::Skip => {
+                log::warn!("skipping {path:?}: {error}");
+                self.skipped_errors.push((path.to_owned(), error));
+                Ok(())
+            }
+            ErrorAction::Abort => Err(error),
+        }
+    }
+
+    fn walk_recursive(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        remaining_callbacks: usize,
+    ) -> Result<()> {
         if remaining_callbacks == 0 {
             return Ok(());
         }
 
+        if depth > 0 && !self.follow_symlinks && path.is_symlink() && path.is_dir() {
+            return Ok(());
+        }
+
+        if depth > 0 && self.skip_hidden && is_hidden(path) {
+            return Ok(());
+        }
+
         let mut handle = if path.is_dir() {
             Handle::Dir(DirHandle::new(path))
         } else if path.is_file() {
             Handle::File(FileHandle::new(path))
         } else {
-            return Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                "Unsupported entity type",
-            ));
+            let error = io::Error::new(io::ErrorKind::Unsupported, "Unsupported entity type");
+            return self.handle_error(path, error);
         };
 
         let remaining_callbacks = self.run_callbacks(&mut handle, remaining_callbacks);
@@ -54,27 +162,168 @@ impl<'a> Walker<'a> {
         match handle {
             Handle::Dir(dir_handle) => match dir_handle.content {
                 None => Ok(()),
-                Some(Ok(mut read_dir)) => read_dir.try_for_each(|entry| match entry {
-                    Ok(entry) => self.walk_recursive(entry.path().as_path(), remaining_callbacks),
-                    Err(error) => Err(error),
-                }),
-                Some(Err(error)) => Err(error),
+                Some(Ok(read_dir)) => self.walk_dir(path, read_dir, depth, remaining_callbacks),
+                Some(Err(error)) => self.handle_error(path, error),
             },
-            Handle::File(file_handle) => match file_handle.content {
-                None => Ok(()),
-                Some(Ok(content)) => {
-                    let mut content_handle = Handle::Content {
-                        file_path: file_handle.path,
-                        content: &content,
-                    };
+            Handle::File(file_handle) => {
+                let requests = file_handle
+                    .requests
+                    .into_iter()
+                    .take(remaining_callbacks)
+                    .flatten()
+                    .collect::<Vec<_>>();
+                self.read_file(path, &requests)
+            }
+            _ => unreachable!(),
+        }
+    }
 
-                    self.run_callbacks(&mut content_handle, remaining_callbacks);
+    /// Passes the content of the file at `path` to the callbacks that requested it,
+    /// `requests[i]` being the request of `self.callbacks[i]`. The whole content is read
+    /// once for all `read` and `read_limited` requests, and streamed once more if there
+    /// are `read_chunks` requests.
+    fn read_file(&mut self, path: &Path, requests: &[ReadRequest]) -> Result<()> {
+        let limit = requests
+            .iter()
+            .filter_map(|request| match *request {
+                ReadRequest::Full => Some(None),
+                ReadRequest::Limited(max_bytes) => Some(Some(max_bytes)),
+                ReadRequest::Chunks(_) => None,
+            })
+            .reduce(|lhs, rhs| lhs.zip(rhs).map(|(lhs, rhs)| lhs.max(rhs)));
+
+        if let Some(limit) = limit {
+            let (content, truncated) = match read_content(path, limit) {
+                Ok(result) => result,
+                Err(error) => return self.handle_error(path, error),
+            };
+            for (i, request) in requests.iter().enumerate() {
+                let (content, truncated) = match *request {
+                    ReadRequest::Full => (&content[..], truncated),
+                    ReadRequest::Limited(max_bytes) => (
+                        &content[..content.len().min(max_bytes)],
+                        truncated || content.len() > max_bytes,
+                    ),
+                    ReadRequest::Chunks(_) => continue,
+                };
+                (self.callbacks[i])(&mut Handle::Content {
+                    file_path: path,
+                    content,
+                    truncated,
+                });
+            }
+        }
 
-                    Ok(())
+        let chunk_sizes = requests
+            .iter()
+            .enumerate()
+            .filter_map(|(i, request)| match *request {
+                ReadRequest::Chunks(chunk_size) => Some((i, chunk_size)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if chunk_sizes.is_empty() {
+            return Ok(());
+        }
+        match self.stream_chunks(path, &chunk_sizes) {
+            Ok(()) => Ok(()),
+            Err(error) => self.handle_error(path, error),
+        }
+    }
+
+    /// `chunk_sizes` are pairs of a callback index and its chunk size. The file is read
+    /// into a single buffer of the largest chunk size, which is split for the callbacks
+    /// requesting smaller chunks.
+    fn stream_chunks(&mut self, path: &Path, chunk_sizes: &[(usize, usize)]) -> Result<()> {
+        let buffer_size = chunk_sizes.iter().map(|&(_, size)| size).max().unwrap();
+        let mut buffer = vec![0; buffer_size];
+        let mut file = fs::File::open(path)?;
+
+        let mut carried = 0;
+        loop {
+            let filled = carried + read_full(&mut file, &mut buffer[carried..])?;
+            // A byte is read ahead to know whether these chunks are the last ones.
+            let mut next = [0];
+            let at_end = filled < buffer_size || read_full(&mut file, &mut next)? == 0;
+
+            for &(i, chunk_size) in chunk_sizes {
+                let mut offset = 0;
+                loop {
+                    let end = filled.min(offset + chunk_size);
+                    (self.callbacks[i])(&mut Handle::Content {
+                        file_path: path,
+                        content: &buffer[offset..end],
+                        truncated: !at_end || end < filled,
+                    });
+                    offset = end;
+                    if offset == filled {
+                        break;
+                    }
                 }
-                Some(Err(error)) => Err(error),
-            },
-            _ => unreachable!(),
+            }
+
+            if at_end {
+                return Ok(());
+            }
+            buffer[0] = next[0];
+            carried = 1;
+        }
+    }
+
+    fn walk_dir(
+        &mut self,
+        path: &Path,
+        read_dir: fs::ReadDir,
+        depth: usize,
+        remaining_callbacks: usize,
+    ) -> Result<()> {
+        if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Ok(());
+        }
+
+        if !self.follow_symlinks {
+            return self.walk_entries(path, read_dir, depth, remaining_callbacks);
+        }
+
+        let canonical_path = match fs::canonicalize(path) {
+            Ok(canonical_path) => canonical_path,
+            Err(error) => return self.handle_error(path, error),
+        };
+        if self.ancestors.contains(&canonical_path) {
+            log::warn!("skipping {path:?}: symlink loop to {canonical_path:?}");
+            return Ok(());
+        }
+
+        self.ancestors.push(canonical_path);
+        let result = self.walk_entries(path, read_dir, depth, remaining_callbacks);
+        self.ancestors.pop();
+        result
+    }
+
+    fn walk_entries(
+        &mut self,
+        path: &Path,
+        read_dir: fs::ReadDir,
+        depth: usize,
+        remaining_callbacks: usize,
+    ) -> Result<()> {
+        if self.sort_entries {
+            let mut entries = Vec::new();
+            for entry in read_dir {
+                match entry {
+                    Ok(entry) => entries.push(entry),
+                    Err(error) => self.handle_error(path, error)?,
+                }
+            }
+            entries.sort_by_key(fs::DirEntry::file_name);
+            entries.into_iter().try_for_each(|entry| {
+                self.walk_recursive(&entry.path(), depth + 1, remaining_callbacks)
+            })
+        } else {
+            read_dir.into_iter().try_for_each(|entry| match entry {
+                Ok(entry) => self.walk_recursive(&entry.path(), depth + 1, remaining_callbacks),
+                Err(error) => self.handle_error(path, error),
+            })
         }
     }
 
@@ -96,10 +345,10 @@ impl<'a> Walker<'a> {
                         descend
                     }
                     Handle::File(file_handle) => {
-                        let read = file_handle.is_read;
-                        file_handle.is_read = false;
+                        let request = file_handle.request.take();
+                        file_handle.requests.push(request);
 
-                        read
+                        request.is_some()
                     }
                     _ => true,
                 };
@@ -113,6 +362,9 @@ impl<'a> Walker<'a> {
 
         skipped_callbacks.iter().rev().for_each(|&i| {
             self.callbacks.swap(i, remaining_callbacks - 1);
+            if let Handle::File(file_handle) = handle {
+                file_handle.requests.swap(i, remaining_callbacks - 1);
+            }
             remaining_callbacks -= 1;
         });
 
@@ -125,9 +377,13 @@ impl<'a> Walker<'a> {
 pub enum Handle<'a> {
     Dir(DirHandle<'a>),
     File(FileHandle<'a>),
+    /// The content requested by `FileHandle::read`, `read_limited` or `read_chunks`.
     Content {
         file_path: &'a Path,
         content: &'a [u8],
+        /// Set if the file continues past `content`: it was cut by `read_limited`, or
+        /// it is not the last chunk given by `read_chunks`.
+        truncated: bool,
     },
 }
 
@@ -158,33 +414,135 @@ impl<'a> DirHandle<'a> {
     pub fn path(&self) -> &Path {
         self.path
     }
+
+    /// See `is_hidden`.
+    pub fn is_hidden(&self) -> bool {
+        is_hidden(self.path)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ReadRequest {
+    Full,
+    Limited(usize),
+    Chunks(usize),
 }
 
 pub struct FileHandle<'a> {
     path: &'a Path,
-    is_read: bool,
-    content: Option<Result<Vec<u8>>>,
+    /// The request of the callback being run.
+    request: Option<ReadRequest>,
+    /// The requests of the callbacks run already, in the order of the callbacks.
+    requests: Vec<Option<ReadRequest>>,
 }
 
 impl<'a> FileHandle<'a> {
     fn new(path: &'a std::path::Path) -> Self {
         Self {
             path,
-            is_read: false,
-            content: None,
+            request: None,
+            requests: Vec::new(),
         }
     }
 
+    /// Requests the whole content of the file. If a callback calls several of `read`,
+    /// `read_limited` and `read_chunks`, only the last call counts.
     pub fn read(&mut self) {
-        if self.content.is_none() {
-            let content = fs::read(self.path);
-            self.content = Some(content);
-        }
+        self.request = Some(ReadRequest::Full);
+    }
 
-        self.is_read = true;
+    /// Requests at most `max_bytes` of the file, which is never read further unless
+    /// another callback asks for more.
+    pub fn read_limited(&mut self, max_bytes: usize) {
+        self.request = Some(ReadRequest::Limited(max_bytes));
+    }
+
+    /// Requests the content as a sequence of chunks of at most `chunk_size` bytes each,
+    /// passed to the callback one after another. Memory use is bounded by the chunk size
+    /// whatever the file size. An empty file gives a single empty chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn read_chunks(&mut self, chunk_size: usize) {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        self.request = Some(ReadRequest::Chunks(chunk_size));
     }
 
     pub fn path(&self) -> &Path {
         self.path
     }
+
+    /// Queries file metadata without reading its content.
+    pub fn metadata(&self) -> Result<fs::Metadata> {
+        fs::metadata(self.path)
+    }
+
+    /// See `is_hidden`.
+    pub fn is_hidden(&self) -> bool {
+        is_hidden(self.path)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns `true` if the entry at `path` is hidden: on Windows, if it has the hidden or
+/// the system attribute, elsewhere if its name starts with a dot. Paths without a file
+/// name, like `.` or `..`, are never hidden.
+///
+/// On Windows, entries whose attributes cannot be queried are not hidden, their errors
+/// are left to the walk.
+pub fn is_hidden(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+
+        path.file_name().is_some()
+            && fs::symlink_metadata(path).is_ok_and(|metadata| {
+                metadata.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0
+            })
+    }
+
+    // Compared as bytes, so that names which are not valid UTF-8 work too.
+    #[cfg(not(windows))]
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reads until `buffer` is full or the reader is exhausted.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reads at most `limit` bytes of the file, or all of it if there is no limit. Returns
+/// the content and whether the file is longer.
+fn read_content(path: &Path, limit: Option<usize>) -> Result<(Vec<u8>, bool)> {
+    let Some(limit) = limit else {
+        return Ok((fs::read(path)?, false));
+    };
+
+    let file = fs::File::open(path)?;
+    let len = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+    // The file might have grown since the metadata was queried, so the length is only
+    // a hint for the buffer.
+    let mut content = Vec::with_capacity(limit.min(len));
+    let mut file = file.take(limit as u64);
+    file.read_to_end(&mut content)?;
+
+    let truncated = content.len() == limit && read_full(file.get_mut(), &mut [0])? > 0;
+    Ok((content, truncated))
 }
@@ -1,11 +1,17 @@
 use tempdir::TempDir;
 
 use std::{
+    collections::HashMap,
+    ffi::OsString,
     fs, io,
     path::{Component, Path},
+    time::{Duration, SystemTime},
 };
 
-use fswalk::{Handle, Walker};
+use fswalk::{
+    convenience::{count_by_extension, dir_size, newest_file},
+    is_hidden, ErrorAction, Handle, Walker,
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -59,7 +65,9 @@ fn test_simple() {
         walker.add_callback(|handle| match handle {
             Handle::Dir(dir_handle) => dir_handle.descend(),
             Handle::File(file_handle) => file_handle.read(),
-            Handle::Content { content, file_path } => {
+            Handle::Content {
+                content, file_path, ..
+            } => {
                 let file_path_components = file_path.components().collect::<Vec<_>>();
                 for (path_str, expected_content) in tree_desc {
                     let desc_components = Path::new(path_str).components().collect::<Vec<_>>();
@@ -170,3 +178,637 @@ fn test_error() {
     walker.add_callback(|_| ());
     assert!(walker.walk("oiuabsas/sapdigu/aspgdh").is_err());
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+const CONVENIENCE_TREE: TreeDesc = &[
+    ("a.txt", b"0123456789"),
+    ("dir/b.txt", b"hello"),
+    ("dir/c.rs", b"fn f(){}"),
+    ("dir/.hidden", b"1234"),
+    ("dir/sub/noext", b"abc"),
+    ("dir/sub/archive.tar.gz", b"gzgzgz"),
+    ("empty/", b""),
+];
+
+fn set_mtime(path: impl AsRef<Path>, secs: u64) -> io::Result<()> {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(time)
+}
+
+#[test]
+fn test_dir_size() {
+    let tmp_dir = make_tree(CONVENIENCE_TREE).unwrap();
+
+    assert_eq!(dir_size(tmp_dir.path()).unwrap().0, 36);
+    assert_eq!(dir_size(tmp_dir.path().join("dir/sub")).unwrap().0, 9);
+    assert_eq!(dir_size(tmp_dir.path().join("a.txt")).unwrap().0, 10);
+    assert_eq!(dir_size(tmp_dir.path().join("empty")).unwrap().0, 0);
+    assert!(dir_size(tmp_dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn test_count_by_extension() {
+    let tmp_dir = make_tree(CONVENIENCE_TREE).unwrap();
+
+    let expected = [("txt", 2), ("rs", 1), ("gz", 1), ("", 2)]
+        .into_iter()
+        .map(|(ext, count)| (OsString::from(ext), count))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(count_by_extension(tmp_dir.path()).unwrap().0, expected);
+
+    assert!(count_by_extension(tmp_dir.path().join("empty"))
+        .unwrap()
+        .0
+        .is_empty());
+}
+
+#[test]
+fn test_newest_file() {
+    let tmp_dir = make_tree(CONVENIENCE_TREE).unwrap();
+    for (path_str, _) in CONVENIENCE_TREE {
+        if !path_str.ends_with("/") {
+            set_mtime(tmp_dir.path().join(path_str), 1_000).unwrap();
+        }
+    }
+    set_mtime(tmp_dir.path().join("dir/sub/noext"), 5_000).unwrap();
+    set_mtime(tmp_dir.path().join("dir/.hidden"), 3_000).unwrap();
+
+    let (path, mtime) = newest_file(tmp_dir.path()).unwrap().0.unwrap();
+    assert_eq!(path, tmp_dir.path().join("dir/sub/noext"));
+    assert_eq!(mtime, SystemTime::UNIX_EPOCH + Duration::from_secs(5_000));
+
+    let (path, _) = newest_file(tmp_dir.path().join("dir/b.txt"))
+        .unwrap()
+        .0
+        .unwrap();
+    assert_eq!(path, tmp_dir.path().join("dir/b.txt"));
+
+    assert!(newest_file(tmp_dir.path().join("empty"))
+        .unwrap()
+        .0
+        .is_none());
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Relative paths of all entries under `root`, in the order of the walk.
+fn visited_paths<'a>(
+    configure: impl FnOnce(Walker<'a>) -> Walker<'a>,
+    root: &'a Path,
+) -> io::Result<Vec<String>> {
+    let mut paths = vec![];
+
+    {
+        let mut walker = configure(Walker::new());
+        walker.add_callback(|handle| {
+            let path = match handle {
+                Handle::Dir(dir_handle) => {
+                    dir_handle.descend();
+                    dir_handle.path()
+                }
+                Handle::File(file_handle) => file_handle.path(),
+                Handle::Content { .. } => unreachable!(),
+            };
+            let relative = path.strip_prefix(root).unwrap();
+            paths.push(relative.to_str().unwrap().to_owned());
+        });
+        walker.walk(root)?;
+    }
+
+    Ok(paths)
+}
+
+#[test]
+fn test_sort_entries() {
+    let tree_desc: TreeDesc = &[
+        ("b/z", b""),
+        ("b/a/", b""),
+        ("c", b""),
+        ("a/y/x", b""),
+        ("a/x", b""),
+        ("ab", b""),
+    ];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+
+    let paths = visited_paths(|walker| walker.sort_entries(true), tmp_dir.path()).unwrap();
+    assert_eq!(
+        paths,
+        ["", "a", "a/x", "a/y", "a/y/x", "ab", "b", "b/a", "b/z", "c"]
+    );
+}
+
+#[test]
+fn test_max_depth() {
+    let tree_desc: TreeDesc = &[("1/2/3/4/5/6/7/8/deep", b"deep"), ("1/shallow", b"")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+
+    let paths = visited_paths(
+        |walker| walker.max_depth(3).sort_entries(true),
+        tmp_dir.path(),
+    )
+    .unwrap();
+    assert_eq!(paths, ["", "1", "1/2", "1/2/3", "1/shallow"]);
+
+    let paths = visited_paths(|walker| walker.max_depth(0), tmp_dir.path()).unwrap();
+    assert_eq!(paths, [""]);
+
+    let paths = visited_paths(|walker| walker.max_depth(100), tmp_dir.path()).unwrap();
+    assert_eq!(paths.len(), 11);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_loop() {
+    use std::os::unix::fs::symlink;
+
+    let tree_desc: TreeDesc = &[("a/b/file", b"content")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    symlink(tmp_dir.path().join("a"), tmp_dir.path().join("a/b/loop")).unwrap();
+    symlink(tmp_dir.path().join("a/b"), tmp_dir.path().join("shortcut")).unwrap();
+
+    let paths = visited_paths(|walker| walker.sort_entries(true), tmp_dir.path()).unwrap();
+    assert_eq!(
+        paths,
+        [
+            "",
+            "a",
+            "a/b",
+            "a/b/file",
+            "a/b/loop",
+            "shortcut",
+            "shortcut/file",
+            "shortcut/loop",
+            "shortcut/loop/b",
+        ]
+    );
+
+    let paths = visited_paths(
+        |walker| walker.sort_entries(true).follow_symlinks(false),
+        tmp_dir.path(),
+    )
+    .unwrap();
+    assert_eq!(paths, ["", "a", "a/b", "a/b/file"]);
+
+    // The root is followed even if it is a symlink.
+    let root = tmp_dir.path().join("shortcut");
+    let paths = visited_paths(
+        |walker| walker.sort_entries(true).follow_symlinks(false),
+        &root,
+    )
+    .unwrap();
+    assert_eq!(paths, ["", "file"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_to_file() {
+    use std::os::unix::fs::symlink;
+
+    let tree_desc: TreeDesc = &[("file", b"content")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    symlink(tmp_dir.path().join("file"), tmp_dir.path().join("link")).unwrap();
+
+    for follow in [false, true] {
+        let paths = visited_paths(
+            |walker| walker.sort_entries(true).follow_symlinks(follow),
+            tmp_dir.path(),
+        )
+        .unwrap();
+        assert_eq!(paths, ["", "file", "link"]);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_skip_hidden() {
+    let tree_desc: TreeDesc = &[
+        (".hidden", b""),
+        ("visible", b""),
+        (".git/config", b""),
+        ("dir/.env", b""),
+        ("dir/file", b""),
+    ];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+
+    let paths = visited_paths(
+        |walker| walker.sort_entries(true).skip_hidden(true),
+        tmp_dir.path(),
+    )
+    .unwrap();
+    assert_eq!(paths, ["", "dir", "dir/file", "visible"]);
+
+    let paths = visited_paths(
+        |walker| walker.sort_entries(true).skip_hidden(false),
+        tmp_dir.path(),
+    )
+    .unwrap();
+    assert_eq!(
+        paths,
+        [
+            "",
+            ".git",
+            ".git/config",
+            ".hidden",
+            "dir",
+            "dir/.env",
+            "dir/file",
+            "visible"
+        ]
+    );
+
+    // The root is walked even if it is hidden.
+    let root = tmp_dir.path().join(".git");
+    let paths = visited_paths(|walker| walker.skip_hidden(true), &root).unwrap();
+    assert_eq!(paths, ["", "config"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_is_hidden() {
+    let tree_desc: TreeDesc = &[(".hidden", b""), (".git/config", b""), ("dir/.env", b"")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+
+    let mut hidden = vec![];
+    {
+        let mut walker = Walker::new().sort_entries(true);
+        walker.add_callback(|handle| {
+            let (path, is_hidden) = match handle {
+                Handle::Dir(dir_handle) => {
+                    dir_handle.descend();
+                    (dir_handle.path(), dir_handle.is_hidden())
+                }
+                Handle::File(file_handle) => (file_handle.path(), file_handle.is_hidden()),
+                Handle::Content { .. } => unreachable!(),
+            };
+            if is_hidden {
+                hidden.push(path.strip_prefix(tmp_dir.path()).unwrap().to_owned());
+            }
+        });
+        walker.walk(tmp_dir.path()).unwrap();
+    }
+    assert_eq!(
+        hidden,
+        [
+            Path::new(".git"),
+            Path::new(".hidden"),
+            Path::new("dir/.env")
+        ]
+    );
+
+    assert!(is_hidden(Path::new("a/.b")));
+    assert!(!is_hidden(Path::new(".b/a")));
+    for path in ["", ".", "..", "a/.", "a/.."] {
+        assert!(!is_hidden(Path::new(path)), "{path:?}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let tmp_dir = make_tree(&[("dir/", b"")]).unwrap();
+    let dir = tmp_dir.path().join(OsStr::from_bytes(b"caf\xe9"));
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join(OsStr::from_bytes(b"\xff\xfe")), b"latin").unwrap();
+    fs::write(dir.join(OsStr::from_bytes(b".\xff")), b"hidden").unwrap();
+
+    for (skip_hidden, expected) in [
+        (false, &[&b"hidden"[..], b"latin"][..]),
+        (true, &[b"latin"]),
+    ] {
+        let mut contents = vec![];
+        {
+            let mut walker = Walker::new().skip_hidden(skip_hidden).sort_entries(true);
+            walker.add_callback(|handle| match handle {
+                Handle::Dir(dir_handle) => dir_handle.descend(),
+                Handle::File(file_handle) => file_handle.read(),
+                Handle::Content {
+                    file_path, content, ..
+                } => {
+                    assert!(file_path.starts_with(&dir));
+                    contents.push(content.to_vec());
+                }
+            });
+            walker.walk(tmp_dir.path()).unwrap();
+        }
+        assert_eq!(contents, expected);
+    }
+
+    assert_eq!(dir_size(tmp_dir.path()).unwrap().0, 11);
+}
+
+#[cfg(windows)]
+#[test]
+fn test_skip_hidden_windows() {
+    use std::process::Command;
+
+    let tree_desc: TreeDesc = &[
+        (".dotfile", b""),
+        ("hidden/file", b""),
+        ("dir/secret", b""),
+        ("dir/file", b""),
+    ];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    for path in ["hidden", "dir/secret"] {
+        let status = Command::new("attrib")
+            .arg("+h")
+            .arg(tmp_dir.path().join(path))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    // Dotfiles are not hidden on Windows.
+    let paths = visited_paths(
+        |walker| walker.sort_entries(true).skip_hidden(true),
+        tmp_dir.path(),
+    )
+    .unwrap();
+    assert_eq!(paths, ["", ".dotfile", "dir", "dir\\file"]);
+
+    let paths = visited_paths(|walker| walker.sort_entries(true), tmp_dir.path()).unwrap();
+    assert_eq!(paths.len(), 7);
+}
+
+#[cfg(windows)]
+#[test]
+fn test_verbatim_paths() {
+    let tree_desc: TreeDesc = &[("a/b/file", b"content"), ("c", b"")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+
+    // Canonical paths on Windows are verbatim ones, prefixed with `\\?\`.
+    let root = fs::canonicalize(tmp_dir.path()).unwrap();
+    assert!(matches!(
+        root.components().next(),
+        Some(Component::Prefix(prefix)) if prefix.kind().is_verbatim()
+    ));
+
+    let paths = visited_paths(|walker| walker.sort_entries(true), &root).unwrap();
+    assert_eq!(paths, ["", "a", "a\\b", "a\\b\\file", "c"]);
+    assert_eq!(dir_size(&root).unwrap().0, 7);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_error_actions() {
+    let missing = Path::new("oiuabsas/sapdigu/aspgdh");
+
+    let mut walker = Walker::new().on_error(|_, _| ErrorAction::Skip);
+    walker.add_callback(|_| ());
+    let skipped = walker.walk(missing).unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].0, missing);
+    assert_eq!(skipped[0].1.kind(), io::ErrorKind::Unsupported);
+
+    let mut seen = vec![];
+    let mut walker = Walker::new().on_error(|path, error| {
+        seen.push((path.to_owned(), error.kind()));
+        ErrorAction::Abort
+    });
+    walker.add_callback(|_| ());
+    assert!(walker.walk(missing).is_err());
+    drop(walker);
+    assert_eq!(seen, [(missing.to_owned(), io::ErrorKind::Unsupported)]);
+}
+
+#[test]
+fn test_file_deleted_before_read() {
+    let tree_desc: TreeDesc = &[("a", b"a"), ("b", b"b"), ("c", b"c")];
+
+    for skip in [true, false] {
+        let tmp_dir = make_tree(tree_desc).unwrap();
+        let mut contents = vec![];
+
+        let result = {
+            let action = if skip {
+                ErrorAction::Skip
+            } else {
+                ErrorAction::Abort
+            };
+            let mut walker = Walker::new()
+                .sort_entries(true)
+                .on_error(move |_, _| action);
+            walker.add_callback(|handle| match handle {
+                Handle::Dir(dir_handle) => dir_handle.descend(),
+                Handle::File(file_handle) => {
+                    if file_handle.path().ends_with("b") {
+                        fs::remove_file(file_handle.path()).unwrap();
+                    }
+                    file_handle.read();
+                }
+                Handle::Content { content, .. } => contents.push(content.to_vec()),
+            });
+            walker.walk(tmp_dir.path())
+        };
+
+        if skip {
+            let skipped = result.unwrap();
+            assert_eq!(skipped.len(), 1);
+            assert_eq!(skipped[0].0, tmp_dir.path().join("b"));
+            assert_eq!(skipped[0].1.kind(), io::ErrorKind::NotFound);
+            assert_eq!(contents, [b"a", b"c"]);
+        } else {
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+            assert_eq!(contents, [b"a"]);
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unreadable_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tree_desc: TreeDesc = &[("a/file", b""), ("locked/file", b""), ("z/file", b"")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    let locked = tmp_dir.path().join("locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+    // Permissions don't apply to root.
+    if fs::read_dir(&locked).is_err() {
+        let mut walker = Walker::new();
+        walker.add_callback(|handle| {
+            if let Handle::Dir(dir_handle) = handle {
+                dir_handle.descend();
+            }
+        });
+        let error = walker.walk(tmp_dir.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+        let paths = visited_paths(
+            |walker| walker.sort_entries(true).on_error(|_, _| ErrorAction::Skip),
+            tmp_dir.path(),
+        )
+        .unwrap();
+        assert_eq!(paths, ["", "a", "a/file", "locked", "z", "z/file"]);
+    }
+
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_convenience_unreadable_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tree_desc: TreeDesc = &[("a.txt", b"abc"), ("locked/b.rs", b"defgh")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    let locked = tmp_dir.path().join("locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+    // Permissions don't apply to root.
+    if fs::read_dir(&locked).is_err() {
+        let (size, skipped) = dir_size(tmp_dir.path()).unwrap();
+        assert_eq!(size, 3);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, locked);
+        assert_eq!(skipped[0].1.kind(), io::ErrorKind::PermissionDenied);
+
+        let (counts, skipped) = count_by_extension(tmp_dir.path()).unwrap();
+        assert_eq!(counts, HashMap::from([(OsString::from("txt"), 1)]));
+        assert_eq!(skipped.len(), 1);
+
+        let (newest, skipped) = newest_file(tmp_dir.path()).unwrap();
+        assert_eq!(newest.unwrap().0, tmp_dir.path().join("a.txt"));
+        assert_eq!(skipped.len(), 1);
+
+        let error = dir_size(&locked).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn generated_content(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+#[test]
+fn test_read_limited() {
+    const LIMIT: usize = 4096;
+
+    let tree_desc: TreeDesc = &[("small", b"hello, world!"), ("empty", b"")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    let big_content = generated_content(8 << 20);
+    fs::write(tmp_dir.path().join("big"), &big_content).unwrap();
+    fs::write(tmp_dir.path().join("exact"), &big_content[..LIMIT]).unwrap();
+
+    let mut contents = HashMap::new();
+    {
+        let mut walker = Walker::new();
+        walker.add_callback(|handle| match handle {
+            Handle::Dir(dir_handle) => dir_handle.descend(),
+            Handle::File(file_handle) => file_handle.read_limited(LIMIT),
+            Handle::Content {
+                file_path,
+                content,
+                truncated,
+            } => {
+                let name = file_path.file_name().unwrap().to_owned();
+                let previous = contents.insert(name, (content.to_vec(), *truncated));
+                assert!(previous.is_none());
+            }
+        });
+        walker.walk(tmp_dir.path()).unwrap();
+    }
+
+    let content = |name: &str| contents[&OsString::from(name)].clone();
+    assert_eq!(content("big"), (big_content[..LIMIT].to_vec(), true));
+    assert_eq!(content("exact"), (big_content[..LIMIT].to_vec(), false));
+    assert_eq!(content("small"), (b"hello, world!".to_vec(), false));
+    assert_eq!(content("empty"), (vec![], false));
+}
+
+#[test]
+fn test_read_chunks() {
+    const CHUNK_SIZE: usize = 64 << 10;
+
+    for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, (5 << 20) + 123] {
+        let tmp_dir = make_tree(&[]).unwrap();
+        let file_content = generated_content(len);
+        fs::write(tmp_dir.path().join("file"), &file_content).unwrap();
+
+        let mut chunks = vec![];
+        let mut chunk_count = 0;
+        let mut last_chunk_count = 0;
+        {
+            let mut walker = Walker::new();
+            walker.add_callback(|handle| match handle {
+                Handle::Dir(dir_handle) => dir_handle.descend(),
+                Handle::File(file_handle) => file_handle.read_chunks(CHUNK_SIZE),
+                Handle::Content {
+                    content, truncated, ..
+                } => {
+                    assert!(content.len() <= CHUNK_SIZE);
+                    assert_eq!(last_chunk_count, 0, "chunk after the last one");
+                    chunks.extend_from_slice(content);
+                    chunk_count += 1;
+                    if !*truncated {
+                        last_chunk_count += 1;
+                    }
+                }
+            });
+            walker.walk(tmp_dir.path()).unwrap();
+        }
+
+        assert!(chunks == file_content, "file of {len} bytes");
+        assert_eq!(chunk_count, len.div_ceil(CHUNK_SIZE).max(1));
+        assert_eq!(last_chunk_count, 1);
+    }
+}
+
+#[test]
+fn test_mixed_read_requests() {
+    let tree_desc: TreeDesc = &[("file", b"0123456789")];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+
+    let mut full = vec![];
+    let mut limited = vec![];
+    let mut chunks = vec![];
+    let mut not_read = 0;
+    {
+        fn make_callback<'a>(
+            read: impl Fn(&mut fswalk::FileHandle) + 'a,
+            contents: &'a mut Vec<(Vec<u8>, bool)>,
+        ) -> impl FnMut(&mut Handle) + 'a {
+            move |handle| match handle {
+                Handle::Dir(dir_handle) => dir_handle.descend(),
+                Handle::File(file_handle) => read(file_handle),
+                Handle::Content {
+                    content, truncated, ..
+                } => contents.push((content.to_vec(), *truncated)),
+            }
+        }
+
+        let mut walker = Walker::new();
+        walker.add_callback(make_callback(|file| file.read_chunks(4), &mut chunks));
+        walker.add_callback(|handle| match handle {
+            Handle::Dir(dir_handle) => dir_handle.descend(),
+            Handle::File(_) => {}
+            Handle::Content { .. } => not_read += 1,
+        });
+        walker.add_callback(make_callback(|file| file.read_limited(3), &mut limited));
+        walker.add_callback(make_callback(|file| file.read(), &mut full));
+        walker.walk(tmp_dir.path()).unwrap();
+    }
+
+    assert_eq!(full, [(b"0123456789".to_vec(), false)]);
+    assert_eq!(limited, [(b"012".to_vec(), true)]);
+    assert_eq!(
+        chunks,
+        [
+            (b"0123".to_vec(), true),
+            (b"4567".to_vec(), true),
+            (b"89".to_vec(), false),
+        ]
+    );
+    assert_eq!(not_read, 0);
+}
@@ -1,49 +1,198 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields};
+use proc_macro2::TokenTree;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse_macro_input, parse_quote, spanned::Spanned, Attribute, Data, DeriveInput, Field, Fields,
+    GenericArgument, Ident, Index, Member, Meta, NestedMeta, PathArguments, ReturnType, Type,
+};
 
-#[proc_macro_derive(Scan)]
+/// Works for structs of all kinds and for enums. Fields marked with `#[scan(skip)]` are
+/// not scanned, so they can be of any type.
+///
+/// A type parameter gets a `Scan` bound only if it is used in a scanned field outside
+/// of a reference: references are never scanned, see `impl Scan for &T`. Bounds on
+/// associated types like `T::Item` are not inferred, add them to the where-clause.
+#[proc_macro_derive(Scan, attributes(scan))]
 pub fn derive_scan(input: TokenStream) -> TokenStream {
     let DeriveInput {
         ident,
         data,
-        generics,
+        mut generics,
         ..
     }: DeriveInput = parse_macro_input!(input);
 
-    let fields = match data {
-        Data::Struct(struct_data) => struct_data.fields,
-        _ => panic!("`Scan` can only be derived for structs"),
+    let visit_gcs = format_ident!("visit_gcs");
+    let visit_gc_origins = format_ident!("visit_gc_origins");
+    let (visit_gcs_body, scanned_types) = match derive_body(&data, &visit_gcs) {
+        Ok(derived) => derived,
+        Err(err) => return err.to_compile_error().into(),
     };
-
-    let gc_collection_statements = match fields {
-        Fields::Unit => vec![],
-        Fields::Named(named_fields) => named_fields
-            .named
-            .into_iter()
-            .map(|field| {
-                let field_name = field.ident;
-                quote! {
-                    gcs.extend((&self.#field_name as &dyn Scan).collect_gcs());
-                }
-            })
-            .collect(),
-        _ => unimplemented!(),
+    let (visit_gc_origins_body, _) = match derive_body(&data, &visit_gc_origins) {
+        Ok(derived) => derived,
+        Err(err) => return err.to_compile_error().into(),
     };
 
+    let bounded_params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .filter(|param| scanned_types.iter().any(|ty| type_uses_param(ty, param)))
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for param in bounded_params {
+        where_clause.predicates.push(parse_quote!(#param: Scan));
+    }
+
     let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
 
     let expanded = quote! {
         impl #impl_generics Scan for #ident #type_generics #where_clause {
-            fn collect_gcs(&self) -> Vec<usize> {
-                let mut gcs = Vec::new();
-
-                #(#gc_collection_statements)*
+            // Types without scanned fields don't use the visitor.
+            #[allow(unused_variables)]
+            fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+                #visit_gcs_body
+            }
 
-                gcs
+            #[allow(unused_variables)]
+            fn visit_gc_origins(&self, visitor: &mut dyn FnMut(::gc::GcOrigin)) {
+                #visit_gc_origins_body
             }
         }
     };
 
     expanded.into()
 }
+
+/// Statements passing the scanned fields of `self` to `visitor` with the `method` of
+/// `Scan`, and the types of these fields.
+fn derive_body(data: &Data, method: &Ident) -> syn::Result<(proc_macro2::TokenStream, Vec<Type>)> {
+    match data {
+        Data::Struct(struct_data) => {
+            let fields = scanned_fields(&struct_data.fields)?;
+            // Spanned by the field type, so that an unscannable field is reported at the
+            // field.
+            let statements = fields.iter().map(|(member, field)| {
+                quote_spanned! {field.ty.span()=>
+                    Scan::#method(&self.#member, visitor);
+                }
+            });
+            let body = quote!(#(#statements)*);
+            Ok((
+                body,
+                fields.into_iter().map(|(_, field)| field.ty).collect(),
+            ))
+        }
+        Data::Enum(enum_data) if enum_data.variants.is_empty() => {
+            Ok((quote!(match *self {}), vec![]))
+        }
+        Data::Enum(enum_data) => {
+            let mut arms = vec![];
+            let mut scanned_types = vec![];
+            for variant in &enum_data.variants {
+                let fields = scanned_fields(&variant.fields)?;
+                let bindings = (0..fields.len())
+                    .map(|i| format_ident!("field_{}", i))
+                    .collect::<Vec<_>>();
+                let members = fields.iter().map(|(member, _)| member);
+                let statements = fields.iter().zip(&bindings).map(|((_, field), binding)| {
+                    quote_spanned! {field.ty.span()=>
+                        Scan::#method(#binding, visitor);
+                    }
+                });
+
+                // Braces fit all the kinds of variants, e.g. `Self::Pair { 0: field_0, .. }`.
+                let variant_ident = &variant.ident;
+                arms.push(quote! {
+                    Self::#variant_ident { #(#members: #bindings,)* .. } => {
+                        #(#statements)*
+                    }
+                });
+                scanned_types.extend(fields.into_iter().map(|(_, field)| field.ty));
+            }
+            Ok((quote!(match self { #(#arms)* }), scanned_types))
+        }
+        Data::Union(union_data) => Err(syn::Error::new_spanned(
+            union_data.union_token,
+            "`Scan` can't be derived for unions",
+        )),
+    }
+}
+
+/// Fields not marked with `#[scan(skip)]`, with their names or indices.
+fn scanned_fields(fields: &Fields) -> syn::Result<Vec<(Member, Field)>> {
+    let mut scanned_fields = vec![];
+    for (i, field) in fields.iter().enumerate() {
+        if is_skipped(&field.attrs)? {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        scanned_fields.push((member, field.clone()));
+    }
+    Ok(scanned_fields)
+}
+
+fn is_skipped(attrs: &[Attribute]) -> syn::Result<bool> {
+    let mut skipped = false;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("scan")) {
+        let invalid = || syn::Error::new_spanned(attr, "expected `#[scan(skip)]`");
+        let Meta::List(list) = attr.parse_meta()? else {
+            return Err(invalid());
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => skipped = true,
+                _ => return Err(invalid()),
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+fn type_uses_param(ty: &Type, param: &Ident) -> bool {
+    match ty {
+        Type::Reference(_) | Type::Ptr(_) | Type::BareFn(_) | Type::Never(_) => false,
+        Type::Array(array) => type_uses_param(&array.elem, param),
+        Type::Slice(slice) => type_uses_param(&slice.elem, param),
+        Type::Group(group) => type_uses_param(&group.elem, param),
+        Type::Paren(paren) => type_uses_param(&paren.elem, param),
+        Type::Tuple(tuple) => tuple.elems.iter().any(|elem| type_uses_param(elem, param)),
+        Type::Path(path) => {
+            path.path.is_ident(param)
+                || path
+                    .path
+                    .segments
+                    .iter()
+                    .any(|segment| arguments_use_param(&segment.arguments, param))
+        }
+        // Trait objects, `impl Trait` and macros: look for the parameter anywhere.
+        _ => tokens_use_param(quote!(#ty), param),
+    }
+}
+
+fn arguments_use_param(arguments: &PathArguments, param: &Ident) -> bool {
+    match arguments {
+        PathArguments::None => false,
+        PathArguments::AngleBracketed(args) => args.args.iter().any(|arg| match arg {
+            GenericArgument::Type(ty) => type_uses_param(ty, param),
+            GenericArgument::Binding(binding) => type_uses_param(&binding.ty, param),
+            _ => false,
+        }),
+        PathArguments::Parenthesized(args) => {
+            let output_uses_param = match &args.output {
+                ReturnType::Default => false,
+                ReturnType::Type(_, ty) => type_uses_param(ty, param),
+            };
+            output_uses_param || args.inputs.iter().any(|ty| type_uses_param(ty, param))
+        }
+    }
+}
+
+fn tokens_use_param(tokens: proc_macro2::TokenStream, param: &Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => ident == *param,
+        TokenTree::Group(group) => tokens_use_param(group.stream(), param),
+        _ => false,
+    })
+}
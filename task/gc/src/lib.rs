@@ -3,32 +3,95 @@
 pub use gc_derive::Scan;
 
 use std::{
-    cell::RefCell,
-    collections::HashSet,
+    any,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
     marker::PhantomData,
     ops::Deref,
     rc::{Rc, Weak},
+    sync::atomic::{AtomicU64, Ordering},
 };
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Unique among all the arenas of the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArenaId(u64);
+
+impl ArenaId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ArenaId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 pub struct Gc<T> {
     weak: Weak<T>,
+    arena_id: ArenaId,
 }
 
 impl<T> Clone for Gc<T> {
     fn clone(&self) -> Self {
         Self {
             weak: self.weak.clone(),
+            arena_id: self.arena_id,
         }
     }
 }
 
 impl<T> Gc<T> {
+    /// # Panics
+    ///
+    /// If the object is collected already, see `try_borrow`.
     pub fn borrow(&self) -> GcRef<'_, T> {
-        GcRef {
-            rc: self.weak.upgrade().unwrap(),
+        self.try_borrow().unwrap_or_else(|| {
+            panic!(
+                "`Gc<{}>` points to an object freed by arena {}: either the arena is \
+                 dropped, or the handle was only reachable from objects the arena doesn't \
+                 trace, like ones of another arena or `Rc`s shared outside of it; use \
+                 `Gc::try_borrow` if that is expected",
+                any::type_name::<T>(),
+                self.arena_id
+            )
+        })
+    }
+
+    /// `None` if the object is collected already. Unless handles were stored in objects
+    /// the arena can't trace, that means the arena is dropped.
+    pub fn try_borrow(&self) -> Option<GcRef<'_, T>> {
+        #[cfg(debug_assertions)]
+        borrow_log::record(self.weak.as_ptr() as usize);
+
+        self.weak.upgrade().map(|rc| GcRef {
+            rc,
             lifetime: PhantomData,
+        })
+    }
+
+    /// Whether the object is not collected yet.
+    pub fn is_alive(&self) -> bool {
+        self.weak.strong_count() > 0
+    }
+
+    /// The arena which allocated the object.
+    pub fn arena_id(&self) -> ArenaId {
+        self.arena_id
+    }
+
+    /// The handle as a capture of a `GcClosure`.
+    pub fn erase(&self) -> ErasedGcRef {
+        ErasedGcRef {
+            address: self.weak.as_ptr() as usize,
+            arena_id: self.arena_id,
         }
     }
 }
@@ -38,6 +101,17 @@ pub struct GcRef<'a, T> {
     lifetime: PhantomData<&'a Gc<T>>,
 }
 
+impl<'a, T> GcRef<'a, T> {
+    /// Projects into a part of the object, like `std::cell::Ref::map`.
+    pub fn map<U: ?Sized, F: Fn(&T) -> &U>(self, project: F) -> MappedGcRef<'a, T, U, F> {
+        MappedGcRef {
+            rc: self.rc,
+            project,
+            lifetime: PhantomData,
+        }
+    }
+}
+
 impl<'a, T> Deref for GcRef<'a, T> {
     type Target = T;
 
@@ -46,59 +120,523 @@ impl<'a, T> Deref for GcRef<'a, T> {
     }
 }
 
+/// See `GcRef::map`. The projection is applied on every dereference.
+pub struct MappedGcRef<'a, T, U: ?Sized, F> {
+    rc: Rc<T>,
+    project: F,
+    lifetime: PhantomData<(&'a Gc<T>, &'a U)>,
+}
+
+impl<'a, T, U: ?Sized, F: Fn(&T) -> &U> Deref for MappedGcRef<'a, T, U, F> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        (self.project)(&self.rc)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be scanned for `Gc` references",
+    label = "`{Self}` does not implement `Scan`",
+    note = "derive or implement `Scan` for it, or mark the field with `#[scan(skip)]`"
+)]
 pub trait Scan {
-    fn collect_gcs(&self) -> Vec<usize>;
-}
+    /// Passes the addresses of the objects the `Gc`s point to to `visitor`. The other
+    /// methods default to it.
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize));
+
+    /// Same as `visit_gcs`, with the arenas the objects come from, so that `Arena::sweep`
+    /// can tell foreign references apart. `Arena::sweep` only scans objects through it.
+    ///
+    /// The default doesn't know the arenas, so derived and built-in implementations
+    /// provide it as well.
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        self.visit_gcs(&mut |address| {
+            visitor(GcOrigin {
+                address,
+                arena_id: None,
+            })
+        })
+    }
 
-impl Scan for i32 {
+    /// Same as `visit_gcs`, but collects the addresses into a `Vec`.
     fn collect_gcs(&self) -> Vec<usize> {
-        vec![]
+        let mut addresses = Vec::new();
+        self.visit_gcs(&mut |address| addresses.push(address));
+        addresses
+    }
+
+    /// Same as `visit_gc_origins`, but collects the origins into a `Vec`.
+    fn collect_gc_origins(&self) -> Vec<GcOrigin> {
+        let mut origins = Vec::new();
+        self.visit_gc_origins(&mut |origin| origins.push(origin));
+        origins
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcOrigin {
+    pub address: usize,
+    /// Unknown for `Scan` implementations which only implement `visit_gcs`.
+    pub arena_id: Option<ArenaId>,
+}
+
+macro_rules! impl_scan_for_leaves {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl Scan for $type {
+                fn visit_gcs(&self, _visitor: &mut dyn FnMut(usize)) {}
+
+                fn visit_gc_origins(&self, _visitor: &mut dyn FnMut(GcOrigin)) {}
+            }
+        )*
+    };
+}
+
+// Types which can't hold a `Gc`. `&'static str` is covered by the impl for references.
+impl_scan_for_leaves! {
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize,
+    f32, f64, bool, char, (), str, String,
+}
+
+/// A borrow can point to `Gc`s, but it must not extend their reachability, so
+/// references are never scanned: only owned handles keep allocations alive.
+///
+/// Beware of autoref: `x.collect_gcs()` with `x: &U` where `U` is not `Scan`, like
+/// `&Rc<dyn Scan>`, resolves to this impl and returns nothing, dereference first.
+impl<T: ?Sized> Scan for &T {
+    fn visit_gcs(&self, _visitor: &mut dyn FnMut(usize)) {}
+
+    fn visit_gc_origins(&self, _visitor: &mut dyn FnMut(GcOrigin)) {}
+}
+
+impl<T: ?Sized> Scan for &mut T {
+    fn visit_gcs(&self, _visitor: &mut dyn FnMut(usize)) {}
+
+    fn visit_gc_origins(&self, _visitor: &mut dyn FnMut(GcOrigin)) {}
+}
+
 impl<T> Scan for Gc<T> {
-    fn collect_gcs(&self) -> Vec<usize> {
-        vec![self.weak.as_ptr() as usize]
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        visitor(self.weak.as_ptr() as usize)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        visitor(GcOrigin {
+            address: self.weak.as_ptr() as usize,
+            arena_id: Some(self.arena_id),
+        })
     }
 }
 
 impl<T: Scan> Scan for Option<T> {
-    fn collect_gcs(&self) -> Vec<usize> {
-        match self {
-            Some(x) => x.collect_gcs(),
-            None => vec![],
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        if let Some(x) = self {
+            x.visit_gcs(visitor)
+        }
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        if let Some(x) = self {
+            x.visit_gc_origins(visitor)
         }
     }
 }
 
-impl<T: Scan> Scan for Vec<T> {
-    fn collect_gcs(&self) -> Vec<usize> {
-        self.iter().flat_map(Scan::collect_gcs).collect()
+impl<T: Scan + ?Sized> Scan for Box<T> {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        (**self).visit_gcs(visitor)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        (**self).visit_gc_origins(visitor)
+    }
+}
+
+/// Scans through to the shared object. `Arena::sweep` counts the references of an object
+/// shared by several `Rc`s once, as the `Gc`s in it are only cloned once. Unlike `Gc`
+/// ones, cycles of `Rc`s are never collected: breaking them is the user's responsibility,
+/// and scanning such a cycle doesn't terminate.
+impl<T: Scan + ?Sized> Scan for Rc<T> {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        (**self).visit_gcs(visitor)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        if ScannedRcs::insert(Rc::as_ptr(self) as *const () as usize) {
+            (**self).visit_gc_origins(visitor)
+        }
+    }
+}
+
+thread_local! {
+    static SCANNED_RCS: RefCell<Option<HashSet<usize>>> = const { RefCell::new(None) };
+}
+
+/// The shared objects `Arena::sweep` has counted the references of, while alive.
+struct ScannedRcs;
+
+impl ScannedRcs {
+    fn start() -> Self {
+        SCANNED_RCS.set(Some(HashSet::new()));
+        Self
+    }
+
+    /// Whether the references of the object at `address` are to be counted, which is
+    /// always the case outside of `Arena::sweep`.
+    fn insert(address: usize) -> bool {
+        SCANNED_RCS.with_borrow_mut(|scanned| {
+            scanned
+                .as_mut()
+                .is_none_or(|scanned| scanned.insert(address))
+        })
+    }
+}
+
+impl Drop for ScannedRcs {
+    fn drop(&mut self) {
+        SCANNED_RCS.set(None);
     }
 }
 
 impl<T: Scan> Scan for RefCell<T> {
-    fn collect_gcs(&self) -> Vec<usize> {
-        self.borrow().collect_gcs()
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        self.borrow().visit_gcs(visitor)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        self.borrow().visit_gc_origins(visitor)
+    }
+}
+
+impl<T: Scan + Copy> Scan for Cell<T> {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        self.get().visit_gcs(visitor)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        self.get().visit_gc_origins(visitor)
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Default)]
+/// Collections scan every item in the order of iteration.
+macro_rules! impl_scan_for_collections {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl<T: Scan> Scan for $type {
+                fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+                    self.iter().for_each(|item| item.visit_gcs(visitor))
+                }
+
+                fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+                    self.iter().for_each(|item| item.visit_gc_origins(visitor))
+                }
+            }
+        )*
+    };
+}
+
+impl_scan_for_collections!([T], Vec<T>, VecDeque<T>, HashSet<T>, BTreeSet<T>);
+
+impl<T: Scan, const N: usize> Scan for [T; N] {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        self.as_slice().visit_gcs(visitor)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        self.as_slice().visit_gc_origins(visitor)
+    }
+}
+
+/// Both the keys and the values are scanned.
+macro_rules! impl_scan_for_maps {
+    ($($type:ident),* $(,)?) => {
+        $(
+            impl<K: Scan, V: Scan> Scan for $type<K, V> {
+                fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+                    for (key, value) in self {
+                        key.visit_gcs(visitor);
+                        value.visit_gcs(visitor);
+                    }
+                }
+
+                fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+                    for (key, value) in self {
+                        key.visit_gc_origins(visitor);
+                        value.visit_gc_origins(visitor);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_scan_for_maps!(HashMap, BTreeMap);
+
+macro_rules! impl_scan_for_tuples {
+    ($(($($param:ident $index:tt),+))*) => {
+        $(
+            impl<$($param: Scan),+> Scan for ($($param,)+) {
+                fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+                    $(self.$index.visit_gcs(visitor);)+
+                }
+
+                fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+                    $(self.$index.visit_gc_origins(visitor);)+
+                }
+            }
+        )*
+    };
+}
+
+impl_scan_for_tuples! {
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What `Scan` needs to know of a `Gc`: the address of the object and its arena. It
+/// doesn't keep the object alive on its own, see `GcClosure`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ErasedGcRef {
+    address: usize,
+    arena_id: ArenaId,
+}
+
+impl<T> From<&Gc<T>> for ErasedGcRef {
+    fn from(gc: &Gc<T>) -> Self {
+        gc.erase()
+    }
+}
+
+impl Scan for ErasedGcRef {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        visitor(self.address)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        visitor(GcOrigin {
+            address: self.address,
+            arena_id: Some(self.arena_id),
+        })
+    }
+}
+
+/// A closure stored in the arena together with the list of the `Gc`s it captures,
+/// which closures can't be scanned for. Prefer `gc_closure!`, which builds both from
+/// the same list of variables.
+///
+/// The list must match the captures exactly, and both mistakes go unnoticed until a
+/// sweep:
+/// * A capture missing from the list looks like a handle held outside of the arena, so
+///   its object is a root: it and everything reachable from it, e.g. the object holding
+///   the closure, are never collected.
+/// * A listed handle the closure doesn't hold is counted as a reference from inside the
+///   arena instead of the real one, so its object is collected as soon as it's
+///   unreachable from the arena, while the real handle is still in use.
+///
+/// `validate_captures` catches some of the former in tests.
+pub struct GcClosure<F> {
+    function: F,
+    captures: Vec<ErasedGcRef>,
+}
+
+impl<F> GcClosure<F> {
+    pub fn new(function: F, captures: Vec<ErasedGcRef>) -> Self {
+        Self { function, captures }
+    }
+
+    pub fn captures(&self) -> &[ErasedGcRef] {
+        &self.captures
+    }
+
+    /// Wraps the closure keeping the captures, e.g. into a `Box<dyn Fn(..)>` to name the
+    /// type of a field.
+    pub fn map<G>(self, wrap: impl FnOnce(F) -> G) -> GcClosure<G> {
+        GcClosure {
+            function: wrap(self.function),
+            captures: self.captures,
+        }
+    }
+
+    /// Calls the closure with `call`, e.g. on dummy arguments, and checks that every `Gc`
+    /// borrowed meanwhile is either captured or one of `arguments`. Only borrows are
+    /// seen, so captures which are merely cloned or stored go unchecked. Does nothing
+    /// but the call in release builds.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if an object borrowed by the closure is not listed.
+    pub fn validate_captures<R>(&self, arguments: &[ErasedGcRef], call: impl FnOnce(&F) -> R) -> R {
+        #[cfg(debug_assertions)]
+        {
+            let (result, borrowed) = borrow_log::capture(|| call(&self.function));
+            let missed = borrowed
+                .into_iter()
+                .filter(|address| {
+                    !self
+                        .captures
+                        .iter()
+                        .chain(arguments)
+                        .any(|gc| gc.address == *address)
+                })
+                .collect::<HashSet<_>>();
+            assert!(
+                missed.is_empty(),
+                "`GcClosure<{}>` borrowed {} object(s) missing from its captures, \
+                 which makes them roots: add every captured `Gc` to the list",
+                any::type_name::<F>(),
+                missed.len()
+            );
+            result
+        }
+
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = arguments;
+            call(&self.function)
+        }
+    }
+}
+
+impl<F> Deref for GcClosure<F> {
+    type Target = F;
+
+    fn deref(&self) -> &Self::Target {
+        &self.function
+    }
+}
+
+impl<F> Scan for GcClosure<F> {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        self.captures.visit_gcs(visitor)
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        self.captures.visit_gc_origins(visitor)
+    }
+}
+
+impl<F> fmt::Debug for GcClosure<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcClosure")
+            .field("captures", &self.captures)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builds a `GcClosure` capturing clones of the listed `Gc` variables, which the body
+/// uses under the same names, e.g. `gc_closure!(env, counter; |x: i64| x + 1)`.
+#[macro_export]
+macro_rules! gc_closure {
+    ($($capture:ident),* $(,)?; || $body:expr) => {
+        $crate::gc_closure!($($capture),*; | | $body)
+    };
+    ($($capture:ident),* $(,)?; |$($arg:ident $(: $type:ty)?),* $(,)?| $body:expr) => {{
+        let captures = ::std::vec![$($crate::Gc::erase(&$capture)),*];
+        $(let $capture = ::std::clone::Clone::clone(&$capture);)*
+        $crate::GcClosure::new(move |$($arg $(: $type)?),*| $body, captures)
+    }};
+}
+
+/// The addresses of the objects borrowed on this thread, while someone listens.
+#[cfg(debug_assertions)]
+mod borrow_log {
+    use std::cell::RefCell;
+
+    thread_local! {
+        static LOG: RefCell<Option<Vec<usize>>> = const { RefCell::new(None) };
+    }
+
+    pub fn record(address: usize) {
+        LOG.with(|log| {
+            if let Some(log) = log.borrow_mut().as_mut() {
+                log.push(address);
+            }
+        });
+    }
+
+    /// Nested calls record into their own logs only.
+    pub fn capture<R>(f: impl FnOnce() -> R) -> (R, Vec<usize>) {
+        let outer = LOG.with(|log| log.replace(Some(vec![])));
+        let result = f();
+        let borrowed = LOG.with(|log| log.replace(outer)).unwrap_or_default();
+        (result, borrowed)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A `Gc` stored in an object of one arena while pointing to an object of another.
+/// The arena of the holder doesn't trace such references, while the other arena only
+/// sees the `Gc` as an external handle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForeignReference {
+    pub holder_type: &'static str,
+    pub holder_arena_id: ArenaId,
+    pub target_arena_id: ArenaId,
+}
+
+impl fmt::Display for ForeignReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` allocated by arena {} holds a `Gc` to an object of arena {}, \
+             which neither arena traces: keep every object graph within a single arena",
+            self.holder_type, self.holder_arena_id, self.target_arena_id
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SweepReport {
+    pub collected: usize,
+    /// Only in release builds, debug builds panic on a foreign reference right away.
+    pub foreign_references: Vec<ForeignReference>,
+}
+
+struct Allocation {
+    object: Rc<dyn Scan + 'static>,
+    type_name: &'static str,
+}
+
+impl Allocation {
+    fn address(&self) -> usize {
+        Rc::as_ptr(&self.object) as *const () as usize
+    }
+}
+
 pub struct Arena {
-    allocations: Vec<Rc<dyn Scan + 'static>>,
+    id: ArenaId,
+    allocations: Vec<Allocation>,
+    /// Allocation addresses to their indices in `allocations`.
+    indices: HashMap<usize, usize>,
 }
 
 impl Arena {
     pub fn new() -> Self {
         Self {
+            id: ArenaId::next(),
             allocations: Vec::new(),
+            indices: HashMap::new(),
         }
     }
 
+    pub fn id(&self) -> ArenaId {
+        self.id
+    }
+
     pub fn allocation_count(&self) -> usize {
         self.allocations.len()
     }
@@ -107,53 +645,107 @@ impl Arena {
         let allocation = Rc::new(object);
         let gc = Gc {
             weak: Rc::downgrade(&allocation),
+            arena_id: self.id,
         };
 
+        let allocation = Allocation {
+            object: allocation,
+            type_name: any::type_name::<T>(),
+        };
+        self.indices
+            .insert(allocation.address(), self.allocations.len());
         self.allocations.push(allocation);
 
         gc
     }
 
-    pub fn sweep(&mut self) {
+    /// # Panics
+    ///
+    /// In debug builds, if some object holds a `Gc` from another arena, see
+    /// `ForeignReference`.
+    pub fn sweep(&mut self) -> SweepReport {
+        let mut report = SweepReport::default();
+
         let mut internal_reference_counts = vec![0; self.allocation_count()];
-        self.allocations.iter().for_each(|allocation| {
-            allocation.collect_gcs().iter().for_each(|address| {
-                if let Some(index) = self.find_index_by_address(*address) {
-                    internal_reference_counts[index] += 1;
-                }
-            })
-        });
+        let scanned_rcs = ScannedRcs::start();
+        for allocation in &self.allocations {
+            allocation
+                .object
+                .visit_gc_origins(&mut |origin| match origin.arena_id {
+                    Some(arena_id) if arena_id != self.id => {
+                        let reference = ForeignReference {
+                            holder_type: allocation.type_name,
+                            holder_arena_id: self.id,
+                            target_arena_id: arena_id,
+                        };
+                        if cfg!(debug_assertions) {
+                            panic!("{reference}");
+                        }
+                        report.foreign_references.push(reference);
+                    }
+                    _ => {
+                        if let Some(index) = self.find_index_by_address(origin.address) {
+                            internal_reference_counts[index] += 1;
+                        }
+                    }
+                });
+        }
+        drop(scanned_rcs);
 
-        let mut marked = HashSet::<usize>::new();
-        self.allocations
+        let roots = self
+            .allocations
             .iter()
             .enumerate()
-            .for_each(|(i, allocation)| {
-                if Rc::weak_count(allocation) > internal_reference_counts[i] {
-                    self.mark_all(Rc::as_ptr(allocation) as *const () as usize, &mut marked);
-                }
-            });
+            .filter(|(i, allocation)| {
+                Rc::weak_count(&allocation.object) > internal_reference_counts[*i]
+            })
+            .map(|(_, allocation)| allocation.address())
+            .collect();
+        let marked = self.mark_all(roots);
 
+        let allocation_count = self.allocation_count();
         self.allocations
-            .retain(|allocation| marked.contains(&(Rc::as_ptr(allocation) as *const () as usize)));
+            .retain(|allocation| marked.contains(&allocation.address()));
+        report.collected = allocation_count - self.allocation_count();
+
+        if report.collected > 0 {
+            self.indices = self
+                .allocations
+                .iter()
+                .enumerate()
+                .map(|(i, allocation)| (allocation.address(), i))
+                .collect();
+        }
+
+        report
     }
 
     fn find_index_by_address(&self, address: usize) -> Option<usize> {
-        self.allocations
-            .iter()
-            .position(|allocation| Rc::as_ptr(allocation) as *const () as usize == address)
+        self.indices.get(&address).copied()
     }
 
-    fn mark_all(&self, root_address: usize, marked: &mut HashSet<usize>) {
-        if !marked.insert(root_address) {
-            return;
+    /// Addresses reachable from the roots. Iterative, as `Gc` chains can be long.
+    fn mark_all(&self, roots: Vec<usize>) -> HashSet<usize> {
+        let mut marked = HashSet::new();
+        let mut work_list = roots;
+        while let Some(address) = work_list.pop() {
+            if !marked.insert(address) {
+                continue;
+            }
+            if let Some(index) = self.find_index_by_address(address) {
+                self.allocations[index].object.visit_gcs(&mut |address| {
+                    if !marked.contains(&address) {
+                        work_list.push(address);
+                    }
+                });
+            }
         }
+        marked
+    }
+}
 
-        if let Some(index) = self.find_index_by_address(root_address) {
-            self.allocations[index]
-                .collect_gcs()
-                .iter()
-                .for_each(|&address| self.mark_all(address, marked));
-        }
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -1,6 +1,10 @@
-use gc::{Arena, Gc, Scan};
+use gc::{gc_closure, Arena, Gc, GcClosure, GcOrigin, Scan, SweepReport};
 
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -22,6 +26,46 @@ struct Vertex {
     neigh: Vec<Gc<RefCell<Vertex>>>,
 }
 
+#[derive(Scan)]
+enum Value {
+    Int(i32),
+    Pair(Gc<RefCell<Value>>, Gc<RefCell<Value>>),
+    Labeled {
+        #[scan(skip)]
+        label: String,
+        value: Gc<RefCell<Value>>,
+    },
+    Nothing,
+}
+
+#[derive(Scan)]
+struct Edge(Gc<Value>, #[scan(skip)] u64, Option<Gc<Value>>);
+
+#[derive(Scan)]
+struct Tree<T>
+where
+    T: Clone,
+{
+    value: T,
+    children: Vec<Gc<Tree<T>>>,
+}
+
+type Function = GcClosure<Box<dyn Fn(i32) -> i32>>;
+
+#[derive(Scan)]
+struct Callback {
+    #[scan(skip)]
+    name: &'static str,
+    function: RefCell<Option<Function>>,
+}
+
+#[derive(Default, Scan)]
+struct Registry {
+    by_name: HashMap<String, Gc<RefCell<Node>>>,
+    #[scan(skip)]
+    lookups: u64,
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[test]
@@ -94,6 +138,26 @@ fn test_cyclic_list() {
     assert_eq!(arena.allocation_count(), 0);
 }
 
+#[test]
+fn test_long_list() {
+    const LENGTH: usize = 100_000;
+    let mut arena = Arena::new();
+
+    let mut head = arena.alloc(RefCell::new(Node::default()));
+    for _ in 1..LENGTH {
+        head = arena.alloc(RefCell::new(Node {
+            next: Some(head.clone()),
+        }));
+    }
+
+    assert_eq!(arena.sweep().collected, 0);
+    assert_eq!(arena.allocation_count(), LENGTH);
+
+    drop(head);
+    assert_eq!(arena.sweep().collected, LENGTH);
+    assert_eq!(arena.allocation_count(), 0);
+}
+
 #[test]
 fn test_cliques() {
     let mut arena = Arena::new();
@@ -130,3 +194,546 @@ fn test_cliques() {
     arena.sweep();
     assert_eq!(arena.allocation_count(), 0);
 }
+
+#[test]
+fn test_sweep_report() {
+    let mut arena = Arena::new();
+    let head = arena.alloc(RefCell::new(Node::default()));
+    let tail = arena.alloc(RefCell::new(Node::default()));
+    head.borrow().borrow_mut().next = Some(tail.clone());
+    arena.alloc(Void);
+    drop(tail);
+
+    let report = arena.sweep();
+    assert_eq!(
+        report,
+        SweepReport {
+            collected: 1,
+            foreign_references: vec![],
+        }
+    );
+    assert_eq!(arena.allocation_count(), 2);
+    assert_eq!(head.arena_id(), arena.id());
+    assert_ne!(Arena::new().id(), arena.id());
+
+    drop(head);
+    assert_eq!(arena.sweep().collected, 2);
+}
+
+fn make_cross_arena_cycle(first: &mut Arena, second: &mut Arena) -> Gc<RefCell<Node>> {
+    let node = first.alloc(RefCell::new(Node::default()));
+    let foreign = second.alloc(RefCell::new(Node {
+        next: Some(node.clone()),
+    }));
+    node.borrow().borrow_mut().next = Some(foreign);
+    node
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "holds a `Gc` to an object of arena")]
+fn test_cross_arena_panics() {
+    let mut first = Arena::new();
+    let mut second = Arena::new();
+    let _node = make_cross_arena_cycle(&mut first, &mut second);
+    first.sweep();
+}
+
+#[test]
+#[cfg(not(debug_assertions))]
+fn test_cross_arena_reported() {
+    let mut first = Arena::new();
+    let mut second = Arena::new();
+    let node = make_cross_arena_cycle(&mut first, &mut second);
+
+    let report = first.sweep();
+    assert_eq!(report.collected, 0);
+    assert_eq!(report.foreign_references.len(), 1);
+    let reference = &report.foreign_references[0];
+    assert_eq!(reference.holder_arena_id, first.id());
+    assert_eq!(reference.target_arena_id, second.id());
+    assert!(reference.holder_type.contains("Node"));
+    assert!(reference
+        .to_string()
+        .contains(&format!("arena {}", second.id())));
+
+    let report = second.sweep();
+    assert_eq!(report.foreign_references.len(), 1);
+    drop(node);
+}
+
+#[test]
+#[should_panic(expected = "points to an object freed by arena")]
+fn test_dangling_borrow() {
+    let mut first = Arena::new();
+    let node = first.alloc(Void);
+    drop(first);
+    node.borrow();
+}
+
+#[derive(Scan)]
+struct Holder {
+    node: Gc<RefCell<Node>>,
+}
+
+#[test]
+fn test_shared_rc() {
+    let mut arena = Arena::new();
+    let leaf = arena.alloc(RefCell::new(Node::default()));
+    let holder = Rc::new(Holder { node: leaf.clone() });
+    let first = arena.alloc(holder.clone());
+    let second = arena.alloc(holder);
+
+    // The leaf is referenced once from the arena, however many objects share the holder.
+    assert_eq!(arena.sweep().collected, 0);
+    assert!(leaf.is_alive());
+
+    drop((first, second));
+    assert_eq!(arena.sweep().collected, 2);
+    assert!(leaf.is_alive());
+    drop(leaf);
+    assert_eq!(arena.sweep().collected, 1);
+}
+
+#[test]
+fn test_try_borrow() {
+    let mut arena = Arena::new();
+    let rooted = arena.alloc(Int { x: 7 });
+    let target = arena.alloc(RefCell::new(Node::default()));
+
+    // An `Rc` shared outside of the arena hides the only handle to the target.
+    let shared = Rc::new(RefCell::new(Node {
+        next: Some(target.clone()),
+    }));
+    drop(arena.alloc(shared.clone()));
+    drop(target);
+
+    assert_eq!(arena.sweep().collected, 2);
+    let dangling = shared.borrow().next.clone().unwrap();
+    assert!(!dangling.is_alive());
+    assert!(dangling.try_borrow().is_none());
+
+    assert!(rooted.is_alive());
+    assert_eq!(rooted.try_borrow().unwrap().x, 7);
+    let x = rooted.borrow().map(|int| &int.x);
+    assert_eq!(*x, 7);
+    drop(x);
+
+    drop(arena);
+    assert!(!rooted.is_alive());
+    assert!(rooted.try_borrow().is_none());
+}
+
+#[test]
+fn test_enum() {
+    let mut arena = Arena::new();
+    let int = arena.alloc(RefCell::new(Value::Int(1)));
+    let nothing = arena.alloc(RefCell::new(Value::Nothing));
+    let pair = arena.alloc(RefCell::new(Value::Pair(int.clone(), nothing.clone())));
+    drop(int);
+    drop(nothing);
+    assert_eq!(arena.sweep().collected, 0);
+
+    let labeled = arena.alloc(RefCell::new(Value::Labeled {
+        label: "pair".to_string(),
+        value: pair.clone(),
+    }));
+    drop(pair);
+    assert_eq!(arena.sweep().collected, 0);
+    if let Value::Labeled { label, .. } = &*labeled.borrow().borrow() {
+        assert_eq!(label, "pair");
+    }
+
+    // Changing the variant drops the references of the previous one.
+    *labeled.borrow().borrow_mut() = Value::Int(2);
+    assert_eq!(arena.sweep().collected, 3);
+    assert!(matches!(*labeled.borrow().borrow(), Value::Int(2)));
+
+    // A cycle through a variant.
+    let other = arena.alloc(RefCell::new(Value::Nothing));
+    *labeled.borrow().borrow_mut() = Value::Pair(labeled.clone(), other.clone());
+    drop(other);
+    assert_eq!(arena.sweep().collected, 0);
+    drop(labeled);
+    assert_eq!(arena.sweep().collected, 2);
+    assert_eq!(arena.allocation_count(), 0);
+}
+
+#[test]
+fn test_tuple_struct() {
+    let mut arena = Arena::new();
+    let first = arena.alloc(Value::Nothing);
+    let second = arena.alloc(Value::Int(3));
+    let edge = arena.alloc(Edge(first.clone(), 7, Some(second.clone())));
+    drop(first);
+    drop(second);
+    assert_eq!(arena.sweep().collected, 0);
+    assert_eq!(edge.borrow().1, 7);
+
+    let loose = arena.alloc(Edge(edge.borrow().0.clone(), 0, None));
+    drop(edge);
+    assert_eq!(arena.sweep().collected, 2);
+    assert_eq!(arena.allocation_count(), 2);
+
+    drop(loose);
+    assert_eq!(arena.sweep().collected, 2);
+}
+
+#[test]
+fn test_generic_struct() {
+    let mut arena = Arena::new();
+    let value = arena.alloc(Value::Int(4));
+    let leaf = arena.alloc(Tree {
+        value: Some(value.clone()),
+        children: vec![],
+    });
+    drop(value);
+    let root = arena.alloc(Tree {
+        value: None,
+        children: vec![leaf.clone(), leaf],
+    });
+    let unrelated = arena.alloc(Tree {
+        value: 5,
+        children: vec![],
+    });
+    drop(unrelated);
+    assert_eq!(arena.sweep().collected, 1);
+    assert_eq!(arena.allocation_count(), 3);
+    assert_eq!(root.borrow().children.len(), 2);
+
+    drop(root);
+    assert_eq!(arena.sweep().collected, 3);
+}
+
+fn addresses(gcs: &[&Gc<RefCell<Node>>]) -> Vec<usize> {
+    gcs.iter().flat_map(|gc| (*gc).collect_gcs()).collect()
+}
+
+#[test]
+fn test_map_values() {
+    let mut arena = Arena::new();
+    let nodes = (0..5)
+        .map(|_| arena.alloc(RefCell::new(Node::default())))
+        .collect::<Vec<_>>();
+
+    let mut registry = Registry::default();
+    for (i, node) in nodes.iter().enumerate().skip(2) {
+        registry.by_name.insert(format!("node {i}"), node.clone());
+    }
+    registry.lookups += 1;
+    let mut collected = registry.collect_gcs();
+    collected.sort();
+    let mut expected = addresses(&[&nodes[2], &nodes[3], &nodes[4]]);
+    expected.sort();
+    assert_eq!(collected, expected);
+
+    let registry = arena.alloc(registry);
+    drop(nodes);
+    assert_eq!(arena.sweep().collected, 2);
+    assert_eq!(arena.allocation_count(), 4);
+    assert_eq!(registry.borrow().lookups, 1);
+    drop(registry);
+    assert_eq!(arena.sweep().collected, 4);
+}
+
+#[test]
+fn test_std_types() {
+    let mut arena = Arena::new();
+    let [a, b, c] = [(); 3].map(|_| arena.alloc(RefCell::new(Node::default())));
+
+    let array = [a.clone(), b.clone(), c.clone()];
+    assert_eq!(array.collect_gcs(), addresses(&[&a, &b, &c]));
+    assert_eq!(array[1..].collect_gcs(), addresses(&[&b, &c]));
+
+    let tuple = (
+        a.clone(),
+        5u64,
+        "label",
+        Some(b.clone()),
+        1.5f64,
+        'x',
+        c.clone(),
+    );
+    assert_eq!(tuple.collect_gcs(), addresses(&[&a, &b, &c]));
+    assert_eq!((c.clone(),).collect_gcs(), addresses(&[&c]));
+
+    let nested = (
+        Box::new(a.clone()),
+        Rc::new(vec![b.clone()]),
+        [Some(c.clone()), None],
+    );
+    assert_eq!(nested.collect_gcs(), addresses(&[&a, &b, &c]));
+    assert_eq!(
+        VecDeque::from([c.clone(), a.clone()]).collect_gcs(),
+        addresses(&[&c, &a])
+    );
+    assert_eq!(
+        BTreeMap::from([(2, b.clone()), (1, a.clone())]).collect_gcs(),
+        addresses(&[&a, &b])
+    );
+    assert_eq!(HashSet::from([String::from("a")]).collect_gcs(), []);
+    assert_eq!(
+        (String::new(), 0u8, true, (), std::cell::Cell::new(7i64)).collect_gcs(),
+        []
+    );
+
+    let origins = tuple.collect_gc_origins();
+    assert_eq!(origins.len(), 3);
+    assert!(origins
+        .iter()
+        .all(|origin| origin.arena_id == Some(arena.id())));
+}
+
+fn boxed(closure: GcClosure<impl Fn(i32) -> i32 + 'static>) -> Function {
+    closure.map(|function| Box::new(function) as Box<_>)
+}
+
+#[test]
+fn test_closure_captures() {
+    let mut arena = Arena::new();
+    let int = arena.alloc(Int { x: 5 });
+    let other = arena.alloc(Int { x: 7 });
+
+    let closure = gc_closure!(int, other; |y: i32| int.borrow().x * y + other.borrow().x);
+    assert_eq!(closure.captures(), [int.erase(), other.erase()]);
+    assert_eq!(
+        closure.collect_gcs(),
+        [int.collect_gcs(), other.collect_gcs()].concat()
+    );
+    let callback = arena.alloc(Callback {
+        name: "linear",
+        function: RefCell::new(Some(boxed(closure))),
+    });
+
+    // The captures are reachable only from the closure now.
+    drop((int, other));
+    assert_eq!(arena.sweep().collected, 0);
+    let function = callback.borrow().function.borrow_mut().take().unwrap();
+    assert_eq!(function(2), 17);
+    assert_eq!(callback.borrow().name, "linear");
+
+    let no_captures = gc_closure!(; || 3);
+    assert!(no_captures.captures().is_empty());
+    assert_eq!(no_captures(), 3);
+
+    drop(function);
+    assert_eq!(arena.sweep().collected, 2);
+    drop(callback);
+    assert_eq!(arena.sweep().collected, 1);
+}
+
+/// A callback whose closure captures the callback itself.
+fn make_self_referencing_callback(arena: &mut Arena, list_capture: bool) -> Gc<Callback> {
+    let callback = arena.alloc(Callback {
+        name: "recursive",
+        function: RefCell::new(None),
+    });
+    let function = if list_capture {
+        boxed(gc_closure!(callback; |y: i32| y + callback.borrow().name.len() as i32))
+    } else {
+        let captured = callback.clone();
+        boxed(GcClosure::new(
+            move |y: i32| y + captured.borrow().name.len() as i32,
+            vec![],
+        ))
+    };
+    *callback.borrow().function.borrow_mut() = Some(function);
+    callback
+}
+
+#[test]
+fn test_closure_cycle() {
+    let mut arena = Arena::new();
+    drop(make_self_referencing_callback(&mut arena, true));
+    assert_eq!(arena.sweep().collected, 1);
+
+    // A capture missing from the list looks like an external handle, so the cycle leaks.
+    let callback = make_self_referencing_callback(&mut arena, false);
+    let function = callback.borrow().function.borrow_mut().take();
+    assert_eq!(function.as_ref().unwrap()(1), 10);
+    *callback.borrow().function.borrow_mut() = function;
+    drop(callback);
+    assert_eq!(arena.sweep().collected, 0);
+    assert_eq!(arena.allocation_count(), 1);
+}
+
+#[test]
+fn test_closure_wrong_captures() {
+    let mut arena = Arena::new();
+    let int = arena.alloc(Int { x: 35 });
+
+    // The closure doesn't hold the handle it lists, so the real handle outside of the
+    // arena goes unnoticed and the object is collected under it.
+    let holder = arena.alloc(GcClosure::new(|| 0, vec![int.erase()]));
+    assert_eq!(arena.sweep().collected, 0);
+    drop(holder);
+    assert_eq!(arena.sweep().collected, 2);
+    assert!(!int.is_alive());
+}
+
+#[test]
+fn test_validate_captures() {
+    let mut arena = Arena::new();
+    let int = arena.alloc(Int { x: 2 });
+    let argument = arena.alloc(Int { x: 3 });
+
+    let closure = gc_closure!(int; |other: &Gc<Int>| int.borrow().x * other.borrow().x);
+    let product = closure.validate_captures(&[argument.erase()], |function| function(&argument));
+    assert_eq!(product, 6);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "borrowed 1 object(s) missing from its captures")]
+fn test_validate_captures_missed() {
+    let mut arena = Arena::new();
+    let int = arena.alloc(Int { x: 2 });
+    let captured = int.clone();
+    let closure = GcClosure::new(move || captured.borrow().x, vec![]);
+    closure.validate_captures(&[], |function| function());
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn visited(value: &impl Scan) -> Vec<usize> {
+    let mut addresses = vec![];
+    value.visit_gcs(&mut |address| addresses.push(address));
+    addresses
+}
+
+fn visited_origins(value: &impl Scan) -> Vec<GcOrigin> {
+    let mut origins = vec![];
+    value.visit_gc_origins(&mut |origin| origins.push(origin));
+    origins
+}
+
+fn assert_visitors_match(value: &impl Scan) {
+    assert_eq!(visited(value), value.collect_gcs());
+    assert_eq!(visited_origins(value), value.collect_gc_origins());
+}
+
+#[test]
+fn test_visitors_match_collect() {
+    let mut arena = Arena::new();
+    let [a, b, c] = [(); 3].map(|_| arena.alloc(RefCell::new(Node::default())));
+    a.borrow().borrow_mut().next = Some(b.clone());
+
+    assert_visitors_match(&Void);
+    assert_visitors_match(&Int { x: 1 });
+    assert_visitors_match(&*a.borrow());
+    assert_eq!(visited(&*a.borrow()), addresses(&[&b]));
+    assert_visitors_match(&Vertex {
+        neigh: vec![arena.alloc(RefCell::new(Vertex::default())); 3],
+    });
+
+    let value = arena.alloc(RefCell::new(Value::Int(1)));
+    for value in [
+        Value::Pair(value.clone(), value.clone()),
+        Value::Labeled {
+            label: String::from("label"),
+            value: value.clone(),
+        },
+        Value::Nothing,
+    ] {
+        assert_visitors_match(&value);
+    }
+    let edge_value = arena.alloc(Value::Nothing);
+    assert_visitors_match(&Edge(edge_value.clone(), 7, Some(edge_value)));
+
+    let tuple = (
+        Box::new(a.clone()),
+        Rc::new(vec![b.clone()]),
+        [Some(c.clone()), None],
+        std::cell::Cell::new(1u8),
+        VecDeque::from([c.clone()]),
+        BTreeMap::from([(1, a.clone())]),
+        "label",
+    );
+    assert_visitors_match(&tuple);
+    assert_eq!(visited(&tuple), addresses(&[&a, &b, &c, &c, &a]));
+    assert!(visited_origins(&tuple)
+        .iter()
+        .all(|origin| origin.arena_id == Some(arena.id())));
+
+    let closure = gc_closure!(a, b; || a.is_alive() && b.is_alive());
+    assert_visitors_match(&closure);
+    assert_eq!(visited(&closure), addresses(&[&a, &b]));
+}
+
+/// Counts how it's scanned, and makes the allocating methods fail.
+struct Tallied {
+    next: Option<Gc<Tallied>>,
+    visits: Rc<std::cell::Cell<usize>>,
+}
+
+impl Scan for Tallied {
+    fn collect_gcs(&self) -> Vec<usize> {
+        panic!("`collect_gcs` is called");
+    }
+
+    fn collect_gc_origins(&self) -> Vec<GcOrigin> {
+        panic!("`collect_gc_origins` is called");
+    }
+
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        self.visits.set(self.visits.get() + 1);
+        self.next.visit_gcs(visitor);
+    }
+
+    fn visit_gc_origins(&self, visitor: &mut dyn FnMut(GcOrigin)) {
+        self.visits.set(self.visits.get() + 1);
+        self.next.visit_gc_origins(visitor);
+    }
+}
+
+#[test]
+fn test_sweep_uses_visitors() {
+    let mut arena = Arena::new();
+    let visits = Rc::new(std::cell::Cell::new(0));
+    let tail = arena.alloc(Tallied {
+        next: None,
+        visits: visits.clone(),
+    });
+    let head = arena.alloc(Tallied {
+        next: Some(tail),
+        visits: visits.clone(),
+    });
+    arena.alloc(Tallied {
+        next: None,
+        visits: visits.clone(),
+    });
+
+    // Both objects of the list are counted and marked, the garbage is only counted.
+    assert_eq!(arena.sweep().collected, 1);
+    assert_eq!(visits.get(), 5);
+    drop(head);
+    assert_eq!(arena.sweep().collected, 2);
+}
+
+/// Implements only the required method.
+struct Minimal {
+    next: Option<Gc<RefCell<Minimal>>>,
+}
+
+impl Scan for Minimal {
+    fn visit_gcs(&self, visitor: &mut dyn FnMut(usize)) {
+        self.next.visit_gcs(visitor)
+    }
+}
+
+#[test]
+fn test_minimal_scan() {
+    let mut arena = Arena::new();
+    let first = arena.alloc(RefCell::new(Minimal { next: None }));
+    let second = arena.alloc(RefCell::new(Minimal {
+        next: Some(first.clone()),
+    }));
+    first.borrow().borrow_mut().next = Some(second.clone());
+
+    assert_eq!(visited(&*first.borrow()), second.collect_gcs());
+    assert_eq!(visited_origins(&*first.borrow())[0].arena_id, None);
+    drop(second);
+    assert_eq!(arena.sweep().collected, 0);
+    drop(first);
+    assert_eq!(arena.sweep().collected, 2);
+}
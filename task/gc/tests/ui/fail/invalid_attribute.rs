@@ -0,0 +1,9 @@
+use gc::Scan;
+
+#[derive(Scan)]
+struct Holder {
+    #[scan(skip_all)]
+    count: i32,
+}
+
+fn main() {}
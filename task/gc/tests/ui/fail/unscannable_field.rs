@@ -0,0 +1,11 @@
+use gc::Scan;
+
+struct NotScan;
+
+#[derive(Scan)]
+struct Holder {
+    count: i32,
+    file: NotScan,
+}
+
+fn main() {}
@@ -0,0 +1,64 @@
+use gc::{Arena, Gc, Scan};
+
+struct NotScan;
+
+#[derive(Scan)]
+struct Node {
+    next: Option<Gc<Node>>,
+}
+
+#[derive(Scan)]
+enum Never {}
+
+#[derive(Scan)]
+enum Unit {
+    First,
+    Second,
+}
+
+// The existing where-clause is kept, and `T: Scan` is added for the scanned fields only.
+#[derive(Scan)]
+enum Either<'a, T, U>
+where
+    T: Clone,
+{
+    Left(T),
+    Right {
+        #[scan(skip)]
+        value: U,
+        node: Gc<Node>,
+    },
+    Borrowed(&'a T, #[scan(skip)] U),
+}
+
+#[derive(Scan)]
+struct Pair<T>(T, #[scan(skip)] NotScan, Gc<Node>);
+
+#[derive(Scan)]
+struct Empty();
+
+fn assert_never_scan<T: Scan>() {}
+
+fn main() {
+    assert_never_scan::<Never>();
+    assert!(Unit::First.collect_gcs().is_empty());
+    assert!(Unit::Second.collect_gcs().is_empty());
+    assert!(Empty().collect_gcs().is_empty());
+
+    let mut arena = Arena::new();
+    let node = arena.alloc(Node { next: None });
+
+    let left = Either::<_, NotScan>::Left(vec![node.clone()]);
+    assert_eq!(left.collect_gcs().len(), 1);
+    let right: Either<'_, Vec<Gc<Node>>, _> = Either::Right {
+        value: NotScan,
+        node: node.clone(),
+    };
+    assert_eq!(right.collect_gcs().len(), 1);
+    let nodes = vec![node.clone()];
+    let borrowed = Either::Borrowed(&nodes, NotScan);
+    assert!(borrowed.collect_gcs().is_empty());
+
+    let pair = Pair(Some(node.clone()), NotScan, node);
+    assert_eq!(pair.collect_gcs().len(), 2);
+}
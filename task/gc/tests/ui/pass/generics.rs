@@ -0,0 +1,61 @@
+use gc::{Arena, Gc, Scan};
+
+use std::fmt::Debug;
+
+struct NotScan;
+
+#[derive(Scan)]
+struct Node {
+    next: Option<Gc<Node>>,
+}
+
+// `T` is used only behind a reference, so no `T: Scan` bound is added.
+#[derive(Scan)]
+struct View<'a, T> {
+    items: &'a [T],
+    count: i32,
+}
+
+// The existing where-clause is kept, and `T: Scan` is added to it.
+#[derive(Scan)]
+struct Wrapper<T>
+where
+    T: Clone,
+{
+    inner: Vec<T>,
+}
+
+#[derive(Scan)]
+struct Tagged<T: Debug, U> {
+    node: Gc<Node>,
+    #[scan(skip)]
+    tag: T,
+    #[scan(skip)]
+    extra: U,
+}
+
+fn main() {
+    let mut arena = Arena::new();
+    let node = arena.alloc(Node { next: None });
+
+    let items = [NotScan, NotScan];
+    let view = View {
+        items: &items,
+        count: 2,
+    };
+    assert!(view.collect_gcs().is_empty());
+
+    let wrapper = Wrapper {
+        inner: vec![node.clone(), node.clone()],
+    };
+    assert_eq!(wrapper.collect_gcs().len(), 2);
+
+    let tagged = Tagged {
+        node,
+        tag: "tag",
+        extra: NotScan,
+    };
+    assert_eq!(tagged.collect_gcs().len(), 1);
+    assert_eq!(tagged.tag, "tag");
+    let NotScan = tagged.extra;
+}
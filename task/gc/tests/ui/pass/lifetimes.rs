@@ -0,0 +1,37 @@
+use gc::{Arena, Gc, Scan};
+
+#[derive(Scan)]
+struct Node {
+    next: Option<Gc<Node>>,
+}
+
+#[derive(Scan)]
+struct Span<'a> {
+    text: &'a str,
+    node: Gc<Node>,
+}
+
+#[derive(Scan)]
+struct Nested<'a, 'b: 'a> {
+    span: &'a Span<'b>,
+    nodes: Vec<Gc<Node>>,
+}
+
+fn main() {
+    let mut arena = Arena::new();
+    let node = arena.alloc(Node { next: None });
+
+    let text = String::from("text");
+    let span = Span {
+        text: &text,
+        node: node.clone(),
+    };
+    assert_eq!(span.collect_gcs().len(), 1);
+
+    // The borrowed span is not scanned, only the owned handles are.
+    let nested = Nested {
+        span: &span,
+        nodes: vec![node.clone(), node],
+    };
+    assert_eq!(nested.collect_gcs().len(), 2);
+}
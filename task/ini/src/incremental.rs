@@ -0,0 +1,265 @@
+use crate::{
+    parse_line, DuplicateKeyPolicy, IniFile, Line, OrderedIniFile, ParseError, ParseOptions,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    io::{self, Read},
+    mem,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A meaningful line of the file. Every pair is reported, even a repeated one: only
+/// `DuplicateKeyPolicy::Error` is checked here, the other policies are up to the consumer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IniEvent {
+    Section {
+        name: String,
+        line: usize,
+    },
+    Pair {
+        key: String,
+        value: String,
+        line: usize,
+    },
+    /// The whole line, trimmed, including the comment char.
+    Comment {
+        text: String,
+        line: usize,
+    },
+}
+
+/// What has to be remembered between lines to check that they fit into the file.
+#[derive(Debug, Default)]
+struct EventState {
+    section: Option<String>,
+    /// Keys of every section, only tracked for `DuplicateKeyPolicy::Error`.
+    keys: HashMap<String, HashSet<String>>,
+}
+
+impl EventState {
+    fn next_event(
+        &mut self,
+        line: &str,
+        line_number: usize,
+        options: &ParseOptions,
+    ) -> Result<Option<IniEvent>, ParseError> {
+        let event = match parse_line(line, line_number, options)? {
+            Line::Empty => return Ok(None),
+            Line::Comment(text) => IniEvent::Comment {
+                text: text.to_string(),
+                line: line_number,
+            },
+            Line::Section(name) => {
+                self.section = Some(name.to_string());
+                IniEvent::Section {
+                    name: name.to_string(),
+                    line: line_number,
+                }
+            }
+            Line::Pair(pair) => {
+                let Some(section) = &self.section else {
+                    return Err(ParseError::KeyValueBeforeSection { line: line_number });
+                };
+                if options.duplicate_keys == DuplicateKeyPolicy::Error
+                    && !self
+                        .keys
+                        .entry(section.clone())
+                        .or_default()
+                        .insert(pair.key.to_string())
+                {
+                    return Err(ParseError::DuplicateKey { line: line_number });
+                }
+                IniEvent::Pair {
+                    key: pair.key.to_string(),
+                    value: pair.value.into_owned(),
+                    line: line_number,
+                }
+            }
+        };
+        Ok(Some(event))
+    }
+}
+
+/// Parses the whole content at once, see `IncrementalParser` for the streaming version.
+pub fn parse_events(content: &str, options: &ParseOptions) -> Result<Vec<IniEvent>, ParseError> {
+    let mut state = EventState::default();
+    let mut events = vec![];
+    for (index, line) in content.lines().enumerate() {
+        events.extend(state.next_event(line, index + 1, options)?);
+    }
+    Ok(events)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A push-based parser for content coming in chunks, e.g. from the network.
+///
+/// A chunk may end anywhere, even in the middle of a UTF-8 sequence: the incomplete last
+/// line is kept until the rest of it comes, and is validated only once it's complete.
+/// After an error, every call returns that error again.
+#[derive(Debug)]
+pub struct IncrementalParser {
+    options: ParseOptions,
+    state: EventState,
+    /// The incomplete last line.
+    pending: Vec<u8>,
+    completed_lines: usize,
+    error: Option<ParseError>,
+}
+
+impl IncrementalParser {
+    pub fn new(options: ParseOptions) -> Self {
+        Self {
+            options,
+            state: EventState::default(),
+            pending: vec![],
+            completed_lines: 0,
+            error: None,
+        }
+    }
+
+    /// Returns the events of the lines completed by this chunk.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<IniEvent>, ParseError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut events = vec![];
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            self.pending.extend_from_slice(&rest[..end]);
+            rest = &rest[end + 1..];
+
+            let line = mem::take(&mut self.pending);
+            events.extend(self.complete_line(&line)?);
+            self.pending = line;
+            self.pending.clear();
+        }
+        self.pending.extend_from_slice(rest);
+
+        Ok(events)
+    }
+
+    /// Flushes the last line if it isn't terminated, which is the only place an incomplete
+    /// UTF-8 sequence at the end of the content can be detected.
+    pub fn finish(mut self) -> Result<Vec<IniEvent>, ParseError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.pending.is_empty() {
+            return Ok(vec![]);
+        }
+        let line = mem::take(&mut self.pending);
+        Ok(self.complete_line(&line)?.into_iter().collect())
+    }
+
+    fn complete_line(&mut self, line: &[u8]) -> Result<Option<IniEvent>, ParseError> {
+        self.completed_lines += 1;
+        let line_number = self.completed_lines;
+
+        let result = match std::str::from_utf8(line) {
+            Ok(line) => self.state.next_event(line, line_number, &self.options),
+            Err(_) => Err(ParseError::InvalidUtf8 { line: line_number }),
+        };
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+        result
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read: {err}"),
+            Self::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Parse(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// Like `parse_with_options`, but reads the content chunk by chunk instead of
+/// buffering all of it.
+pub fn parse_from_reader<R: Read>(
+    mut reader: R,
+    options: &ParseOptions,
+) -> Result<IniFile, ReadError> {
+    let mut parser = IncrementalParser::new(options.clone());
+    let mut result = OrderedIniFile::new();
+    let mut current_section = None;
+    let mut buffer = [0; 8192];
+
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let events = parser.feed(&buffer[..len])?;
+        apply_events(&mut result, &mut current_section, events, options);
+    }
+    let events = parser.finish()?;
+    apply_events(&mut result, &mut current_section, events, options);
+
+    Ok(result.into())
+}
+
+fn apply_events(
+    result: &mut OrderedIniFile,
+    current_section: &mut Option<String>,
+    events: Vec<IniEvent>,
+    options: &ParseOptions,
+) {
+    for event in events {
+        match event {
+            IniEvent::Section { name, .. } => {
+                result.section_or_insert(&name);
+                *current_section = Some(name);
+            }
+            IniEvent::Pair { key, value, .. } => {
+                let section = current_section.as_deref().expect("pair before section");
+                let section = result.section_or_insert(section);
+                if options.duplicate_keys == DuplicateKeyPolicy::KeepFirst
+                    && section.contains_key(&key)
+                {
+                    continue;
+                }
+                section.insert(&key, &value);
+            }
+            IniEvent::Comment { .. } => {}
+        }
+    }
+}
@@ -1,70 +1,311 @@
 #![forbid(unsafe_code)]
 
-use std::collections::HashMap;
+pub mod incremental;
+pub mod ordered;
+pub mod schema;
+pub mod writer;
+
+pub use incremental::{parse_events, parse_from_reader, IncrementalParser, IniEvent, ReadError};
+pub use ordered::{DuplicateKeyPolicy, OrderedIniFile, Section};
+pub use writer::{to_string, to_string_ordered, write, write_ordered};
+
+use std::{borrow::Cow, collections::HashMap, error::Error, fmt};
 
 ////////////////////////////////////////////////////////////////////////////////
 
 pub type IniFile = HashMap<String, HashMap<String, String>>;
 
-pub fn parse(content: &str) -> IniFile {
-    let mut result = HashMap::new();
-    let mut current_section_title: Option<&str> = None;
+/// Line numbers start from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    KeyValueBeforeSection { line: usize },
+    UnterminatedSectionHeader { line: usize },
+    BracketInSectionName { line: usize },
+    EmptySectionName { line: usize },
+    TooManyEquals { line: usize },
+    DuplicateKey { line: usize },
+    UnterminatedQuote { line: usize },
+    InvalidEscape { line: usize },
+    TextAfterQuotedValue { line: usize },
+    InvalidUtf8 { line: usize },
+}
+
+impl ParseError {
+    pub fn line(&self) -> usize {
+        match *self {
+            Self::KeyValueBeforeSection { line }
+            | Self::UnterminatedSectionHeader { line }
+            | Self::BracketInSectionName { line }
+            | Self::EmptySectionName { line }
+            | Self::TooManyEquals { line }
+            | Self::DuplicateKey { line }
+            | Self::UnterminatedQuote { line }
+            | Self::InvalidEscape { line }
+            | Self::TextAfterQuotedValue { line }
+            | Self::InvalidUtf8 { line } => line,
+        }
+    }
+}
 
-    for mut line in content.lines() {
-        line = line.trim();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::KeyValueBeforeSection { .. } => "key-value pair before any section",
+            Self::UnterminatedSectionHeader { .. } => "section header is missing ']'",
+            Self::BracketInSectionName { .. } => "section name contains a bracket",
+            Self::EmptySectionName { .. } => "section name is empty",
+            Self::TooManyEquals { .. } => "key-value pair contains more than one '='",
+            Self::DuplicateKey { .. } => "key is already defined in this section",
+            Self::UnterminatedQuote { .. } => "quoted value is missing the closing quote",
+            Self::InvalidEscape { .. } => "unknown escape sequence in quoted value",
+            Self::TextAfterQuotedValue { .. } => "unexpected text after quoted value",
+            Self::InvalidUtf8 { .. } => "line is not valid UTF-8",
+        };
+        write!(f, "line {}: {description}", self.line())
+    }
+}
+
+impl Error for ParseError {}
+
+////////////////////////////////////////////////////////////////////////////////
 
-        if line.starts_with('[') {
-            current_section_title = Some(parse_section_title(line));
+/// Options of the extended syntax, the default ones enable all of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub duplicate_keys: DuplicateKeyPolicy,
+    /// A line starting with one of these, after whitespace, is a comment.
+    pub comment_chars: Vec<char>,
+    /// Allows comments after a section header or a value. To be recognized there, a
+    /// comment char must follow whitespace, so that `url = a.org/#top` keeps its `#`.
+    pub allow_inline_comments: bool,
+    /// Allows values wrapped in `"` or `'`. Quoted values keep their whitespace, may
+    /// contain `=` and comment chars, and support escapes: `\\`, `\"`, `\'`, `\n`,
+    /// `\t`, `\r`, `\0`.
+    pub allow_quoted_values: bool,
+}
 
-            if !result.contains_key(current_section_title.unwrap()) {
-                result.insert(current_section_title.unwrap().to_string(), HashMap::new());
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            duplicate_keys: DuplicateKeyPolicy::default(),
+            comment_chars: vec![';', '#'],
+            allow_inline_comments: true,
+            allow_quoted_values: true,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Only the basic syntax: no comments and no quoting.
+    pub fn strict() -> Self {
+        Self {
+            duplicate_keys: DuplicateKeyPolicy::default(),
+            comment_chars: vec![],
+            allow_inline_comments: false,
+            allow_quoted_values: false,
+        }
+    }
+
+    fn is_comment(&self, line: &str) -> bool {
+        line.starts_with(self.comment_chars.as_slice())
+    }
+
+    /// Cuts off an inline comment, if there is one.
+    fn strip_inline_comment<'a>(&self, text: &'a str) -> &'a str {
+        if !self.allow_inline_comments {
+            return text;
+        }
+        let mut prev_is_whitespace = true;
+        for (i, c) in text.char_indices() {
+            if prev_is_whitespace && self.comment_chars.contains(&c) {
+                return text[..i].trim_end();
             }
-        } else if !line.is_empty() {
-            let pair = parse_value_pair(line);
+            prev_is_whitespace = c.is_whitespace();
+        }
+        text
+    }
+}
 
-            assert!(current_section_title.is_some());
-            let map = result.get_mut(current_section_title.unwrap());
+////////////////////////////////////////////////////////////////////////////////
 
-            assert!(map.is_some());
-            let map: &mut HashMap<String, String> = map.unwrap();
+/// Repeated keys keep the last value, see `parse_ordered` for other options.
+pub fn parse(content: &str) -> Result<IniFile, ParseError> {
+    parse_with_options(content, &ParseOptions::default())
+}
+
+pub fn parse_with_options(content: &str, options: &ParseOptions) -> Result<IniFile, ParseError> {
+    parse_ordered_with_options(content, options).map(IniFile::from)
+}
 
-            map.insert(pair.key.to_string(), pair.value.to_string());
+/// Like `parse`, but keeps sections and keys in the order of their first occurrence.
+pub fn parse_ordered(
+    content: &str,
+    duplicate_keys: DuplicateKeyPolicy,
+) -> Result<OrderedIniFile, ParseError> {
+    let options = ParseOptions {
+        duplicate_keys,
+        ..Default::default()
+    };
+    parse_ordered_with_options(content, &options)
+}
+
+pub fn parse_ordered_with_options(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<OrderedIniFile, ParseError> {
+    let mut result = OrderedIniFile::new();
+    let mut current_section_title: Option<&str> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+
+        match parse_line(line, line_number, options)? {
+            Line::Empty | Line::Comment(_) => {}
+            Line::Section(title) => {
+                current_section_title = Some(title);
+                result.section_or_insert(title);
+            }
+            Line::Pair(pair) => {
+                let Some(title) = current_section_title else {
+                    return Err(ParseError::KeyValueBeforeSection { line: line_number });
+                };
+                let section = result.section_or_insert(title);
+
+                if section.contains_key(pair.key) {
+                    match options.duplicate_keys {
+                        DuplicateKeyPolicy::KeepFirst => continue,
+                        DuplicateKeyPolicy::KeepLast => {}
+                        DuplicateKeyPolicy::Error => {
+                            return Err(ParseError::DuplicateKey { line: line_number })
+                        }
+                    }
+                }
+                section.insert(pair.key, &pair.value);
+            }
         }
     }
 
-    result
+    Ok(result)
 }
 
+/// A single line of the file, see `parse_line`.
 #[derive(Debug)]
-struct ValuePair<'a> {
-    key: &'a str,
-    value: &'a str,
+pub(crate) enum Line<'a> {
+    Empty,
+    /// The whole line, trimmed, including the comment char.
+    Comment(&'a str),
+    Section(&'a str),
+    Pair(ValuePair<'a>),
 }
 
-fn parse_value_pair(line: &str) -> ValuePair {
-    let mut iter = line.split('=');
+/// Parses a line on its own, without checking that it fits into the file, e.g. that
+/// a pair comes after a section.
+pub(crate) fn parse_line<'a>(
+    line: &'a str,
+    line_number: usize,
+    options: &ParseOptions,
+) -> Result<Line<'a>, ParseError> {
+    let line = line.trim();
+
+    if options.is_comment(line) {
+        Ok(Line::Comment(line))
+    } else if line.starts_with('[') {
+        let title = parse_section_title(options.strip_inline_comment(line), line_number)?;
+        Ok(Line::Section(title))
+    } else if line.is_empty() {
+        Ok(Line::Empty)
+    } else {
+        parse_value_pair(line, line_number, options).map(Line::Pair)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ValuePair<'a> {
+    pub key: &'a str,
+    pub value: Cow<'a, str>,
+}
 
-    let key = iter.next().unwrap().trim();
+fn parse_value_pair<'a>(
+    line: &'a str,
+    line_number: usize,
+    options: &ParseOptions,
+) -> Result<ValuePair<'a>, ParseError> {
+    let (key, value) = match line.split_once('=') {
+        Some((key, value)) if options.strip_inline_comment(key).len() == key.len() => {
+            (key.trim(), value.trim())
+        }
+        // Either there is no '=' at all, or it is inside a comment.
+        _ => (options.strip_inline_comment(line), ""),
+    };
 
-    let value = match iter.next() {
-        Some(val) => val.trim(),
-        None => "",
+    let value = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) if options.allow_quoted_values => {
+            let (value, rest) = parse_quoted_value(&value[1..], quote, line_number)?;
+            if !options.strip_inline_comment(rest).trim().is_empty() {
+                return Err(ParseError::TextAfterQuotedValue { line: line_number });
+            }
+            Cow::Owned(value)
+        }
+        _ => {
+            let value = options.strip_inline_comment(value);
+            if value.contains('=') {
+                return Err(ParseError::TooManyEquals { line: line_number });
+            }
+            Cow::Borrowed(value)
+        }
     };
 
-    assert!(iter.next().is_none());
+    Ok(ValuePair { key, value })
+}
+
+/// `text` starts right after the opening quote. Returns the unescaped value and the
+/// rest of the line after the closing quote.
+fn parse_quoted_value(
+    text: &str,
+    quote: char,
+    line_number: usize,
+) -> Result<(String, &str), ParseError> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
 
-    ValuePair { key, value }
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let unescaped = match chars.next() {
+                    Some((_, '\\')) => '\\',
+                    Some((_, '"')) => '"',
+                    Some((_, '\'')) => '\'',
+                    Some((_, 'n')) => '\n',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'r')) => '\r',
+                    Some((_, '0')) => '\0',
+                    Some(_) => return Err(ParseError::InvalidEscape { line: line_number }),
+                    None => break,
+                };
+                value.push(unescaped);
+            }
+            c if c == quote => return Ok((value, &text[i + c.len_utf8()..])),
+            c => value.push(c),
+        }
+    }
+
+    Err(ParseError::UnterminatedQuote { line: line_number })
 }
 
-fn parse_section_title(line: &str) -> &str {
-    assert!(line.ends_with(']'));
+fn parse_section_title(line: &str, line_number: usize) -> Result<&str, ParseError> {
+    if !line.ends_with(']') {
+        return Err(ParseError::UnterminatedSectionHeader { line: line_number });
+    }
 
     let title = &line[1..line.len() - 1];
 
-    assert_eq!(title.find('['), None);
-    assert_eq!(title.find(']'), None);
+    if title.contains(['[', ']']) {
+        return Err(ParseError::BracketInSectionName { line: line_number });
+    }
 
-    assert!(!title.is_empty());
+    if title.is_empty() {
+        return Err(ParseError::EmptySectionName { line: line_number });
+    }
 
-    title
+    Ok(title)
 }
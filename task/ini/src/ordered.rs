@@ -0,0 +1,140 @@
+use crate::IniFile;
+
+use std::collections::HashMap;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What to do when a key appears in a section more than once, including
+/// across repeated occurrences of the section.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    KeepFirst,
+    /// The key keeps the position of its first occurrence.
+    #[default]
+    KeepLast,
+    /// Fail with `ParseError::DuplicateKey`.
+    Error,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    name: String,
+    pairs: Vec<(String, String)>,
+    index: HashMap<String, usize>,
+}
+
+impl Section {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            pairs: vec![],
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.index.get(key).map(|&i| self.pairs[i].1.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Returns the previous value. A new key goes to the end of the section.
+    pub fn insert(&mut self, key: &str, value: &str) -> Option<String> {
+        match self.index.get(key) {
+            Some(&i) => Some(std::mem::replace(&mut self.pairs[i].1, value.to_string())),
+            None => {
+                self.index.insert(key.to_string(), self.pairs.len());
+                self.pairs.push((key.to_string(), value.to_string()));
+                None
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Pairs in file order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OrderedIniFile {
+    sections: Vec<Section>,
+    index: HashMap<String, usize>,
+}
+
+impl OrderedIniFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.index.get(name).map(|&i| &self.sections[i])
+    }
+
+    pub fn section_mut(&mut self, name: &str) -> Option<&mut Section> {
+        self.index.get(name).map(|&i| &mut self.sections[i])
+    }
+
+    /// Returns the existing section or appends a new empty one.
+    pub fn section_or_insert(&mut self, name: &str) -> &mut Section {
+        let i = match self.index.get(name) {
+            Some(&i) => i,
+            None => {
+                self.index.insert(name.to_string(), self.sections.len());
+                self.sections.push(Section::new(name));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[i]
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)?.get(key)
+    }
+
+    pub fn insert(&mut self, section: &str, key: &str, value: &str) -> Option<String> {
+        self.section_or_insert(section).insert(key, value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// Sections in the order of their first occurrence in the file.
+    pub fn sections(&self) -> impl Iterator<Item = &Section> {
+        self.sections.iter()
+    }
+}
+
+impl From<OrderedIniFile> for IniFile {
+    fn from(ini: OrderedIniFile) -> Self {
+        ini.sections
+            .into_iter()
+            .map(|section| (section.name, section.pairs.into_iter().collect()))
+            .collect()
+    }
+}
@@ -0,0 +1,345 @@
+use crate::IniFile;
+
+use std::{collections::HashMap, error::Error, fmt};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Str,
+    /// Both bounds are inclusive.
+    Int {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    /// One of `true`, `false`, `yes`, `no`, `on`, `off`, `1`, `0`, case-insensitive.
+    Bool,
+    Float,
+    Enum(&'static [&'static str]),
+}
+
+impl Kind {
+    fn parse(&self, value: &str) -> Option<Value> {
+        match *self {
+            Self::Str => Some(Value::Str(value.to_string())),
+            Self::Int { min, max } => value
+                .parse::<i64>()
+                .ok()
+                .filter(|&n| min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max))
+                .map(Value::Int),
+            Self::Bool => match value.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Some(Value::Bool(true)),
+                "false" | "no" | "off" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            Self::Float => value.parse::<f64>().ok().map(Value::Float),
+            Self::Enum(variants) => variants
+                .contains(&value)
+                .then(|| Value::Str(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Str => write!(f, "a string"),
+            Self::Int {
+                min: Some(min),
+                max: Some(max),
+            } => write!(f, "an integer from {min} to {max}"),
+            Self::Int {
+                min: Some(min),
+                max: None,
+            } => write!(f, "an integer not less than {min}"),
+            Self::Int {
+                min: None,
+                max: Some(max),
+            } => write!(f, "an integer not greater than {max}"),
+            Self::Int {
+                min: None,
+                max: None,
+            } => write!(f, "an integer"),
+            Self::Bool => write!(f, "a boolean"),
+            Self::Float => write!(f, "a float"),
+            Self::Enum(variants) => write!(f, "one of: {}", variants.join(", ")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Values of both `Kind::Str` and `Kind::Enum`.
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    UnknownSection {
+        section: String,
+    },
+    UnknownKey {
+        section: String,
+        key: String,
+    },
+    MissingKey {
+        section: String,
+        key: String,
+    },
+    InvalidValue {
+        section: String,
+        key: String,
+        value: String,
+        expected: String,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSection { section } => write!(f, "unknown section [{section}]"),
+            Self::UnknownKey { section, key } => write!(f, "[{section}] {key}: unknown key"),
+            Self::MissingKey { section, key } => {
+                write!(f, "[{section}] {key}: missing required key")
+            }
+            Self::InvalidValue {
+                section,
+                key,
+                value,
+                expected,
+            } => write!(
+                f,
+                "[{section}] {key}: invalid value {value:?}, expected {expected}"
+            ),
+        }
+    }
+}
+
+impl Error for SchemaError {}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What to do with sections and keys that are not declared in the schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownKeys {
+    #[default]
+    Ignore,
+    /// Accept the file, but report them in `ValidatedConfig::warnings`.
+    Warn,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+struct KeySchema {
+    kind: Kind,
+    required: bool,
+    default: Option<Value>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SectionSchema {
+    keys: Vec<(String, KeySchema)>,
+}
+
+impl SectionSchema {
+    pub fn required(self, key: &str, kind: Kind) -> Self {
+        self.key(key, kind, true, None)
+    }
+
+    pub fn optional(self, key: &str, kind: Kind) -> Self {
+        self.key(key, kind, false, None)
+    }
+
+    /// Panics if `default` is not a valid value of `kind`.
+    pub fn with_default(self, key: &str, kind: Kind, default: &str) -> Self {
+        let default = kind
+            .parse(default)
+            .unwrap_or_else(|| panic!("default {default:?} of '{key}' is not {kind}"));
+        self.key(key, kind, false, Some(default))
+    }
+
+    fn key(mut self, key: &str, kind: Kind, required: bool, default: Option<Value>) -> Self {
+        self.keys.retain(|(name, _)| name != key);
+        self.keys.push((
+            key.to_string(),
+            KeySchema {
+                kind,
+                required,
+                default,
+            },
+        ));
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    sections: Vec<(String, SectionSchema)>,
+    unknown_keys: UnknownKeys,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn section(mut self, name: &str, f: impl FnOnce(SectionSchema) -> SectionSchema) -> Self {
+        match self.sections.iter_mut().find(|(title, _)| title == name) {
+            Some((_, section)) => *section = f(std::mem::take(section)),
+            None => self
+                .sections
+                .push((name.to_string(), f(SectionSchema::default()))),
+        }
+        self
+    }
+
+    pub fn unknown_keys(mut self, mode: UnknownKeys) -> Self {
+        self.unknown_keys = mode;
+        self
+    }
+
+    /// Collects all violations instead of stopping at the first one.
+    pub fn validate(&self, ini: &IniFile) -> Result<ValidatedConfig, Vec<SchemaError>> {
+        let mut errors = vec![];
+        let mut unknowns = vec![];
+        let mut values = HashMap::new();
+
+        for (title, section_schema) in &self.sections {
+            let section = ini.get(title);
+            let mut section_values = HashMap::new();
+
+            for (key, key_schema) in &section_schema.keys {
+                let value = match section.and_then(|section| section.get(key)) {
+                    Some(raw) => match key_schema.kind.parse(raw) {
+                        Some(value) => value,
+                        None => {
+                            errors.push(SchemaError::InvalidValue {
+                                section: title.clone(),
+                                key: key.clone(),
+                                value: raw.clone(),
+                                expected: key_schema.kind.to_string(),
+                            });
+                            continue;
+                        }
+                    },
+                    None if key_schema.required => {
+                        errors.push(SchemaError::MissingKey {
+                            section: title.clone(),
+                            key: key.clone(),
+                        });
+                        continue;
+                    }
+                    None => match &key_schema.default {
+                        Some(default) => default.clone(),
+                        None => continue,
+                    },
+                };
+                section_values.insert(key.clone(), value);
+            }
+
+            if let Some(section) = section {
+                let mut unknown_keys = section
+                    .keys()
+                    .filter(|key| section_schema.keys.iter().all(|(name, _)| name != *key))
+                    .collect::<Vec<_>>();
+                unknown_keys.sort();
+                unknowns.extend(unknown_keys.into_iter().map(|key| SchemaError::UnknownKey {
+                    section: title.clone(),
+                    key: key.clone(),
+                }));
+            }
+
+            values.insert(title.clone(), section_values);
+        }
+
+        let mut unknown_sections = ini
+            .keys()
+            .filter(|title| self.sections.iter().all(|(name, _)| name != *title))
+            .collect::<Vec<_>>();
+        unknown_sections.sort();
+        unknowns.extend(
+            unknown_sections
+                .into_iter()
+                .map(|title| SchemaError::UnknownSection {
+                    section: title.clone(),
+                }),
+        );
+
+        let warnings = match self.unknown_keys {
+            UnknownKeys::Ignore => vec![],
+            UnknownKeys::Warn => unknowns,
+            UnknownKeys::Error => {
+                errors.extend(unknowns);
+                vec![]
+            }
+        };
+
+        if errors.is_empty() {
+            Ok(ValidatedConfig { values, warnings })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Getters panic only if the key is not declared in the schema with a matching kind,
+/// or if it is optional, has no default and is absent: see `ValidatedConfig::get`.
+#[derive(Clone, Debug)]
+pub struct ValidatedConfig {
+    values: HashMap<String, HashMap<String, Value>>,
+    warnings: Vec<SchemaError>,
+}
+
+impl ValidatedConfig {
+    pub fn get(&self, section: &str, key: &str) -> Option<&Value> {
+        self.values.get(section)?.get(key)
+    }
+
+    pub fn warnings(&self) -> &[SchemaError] {
+        &self.warnings
+    }
+
+    pub fn get_str(&self, section: &str, key: &str) -> &str {
+        match self.expect(section, key) {
+            Value::Str(value) => value,
+            value => panic!("[{section}] {key} is not a string: {value:?}"),
+        }
+    }
+
+    pub fn get_enum(&self, section: &str, key: &str) -> &str {
+        self.get_str(section, key)
+    }
+
+    pub fn get_int(&self, section: &str, key: &str) -> i64 {
+        match self.expect(section, key) {
+            Value::Int(value) => *value,
+            value => panic!("[{section}] {key} is not an integer: {value:?}"),
+        }
+    }
+
+    pub fn get_bool(&self, section: &str, key: &str) -> bool {
+        match self.expect(section, key) {
+            Value::Bool(value) => *value,
+            value => panic!("[{section}] {key} is not a boolean: {value:?}"),
+        }
+    }
+
+    pub fn get_float(&self, section: &str, key: &str) -> f64 {
+        match self.expect(section, key) {
+            Value::Float(value) => *value,
+            value => panic!("[{section}] {key} is not a float: {value:?}"),
+        }
+    }
+
+    fn expect(&self, section: &str, key: &str) -> &Value {
+        self.get(section, key)
+            .unwrap_or_else(|| panic!("[{section}] {key} has no value"))
+    }
+}
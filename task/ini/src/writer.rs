@@ -0,0 +1,130 @@
+use crate::{IniFile, OrderedIniFile, ParseOptions};
+
+use std::io::{self, Write};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Writes sections and keys sorted, so that the output is deterministic.
+///
+/// Values are quoted when needed, so `parse(&to_string(ini)) == Ok(ini)` holds as long as
+/// every section name and key can be written, see `write_ordered`.
+pub fn write(ini: &IniFile, writer: impl Write) -> io::Result<()> {
+    let mut sections = ini.iter().collect::<Vec<_>>();
+    sections.sort_unstable_by_key(|(name, _)| *name);
+    write_sections(
+        sections.into_iter().map(|(name, pairs)| {
+            let mut pairs = pairs.iter().collect::<Vec<_>>();
+            pairs.sort_unstable();
+            (
+                name.as_str(),
+                pairs.into_iter().map(|(k, v)| (k.as_str(), v.as_str())),
+            )
+        }),
+        writer,
+    )
+}
+
+/// Keeps the order of sections and keys.
+///
+/// Fails with `io::ErrorKind::InvalidInput` if a section name or a key can't be parsed
+/// back. Neither can contain line breaks or a comment char after whitespace. Section
+/// names must be non-empty and can't contain brackets. Keys can't contain `=`, start
+/// or end with whitespace, or start with `[` or a comment char.
+pub fn write_ordered(ini: &OrderedIniFile, writer: impl Write) -> io::Result<()> {
+    write_sections(
+        ini.sections()
+            .map(|section| (section.name(), section.iter())),
+        writer,
+    )
+}
+
+/// Panics if the file can't be written, see `write_ordered`.
+pub fn to_string(ini: &IniFile) -> String {
+    let mut output = vec![];
+    write(ini, &mut output).expect("failed to write ini file");
+    String::from_utf8(output).unwrap()
+}
+
+/// Panics if the file can't be written, see `write_ordered`.
+pub fn to_string_ordered(ini: &OrderedIniFile) -> String {
+    let mut output = vec![];
+    write_ordered(ini, &mut output).expect("failed to write ini file");
+    String::from_utf8(output).unwrap()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn write_sections<'a, P>(
+    sections: impl Iterator<Item = (&'a str, P)>,
+    mut writer: impl Write,
+) -> io::Result<()>
+where
+    P: Iterator<Item = (&'a str, &'a str)>,
+{
+    let options = ParseOptions::default();
+
+    for (i, (name, pairs)) in sections.enumerate() {
+        if !is_valid_section_name(name, &options) {
+            return Err(invalid_input(format!("invalid section name {name:?}")));
+        }
+        if i > 0 {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "[{name}]")?;
+
+        for (key, value) in pairs {
+            if !is_valid_key(key, &options) {
+                return Err(invalid_input(format!("invalid key {key:?} in [{name}]")));
+            }
+            if needs_quotes(value, &options) {
+                writeln!(writer, "{key} = \"{}\"", escape(value))?;
+            } else {
+                writeln!(writer, "{key} = {value}")?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn has_no_comment(text: &str, options: &ParseOptions) -> bool {
+    options.strip_inline_comment(text).len() == text.len()
+}
+
+fn is_valid_section_name(name: &str, options: &ParseOptions) -> bool {
+    !name.is_empty()
+        && !name.contains(['[', ']', '\n', '\r'])
+        && has_no_comment(&format!("[{name}]"), options)
+}
+
+fn is_valid_key(key: &str, options: &ParseOptions) -> bool {
+    key.trim() == key
+        && !key.contains(['=', '\n', '\r'])
+        && !key.starts_with('[')
+        && !options.is_comment(key)
+        && has_no_comment(key, options)
+}
+
+fn needs_quotes(value: &str, options: &ParseOptions) -> bool {
+    value.trim() != value
+        || value.starts_with(['"', '\''])
+        || value.contains(|c| matches!(c, '=' | '\n' | '\r') || options.comment_chars.contains(&c))
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
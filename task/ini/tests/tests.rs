@@ -1,4 +1,9 @@
-use ini::{parse, IniFile};
+use ini::{
+    parse, parse_events, parse_from_reader, parse_ordered, parse_with_options,
+    schema::{Kind, Schema, SchemaError, UnknownKeys, Value},
+    to_string, to_string_ordered, write, DuplicateKeyPolicy, IncrementalParser, IniEvent, IniFile,
+    OrderedIniFile, ParseError, ParseOptions,
+};
 
 use pretty_assertions::assert_eq;
 
@@ -7,7 +12,8 @@ fn test_simple() {
     let ini = parse(
         "[section]\n\
          key=value",
-    );
+    )
+    .unwrap();
 
     let mut expected = IniFile::new();
     expected.insert(
@@ -23,7 +29,8 @@ fn test_simple() {
 #[test]
 fn test_whitespaces() {
     let ini =
-        parse(" \n  [  section\t]\n   \tkey lolo  hohoho \t=\r   value   after  spaces  \t\n");
+        parse(" \n  [  section\t]\n   \tkey lolo  hohoho \t=\r   value   after  spaces  \t\n")
+            .unwrap();
 
     let mut expected = IniFile::new();
     expected.insert(
@@ -54,7 +61,8 @@ fn test_complex() {
          key   =    value\n\
          \t\n\
          \n",
-    );
+    )
+    .unwrap();
 
     let mut expected = IniFile::new();
     expected.insert(
@@ -89,7 +97,8 @@ fn test_sections_union() {
          key=value\n\
          [section]\n\
          foo=bar",
-    );
+    )
+    .unwrap();
 
     let mut expected = IniFile::new();
     expected.insert(
@@ -112,7 +121,8 @@ fn test_sections_overwrite() {
          key=value\n\
          [section]\n\
          key=bar",
-    );
+    )
+    .unwrap();
 
     let mut expected = IniFile::new();
     expected.insert(
@@ -127,14 +137,14 @@ fn test_sections_overwrite() {
 
 #[test]
 fn test_empty() {
-    assert_eq!(parse(""), IniFile::new());
-    assert_eq!(parse("   "), IniFile::new());
-    assert_eq!(parse("  \n\n\t\n\t \t   \n"), IniFile::new());
+    assert_eq!(parse("").unwrap(), IniFile::new());
+    assert_eq!(parse("   ").unwrap(), IniFile::new());
+    assert_eq!(parse("  \n\n\t\n\t \t   \n").unwrap(), IniFile::new());
 }
 
 #[test]
 fn test_empty_section() {
-    let ini = parse("[section]");
+    let ini = parse("[section]").unwrap();
 
     let mut expected = IniFile::new();
     expected.entry("section".to_string()).or_default();
@@ -177,7 +187,7 @@ fn test_empty_value() {
 
     for file in FILES {
         eprintln!("Testing case:\n{}", file);
-        assert_eq!(parse(file), expected);
+        assert_eq!(parse(file).unwrap(), expected);
     }
 }
 
@@ -192,7 +202,8 @@ fn test_utf8() {
          Schlüssel = lang værdi\n\
          מַפְתֵחַ =
          مفتاح",
-    );
+    )
+    .unwrap();
 
     let mut expected = IniFile::new();
     expected.insert(
@@ -220,40 +231,783 @@ fn test_utf8() {
 }
 
 #[test]
-#[should_panic]
 fn test_stray_pair() {
-    parse("hello = world");
+    assert_eq!(
+        parse("hello = world"),
+        Err(ParseError::KeyValueBeforeSection { line: 1 })
+    );
 }
 
 #[test]
-#[should_panic]
 fn test_stray_key() {
-    parse("hello =");
+    assert_eq!(
+        parse("\n\nhello ="),
+        Err(ParseError::KeyValueBeforeSection { line: 3 })
+    );
 }
 
 #[test]
-#[should_panic]
 fn test_missing_bracket() {
-    parse(
-        "[section\n\
-         abra = cadabra",
+    assert_eq!(
+        parse(
+            "[section]\n\
+             foo = bar\n\
+             [section\n\
+             abra = cadabra",
+        ),
+        Err(ParseError::UnterminatedSectionHeader { line: 3 })
+    );
+    assert_eq!(
+        parse("["),
+        Err(ParseError::UnterminatedSectionHeader { line: 1 })
     );
 }
 
 #[test]
-#[should_panic]
 fn test_double_bracket() {
-    parse(
-        "[[section]]\n\
-         abra = cadabra",
+    assert_eq!(
+        parse(
+            "[[section]]\n\
+             abra = cadabra",
+        ),
+        Err(ParseError::BracketInSectionName { line: 1 })
+    );
+}
+
+#[test]
+fn test_empty_section_name() {
+    assert_eq!(
+        parse(
+            "[section]\n\
+             \n\
+             \t[]  ",
+        ),
+        Err(ParseError::EmptySectionName { line: 3 })
     );
 }
 
 #[test]
-#[should_panic]
 fn test_triple_equals() {
-    parse(
+    let err = parse(
         "[section]\n\
          abra = cadabra=foo",
+    )
+    .unwrap_err();
+    assert_eq!(err, ParseError::TooManyEquals { line: 2 });
+    assert_eq!(err.line(), 2);
+    assert_eq!(
+        err.to_string(),
+        "line 2: key-value pair contains more than one '='"
+    );
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn single_section(pairs: &[(&str, &str)]) -> IniFile {
+    let mut expected = IniFile::new();
+    expected.insert(
+        "section".to_string(),
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    );
+    expected
+}
+
+#[test]
+fn test_comments() {
+    let ini = parse(
+        "; leading comment\n\
+         [section] ; header comment\n\
+         \t# indented comment\n\
+         key = value ; trailing comment\n\
+         other = value # another one\n\
+         url = example.org/#anchor\n\
+         empty = ; nothing here\n\
+         flag ; a = b",
+    )
+    .unwrap();
+    assert_eq!(
+        ini,
+        single_section(&[
+            ("key", "value"),
+            ("other", "value"),
+            ("url", "example.org/#anchor"),
+            ("empty", ""),
+            ("flag", ""),
+        ])
+    );
+
+    assert_eq!(parse("# only a comment").unwrap(), IniFile::new());
+    assert_eq!(parse(";").unwrap(), IniFile::new());
+}
+
+#[test]
+fn test_quoted_values() {
+    let ini = parse(
+        "[section]\n\
+         expr = \"a = b ; not a comment\"\n\
+         padded = '  spaces  ' ; a comment\n\
+         escapes = \"line\\nnext\\t\\\"q\\\" \\\\ \\'\"\n\
+         mixed = 'say \"hi\"'\n\
+         empty = \"\"\n\
+         inner = a\"b",
+    )
+    .unwrap();
+    assert_eq!(
+        ini,
+        single_section(&[
+            ("expr", "a = b ; not a comment"),
+            ("padded", "  spaces  "),
+            ("escapes", "line\nnext\t\"q\" \\ '"),
+            ("mixed", "say \"hi\""),
+            ("empty", ""),
+            ("inner", "a\"b"),
+        ])
+    );
+}
+
+#[test]
+fn test_quoted_value_errors() {
+    let parse_line = |line: &str| parse(&format!("[section]\n{line}"));
+    assert_eq!(
+        parse_line("key = \"unterminated"),
+        Err(ParseError::UnterminatedQuote { line: 2 })
+    );
+    assert_eq!(
+        parse_line("key = 'ends with escape\\'"),
+        Err(ParseError::UnterminatedQuote { line: 2 })
+    );
+    assert_eq!(
+        parse_line("key = \"bad \\q escape\""),
+        Err(ParseError::InvalidEscape { line: 2 })
+    );
+    assert_eq!(
+        parse_line("key = \"value\" tail"),
+        Err(ParseError::TextAfterQuotedValue { line: 2 })
+    );
+    assert_eq!(
+        parse_line("key = a = b ; c"),
+        Err(ParseError::TooManyEquals { line: 2 })
+    );
+}
+
+#[test]
+fn test_strict_options() {
+    let content = "[section]\n\
+                   key = \"a ; b\" # c\n\
+                   other = 'x'";
+    let ini = parse_with_options(content, &ParseOptions::strict()).unwrap();
+    assert_eq!(
+        ini,
+        single_section(&[("key", "\"a ; b\" # c"), ("other", "'x'")])
+    );
+
+    assert_eq!(
+        parse_with_options("; comment", &ParseOptions::strict()),
+        Err(ParseError::KeyValueBeforeSection { line: 1 })
+    );
+
+    let options = ParseOptions {
+        allow_inline_comments: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_with_options(content, &options),
+        Err(ParseError::TextAfterQuotedValue { line: 2 })
+    );
+
+    let options = ParseOptions {
+        comment_chars: vec!['%'],
+        ..Default::default()
+    };
+    let ini = parse_with_options("% comment\n[section]\nkey = 1 % one ; two", &options).unwrap();
+    assert_eq!(ini, single_section(&[("key", "1")]));
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn render(ini: &OrderedIniFile) -> String {
+    let mut output = String::new();
+    for section in ini.sections() {
+        output += &format!("[{}]\n", section.name());
+        for (key, value) in section.iter() {
+            output += &format!("{key}={value}\n");
+        }
+    }
+    output
+}
+
+#[test]
+fn test_ordered_round_trip() {
+    let content = "\
+[zeta]
+y=1
+b=2
+x=3
+[alpha]
+[mid]
+z=
+a=last
+";
+    let ini = parse_ordered(content, DuplicateKeyPolicy::KeepLast).unwrap();
+    assert_eq!(render(&ini), content);
+    assert_eq!(
+        ini.sections().map(|s| s.name()).collect::<Vec<_>>(),
+        ["zeta", "alpha", "mid"]
+    );
+    assert_eq!(ini.get("zeta", "b"), Some("2"));
+    assert_eq!(ini.get("mid", "z"), Some(""));
+    assert_eq!(ini.get("mid", "q"), None);
+    assert_eq!(ini.get("nope", "a"), None);
+    assert!(ini.section("alpha").unwrap().is_empty());
+}
+
+#[test]
+fn test_ordered_merges_repeated_sections() {
+    let ini = parse_ordered(
+        "[a]\n\
+         k1 = 1\n\
+         [b]\n\
+         k = 2\n\
+         [a]\n\
+         k2 = 3",
+        DuplicateKeyPolicy::default(),
+    )
+    .unwrap();
+    assert_eq!(render(&ini), "[a]\nk1=1\nk2=3\n[b]\nk=2\n");
+    assert_eq!(
+        IniFile::from(ini),
+        parse("[b]\nk=2\n[a]\nk2=3\nk1=1").unwrap()
+    );
+}
+
+const DUPLICATES: &str = "\
+[section]
+key = first
+other = value
+[other]
+[section]
+key = second
+";
+
+#[test]
+fn test_duplicate_keep_first() {
+    let ini = parse_ordered(DUPLICATES, DuplicateKeyPolicy::KeepFirst).unwrap();
+    assert_eq!(ini.get("section", "key"), Some("first"));
+    assert_eq!(render(&ini), "[section]\nkey=first\nother=value\n[other]\n");
+}
+
+#[test]
+fn test_duplicate_keep_last() {
+    let ini = parse_ordered(DUPLICATES, DuplicateKeyPolicy::KeepLast).unwrap();
+    assert_eq!(ini.get("section", "key"), Some("second"));
+    assert_eq!(
+        render(&ini),
+        "[section]\nkey=second\nother=value\n[other]\n"
+    );
+    assert_eq!(parse(DUPLICATES).unwrap(), IniFile::from(ini));
+}
+
+#[test]
+fn test_duplicate_error() {
+    let err = parse_ordered(DUPLICATES, DuplicateKeyPolicy::Error).unwrap_err();
+    assert_eq!(err, ParseError::DuplicateKey { line: 6 });
+    assert_eq!(
+        err.to_string(),
+        "line 6: key is already defined in this section"
+    );
+}
+
+#[test]
+fn test_ordered_insert() {
+    let mut ini = OrderedIniFile::new();
+    assert_eq!(ini.insert("b", "x", "1"), None);
+    assert_eq!(ini.insert("a", "y", "2"), None);
+    assert_eq!(ini.insert("b", "x", "3"), Some("1".to_string()));
+    assert_eq!(render(&ini), "[b]\nx=3\n[a]\ny=2\n");
+    assert_eq!(ini.len(), 2);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+const FIXTURE: &str = "\
+[server]
+host = example.org
+port = 8080
+verbose = yes
+ratio = 0.75
+level = debug
+
+[client]
+retries = 3
+";
+
+fn fixture_schema() -> Schema {
+    Schema::new()
+        .section("server", |s| {
+            s.required("host", Kind::Str)
+                .required(
+                    "port",
+                    Kind::Int {
+                        min: Some(1),
+                        max: Some(65535),
+                    },
+                )
+                .required("verbose", Kind::Bool)
+                .required("ratio", Kind::Float)
+                .required("level", Kind::Enum(&["debug", "info", "error"]))
+                .with_default(
+                    "timeout",
+                    Kind::Int {
+                        min: None,
+                        max: None,
+                    },
+                    "30",
+                )
+                .with_default("secure", Kind::Bool, "off")
+                .optional("motd", Kind::Str)
+        })
+        .section("client", |s| {
+            s.required(
+                "retries",
+                Kind::Int {
+                    min: Some(0),
+                    max: None,
+                },
+            )
+            .with_default("name", Kind::Str, "anonymous")
+        })
+}
+
+#[test]
+fn test_schema_every_kind() {
+    let config = fixture_schema().validate(&parse(FIXTURE).unwrap()).unwrap();
+    assert_eq!(config.get_str("server", "host"), "example.org");
+    assert_eq!(config.get_int("server", "port"), 8080);
+    assert!(config.get_bool("server", "verbose"));
+    assert_eq!(config.get_float("server", "ratio"), 0.75);
+    assert_eq!(config.get_enum("server", "level"), "debug");
+    assert_eq!(config.get_int("client", "retries"), 3);
+    assert_eq!(config.get("server", "motd"), None);
+    assert!(config.warnings().is_empty());
+}
+
+#[test]
+fn test_schema_defaults() {
+    let config = fixture_schema().validate(&parse(FIXTURE).unwrap()).unwrap();
+    assert_eq!(config.get_int("server", "timeout"), 30);
+    assert!(!config.get_bool("server", "secure"));
+    assert_eq!(config.get_str("client", "name"), "anonymous");
+
+    let config = fixture_schema()
+        .validate(&parse(&format!("{FIXTURE}name = bob\n")).unwrap())
+        .unwrap();
+    assert_eq!(
+        config.get("client", "name"),
+        Some(&Value::Str("bob".into()))
+    );
+}
+
+#[test]
+fn test_schema_invalid_values() {
+    let ini = parse(
+        "[server]\n\
+         host = example.org\n\
+         port = 0\n\
+         verbose = maybe\n\
+         ratio = half\n\
+         level = trace\n\
+         timeout = soon\n\
+         [client]\n\
+         retries = -1",
+    )
+    .unwrap();
+    let invalid =
+        |section: &str, key: &str, value: &str, expected: &str| SchemaError::InvalidValue {
+            section: section.into(),
+            key: key.into(),
+            value: value.into(),
+            expected: expected.into(),
+        };
+    assert_eq!(
+        fixture_schema().validate(&ini).unwrap_err(),
+        vec![
+            invalid("server", "port", "0", "an integer from 1 to 65535"),
+            invalid("server", "verbose", "maybe", "a boolean"),
+            invalid("server", "ratio", "half", "a float"),
+            invalid("server", "level", "trace", "one of: debug, info, error"),
+            invalid("server", "timeout", "soon", "an integer"),
+            invalid("client", "retries", "-1", "an integer not less than 0"),
+        ]
+    );
+}
+
+#[test]
+fn test_schema_aggregated_errors() {
+    let ini = parse(
+        "[server]\n\
+         port = http\n\
+         level = info\n\
+         colour = blue\n\
+         [extra]",
+    )
+    .unwrap();
+    let errors = fixture_schema()
+        .unknown_keys(UnknownKeys::Error)
+        .validate(&ini)
+        .unwrap_err();
+    assert_eq!(
+        errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "[server] host: missing required key",
+            "[server] port: invalid value \"http\", expected an integer from 1 to 65535",
+            "[server] verbose: missing required key",
+            "[server] ratio: missing required key",
+            "[client] retries: missing required key",
+            "[server] colour: unknown key",
+            "unknown section [extra]",
+        ]
+    );
+}
+
+#[test]
+fn test_schema_unknown_keys_modes() {
+    let ini = parse(&format!("{FIXTURE}colour = blue\n[extra]\nfoo = bar\n")).unwrap();
+    let unknowns = vec![
+        SchemaError::UnknownKey {
+            section: "client".into(),
+            key: "colour".into(),
+        },
+        SchemaError::UnknownSection {
+            section: "extra".into(),
+        },
+    ];
+
+    let config = fixture_schema().validate(&ini).unwrap();
+    assert!(config.warnings().is_empty());
+
+    let config = fixture_schema()
+        .unknown_keys(UnknownKeys::Warn)
+        .validate(&ini)
+        .unwrap();
+    assert_eq!(config.warnings(), unknowns);
+    assert_eq!(config.get_int("client", "retries"), 3);
+
+    assert_eq!(
+        fixture_schema()
+            .unknown_keys(UnknownKeys::Error)
+            .validate(&ini)
+            .unwrap_err(),
+        unknowns
+    );
+}
+
+#[test]
+#[should_panic]
+fn test_schema_invalid_default() {
+    Schema::new().section("section", |s| s.with_default("key", Kind::Bool, "maybe"));
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn ini_from(sections: &[(&str, &[(&str, &str)])]) -> IniFile {
+    sections
+        .iter()
+        .map(|(name, pairs)| {
+            let pairs = pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            (name.to_string(), pairs)
+        })
+        .collect()
+}
+
+#[test]
+fn test_write_sorted() {
+    let ini = ini_from(&[
+        ("zeta", &[("b", "2"), ("a", "1")]),
+        ("alpha", &[]),
+        ("mid", &[("key", "value")]),
+    ]);
+    assert_eq!(
+        to_string(&ini),
+        "[alpha]\n\
+         \n\
+         [mid]\n\
+         key = value\n\
+         \n\
+         [zeta]\n\
+         a = 1\n\
+         b = 2\n"
+    );
+
+    let mut output = vec![];
+    write(&ini, &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), to_string(&ini));
+}
+
+#[test]
+fn test_write_ordered() {
+    let content = "[zeta]\ny = 1\nb = 2\n\n[alpha]\nkey = \" padded\"\n";
+    let ini = parse_ordered(content, DuplicateKeyPolicy::KeepLast).unwrap();
+    assert_eq!(to_string_ordered(&ini), content);
+}
+
+const TRICKY_VALUES: &[&str] = &[
+    "",
+    "plain",
+    "with spaces inside",
+    "  leading",
+    "trailing\t",
+    " ",
+    "a = b",
+    "=",
+    "a ; not a comment",
+    "a # not a comment",
+    ";",
+    "#hash",
+    "url/#anchor",
+    "multi\nline",
+    "carriage\rreturn",
+    "\r\n",
+    "\"quoted\"",
+    "'single'",
+    "mid\"quote",
+    "it's",
+    "back\\slash",
+    "\\",
+    "\\n",
+    "\\\"",
+    "[not a section]",
+    "юникод ; 部分",
+    "\u{a0}nbsp",
+];
+
+#[test]
+fn test_round_trip_values() {
+    for value in TRICKY_VALUES {
+        let ini = ini_from(&[("section", &[("key", value)])]);
+        assert_eq!(parse(&to_string(&ini)).unwrap(), ini, "{value:?}");
+    }
+}
+
+#[test]
+fn test_round_trip_names() {
+    let keys = ["key", "two words", "", "a#b", "k;", "ключ", "a\"b", "x]"];
+    let sections = ["section", " padded\t", "a=b", ";x", "a#b", "раздел"];
+    for section in sections {
+        for key in keys {
+            let ini = ini_from(&[(section, &[(key, "value")]), ("other", &[(key, "")])]);
+            assert_eq!(parse(&to_string(&ini)).unwrap(), ini, "{section:?} {key:?}");
+        }
+    }
+}
+
+#[test]
+fn test_round_trip_random() {
+    const ALPHABET: &[char] = &[
+        'a', 'b', ' ', '\t', '=', ';', '#', '"', '\'', '\\', '\n', '\r', '[', ']', 'n', 'ы',
+    ];
+
+    // xorshift, to keep the test deterministic without extra dependencies
+    let mut state = 0x2545f4914f6cdd1d_u64;
+    let mut next = move |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+
+    for _ in 0..1000 {
+        let mut ini = IniFile::new();
+        for s in 0..next(4) {
+            let section = ini.entry(format!("section {s}")).or_default();
+            for k in 0..next(5) {
+                let value = (0..next(12))
+                    .map(|_| ALPHABET[next(ALPHABET.len())])
+                    .collect();
+                section.insert(format!("key{k}"), value);
+            }
+        }
+        assert_eq!(parse(&to_string(&ini)).unwrap(), ini, "{ini:?}");
+    }
+}
+
+#[test]
+fn test_write_invalid_names() {
+    let invalid = [
+        ini_from(&[("", &[])]),
+        ini_from(&[("a[b", &[])]),
+        ini_from(&[("a]b", &[])]),
+        ini_from(&[("line\nbreak", &[])]),
+        ini_from(&[("a ;b", &[])]),
+        ini_from(&[("section", &[("a=b", "")])]),
+        ini_from(&[("section", &[("[key", "")])]),
+        ini_from(&[("section", &[(";key", "")])]),
+        ini_from(&[("section", &[("key ", "")])]),
+        ini_from(&[("section", &[("a #b", "")])]),
+    ];
+    for ini in invalid {
+        let err = write(&ini, std::io::sink()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{ini:?}");
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+const STREAM_FIXTURE: &str = "; settings\r\n\
+                              [сервер]\n\
+                              host = example.org # main\n\
+                              \n\
+                              greeting = \"привет, 世界\"\n\
+                              [部分]\n\
+                              emoji=🦀\n\
+                              [сервер]\n\
+                              port = 8080";
+
+fn feed_in_chunks(content: &[u8], split_points: &[usize]) -> Result<Vec<IniEvent>, ParseError> {
+    let mut parser = IncrementalParser::new(ParseOptions::default());
+    let mut events = vec![];
+    let mut start = 0;
+    for &end in split_points.iter().chain([&content.len()]) {
+        events.extend(parser.feed(&content[start..end])?);
+        start = end;
+    }
+    events.extend(parser.finish()?);
+    Ok(events)
+}
+
+#[test]
+fn test_events() {
+    let events = parse_events("# top\n[a]\nk = v\n[b]\nk", &ParseOptions::default()).unwrap();
+    assert_eq!(
+        events,
+        vec![
+            IniEvent::Comment {
+                text: "# top".to_string(),
+                line: 1
+            },
+            IniEvent::Section {
+                name: "a".to_string(),
+                line: 2
+            },
+            IniEvent::Pair {
+                key: "k".to_string(),
+                value: "v".to_string(),
+                line: 3
+            },
+            IniEvent::Section {
+                name: "b".to_string(),
+                line: 4
+            },
+            IniEvent::Pair {
+                key: "k".to_string(),
+                value: "".to_string(),
+                line: 5
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_incremental_every_split() {
+    let expected = parse_events(STREAM_FIXTURE, &ParseOptions::default()).unwrap();
+    let content = STREAM_FIXTURE.as_bytes();
+    for split in 0..=content.len() {
+        assert_eq!(
+            feed_in_chunks(content, &[split]).unwrap(),
+            expected,
+            "{split}"
+        );
+    }
+    for len in 1..4 {
+        let split_points: Vec<_> = (len..content.len()).step_by(len).collect();
+        assert_eq!(feed_in_chunks(content, &split_points).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_incremental_utf8_split() {
+    let mut parser = IncrementalParser::new(ParseOptions::default());
+    let content = "[раздел]\nключ = 🦀\n".as_bytes();
+    let crab = content.len() - 3;
+
+    assert_eq!(parser.feed(&content[..3]).unwrap(), vec![]);
+    assert_eq!(
+        parser.feed(&content[3..crab]).unwrap(),
+        vec![IniEvent::Section {
+            name: "раздел".to_string(),
+            line: 1
+        }]
+    );
+    assert_eq!(
+        parser.feed(&content[crab..]).unwrap(),
+        vec![IniEvent::Pair {
+            key: "ключ".to_string(),
+            value: "🦀".to_string(),
+            line: 2
+        }]
+    );
+    assert_eq!(parser.finish().unwrap(), vec![]);
+}
+
+#[test]
+fn test_incremental_errors() {
+    let content = "[section]\nkey = 1\nkey = 2\n[]\n".as_bytes();
+    let options = ParseOptions {
+        duplicate_keys: DuplicateKeyPolicy::Error,
+        ..Default::default()
+    };
+    for split in 0..=content.len() {
+        let mut parser = IncrementalParser::new(options.clone());
+        let result = parser.feed(&content[..split]).and_then(|mut events| {
+            events.extend(parser.feed(&content[split..])?);
+            Ok(events)
+        });
+        assert_eq!(result, Err(ParseError::DuplicateKey { line: 3 }), "{split}");
+        assert_eq!(parser.finish(), Err(ParseError::DuplicateKey { line: 3 }));
+    }
+
+    let content = b"[section]\nkey = 1\nbad = \xff\xfe\n";
+    for split in 0..=content.len() {
+        assert_eq!(
+            feed_in_chunks(content, &[split]),
+            Err(ParseError::InvalidUtf8 { line: 3 }),
+            "{split}"
+        );
+    }
+
+    // A truncated sequence at the very end only shows up on `finish`.
+    let mut parser = IncrementalParser::new(ParseOptions::default());
+    let content = "[section]\nkey = 🦀".as_bytes();
+    assert!(parser.feed(&content[..content.len() - 1]).is_ok());
+    assert_eq!(parser.finish(), Err(ParseError::InvalidUtf8 { line: 2 }));
+}
+
+#[test]
+fn test_parse_from_reader() {
+    let ini = parse_from_reader(STREAM_FIXTURE.as_bytes(), &ParseOptions::default()).unwrap();
+    assert_eq!(ini, parse(STREAM_FIXTURE).unwrap());
+
+    let options = ParseOptions {
+        duplicate_keys: DuplicateKeyPolicy::KeepFirst,
+        ..Default::default()
+    };
+    let content = "[a]\nk = 1\n[b]\nk = 2\n[a]\nk = 3\nj = 4";
+    assert_eq!(
+        parse_from_reader(content.as_bytes(), &options).unwrap(),
+        parse_with_options(content, &options).unwrap()
+    );
+
+    let err = parse_from_reader("[a]\n= \"x".as_bytes(), &ParseOptions::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 2: quoted value is missing the closing quote"
     );
 }
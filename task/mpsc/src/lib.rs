@@ -1,36 +1,113 @@
+//! A single-threaded MPSC channel, see [`channel`].
+//!
+//! # When the elements are dropped
+//!
+//! The channel never keeps an element alive longer than the receiver could still get
+//! it. Senders only hold weak references to the buffer, so they can't keep the elements
+//! alive however many of them there are and whenever they are dropped:
+//!
+//! * Dropping the receiver closes the channel and then drops the elements still
+//!   buffered, front to back, before its drop returns. An element which sends to the
+//!   channel from its own drop finds it closed already.
+//! * Dropping all the senders drops nothing: the buffered elements can still be
+//!   received, and those left are dropped with the receiver.
+//! * [`Receiver::close`] closes the channel for the senders right away and drops nothing
+//!   either, the buffered elements stay with the receiver.
+//! * An element the channel doesn't accept is given back in the error, to be dropped
+//!   by the caller.
+//!
+//! A received element is the caller's from then on.
+
 #![forbid(unsafe_code)]
 
+pub mod rpc;
+
 use std::{
     cell::RefCell,
     collections::VecDeque,
-    fmt::Debug,
+    error::Error,
+    fmt::{self, Debug, Display},
     rc::{Rc, Weak},
+    time::Duration,
 };
 
-use thiserror::Error;
+#[cfg(feature = "async")]
+use std::{
+    cell::Cell,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Error, Debug)]
-#[error("channel is closed")]
-pub struct SendError<T: Debug> {
+pub struct SendError<T> {
     pub value: T,
 }
 
-pub type Buffer<T> = RefCell<VecDeque<T>>;
+/// Like `std::sync::mpsc::SendError`, doesn't require the value to be `Debug`.
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("channel is closed")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+pub struct Buffer<T> {
+    queue: RefCell<VecDeque<T>>,
+    #[cfg(feature = "async")]
+    waker: Cell<Option<Waker>>,
+}
+
+impl<T> Buffer<T> {
+    fn take(&self) -> VecDeque<T> {
+        self.queue.take()
+    }
+
+    #[cfg(feature = "async")]
+    fn wake(&self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Default for Buffer<T> {
+    fn default() -> Self {
+        VecDeque::new().into()
+    }
+}
+
+impl<T> From<VecDeque<T>> for Buffer<T> {
+    fn from(queue: VecDeque<T>) -> Self {
+        Self {
+            queue: RefCell::new(queue),
+            #[cfg(feature = "async")]
+            waker: Cell::new(None),
+        }
+    }
+}
 
 pub struct Sender<T> {
     buffer: Weak<Buffer<T>>,
 }
 
-impl<T: Debug> Sender<T> {
-    pub fn new(buffer: Weak<RefCell<VecDeque<T>>>) -> Self {
+impl<T> Sender<T> {
+    pub fn new(buffer: Weak<Buffer<T>>) -> Self {
         Self { buffer }
     }
 
     pub fn send(&self, value: T) -> Result<(), SendError<T>> {
         if let Some(rc) = self.buffer.upgrade() {
-            rc.as_ref().borrow_mut().push_back(value);
+            rc.queue.borrow_mut().push_back(value);
+            #[cfg(feature = "async")]
+            rc.wake();
             drop(rc);
 
             Ok(())
@@ -43,6 +120,17 @@ impl<T: Debug> Sender<T> {
         self.buffer.upgrade().is_none()
     }
 
+    /// The number of buffered elements, 0 once the channel is closed.
+    pub fn len(&self) -> usize {
+        self.buffer
+            .upgrade()
+            .map_or(0, |rc| rc.queue.borrow().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn same_channel(&self, other: &Self) -> bool {
         self.buffer.ptr_eq(&other.buffer)
     }
@@ -56,9 +144,109 @@ impl<T> Clone for Sender<T> {
     }
 }
 
+#[cfg(feature = "async")]
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // The last sender closes the channel, so a pending receiver must be woken up
+        // to observe it.
+        if let Some(rc) = self.buffer.upgrade() {
+            if Rc::weak_count(&rc) == 1 {
+                rc.wake();
+            }
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Error, Debug)]
+pub enum BoundedSendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+impl<T> BoundedSendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) => value,
+        }
+    }
+}
+
+impl<T> Debug for BoundedSendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("Full(..)"),
+            Self::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+impl<T> Display for BoundedSendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("channel is full"),
+            Self::Closed(_) => f.write_str("channel is closed"),
+        }
+    }
+}
+
+impl<T> Error for BoundedSendError<T> {}
+
+/// A sender of a channel made by [`channel_with_capacity`].
+pub struct BoundedSender<T> {
+    sender: Sender<T>,
+    capacity: usize,
+}
+
+impl<T> BoundedSender<T> {
+    /// Fails with `Full` if the channel holds `capacity` elements already. A closed
+    /// channel is reported as `Closed`, even if it is full too.
+    pub fn send(&self, value: T) -> Result<(), BoundedSendError<T>> {
+        if self.sender.is_closed() {
+            return Err(BoundedSendError::Closed(value));
+        }
+        if self.sender.len() >= self.capacity {
+            return Err(BoundedSendError::Full(value));
+        }
+        self.sender
+            .send(value)
+            .map_err(|SendError { value }| BoundedSendError::Closed(value))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// The number of buffered elements, 0 once the channel is closed.
+    pub fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sender.is_empty()
+    }
+
+    pub fn same_channel(&self, other: &Self) -> bool {
+        self.sender.same_channel(&other.sender)
+    }
+}
+
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(thiserror::Error, Debug)]
 pub enum ReceiveError {
     #[error("channel is empty")]
     Empty,
@@ -72,19 +260,25 @@ pub struct Receiver<T> {
 }
 
 impl<T> Receiver<T> {
-    pub fn new(buffer: Rc<RefCell<VecDeque<T>>>) -> Self {
+    pub fn new(buffer: Rc<Buffer<T>>) -> Self {
         Self {
             buffer,
             is_closed: false,
         }
     }
 
+    /// Same as `try_recv`, kept for compatibility.
     pub fn recv(&mut self) -> Result<T, ReceiveError> {
-        if let Some(element) = self.buffer.as_ref().borrow_mut().pop_front() {
+        self.try_recv()
+    }
+
+    /// Returns the next element if one is buffered, without waiting.
+    pub fn try_recv(&mut self) -> Result<T, ReceiveError> {
+        if let Some(element) = self.buffer.queue.borrow_mut().pop_front() {
             return Ok(element);
         }
 
-        if Rc::<RefCell<VecDeque<T>>>::weak_count(&self.buffer) == 0 {
+        if Rc::weak_count(&self.buffer) == 0 {
             self.close();
         }
 
@@ -95,23 +289,180 @@ impl<T> Receiver<T> {
         Err(ReceiveError::Empty)
     }
 
+    /// Returns the next element like `try_recv`, without waiting: `timeout` is ignored.
+    ///
+    /// Both halves of the channel are `!Send`, so nothing could send to it while this
+    /// thread waited, and an empty channel stays empty for the whole `timeout`. The
+    /// method keeps consumers written against `std::sync::mpsc` working.
+    pub fn recv_timeout(&mut self, _timeout: Duration) -> Result<T, ReceiveError> {
+        self.try_recv()
+    }
+
+    /// An iterator over the buffered elements, which ends once the channel is empty,
+    /// whether it's closed or not: nothing could be sent to an open channel while the
+    /// iterator waited in this thread. Elements sent later are received by the next
+    /// iterator.
+    pub fn iter(&mut self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    /// Same as `iter`, kept for compatibility.
+    pub fn try_iter(&mut self) -> Iter<'_, T> {
+        self.iter()
+    }
+
+    /// The number of buffered elements, which are still received after the channel
+    /// is closed.
+    pub fn len(&self) -> usize {
+        self.buffer.queue.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the channel for the senders. The buffered elements stay and can still be
+    /// received, the senders see the channel closed at once.
     pub fn close(&mut self) {
+        if self.is_closed {
+            return;
+        }
         self.is_closed = true;
-        self.buffer = RefCell::from(self.buffer.take()).into();
+        // Senders upgrade their weak references to send, so moving the elements out to
+        // a buffer of our own and dropping the shared one is what closes the channel.
+        // The shared one is empty by then, nothing is dropped with it.
+        self.buffer = Rc::new(self.buffer.take().into());
+    }
+
+    /// Polls the channel for the next element.
+    ///
+    /// Returns `Poll::Ready(Some(value))` if an element is buffered and `Poll::Ready(None)`
+    /// once the channel is closed and drained. Otherwise the waker from `cx` is stored in
+    /// the shared state and `Poll::Pending` is returned: the next `Sender::send` or the drop
+    /// of the last `Sender` wakes it. Only the waker from the most recent call is kept.
+    ///
+    /// The channel is `Rc`-based, so neither half is `Send`: this is meant for
+    /// single-threaded executors, like the one built in the `rio` task.
+    #[cfg(feature = "async")]
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self.recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(ReceiveError::Closed) => Poll::Ready(None),
+            Err(ReceiveError::Empty) => {
+                let waker = match self.buffer.waker.take() {
+                    Some(waker) if waker.will_wake(cx.waker()) => waker,
+                    _ => cx.waker().clone(),
+                };
+                self.buffer.waker.set(Some(waker));
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Turns the receiver into a [`futures_core::Stream`] of its elements.
+    ///
+    /// The stream ends once the channel is closed and drained. See [`Receiver::poll_recv`].
+    #[cfg(feature = "async")]
+    pub fn into_stream(self) -> ReceiverStream<T> {
+        ReceiverStream { receiver: self }
     }
 }
 
+/// Closes the channel before the buffered elements are dropped with the buffer.
 impl<T> Drop for Receiver<T> {
     fn drop(&mut self) {
         self.close();
     }
 }
 
+/// See [`Receiver::iter`].
+pub struct Iter<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterates like [`Receiver::iter`], consuming the receiver.
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A [`futures_core::Stream`] over the elements of a [`Receiver`].
+///
+/// Like the receiver itself, it is `!Send` and must be polled from a single thread.
+#[cfg(feature = "async")]
+pub struct ReceiverStream<T> {
+    receiver: Receiver<T>,
+}
+
+#[cfg(feature = "async")]
+impl<T> ReceiverStream<T> {
+    pub fn into_inner(self) -> Receiver<T> {
+        self.receiver
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> futures_core::Stream for ReceiverStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
-pub fn channel<T: std::fmt::Debug>() -> (Sender<T>, Receiver<T>) {
-    let buffer = Rc::new(RefCell::new(VecDeque::<T>::default()));
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let buffer = Rc::new(Buffer::<T>::default());
     let weak = Rc::downgrade(&buffer);
 
     (Sender::new(weak), Receiver::new(buffer))
 }
+
+/// A channel that holds at most `capacity` elements, further sends fail with
+/// `BoundedSendError::Full` until the receiver takes some.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0: such a channel could never accept an element.
+pub fn channel_with_capacity<T>(capacity: usize) -> (BoundedSender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be positive");
+
+    let (sender, receiver) = channel();
+    (BoundedSender { sender, capacity }, receiver)
+}
@@ -0,0 +1,181 @@
+//! Request-response on top of [`channel`]: every request carries a one-shot slot
+//! for its reply.
+
+use crate::{channel, ReceiveError, Receiver, SendError, Sender};
+
+use std::{
+    cell::RefCell,
+    error,
+    fmt::{self, Debug, Display},
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("request was dropped without a reply")]
+pub struct Canceled;
+
+pub enum ReplyError<Resp> {
+    AlreadyReplied(Resp),
+    Dropped(Resp),
+}
+
+/// Like `SendError`, doesn't require the response to be `Debug`.
+impl<Resp> Debug for ReplyError<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyReplied(_) => f.write_str("AlreadyReplied(..)"),
+            Self::Dropped(_) => f.write_str("Dropped(..)"),
+        }
+    }
+}
+
+impl<Resp> Display for ReplyError<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyReplied(_) => f.write_str("request is replied already"),
+            Self::Dropped(_) => f.write_str("pending reply is dropped"),
+        }
+    }
+}
+
+impl<Resp> error::Error for ReplyError<Resp> {}
+
+enum Slot<Resp> {
+    Pending,
+    Ready(Resp),
+    Taken,
+    Canceled,
+}
+
+type SharedSlot<Resp> = Rc<RefCell<Slot<Resp>>>;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The reply to a request made with [`RpcClient::call`].
+pub struct PendingReply<Resp> {
+    slot: SharedSlot<Resp>,
+}
+
+impl<Resp> PendingReply<Resp> {
+    /// Returns the reply if it has arrived, or `Ok(None)` if it has not yet. Once the
+    /// reply is taken, it's `Ok(None)` forever.
+    ///
+    /// Returns `Err(Canceled)` if the server dropped the request without replying.
+    pub fn try_take(&mut self) -> Result<Option<Resp>, Canceled> {
+        let mut slot = self.slot.borrow_mut();
+        match &*slot {
+            Slot::Pending | Slot::Taken => Ok(None),
+            Slot::Canceled => Err(Canceled),
+            Slot::Ready(_) => match std::mem::replace(&mut *slot, Slot::Taken) {
+                Slot::Ready(resp) => Ok(Some(resp)),
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+
+impl<Resp> Debug for PendingReply<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingReply").finish_non_exhaustive()
+    }
+}
+
+/// Fulfills the reply to a request received by [`RpcServer::recv`]. Dropping it without
+/// a reply cancels the request.
+pub struct ReplyHandle<Resp> {
+    slot: SharedSlot<Resp>,
+    is_replied: bool,
+}
+
+impl<Resp> ReplyHandle<Resp> {
+    /// Fails if called for the second time, and if the client has dropped the
+    /// `PendingReply` already. The server can ignore the latter.
+    pub fn reply(&mut self, resp: Resp) -> Result<(), ReplyError<Resp>> {
+        if self.is_replied {
+            return Err(ReplyError::AlreadyReplied(resp));
+        }
+        self.is_replied = true;
+
+        if Rc::strong_count(&self.slot) == 1 {
+            return Err(ReplyError::Dropped(resp));
+        }
+        *self.slot.borrow_mut() = Slot::Ready(resp);
+        Ok(())
+    }
+}
+
+impl<Resp> Debug for ReplyHandle<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplyHandle")
+            .field("is_replied", &self.is_replied)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Resp> Drop for ReplyHandle<Resp> {
+    fn drop(&mut self) {
+        if !self.is_replied {
+            *self.slot.borrow_mut() = Slot::Canceled;
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub struct RpcClient<Req, Resp> {
+    sender: Sender<(Req, ReplyHandle<Resp>)>,
+}
+
+impl<Req, Resp> RpcClient<Req, Resp> {
+    pub fn call(&self, req: Req) -> Result<PendingReply<Resp>, SendError<Req>> {
+        let slot = Rc::new(RefCell::new(Slot::Pending));
+        let handle = ReplyHandle {
+            slot: slot.clone(),
+            is_replied: false,
+        };
+
+        match self.sender.send((req, handle)) {
+            Ok(()) => Ok(PendingReply { slot }),
+            Err(SendError { value: (req, _) }) => Err(SendError { value: req }),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+impl<Req, Resp> Clone for RpcClient<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+pub struct RpcServer<Req, Resp> {
+    receiver: Receiver<(Req, ReplyHandle<Resp>)>,
+}
+
+impl<Req, Resp> RpcServer<Req, Resp> {
+    pub fn recv(&mut self) -> Result<(Req, ReplyHandle<Resp>), ReceiveError> {
+        self.receiver.recv()
+    }
+
+    /// Closes the channel for new requests. The requests sent already can still be
+    /// received and replied.
+    pub fn close(&mut self) {
+        self.receiver.close();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn rpc_channel<Req, Resp>() -> (RpcClient<Req, Resp>, RpcServer<Req, Resp>) {
+    let (sender, receiver) = channel();
+    (RpcClient { sender }, RpcServer { receiver })
+}
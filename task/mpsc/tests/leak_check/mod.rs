@@ -0,0 +1,30 @@
+//! Test support for checking when the elements of a channel are dropped.
+
+use std::{cell::Cell, rc::Rc};
+
+/// Counts the drops of the trackers made from it.
+#[derive(Default, Clone)]
+pub struct DropCount(Rc<Cell<usize>>);
+
+impl DropCount {
+    pub fn tracker(&self) -> DropTracker {
+        DropTracker {
+            drops: self.0.clone(),
+        }
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.get()
+    }
+}
+
+/// A value which increments its count when dropped.
+pub struct DropTracker {
+    drops: Rc<Cell<usize>>,
+}
+
+impl Drop for DropTracker {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
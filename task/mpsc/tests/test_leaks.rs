@@ -0,0 +1,198 @@
+mod leak_check;
+
+use leak_check::{DropCount, DropTracker};
+use mpsc::{channel, channel_with_capacity, rpc::rpc_channel, BoundedSendError, Sender};
+
+use std::{cell::Cell, rc::Rc};
+
+fn send_all(sender: &Sender<DropTracker>, drops: &DropCount, count: usize) {
+    for _ in 0..count {
+        assert!(sender.send(drops.tracker()).is_ok());
+    }
+}
+
+#[test]
+fn test_receiver_dropped_first() {
+    let drops = DropCount::default();
+    let (sender, receiver) = channel();
+    send_all(&sender, &drops, 3);
+    assert_eq!(drops.get(), 0);
+
+    drop(receiver);
+    assert_eq!(drops.get(), 3);
+
+    // Given back, and dropped by the caller.
+    let err = sender.send(drops.tracker()).unwrap_err();
+    assert_eq!(drops.get(), 3);
+    drop(err);
+    assert_eq!(drops.get(), 4);
+
+    drop(sender);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn test_senders_dropped_first() {
+    let drops = DropCount::default();
+    let (sender, mut receiver) = channel();
+    let second_sender = sender.clone();
+    send_all(&sender, &drops, 2);
+    send_all(&second_sender, &drops, 2);
+
+    drop(sender);
+    drop(second_sender);
+    assert_eq!(drops.get(), 0);
+    assert_eq!(receiver.len(), 4);
+
+    let received = receiver.recv().unwrap();
+    assert_eq!(drops.get(), 0);
+    drop(received);
+    assert_eq!(drops.get(), 1);
+
+    drop(receiver);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn test_close_with_items() {
+    let drops = DropCount::default();
+    let (sender, mut receiver) = channel();
+    send_all(&sender, &drops, 3);
+
+    receiver.close();
+    assert_eq!(drops.get(), 0);
+    assert_eq!(receiver.len(), 3);
+    drop(sender.send(drops.tracker()).unwrap_err());
+    assert_eq!(drops.get(), 1);
+
+    // The senders have no say in the elements after the close.
+    drop(sender);
+    assert_eq!(drops.get(), 1);
+
+    drop(receiver.recv().unwrap());
+    assert_eq!(drops.get(), 2);
+
+    // Closing again changes nothing.
+    receiver.close();
+    assert_eq!(receiver.len(), 2);
+    assert_eq!(drops.get(), 2);
+
+    drop(receiver);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn test_scopes() {
+    let drops = DropCount::default();
+    {
+        let (sender, receiver) = channel();
+        {
+            let sender = sender.clone();
+            send_all(&sender, &drops, 2);
+        }
+        assert_eq!(drops.get(), 0);
+        {
+            let mut receiver = receiver;
+            let _received = receiver.recv().unwrap();
+            send_all(&sender, &drops, 1);
+        }
+        // Both the received one and the buffered ones.
+        assert_eq!(drops.get(), 3);
+        assert!(sender.is_closed());
+    }
+    assert_eq!(drops.get(), 3);
+}
+
+#[test]
+fn test_closed_by_try_recv() {
+    let drops = DropCount::default();
+    let (sender, mut receiver) = channel();
+    send_all(&sender, &drops, 2);
+    drop(sender);
+
+    let values = receiver.iter().collect::<Vec<_>>();
+    assert_eq!(values.len(), 2);
+    assert_eq!(drops.get(), 0);
+    drop(values);
+    assert_eq!(drops.get(), 2);
+
+    assert!(receiver.try_recv().is_err());
+    drop(receiver);
+    assert_eq!(drops.get(), 2);
+}
+
+#[test]
+fn test_partially_iterated() {
+    let drops = DropCount::default();
+    let (sender, mut receiver) = channel();
+    send_all(&sender, &drops, 5);
+
+    receiver.try_iter().take(2).for_each(drop);
+    assert_eq!(drops.get(), 2);
+    drop(receiver);
+    assert_eq!(drops.get(), 5);
+}
+
+#[test]
+fn test_bounded() {
+    let drops = DropCount::default();
+    let (sender, receiver) = channel_with_capacity(2);
+    for _ in 0..2 {
+        assert!(sender.send(drops.tracker()).is_ok());
+    }
+
+    let err = sender.send(drops.tracker()).unwrap_err();
+    assert!(matches!(err, BoundedSendError::Full(_)));
+    assert_eq!(drops.get(), 0);
+    drop(err.into_inner());
+    assert_eq!(drops.get(), 1);
+
+    drop(receiver);
+    assert_eq!(drops.get(), 3);
+    let err = sender.send(drops.tracker()).unwrap_err();
+    assert!(matches!(err, BoundedSendError::Closed(_)));
+    drop(err);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn test_dropped_after_close() {
+    /// Records whether the channel is closed when it's dropped.
+    struct ChecksClosed {
+        sender: Sender<ChecksClosed>,
+        closed: Rc<Cell<Option<bool>>>,
+    }
+
+    impl Drop for ChecksClosed {
+        fn drop(&mut self) {
+            self.closed.set(Some(self.sender.is_closed()));
+        }
+    }
+
+    let closed = Rc::new(Cell::new(None));
+    let (sender, receiver) = channel();
+    let value = ChecksClosed {
+        sender: sender.clone(),
+        closed: closed.clone(),
+    };
+    assert!(sender.send(value).is_ok());
+
+    drop(receiver);
+    assert_eq!(closed.get(), Some(true));
+}
+
+#[test]
+fn test_rpc_requests() {
+    let drops = DropCount::default();
+    let (client, server) = rpc_channel::<DropTracker, DropTracker>();
+    let mut pending = client.call(drops.tracker()).unwrap();
+    let _second = client.call(drops.tracker()).unwrap();
+
+    // The queued requests and their reply handles go with the server.
+    drop(server);
+    assert_eq!(drops.get(), 2);
+    assert!(pending.try_take().is_err());
+
+    drop(client.call(drops.tracker()).unwrap_err());
+    assert_eq!(drops.get(), 3);
+}
@@ -0,0 +1,110 @@
+use mpsc::{
+    rpc::{rpc_channel, Canceled, ReplyError},
+    ReceiveError,
+};
+
+#[test]
+fn test_doubling_worker() {
+    let (client, mut server) = rpc_channel::<i32, i32>();
+
+    let mut pending = (1..=5).map(|i| client.call(i).unwrap()).collect::<Vec<_>>();
+    for reply in &mut pending {
+        assert_eq!(reply.try_take(), Ok(None));
+    }
+
+    // Answers the requests in reverse order.
+    let mut requests = vec![];
+    loop {
+        match server.recv() {
+            Ok(request) => requests.push(request),
+            Err(ReceiveError::Empty) => break,
+            Err(ReceiveError::Closed) => unreachable!(),
+        }
+    }
+    assert_eq!(requests.len(), 5);
+    for (i, (req, mut handle)) in requests.into_iter().enumerate().rev() {
+        handle.reply(req * 2).unwrap();
+        assert_eq!(pending[i].try_take(), Ok(Some(req * 2)));
+        assert_eq!(pending[i].try_take(), Ok(None));
+    }
+
+    let mut reply = client.clone().call(21).unwrap();
+    let (req, mut handle) = server.recv().unwrap();
+    handle.reply(req * 2).unwrap();
+    drop(handle);
+    assert_eq!(reply.try_take(), Ok(Some(42)));
+}
+
+#[test]
+fn test_cancel() {
+    let (client, mut server) = rpc_channel::<i32, i32>();
+    let mut first = client.call(1).unwrap();
+    let mut second = client.call(2).unwrap();
+
+    let (_, handle) = server.recv().unwrap();
+    drop(handle);
+    assert_eq!(first.try_take(), Err(Canceled));
+    assert_eq!(first.try_take(), Err(Canceled));
+
+    // Dropping the server cancels the requests left in the queue.
+    drop(server);
+    assert_eq!(second.try_take(), Err(Canceled));
+    assert!(client.is_closed());
+    assert_eq!(client.call(3).unwrap_err().value, 3);
+}
+
+#[test]
+fn test_close() {
+    let (client, mut server) = rpc_channel::<i32, i32>();
+    let mut reply = client.call(1).unwrap();
+    server.close();
+    assert_eq!(client.call(2).unwrap_err().value, 2);
+
+    let (req, mut handle) = server.recv().unwrap();
+    handle.reply(req + 1).unwrap();
+    assert_eq!(reply.try_take(), Ok(Some(2)));
+    assert!(matches!(server.recv(), Err(ReceiveError::Closed)));
+}
+
+#[test]
+fn test_double_reply() {
+    let (client, mut server) = rpc_channel::<i32, String>();
+    let mut reply = client.call(1).unwrap();
+
+    let (_, mut handle) = server.recv().unwrap();
+    handle.reply("first".to_string()).unwrap();
+    let err = handle.reply("second".to_string()).unwrap_err();
+    assert!(matches!(&err, ReplyError::AlreadyReplied(resp) if resp == "second"));
+    drop(handle);
+
+    assert_eq!(reply.try_take(), Ok(Some("first".to_string())));
+}
+
+#[test]
+fn test_client_drops_pending_reply() {
+    let (client, mut server) = rpc_channel::<i32, i32>();
+    drop(client.call(1).unwrap());
+    drop(client);
+
+    let (req, mut handle) = server.recv().unwrap();
+    let err = handle.reply(req).unwrap_err();
+    assert!(matches!(err, ReplyError::Dropped(1)));
+    drop(handle);
+
+    assert!(matches!(server.recv(), Err(ReceiveError::Closed)));
+}
+
+#[test]
+fn test_reply_without_debug() {
+    struct Opaque(i32);
+
+    let (client, mut server) = rpc_channel::<(), Opaque>();
+    let mut reply = client.call(()).unwrap();
+
+    let (_, mut handle) = server.recv().unwrap();
+    handle.reply(Opaque(1)).unwrap();
+    let err = handle.reply(Opaque(2)).unwrap_err();
+    assert_eq!(format!("{err:?}"), "AlreadyReplied(..)");
+    assert_eq!(err.to_string(), "request is replied already");
+    assert!(matches!(reply.try_take(), Ok(Some(Opaque(1)))));
+}
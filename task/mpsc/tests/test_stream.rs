@@ -0,0 +1,121 @@
+#![cfg(feature = "async")]
+
+use futures_core::Stream;
+use mpsc::channel;
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+#[derive(Default)]
+struct CountingWaker {
+    wakes: AtomicUsize,
+}
+
+impl CountingWaker {
+    fn wakes(&self) -> usize {
+        self.wakes.load(Ordering::SeqCst)
+    }
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_poll_recv() {
+    let (sender, mut receiver) = channel::<i32>();
+    let mut cx = Context::from_waker(Waker::noop());
+
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+    sender.send(1).unwrap();
+    sender.send(2).unwrap();
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(Some(1)));
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(Some(2)));
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+
+    sender.send(3).unwrap();
+    drop(sender);
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(Some(3)));
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(None));
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(None));
+}
+
+#[test]
+fn test_wake_on_send() {
+    let (sender, mut receiver) = channel::<i32>();
+    let counter = Arc::new(CountingWaker::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    sender.send(1).unwrap();
+    assert_eq!(counter.wakes(), 0);
+    assert_eq!(Arc::strong_count(&counter), 2);
+
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(Some(1)));
+    assert_eq!(Arc::strong_count(&counter), 2);
+
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+    assert_eq!(Arc::strong_count(&counter), 3);
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+    assert_eq!(Arc::strong_count(&counter), 3);
+
+    sender.send(2).unwrap();
+    assert_eq!(counter.wakes(), 1);
+    assert_eq!(Arc::strong_count(&counter), 2);
+
+    sender.send(3).unwrap();
+    assert_eq!(counter.wakes(), 1);
+
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(Some(2)));
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(Some(3)));
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+    assert_eq!(counter.wakes(), 1);
+}
+
+#[test]
+fn test_wake_on_close() {
+    let (sender, mut receiver) = channel::<i32>();
+    let counter = Arc::new(CountingWaker::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let other = sender.clone();
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+
+    drop(sender);
+    assert_eq!(counter.wakes(), 0);
+    drop(other);
+    assert_eq!(counter.wakes(), 1);
+    assert_eq!(Arc::strong_count(&counter), 2);
+
+    assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(None));
+}
+
+#[test]
+fn test_stream() {
+    let (sender, receiver) = channel::<i32>();
+    let mut stream = receiver.into_stream();
+    let mut cx = Context::from_waker(Waker::noop());
+
+    assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Pending);
+    for i in 0..3 {
+        sender.send(i).unwrap();
+    }
+    drop(sender);
+
+    for i in 0..3 {
+        assert_eq!(
+            Pin::new(&mut stream).poll_next(&mut cx),
+            Poll::Ready(Some(i))
+        );
+    }
+    assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(None));
+}
@@ -1,6 +1,10 @@
-use mpsc::{channel, ReceiveError};
+use mpsc::{channel, channel_with_capacity, BoundedSendError, ReceiveError};
 
-use std::{error::Error, iter::repeat};
+use std::{
+    error::Error,
+    iter::repeat,
+    time::{Duration, Instant},
+};
 
 #[derive(Debug)]
 struct Int(usize);
@@ -112,3 +116,227 @@ fn test_same_channel() {
     assert!(!first.same_channel(&second));
     assert!(!second.same_channel(&first));
 }
+
+#[test]
+fn test_iter() {
+    let (sender, mut receiver) = channel::<Int>();
+    let second_sender = sender.clone();
+    for i in 0..5 {
+        sender.send(Int(i)).unwrap();
+    }
+    drop(sender);
+    second_sender.send(Int(5)).unwrap();
+    drop(second_sender);
+
+    let values = receiver.iter().map(|Int(i)| i).collect::<Vec<_>>();
+    assert_eq!(values, [0, 1, 2, 3, 4, 5]);
+    assert_eq!(receiver.iter().count(), 0);
+    assert!(matches!(receiver.try_recv(), Err(ReceiveError::Closed)));
+
+    let (sender, receiver) = channel::<Int>();
+    sender.send(Int(1)).unwrap();
+    sender.send(Int(2)).unwrap();
+    receiver_is_iterator(receiver, sender, [1, 2]);
+}
+
+fn receiver_is_iterator<I>(receiver: I, sender: mpsc::Sender<Int>, expected: [usize; 2])
+where
+    I: IntoIterator<Item = Int>,
+{
+    drop(sender);
+    let values = receiver.into_iter().map(|Int(i)| i).collect::<Vec<_>>();
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn test_iter_empty_open() {
+    let (sender, mut receiver) = channel::<Int>();
+    sender.send(Int(0)).unwrap();
+    assert_eq!(receiver.iter().map(|Int(i)| i).collect::<Vec<_>>(), [0]);
+
+    sender.send(Int(1)).unwrap();
+    assert_eq!(receiver.iter().map(|Int(i)| i).collect::<Vec<_>>(), [1]);
+
+    sender.send(Int(2)).unwrap();
+    let mut values = vec![];
+    for Int(i) in &mut receiver {
+        values.push(i);
+    }
+    assert_eq!(values, [2]);
+    assert!(matches!(receiver.try_recv(), Err(ReceiveError::Empty)));
+}
+
+#[test]
+fn test_try_iter() {
+    let (sender, mut receiver) = channel::<Int>();
+    for i in 0..3 {
+        sender.send(Int(i)).unwrap();
+    }
+
+    let values = receiver.try_iter().map(|Int(i)| i).collect::<Vec<_>>();
+    assert_eq!(values, [0, 1, 2]);
+    assert_eq!(receiver.try_iter().count(), 0);
+
+    // Items sent after the iterator has stopped are still there.
+    sender.send(Int(3)).unwrap();
+    sender.send(Int(4)).unwrap();
+    assert_eq!(receiver.try_iter().next().unwrap().0, 3);
+    sender.send(Int(5)).unwrap();
+    drop(sender);
+
+    let values = receiver.try_iter().map(|Int(i)| i).collect::<Vec<_>>();
+    assert_eq!(values, [4, 5]);
+    assert!(matches!(receiver.try_recv(), Err(ReceiveError::Closed)));
+}
+
+#[test]
+fn test_recv_timeout() {
+    let (sender, mut receiver) = channel::<Int>();
+    sender.send(Int(1)).unwrap();
+
+    let start = Instant::now();
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(10)).unwrap().0, 1);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let start = Instant::now();
+    let err = receiver.recv_timeout(Duration::from_secs(10)).unwrap_err();
+    assert!(matches!(err, ReceiveError::Empty));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    drop(sender);
+    let start = Instant::now();
+    let err = receiver.recv_timeout(Duration::from_secs(10)).unwrap_err();
+    assert!(matches!(err, ReceiveError::Closed));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_send_without_debug() {
+    struct NoDebug(usize);
+
+    let (sender, mut receiver) = channel::<NoDebug>();
+    sender.send(NoDebug(1)).unwrap();
+    assert_eq!(receiver.try_recv().unwrap().0, 1);
+
+    drop(receiver);
+    let err = sender.send(NoDebug(2)).unwrap_err();
+    assert_eq!(err.value.0, 2);
+    assert_eq!(err.to_string(), "channel is closed");
+}
+
+#[test]
+fn test_bounded_full() {
+    let (sender, mut receiver) = channel_with_capacity::<Int>(3);
+    assert_eq!(sender.capacity(), 3);
+    assert!(sender.is_empty() && receiver.is_empty());
+
+    for i in 0..3 {
+        sender.send(Int(i)).unwrap();
+    }
+    assert_eq!(sender.len(), 3);
+    assert_eq!(receiver.len(), 3);
+
+    let err = sender.clone().send(Int(3)).unwrap_err();
+    assert!(matches!(err, BoundedSendError::Full(Int(3))));
+    assert_eq!(err.to_string(), "channel is full");
+    assert_eq!(receiver.len(), 3);
+
+    assert_eq!(receiver.recv().unwrap().0, 0);
+    assert_eq!(sender.len(), 2);
+    sender.send(Int(4)).unwrap();
+    assert!(matches!(
+        sender.send(Int(5)),
+        Err(BoundedSendError::Full(Int(5)))
+    ));
+
+    let values = receiver.try_iter().map(|Int(i)| i).collect::<Vec<_>>();
+    assert_eq!(values, [1, 2, 4]);
+    assert!(sender.is_empty());
+    sender.send(Int(6)).unwrap();
+}
+
+#[test]
+fn test_bounded_closed_before_full() {
+    // Closed, but not dropped: the receiver keeps the elements, and senders see
+    // the channel closed rather than full.
+    let (sender, mut receiver) = channel_with_capacity::<Int>(2);
+    sender.send(Int(0)).unwrap();
+    sender.send(Int(1)).unwrap();
+    receiver.close();
+
+    assert!(sender.is_closed());
+    let err = sender.send(Int(2)).unwrap_err();
+    assert!(matches!(err, BoundedSendError::Closed(Int(2))));
+    assert_eq!(err.to_string(), "channel is closed");
+    assert_eq!(sender.len(), 0);
+    assert_eq!(receiver.len(), 2);
+
+    assert_eq!(receiver.recv().unwrap().0, 0);
+    assert!(matches!(
+        sender.send(Int(3)),
+        Err(BoundedSendError::Closed(Int(3)))
+    ));
+    assert_eq!(receiver.recv().unwrap().0, 1);
+    assert!(matches!(receiver.recv(), Err(ReceiveError::Closed)));
+
+    let (sender, mut receiver) = channel_with_capacity::<Int>(2);
+    receiver.close();
+    assert_eq!(sender.send(Int(4)).unwrap_err().into_inner().0, 4);
+}
+
+#[test]
+fn test_bounded_receiver_dropped() {
+    let (sender, receiver) = channel_with_capacity::<Int>(1);
+    sender.send(Int(0)).unwrap();
+    drop(receiver);
+
+    assert!(sender.is_closed());
+    assert!(matches!(
+        sender.send(Int(1)),
+        Err(BoundedSendError::Closed(Int(1)))
+    ));
+}
+
+#[test]
+fn test_bounded_senders_dropped() {
+    let (sender, mut receiver) = channel_with_capacity::<Int>(2);
+    let second_sender = sender.clone();
+    assert!(sender.same_channel(&second_sender));
+    sender.send(Int(0)).unwrap();
+    second_sender.send(Int(1)).unwrap();
+    drop(sender);
+    assert!(matches!(
+        second_sender.send(Int(2)),
+        Err(BoundedSendError::Full(Int(2)))
+    ));
+
+    assert_eq!(receiver.recv().unwrap().0, 0);
+    drop(second_sender);
+
+    // The last sender is gone, so the channel closes once drained.
+    assert_eq!(receiver.len(), 1);
+    assert_eq!(receiver.recv().unwrap().0, 1);
+    assert!(matches!(receiver.recv(), Err(ReceiveError::Closed)));
+    assert!(receiver.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "capacity must be positive")]
+fn test_bounded_zero_capacity() {
+    channel_with_capacity::<Int>(0);
+}
+
+#[test]
+fn test_len() {
+    let (sender, mut receiver) = channel::<Int>();
+    assert!(sender.is_empty() && receiver.is_empty());
+    sender.send(Int(0)).unwrap();
+    sender.send(Int(1)).unwrap();
+    assert_eq!((sender.len(), receiver.len()), (2, 2));
+
+    receiver.recv().unwrap();
+    assert_eq!((sender.len(), receiver.len()), (1, 1));
+
+    drop(receiver);
+    assert_eq!(sender.len(), 0);
+}
@@ -1,70 +1,257 @@
 use std::{
     collections::HashMap,
+    fmt,
     future::Future,
     io::{BufRead, Write},
+    mem,
     ops::DerefMut,
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::{
-        atomic::{AtomicU64, AtomicU8, Ordering},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering},
+        Arc, Condvar, Mutex, OnceLock,
     },
+    time::{Duration, Instant},
 };
 
 use crate::{
-    colors::{cell_color, colors_for_player, head_color},
-    state::GameState,
+    colors::{bonus_color, cell_color, colors_for_player, faded, grayed, head_color, status_color},
+    prefs::{self, Preferences},
+    state::{head_position, tick_progress, CellState, GameState},
+    summary::{GameSummary, TimelinePoint},
 };
 
 use anyhow::bail;
 use eframe::egui;
-use egui::{pos2, vec2, Align, Color32, Layout, Rect, RichText, Sense, Slider, Vec2};
+use egui::{
+    pos2, vec2, Align, Align2, Color32, Layout, ProgressBar, Rect, RichText, Sense, Slider, Stroke,
+    Vec2,
+};
 use num_traits::FromPrimitive;
 use paperio_proto::{
-    traits::{JsonRead, JsonWrite},
-    Cell, Command, Direction, Message, PlayerId, PlayerInfo,
+    traits::{Format, MessageRead, MessageWrite},
+    Cell, Command, Direction, GameParams, Message, PlayerId, PlayerInfo, World,
 };
 
-const KEY_MAP: [(egui::Key, Direction); 4] = [
-    (egui::Key::ArrowUp, Direction::Up),
-    (egui::Key::ArrowDown, Direction::Down),
-    (egui::Key::ArrowRight, Direction::Right),
-    (egui::Key::ArrowLeft, Direction::Left),
-];
+const PAUSE_KEY: egui::Key = egui::Key::Space;
+const STEP_KEY: egui::Key = egui::Key::N;
+
+/// Of the chart on the end screen, see `PaperioApp::draw_score_chart`.
+const SCORE_CHART_SIZE: Vec2 = vec2(400., 200.);
+
+/// How often a paused spectator checks whether it can read on.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The direction keys, see `PaperioApp::with_keys`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Keys {
+    #[default]
+    Arrows,
+    Wasd,
+}
+
+impl Keys {
+    fn key_map(self) -> [(egui::Key, Direction); 4] {
+        match self {
+            Self::Arrows => [
+                (egui::Key::ArrowUp, Direction::Up),
+                (egui::Key::ArrowDown, Direction::Down),
+                (egui::Key::ArrowRight, Direction::Right),
+                (egui::Key::ArrowLeft, Direction::Left),
+            ],
+            Self::Wasd => [
+                (egui::Key::W, Direction::Up),
+                (egui::Key::S, Direction::Down),
+                (egui::Key::D, Direction::Right),
+                (egui::Key::A, Direction::Left),
+            ],
+        }
+    }
+}
+
+impl FromStr for Keys {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arrows" => Ok(Self::Arrows),
+            "wasd" => Ok(Self::Wasd),
+            _ => Err(format!("unknown keys '{s}', expected 'arrows' or 'wasd'")),
+        }
+    }
+}
+
+impl fmt::Display for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Arrows => write!(f, "arrows"),
+            Self::Wasd => write!(f, "wasd"),
+        }
+    }
+}
 
 enum State {
     AwaitForGameStart,
     Tick(GameState),
-    Ended,
+    /// The final tick stays on the screen, along with the summary of the game.
+    Ended(GameState, GameSummary),
+    /// The connection has failed, the user can retry it, see `Backend::wait_for_retry`.
+    Error(String),
+}
+
+/// Pausing the view, see `PaperioApp::set_paused`.
+#[derive(Default)]
+struct Playback {
+    paused: AtomicBool,
+    /// Frames to show while paused, see `PaperioApp::step`.
+    steps: AtomicU32,
+}
+
+impl Playback {
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Whether a paused spectator should stop reading for now.
+    fn holds(&self) -> bool {
+        self.is_paused() && self.steps.load(Ordering::Relaxed) == 0
+    }
+
+    /// Whether a new frame should be buffered instead of shown. Uses up a step if there
+    /// is one.
+    fn holds_frame(&self) -> bool {
+        self.is_paused()
+            && self
+                .steps
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps| {
+                    steps.checked_sub(1)
+                })
+                .is_err()
+    }
+}
+
+#[derive(Default)]
+struct RetrySignal {
+    requested: Mutex<bool>,
+    changed: Condvar,
 }
 
 pub struct PaperioApp {
     state: Arc<Mutex<State>>,
     direction: AtomicDirection,
-    tick_duration: Arc<AtomicU64>,
+    key_map: [(egui::Key, Direction); 4],
     is_spectator: bool,
-    player_nicknames: Option<HashMap<PlayerId, PlayerInfo>>,
+    playback: Arc<Playback>,
+    tick_duration: Arc<AtomicU64>,
+    /// Unset for spectators from the start, and once my player is eliminated.
+    input_enabled: Arc<AtomicBool>,
+    win_threshold: f64,
+    format: Format,
+    /// Set by the server for spectators, see `Message::PlayerAnnouncement`.
+    player_nicknames: Arc<Mutex<Option<HashMap<PlayerId, PlayerInfo>>>>,
+    preferences: Preferences,
+    preferences_path: Option<PathBuf>,
+    /// Whether the heads move smoothly between the cells, see `state::head_position`.
+    smooth: AtomicBool,
+    /// Set on the first frame, so that the backend can repaint on new messages.
+    repaint_ctx: Arc<OnceLock<egui::Context>>,
+    retry: Arc<RetrySignal>,
+    /// Kept across connections to resynchronize on a full tick, see `Backend::run`.
+    last_params: Arc<Mutex<Option<GameParams>>>,
+    /// Where the summary has been saved to, or why it couldn't be.
+    summary_note: Mutex<Option<String>>,
 }
 
 impl PaperioApp {
-    pub fn new(tick_delay_ms: u64, is_spectator: bool) -> Self {
+    pub fn new(tick_delay_ms: u64, is_spectator: bool, win_threshold: f64) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
         Self {
             state: Arc::new(Mutex::new(State::AwaitForGameStart)),
             direction: AtomicDirection::new(Direction::Left),
-            tick_duration: Arc::new(AtomicU64::new(tick_delay_ms)),
+            key_map: Keys::default().key_map(),
             is_spectator,
-            player_nicknames: None,
+            playback: Arc::default(),
+            tick_duration: Arc::new(AtomicU64::new(tick_delay_ms)),
+            input_enabled: Arc::new(AtomicBool::new(!is_spectator)),
+            win_threshold,
+            format: Format::Json,
+            player_nicknames: Arc::default(),
+            preferences: Preferences::default(),
+            preferences_path: None,
+            smooth: AtomicBool::new(false),
+            repaint_ctx: Arc::default(),
+            retry: Arc::default(),
+            last_params: Arc::default(),
+            summary_note: Mutex::default(),
+        }
+    }
+
+    /// Preferences are written to `path` on exit and periodically while running.
+    pub fn with_preferences(mut self, preferences: Preferences, path: Option<PathBuf>) -> Self {
+        self.preferences = preferences;
+        self.preferences_path = path;
+        self
+    }
+
+    /// Must match the format the server uses for this endpoint.
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Can also be switched in the window.
+    pub fn with_smooth(mut self, smooth: bool) -> Self {
+        self.smooth = AtomicBool::new(smooth);
+        self
+    }
+
+    pub fn with_keys(mut self, keys: Keys) -> Self {
+        self.key_map = keys.key_map();
+        self
+    }
+
+    /// Toggled with Space in the window.
+    ///
+    /// Pausing only freezes the view, not the game: a paused player keeps answering
+    /// `NoOp` to every tick, since the server expects a command on each one. Only with
+    /// the server's `--turn-timeout-ms` off could it hold the game instead, and then it
+    /// would hold everyone. A paused spectator simply stops reading. The ticks received
+    /// meanwhile aren't shown, except for the latest one on resume.
+    pub fn set_paused(&self, paused: bool) {
+        self.playback.paused.store(paused, Ordering::Relaxed);
+        if !paused {
+            self.playback.steps.store(0, Ordering::Relaxed);
+            if let State::Tick(game) = self.state.lock().unwrap().deref_mut() {
+                show_buffered(game);
+            }
         }
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.playback.is_paused()
+    }
+
+    /// Done with N in the window while paused: shows the tick received since the pause,
+    /// or lets the next one through if there is none yet.
+    pub fn step(&self) {
+        if let State::Tick(game) = self.state.lock().unwrap().deref_mut() {
+            if show_buffered(game) {
+                return;
+            }
+        }
+        self.playback.steps.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn set_nicknames(&mut self, nicknames: HashMap<PlayerId, PlayerInfo>) {
-        self.player_nicknames = Some(nicknames)
+        *self.player_nicknames.lock().unwrap() = Some(nicknames)
     }
 
     fn get_nickname(&self, player_id: &PlayerId) -> String {
         self.player_nicknames
+            .lock()
+            .unwrap()
             .as_ref()
             .and_then(|nicknames| nicknames.get(player_id).map(|i| &i.user_name).cloned())
             .unwrap_or_else(|| {
@@ -75,75 +262,298 @@ impl PaperioApp {
                 }
             })
     }
-}
 
-impl PaperioApp {
+    /// Shows the waiting screen until the backend reconnects, see `Backend::wait_for_retry`.
+    fn retry(&self) {
+        *self.state.lock().unwrap() = State::AwaitForGameStart;
+        *self.summary_note.lock().unwrap() = None;
+        *self.retry.requested.lock().unwrap() = true;
+        self.retry.changed.notify_all();
+    }
+
+    /// A handle for the thread talking to the server.
+    pub fn backend(&self) -> Backend {
+        Backend {
+            state: self.state.clone(),
+            direction: self.direction.clone(),
+            is_spectator: self.is_spectator,
+            playback: self.playback.clone(),
+            tick_duration: self.tick_duration.clone(),
+            input_enabled: self.input_enabled.clone(),
+            player_nicknames: self.player_nicknames.clone(),
+            win_threshold: self.win_threshold,
+            format: self.format,
+            repaint_ctx: self.repaint_ctx.clone(),
+            retry: self.retry.clone(),
+            last_params: self.last_params.clone(),
+        }
+    }
+
     pub fn run_backend(
+        &self,
+        reader: impl BufRead + Send + 'static,
+        writer: impl Write + Send + 'static,
+    ) -> impl Future<Output = anyhow::Result<()>> {
+        self.backend().run(reader, writer)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Feeds the messages of a connection to the app, see `PaperioApp::backend`.
+pub struct Backend {
+    state: Arc<Mutex<State>>,
+    direction: AtomicDirection,
+    is_spectator: bool,
+    playback: Arc<Playback>,
+    tick_duration: Arc<AtomicU64>,
+    input_enabled: Arc<AtomicBool>,
+    player_nicknames: Arc<Mutex<Option<HashMap<PlayerId, PlayerInfo>>>>,
+    win_threshold: f64,
+    format: Format,
+    repaint_ctx: Arc<OnceLock<egui::Context>>,
+    retry: Arc<RetrySignal>,
+    last_params: Arc<Mutex<Option<GameParams>>>,
+}
+
+impl Backend {
+    fn request_repaint(&self) {
+        if let Some(ctx) = self.repaint_ctx.get() {
+            ctx.request_repaint();
+        }
+    }
+
+    /// Shows the message with a retry button instead of the game.
+    pub fn set_error(&self, message: String) {
+        *self.state.lock().unwrap() = State::Error(message);
+        self.request_repaint();
+    }
+
+    /// Blocks until the user presses the retry button.
+    pub fn wait_for_retry(&self) {
+        let requested = self.retry.requested.lock().unwrap();
+        let mut requested = self
+            .retry
+            .changed
+            .wait_while(requested, |requested| !*requested)
+            .unwrap();
+        *requested = false;
+    }
+
+    /// Plays a game over a new connection. An error is also shown in the window, see
+    /// `set_error`.
+    ///
+    /// After a reconnection in the middle of the game, the field is resynchronized on the
+    /// next `StartGame` or full tick, the deltas before it are skipped.
+    pub fn run(
         &self,
         mut reader: impl BufRead + Send + 'static,
         mut writer: impl Write + Send + 'static,
     ) -> impl Future<Output = anyhow::Result<()>> {
         let state = self.state.clone();
         let direction_store = self.direction.clone();
-        let tick_duration_store = self.tick_duration.clone();
         let is_spectator = self.is_spectator;
+        let playback = self.playback.clone();
+        let tick_duration_store = self.tick_duration.clone();
+        let input_enabled = self.input_enabled.clone();
+        let player_nicknames = self.player_nicknames.clone();
+        let win_threshold = self.win_threshold;
+        let format = self.format;
+        let last_params = self.last_params.clone();
+        let repaint_ctx = self.repaint_ctx.clone();
+        let request_repaint = move || {
+            if let Some(ctx) = repaint_ctx.get() {
+                ctx.request_repaint();
+            }
+        };
+        let game_input_enabled = input_enabled.clone();
+        let new_game = move |params| {
+            GameState::new(params, win_threshold).with_input_enabled(game_input_enabled.clone())
+        };
+
+        *state.lock().unwrap() = State::AwaitForGameStart;
+        self.request_repaint();
 
         async move {
-            // receive `GameParams` msg
-            log::info!("Waiting for the first message from server with game params");
-            let Message::StartGame(params) = reader.read_message()? else {
-                bail!("first message is not `StartGame`")
-            };
-            *state.lock().unwrap() = State::Tick(GameState::new(params));
-
-            // receive tick msgs
-            log::info!("Entering loop of receiving tick messages");
-            loop {
-                let read_message = reader.read_message()?;
-                match read_message {
-                    Message::StartGame(_) => bail!("unexpected `StartGame` message"),
-                    Message::Tick(world) => {
-                        let mut state_guard = state.lock().unwrap();
-                        match state_guard.deref_mut() {
-                            State::AwaitForGameStart => {
-                                bail!("unexpected tick while waiting for game to start")
+            let result: anyhow::Result<()> = async {
+                log::info!("Waiting for the first message from server with game params");
+                loop {
+                    while is_spectator && playback.holds() {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        std::thread::sleep(PAUSE_POLL_INTERVAL);
+                        #[cfg(target_arch = "wasm32")]
+                        gloo_timers::future::TimeoutFuture::new(
+                            PAUSE_POLL_INTERVAL.as_millis() as u32
+                        )
+                        .await;
+                    }
+
+                    let read_message = reader.read_message_as(format)?;
+                    match read_message {
+                        Message::StartGame(params) => {
+                            let mut state_guard = state.lock().unwrap();
+                            if !matches!(*state_guard, State::AwaitForGameStart) {
+                                bail!("unexpected `StartGame` message")
                             }
-                            State::Tick(game_field) => {
-                                game_field.update(world);
+                            *last_params.lock().unwrap() = Some(params);
+                            *state_guard = State::Tick(new_game(params));
+                            drop(state_guard);
+                            log::info!("Entering loop of receiving tick messages");
+                            request_repaint();
+                            continue;
+                        }
+                        Message::Tick(world) => {
+                            let mut state_guard = state.lock().unwrap();
+                            match state_guard.deref_mut() {
+                                State::AwaitForGameStart => {
+                                    let Some(params) = *last_params.lock().unwrap() else {
+                                        bail!("first message is not `StartGame`")
+                                    };
+                                    log::info!("resynchronized at tick {}", world.tick_num);
+                                    let mut game_field = new_game(params);
+                                    game_field.update(world);
+                                    game_field.arrived_at = arrival_time();
+                                    *state_guard = State::Tick(game_field);
+                                }
+                                State::Tick(game_field) => {
+                                    show_or_buffer(game_field, world, &playback)
+                                }
+                                State::Ended(..) | State::Error(_) => {
+                                    bail!("unexpected tick when game ended")
+                                }
                             }
-                            State::Ended => bail!("unexpected tick when game ended"),
+                        }
+                        Message::TickDelta(delta) => {
+                            let mut state_guard = state.lock().unwrap();
+                            match state_guard.deref_mut() {
+                                State::AwaitForGameStart
+                                    if last_params.lock().unwrap().is_some() =>
+                                {
+                                    log::debug!("skipping tick delta until the next full tick")
+                                }
+                                State::Tick(game_field) => {
+                                    // The deltas received while paused go on top of each other.
+                                    let mut world = game_field
+                                        .buffered
+                                        .as_ref()
+                                        .unwrap_or(&game_field.world)
+                                        .clone();
+                                    match world.apply_delta(&delta) {
+                                        Ok(()) => show_or_buffer(game_field, world, &playback),
+                                        // The field stays as is until the next full tick.
+                                        Err(err) => log::warn!("skipping tick delta: {err}"),
+                                    }
+                                }
+                                _ => bail!("unexpected tick delta outside of the game"),
+                            }
+                        }
+                        Message::EndGame {} => {
+                            log::info!("End game message received");
+                            let mut state_guard = state.lock().unwrap();
+                            let State::Tick(mut game_field) =
+                                mem::replace(state_guard.deref_mut(), State::AwaitForGameStart)
+                            else {
+                                bail!("unexpected `EndGame` outside of the game")
+                            };
+                            show_buffered(&mut game_field);
+                            let summary = GameSummary::new(
+                                &game_field.timelines,
+                                player_nicknames.lock().unwrap().as_ref(),
+                                game_field.world.tick_num,
+                            );
+                            *state_guard = State::Ended(game_field, summary);
+                            drop(state_guard);
+                            request_repaint();
+                            break;
+                        }
+                        // Statuses are out of band: no tick delay and no reply.
+                        Message::Status {
+                            tick_num,
+                            text,
+                            level,
+                        } => {
+                            if let State::Tick(game_field) = state.lock().unwrap().deref_mut() {
+                                game_field.push_status(tick_num, text, level);
+                            }
+                            request_repaint();
+                            continue;
+                        }
+                        Message::PlayerAnnouncement(infos) => {
+                            *player_nicknames.lock().unwrap() = Some(infos);
+                            request_repaint();
+                            continue;
+                        }
+                        Message::Unknown { message_type } => {
+                            log::warn!("skipping message of unknown type `{message_type}`");
+                            continue;
                         }
                     }
-                    Message::EndGame {} => {
-                        log::info!("End game message received");
-                        *state.lock().unwrap() = State::Ended;
-                        break;
-                    }
-                }
 
-                let tick_ms = tick_duration_store.load(Ordering::Relaxed);
+                    request_repaint();
+                    let tick_ms = tick_duration_store.load(Ordering::Relaxed);
 
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    std::thread::sleep(std::time::Duration::from_millis(tick_ms));
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        std::thread::sleep(std::time::Duration::from_millis(tick_ms));
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    gloo_timers::future::TimeoutFuture::new(tick_ms as u32).await;
+
+                    let cmd = if input_enabled.load(Ordering::Relaxed) && !playback.is_paused() {
+                        let direction = direction_store.load();
+                        Command::ChangeDirection(direction)
+                    } else {
+                        Command::NoOp
+                    };
+                    writer.write_command_as(format, &cmd)?;
+                    writer.flush()?;
                 }
-                #[cfg(target_arch = "wasm32")]
-                gloo_timers::future::TimeoutFuture::new(tick_ms as u32).await;
+                Ok(())
+            }
+            .await;
 
-                let cmd = if is_spectator {
-                    Command::NoOp
-                } else {
-                    let direction = direction_store.load();
-                    Command::ChangeDirection(direction)
-                };
-                writer.write_command(&cmd)?;
-                writer.flush()?;
+            if let Err(err) = &result {
+                log::error!("backend has failed: {err:#}");
+                *state.lock().unwrap() = State::Error(format!("{err:#}"));
+                request_repaint();
             }
-            Ok(())
+            result
         }
     }
+}
+
+impl PaperioApp {
+    /// Draws the latest statuses over the bottom left corner of the window.
+    fn draw_statuses(&self, ctx: &egui::Context, game: &GameState) {
+        let statuses = game.visible_statuses().collect::<Vec<_>>();
+        let Some(max_opacity) = statuses
+            .iter()
+            .map(|&(_, opacity)| opacity)
+            .reduce(f32::max)
+        else {
+            return;
+        };
+
+        egui::Area::new(egui::Id::new("statuses"))
+            .anchor(Align2::LEFT_BOTTOM, vec2(10., -10.))
+            .show(ctx, |ui| {
+                egui::Frame::none()
+                    .fill(Color32::from_black_alpha(160).gamma_multiply(max_opacity))
+                    .inner_margin(8.)
+                    .show(ui, |ui| {
+                        for (status, opacity) in statuses {
+                            let color = status_color(status.level).gamma_multiply(opacity);
+                            ui.label(RichText::new(&status.text).size(24.).strong().color(color));
+                        }
+                    });
+            });
+    }
 
-    fn draw_field(&self, ui: &mut egui::Ui, game: &GameState) {
+    /// The heads are drawn `progress` of the way from their previous cells to the current
+    /// ones, the rest of the field as of the current tick. The players who have lost are
+    /// faded, with a cross where their heads were, and the whole field is `grayed` once
+    /// the game has ended.
+    fn draw_field(&self, ui: &mut egui::Ui, game: &GameState, progress: f32, grayed_out: bool) {
         let params = game.params;
         let size_in_cells = vec2(params.x_cells_count as f32, params.y_cells_count as f32);
         let size_in_pixels = ui.available_size_before_wrap();
@@ -154,41 +564,263 @@ impl PaperioApp {
             ui.allocate_painter(size_in_cells * cell_size_with_border, Sense::hover());
 
         let zero_pos = ui.min_rect().min.to_vec2();
-        let draw_cell = |Cell(x, y): Cell, color: Color32| {
+        let rect_at = |(x, y): (f32, f32)| {
             // Game indexation is down-to-top, but we draw top-to-down, so invert Oy here.
-            let y = params.y_cells_count - 1 - y as u32;
-            let rect_corner = pos2(x as f32, y as f32) * cell_size_with_border + zero_pos;
-            let rect = Rect::from_min_size(rect_corner, cell_sizes);
-            painter.rect_filled(rect, 0., color);
+            let y = params.y_cells_count as f32 - 1. - y;
+            let rect_corner = pos2(x, y) * cell_size_with_border + zero_pos;
+            Rect::from_min_size(rect_corner, cell_sizes)
         };
+        let cell_rect = |Cell(x, y): Cell| rect_at((x as f32, y as f32));
+        let paint = |color: Color32| if grayed_out { grayed(color) } else { color };
+        let draw_cell = |cell: Cell, color: Color32| {
+            painter.rect_filled(cell_rect(cell), 0., paint(color));
+        };
+        let has_lost = |id: &PlayerId| game.world.players.get(id).is_some_and(|p| p.has_lost);
 
         for (y, row) in game.field.iter().enumerate() {
             for (x, c) in row.iter().enumerate() {
-                let color = cell_color(c);
+                let mut color = cell_color(c);
+                if let CellState::Captured(id) | CellState::Trace(id) = c {
+                    if has_lost(id) {
+                        color = faded(color);
+                    }
+                }
                 draw_cell(Cell(x as i32, y as i32), color)
             }
         }
+        // Smaller than a cell, so that the owner of the cell can still be seen.
+        for bonus in &game.world.bonuses {
+            let center = cell_rect(bonus.position).center();
+            painter.circle_filled(center, cell_sizes.x / 3., paint(bonus_color(bonus.kind)));
+        }
         for (id, player) in &game.world.players {
-            if !player.has_lost {
-                let color = head_color(id);
-                draw_cell(player.position, color)
+            if player.has_lost && !player.position_hidden {
+                let rect = cell_rect(player.position);
+                let stroke = Stroke::new(2., paint(head_color(id)));
+                painter.line_segment([rect.left_top(), rect.right_bottom()], stroke);
+                painter.line_segment([rect.right_top(), rect.left_bottom()], stroke);
+                continue;
+            }
+            let previous = game.previous_positions.get(id).copied();
+            if let Some(position) = head_position(previous, player, progress) {
+                painter.rect_filled(rect_at(position), 0., paint(head_color(id)));
+            }
+        }
+    }
+
+    /// A row per player seen in the game, the ones still in it first.
+    fn draw_stats(&self, ui: &mut egui::Ui, game: &GameState) {
+        let mut stats = game.stats.iter().collect::<Vec<_>>();
+        stats.sort_unstable_by(|(id1, s1), (id2, s2)| {
+            s1.lost_at
+                .is_some()
+                .cmp(&s2.lost_at.is_some())
+                .then(s2.ticks_survived.cmp(&s1.ticks_survived))
+                .then(id1.cmp(id2))
+        });
+
+        egui::Grid::new("stats").striped(true).show(ui, |ui| {
+            for header in ["", "Max cells", "Kills", "Ticks"] {
+                ui.label(RichText::new(header).strong());
+            }
+            ui.end_row();
+            for (id, stats) in stats {
+                let mut name = RichText::new(self.get_nickname(id)).color(head_color(id));
+                if stats.lost_at.is_some() {
+                    name = name.strikethrough();
+                }
+                ui.label(name);
+                ui.label(stats.max_territory.to_string());
+                ui.label(stats.kills.to_string());
+                let ticks = match stats.lost_at {
+                    Some(tick_num) => format!("{} (lost at {tick_num})", stats.ticks_survived),
+                    None => stats.ticks_survived.to_string(),
+                };
+                ui.label(ticks);
+                ui.end_row();
+            }
+        });
+    }
+
+    /// The final standings, see `GameSummary`.
+    fn draw_standings(&self, ui: &mut egui::Ui, summary: &GameSummary) {
+        egui::Grid::new("standings").striped(true).show(ui, |ui| {
+            for header in ["", "Score", "Max cells", "Captured", "Ticks"] {
+                ui.label(RichText::new(header).strong());
+            }
+            ui.end_row();
+            for standing in &summary.standings {
+                let id = &standing.player_id;
+                let mut name = RichText::new(self.get_nickname(id)).color(head_color(id));
+                if standing.eliminated_at.is_some() {
+                    name = name.strikethrough();
+                }
+                ui.label(name);
+                ui.label(standing.score.to_string());
+                ui.label(standing.peak_territory.to_string());
+                ui.label(standing.cells_captured.to_string());
+                let ticks = match standing.eliminated_at {
+                    Some(tick_num) => format!("{} (lost at {tick_num})", standing.ticks_survived),
+                    None => standing.ticks_survived.to_string(),
+                };
+                ui.label(ticks);
+                ui.end_row();
             }
+        });
+    }
+
+    /// The scores of all the players over the game, on one plot from zero to the best
+    /// score and the last tick.
+    fn draw_score_chart(&self, ui: &mut egui::Ui, summary: &GameSummary) {
+        let (rect, _) = ui.allocate_exact_size(SCORE_CHART_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(rect, 0., Stroke::new(1., Color32::GRAY));
+
+        let max_score = summary.max_score().max(1) as f32;
+        let end_tick = summary.end_tick.max(1) as f32;
+        let point_pos = |point: &TimelinePoint| {
+            pos2(
+                rect.left() + rect.width() * point.tick_num as f32 / end_tick,
+                rect.bottom() - rect.height() * point.score as f32 / max_score,
+            )
+        };
+        for standing in &summary.standings {
+            let points = standing.timeline.iter().map(point_pos).collect();
+            let stroke = Stroke::new(2., head_color(&standing.player_id));
+            painter.add(egui::Shape::line(points, stroke));
         }
+
+        let font = egui::FontId::proportional(14.);
+        painter.text(
+            rect.left_top() + vec2(4., 4.),
+            Align2::LEFT_TOP,
+            summary.max_score().to_string(),
+            font.clone(),
+            Color32::GRAY,
+        );
+        painter.text(
+            rect.right_bottom() - vec2(4., 4.),
+            Align2::RIGHT_BOTTOM,
+            format!("tick {}", summary.end_tick),
+            font,
+            Color32::GRAY,
+        );
+    }
+
+    fn draw_end_screen(&self, ui: &mut egui::Ui, game: &GameState, summary: &GameSummary) {
+        let text = match game.winner() {
+            Some(winner_id) => {
+                let score = game.world.players[&winner_id].score;
+                let text = format!("{} wins with {score}!", self.get_nickname(&winner_id));
+                RichText::new(text).color(head_color(&winner_id))
+            }
+            None => RichText::new("Game ended in a tie").color(Color32::GRAY),
+        };
+        ui.label(text.size(40.).strong());
+        ui.label(RichText::new(format!("Game ended at tick {}", game.world.tick_num)).size(20.));
+
+        ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+            self.draw_field(ui, game, 1., true);
+            ui.with_layout(Layout::top_down(Align::Min), |ui| {
+                self.draw_standings(ui, summary);
+                ui.separator();
+                self.draw_score_chart(ui, summary);
+
+                // There is no file system to write to in the browser.
+                if cfg!(not(target_arch = "wasm32")) {
+                    ui.separator();
+                    let mut note = self.summary_note.lock().unwrap();
+                    if ui.button("Save summary as JSON").clicked() {
+                        *note = Some(match summary.save(Path::new(".")) {
+                            Ok(path) => format!("Saved to {}", path.display()),
+                            Err(err) => format!("Failed to save the summary: {err}"),
+                        });
+                    }
+                    if let Some(note) = note.as_ref() {
+                        ui.label(note);
+                    }
+                }
+            });
+        });
     }
 }
 
 impl eframe::App for PaperioApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.request_repaint();
+        // The backend repaints on new messages, frames in between are only needed to
+        // animate the heads.
+        self.repaint_ctx.get_or_init(|| ctx.clone());
+        ctx.input(|i| {
+            let viewport = i.viewport();
+            if let Some(rect) = viewport.inner_rect {
+                self.preferences.window_size = Some((rect.width(), rect.height()));
+            }
+            if let Some(rect) = viewport.outer_rect {
+                self.preferences.window_position = Some((rect.min.x, rect.min.y));
+            }
+        });
         egui::CentralPanel::default().show(ctx, |ui| {
+            let mut retry = false;
+            let (mut toggle_pause, mut step) = (false, false);
             let mut state_guard = self.state.lock().unwrap();
             match state_guard.deref_mut() {
                 State::AwaitForGameStart => {
                     ui.label("Waiting to 'start_game'");
                 }
                 State::Tick(ref game) => {
+                    let tick_ms = self.tick_duration.load(Ordering::Relaxed);
+                    let progress = match game
+                        .arrived_at
+                        .filter(|_| self.smooth.load(Ordering::Relaxed))
+                    {
+                        Some(arrived_at) => {
+                            tick_progress(arrived_at.elapsed(), Duration::from_millis(tick_ms))
+                        }
+                        None => 1.,
+                    };
+                    if progress < 1. {
+                        ctx.request_repaint();
+                    }
+
+                    if let Some(leader_id) = &game.threshold_leader {
+                        let share = game.territory_shares[leader_id] * 100.;
+                        let text = format!(
+                            "{} controls {share:.1}% of the board!",
+                            self.get_nickname(leader_id)
+                        );
+                        let text = RichText::new(text)
+                            .size(40.)
+                            .strong()
+                            .color(colors_for_player(leader_id).head);
+                        ui.label(text);
+                    }
+                    if game.world.warmup {
+                        ui.label(
+                            RichText::new("Warm-up: nothing counts yet")
+                                .size(30.)
+                                .strong()
+                                .color(Color32::LIGHT_BLUE),
+                        );
+                    }
+                    if self.is_paused() {
+                        let text = format!(
+                            "Paused at tick {}: {PAUSE_KEY:?} to resume, {STEP_KEY:?} to step",
+                            game.world.tick_num
+                        );
+                        ui.label(
+                            RichText::new(text)
+                                .size(30.)
+                                .strong()
+                                .color(Color32::YELLOW),
+                        );
+                    }
+                    if let Some(tick_num) = game.eliminated_at {
+                        let text = format!("Eliminated at tick {tick_num} — spectating");
+                        ui.label(RichText::new(text).size(30.).strong().color(Color32::GRAY));
+                    }
+
                     ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
-                        self.draw_field(ui, game);
+                        self.draw_field(ui, game, progress, false);
 
                         ui.with_layout(Layout::top_down(Align::Min), |ui| {
                             let mut scores = game
@@ -205,35 +837,104 @@ impl eframe::App for PaperioApp {
                             for (id, score) in &scores {
                                 let player_name = self.get_nickname(id);
                                 let text = format!("{player_name}: {score}");
-                                let text = RichText::new(text)
-                                    .size(30.)
-                                    .color(colors_for_player(id).captured);
+                                let colors = colors_for_player(id);
+                                let text = RichText::new(text).size(30.).color(colors.captured);
                                 ui.label(text);
+
+                                let share = game.territory_shares.get(*id).copied().unwrap_or(0.);
+                                let progress_bar = ProgressBar::new(share as f32)
+                                    .text(format!("{:.1}%", share * 100.))
+                                    .fill(colors.captured)
+                                    .desired_width(200.);
+                                ui.add(progress_bar);
                             }
 
-                            let tick_ms = self.tick_duration.load(Ordering::Relaxed);
                             let mut slider_tick_ms = tick_ms;
                             ui.add(Slider::new(&mut slider_tick_ms, 0..=1000));
                             ui.label("Tick (ms)");
                             if slider_tick_ms != tick_ms {
                                 self.tick_duration.store(slider_tick_ms, Ordering::Relaxed);
                             }
+                            let mut smooth = self.smooth.load(Ordering::Relaxed);
+                            if ui.checkbox(&mut smooth, "Smooth movement").changed() {
+                                self.smooth.store(smooth, Ordering::Relaxed);
+                            }
+
+                            ui.separator();
+                            self.draw_stats(ui, game);
                         })
                     });
 
-                    for (k, d) in KEY_MAP {
-                        if ui.input(|i| i.key_pressed(k)) {
-                            self.direction.store(d);
+                    self.draw_statuses(ctx, game);
+
+                    (toggle_pause, step) =
+                        ui.input(|i| (i.key_pressed(PAUSE_KEY), i.key_pressed(STEP_KEY)));
+
+                    if self.input_enabled.load(Ordering::Relaxed) {
+                        for (k, d) in self.key_map {
+                            if ui.input(|i| i.key_pressed(k)) {
+                                self.direction.store(d);
+                            }
                         }
                     }
                 }
-                State::Ended => {
-                    ui.label("Game ended");
+                State::Ended(ref game, ref summary) => self.draw_end_screen(ui, game, summary),
+                State::Error(ref message) => {
+                    ui.label(RichText::new("Disconnected").size(40.).strong());
+                    ui.label(RichText::new(message).size(20.).color(Color32::RED));
+                    retry = ui.button("Retry").clicked();
                 }
             }
             drop(state_guard);
+            if retry {
+                self.retry();
+            }
+            if toggle_pause {
+                self.set_paused(!self.is_paused());
+            } else if step && self.is_paused() {
+                self.step();
+            }
         });
     }
+
+    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+        let Some(path) = &self.preferences_path else {
+            return;
+        };
+        self.preferences.tick_delay_ms = self.tick_duration.load(Ordering::Relaxed);
+        if let Err(err) = prefs::save(path, &self.preferences) {
+            log::warn!("failed to save preferences to {}: {err}", path.display());
+        }
+    }
+}
+
+/// Shows the world buffered while paused, if there is one.
+fn show_buffered(game: &mut GameState) -> bool {
+    let Some(world) = game.buffered.take() else {
+        return false;
+    };
+    game.update(world);
+    game.arrived_at = arrival_time();
+    true
+}
+
+fn show_or_buffer(game: &mut GameState, world: World, playback: &Playback) {
+    if playback.holds_frame() {
+        game.buffered = Some(world);
+    } else {
+        game.buffered = None;
+        game.update(world);
+        game.arrived_at = arrival_time();
+    }
+}
+
+/// `Instant` isn't available on the web, the heads jump from cell to cell there.
+fn arrival_time() -> Option<Instant> {
+    if cfg!(target_arch = "wasm32") {
+        None
+    } else {
+        Some(Instant::now())
+    }
 }
 
 struct AtomicDirection(Arc<AtomicU8>);
@@ -256,3 +957,274 @@ impl AtomicDirection {
         Self(self.0.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::connection::block_on;
+
+    use paperio_proto::{World, WorldDelta};
+
+    use std::{
+        io::{self, BufReader, BufWriter, Cursor},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    fn tick(tick_num: u32) -> Message {
+        Message::Tick(World {
+            players: HashMap::new(),
+            tick_num,
+            bonuses: vec![],
+            warmup: false,
+        })
+    }
+
+    fn delta(tick_num: u32) -> Message {
+        Message::TickDelta(WorldDelta {
+            base_tick_num: tick_num - 1,
+            tick_num,
+            players: HashMap::new(),
+            removed_players: vec![],
+            bonuses: vec![],
+            warmup: false,
+        })
+    }
+
+    /// Plays the messages to a single client, reading its commands after the frames, and
+    /// drops the connection.
+    fn run_against_mock_server(backend: &Backend, messages: Vec<Message>) -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = BufWriter::new(stream);
+            // The client may drop the connection on a message it doesn't expect.
+            for message in messages {
+                let sent = writer
+                    .write_message_as(Format::Json, &message)
+                    .and_then(|()| writer.flush());
+                if sent.is_err() {
+                    break;
+                }
+                if matches!(message, Message::Tick(_) | Message::TickDelta(_))
+                    && reader.read_command_as(Format::Json).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        let writer = BufWriter::new(stream.try_clone().unwrap());
+        let result = block_on(backend.run(BufReader::new(stream), writer));
+        server.join().unwrap();
+        result
+    }
+
+    fn shown_tick(app: &PaperioApp) -> Option<u32> {
+        match &*app.state.lock().unwrap() {
+            State::Tick(game) | State::Ended(game, _) => Some(game.world.tick_num),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn dropped_connection_shows_error() {
+        let app = PaperioApp::new(0, true, 0.5);
+        let messages = vec![Message::StartGame(Default::default()), tick(1), tick(2)];
+        assert!(run_against_mock_server(&app.backend(), messages).is_err());
+
+        let State::Error(message) = &*app.state.lock().unwrap() else {
+            panic!("error is not shown");
+        };
+        assert!(!message.is_empty());
+    }
+
+    #[test]
+    fn retry_resynchronizes_on_full_tick() {
+        let app = PaperioApp::new(0, true, 0.5);
+        let backend = app.backend();
+        let messages = vec![Message::StartGame(Default::default()), tick(1)];
+        assert!(run_against_mock_server(&backend, messages).is_err());
+
+        let waiting = thread::spawn({
+            let backend = app.backend();
+            move || backend.wait_for_retry()
+        });
+        app.retry();
+        waiting.join().unwrap();
+        assert!(matches!(
+            *app.state.lock().unwrap(),
+            State::AwaitForGameStart
+        ));
+
+        // The deltas are useless until the first full tick.
+        let messages = vec![delta(5), tick(6), delta(7), Message::EndGame {}];
+        run_against_mock_server(&backend, messages).unwrap();
+        assert!(matches!(*app.state.lock().unwrap(), State::Ended(..)));
+        assert_eq!(shown_tick(&app), Some(7));
+    }
+
+    #[test]
+    fn first_connection_needs_start_game() {
+        let app = PaperioApp::new(0, true, 0.5);
+        let err = run_against_mock_server(&app.backend(), vec![tick(1)]).unwrap_err();
+        assert!(err.to_string().contains("StartGame"));
+        assert!(matches!(*app.state.lock().unwrap(), State::Error(_)));
+    }
+
+    #[test]
+    fn keys_from_str() {
+        for keys in [Keys::Arrows, Keys::Wasd] {
+            assert_eq!(keys.to_string().parse(), Ok(keys));
+        }
+        assert_eq!("wasd".parse::<Keys>().unwrap().key_map()[0].0, egui::Key::W);
+        assert!("hjkl".parse::<Keys>().is_err());
+    }
+
+    fn script(messages: &[Message]) -> Cursor<Vec<u8>> {
+        let mut buffer = vec![];
+        for message in messages {
+            Format::Json.encode_message(message, &mut buffer).unwrap();
+        }
+        Cursor::new(buffer)
+    }
+
+    /// Calls `on_command` with every command the backend sends.
+    struct ScriptedWriter<F> {
+        buffer: Vec<u8>,
+        on_command: F,
+    }
+
+    impl<F: FnMut(Command)> ScriptedWriter<F> {
+        fn new(on_command: F) -> Self {
+            Self {
+                buffer: vec![],
+                on_command,
+            }
+        }
+    }
+
+    impl<F: FnMut(Command)> Write for ScriptedWriter<F> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let command = Cursor::new(mem::take(&mut self.buffer)).read_command_as(Format::Json)?;
+            (self.on_command)(command);
+            Ok(())
+        }
+    }
+
+    fn buffered_tick(app: &PaperioApp) -> Option<u32> {
+        match &*app.state.lock().unwrap() {
+            State::Tick(game) => game.buffered.as_ref().map(|world| world.tick_num),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn paused_player_answers_noop() {
+        let app = Arc::new(PaperioApp::new(0, false, 0.5));
+        app.set_paused(true);
+        let replies = Arc::new(Mutex::new(vec![]));
+        let writer = ScriptedWriter::new({
+            let (app, replies) = (app.clone(), replies.clone());
+            move |command| {
+                let reply = (command, shown_tick(&app), buffered_tick(&app));
+                replies.lock().unwrap().push(reply);
+            }
+        });
+        let reader = script(&[
+            Message::StartGame(Default::default()),
+            tick(1),
+            tick(2),
+            delta(3),
+            Message::EndGame {},
+        ]);
+        block_on(app.run_backend(reader, writer)).unwrap();
+
+        let replies = replies.lock().unwrap();
+        assert!(replies
+            .iter()
+            .all(|(command, _, _)| matches!(command, Command::NoOp)));
+        let ticks = replies
+            .iter()
+            .map(|&(_, shown, buffered)| (shown, buffered));
+        assert_eq!(
+            ticks.collect::<Vec<_>>(),
+            [(Some(0), Some(1)), (Some(0), Some(2)), (Some(0), Some(3))]
+        );
+        // The end screen shows the final tick anyway.
+        assert_eq!(shown_tick(&app), Some(3));
+    }
+
+    #[test]
+    fn step_and_resume() {
+        let app = Arc::new(PaperioApp::new(0, false, 0.5));
+        app.set_paused(true);
+        let replies = Arc::new(Mutex::new(vec![]));
+        let writer = ScriptedWriter::new({
+            let (app, replies) = (app.clone(), replies.clone());
+            move |command| {
+                let mut replies = replies.lock().unwrap();
+                replies.push((command, shown_tick(&app)));
+                match replies.len() {
+                    1 => app.step(),
+                    2 => app.set_paused(false),
+                    _ => {}
+                }
+            }
+        });
+        let reader = script(&[
+            Message::StartGame(Default::default()),
+            tick(1),
+            tick(2),
+            tick(3),
+            Message::EndGame {},
+        ]);
+        block_on(app.run_backend(reader, writer)).unwrap();
+
+        let replies = replies.lock().unwrap();
+        let shown = replies.iter().map(|&(_, shown)| shown).collect::<Vec<_>>();
+        assert_eq!(shown, [Some(0), Some(1), Some(3)]);
+        assert!(matches!(replies[1].0, Command::NoOp));
+        assert!(matches!(
+            replies[2].0,
+            Command::ChangeDirection(Direction::Left)
+        ));
+    }
+
+    #[test]
+    fn paused_spectator_stops_reading() {
+        let app = PaperioApp::new(0, true, 0.5);
+        app.set_paused(true);
+        // Nothing is buffered yet, so the first frame is let through.
+        app.step();
+
+        let reader = script(&[
+            Message::StartGame(Default::default()),
+            tick(1),
+            tick(2),
+            Message::EndGame {},
+        ]);
+        let backend = app.backend();
+        let spectator =
+            thread::spawn(move || block_on(backend.run(reader, ScriptedWriter::new(|_| {}))));
+
+        thread::sleep(Duration::from_millis(200));
+        assert!(matches!(*app.state.lock().unwrap(), State::Tick(_)));
+        assert_eq!(shown_tick(&app), Some(1));
+        assert_eq!(buffered_tick(&app), None);
+
+        app.set_paused(false);
+        spectator.join().unwrap().unwrap();
+        assert!(matches!(*app.state.lock().unwrap(), State::Ended(..)));
+        assert_eq!(shown_tick(&app), Some(2));
+    }
+}
@@ -1,5 +1,5 @@
 use egui::Color32;
-use paperio_proto::PlayerId;
+use paperio_proto::{BonusKind, PlayerId, StatusLevel};
 
 use crate::state::CellState;
 
@@ -59,3 +59,32 @@ pub fn cell_color(s: &CellState) -> Color32 {
         CellState::Trace(id) => colors_for_player(id).traced,
     }
 }
+
+/// The cells of the players who have lost, washed out towards the free cells.
+pub fn faded(color: Color32) -> Color32 {
+    let mix = |c: u8| ((c as u32 + 2 * 255) / 3) as u8;
+    Color32::from_rgb(mix(color.r()), mix(color.g()), mix(color.b()))
+}
+
+/// The board after the game has ended, mostly gray, but the players are still told apart.
+pub fn grayed(color: Color32) -> Color32 {
+    let (r, g, b) = (color.r() as u32, color.g() as u32, color.b() as u32);
+    let gray = (r * 30 + g * 59 + b * 11) / 100;
+    let mix = |c: u32| ((c + 3 * gray) / 4) as u8;
+    Color32::from_rgb(mix(r), mix(g), mix(b))
+}
+
+pub fn bonus_color(kind: BonusKind) -> Color32 {
+    match kind {
+        BonusKind::Nitro => Color32::from_rgb(255, 193, 7),
+        BonusKind::Slowdown => Color32::from_rgb(103, 58, 183),
+    }
+}
+
+pub fn status_color(level: StatusLevel) -> Color32 {
+    match level {
+        StatusLevel::Info => Color32::WHITE,
+        StatusLevel::Warning => Color32::from_rgb(255, 213, 79),
+        StatusLevel::Alert => Color32::from_rgb(255, 82, 82),
+    }
+}
@@ -0,0 +1,96 @@
+//! Connecting to a server that may not be up yet, see `connect_with_backoff`.
+
+use std::{
+    future::Future,
+    io,
+    net::TcpStream,
+    task::{Context, Poll},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub const INITIAL_DELAY: Duration = Duration::from_millis(50);
+pub const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// The delays between connection attempts: doubled every time, up to `MAX_DELAY`.
+pub fn backoff_delays() -> impl Iterator<Item = Duration> {
+    std::iter::successors(Some(INITIAL_DELAY), |&delay| {
+        Some((delay * 2).min(MAX_DELAY))
+    })
+}
+
+/// Retries until `timeout` runs out, then returns the last error.
+pub fn connect_with_backoff(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let deadline = Instant::now() + timeout;
+    let mut delays = backoff_delays();
+    loop {
+        let err = match TcpStream::connect(address) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => err,
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(err);
+        }
+        let delay = delays.next().unwrap().min(left);
+        log::warn!("failed to connect to {address}: {err}, retrying in {delay:.1?}");
+        thread::sleep(delay);
+    }
+}
+
+/// Runs `Backend::run` on the current thread: natively it never waits for
+/// anything but IO, which blocks.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = futures::task::noop_waker();
+    let mut ctx = Context::from_waker(&waker);
+    match future.as_mut().poll(&mut ctx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    #[test]
+    fn delays_double_up_to_the_limit() {
+        let delays = backoff_delays().take(8).collect::<Vec<_>>();
+        assert_eq!(delays[0], INITIAL_DELAY);
+        assert_eq!(delays[1], INITIAL_DELAY * 2);
+        assert_eq!(delays[2], INITIAL_DELAY * 4);
+        assert_eq!(delays[7], MAX_DELAY);
+    }
+
+    fn free_address() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[test]
+    fn waits_for_the_server() {
+        let address = free_address();
+        let server = thread::spawn({
+            let address = address.clone();
+            move || {
+                thread::sleep(Duration::from_millis(200));
+                let listener = TcpListener::bind(address).unwrap();
+                listener.accept().unwrap();
+            }
+        });
+        assert!(connect_with_backoff(&address, Duration::from_secs(5)).is_ok());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn gives_up_after_timeout() {
+        let started = Instant::now();
+        let result = connect_with_backoff(&free_address(), Duration::from_millis(300));
+        assert!(result.is_err());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(started.elapsed() < MAX_DELAY);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod app;
 mod colors;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod connection;
+pub mod prefs;
 mod state;
+pub mod summary;
@@ -1,12 +1,18 @@
 use std::{
-    future::Future,
     io::{BufReader, BufWriter},
-    net::TcpStream,
+    path::PathBuf,
     thread,
+    time::Duration,
 };
 
+use anyhow::Context;
 use clap::Parser;
-use paperio_gui::app::PaperioApp;
+use paperio_gui::{
+    app::{Backend, Keys, PaperioApp},
+    connection,
+    prefs::{self, Overrides},
+};
+use paperio_proto::traits::Format;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -15,10 +21,32 @@ struct Arguments {
     address: String,
     #[arg(short, long, default_value_t = 8000)]
     port: u16,
-    #[arg(short, long, default_value_t = 120)]
-    tick_delay_ms: u64,
+    /// How long to keep trying to connect to the server, in seconds.
+    #[arg(long, default_value_t = 10)]
+    connect_timeout: u64,
+    /// Overrides the saved value, 120 by default.
+    #[arg(short, long)]
+    tick_delay_ms: Option<u64>,
     #[arg(short, long, action)]
     spectator: bool,
+    /// The format the server uses for this endpoint: json or binary.
+    #[arg(long, default_value_t = Format::Json)]
+    format: Format,
+    /// Share of the board after which the leading player is announced.
+    #[arg(long, default_value_t = 0.5)]
+    win_threshold: f64,
+    /// Directory of the preferences file instead of the platform default.
+    #[arg(long)]
+    config_dir: Option<PathBuf>,
+    /// Delete the saved preferences and start with the defaults.
+    #[arg(long, action)]
+    reset_prefs: bool,
+    /// Move the heads smoothly between the cells instead of jumping on every tick.
+    #[arg(long, action)]
+    smooth: bool,
+    /// The direction keys: arrows or wasd. Space pauses the view, N steps through it.
+    #[arg(long, default_value_t = Keys::Arrows)]
+    keys: Keys,
 }
 
 fn main() {
@@ -27,33 +55,79 @@ fn main() {
     stderrlog::new()
         .verbosity(log::Level::Debug)
         .module(module_path!())
+        .module("paperio_gui")
         .init()
         .expect("failed to initialize stderr logger");
 
-    let stream = TcpStream::connect(format!("{}:{}", args.address, args.port))
-        .expect("failed to connect to tcp socket");
-    let stream_clone = stream.try_clone().expect("failed to clone tcp stream");
+    let preferences_path = args
+        .config_dir
+        .clone()
+        .or_else(prefs::default_config_dir)
+        .map(|dir| dir.join(prefs::PREFERENCES_FILE_NAME));
+    if args.reset_prefs {
+        if let Some(path) = &preferences_path {
+            if let Err(err) = prefs::reset(path) {
+                log::warn!("failed to delete preferences {}: {err}", path.display());
+            }
+        }
+    }
+    let preferences = preferences_path
+        .as_deref()
+        .map(prefs::load)
+        .unwrap_or_default()
+        .merged(&Overrides {
+            tick_delay_ms: args.tick_delay_ms,
+        });
 
     // run gui in current thread
+    let window_size = preferences.window_size_or_default();
+    let window_position = preferences.window_position;
     let native_options = eframe::NativeOptions {
-        window_builder: Some(Box::new(|b| b.with_inner_size((1200., 980.)))),
+        window_builder: Some(Box::new(move |b| {
+            let b = b.with_inner_size(window_size);
+            match window_position {
+                Some(position) => b.with_position(position),
+                None => b,
+            }
+        })),
+        // The geometry is kept in our own preferences file.
+        persist_window: false,
         ..Default::default()
     };
-    let app = PaperioApp::new(args.tick_delay_ms, args.spectator);
-    let reader = BufReader::new(stream);
-    let writer = BufWriter::new(stream_clone);
-    let mut backend_future = Box::pin(app.run_backend(reader, writer));
-    let handle = thread::spawn(move || {
-        let waker = futures::task::noop_waker();
-        let mut ctx = futures::task::Context::from_waker(&waker);
-        match backend_future.as_mut().poll(&mut ctx) {
-            std::task::Poll::Ready(result) => {
-                log::info!("result: {result:?}");
-                result
-            }
-            std::task::Poll::Pending => unreachable!(),
+    let app = PaperioApp::new(
+        preferences.tick_delay_ms,
+        args.spectator,
+        args.win_threshold,
+    )
+    .with_preferences(preferences, preferences_path)
+    .with_format(args.format)
+    .with_smooth(args.smooth)
+    .with_keys(args.keys);
+
+    // The window shows the error and waits for a retry if the connection fails, the
+    // thread ends with the game.
+    let backend = app.backend();
+    let address = format!("{}:{}", args.address, args.port);
+    let connect_timeout = Duration::from_secs(args.connect_timeout);
+    thread::spawn(move || loop {
+        match connect_and_run(&backend, &address, connect_timeout) {
+            Ok(()) => break,
+            Err(err) => backend.set_error(format!("{err:#}")),
         }
+        backend.wait_for_retry();
     });
     eframe::run_native("paperio", native_options, Box::new(|_| Ok(Box::new(app)))).unwrap();
-    handle.join().unwrap().unwrap();
+}
+
+fn connect_and_run(
+    backend: &Backend,
+    address: &str,
+    connect_timeout: Duration,
+) -> anyhow::Result<()> {
+    let stream = connection::connect_with_backoff(address, connect_timeout)
+        .with_context(|| format!("failed to connect to {address}"))?;
+    let writer = BufWriter::new(stream.try_clone().context("failed to clone tcp stream")?);
+    let result = connection::block_on(backend.run(BufReader::new(stream), writer));
+    log::info!("result: {result:?}");
+    result
 }
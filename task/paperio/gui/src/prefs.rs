@@ -0,0 +1,228 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub const PREFERENCES_FILE_NAME: &str = "gui.json";
+pub const DEFAULT_TICK_DELAY_MS: u64 = 120;
+pub const DEFAULT_WINDOW_SIZE: (f32, f32) = (1200., 980.);
+
+/// User preferences persisted between sessions. Missing fields take default values,
+/// so that files written by older versions still load.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Preferences {
+    pub tick_delay_ms: u64,
+    /// Inner size of the window in logical points.
+    pub window_size: Option<(f32, f32)>,
+    /// Position of the outer top-left corner of the window.
+    pub window_position: Option<(f32, f32)>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            tick_delay_ms: DEFAULT_TICK_DELAY_MS,
+            window_size: None,
+            window_position: None,
+        }
+    }
+}
+
+/// Values given on the command line, they take precedence over the saved ones.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    pub tick_delay_ms: Option<u64>,
+}
+
+impl Preferences {
+    pub fn merged(self, overrides: &Overrides) -> Self {
+        Self {
+            tick_delay_ms: overrides.tick_delay_ms.unwrap_or(self.tick_delay_ms),
+            ..self
+        }
+    }
+
+    /// Saved size if it is usable, the default one otherwise.
+    pub fn window_size_or_default(&self) -> (f32, f32) {
+        self.window_size
+            .filter(|&(width, height)| width >= 1. && height >= 1.)
+            .unwrap_or(DEFAULT_WINDOW_SIZE)
+    }
+
+    pub fn from_json(content: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(content)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("preferences are always serializable")
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Platform-specific directory for the config files of the gui.
+pub fn default_config_dir() -> Option<PathBuf> {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(target_os = "windows") {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    base.map(|dir| dir.join("paperio"))
+}
+
+/// Never fails: a missing file silently gives the defaults, an unreadable or corrupt
+/// one gives them with a warning.
+pub fn load(path: &Path) -> Preferences {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Preferences::default(),
+        Err(err) => {
+            log::warn!("failed to read preferences from {}: {err}", path.display());
+            return Preferences::default();
+        }
+    };
+    match Preferences::from_json(&content) {
+        Ok(preferences) => preferences,
+        Err(err) => {
+            log::warn!(
+                "ignoring corrupt preferences file {}: {err}",
+                path.display()
+            );
+            Preferences::default()
+        }
+    }
+}
+
+/// Writes to a temporary file first, so that a crash never leaves a truncated file.
+pub fn save(path: &Path, preferences: &Preferences) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, preferences.to_json())?;
+    fs::rename(&tmp_path, path)
+}
+
+pub fn reset(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("paperio-gui-prefs-{}-{name}", std::process::id()))
+            .join(PREFERENCES_FILE_NAME)
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let preferences = Preferences {
+            tick_delay_ms: 40,
+            window_size: Some((800., 600.)),
+            window_position: Some((1920., 0.)),
+        };
+        assert_eq!(
+            Preferences::from_json(&preferences.to_json()).unwrap(),
+            preferences
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_defaulted() {
+        let preferences = Preferences::from_json(r#"{"tick_delay_ms": 40}"#).unwrap();
+        assert_eq!(
+            preferences,
+            Preferences {
+                tick_delay_ms: 40,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        let from_file = Preferences {
+            tick_delay_ms: 40,
+            window_size: Some((800., 600.)),
+            window_position: None,
+        };
+
+        let merged = Preferences::default().merged(&Overrides::default());
+        assert_eq!(merged, Preferences::default());
+
+        let merged = from_file.clone().merged(&Overrides::default());
+        assert_eq!(merged, from_file);
+
+        let merged = from_file.clone().merged(&Overrides {
+            tick_delay_ms: Some(500),
+        });
+        assert_eq!(merged.tick_delay_ms, 500);
+        assert_eq!(merged.window_size, from_file.window_size);
+    }
+
+    #[test]
+    fn window_size_fallback() {
+        let mut preferences = Preferences::default();
+        assert_eq!(preferences.window_size_or_default(), DEFAULT_WINDOW_SIZE);
+        preferences.window_size = Some((0., 600.));
+        assert_eq!(preferences.window_size_or_default(), DEFAULT_WINDOW_SIZE);
+        preferences.window_size = Some((800., 600.));
+        assert_eq!(preferences.window_size_or_default(), (800., 600.));
+    }
+
+    #[test]
+    fn save_load_reset() {
+        let path = temp_path("save");
+        let preferences = Preferences {
+            tick_delay_ms: 40,
+            ..Default::default()
+        };
+
+        save(&path, &preferences).unwrap();
+        assert_eq!(load(&path), preferences);
+
+        reset(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(load(&path), Preferences::default());
+        reset(&path).unwrap();
+
+        fs::remove_dir(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_gives_defaults() {
+        let path = temp_path("corrupt");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        for content in [
+            "",
+            "{",
+            "not json",
+            r#"{"tick_delay_ms": "fast"}"#,
+            "[1, 2]",
+        ] {
+            fs::write(&path, content).unwrap();
+            assert_eq!(load(&path), Preferences::default(), "{content:?}");
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,4 +1,28 @@
-use paperio_proto::{Cell, GameParams, PlayerId, World};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use paperio_proto::{Cell, GameParams, Player, PlayerId, StatusLevel, World};
+
+use crate::summary::{Timeline, TimelinePoint};
+
+/// Statuses are shown at full opacity for this many ticks...
+pub const STATUS_HOLD_TICKS: u32 = 20;
+/// ...and then fade out over this many.
+pub const STATUS_FADE_TICKS: u32 = 10;
+/// Only this many of the latest statuses are kept.
+pub const MAX_STATUSES: usize = 4;
+
+/// The id the server gives to the player the client controls.
+const MY_ID: &str = "i";
+/// Heads which have moved farther between two ticks than nitro moves them aren't
+/// animated, e.g. the ones which were hidden in the fog.
+const MAX_ANIMATED_DISTANCE: i32 = 2;
 
 #[derive(Debug, Clone)]
 pub enum CellState {
@@ -7,14 +31,55 @@ pub enum CellState {
     Trace(PlayerId),
 }
 
+/// What has happened to a player over the game, see `GameState::update`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    /// In cells.
+    pub max_territory: usize,
+    /// Players who have lost crossing the trace of this one or encircled by it, see
+    /// `killer_of`.
+    pub kills: u32,
+    /// From the first tick the player was seen on to the last one it was in the game on.
+    pub ticks_survived: u32,
+    pub lost_at: Option<u32>,
+    first_tick: u32,
+}
+
 pub struct GameState {
     pub params: GameParams,
     pub field: Vec<Vec<CellState>>,
     pub world: World,
+    /// Share of the board captured by every player, from 0 to 1.
+    pub territory_shares: HashMap<PlayerId, f64>,
+    /// The player whose territory share has crossed the win threshold.
+    pub threshold_leader: Option<PlayerId>,
+    /// The latest statuses, oldest first.
+    pub statuses: VecDeque<Status>,
+    /// The tick my player was eliminated on. Once set, it stays so until the game ends.
+    pub eliminated_at: Option<u32>,
+    /// The heads of the players in the game on the previous tick, see `head_position`.
+    pub previous_positions: HashMap<PlayerId, Cell>,
+    /// When the current world has arrived, set by the backend.
+    pub arrived_at: Option<Instant>,
+    /// The latest world received while the view is paused, shown on resume.
+    pub buffered: Option<World>,
+    /// Every player seen in the game, including the ones which are gone.
+    pub stats: HashMap<PlayerId, PlayerStats>,
+    /// Of the same players, for `GameSummary`.
+    pub timelines: HashMap<PlayerId, Timeline>,
+    win_threshold: f64,
+    /// Cleared on elimination, so that the backend stops sending directions.
+    input_enabled: Arc<AtomicBool>,
+}
+
+pub struct Status {
+    pub tick_num: u32,
+    pub text: String,
+    pub level: StatusLevel,
 }
 
 impl GameState {
-    pub fn new(params: GameParams) -> Self {
+    pub fn new(params: GameParams, win_threshold: f64) -> Self {
         let cells = vec![
             vec![CellState::Free; params.x_cells_count as usize];
             params.y_cells_count as usize
@@ -25,10 +90,28 @@ impl GameState {
             world: World {
                 players: Default::default(),
                 tick_num: 0,
+                bonuses: vec![],
+                warmup: false,
             },
+            territory_shares: HashMap::new(),
+            threshold_leader: None,
+            statuses: VecDeque::new(),
+            eliminated_at: None,
+            previous_positions: HashMap::new(),
+            arrived_at: None,
+            buffered: None,
+            stats: HashMap::new(),
+            timelines: HashMap::new(),
+            win_threshold,
+            input_enabled: Arc::new(AtomicBool::new(true)),
         }
     }
 
+    pub fn with_input_enabled(mut self, input_enabled: Arc<AtomicBool>) -> Self {
+        self.input_enabled = input_enabled;
+        self
+    }
+
     fn clear_field(&mut self) {
         for row in &mut self.field {
             for cell in row {
@@ -49,6 +132,592 @@ impl GameState {
                 self.field[y as usize][x as usize] = CellState::Trace(id.clone());
             }
         }
+        self.territory_shares = world
+            .players
+            .iter()
+            .map(|(id, p)| (id.clone(), territory_share(p, &self.params)))
+            .collect();
+        self.threshold_leader = threshold_leader(&self.territory_shares, self.win_threshold);
+        if self.eliminated_at.is_none() && is_eliminated(&self.world, &world) {
+            self.eliminated_at = Some(world.tick_num);
+            self.input_enabled.store(false, Ordering::Relaxed);
+        }
+        self.update_stats(&world);
+        self.previous_positions = self
+            .world
+            .players
+            .iter()
+            .filter(|(_, p)| !p.has_lost && !p.position_hidden)
+            .map(|(id, p)| (id.clone(), p.position))
+            .collect();
         self.world = world;
     }
 }
+
+/// Whether my player has lost on the `current` tick: either it is marked so, or it was
+/// in the game on the `previous` one and has disappeared. Spectators have no player,
+/// so they are never eliminated.
+fn is_eliminated(previous: &World, current: &World) -> bool {
+    has_lost_on(previous, current, MY_ID)
+}
+
+/// Whether the player has lost on the `current` tick, see `is_eliminated`.
+fn has_lost_on(previous: &World, current: &World, id: &str) -> bool {
+    match current.players.get(id) {
+        Some(player) => player.has_lost,
+        None => previous
+            .players
+            .get(id)
+            .is_some_and(|player| !player.has_lost),
+    }
+}
+
+/// Guesses who has made the player `loser_id` lose on the `current` tick, as the world
+/// doesn't tell: the player whose head has got on its trace, or else the one whose
+/// territory has got its head. Nobody for the ones which have run into the border or
+/// their own trace.
+pub fn killer_of(previous: &World, current: &World, loser_id: &str) -> Option<PlayerId> {
+    let loser = previous.players.get(loser_id)?;
+    let mut others = current
+        .players
+        .iter()
+        .filter(|(id, p)| *id != loser_id && !p.has_lost && !p.position_hidden)
+        .collect::<Vec<_>>();
+    others.sort_unstable_by_key(|(id, _)| *id);
+    others
+        .iter()
+        .find(|(_, p)| loser.lines.contains(&p.position))
+        .or_else(|| {
+            others
+                .iter()
+                .find(|(_, p)| p.territory.contains(&loser.position))
+        })
+        .map(|(id, _)| (*id).clone())
+}
+
+impl GameState {
+    fn update_stats(&mut self, world: &World) {
+        for (id, player) in &world.players {
+            let stats = self.stats.entry(id.clone()).or_insert_with(|| PlayerStats {
+                first_tick: world.tick_num,
+                ..PlayerStats::default()
+            });
+            stats.max_territory = stats.max_territory.max(player.territory.len());
+            if !player.has_lost {
+                stats.ticks_survived = world.tick_num.saturating_sub(stats.first_tick);
+            }
+            self.timelines
+                .entry(id.clone())
+                .or_default()
+                .points
+                .push(TimelinePoint {
+                    tick_num: world.tick_num,
+                    score: player.score,
+                    territory: player.territory.len(),
+                });
+        }
+
+        let losers = self
+            .stats
+            .iter()
+            .filter(|(id, stats)| stats.lost_at.is_none() && has_lost_on(&self.world, world, id))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for loser_id in losers {
+            self.stats.get_mut(&loser_id).unwrap().lost_at = Some(world.tick_num);
+            self.timelines.get_mut(&loser_id).unwrap().eliminated_at = Some(world.tick_num);
+            if let Some(killer_id) = killer_of(&self.world, world, &loser_id) {
+                self.stats.get_mut(&killer_id).unwrap().kills += 1;
+            }
+        }
+    }
+
+    /// The player whose territory has crossed the win threshold, or else the one with
+    /// the highest score. Nobody on a tie.
+    pub fn winner(&self) -> Option<PlayerId> {
+        if let Some(leader_id) = &self.threshold_leader {
+            return Some(leader_id.clone());
+        }
+        let max_score = self.world.players.values().map(|p| p.score).max()?;
+        let mut leaders = self
+            .world
+            .players
+            .iter()
+            .filter(|(_, p)| p.score == max_score);
+        let (leader_id, _) = leaders.next()?;
+        leaders.next().is_none().then(|| leader_id.clone())
+    }
+}
+
+impl GameState {
+    pub fn push_status(&mut self, tick_num: u32, text: String, level: StatusLevel) {
+        if self.statuses.len() == MAX_STATUSES {
+            self.statuses.pop_front();
+        }
+        self.statuses.push_back(Status {
+            tick_num,
+            text,
+            level,
+        });
+    }
+
+    /// Statuses that haven't faded out yet as of the current tick, with their opacities.
+    pub fn visible_statuses(&self) -> impl Iterator<Item = (&Status, f32)> {
+        let current_tick = self.world.tick_num;
+        self.statuses.iter().filter_map(move |status| {
+            status_opacity(current_tick, status.tick_num).map(|opacity| (status, opacity))
+        })
+    }
+}
+
+/// Opacity of a status sent on `message_tick` as of `current_tick`, from 0 to 1: full
+/// for `STATUS_HOLD_TICKS`, then decreasing linearly. `None` once it has faded out.
+/// Statuses from the future (sent before their tick is shown) are fully opaque.
+pub fn status_opacity(current_tick: u32, message_tick: u32) -> Option<f32> {
+    let age = current_tick.saturating_sub(message_tick);
+    if age < STATUS_HOLD_TICKS {
+        return Some(1.);
+    }
+    let fade_age = age - STATUS_HOLD_TICKS;
+    (fade_age < STATUS_FADE_TICKS).then(|| 1. - fade_age as f32 / STATUS_FADE_TICKS as f32)
+}
+
+/// The share of the tick interval elapsed since the world has arrived, from 0 to 1.
+/// Without an interval there is nothing to animate.
+pub fn tick_progress(elapsed: Duration, tick_interval: Duration) -> f32 {
+    if tick_interval.is_zero() {
+        return 1.;
+    }
+    (elapsed.as_secs_f32() / tick_interval.as_secs_f32()).min(1.)
+}
+
+/// Where to draw the head of the player in cells, `progress` of the way from its
+/// `previous` cell to the current one. Eliminated and hidden heads aren't drawn, and
+/// the ones without a previous cell close by are drawn where they are.
+pub fn head_position(previous: Option<Cell>, player: &Player, progress: f32) -> Option<(f32, f32)> {
+    if player.has_lost || player.position_hidden {
+        return None;
+    }
+    let Cell(x, y) = player.position;
+    let Some(from) =
+        previous.filter(|&from| from.distance_to(player.position) <= MAX_ANIMATED_DISTANCE)
+    else {
+        return Some((x as f32, y as f32));
+    };
+    let progress = progress.clamp(0., 1.);
+    let lerp = |from: i32, to: i32| from as f32 + (to - from) as f32 * progress;
+    Some((lerp(from.0, x), lerp(from.1, y)))
+}
+
+pub fn territory_share(player: &Player, params: &GameParams) -> f64 {
+    let area = params.x_cells_count * params.y_cells_count;
+    player.territory.len() as f64 / area as f64
+}
+
+/// Returns the player with the largest territory share if it is at least `threshold`.
+/// Ties are resolved in favor of the smallest id.
+pub fn threshold_leader(shares: &HashMap<PlayerId, f64>, threshold: f64) -> Option<PlayerId> {
+    shares
+        .iter()
+        .filter(|(_, &share)| share > 0. && share >= threshold)
+        .max_by(|(id1, s1), (id2, s2)| s1.total_cmp(s2).then(id2.cmp(id1)))
+        .map(|(id, _)| id.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: GameParams = GameParams {
+        x_cells_count: 10,
+        y_cells_count: 10,
+    };
+
+    fn player(territory_size: i32, has_lost: bool) -> Player {
+        Player {
+            score: 0,
+            territory: (0..territory_size).map(|i| Cell(i % 10, i / 10)).collect(),
+            position: Cell(0, 0),
+            lines: vec![],
+            direction: None,
+            has_lost,
+            position_hidden: false,
+        }
+    }
+
+    fn world(players: &[(&str, Player)]) -> World {
+        World {
+            players: players
+                .iter()
+                .map(|(id, p)| (id.to_string(), p.clone()))
+                .collect(),
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    fn shares(pairs: &[(&str, f64)]) -> HashMap<PlayerId, f64> {
+        pairs.iter().map(|&(id, s)| (id.to_string(), s)).collect()
+    }
+
+    #[test]
+    fn territory_share_math() {
+        assert_eq!(territory_share(&player(0, false), &PARAMS), 0.);
+        assert_eq!(territory_share(&player(25, false), &PARAMS), 0.25);
+        assert_eq!(territory_share(&player(100, false), &PARAMS), 1.);
+        assert_eq!(territory_share(&player(0, true), &PARAMS), 0.);
+    }
+
+    #[test]
+    fn update_computes_shares() {
+        let mut state = GameState::new(PARAMS, 0.5);
+        state.update(world(&[
+            ("i", player(30, false)),
+            ("2", player(9, false)),
+            ("3", player(0, true)),
+        ]));
+
+        assert_eq!(
+            state.territory_shares,
+            shares(&[("i", 0.3), ("2", 0.09), ("3", 0.)])
+        );
+        assert_eq!(state.threshold_leader, None);
+
+        state.update(world(&[("i", player(50, false)), ("3", player(0, true))]));
+        assert_eq!(state.territory_shares, shares(&[("i", 0.5), ("3", 0.)]));
+        assert_eq!(state.threshold_leader, Some("i".to_string()));
+    }
+
+    fn world_at(tick_num: u32, players: &[(&str, Player)]) -> World {
+        World {
+            tick_num,
+            ..world(players)
+        }
+    }
+
+    #[test]
+    fn elimination_is_latched() {
+        let input_enabled = Arc::new(AtomicBool::new(true));
+        let mut state = GameState::new(PARAMS, 0.5).with_input_enabled(input_enabled.clone());
+        let playing = [("i", player(9, false)), ("2", player(9, false))];
+        for tick_num in [1, 7, 13] {
+            state.update(world_at(tick_num, &playing));
+            assert_eq!(state.eliminated_at, None);
+            assert!(input_enabled.load(Ordering::Relaxed));
+        }
+
+        let lost = [("i", player(0, true)), ("2", player(9, false))];
+        state.update(world_at(19, &lost));
+        assert_eq!(state.eliminated_at, Some(19));
+        assert!(!input_enabled.load(Ordering::Relaxed));
+
+        // The server may then drop the player, or even report it alive again.
+        state.update(world_at(25, &[("2", player(20, false))]));
+        state.update(world_at(31, &playing));
+        assert_eq!(state.eliminated_at, Some(19));
+        assert!(!input_enabled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn elimination_by_disappearance() {
+        let mut state = GameState::new(PARAMS, 0.5);
+        state.update(world_at(
+            1,
+            &[("i", player(9, false)), ("2", player(9, false))],
+        ));
+        state.update(world_at(7, &[("2", player(9, false))]));
+        assert_eq!(state.eliminated_at, Some(7));
+
+        // Spectators never had a player of their own.
+        let input_enabled = Arc::new(AtomicBool::new(false));
+        let mut state = GameState::new(PARAMS, 0.5).with_input_enabled(input_enabled.clone());
+        for tick_num in [1, 7, 13] {
+            state.update(world_at(tick_num, &[("2", player(9, false))]));
+        }
+        state.update(world_at(19, &[]));
+        assert_eq!(state.eliminated_at, None);
+        assert!(!input_enabled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn progress_of_tick() {
+        let tick = Duration::from_millis(120);
+        assert_eq!(tick_progress(Duration::ZERO, tick), 0.);
+        assert_eq!(tick_progress(Duration::from_millis(30), tick), 0.25);
+        assert_eq!(tick_progress(tick, tick), 1.);
+        assert_eq!(tick_progress(Duration::from_secs(5), tick), 1.);
+        assert_eq!(tick_progress(Duration::from_millis(1), Duration::ZERO), 1.);
+    }
+
+    fn head(position: Cell) -> Player {
+        Player {
+            position,
+            ..player(0, false)
+        }
+    }
+
+    #[test]
+    fn head_interpolation() {
+        let player = head(Cell(5, 3));
+        assert_eq!(head_position(Some(Cell(4, 3)), &player, 0.), Some((4., 3.)));
+        assert_eq!(
+            head_position(Some(Cell(4, 3)), &player, 0.5),
+            Some((4.5, 3.))
+        );
+        assert_eq!(
+            head_position(Some(Cell(5, 4)), &player, 0.25),
+            Some((5., 3.75))
+        );
+        assert_eq!(
+            head_position(Some(Cell(5, 3)), &player, 0.5),
+            Some((5., 3.))
+        );
+        // Two cells with nitro.
+        assert_eq!(
+            head_position(Some(Cell(5, 5)), &player, 0.5),
+            Some((5., 4.))
+        );
+
+        // Clamped, so the head never runs past its cell or behind the previous one.
+        assert_eq!(
+            head_position(Some(Cell(4, 3)), &player, 1.5),
+            Some((5., 3.))
+        );
+        assert_eq!(
+            head_position(Some(Cell(4, 3)), &player, -1.),
+            Some((4., 3.))
+        );
+
+        // Appeared or came out of the fog far away.
+        assert_eq!(head_position(None, &player, 0.5), Some((5., 3.)));
+        assert_eq!(
+            head_position(Some(Cell(0, 0)), &player, 0.5),
+            Some((5., 3.))
+        );
+    }
+
+    #[test]
+    fn eliminated_heads_are_not_animated() {
+        let mut lost = head(Cell(5, 3));
+        lost.has_lost = true;
+        assert_eq!(head_position(Some(Cell(4, 3)), &lost, 0.5), None);
+
+        let mut hidden = head(Cell::HIDDEN);
+        hidden.position_hidden = true;
+        assert_eq!(head_position(Some(Cell(4, 3)), &hidden, 0.5), None);
+
+        let mut state = GameState::new(PARAMS, 0.5);
+        let mut eliminated = head(Cell(2, 2));
+        eliminated.has_lost = true;
+        state.update(world(&[
+            ("i", head(Cell(1, 1))),
+            ("2", eliminated),
+            ("3", hidden),
+        ]));
+        state.update(world(&[("i", head(Cell(1, 2)))]));
+        assert_eq!(
+            state.previous_positions,
+            HashMap::from([("i".to_string(), Cell(1, 1))])
+        );
+    }
+
+    fn at(position: Cell, lines: &[Cell], territory_size: i32) -> Player {
+        Player {
+            position,
+            lines: lines.to_vec(),
+            ..player(territory_size, false)
+        }
+    }
+
+    #[test]
+    fn stats_accumulate() {
+        let mut state = GameState::new(PARAMS, 0.5);
+        state.update(world_at(
+            3,
+            &[("i", player(9, false)), ("2", player(9, false))],
+        ));
+        state.update(world_at(
+            9,
+            &[("i", player(20, false)), ("2", at(Cell(9, 9), &[], 12))],
+        ));
+        state.update(world_at(
+            15,
+            &[("i", player(14, false)), ("2", player(0, true))],
+        ));
+        // Joined late, and the lost player is gone.
+        state.update(world_at(
+            21,
+            &[("i", player(16, false)), ("3", player(5, false))],
+        ));
+
+        let stats = |id: &str| state.stats[id].clone();
+        assert_eq!(stats("i").max_territory, 20);
+        assert_eq!(stats("i").ticks_survived, 18);
+        assert_eq!(stats("i").lost_at, None);
+        assert_eq!(stats("2").max_territory, 12);
+        assert_eq!(stats("2").ticks_survived, 6);
+        assert_eq!(stats("2").lost_at, Some(15));
+        assert_eq!(stats("3").ticks_survived, 0);
+        assert_eq!(state.stats.len(), 3);
+        // Player 2 has just run into something.
+        assert!(state.stats.values().all(|stats| stats.kills == 0));
+    }
+
+    #[test]
+    fn timelines_accumulate() {
+        let mut state = GameState::new(PARAMS, 0.5);
+        let scored = |score, territory_size| Player {
+            score,
+            ..player(territory_size, false)
+        };
+        state.update(world_at(1, &[("i", scored(0, 9)), ("2", scored(0, 9))]));
+        state.update(world_at(2, &[("i", scored(4, 13)), ("2", player(0, true))]));
+        state.update(world_at(3, &[("i", scored(4, 13))]));
+
+        let points = |id: &str| {
+            state.timelines[id]
+                .points
+                .iter()
+                .map(|point| (point.tick_num, point.score, point.territory))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(points("i"), [(1, 0, 9), (2, 4, 13), (3, 4, 13)]);
+        assert_eq!(points("2"), [(1, 0, 9), (2, 0, 0)]);
+        assert_eq!(state.timelines["i"].eliminated_at, None);
+        assert_eq!(state.timelines["2"].eliminated_at, Some(2));
+    }
+
+    #[test]
+    fn kills_are_credited() {
+        let mut state = GameState::new(PARAMS, 0.5);
+        let trace = [Cell(5, 5), Cell(5, 6), Cell(5, 7)];
+        state.update(world_at(
+            1,
+            &[
+                ("i", at(Cell(4, 6), &[], 9)),
+                ("2", at(Cell(5, 7), &trace, 9)),
+                ("3", at(Cell(8, 8), &[], 9)),
+            ],
+        ));
+
+        // I cross the trace of player 2.
+        let mut lost = at(Cell(5, 7), &trace, 0);
+        lost.has_lost = true;
+        state.update(world_at(
+            2,
+            &[
+                ("i", at(Cell(5, 6), &[Cell(5, 6)], 9)),
+                ("2", lost),
+                ("3", at(Cell(8, 9), &[], 9)),
+            ],
+        ));
+        assert_eq!(state.stats["i"].kills, 1);
+        assert_eq!(state.stats["2"].lost_at, Some(2));
+
+        // Player 3 disappears inside my territory.
+        state.update(world_at(3, &[("i", at(Cell(1, 1), &[], 100))]));
+        assert_eq!(state.stats["i"].kills, 2);
+        assert_eq!(state.stats["3"].lost_at, Some(3));
+        assert_eq!(state.stats["3"].kills, 0);
+        assert_eq!(state.eliminated_at, None);
+
+        let previous = world_at(1, &[("2", at(Cell(0, 0), &[Cell(0, 1)], 9))]);
+        assert_eq!(killer_of(&previous, &world_at(2, &[]), "2"), None);
+        assert_eq!(killer_of(&previous, &previous, "4"), None);
+    }
+
+    #[test]
+    fn winner_of_the_game() {
+        let scored = |score| Player {
+            score,
+            ..player(9, false)
+        };
+        let mut state = GameState::new(PARAMS, 0.5);
+        assert_eq!(state.winner(), None);
+
+        state.update(world(&[("i", scored(10)), ("2", scored(30))]));
+        assert_eq!(state.winner(), Some("2".to_string()));
+
+        state.update(world(&[("i", scored(30)), ("2", scored(30))]));
+        assert_eq!(state.winner(), None);
+
+        // The territory win beats the score.
+        let mut big = player(60, false);
+        big.score = 5;
+        state.update(world(&[("i", big), ("2", scored(30))]));
+        assert_eq!(state.winner(), Some("i".to_string()));
+    }
+
+    #[test]
+    fn banner_threshold() {
+        assert_eq!(threshold_leader(&shares(&[]), 0.5), None);
+        assert_eq!(threshold_leader(&shares(&[("1", 0.49)]), 0.5), None);
+        assert_eq!(
+            threshold_leader(&shares(&[("1", 0.5)]), 0.5),
+            Some("1".to_string())
+        );
+        assert_eq!(
+            threshold_leader(&shares(&[("1", 0.6), ("2", 0.3), ("3", 0.)]), 0.5),
+            Some("1".to_string())
+        );
+        assert_eq!(
+            threshold_leader(&shares(&[("1", 0.3), ("2", 0.4)]), 0.25),
+            Some("2".to_string())
+        );
+        assert_eq!(
+            threshold_leader(&shares(&[("2", 0.3), ("1", 0.3)]), 0.25),
+            Some("1".to_string())
+        );
+        assert_eq!(threshold_leader(&shares(&[("1", 0.)]), 0.), None);
+    }
+
+    #[test]
+    fn status_aging() {
+        assert_eq!(status_opacity(10, 10), Some(1.));
+        assert_eq!(status_opacity(5, 10), Some(1.));
+        assert_eq!(status_opacity(10 + STATUS_HOLD_TICKS - 1, 10), Some(1.));
+        assert_eq!(status_opacity(10 + STATUS_HOLD_TICKS, 10), Some(1.));
+
+        let mut previous = 1.;
+        for age in 1..STATUS_FADE_TICKS {
+            let opacity = status_opacity(STATUS_HOLD_TICKS + age, 0).unwrap();
+            assert!(0. < opacity && opacity < previous, "{age}: {opacity}");
+            previous = opacity;
+        }
+        assert_eq!(
+            status_opacity(STATUS_HOLD_TICKS + STATUS_FADE_TICKS / 2, 0),
+            Some(0.5)
+        );
+        assert_eq!(
+            status_opacity(STATUS_HOLD_TICKS + STATUS_FADE_TICKS, 0),
+            None
+        );
+        assert_eq!(status_opacity(u32::MAX, 0), None);
+    }
+
+    #[test]
+    fn statuses_are_bounded_and_fade() {
+        let mut state = GameState::new(PARAMS, 0.5);
+        for i in 0..MAX_STATUSES as u32 + 2 {
+            state.push_status(i, i.to_string(), StatusLevel::Info);
+        }
+        let texts = state
+            .statuses
+            .iter()
+            .map(|status| status.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["2", "3", "4", "5"]);
+
+        let mut world = world(&[]);
+        world.tick_num = 2 + STATUS_HOLD_TICKS + STATUS_FADE_TICKS;
+        state.update(world);
+        let visible = state
+            .visible_statuses()
+            .map(|(status, opacity)| (status.text.as_str(), opacity))
+            .collect::<Vec<_>>();
+        assert_eq!(visible.len(), 3);
+        assert_eq!(visible[0].0, "3");
+        assert!(visible[0].1 < visible[1].1 && visible[1].1 < visible[2].1);
+    }
+}
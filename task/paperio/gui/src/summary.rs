@@ -0,0 +1,267 @@
+//! What the end screen tells about a game, computed from the timelines of the players
+//! kept by `GameState`, see `GameSummary::new`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use paperio_proto::{PlayerId, PlayerInfo};
+use serde::Serialize;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A player as of a tick it was seen on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TimelinePoint {
+    pub tick_num: u32,
+    pub score: u32,
+    /// In cells.
+    pub territory: usize,
+}
+
+/// A point per tick shown: games are a few hundred ticks long, so nothing is thinned out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    pub points: Vec<TimelinePoint>,
+    pub eliminated_at: Option<u32>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Standing {
+    pub player_id: PlayerId,
+    /// Only known if the server has announced the players.
+    pub nickname: Option<String>,
+    pub score: u32,
+    pub peak_territory: usize,
+    /// The gains of territory from tick to tick summed up, the starting one isn't counted.
+    pub cells_captured: usize,
+    /// From the first tick the player was seen on to its elimination or the end.
+    pub ticks_survived: u32,
+    pub eliminated_at: Option<u32>,
+    pub timeline: Vec<TimelinePoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GameSummary {
+    pub end_tick: u32,
+    /// The best first, see `GameSummary::new`.
+    pub standings: Vec<Standing>,
+}
+
+impl GameSummary {
+    /// The players are ranked the way the server tells the winner: by score, and the
+    /// ones with the same score by id.
+    pub fn new(
+        timelines: &HashMap<PlayerId, Timeline>,
+        nicknames: Option<&HashMap<PlayerId, PlayerInfo>>,
+        end_tick: u32,
+    ) -> Self {
+        let mut standings = timelines
+            .iter()
+            .map(|(id, timeline)| standing(id, timeline, nicknames, end_tick))
+            .collect::<Vec<_>>();
+        standings.sort_unstable_by(|s1, s2| {
+            s2.score
+                .cmp(&s1.score)
+                .then(s1.player_id.cmp(&s2.player_id))
+        });
+        Self {
+            end_tick,
+            standings,
+        }
+    }
+
+    /// The highest score anyone has had, for the scale of the chart.
+    pub fn max_score(&self) -> u32 {
+        self.standings
+            .iter()
+            .flat_map(|standing| &standing.timeline)
+            .map(|point| point.score)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("summary is always serializable")
+    }
+
+    /// Writes the summary into `dir` as `paperio-summary-<end tick>-<n>.json`, with the
+    /// first `n` that isn't taken.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        for n in 1.. {
+            let path = dir.join(format!("paperio-summary-{}-{n}.json", self.end_tick));
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => return file.write_all(self.to_json().as_bytes()).map(|()| path),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!()
+    }
+}
+
+fn standing(
+    id: &PlayerId,
+    timeline: &Timeline,
+    nicknames: Option<&HashMap<PlayerId, PlayerInfo>>,
+    end_tick: u32,
+) -> Standing {
+    let points = &timeline.points;
+    let cells_captured = points
+        .windows(2)
+        .map(|pair| pair[1].territory.saturating_sub(pair[0].territory))
+        .sum();
+    let ticks_survived = points.first().map_or(0, |first| {
+        timeline
+            .eliminated_at
+            .unwrap_or(end_tick)
+            .saturating_sub(first.tick_num)
+    });
+    Standing {
+        player_id: id.clone(),
+        nickname: nicknames
+            .and_then(|nicknames| nicknames.get(id))
+            .map(|info| info.user_name.clone()),
+        score: points.last().map_or(0, |point| point.score),
+        peak_territory: points
+            .iter()
+            .map(|point| point.territory)
+            .max()
+            .unwrap_or(0),
+        cells_captured,
+        ticks_survived,
+        eliminated_at: timeline.eliminated_at,
+        timeline: points.clone(),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on the ticks from `first_tick` on, one per `(score, territory)`.
+    fn timeline(first_tick: u32, points: &[(u32, usize)], eliminated_at: Option<u32>) -> Timeline {
+        Timeline {
+            points: points
+                .iter()
+                .zip(first_tick..)
+                .map(|(&(score, territory), tick_num)| TimelinePoint {
+                    tick_num,
+                    score,
+                    territory,
+                })
+                .collect(),
+            eliminated_at,
+        }
+    }
+
+    fn summary(timelines: &[(&str, Timeline)], end_tick: u32) -> GameSummary {
+        let timelines = timelines
+            .iter()
+            .map(|(id, timeline)| (id.to_string(), timeline.clone()))
+            .collect();
+        GameSummary::new(&timelines, None, end_tick)
+    }
+
+    #[test]
+    fn captures_and_peaks() {
+        // Grows, loses some to an enemy, and grows again.
+        let summary = summary(
+            &[("i", timeline(1, &[(0, 9), (1, 12), (1, 7), (3, 15)], None))],
+            4,
+        );
+        let standing = &summary.standings[0];
+        assert_eq!(standing.score, 3);
+        assert_eq!(standing.peak_territory, 15);
+        assert_eq!(standing.cells_captured, 3 + 8);
+        assert_eq!(standing.ticks_survived, 3);
+        assert_eq!(standing.eliminated_at, None);
+        assert_eq!(standing.timeline.len(), 4);
+        assert_eq!(summary.max_score(), 3);
+    }
+
+    #[test]
+    fn eliminated_at_first_tick() {
+        let summary = summary(
+            &[
+                ("2", timeline(1, &[(0, 0)], Some(1))),
+                ("i", timeline(1, &[(0, 9), (0, 9), (0, 9)], None)),
+            ],
+            3,
+        );
+        // Ties go by id.
+        let lost = &summary.standings[0];
+        assert_eq!(lost.player_id, "2");
+        assert_eq!(lost.eliminated_at, Some(1));
+        assert_eq!(lost.ticks_survived, 0);
+        assert_eq!(lost.peak_territory, 0);
+        assert_eq!(lost.cells_captured, 0);
+
+        // Never grew.
+        let idle = &summary.standings[1];
+        assert_eq!(idle.cells_captured, 0);
+        assert_eq!(idle.peak_territory, 9);
+        assert_eq!(idle.ticks_survived, 2);
+        assert_eq!(summary.max_score(), 0);
+    }
+
+    #[test]
+    fn standings_by_score_then_id() {
+        let summary = summary(
+            &[
+                ("3", timeline(1, &[(5, 9)], None)),
+                ("i", timeline(1, &[(2, 9)], None)),
+                ("2", timeline(1, &[(5, 9)], Some(1))),
+                ("4", timeline(1, &[(7, 9)], None)),
+            ],
+            1,
+        );
+        let ids = summary
+            .standings
+            .iter()
+            .map(|standing| standing.player_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["4", "2", "3", "i"]);
+    }
+
+    #[test]
+    fn nicknames_and_json() {
+        let timelines = HashMap::from([
+            ("i".to_string(), timeline(2, &[(1, 9)], None)),
+            ("2".to_string(), timeline(2, &[(0, 9)], None)),
+        ]);
+        let nicknames = HashMap::from([(
+            "i".to_string(),
+            PlayerInfo {
+                user_name: "alice".to_string(),
+            },
+        )]);
+        let summary = GameSummary::new(&timelines, Some(&nicknames), 2);
+        assert_eq!(summary.standings[0].nickname.as_deref(), Some("alice"));
+        assert_eq!(summary.standings[1].nickname, None);
+
+        let json = serde_json::from_str::<serde_json::Value>(&summary.to_json()).unwrap();
+        assert_eq!(json["end_tick"], 2);
+        assert_eq!(json["standings"][0]["player_id"], "i");
+        assert_eq!(json["standings"][0]["timeline"][0]["territory"], 9);
+
+        let dir = std::env::temp_dir().join(format!("paperio-gui-summary-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = summary.save(&dir).unwrap();
+        let second = summary.save(&dir).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read_to_string(&second).unwrap(), summary.to_json());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
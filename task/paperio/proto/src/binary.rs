@@ -0,0 +1,857 @@
+//! A compact encoding of messages and commands, an alternative to JSON lines, see
+//! `traits::Format`.
+//!
+//! Every message or command is a frame: the length of its payload as a varint, then the
+//! payload. Integers are LEB128 varints, strings and sequences are prefixed with their
+//! lengths, cells are pairs of little-endian `i16`s, and enums are tagged with a byte.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    Bonus, BonusKind, Cell, Command, Direction, GameParams, Message, Player, PlayerDelta, PlayerId,
+    PlayerInfo, StatusLevel, World, WorldDelta,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Longer frames are rejected instead of being allocated for.
+pub const MAX_FRAME_LEN: usize = 16 << 20;
+
+const START_GAME: u8 = 0;
+const TICK: u8 = 1;
+const END_GAME: u8 = 2;
+const STATUS: u8 = 3;
+const TICK_DELTA: u8 = 4;
+const PLAYER_ANNOUNCEMENT: u8 = 5;
+
+const CHANGE_DIRECTION: u8 = 0;
+const NO_OP: u8 = 1;
+
+const NO_DIRECTION: u8 = u8::MAX;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    let mut header = Vec::with_capacity(10);
+    put_varint(&mut header, payload.len() as u64);
+    writer.write_all(&header)?;
+    writer.write_all(payload)
+}
+
+/// Appends the frame of the message to `buffer`, the same bytes as `write_frame` of its
+/// payload writes. Nothing is appended on errors.
+pub fn encode_message_frame(message: &Message, buffer: &mut Vec<u8>) -> io::Result<()> {
+    let start = buffer.len();
+    if let Err(err) = encode_message(message, buffer) {
+        buffer.truncate(start);
+        return Err(err);
+    }
+    frame_in_place(buffer, start);
+    Ok(())
+}
+
+pub fn encode_command_frame(command: &Command, buffer: &mut Vec<u8>) {
+    let start = buffer.len();
+    encode_command(command, buffer);
+    frame_in_place(buffer, start);
+}
+
+/// Prefixes the payload at `buffer[start..]` with its length.
+fn frame_in_place(buffer: &mut Vec<u8>, start: usize) {
+    let payload_len = buffer.len() - start;
+    put_varint(buffer, payload_len as u64);
+    let header_len = buffer.len() - start - payload_len;
+    buffer[start..].rotate_right(header_len);
+}
+
+/// Replaces the contents of `payload` with the next frame.
+pub fn read_frame(reader: &mut impl BufRead, payload: &mut Vec<u8>) -> io::Result<()> {
+    let mut len = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        len |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        if shift + 7 >= 64 {
+            return Err(invalid_data("frame length overflows"));
+        }
+    }
+    if len > MAX_FRAME_LEN as u64 {
+        return Err(invalid_data(format!("frame of {len} bytes is too long")));
+    }
+
+    payload.clear();
+    payload.resize(len as usize, 0);
+    reader.read_exact(payload)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Appends the payload of the message to `buffer`. `Message::Unknown` is never sent, so
+/// it can't be encoded.
+pub fn encode_message(message: &Message, buffer: &mut Vec<u8>) -> io::Result<()> {
+    match message {
+        Message::StartGame(params) => {
+            buffer.push(START_GAME);
+            put_varint(buffer, params.x_cells_count.into());
+            put_varint(buffer, params.y_cells_count.into());
+        }
+        Message::Tick(world) => {
+            buffer.push(TICK);
+            put_world(buffer, world)?;
+        }
+        Message::TickDelta(delta) => {
+            buffer.push(TICK_DELTA);
+            put_world_delta(buffer, delta)?;
+        }
+        Message::EndGame {} => buffer.push(END_GAME),
+        Message::Status {
+            tick_num,
+            text,
+            level,
+        } => {
+            buffer.push(STATUS);
+            put_varint(buffer, (*tick_num).into());
+            put_str(buffer, text);
+            buffer.push(*level as u8);
+        }
+        Message::PlayerAnnouncement(infos) => {
+            buffer.push(PLAYER_ANNOUNCEMENT);
+            put_varint(buffer, infos.len() as u64);
+            for (player_id, info) in infos {
+                put_str(buffer, player_id);
+                put_str(buffer, &info.user_name);
+            }
+        }
+        Message::Unknown { message_type } => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown message `{message_type}` can't be sent"),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Messages with unknown tags are read as `Message::Unknown`, see `traits::JsonRead`.
+pub fn decode_message(payload: &[u8]) -> io::Result<Message> {
+    let mut decoder = Decoder(payload);
+    let message = match decoder.u8()? {
+        START_GAME => Message::StartGame(GameParams {
+            x_cells_count: decoder.u32()?,
+            y_cells_count: decoder.u32()?,
+        }),
+        TICK => Message::Tick(decoder.world()?),
+        TICK_DELTA => Message::TickDelta(decoder.world_delta()?),
+        END_GAME => Message::EndGame {},
+        STATUS => Message::Status {
+            tick_num: decoder.u32()?,
+            text: decoder.string()?,
+            level: match decoder.u8()? {
+                0 => StatusLevel::Info,
+                1 => StatusLevel::Warning,
+                2 => StatusLevel::Alert,
+                level => return Err(invalid_data(format!("unknown status level {level}"))),
+            },
+        },
+        PLAYER_ANNOUNCEMENT => Message::PlayerAnnouncement(decoder.player_infos()?),
+        tag => {
+            return Ok(Message::Unknown {
+                message_type: format!("binary #{tag}"),
+            })
+        }
+    };
+    decoder.finish()?;
+    Ok(message)
+}
+
+pub fn encode_command(command: &Command, buffer: &mut Vec<u8>) {
+    match command {
+        Command::ChangeDirection(direction) => {
+            buffer.push(CHANGE_DIRECTION);
+            buffer.push(*direction as u8);
+        }
+        Command::NoOp => buffer.push(NO_OP),
+    }
+}
+
+pub fn decode_command(payload: &[u8]) -> io::Result<Command> {
+    let mut decoder = Decoder(payload);
+    let command = match decoder.u8()? {
+        CHANGE_DIRECTION => match decoder.direction()? {
+            Some(direction) => Command::ChangeDirection(direction),
+            None => return Err(invalid_data("direction change without a direction")),
+        },
+        NO_OP => Command::NoOp,
+        tag => return Err(invalid_data(format!("unknown command #{tag}"))),
+    };
+    decoder.finish()?;
+    Ok(command)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn put_str(buffer: &mut Vec<u8>, value: &str) {
+    put_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+fn put_cell(buffer: &mut Vec<u8>, Cell(x, y): Cell) -> io::Result<()> {
+    for coordinate in [x, y] {
+        let coordinate = i16::try_from(coordinate).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cell coordinate {coordinate} doesn't fit into i16"),
+            )
+        })?;
+        buffer.extend_from_slice(&coordinate.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn put_cells(buffer: &mut Vec<u8>, cells: &[Cell]) -> io::Result<()> {
+    put_varint(buffer, cells.len() as u64);
+    cells.iter().try_for_each(|&cell| put_cell(buffer, cell))
+}
+
+fn put_direction(buffer: &mut Vec<u8>, direction: Option<Direction>) {
+    buffer.push(direction.map_or(NO_DIRECTION, |d| d as u8));
+}
+
+fn put_flags(buffer: &mut Vec<u8>, has_lost: bool, position_hidden: bool) {
+    buffer.push(u8::from(has_lost) | (u8::from(position_hidden) << 1));
+}
+
+fn put_bonuses(buffer: &mut Vec<u8>, bonuses: &[Bonus]) -> io::Result<()> {
+    put_varint(buffer, bonuses.len() as u64);
+    for bonus in bonuses {
+        buffer.push(bonus.kind as u8);
+        put_cell(buffer, bonus.position)?;
+    }
+    Ok(())
+}
+
+fn put_world(buffer: &mut Vec<u8>, world: &World) -> io::Result<()> {
+    put_varint(buffer, world.players.len() as u64);
+    for (id, player) in &world.players {
+        put_str(buffer, id);
+        put_varint(buffer, player.score.into());
+        put_cells(buffer, &player.territory)?;
+        put_cell(buffer, player.position)?;
+        put_cells(buffer, &player.lines)?;
+        put_direction(buffer, player.direction);
+        put_flags(buffer, player.has_lost, player.position_hidden);
+    }
+
+    put_varint(buffer, world.tick_num.into());
+    put_bonuses(buffer, &world.bonuses)?;
+    put_warmup(buffer, world.warmup);
+    Ok(())
+}
+
+/// The warm-up flag goes last and only when set, so that games without a warm-up are
+/// encoded as before, see `Decoder::warmup`.
+fn put_warmup(buffer: &mut Vec<u8>, warmup: bool) {
+    if warmup {
+        buffer.push(1);
+    }
+}
+
+fn put_world_delta(buffer: &mut Vec<u8>, delta: &WorldDelta) -> io::Result<()> {
+    put_varint(buffer, delta.base_tick_num.into());
+    put_varint(buffer, delta.tick_num.into());
+
+    put_varint(buffer, delta.players.len() as u64);
+    for (id, player) in &delta.players {
+        put_str(buffer, id);
+        put_varint(buffer, player.score.into());
+        put_cell(buffer, player.position)?;
+        put_direction(buffer, player.direction);
+        put_flags(buffer, player.has_lost, player.position_hidden);
+        put_cells(buffer, &player.territory_added)?;
+        put_cells(buffer, &player.territory_removed)?;
+        put_cells(buffer, &player.lines_added)?;
+        put_cells(buffer, &player.lines_removed)?;
+    }
+
+    put_varint(buffer, delta.removed_players.len() as u64);
+    for id in &delta.removed_players {
+        put_str(buffer, id);
+    }
+    put_bonuses(buffer, &delta.bonuses)?;
+    put_warmup(buffer, delta.warmup);
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_data("payload is truncated"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid_data("varint overflows"))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        u32::try_from(self.varint()?).map_err(|_| invalid_data("value doesn't fit into u32"))
+    }
+
+    /// A length of a sequence, which can't be longer than the rest of the payload.
+    fn len(&mut self) -> io::Result<usize> {
+        let len = self.varint()?;
+        if len > self.0.len() as u64 {
+            return Err(invalid_data("payload is truncated"));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.len()?;
+        String::from_utf8(self.bytes(len)?.to_vec())
+            .map_err(|_| invalid_data("string is not valid UTF-8"))
+    }
+
+    fn cell(&mut self) -> io::Result<Cell> {
+        let bytes = self.bytes(4)?;
+        Ok(Cell(
+            i16::from_le_bytes([bytes[0], bytes[1]]).into(),
+            i16::from_le_bytes([bytes[2], bytes[3]]).into(),
+        ))
+    }
+
+    fn cells(&mut self) -> io::Result<Vec<Cell>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.cell()).collect()
+    }
+
+    fn direction(&mut self) -> io::Result<Option<Direction>> {
+        match self.u8()? {
+            NO_DIRECTION => Ok(None),
+            0 => Ok(Some(Direction::Up)),
+            1 => Ok(Some(Direction::Right)),
+            2 => Ok(Some(Direction::Down)),
+            3 => Ok(Some(Direction::Left)),
+            direction => Err(invalid_data(format!("unknown direction {direction}"))),
+        }
+    }
+
+    /// Returns `has_lost` and `position_hidden`.
+    fn flags(&mut self) -> io::Result<(bool, bool)> {
+        let flags = self.u8()?;
+        Ok((flags & 1 != 0, flags & 2 != 0))
+    }
+
+    fn player(&mut self) -> io::Result<Player> {
+        let score = self.u32()?;
+        let territory = self.cells()?;
+        let position = self.cell()?;
+        let lines = self.cells()?;
+        let direction = self.direction()?;
+        let (has_lost, position_hidden) = self.flags()?;
+        Ok(Player {
+            score,
+            territory,
+            position,
+            lines,
+            direction,
+            has_lost,
+            position_hidden,
+        })
+    }
+
+    fn bonuses(&mut self) -> io::Result<Vec<Bonus>> {
+        let bonus_count = self.len()?;
+        (0..bonus_count)
+            .map(|_| {
+                let kind = match self.u8()? {
+                    0 => BonusKind::Nitro,
+                    1 => BonusKind::Slowdown,
+                    kind => return Err(invalid_data(format!("unknown bonus kind {kind}"))),
+                };
+                Ok(Bonus {
+                    kind,
+                    position: self.cell()?,
+                })
+            })
+            .collect()
+    }
+
+    fn world(&mut self) -> io::Result<World> {
+        let player_count = self.len()?;
+        let mut players = HashMap::with_capacity(player_count);
+        for _ in 0..player_count {
+            let id = self.string()?;
+            players.insert(id, self.player()?);
+        }
+
+        Ok(World {
+            players,
+            tick_num: self.u32()?,
+            bonuses: self.bonuses()?,
+            warmup: self.warmup()?,
+        })
+    }
+
+    /// Only at the end of the payload, see `put_warmup`.
+    fn warmup(&mut self) -> io::Result<bool> {
+        if self.0.is_empty() {
+            return Ok(false);
+        }
+        match self.u8()? {
+            1 => Ok(true),
+            flag => Err(invalid_data(format!("invalid warm-up flag {flag}"))),
+        }
+    }
+
+    fn player_infos(&mut self) -> io::Result<HashMap<PlayerId, PlayerInfo>> {
+        let player_count = self.len()?;
+        let mut infos = HashMap::with_capacity(player_count);
+        for _ in 0..player_count {
+            let id = self.string()?;
+            let user_name = self.string()?;
+            infos.insert(id, PlayerInfo { user_name });
+        }
+        Ok(infos)
+    }
+
+    fn player_delta(&mut self) -> io::Result<PlayerDelta> {
+        let score = self.u32()?;
+        let position = self.cell()?;
+        let direction = self.direction()?;
+        let (has_lost, position_hidden) = self.flags()?;
+        Ok(PlayerDelta {
+            score,
+            position,
+            direction,
+            has_lost,
+            position_hidden,
+            territory_added: self.cells()?,
+            territory_removed: self.cells()?,
+            lines_added: self.cells()?,
+            lines_removed: self.cells()?,
+        })
+    }
+
+    fn world_delta(&mut self) -> io::Result<WorldDelta> {
+        let base_tick_num = self.u32()?;
+        let tick_num = self.u32()?;
+
+        let player_count = self.len()?;
+        let mut players = HashMap::with_capacity(player_count);
+        for _ in 0..player_count {
+            let id = self.string()?;
+            players.insert(id, self.player_delta()?);
+        }
+
+        let removed_count = self.len()?;
+        let removed_players = (0..removed_count)
+            .map(|_| self.string())
+            .collect::<io::Result<_>>()?;
+
+        Ok(WorldDelta {
+            base_tick_num,
+            tick_num,
+            players,
+            removed_players,
+            bonuses: self.bonuses()?,
+            warmup: self.warmup()?,
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self.0.len() {
+            0 => Ok(()),
+            len => Err(invalid_data(format!("{len} trailing bytes in payload"))),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        traits::{Format, JsonRead, JsonWrite, MessageRead, MessageWrite},
+        MAP_SIZE_CELLS,
+    };
+
+    use std::time::Instant;
+
+    fn player(score: u32, cells: impl Iterator<Item = Cell>) -> Player {
+        let territory = cells.collect::<Vec<_>>();
+        Player {
+            score,
+            position: territory.first().copied().unwrap_or(Cell(0, 0)),
+            lines: territory
+                .iter()
+                .take(12)
+                .map(|&Cell(x, y)| Cell(y, x))
+                .collect(),
+            territory,
+            direction: Some(Direction::Left),
+            has_lost: false,
+            position_hidden: false,
+        }
+    }
+
+    /// The whole map split between 4 players, with traces and bonuses.
+    fn full_world() -> World {
+        let players = (0..4)
+            .map(|i| {
+                let cells = (0..MAP_SIZE_CELLS * MAP_SIZE_CELLS)
+                    .filter(move |c| c % 4 == i)
+                    .map(|c| Cell(c % MAP_SIZE_CELLS, c / MAP_SIZE_CELLS));
+                (format!("{}", i + 1), player(1000 + i as u32, cells))
+            })
+            .collect();
+        World {
+            players,
+            tick_num: 299,
+            bonuses: vec![
+                Bonus {
+                    kind: BonusKind::Nitro,
+                    position: Cell(3, 4),
+                },
+                Bonus {
+                    kind: BonusKind::Slowdown,
+                    position: Cell(30, 0),
+                },
+            ],
+            warmup: false,
+        }
+    }
+
+    fn messages() -> Vec<Message> {
+        let mut world = full_world();
+        world.players.insert(
+            "i".to_string(),
+            Player {
+                score: 0,
+                territory: vec![],
+                position: Cell::HIDDEN,
+                lines: vec![],
+                direction: None,
+                has_lost: true,
+                position_hidden: true,
+            },
+        );
+        world.players.get_mut("2").unwrap().has_lost = true;
+
+        let mut next = world.clone();
+        next.tick_num += 1;
+        next.players.remove("3");
+        let player = next.players.get_mut("1").unwrap();
+        player.territory.retain(|cell| cell.0 != 0);
+        player.lines.clear();
+        player.direction = Some(Direction::Down);
+        let delta = WorldDelta::between(&world, &next);
+
+        let mut messages = vec![
+            Message::StartGame(GameParams {
+                x_cells_count: 31,
+                y_cells_count: 300_000,
+            }),
+            Message::Tick(world),
+            Message::TickDelta(delta),
+            Message::Tick(World {
+                players: HashMap::new(),
+                tick_num: 0,
+                bonuses: vec![],
+                warmup: false,
+            }),
+            Message::EndGame {},
+            Message::PlayerAnnouncement(HashMap::from([
+                (
+                    "1".to_string(),
+                    PlayerInfo {
+                        user_name: "игрок".to_string(),
+                    },
+                ),
+                (
+                    "2".to_string(),
+                    PlayerInfo {
+                        user_name: String::new(),
+                    },
+                ),
+            ])),
+        ];
+        for level in [StatusLevel::Info, StatusLevel::Warning, StatusLevel::Alert] {
+            messages.push(Message::Status {
+                tick_num: u32::MAX,
+                text: "игрок 2 vs 3: ✂".to_string(),
+                level,
+            });
+        }
+        messages
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = messages();
+        let mut buffer = vec![];
+        for message in &messages {
+            buffer.write_message_as(Format::Binary, message).unwrap();
+        }
+
+        let mut reader = buffer.as_slice();
+        for message in &messages {
+            assert_eq!(&reader.read_message_as(Format::Binary).unwrap(), message);
+        }
+        assert!(reader.is_empty());
+        assert!(reader.read_message_as(Format::Binary).is_err());
+    }
+
+    #[test]
+    fn warmup_is_appended() {
+        let game = full_world();
+        let warmup = World {
+            warmup: true,
+            ..game.clone()
+        };
+        let encode = |message: &Message| {
+            let mut buffer = vec![];
+            buffer.write_message_as(Format::Binary, message).unwrap();
+            buffer
+        };
+
+        // Without a warm-up the payloads are the same as before the flag.
+        let game_tick = encode(&Message::Tick(game.clone()));
+        let warmup_tick = encode(&Message::Tick(warmup.clone()));
+        assert_eq!(warmup_tick.len(), game_tick.len() + 1);
+        for (bytes, world) in [(&game_tick, &game), (&warmup_tick, &warmup)] {
+            let message = bytes.as_slice().read_message_as(Format::Binary).unwrap();
+            assert_eq!(message, Message::Tick(world.clone()));
+        }
+
+        let mut next = warmup.clone();
+        next.tick_num += 1;
+        let delta = WorldDelta::between(&warmup, &next);
+        assert!(delta.warmup);
+        let bytes = encode(&Message::TickDelta(delta.clone()));
+        let message = bytes.as_slice().read_message_as(Format::Binary).unwrap();
+        assert_eq!(message, Message::TickDelta(delta));
+
+        let delta = WorldDelta::between(&warmup, &game);
+        assert!(!delta.warmup);
+        let mut world = warmup;
+        world.apply_delta(&delta).unwrap();
+        assert_eq!(world, game);
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let mut commands = vec![Command::NoOp];
+        commands.extend(
+            [
+                Direction::Up,
+                Direction::Right,
+                Direction::Down,
+                Direction::Left,
+            ]
+            .map(Command::ChangeDirection),
+        );
+
+        let mut buffer = vec![];
+        for command in &commands {
+            buffer.write_command_as(Format::Binary, command).unwrap();
+        }
+        assert_eq!(buffer.len(), 2 + 3 * 4);
+
+        let mut reader = buffer.as_slice();
+        for command in &commands {
+            let read = reader.read_command_as(Format::Binary).unwrap();
+            assert_eq!(format!("{read:?}"), format!("{command:?}"));
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn frames_in_buffer() {
+        // Encoded twice from the same maps, so that players come in the same order.
+        let messages = messages();
+        let mut buffer = vec![7];
+        for message in &messages {
+            encode_message_frame(message, &mut buffer).unwrap();
+        }
+        encode_command_frame(&Command::NoOp, &mut buffer);
+
+        let mut expected = vec![7];
+        for message in &messages {
+            expected.write_message_as(Format::Binary, message).unwrap();
+        }
+        expected
+            .write_command_as(Format::Binary, &Command::NoOp)
+            .unwrap();
+        assert_eq!(buffer, expected);
+
+        let unknown = Message::Unknown {
+            message_type: "replay_marker".to_string(),
+        };
+        assert!(encode_message_frame(&unknown, &mut buffer).is_err());
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn unknown_messages() {
+        let message = Message::Unknown {
+            message_type: "replay_marker".to_string(),
+        };
+        let err = vec![]
+            .write_message_as(Format::Binary, &message)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Frames of unknown messages are skipped whole.
+        let mut buffer = vec![];
+        write_frame(&mut buffer, &[42, 1, 2, 3]).unwrap();
+        buffer
+            .write_message_as(Format::Binary, &Message::EndGame {})
+            .unwrap();
+        let mut reader = buffer.as_slice();
+        assert_eq!(
+            reader.read_message_as(Format::Binary).unwrap(),
+            Message::Unknown {
+                message_type: "binary #42".to_string()
+            }
+        );
+        assert_eq!(
+            reader.read_message_as(Format::Binary).unwrap(),
+            Message::EndGame {}
+        );
+    }
+
+    #[test]
+    fn invalid_payloads() {
+        let mut payload = vec![];
+        encode_message(&messages()[1], &mut payload).unwrap();
+        for len in 0..payload.len() {
+            assert!(decode_message(&payload[..len]).is_err(), "{len}");
+        }
+        payload.push(0);
+        assert!(decode_message(&payload).is_err());
+
+        assert!(decode_command(&[CHANGE_DIRECTION, 4]).is_err());
+        assert!(decode_command(&[CHANGE_DIRECTION, NO_DIRECTION]).is_err());
+        assert!(decode_command(&[7]).is_err());
+        assert!(decode_command(&[NO_OP, 0]).is_err());
+        assert!(decode_message(&[STATUS, 0, 0, 3]).is_err());
+        assert!(decode_message(&[STATUS, 0, 2, 0xff, 0xfe, 0]).is_err());
+
+        let far = Message::StartGame(GameParams {
+            x_cells_count: 1,
+            y_cells_count: 1,
+        });
+        let mut world = full_world();
+        world.players.get_mut("1").unwrap().position = Cell(1 << 15, 0);
+        assert!(encode_message(&Message::Tick(world), &mut vec![]).is_err());
+        assert!(encode_message(&far, &mut vec![]).is_ok());
+
+        // Frame lengths.
+        let mut frame = vec![];
+        put_varint(&mut frame, MAX_FRAME_LEN as u64 + 1);
+        assert!(read_frame(&mut frame.as_slice(), &mut vec![]).is_err());
+        assert!(read_frame(&mut [0xff; 11].as_slice(), &mut vec![]).is_err());
+        assert!(read_frame(&mut [3, 0].as_slice(), &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, 127, 128, 300, u32::MAX.into(), u64::MAX] {
+            let mut buffer = vec![];
+            put_varint(&mut buffer, value);
+            let mut decoder = Decoder(&buffer);
+            assert_eq!(decoder.varint().unwrap(), value);
+            decoder.finish().unwrap();
+        }
+        let mut buffer = vec![];
+        put_varint(&mut buffer, 127);
+        put_varint(&mut buffer, 128);
+        assert_eq!(buffer, [0x7f, 0x80, 0x01]);
+
+        assert!(Decoder(&[0x80; 10]).varint().is_err());
+        let mut buffer = vec![];
+        put_varint(&mut buffer, u64::from(u32::MAX) + 1);
+        assert!(Decoder(&buffer).u32().is_err());
+    }
+
+    #[test]
+    fn formats() {
+        for format in [Format::Json, Format::Binary] {
+            assert_eq!(format.to_string().parse::<Format>(), Ok(format));
+        }
+        assert!("bincode".parse::<Format>().is_err());
+        assert_eq!(Format::default(), Format::Json);
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn json_vs_binary() {
+        const ITERATIONS: u32 = 1000;
+        let message = Message::Tick(full_world());
+
+        let measure = |format: Format| {
+            let mut buffer = vec![];
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                buffer.clear();
+                buffer.write_message_as(format, &message).unwrap();
+                let decoded = buffer.as_slice().read_message_as(format).unwrap();
+                assert_eq!(decoded, message);
+            }
+            (buffer.len(), start.elapsed() / ITERATIONS)
+        };
+        let (json_len, json_time) = measure(Format::Json);
+        let (binary_len, binary_time) = measure(Format::Binary);
+
+        println!(
+            "full world, encode + decode: json {json_len} bytes in {json_time:?}, \
+             binary {binary_len} bytes in {binary_time:?}, speedup: {:.1}",
+            json_time.as_secs_f64() / binary_time.as_secs_f64()
+        );
+
+        // The JSON traits still work on the same world.
+        let mut buffer = vec![];
+        buffer.write_message(&message).unwrap();
+        assert_eq!(buffer.as_slice().read_message().unwrap(), message);
+    }
+}
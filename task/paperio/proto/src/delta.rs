@@ -0,0 +1,338 @@
+//! Changes of the world between ticks, sent instead of full ticks to clients which
+//! ask for them: late in the game territories are hundreds of cells, but only a few of
+//! them change per tick.
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+use crate::{Bonus, Cell, Direction, Player, PlayerId, World};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The world of the tick `tick_num` as the changes of the world of `base_tick_num`,
+/// see `World::apply_delta`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct WorldDelta {
+    pub base_tick_num: u32,
+    pub tick_num: u32,
+    /// Every player of the new world, including ones which have appeared since.
+    pub players: HashMap<PlayerId, PlayerDelta>,
+    /// Players of the base world which are gone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_players: Vec<PlayerId>,
+    /// There are only a few bonuses, so they are sent whole.
+    #[serde(default)]
+    pub bonuses: Vec<Bonus>,
+    /// `World::warmup` of the new world.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warmup: bool,
+}
+
+/// The scalar fields of `Player` as they are now, and the cells it has gained and lost.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct PlayerDelta {
+    pub score: u32,
+    pub position: Cell,
+    pub direction: Option<Direction>,
+    pub has_lost: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub position_hidden: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub territory_added: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub territory_removed: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines_added: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines_removed: Vec<Cell>,
+}
+
+/// The delta is based on another tick than the world it's applied to, e.g. because a
+/// message was lost. The client should wait for the next full tick.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct DeltaMismatch {
+    pub world_tick_num: u32,
+    pub base_tick_num: u32,
+}
+
+impl fmt::Display for DeltaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "delta is based on tick {}, but the world is of tick {}",
+            self.base_tick_num, self.world_tick_num
+        )
+    }
+}
+
+impl Error for DeltaMismatch {}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns the cells of `next` missing from `previous` and the other way around,
+/// both sorted.
+fn diff_cells(previous: &[Cell], next: &[Cell]) -> (Vec<Cell>, Vec<Cell>) {
+    let previous_set = previous.iter().collect::<HashSet<_>>();
+    let next_set = next.iter().collect::<HashSet<_>>();
+
+    let mut added = next
+        .iter()
+        .filter(|cell| !previous_set.contains(cell))
+        .copied()
+        .collect::<Vec<_>>();
+    let mut removed = previous
+        .iter()
+        .filter(|cell| !next_set.contains(cell))
+        .copied()
+        .collect::<Vec<_>>();
+    added.sort_unstable();
+    removed.sort_unstable();
+    (added, removed)
+}
+
+fn patch_cells(cells: &mut Vec<Cell>, added: &[Cell], removed: &[Cell]) {
+    if !removed.is_empty() {
+        let removed = removed.iter().collect::<HashSet<_>>();
+        cells.retain(|cell| !removed.contains(cell));
+    }
+    if !added.is_empty() {
+        cells.extend_from_slice(added);
+        cells.sort_unstable();
+    }
+}
+
+/// The base of players which have just appeared.
+fn new_player() -> Player {
+    Player {
+        score: 0,
+        territory: vec![],
+        position: Cell(0, 0),
+        lines: vec![],
+        direction: None,
+        has_lost: false,
+        position_hidden: false,
+    }
+}
+
+impl WorldDelta {
+    /// The changes from `previous` to `next`.
+    pub fn between(previous: &World, next: &World) -> Self {
+        let empty = new_player();
+
+        let players = next
+            .players
+            .iter()
+            .map(|(id, player)| {
+                let base = previous.players.get(id).unwrap_or(&empty);
+                let (territory_added, territory_removed) =
+                    diff_cells(&base.territory, &player.territory);
+                let (lines_added, lines_removed) = diff_cells(&base.lines, &player.lines);
+                let delta = PlayerDelta {
+                    score: player.score,
+                    position: player.position,
+                    direction: player.direction,
+                    has_lost: player.has_lost,
+                    position_hidden: player.position_hidden,
+                    territory_added,
+                    territory_removed,
+                    lines_added,
+                    lines_removed,
+                };
+                (id.clone(), delta)
+            })
+            .collect();
+
+        let mut removed_players = previous
+            .players
+            .keys()
+            .filter(|id| !next.players.contains_key(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        removed_players.sort_unstable();
+
+        Self {
+            base_tick_num: previous.tick_num,
+            tick_num: next.tick_num,
+            players,
+            removed_players,
+            bonuses: next.bonuses.clone(),
+            warmup: next.warmup,
+        }
+    }
+}
+
+impl World {
+    /// Turns the world of the base tick of the delta into the world of its tick.
+    ///
+    /// Cells of the players are kept sorted, as the server sends them in full ticks, so
+    /// the result is exactly the world it would have sent. On a mismatch the world isn't
+    /// changed.
+    pub fn apply_delta(&mut self, delta: &WorldDelta) -> Result<(), DeltaMismatch> {
+        if self.tick_num != delta.base_tick_num {
+            return Err(DeltaMismatch {
+                world_tick_num: self.tick_num,
+                base_tick_num: delta.base_tick_num,
+            });
+        }
+
+        for id in &delta.removed_players {
+            self.players.remove(id);
+        }
+        for (id, player_delta) in &delta.players {
+            let player = self.players.entry(id.clone()).or_insert_with(new_player);
+            player.score = player_delta.score;
+            player.position = player_delta.position;
+            player.direction = player_delta.direction;
+            player.has_lost = player_delta.has_lost;
+            player.position_hidden = player_delta.position_hidden;
+            patch_cells(
+                &mut player.territory,
+                &player_delta.territory_added,
+                &player_delta.territory_removed,
+            );
+            patch_cells(
+                &mut player.lines,
+                &player_delta.lines_added,
+                &player_delta.lines_removed,
+            );
+        }
+
+        self.tick_num = delta.tick_num;
+        self.bonuses.clone_from(&delta.bonuses);
+        self.warmup = delta.warmup;
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{BonusKind, Message};
+
+    fn player(territory: &[(i32, i32)], lines: &[(i32, i32)]) -> Player {
+        let cells = |cells: &[(i32, i32)]| {
+            let mut cells = cells.iter().map(|&(x, y)| Cell(x, y)).collect::<Vec<_>>();
+            cells.sort_unstable();
+            cells
+        };
+        Player {
+            score: territory.len() as u32,
+            territory: cells(territory),
+            position: Cell(5, 5),
+            lines: cells(lines),
+            direction: Some(Direction::Up),
+            has_lost: false,
+            position_hidden: false,
+        }
+    }
+
+    fn world(tick_num: u32, players: Vec<(&str, Player)>) -> World {
+        World {
+            players: players
+                .into_iter()
+                .map(|(id, player)| (id.to_string(), player))
+                .collect(),
+            tick_num,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let previous = world(
+            3,
+            vec![
+                ("i", player(&[(0, 0), (0, 1), (1, 1)], &[(2, 2), (2, 3)])),
+                ("2", player(&[(9, 9)], &[])),
+            ],
+        );
+        let mut next = world(
+            4,
+            vec![
+                ("i", player(&[(0, 1), (1, 1), (2, 2), (2, 3), (0, 2)], &[])),
+                ("3", player(&[(7, 7), (7, 8)], &[(6, 8)])),
+            ],
+        );
+        next.players.get_mut("i").unwrap().has_lost = true;
+        next.bonuses.push(Bonus {
+            kind: BonusKind::Slowdown,
+            position: Cell(4, 4),
+        });
+
+        let delta = WorldDelta::between(&previous, &next);
+        assert_eq!((delta.base_tick_num, delta.tick_num), (3, 4));
+        assert_eq!(delta.removed_players, ["2"]);
+        let me = &delta.players["i"];
+        assert_eq!(me.territory_added, [Cell(0, 2), Cell(2, 2), Cell(2, 3)]);
+        assert_eq!(me.territory_removed, [Cell(0, 0)]);
+        assert_eq!(me.lines_removed, [Cell(2, 2), Cell(2, 3)]);
+        assert!(me.has_lost);
+        assert_eq!(delta.players["3"].territory_added.len(), 2);
+
+        let mut patched = previous.clone();
+        patched.apply_delta(&delta).unwrap();
+        assert_eq!(patched, next);
+
+        // Nothing changes between equal worlds but the tick.
+        let delta = WorldDelta::between(
+            &next,
+            &World {
+                tick_num: 5,
+                ..next.clone()
+            },
+        );
+        assert!(delta.removed_players.is_empty());
+        assert!(delta.players.values().all(|player| {
+            player.territory_added.is_empty()
+                && player.territory_removed.is_empty()
+                && player.lines_added.is_empty()
+                && player.lines_removed.is_empty()
+        }));
+    }
+
+    #[test]
+    fn delta_mismatch() {
+        let previous = world(3, vec![("i", player(&[(0, 0)], &[]))]);
+        let next = world(4, vec![("i", player(&[(0, 0), (0, 1)], &[]))]);
+        let delta = WorldDelta::between(&previous, &next);
+
+        let mut stale = world(2, vec![]);
+        let err = stale.apply_delta(&delta).unwrap_err();
+        assert_eq!(
+            err,
+            DeltaMismatch {
+                world_tick_num: 2,
+                base_tick_num: 3,
+            }
+        );
+        assert_eq!(stale, world(2, vec![]));
+
+        let mut patched = previous.clone();
+        patched.apply_delta(&delta).unwrap();
+        assert!(patched.apply_delta(&delta).is_err());
+    }
+
+    #[test]
+    fn delta_json_round_trip() {
+        let previous = world(0, vec![("i", player(&[(0, 0)], &[]))]);
+        let next = world(1, vec![("i", player(&[(0, 0)], &[(0, 1)]))]);
+        let message = Message::TickDelta(WorldDelta::between(&previous, &next));
+
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["type"].as_str(), Some("tick_delta"));
+        assert!(value["params"]["players"]["i"]
+            .get("territory_added")
+            .is_none());
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), message);
+    }
+}
@@ -0,0 +1,72 @@
+//! Markers strategies write to stderr at the start of every tick when the launcher asks
+//! for them with `ENV_VAR`, so that it can tell where a strategy has got stuck.
+//!
+//! A marker is a line of `MARKER` followed by the tick number, e.g.
+//! `[paperio-heartbeat:5f3c9e1a] 57`. The magic keeps it apart from whatever else the
+//! strategy prints, even if a marker ends up after an unfinished line of its own.
+
+use std::io::{self, Write};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Set to `1` by the launcher to enable the markers.
+pub const ENV_VAR: &str = "PAPERIO_HEARTBEAT";
+
+pub const MARKER: &str = "[paperio-heartbeat:5f3c9e1a] ";
+
+/// Writes the marker of the tick with a single write, so that it isn't torn apart by the
+/// other output.
+pub fn write_marker(writer: &mut impl Write, tick_num: u32) -> io::Result<()> {
+    writer.write_all(format!("{MARKER}{tick_num}\n").as_bytes())
+}
+
+/// The tick number of the marker the line ends with, if any. The line may or may not
+/// end with a newline.
+pub fn parse_marker(line: &str) -> Option<u32> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let (_, tick_num) = line.rsplit_once(MARKER)?;
+    if tick_num.is_empty() || !tick_num.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    tick_num.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marker_roundtrip() {
+        for tick_num in [0, 1, 57, u32::MAX] {
+            let mut line = vec![];
+            write_marker(&mut line, tick_num).unwrap();
+            assert_eq!(line.last(), Some(&b'\n'));
+            let line = String::from_utf8(line).unwrap();
+            assert_eq!(parse_marker(&line), Some(tick_num));
+            assert_eq!(parse_marker(line.trim_end()), Some(tick_num));
+        }
+    }
+
+    #[test]
+    fn only_markers_are_parsed() {
+        assert_eq!(
+            parse_marker(&format!("thinking...{MARKER}12\r\n")),
+            Some(12)
+        );
+        for line in [
+            String::new(),
+            "57".to_string(),
+            "tick 57\n".to_string(),
+            "paperio-heartbeat 57\n".to_string(),
+            format!("{MARKER}\n"),
+            format!("{MARKER}-1\n"),
+            format!("{MARKER}+1\n"),
+            format!("{MARKER}57 and more\n"),
+            format!("{MARKER}99999999999\n"),
+            format!("{}57\n", MARKER.trim_end()),
+        ] {
+            assert_eq!(parse_marker(&line), None, "{line:?}");
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Per-cell lookups into a world, see `World::build_index_for`.
+
+use std::collections::VecDeque;
+
+use crate::{Cell, GameParams, PlayerId, World};
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn cell_count(params: &GameParams) -> usize {
+    params.x_cells_count as usize * params.y_cells_count as usize
+}
+
+fn grid_index(params: &GameParams, cell: Cell) -> Option<usize> {
+    params
+        .contains(cell)
+        .then(|| cell.0 as usize * params.y_cells_count as usize + cell.1 as usize)
+}
+
+/// Answers which player has a cell and how far the enemies are in constant time, while
+/// `Player::territory` and `Player::lines` only answer by a linear scan.
+///
+/// It's a snapshot of the world it's built from, so build one per tick. Cells out of
+/// the map belong to nobody.
+pub struct WorldIndex<'a> {
+    params: GameParams,
+    /// Sorted, so that player indices don't depend on the order of the map.
+    player_ids: Vec<&'a PlayerId>,
+    territory_owners: Vec<Option<usize>>,
+    /// A cell of the map per player and cell, as lines of several players can cross it.
+    lines: Vec<bool>,
+    enemy_distances: Vec<Option<u32>>,
+}
+
+impl World {
+    #[deprecated(note = "assumes the default map, use `World::build_index_for`")]
+    pub fn build_index(&self) -> WorldIndex<'_> {
+        self.build_index_for(GameParams::default())
+    }
+
+    /// Indexes the world on the map of `params`, cells out of it are ignored.
+    pub fn build_index_for(&self, params: GameParams) -> WorldIndex<'_> {
+        let mut player_ids = self.players.keys().collect::<Vec<_>>();
+        player_ids.sort_unstable();
+
+        let cell_count = cell_count(&params);
+        let mut territory_owners = vec![None; cell_count];
+        let mut lines = vec![false; cell_count * player_ids.len()];
+        for (i, id) in player_ids.iter().enumerate() {
+            let player = &self.players[*id];
+            for index in player
+                .territory
+                .iter()
+                .filter_map(|&cell| grid_index(&params, cell))
+            {
+                territory_owners[index] = Some(i);
+            }
+            for index in player
+                .lines
+                .iter()
+                .filter_map(|&cell| grid_index(&params, cell))
+            {
+                lines[i * cell_count + index] = true;
+            }
+        }
+
+        let heads = self
+            .iter_enemies()
+            .filter(|(_, enemy)| !enemy.has_lost && !enemy.position_hidden)
+            .map(|(_, enemy)| enemy.position);
+        WorldIndex {
+            params,
+            player_ids,
+            territory_owners,
+            lines,
+            enemy_distances: distances_from(&params, heads),
+        }
+    }
+}
+
+/// A multi-source BFS over the map.
+fn distances_from(params: &GameParams, sources: impl Iterator<Item = Cell>) -> Vec<Option<u32>> {
+    let mut distances = vec![None; cell_count(params)];
+    let mut queue = VecDeque::new();
+    for source in sources {
+        if let Some(index) = grid_index(params, source) {
+            if distances[index].is_none() {
+                distances[index] = Some(0);
+                queue.push_back((source, 0));
+            }
+        }
+    }
+
+    while let Some((cell, distance)) = queue.pop_front() {
+        for neighbour in params.iter_neighbours(cell) {
+            let index = grid_index(params, neighbour).unwrap();
+            if distances[index].is_none() {
+                distances[index] = Some(distance + 1);
+                queue.push_back((neighbour, distance + 1));
+            }
+        }
+    }
+    distances
+}
+
+impl<'a> WorldIndex<'a> {
+    fn player_index(&self, player_id: &str) -> Option<usize> {
+        self.player_ids
+            .binary_search_by(|id| id.as_str().cmp(player_id))
+            .ok()
+    }
+
+    /// The player the cell is the territory of.
+    pub fn owner_of(&self, cell: Cell) -> Option<&'a PlayerId> {
+        let owner = self.territory_owners[grid_index(&self.params, cell)?]?;
+        Some(self.player_ids[owner])
+    }
+
+    pub fn is_territory_of(&self, cell: Cell, player_id: &str) -> bool {
+        self.owner_of(cell).is_some_and(|owner| owner == player_id)
+    }
+
+    pub fn is_line_of(&self, cell: Cell, player_id: &str) -> bool {
+        match (grid_index(&self.params, cell), self.player_index(player_id)) {
+            (Some(index), Some(player)) => self.lines[player * cell_count(&self.params) + index],
+            _ => false,
+        }
+    }
+
+    /// The players whose lines pass through the cell.
+    pub fn line_owners(&self, cell: Cell) -> impl Iterator<Item = &'a PlayerId> + '_ {
+        let index = grid_index(&self.params, cell);
+        let cell_count = cell_count(&self.params);
+        self.player_ids
+            .iter()
+            .enumerate()
+            .filter(move |(player, _)| index.is_some_and(|i| self.lines[player * cell_count + i]))
+            .map(|(_, id)| *id)
+    }
+
+    /// The number of steps from the cell to the nearest head of an enemy which is still
+    /// in the game and not hidden by the fog. `None` if there are none.
+    pub fn nearest_enemy_distance(&self, cell: Cell) -> Option<u32> {
+        self.enemy_distances[grid_index(&self.params, cell)?]
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Player, MAP_SIZE_CELLS};
+
+    use std::{collections::HashMap, time::Instant};
+
+    /// A xorshift, as the crate doesn't depend on `rand`.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, bound: i32) -> i32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as i32
+        }
+
+        fn cell(&mut self) -> Cell {
+            Cell(self.below(MAP_SIZE_CELLS), self.below(MAP_SIZE_CELLS))
+        }
+    }
+
+    /// Territories are disjoint, lines may cross anything.
+    fn random_world(random: &mut Random, player_count: usize) -> World {
+        let mut owners = HashMap::new();
+        for _ in 0..cell_count(&GameParams::default()) / 2 {
+            owners.insert(random.cell(), random.below(player_count as i32) as usize);
+        }
+
+        let players = (0..player_count)
+            .map(|i| {
+                let id = if i == 0 {
+                    "i".to_string()
+                } else {
+                    i.to_string()
+                };
+                let player = Player {
+                    score: 0,
+                    territory: owners
+                        .iter()
+                        .filter(|(_, owner)| **owner == i)
+                        .map(|(cell, _)| *cell)
+                        .collect(),
+                    position: random.cell(),
+                    lines: (0..random.below(20)).map(|_| random.cell()).collect(),
+                    direction: None,
+                    has_lost: random.below(5) == 0,
+                    position_hidden: false,
+                };
+                (id, player)
+            })
+            .collect();
+        World {
+            players,
+            tick_num: 0,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    #[test]
+    fn index_matches_scans() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for player_count in [1, 2, 4, 6] {
+            let world = random_world(&mut random, player_count);
+            let index = world.build_index_for(GameParams::default());
+
+            let enemy_heads = world
+                .iter_enemies()
+                .filter(|(_, enemy)| !enemy.has_lost)
+                .map(|(_, enemy)| enemy.position)
+                .collect::<Vec<_>>();
+            for cell in GameParams::default().iter_cells() {
+                let owner = world
+                    .players
+                    .iter()
+                    .find(|(_, player)| player.territory.contains(&cell))
+                    .map(|(id, _)| id);
+                assert_eq!(index.owner_of(cell), owner);
+
+                for (id, player) in &world.players {
+                    assert_eq!(
+                        index.is_territory_of(cell, id),
+                        player.territory.contains(&cell)
+                    );
+                    assert_eq!(index.is_line_of(cell, id), player.lines.contains(&cell));
+                }
+                let mut line_owners = world
+                    .players
+                    .iter()
+                    .filter(|(_, player)| player.lines.contains(&cell))
+                    .map(|(id, _)| id)
+                    .collect::<Vec<_>>();
+                line_owners.sort_unstable();
+                assert_eq!(index.line_owners(cell).collect::<Vec<_>>(), line_owners);
+
+                let distance = enemy_heads
+                    .iter()
+                    .map(|head| cell.distance_to(*head) as u32)
+                    .min();
+                assert_eq!(index.nearest_enemy_distance(cell), distance);
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_map() {
+        let mut world = random_world(&mut Random(7), 3);
+        world.players.get_mut("1").unwrap().position_hidden = true;
+        world.players.get_mut("1").unwrap().has_lost = false;
+        world.players.get_mut("2").unwrap().has_lost = true;
+        let index = world.build_index_for(GameParams::default());
+
+        for cell in [Cell::HIDDEN, Cell(MAP_SIZE_CELLS, 0), Cell(0, -1)] {
+            assert_eq!(index.owner_of(cell), None);
+            assert!(!index.is_territory_of(cell, "i"));
+            assert!(!index.is_line_of(cell, "i"));
+            assert_eq!(index.line_owners(cell).count(), 0);
+            assert_eq!(index.nearest_enemy_distance(cell), None);
+        }
+        assert!(!index.is_line_of(Cell(0, 0), "unknown"));
+        assert_eq!(index.nearest_enemy_distance(Cell(0, 0)), None);
+    }
+
+    #[test]
+    fn smaller_map() {
+        let params = GameParams {
+            x_cells_count: 15,
+            y_cells_count: 9,
+        };
+        let mut world = random_world(&mut Random(11), 2);
+        let enemy = world.players.get_mut("1").unwrap();
+        enemy.territory = vec![Cell(14, 8), Cell(15, 0), Cell(20, 20)];
+        enemy.position = Cell(14, 8);
+        enemy.has_lost = false;
+        let index = world.build_index_for(params);
+
+        assert_eq!(index.owner_of(Cell(14, 8)).map(String::as_str), Some("1"));
+        for cell in [Cell(15, 0), Cell(20, 20), Cell(0, 9)] {
+            assert_eq!(index.owner_of(cell), None);
+            assert_eq!(index.nearest_enemy_distance(cell), None);
+        }
+        assert_eq!(index.nearest_enemy_distance(Cell(0, 0)), Some(22));
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn index_vs_contains() {
+        const ITERATIONS: u32 = 100;
+        let world = random_world(&mut Random(42), 6);
+
+        let start = Instant::now();
+        let mut scanned = 0;
+        for _ in 0..ITERATIONS {
+            for cell in GameParams::default().iter_cells() {
+                scanned += world
+                    .players
+                    .values()
+                    .filter(|player| player.territory.contains(&cell))
+                    .count();
+            }
+        }
+        let scan_time = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        let mut indexed = 0;
+        for _ in 0..ITERATIONS {
+            let index = world.build_index_for(GameParams::default());
+            for cell in GameParams::default().iter_cells() {
+                indexed += index.owner_of(cell).is_some() as usize;
+            }
+        }
+        let index_time = start.elapsed() / ITERATIONS;
+
+        assert_eq!(scanned, indexed);
+        println!(
+            "owners of all cells, 6 players: contains {scan_time:?}, \
+             build_index + owner_of {index_time:?}, speedup: {:.1}",
+            scan_time.as_secs_f64() / index_time.as_secs_f64()
+        );
+    }
+}
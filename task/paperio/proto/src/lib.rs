@@ -1,4 +1,9 @@
+pub mod binary;
+pub mod delta;
+pub mod heartbeat;
+pub mod index;
 pub mod traits;
+pub mod writer;
 
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
@@ -7,8 +12,13 @@ use strum::EnumIter;
 
 use std::{collections::HashMap, ops::Add};
 
+pub use delta::{DeltaMismatch, PlayerDelta, WorldDelta};
+pub use index::WorldIndex;
+
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The width and the height of the map of `GameParams::default`. Maps may be of other
+/// sizes, which `Message::StartGame` tells.
 pub const MAP_SIZE_CELLS: i32 = 31;
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -18,7 +28,46 @@ pub const MAP_SIZE_CELLS: i32 = 31;
 pub enum Message {
     StartGame(GameParams),
     Tick(World),
+    /// Sent instead of `Tick` to endpoints which ask for it, see `World::apply_delta`.
+    TickDelta(WorldDelta),
     EndGame {},
+    /// An out-of-band annotation for spectators, e.g. a caster's comment. Players
+    /// never receive it, and spectators don't reply to it.
+    Status {
+        tick_num: u32,
+        text: String,
+        level: StatusLevel,
+    },
+    /// The names of the players, sent to spectators right after `StartGame`, keyed as in
+    /// the ticks they get.
+    PlayerAnnouncement(HashMap<PlayerId, PlayerInfo>),
+    /// A message of a type this version doesn't know, see `traits::JsonRead`. It's never
+    /// sent.
+    #[serde(skip)]
+    Unknown {
+        message_type: String,
+    },
+}
+
+impl Message {
+    /// The `type` tags of all the messages that can be sent.
+    pub const TYPES: &'static [&'static str] = &[
+        "start_game",
+        "tick",
+        "tick_delta",
+        "end_game",
+        "status",
+        "player_announcement",
+    ];
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusLevel {
+    #[default]
+    Info,
+    Warning,
+    Alert,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
@@ -31,6 +80,14 @@ pub struct GameParams {
 pub struct World {
     pub players: HashMap<PlayerId, Player>,
     pub tick_num: u32,
+    #[serde(default)]
+    pub bonuses: Vec<Bonus>,
+    /// Set on the warm-up ticks before the game, when players move but can't capture
+    /// or lose anything. They are numbered as usual, from 1, and the game goes on with
+    /// the next tick number. It's omitted when unset, so that games without a warm-up
+    /// are sent as before.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warmup: bool,
 }
 
 pub type PlayerId = String;
@@ -42,7 +99,14 @@ pub struct Player {
     pub position: Cell,
     pub lines: Vec<Cell>,
     pub direction: Option<Direction>,
+    /// Older recordings don't have it, their players are read as still in the game.
+    #[serde(default)]
     pub has_lost: bool,
+    /// Set in fog-of-war games for an enemy whose head is out of sight: `position` is
+    /// `Cell::HIDDEN` then and `direction` is `None`. It's omitted when unset, so that
+    /// clients which don't know about the fog read the same messages as before.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub position_hidden: bool,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, FromPrimitive, EnumIter)]
@@ -54,13 +118,27 @@ pub enum Direction {
     Left,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Bonus {
+    pub kind: BonusKind,
+    pub position: Cell,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BonusKind {
+    Nitro,
+    Slowdown,
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub enum Command {
     ChangeDirection(Direction),
     NoOp,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq, Debug)]
+/// Cells are ordered by x and then by y, as in the vectors of `Player`.
+#[derive(Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Cell(pub i32, pub i32);
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -80,8 +158,42 @@ impl World {
         })
     }
 
+    #[deprecated(note = "assumes the default map, use `GameParams::iter_cells`")]
     pub fn iter_cells(&self) -> impl Iterator<Item = Cell> {
-        (0..MAP_SIZE_CELLS).flat_map(|x| (0..MAP_SIZE_CELLS).map(move |y| Cell(x, y)))
+        GameParams::default().iter_cells()
+    }
+}
+
+/// The map of `MAP_SIZE_CELLS` by `MAP_SIZE_CELLS` cells.
+impl Default for GameParams {
+    fn default() -> Self {
+        Self {
+            x_cells_count: MAP_SIZE_CELLS as u32,
+            y_cells_count: MAP_SIZE_CELLS as u32,
+        }
+    }
+}
+
+impl GameParams {
+    pub fn contains(&self, Cell(x, y): Cell) -> bool {
+        (0..self.x_cells_count as i32).contains(&x) && (0..self.y_cells_count as i32).contains(&y)
+    }
+
+    /// Ordered by x and then by y, as cells are.
+    pub fn iter_cells(&self) -> impl Iterator<Item = Cell> {
+        let height = self.y_cells_count as i32;
+        (0..self.x_cells_count as i32).flat_map(move |x| (0..height).map(move |y| Cell(x, y)))
+    }
+
+    pub fn iter_neighbours(&self, cell: Cell) -> impl Iterator<Item = Cell> {
+        let params = *self;
+        cell.iter_neighbours_unchecked()
+            .filter(move |&c| params.contains(c))
+    }
+
+    pub fn adjacent(&self, cell: Cell, dir: Direction) -> Option<Cell> {
+        let cell = cell.adjacent_unchecked(dir);
+        self.contains(cell).then_some(cell)
     }
 }
 
@@ -97,6 +209,9 @@ impl Direction {
 }
 
 impl Cell {
+    /// The position of a player hidden by the fog, out of any map.
+    pub const HIDDEN: Cell = Cell(-1, -1);
+
     pub fn distance_to(self, other: Cell) -> i32 {
         (other.0 - self.0).abs() + (other.1 - self.1).abs()
     }
@@ -122,8 +237,9 @@ impl Cell {
             .map(move |(dx, dy)| Cell(self.0 + dx, self.1 + dy))
     }
 
+    #[deprecated(note = "assumes the default map, use `GameParams::iter_neighbours`")]
     pub fn iter_neighbors(self) -> impl Iterator<Item = Cell> {
-        self.iter_neighbours_unchecked().filter(|c| c.in_bounds())
+        GameParams::default().iter_neighbours(self)
     }
 
     pub fn adjacent_unchecked(self, dir: Direction) -> Cell {
@@ -135,17 +251,14 @@ impl Cell {
         }
     }
 
+    #[deprecated(note = "assumes the default map, use `GameParams::adjacent`")]
     pub fn adjacent(self, dir: Direction) -> Option<Cell> {
-        let cell = self.adjacent_unchecked(dir);
-        if cell.in_bounds() {
-            Some(cell)
-        } else {
-            None
-        }
+        GameParams::default().adjacent(self, dir)
     }
 
+    #[deprecated(note = "assumes the default map, use `GameParams::contains`")]
     pub fn in_bounds(self) -> bool {
-        self.0 >= 0 && self.0 < MAP_SIZE_CELLS && self.1 >= 0 && self.1 < MAP_SIZE_CELLS
+        GameParams::default().contains(self)
     }
 }
 
@@ -171,7 +284,7 @@ pub struct GameReplay {
     pub messages: Vec<Message>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct PlayerInfo {
     pub user_name: String,
 }
@@ -182,6 +295,8 @@ pub struct PlayerInfo {
 mod test {
     use super::*;
 
+    use crate::traits::{JsonRead, JsonWrite};
+
     #[test]
     fn deserialize_test() {
         let start_game = serde_json::from_str::<Message>(
@@ -235,16 +350,342 @@ mod test {
                         lines: vec![Cell(1, 0), Cell(1, 1)],
                         direction: Some(Direction::Left),
                         has_lost: true,
+                        position_hidden: false,
                     }
                 )]
                 .into_iter()
                 .collect(),
                 tick_num: 748,
+                bonuses: vec![],
+                warmup: false,
             })
         );
 
         let end_game =
             serde_json::from_str::<Message>("{\"type\": \"end_game\", \"params\": {}}").unwrap();
         assert_eq!(end_game, Message::EndGame {});
+
+        let status = serde_json::from_str::<Message>(
+            r#"{
+                "type": "status",
+                "params": {"tick_num": 42, "text": "player 2 is about to cut player 3!", "level": "alert"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            status,
+            Message::Status {
+                tick_num: 42,
+                text: "player 2 is about to cut player 3!".to_string(),
+                level: StatusLevel::Alert,
+            }
+        );
+
+        let announcement = serde_json::from_str::<Message>(
+            r#"{
+                "type": "player_announcement",
+                "params": {"1": {"user_name": "alice"}, "2": {"user_name": "bob"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            announcement,
+            Message::PlayerAnnouncement(HashMap::from([
+                (
+                    "1".to_string(),
+                    PlayerInfo {
+                        user_name: "alice".to_string()
+                    }
+                ),
+                (
+                    "2".to_string(),
+                    PlayerInfo {
+                        user_name: "bob".to_string()
+                    }
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn tick_without_has_lost() {
+        // As recorded before `has_lost` was sent.
+        let tick = serde_json::from_str::<Message>(
+            r#"{
+                "type": "tick",
+                "params": {
+                    "players": {
+                        "i": {
+                            "score": 1,
+                            "territory": [[0, 0]],
+                            "position": [0, 0],
+                            "lines": [],
+                            "direction": null
+                        }
+                    },
+                    "tick_num": 1
+                }
+            }"#,
+        )
+        .unwrap();
+        let Message::Tick(world) = tick else {
+            panic!("expected a tick, got {tick:?}");
+        };
+        assert!(!world.me().has_lost);
+        assert_eq!(world.me().territory, [Cell(0, 0)]);
+    }
+
+    #[test]
+    fn status_round_trip() {
+        for level in [StatusLevel::Info, StatusLevel::Warning, StatusLevel::Alert] {
+            let status = Message::Status {
+                tick_num: 7,
+                text: "hello".to_string(),
+                level,
+            };
+            let mut buffer = vec![];
+            buffer.write_message(&status).unwrap();
+            assert_eq!(buffer.as_slice().read_message().unwrap(), status);
+        }
+    }
+
+    #[test]
+    fn message_types_are_known() {
+        let messages = [
+            Message::StartGame(GameParams {
+                x_cells_count: 1,
+                y_cells_count: 1,
+            }),
+            Message::Tick(World {
+                players: HashMap::new(),
+                tick_num: 0,
+                bonuses: vec![],
+                warmup: false,
+            }),
+            Message::TickDelta(WorldDelta {
+                base_tick_num: 0,
+                tick_num: 1,
+                players: HashMap::new(),
+                removed_players: vec![],
+                bonuses: vec![],
+                warmup: false,
+            }),
+            Message::EndGame {},
+            Message::Status {
+                tick_num: 0,
+                text: String::new(),
+                level: StatusLevel::Info,
+            },
+            Message::PlayerAnnouncement(HashMap::new()),
+        ];
+        for message in messages {
+            let value = serde_json::to_value(&message).unwrap();
+            let message_type = value["type"].as_str().unwrap();
+            assert!(Message::TYPES.contains(&message_type), "{message_type}");
+        }
+    }
+
+    #[test]
+    fn unknown_message_type() {
+        let mut reader = concat!(
+            r#"{"type": "replay_marker", "params": {"whatever": [1, 2, 3]}}"#,
+            "\n",
+            r#"{"type": "end_game", "params": {}}"#,
+            "\n",
+        )
+        .as_bytes();
+        assert_eq!(
+            reader.read_message().unwrap(),
+            Message::Unknown {
+                message_type: "replay_marker".to_string()
+            }
+        );
+        assert_eq!(reader.read_message().unwrap(), Message::EndGame {});
+
+        // Known messages with broken params are still errors.
+        let mut reader = r#"{"type": "tick", "params": {"players": 5}}"#.as_bytes();
+        assert!(reader.read_message().is_err());
+        let mut reader = "not a message".as_bytes();
+        assert!(reader.read_message().is_err());
+    }
+
+    #[test]
+    fn bonuses_test() {
+        let tick = serde_json::from_str::<Message>(
+            r#"{
+                "type": "tick",
+                "params": {
+                    "players": {},
+                    "tick_num": 5,
+                    "bonuses": [
+                        {"kind": "nitro", "position": [3, 4]},
+                        {"kind": "slowdown", "position": [0, 30]}
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let world = World {
+            players: HashMap::new(),
+            tick_num: 5,
+            bonuses: vec![
+                Bonus {
+                    kind: BonusKind::Nitro,
+                    position: Cell(3, 4),
+                },
+                Bonus {
+                    kind: BonusKind::Slowdown,
+                    position: Cell(0, 30),
+                },
+            ],
+            warmup: false,
+        };
+        assert_eq!(tick, Message::Tick(world.clone()));
+
+        let json = serde_json::to_string(&Message::Tick(world.clone())).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Message>(&json).unwrap(),
+            Message::Tick(world)
+        );
+
+        let world = World {
+            players: HashMap::new(),
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        };
+        let json = serde_json::to_string(&world).unwrap();
+        assert_eq!(serde_json::from_str::<World>(&json).unwrap(), world);
+    }
+
+    #[test]
+    fn hidden_position_round_trip() {
+        let hidden = Player {
+            score: 0,
+            territory: vec![Cell(3, 4)],
+            position: Cell::HIDDEN,
+            lines: vec![],
+            direction: None,
+            has_lost: false,
+            position_hidden: true,
+        };
+        let visible = Player {
+            position: Cell(3, 5),
+            direction: Some(Direction::Up),
+            position_hidden: false,
+            ..hidden.clone()
+        };
+
+        let json = serde_json::to_string(&hidden).unwrap();
+        assert_eq!(serde_json::from_str::<Player>(&json).unwrap(), hidden);
+        let value = serde_json::to_value(&hidden).unwrap();
+        assert_eq!(value["position_hidden"].as_bool(), Some(true));
+
+        // Messages without the fog don't change.
+        let value = serde_json::to_value(&visible).unwrap();
+        assert!(value.get("position_hidden").is_none());
+        let player = serde_json::from_str::<Player>(
+            r#"{
+                "score": 0,
+                "territory": [[3, 4]],
+                "position": [3, 5],
+                "lines": [],
+                "direction": "up",
+                "has_lost": false
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(player, visible);
+    }
+
+    #[test]
+    fn warmup_round_trip() {
+        let game = World {
+            players: HashMap::new(),
+            tick_num: 7,
+            bonuses: vec![],
+            warmup: false,
+        };
+        let warmup = World {
+            warmup: true,
+            ..game.clone()
+        };
+
+        let value = serde_json::to_value(&warmup).unwrap();
+        assert_eq!(value["warmup"].as_bool(), Some(true));
+        let json = serde_json::to_string(&warmup).unwrap();
+        assert_eq!(serde_json::from_str::<World>(&json).unwrap(), warmup);
+
+        // Games without a warm-up are sent as before.
+        let value = serde_json::to_value(&game).unwrap();
+        assert!(value.get("warmup").is_none());
+        let world =
+            serde_json::from_str::<World>(r#"{"players": {}, "tick_num": 7, "bonuses": []}"#)
+                .unwrap();
+        assert_eq!(world, game);
+    }
+
+    #[test]
+    fn bounds_of_params() {
+        let params = GameParams {
+            x_cells_count: 15,
+            y_cells_count: 9,
+        };
+        assert!(params.contains(Cell(14, 8)));
+        for cell in [
+            Cell(15, 0),
+            Cell(0, 9),
+            Cell(-1, 0),
+            Cell::HIDDEN,
+            Cell(20, 20),
+        ] {
+            assert!(!params.contains(cell), "{cell:?}");
+        }
+        assert_eq!(params.iter_cells().count(), 15 * 9);
+        assert!(params.iter_cells().all(|cell| params.contains(cell)));
+        assert!(params.iter_cells().is_sorted());
+
+        assert_eq!(params.adjacent(Cell(14, 4), Direction::Right), None);
+        assert_eq!(
+            params.adjacent(Cell(14, 8), Direction::Down),
+            Some(Cell(14, 7))
+        );
+        let mut corner = params.iter_neighbours(Cell(14, 8)).collect::<Vec<_>>();
+        corner.sort_unstable();
+        assert_eq!(corner, [Cell(13, 8), Cell(14, 7)]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn default_bounds_are_unchanged() {
+        let params = GameParams::default();
+        assert_eq!((params.x_cells_count, params.y_cells_count), (31, 31));
+
+        let world = World {
+            players: HashMap::new(),
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        };
+        assert!(world.iter_cells().eq(params.iter_cells()));
+        for cell in [
+            Cell(0, 0),
+            Cell(30, 30),
+            Cell(31, 0),
+            Cell(0, -1),
+            Cell(15, 31),
+        ] {
+            assert_eq!(cell.in_bounds(), params.contains(cell), "{cell:?}");
+            assert!(cell.iter_neighbors().eq(params.iter_neighbours(cell)));
+            for dir in [
+                Direction::Up,
+                Direction::Right,
+                Direction::Down,
+                Direction::Left,
+            ] {
+                assert_eq!(cell.adjacent(dir), params.adjacent(cell, dir));
+            }
+        }
     }
 }
@@ -1,8 +1,94 @@
-use std::io::{self, BufRead, Write};
+use std::{
+    env, fmt,
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
 
-use crate::{Command, Message};
+use serde::{Deserialize, Serialize};
+
+use crate::{binary, Command, Message};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How messages and commands are encoded on the wire: JSON lines, or length-prefixed
+/// frames of `binary`, which are several times smaller and faster to handle.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Binary,
+}
+
+impl Format {
+    /// Tells the format to strategies, which take no options, e.g. ones run by the wasm
+    /// launcher.
+    pub const ENV_VAR: &'static str = "PAPERIO_FORMAT";
+
+    /// From `ENV_VAR`, JSON if it's not set.
+    pub fn from_env() -> Result<Self, String> {
+        match env::var(Self::ENV_VAR) {
+            Ok(value) => value.parse(),
+            Err(env::VarError::NotPresent) => Ok(Self::Json),
+            Err(err) => Err(format!("invalid {}: {err}", Self::ENV_VAR)),
+        }
+    }
+
+    /// Appends the message to `buffer` the way it's sent, as a JSON line or a frame, so
+    /// that it can be written at once. Nothing is appended on errors.
+    pub fn encode_message(self, message: &Message, buffer: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Self::Json => encode_json_line(message, buffer),
+            Self::Binary => binary::encode_message_frame(message, buffer),
+        }
+    }
+
+    pub fn encode_command(self, command: &Command, buffer: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Self::Json => encode_json_line(command, buffer),
+            Self::Binary => {
+                binary::encode_command_frame(command, buffer);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn encode_json_line(value: &impl Serialize, buffer: &mut Vec<u8>) -> io::Result<()> {
+    let start = buffer.len();
+    if let Err(err) = serde_json::to_writer(&mut *buffer, value) {
+        buffer.truncate(start);
+        return Err(err.into());
+    }
+    buffer.push(b'\n');
+    Ok(())
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "binary" => Ok(Self::Binary),
+            _ => Err(format!("unknown format '{s}', expected 'json' or 'binary'")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
 
 pub trait JsonRead {
+    /// Messages of unknown types, e.g. sent by a newer server, are read as
+    /// `Message::Unknown`, so that older clients can skip them.
     fn read_message(&mut self) -> io::Result<Message>;
     fn read_command(&mut self) -> io::Result<Command>;
 }
@@ -16,7 +102,7 @@ impl<T: BufRead> JsonRead for T {
     fn read_message(&mut self) -> io::Result<Message> {
         let mut line = String::new();
         self.read_line(&mut line)?;
-        serde_json::from_str(&line).map_err(|err| err.into())
+        parse_message(&line)
     }
 
     fn read_command(&mut self) -> io::Result<Command> {
@@ -37,3 +123,70 @@ impl<T: Write> JsonWrite for T {
         self.write_all(b"\n")
     }
 }
+
+fn parse_message(line: &str) -> io::Result<Message> {
+    #[derive(Deserialize)]
+    struct Tagged {
+        #[serde(rename = "type")]
+        message_type: String,
+    }
+
+    serde_json::from_str(line).or_else(|err| match serde_json::from_str::<Tagged>(line) {
+        Ok(Tagged { message_type }) if !Message::TYPES.contains(&message_type.as_str()) => {
+            Ok(Message::Unknown { message_type })
+        }
+        _ => Err(err.into()),
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Same as `JsonRead`, in either format.
+pub trait MessageRead {
+    fn read_message_as(&mut self, format: Format) -> io::Result<Message>;
+    fn read_command_as(&mut self, format: Format) -> io::Result<Command>;
+}
+
+/// Same as `JsonWrite`, in either format.
+pub trait MessageWrite {
+    fn write_message_as(&mut self, format: Format, message: &Message) -> io::Result<()>;
+    fn write_command_as(&mut self, format: Format, command: &Command) -> io::Result<()>;
+}
+
+impl<T: BufRead> MessageRead for T {
+    fn read_message_as(&mut self, format: Format) -> io::Result<Message> {
+        match format {
+            Format::Json => self.read_message(),
+            Format::Binary => {
+                let mut payload = vec![];
+                binary::read_frame(self, &mut payload)?;
+                binary::decode_message(&payload)
+            }
+        }
+    }
+
+    fn read_command_as(&mut self, format: Format) -> io::Result<Command> {
+        match format {
+            Format::Json => self.read_command(),
+            Format::Binary => {
+                let mut payload = vec![];
+                binary::read_frame(self, &mut payload)?;
+                binary::decode_command(&payload)
+            }
+        }
+    }
+}
+
+impl<T: Write> MessageWrite for T {
+    fn write_message_as(&mut self, format: Format, message: &Message) -> io::Result<()> {
+        let mut buffer = vec![];
+        format.encode_message(message, &mut buffer)?;
+        self.write_all(&buffer)
+    }
+
+    fn write_command_as(&mut self, format: Format, command: &Command) -> io::Result<()> {
+        let mut buffer = vec![];
+        format.encode_command(command, &mut buffer)?;
+        self.write_all(&buffer)
+    }
+}
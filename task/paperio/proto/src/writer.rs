@@ -0,0 +1,270 @@
+//! Writing JSON lines without allocating per message, see `MessageWriter`.
+
+use serde::Serialize;
+
+use std::io;
+
+use crate::{Message, World};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Where the JSON of a player is in the scratch buffer: `"id":{...}`.
+struct PlayerEntry {
+    start: usize,
+    key_end: usize,
+    end: usize,
+}
+
+/// Encodes messages as JSON lines into a caller's buffer, keeping its own buffers
+/// between messages, so that a warmed-up writer doesn't allocate.
+///
+/// Unlike `JsonWrite`, ticks list players sorted by id, so the same world is always
+/// the same bytes whatever order its map iterates in, e.g. to compare recordings.
+/// Either way the lines read back the same.
+#[derive(Default)]
+pub struct MessageWriter {
+    scratch: Vec<u8>,
+    players: Vec<PlayerEntry>,
+}
+
+impl MessageWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the contents of `out` with the line of the message. `out` is left empty
+    /// on errors.
+    pub fn write_into(&mut self, message: &Message, out: &mut Vec<u8>) -> io::Result<()> {
+        out.clear();
+        let result = match message {
+            Message::Tick(world) => self.write_tick(world, out),
+            message => write_json(out, message),
+        };
+        match result {
+            Ok(()) => {
+                out.push(b'\n');
+                Ok(())
+            }
+            Err(err) => {
+                out.clear();
+                Err(err)
+            }
+        }
+    }
+
+    /// Same as serde's `Message::Tick`, but for the order of the players: each of them
+    /// is encoded into the scratch buffer, which is then copied in the sorted order.
+    fn write_tick(&mut self, world: &World, out: &mut Vec<u8>) -> io::Result<()> {
+        self.scratch.clear();
+        self.players.clear();
+        for (player_id, player) in &world.players {
+            let start = self.scratch.len();
+            write_json(&mut self.scratch, player_id)?;
+            let key_end = self.scratch.len();
+            self.scratch.push(b':');
+            write_json(&mut self.scratch, player)?;
+            self.players.push(PlayerEntry {
+                start,
+                key_end,
+                end: self.scratch.len(),
+            });
+        }
+        // Encoded ids are ordered as the ids themselves unless they need escaping.
+        let scratch = &self.scratch;
+        self.players
+            .sort_unstable_by(|a, b| scratch[a.start..a.key_end].cmp(&scratch[b.start..b.key_end]));
+
+        out.extend_from_slice(br#"{"type":"tick","params":{"players":{"#);
+        for (i, entry) in self.players.iter().enumerate() {
+            if i > 0 {
+                out.push(b',');
+            }
+            out.extend_from_slice(&scratch[entry.start..entry.end]);
+        }
+        out.extend_from_slice(br#"},"tick_num":"#);
+        write_json(out, &world.tick_num)?;
+        out.extend_from_slice(br#","bonuses":"#);
+        write_json(out, &world.bonuses)?;
+        if world.warmup {
+            out.extend_from_slice(br#","warmup":true"#);
+        }
+        out.extend_from_slice(b"}}");
+        Ok(())
+    }
+}
+
+fn write_json(out: &mut Vec<u8>, value: &(impl Serialize + ?Sized)) -> io::Result<()> {
+    serde_json::to_writer(out, value).map_err(io::Error::from)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        traits::{JsonRead, JsonWrite},
+        Bonus, BonusKind, Cell, Direction, Player, StatusLevel,
+    };
+
+    use std::collections::HashMap;
+
+    fn player(i: i32) -> Player {
+        Player {
+            score: i as u32,
+            territory: (0..i * 10).map(|j| Cell(j % 31, i)).collect(),
+            position: Cell(i, i),
+            lines: vec![Cell(i, i + 1)],
+            direction: Some(Direction::Up),
+            has_lost: i == 2,
+            position_hidden: false,
+        }
+    }
+
+    fn world(players: HashMap<String, Player>) -> World {
+        World {
+            players,
+            tick_num: 42,
+            bonuses: vec![Bonus {
+                kind: BonusKind::Nitro,
+                position: Cell(3, 4),
+            }],
+            warmup: false,
+        }
+    }
+
+    const IDS: [&str; 5] = ["1", "2", "3", "4", "i"];
+
+    #[test]
+    fn ticks_are_deterministic() {
+        let forward = IDS
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.to_string(), player(i as i32)))
+            .collect::<HashMap<_, _>>();
+        let mut backward = HashMap::with_capacity(100);
+        for (i, id) in IDS.iter().enumerate().rev() {
+            backward.insert(id.to_string(), player(i as i32));
+        }
+        assert_eq!(forward, backward);
+
+        let mut writer = MessageWriter::new();
+        let mut expected = vec![];
+        writer
+            .write_into(&Message::Tick(world(forward.clone())), &mut expected)
+            .unwrap();
+        for players in [forward, backward.clone(), backward] {
+            for _ in 0..3 {
+                let mut out = vec![];
+                writer
+                    .write_into(&Message::Tick(world(players.clone())), &mut out)
+                    .unwrap();
+                assert_eq!(out, expected);
+
+                let mut fresh = vec![];
+                MessageWriter::new()
+                    .write_into(&Message::Tick(world(players.clone())), &mut fresh)
+                    .unwrap();
+                assert_eq!(fresh, expected);
+            }
+        }
+
+        let line = String::from_utf8(expected).unwrap();
+        let positions = IDS.map(|id| line.find(&format!(r#""{id}":{{"#)).unwrap());
+        assert!(positions.is_sorted(), "{line}");
+    }
+
+    #[test]
+    fn ticks_read_back() {
+        let mut writer = MessageWriter::new();
+        let mut out = vec![];
+
+        // With a single player the order doesn't matter, so the bytes are serde's.
+        let tick = Message::Tick(world(HashMap::from([("i".to_string(), player(3))])));
+        writer.write_into(&tick, &mut out).unwrap();
+        let mut expected = vec![];
+        expected.write_message(&tick).unwrap();
+        assert_eq!(out, expected);
+
+        let tick = Message::Tick(world(
+            IDS.iter()
+                .map(|id| (id.to_string(), player(id.len() as i32)))
+                .collect(),
+        ));
+        writer.write_into(&tick, &mut out).unwrap();
+        assert_eq!(out.last(), Some(&b'\n'));
+        assert_eq!(out.as_slice().read_message().unwrap(), tick);
+
+        let empty = Message::Tick(World {
+            players: HashMap::new(),
+            tick_num: 0,
+            bonuses: vec![],
+            warmup: false,
+        });
+        writer.write_into(&empty, &mut out).unwrap();
+        assert_eq!(
+            out,
+            concat!(
+                r#"{"type":"tick","params":{"players":{},"tick_num":0,"bonuses":[]}}"#,
+                "\n"
+            )
+            .as_bytes()
+        );
+        assert_eq!(out.as_slice().read_message().unwrap(), empty);
+
+        let warmup = Message::Tick(World {
+            players: HashMap::new(),
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: true,
+        });
+        writer.write_into(&warmup, &mut out).unwrap();
+        let mut expected = vec![];
+        expected.write_message(&warmup).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn buffers_are_reused_without_residue() {
+        let messages = [
+            Message::Tick(world(
+                IDS.iter().map(|id| (id.to_string(), player(20))).collect(),
+            )),
+            Message::Tick(world(HashMap::from([("i".to_string(), player(1))]))),
+            Message::Status {
+                tick_num: 1,
+                text: "short".to_string(),
+                level: StatusLevel::Info,
+            },
+            Message::EndGame {},
+        ];
+
+        let mut writer = MessageWriter::new();
+        let mut out = vec![];
+        let mut capacities = None;
+        for message in &messages {
+            writer.write_into(message, &mut out).unwrap();
+            assert_eq!(out.as_slice().read_message().unwrap(), *message);
+            if !matches!(message, Message::Tick(_)) {
+                let mut expected = vec![];
+                expected.write_message(message).unwrap();
+                assert_eq!(out, expected);
+            }
+
+            // Messages get smaller, so nothing is reallocated after the first one.
+            let current = (
+                out.capacity(),
+                writer.scratch.capacity(),
+                writer.players.capacity(),
+            );
+            assert_eq!(*capacities.get_or_insert(current), current);
+        }
+
+        let unknown = Message::Unknown {
+            message_type: "replay_marker".to_string(),
+        };
+        assert!(writer.write_into(&unknown, &mut out).is_err());
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+//! A line-based TCP console to inspect and control a running game, see
+//! `spawn_admin_console`.
+
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use log::*;
+use paperio_proto::World;
+use serde::{Deserialize, Serialize};
+
+use crate::game::PlayerId;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AdminCommand {
+    /// Stops ticking until `Resume`, still answering the console.
+    Pause,
+    Resume,
+    Status,
+    /// Replies with the world spectators see.
+    DumpWorld,
+    /// Disconnects the player as an IO error would.
+    Kick(PlayerId),
+    /// Ends the game before the next tick, as if it has lasted all of its ticks.
+    End,
+    /// Makes every tick last at least this long, zero removes the limit.
+    SetTickMs(u64),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseAdminError {
+    Empty,
+    UnknownCommand(String),
+    UnknownSetting(String),
+    MissingArgument(&'static str),
+    InvalidArgument(String),
+    UnexpectedArgument(String),
+}
+
+impl fmt::Display for ParseAdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "command is empty"),
+            Self::UnknownCommand(command) => write!(
+                f,
+                "unknown command '{command}', expected 'pause', 'resume', 'status', \
+                'dump-world', 'kick', 'end' or 'set'"
+            ),
+            Self::UnknownSetting(setting) => {
+                write!(f, "unknown setting '{setting}', expected 'tick-ms'")
+            }
+            Self::MissingArgument(argument) => write!(f, "missing {argument}"),
+            Self::InvalidArgument(argument) => write!(f, "invalid argument '{argument}'"),
+            Self::UnexpectedArgument(argument) => write!(f, "unexpected argument '{argument}'"),
+        }
+    }
+}
+
+/// Parses a line like `kick 2` or `set tick-ms 100`.
+pub fn parse_admin_command(line: &str) -> Result<AdminCommand, ParseAdminError> {
+    let mut words = line.split_whitespace();
+    let mut argument = |name| words.next().ok_or(ParseAdminError::MissingArgument(name));
+
+    let command = match argument("command").map_err(|_| ParseAdminError::Empty)? {
+        "pause" => AdminCommand::Pause,
+        "resume" => AdminCommand::Resume,
+        "status" => AdminCommand::Status,
+        "dump-world" => AdminCommand::DumpWorld,
+        "end" => AdminCommand::End,
+        "kick" => {
+            let player_id = argument("player id")?;
+            player_id
+                .parse()
+                .ok()
+                .and_then(PlayerId::new)
+                .map(AdminCommand::Kick)
+                .ok_or_else(|| ParseAdminError::InvalidArgument(player_id.to_string()))?
+        }
+        "set" => match argument("setting")? {
+            "tick-ms" => {
+                let millis = argument("milliseconds")?;
+                millis
+                    .parse()
+                    .map(AdminCommand::SetTickMs)
+                    .map_err(|_| ParseAdminError::InvalidArgument(millis.to_string()))?
+            }
+            setting => return Err(ParseAdminError::UnknownSetting(setting.to_string())),
+        },
+        command => return Err(ParseAdminError::UnknownCommand(command.to_string())),
+    };
+
+    match words.next() {
+        Some(extra) => Err(ParseAdminError::UnexpectedArgument(extra.to_string())),
+        None => Ok(command),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PlayerStatus {
+    pub player_id: PlayerId,
+    pub score: u32,
+    pub has_lost: bool,
+    /// Unset after an IO error or a kick.
+    pub connected: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct GameStatus {
+    /// The tick to be played next.
+    pub tick: usize,
+    pub paused: bool,
+    pub tick_ms: Option<u64>,
+    pub players: Vec<PlayerStatus>,
+}
+
+/// A reply to a command, written as a JSON line tagged by `result`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum AdminResponse {
+    Ok,
+    Error { message: String },
+    Status(GameStatus),
+    World(World),
+}
+
+impl AdminResponse {
+    pub fn error(message: impl fmt::Display) -> Self {
+        Self::Error {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A command of the console to `Server::run`, which replies to it before the next tick.
+pub struct AdminRequest {
+    pub command: AdminCommand,
+    pub reply: Sender<AdminResponse>,
+}
+
+/// What the console has changed in the game, kept by `Server::run`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct AdminState {
+    pub paused: bool,
+    pub end_requested: bool,
+    pub tick_duration: Option<Duration>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Serves the console on `listener` in background threads, sessions are concurrent.
+/// With a `token`, it must be the first line of a session.
+pub fn spawn_admin_console(listener: TcpListener, token: Option<String>) -> Receiver<AdminRequest> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("failed to accept admin connection: {err}");
+                    continue;
+                }
+            };
+            let sender = sender.clone();
+            let token = token.clone();
+            thread::spawn(move || {
+                let result = stream.try_clone().and_then(|reader| {
+                    run_session(BufReader::new(reader), &stream, token.as_deref(), &sender)
+                });
+                if let Err(err) = result {
+                    warn!("admin session failed: {err}");
+                }
+            });
+        }
+    });
+    receiver
+}
+
+/// Answers the commands of `reader` line by line until it's closed. A wrong token ends
+/// the session right away.
+pub fn run_session(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    token: Option<&str>,
+    requests: &Sender<AdminRequest>,
+) -> io::Result<()> {
+    let mut lines = reader.lines();
+    if let Some(token) = token {
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        if line?.trim() != token {
+            return write_response(&mut writer, &AdminResponse::error("invalid token"));
+        }
+        write_response(&mut writer, &AdminResponse::Ok)?;
+    }
+
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match parse_admin_command(&line) {
+            Ok(command) => request(requests, command),
+            Err(err) => AdminResponse::error(err),
+        };
+        write_response(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+fn request(requests: &Sender<AdminRequest>, command: AdminCommand) -> AdminResponse {
+    let (reply, response) = mpsc::channel();
+    if requests.send(AdminRequest { command, reply }).is_err() {
+        return AdminResponse::error("the game is over");
+    }
+    response
+        .recv()
+        .unwrap_or_else(|_| AdminResponse::error("the game is over"))
+}
+
+fn write_response(writer: &mut impl Write, response: &AdminResponse) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, response)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(id: usize) -> PlayerId {
+        PlayerId::new(id).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        for (line, command) in [
+            ("pause", AdminCommand::Pause),
+            (" resume ", AdminCommand::Resume),
+            ("status\n", AdminCommand::Status),
+            ("dump-world", AdminCommand::DumpWorld),
+            ("kick 3", AdminCommand::Kick(player(3))),
+            ("end", AdminCommand::End),
+            ("set  tick-ms\t250", AdminCommand::SetTickMs(250)),
+            ("set tick-ms 0", AdminCommand::SetTickMs(0)),
+        ] {
+            assert_eq!(parse_admin_command(line), Ok(command), "{line}");
+        }
+    }
+
+    #[test]
+    fn parse_malformed() {
+        use ParseAdminError::*;
+
+        for (line, error) in [
+            ("  ", Empty),
+            ("stop", UnknownCommand("stop".to_string())),
+            ("kick", MissingArgument("player id")),
+            ("kick 0", InvalidArgument("0".to_string())),
+            ("kick two", InvalidArgument("two".to_string())),
+            ("set", MissingArgument("setting")),
+            ("set speed 2", UnknownSetting("speed".to_string())),
+            ("set tick-ms", MissingArgument("milliseconds")),
+            ("set tick-ms -1", InvalidArgument("-1".to_string())),
+            ("pause now", UnexpectedArgument("now".to_string())),
+            ("kick 1 2", UnexpectedArgument("2".to_string())),
+        ] {
+            assert_eq!(parse_admin_command(line), Err(error), "{line}");
+        }
+    }
+
+    /// Runs a session on `input`, answering its requests with `answer`. Returns the
+    /// commands requested and the response lines.
+    fn script_session(
+        input: &str,
+        token: Option<&str>,
+        answer: impl Fn(AdminCommand) -> AdminResponse + Send + 'static,
+    ) -> (Vec<AdminCommand>, Vec<String>) {
+        let (sender, receiver) = mpsc::channel::<AdminRequest>();
+        let server = thread::spawn(move || {
+            receiver
+                .iter()
+                .map(|request| {
+                    request.reply.send(answer(request.command)).unwrap();
+                    request.command
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut output = vec![];
+        run_session(input.as_bytes(), &mut output, token, &sender).unwrap();
+        drop(sender);
+
+        let commands = server.join().unwrap();
+        let lines = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        (commands, lines)
+    }
+
+    fn parse_response(line: &str) -> AdminResponse {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn session_forwards_commands() {
+        let (commands, lines) =
+            script_session("pause\n\nbogus\nkick 2\n", None, |command| match command {
+                AdminCommand::Kick(_) => AdminResponse::error("no such player"),
+                _ => AdminResponse::Ok,
+            });
+
+        assert_eq!(
+            commands,
+            [AdminCommand::Pause, AdminCommand::Kick(player(2))]
+        );
+        let responses = lines
+            .iter()
+            .map(|line| parse_response(line))
+            .collect::<Vec<_>>();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0], AdminResponse::Ok);
+        assert!(
+            matches!(&responses[1], AdminResponse::Error { message } if message.contains("bogus"))
+        );
+        assert_eq!(responses[2], AdminResponse::error("no such player"));
+    }
+
+    #[test]
+    fn session_checks_token() {
+        let (commands, lines) =
+            script_session("wrong\npause\n", Some("secret"), |_| AdminResponse::Ok);
+        assert!(commands.is_empty());
+        assert_eq!(lines.len(), 1);
+        assert_eq!(
+            parse_response(&lines[0]),
+            AdminResponse::error("invalid token")
+        );
+
+        let (commands, lines) = script_session("secret\npause\nresume\n", Some("secret"), |_| {
+            AdminResponse::Ok
+        });
+        assert_eq!(commands, [AdminCommand::Pause, AdminCommand::Resume]);
+        assert_eq!(lines.len(), 3);
+
+        let (commands, lines) = script_session("", Some("secret"), |_| AdminResponse::Ok);
+        assert!(commands.is_empty());
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn session_after_game() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+
+        let mut output = vec![];
+        run_session("status\n".as_bytes(), &mut output, None, &sender).unwrap();
+        let line = String::from_utf8(output).unwrap();
+        assert_eq!(
+            parse_response(line.trim()),
+            AdminResponse::error("the game is over")
+        );
+    }
+}
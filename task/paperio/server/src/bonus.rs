@@ -0,0 +1,213 @@
+use paperio_proto::{Bonus, BonusKind, Cell};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug)]
+pub struct BonusConfig {
+    /// A bonus spawn is attempted every `period` ticks.
+    pub period: u32,
+    /// No spawns happen while this many bonuses are lying on the map.
+    pub max_count: usize,
+    /// How many ticks the effect of a consumed bonus lasts.
+    pub duration: u32,
+    pub seed: u64,
+}
+
+impl BonusConfig {
+    /// The spawn period of `--bonuses` without `--bonus-rate`.
+    pub const DEFAULT_PERIOD: u32 = 20;
+    pub const DEFAULT_MAX_COUNT: usize = 3;
+    pub const DEFAULT_DURATION: u32 = 10;
+
+    pub fn new(period: u32, seed: u64) -> Self {
+        Self {
+            period,
+            max_count: Self::DEFAULT_MAX_COUNT,
+            duration: Self::DEFAULT_DURATION,
+            seed,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ActiveBonus {
+    pub kind: BonusKind,
+    pub ticks_left: u32,
+}
+
+/// How many cells a player moves on the tick with these effects: nitro moves it two
+/// cells, one after another, and slowdown only on even ticks. Both at once cancel out.
+pub fn cells_per_tick(bonuses: &[ActiveBonus], tick: u32) -> u32 {
+    let has = |kind| bonuses.iter().any(|bonus| bonus.kind == kind);
+    match (has(BonusKind::Nitro), has(BonusKind::Slowdown)) {
+        (true, false) => 2,
+        (false, true) => u32::from(tick.is_multiple_of(2)),
+        _ => 1,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub struct BonusSpawner {
+    config: BonusConfig,
+    rng: SplitMix64,
+}
+
+impl BonusSpawner {
+    pub fn new(config: BonusConfig) -> Self {
+        Self {
+            config,
+            rng: SplitMix64(config.seed),
+        }
+    }
+
+    pub fn config(&self) -> &BonusConfig {
+        &self.config
+    }
+
+    /// Places at most one bonus on a random cell for which `is_free` holds.
+    pub fn try_spawn(
+        &mut self,
+        tick: u32,
+        bonuses: &mut Vec<Bonus>,
+        cells: impl Iterator<Item = Cell>,
+        is_free: impl Fn(Cell) -> bool,
+    ) {
+        if self.config.period == 0
+            || !tick.is_multiple_of(self.config.period)
+            || bonuses.len() >= self.config.max_count
+        {
+            return;
+        }
+
+        let free_cells = cells
+            .filter(|&cell| is_free(cell) && bonuses.iter().all(|b| b.position != cell))
+            .collect::<Vec<_>>();
+        if free_cells.is_empty() {
+            return;
+        }
+
+        let position = free_cells[self.rng.next_below(free_cells.len())];
+        let kind = if self.rng.next_below(2) == 0 {
+            BonusKind::Nitro
+        } else {
+            BonusKind::Slowdown
+        };
+        bonuses.push(Bonus { kind, position });
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A small seedable RNG, so that the same seed plays the same game.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn next_below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::MAP_SIZE_CELLS;
+
+    fn all_cells() -> impl Iterator<Item = Cell> {
+        (0..MAP_SIZE_CELLS).flat_map(|x| (0..MAP_SIZE_CELLS).map(move |y| Cell(x, y)))
+    }
+
+    #[test]
+    fn spawns_on_period_up_to_max_count() {
+        let mut spawner = BonusSpawner::new(BonusConfig::new(5, 42));
+        let mut bonuses = vec![];
+
+        for tick in 1..5 {
+            spawner.try_spawn(tick, &mut bonuses, all_cells(), |_| true);
+        }
+        assert!(bonuses.is_empty());
+
+        for tick in 5..=100 {
+            spawner.try_spawn(tick, &mut bonuses, all_cells(), |_| true);
+        }
+        assert_eq!(bonuses.len(), BonusConfig::DEFAULT_MAX_COUNT);
+    }
+
+    #[test]
+    fn spawns_on_free_cells_only() {
+        for seed in 0..200 {
+            let mut config = BonusConfig::new(1, seed);
+            config.max_count = 10;
+            let mut spawner = BonusSpawner::new(config);
+            let mut bonuses = vec![];
+
+            let is_free = |Cell(x, y): Cell| (x + y) % 7 == 0 && x != 14;
+            for tick in 1..=20 {
+                spawner.try_spawn(tick, &mut bonuses, all_cells(), is_free);
+            }
+
+            assert_eq!(bonuses.len(), 10);
+            for (i, bonus) in bonuses.iter().enumerate() {
+                assert!(is_free(bonus.position));
+                assert!(bonuses[..i].iter().all(|b| b.position != bonus.position));
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_bonuses() {
+        let spawn = |seed| {
+            let mut spawner = BonusSpawner::new(BonusConfig::new(1, seed));
+            let mut bonuses = vec![];
+            for tick in 1..=3 {
+                spawner.try_spawn(tick, &mut bonuses, all_cells(), |_| true);
+            }
+            bonuses
+        };
+        assert_eq!(spawn(7), spawn(7));
+        assert_ne!(spawn(7), spawn(8));
+    }
+
+    #[test]
+    fn speed_of_effects() {
+        let active = |kinds: &[BonusKind]| {
+            kinds
+                .iter()
+                .map(|&kind| ActiveBonus {
+                    kind,
+                    ticks_left: 1,
+                })
+                .collect::<Vec<_>>()
+        };
+        for tick in [1, 2] {
+            assert_eq!(cells_per_tick(&[], tick), 1);
+            assert_eq!(cells_per_tick(&active(&[BonusKind::Nitro]), tick), 2);
+            let both = active(&[BonusKind::Slowdown, BonusKind::Nitro]);
+            assert_eq!(cells_per_tick(&both, tick), 1);
+        }
+        let slowdown = active(&[BonusKind::Slowdown, BonusKind::Slowdown]);
+        assert_eq!(cells_per_tick(&slowdown, 1), 0);
+        assert_eq!(cells_per_tick(&slowdown, 2), 1);
+    }
+
+    #[test]
+    fn no_spawn_without_free_cells() {
+        let mut spawner = BonusSpawner::new(BonusConfig::new(1, 0));
+        let mut bonuses = vec![];
+        spawner.try_spawn(1, &mut bonuses, all_cells(), |_| false);
+        assert!(bonuses.is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+//! Built-in players to fill the seats nobody connects to, see `BotEndpoint`.
+
+use std::{collections::HashSet, fmt, io, str::FromStr};
+
+use paperio_proto::{Cell, Command, Direction, GameParams, Message, Player, World};
+
+use crate::{bonus::SplitMix64, endpoint::Endpoint};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BotKind {
+    /// Walks along the edge of its territory, capturing a few cells at a time and
+    /// running home when an enemy comes close.
+    Coward,
+    /// Goes straight, turning at random now and then. It only avoids suicide.
+    Random,
+}
+
+impl FromStr for BotKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "coward" => Ok(Self::Coward),
+            "random" => Ok(Self::Random),
+            _ => Err(format!("unknown bot '{s}', expected 'coward' or 'random'")),
+        }
+    }
+}
+
+impl fmt::Display for BotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Coward => write!(f, "coward"),
+            Self::Random => write!(f, "random"),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The coward heads home once its lines are this long.
+const COWARD_MAX_LINES: usize = 4;
+/// The coward heads home when an enemy head is this many cells farther than the length
+/// of its lines, as the enemy would need about that many to cut them.
+const COWARD_DANGER_MARGIN: i32 = 3;
+/// The random walker turns on every this many ticks on average.
+const RANDOM_TURN_ODDS: usize = 5;
+
+/// A player in the process of the server, answering the ticks it's sent without any IO.
+/// `Message::TickDelta` is applied to the last full tick, so it works with deltas too.
+///
+/// It plays on the default map until `Message::StartGame` tells otherwise.
+pub struct BotEndpoint {
+    kind: BotKind,
+    rng: SplitMix64,
+    params: GameParams,
+    world: Option<World>,
+}
+
+impl BotEndpoint {
+    pub fn new(kind: BotKind, seed: u64) -> Self {
+        Self {
+            kind,
+            rng: SplitMix64(seed),
+            params: GameParams::default(),
+            world: None,
+        }
+    }
+
+    pub fn kind(&self) -> BotKind {
+        self.kind
+    }
+
+    fn choose_direction(&mut self, world: &World) -> Option<Direction> {
+        let me = world.me();
+        let safe = safe_directions(me, &self.params);
+        match self.kind {
+            BotKind::Coward => coward_direction(world, &safe),
+            BotKind::Random => {
+                let keeps_going = safe.first().is_some_and(|&dir| Some(dir) == me.direction);
+                if keeps_going && self.rng.next_below(RANDOM_TURN_ODDS) != 0 {
+                    return safe.first().copied();
+                }
+                (!safe.is_empty()).then(|| safe[self.rng.next_below(safe.len())])
+            }
+        }
+    }
+}
+
+impl Endpoint for BotEndpoint {
+    fn send_message(&mut self, message: &Message) -> io::Result<()> {
+        match message {
+            Message::StartGame(params) => self.params = *params,
+            Message::Tick(world) => self.world = Some(world.clone()),
+            Message::TickDelta(delta) => {
+                let world = self.world.as_mut().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "tick delta before a full tick")
+                })?;
+                world
+                    .apply_delta(delta)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn get_command(&mut self) -> io::Result<Command> {
+        let Some(world) = self.world.take() else {
+            return Ok(Command::NoOp);
+        };
+        let me = world.me();
+        let command = match self.choose_direction(&world) {
+            Some(dir) if !me.has_lost && Some(dir) != me.direction => Command::ChangeDirection(dir),
+            _ => Command::NoOp,
+        };
+        self.world = Some(world);
+        Ok(command)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The directions which don't end the game right away: no turning back, leaving the map
+/// or crossing own lines. The current direction goes first.
+fn safe_directions(me: &Player, params: &GameParams) -> Vec<Direction> {
+    let current = me.direction.unwrap_or(Direction::Up);
+    [
+        current,
+        current.next(true),
+        current.next(false),
+        current.opposite(),
+    ]
+    .into_iter()
+    .filter(|&dir| me.direction != Some(dir.opposite()))
+    .filter(|&dir| {
+        params
+            .adjacent(me.position, dir)
+            .is_some_and(|cell| !me.lines.contains(&cell))
+    })
+    .collect()
+}
+
+fn distance_to_territory(cell: Cell, me: &Player) -> i32 {
+    me.territory
+        .iter()
+        .map(|&own| cell.distance_to(own))
+        .min()
+        .unwrap_or(0)
+}
+
+fn coward_direction(world: &World, safe: &[Direction]) -> Option<Direction> {
+    let me = world.me();
+    let next = |dir: Direction| me.position.adjacent_unchecked(dir);
+
+    let threatened = world
+        .iter_enemies()
+        .filter(|(_, enemy)| !enemy.has_lost && !enemy.position_hidden)
+        .any(|(_, enemy)| {
+            enemy.position.distance_to(me.position) <= me.lines.len() as i32 + COWARD_DANGER_MARGIN
+        });
+    if !me.lines.is_empty() && (me.lines.len() >= COWARD_MAX_LINES || threatened) {
+        return safe
+            .iter()
+            .copied()
+            .min_by_key(|&dir| distance_to_territory(next(dir), me));
+    }
+
+    let territory = me.territory.iter().copied().collect::<HashSet<_>>();
+    if territory.contains(&me.position) && !threatened {
+        // Step out of the territory wherever the edge is.
+        if let Some(&dir) = safe.iter().find(|&&dir| !territory.contains(&next(dir))) {
+            return Some(dir);
+        }
+    }
+    safe.first().copied()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::WorldDelta;
+
+    use std::collections::HashMap;
+
+    fn square(center: Cell) -> Vec<Cell> {
+        let mut cells = (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| Cell(center.0 + dx, center.1 + dy)))
+            .collect::<Vec<_>>();
+        cells.sort_unstable();
+        cells
+    }
+
+    fn player(position: Cell, direction: Direction, lines: Vec<Cell>) -> Player {
+        Player {
+            score: 0,
+            territory: square(Cell(9, 9)),
+            position,
+            lines,
+            direction: Some(direction),
+            has_lost: false,
+            position_hidden: false,
+        }
+    }
+
+    fn world(me: Player, enemies: impl IntoIterator<Item = Cell>) -> World {
+        let mut players = HashMap::from([("i".to_string(), me)]);
+        for (i, position) in enemies.into_iter().enumerate() {
+            let mut enemy = player(position, Direction::Left, vec![]);
+            enemy.territory = square(Cell(25, 25));
+            players.insert((i + 2).to_string(), enemy);
+        }
+        World {
+            players,
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    fn command(bot: &mut BotEndpoint, world: World) -> Command {
+        bot.send_message(&Message::Tick(world)).unwrap();
+        bot.get_command().unwrap()
+    }
+
+    #[test]
+    fn parse_kind() {
+        for kind in [BotKind::Coward, BotKind::Random] {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+        }
+        assert!("aggressive".parse::<BotKind>().is_err());
+    }
+
+    #[test]
+    fn safe_directions_avoid_suicide() {
+        // At the left border, going down along own lines.
+        let params = GameParams::default();
+        let me = player(Cell(0, 5), Direction::Down, vec![Cell(1, 5), Cell(0, 6)]);
+        assert_eq!(safe_directions(&me, &params), [Direction::Down]);
+
+        let me = player(Cell(0, 0), Direction::Down, vec![Cell(1, 0)]);
+        assert!(safe_directions(&me, &params).is_empty());
+
+        // At the right border of a smaller map.
+        let params = GameParams {
+            x_cells_count: 15,
+            y_cells_count: 15,
+        };
+        let me = player(Cell(14, 5), Direction::Right, vec![Cell(13, 5)]);
+        assert_eq!(
+            safe_directions(&me, &params),
+            [Direction::Down, Direction::Up]
+        );
+    }
+
+    #[test]
+    fn coward_steps_out_and_comes_back() {
+        let mut bot = BotEndpoint::new(BotKind::Coward, 0);
+
+        // On the edge of the territory, going left along it.
+        let me = player(Cell(9, 10), Direction::Left, vec![]);
+        assert!(matches!(
+            command(&mut bot, world(me, [])),
+            Command::ChangeDirection(Direction::Up)
+        ));
+
+        // Out with short lines, keeps going.
+        let me = player(Cell(9, 12), Direction::Up, vec![Cell(9, 11)]);
+        assert!(matches!(command(&mut bot, world(me, [])), Command::NoOp));
+
+        // Out with long lines, turns back to the territory.
+        let lines = vec![Cell(9, 11), Cell(9, 12), Cell(9, 13), Cell(10, 13)];
+        let me = player(Cell(11, 13), Direction::Right, lines);
+        assert!(matches!(
+            command(&mut bot, world(me, [])),
+            Command::ChangeDirection(Direction::Down)
+        ));
+    }
+
+    #[test]
+    fn coward_runs_from_enemies() {
+        let mut bot = BotEndpoint::new(BotKind::Coward, 0);
+
+        let me = player(Cell(9, 12), Direction::Up, vec![Cell(9, 11)]);
+        assert!(matches!(
+            command(&mut bot, world(me.clone(), [Cell(20, 20)])),
+            Command::NoOp
+        ));
+        assert!(matches!(
+            command(&mut bot, world(me, [Cell(11, 13)])),
+            Command::ChangeDirection(Direction::Left | Direction::Right)
+        ));
+
+        // Stays home.
+        let me = player(Cell(9, 10), Direction::Left, vec![]);
+        assert!(matches!(
+            command(&mut bot, world(me, [Cell(8, 12)])),
+            Command::NoOp
+        ));
+    }
+
+    #[test]
+    fn random_is_seeded_and_safe() {
+        let walk = |seed| {
+            let mut bot = BotEndpoint::new(BotKind::Random, seed);
+            (0..100)
+                .map(|_| {
+                    let me = player(Cell(15, 15), Direction::Left, vec![]);
+                    match command(&mut bot, world(me, [])) {
+                        Command::ChangeDirection(dir) => dir,
+                        Command::NoOp => Direction::Left,
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let directions = walk(42);
+        assert_eq!(directions, walk(42));
+        assert_ne!(directions, walk(43));
+        assert!(!directions.contains(&Direction::Right));
+        let turns = directions
+            .iter()
+            .filter(|&&dir| dir != Direction::Left)
+            .count();
+        assert!((5..50).contains(&turns), "{turns}");
+    }
+
+    #[test]
+    fn deltas_need_a_full_tick() {
+        let mut bot = BotEndpoint::new(BotKind::Coward, 0);
+        assert!(matches!(bot.get_command().unwrap(), Command::NoOp));
+
+        let me = player(Cell(9, 10), Direction::Left, vec![]);
+        let delta = WorldDelta::between(&world(me.clone(), []), &world(me, []));
+        let err = bot.send_message(&Message::TickDelta(delta)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,219 @@
+//! Server-side accounting of the time players take to respond, see `BudgetConfig`.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What to do with a player who has exceeded its budget.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BudgetPolicy {
+    /// Log a warning and accept the command.
+    Warn,
+    /// Log a warning and ignore the command, as if the player sent `NoOp`.
+    NoOp,
+    /// Forfeit the player on the `max_violations`'th violation, warn before.
+    Forfeit,
+}
+
+impl FromStr for BudgetPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "noop" => Ok(Self::NoOp),
+            "forfeit" => Ok(Self::Forfeit),
+            _ => Err(format!(
+                "unknown budget policy '{s}', expected 'warn', 'noop' or 'forfeit'"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BudgetPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warn => write!(f, "warn"),
+            Self::NoOp => write!(f, "noop"),
+            Self::Forfeit => write!(f, "forfeit"),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Every player has a token bucket of response time: each tick adds `per_tick` to it,
+/// up to `cap`, and the time the player took to respond is taken from it. Responding
+/// in more time than there is in the bucket is a violation, handled by `policy`.
+///
+/// So fast ticks let a player spend more time on a later one, but not unboundedly.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BudgetConfig {
+    pub per_tick: Duration,
+    pub cap: Duration,
+    pub policy: BudgetPolicy,
+    pub max_violations: usize,
+}
+
+impl BudgetConfig {
+    pub const DEFAULT_CAP_TICKS: u32 = 5;
+
+    /// The cap is `DEFAULT_CAP_TICKS` times `per_tick`, a single violation forfeits.
+    pub fn new(per_tick: Duration, policy: BudgetPolicy) -> Self {
+        Self {
+            per_tick,
+            cap: per_tick * Self::DEFAULT_CAP_TICKS,
+            policy,
+            max_violations: 1,
+        }
+    }
+
+    /// The cap is never less than `per_tick`.
+    pub fn with_cap(mut self, cap: Duration) -> Self {
+        self.cap = cap.max(self.per_tick);
+        self
+    }
+
+    /// Only matters for `BudgetPolicy::Forfeit`.
+    pub fn with_max_violations(mut self, max_violations: usize) -> Self {
+        self.max_violations = max_violations;
+        self
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct BudgetStats {
+    /// Amount of the responses taken into account.
+    pub responses: u32,
+    pub total: Duration,
+    pub max: Duration,
+    pub violations: usize,
+}
+
+impl BudgetStats {
+    pub fn mean(&self) -> Duration {
+        self.total.checked_div(self.responses).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for BudgetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} responses, mean {:?}, max {:?}, {} violation(s)",
+            self.responses,
+            self.mean(),
+            self.max,
+            self.violations
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct PlayerBudget {
+    available: Duration,
+    stats: BudgetStats,
+}
+
+impl PlayerBudget {
+    /// Accounts a response of the next tick, returns whether it's a violation.
+    /// The bucket is emptied by a violation.
+    pub fn charge(&mut self, config: &BudgetConfig, elapsed: Duration) -> bool {
+        self.available = (self.available + config.per_tick).min(config.cap);
+
+        self.stats.responses += 1;
+        self.stats.total += elapsed;
+        self.stats.max = self.stats.max.max(elapsed);
+
+        let is_violation = elapsed > self.available;
+        self.available = self.available.saturating_sub(elapsed);
+        if is_violation {
+            self.stats.violations += 1;
+        }
+        is_violation
+    }
+
+    /// The time left in the bucket after the last response.
+    pub fn available(&self) -> Duration {
+        self.available
+    }
+
+    pub fn stats(&self) -> &BudgetStats {
+        &self.stats
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn token_bucket() {
+        let config = BudgetConfig::new(ms(10), BudgetPolicy::Warn).with_cap(ms(30));
+        let mut budget = PlayerBudget::default();
+
+        // Fast ticks accumulate the budget up to the cap.
+        assert!(!budget.charge(&config, ms(2)));
+        assert_eq!(budget.available(), ms(8));
+        assert!(!budget.charge(&config, ms(0)));
+        assert_eq!(budget.available(), ms(18));
+        for _ in 0..3 {
+            assert!(!budget.charge(&config, ms(0)));
+        }
+        assert_eq!(budget.available(), ms(30));
+
+        // A slow tick spends time saved by the fast ones.
+        assert!(!budget.charge(&config, ms(25)));
+        assert_eq!(budget.available(), ms(5));
+        assert!(budget.charge(&config, ms(20)));
+        assert_eq!(budget.available(), ms(0));
+        assert!(!budget.charge(&config, ms(10)));
+        assert!(budget.charge(&config, ms(11)));
+
+        assert_eq!(
+            *budget.stats(),
+            BudgetStats {
+                responses: 9,
+                total: ms(68),
+                max: ms(25),
+                violations: 2,
+            }
+        );
+        assert_eq!(budget.stats().mean(), Duration::from_nanos(68_000_000 / 9));
+    }
+
+    #[test]
+    fn cap_is_at_least_per_tick() {
+        let config = BudgetConfig::new(ms(10), BudgetPolicy::Warn).with_cap(ms(1));
+        assert_eq!(config.cap, ms(10));
+        assert_eq!(
+            BudgetConfig::new(ms(10), BudgetPolicy::Warn).cap,
+            ms(10) * BudgetConfig::DEFAULT_CAP_TICKS
+        );
+
+        let mut budget = PlayerBudget::default();
+        assert!(!budget.charge(&config, ms(10)));
+        assert!(budget.charge(&config, ms(11)));
+    }
+
+    #[test]
+    fn parse_policy() {
+        for policy in [
+            BudgetPolicy::Warn,
+            BudgetPolicy::NoOp,
+            BudgetPolicy::Forfeit,
+        ] {
+            assert_eq!(policy.to_string().parse::<BudgetPolicy>(), Ok(policy));
+        }
+        assert!("skip".parse::<BudgetPolicy>().is_err());
+        assert_eq!(BudgetStats::default().mean(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,65 @@
+//! Sending endpoints the changes of the world instead of full ticks, see
+//! `paperio_proto::WorldDelta`.
+
+use paperio_proto::{Message, World, WorldDelta};
+
+use crate::game::PlayerId;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeltaConfig {
+    /// A full tick is sent every this many ticks anyway, so that an endpoint which has
+    /// missed a delta recovers.
+    pub keyframe_interval: usize,
+    /// Other players get full ticks, e.g. the built-in bots which don't know deltas.
+    pub players: Vec<PlayerId>,
+    pub spectators: bool,
+}
+
+impl DeltaConfig {
+    pub const DEFAULT_KEYFRAME_INTERVAL: usize = 20;
+
+    pub fn new(players: impl IntoIterator<Item = PlayerId>, spectators: bool) -> Self {
+        Self {
+            keyframe_interval: Self::DEFAULT_KEYFRAME_INTERVAL,
+            players: players.into_iter().collect(),
+            spectators,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If the interval is zero.
+    pub fn with_keyframe_interval(mut self, interval: usize) -> Self {
+        assert!(interval > 0, "keyframe interval should be positive");
+        self.keyframe_interval = interval;
+        self
+    }
+
+    pub fn is_keyframe(&self, tick: usize) -> bool {
+        tick.is_multiple_of(self.keyframe_interval)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Remembers the last world sent to an endpoint.
+#[derive(Default)]
+pub struct DeltaEncoder {
+    last_sent: Option<World>,
+}
+
+impl DeltaEncoder {
+    /// A full tick on keyframes and when there is no world to base a delta on yet.
+    pub fn encode(&mut self, world: World, keyframe: bool) -> Message {
+        let message = match &self.last_sent {
+            Some(previous) if !keyframe => {
+                Message::TickDelta(WorldDelta::between(previous, &world))
+            }
+            _ => Message::Tick(world.clone()),
+        };
+        self.last_sent = Some(world);
+        message
+    }
+}
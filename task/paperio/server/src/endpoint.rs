@@ -1,13 +1,31 @@
-use std::io::{self, BufRead, Write};
+use std::{
+    io::{self, BufRead, Write},
+    net::TcpStream,
+    time::Duration,
+};
 
 use paperio_proto::{
-    traits::{JsonRead, JsonWrite},
+    binary,
+    traits::{Format, JsonRead},
+    writer::MessageWriter,
     Command, Message,
 };
 
 pub trait Endpoint {
     fn send_message(&mut self, message: &Message) -> io::Result<()>;
     fn get_command(&mut self) -> io::Result<Command>;
+
+    /// What has been written to the peer so far, zeros for endpoints which don't count.
+    fn write_stats(&self) -> WriteStats {
+        WriteStats::default()
+    }
+
+    /// Makes `get_command` fail with `WouldBlock` or `TimedOut` if the command doesn't
+    /// come in `timeout`, see `timeout::is_timeout`. Endpoints which can't time out
+    /// ignore it.
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 impl<'a, T: Endpoint> Endpoint for &'a mut T {
@@ -18,26 +36,368 @@ impl<'a, T: Endpoint> Endpoint for &'a mut T {
     fn get_command(&mut self) -> io::Result<Command> {
         T::get_command(self)
     }
+
+    fn write_stats(&self) -> WriteStats {
+        T::write_stats(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        T::set_read_timeout(self, timeout)
+    }
 }
 
-pub struct JsonEndpoint<R, W> {
+impl<T: Endpoint + ?Sized> Endpoint for Box<T> {
+    fn send_message(&mut self, message: &Message) -> io::Result<()> {
+        T::send_message(self, message)
+    }
+
+    fn get_command(&mut self) -> io::Result<Command> {
+        T::get_command(self)
+    }
+
+    fn write_stats(&self) -> WriteStats {
+        T::write_stats(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        T::set_read_timeout(self, timeout)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteStats {
+    pub bytes_written: u64,
+    /// Calls of `write_all`, one per message.
+    pub writes: u64,
+}
+
+/// Talks to a peer over a byte stream in the negotiated format.
+///
+/// Every message is encoded into a buffer, which is reused, and sent with a single write
+/// and a flush, so the writer needs no buffering of its own. JSON ticks list players
+/// sorted, see `MessageWriter`.
+///
+/// Commands are read into a buffer of their own, which keeps the start of a command
+/// whose read has timed out, so the next read picks up where that one left off.
+pub struct StreamEndpoint<R, W> {
     reader: R,
     writer: W,
+    format: Format,
+    buffer: Vec<u8>,
+    /// What has been read from `reader`, but not parsed into commands yet.
+    unread: Vec<u8>,
+    json_writer: MessageWriter,
+    stats: WriteStats,
+    socket: Option<TcpStream>,
 }
 
-impl<R: BufRead, W: Write> JsonEndpoint<R, W> {
-    pub fn new(reader: R, writer: W) -> Self {
-        Self { reader, writer }
+impl<R: BufRead, W: Write> StreamEndpoint<R, W> {
+    pub fn new(reader: R, writer: W, format: Format) -> Self {
+        Self {
+            reader,
+            writer,
+            format,
+            buffer: vec![],
+            unread: vec![],
+            json_writer: MessageWriter::new(),
+            stats: WriteStats::default(),
+            socket: None,
+        }
+    }
+
+    /// The socket the reader reads from, which read timeouts are set on.
+    pub fn with_socket(mut self, socket: TcpStream) -> Self {
+        self.socket = Some(socket);
+        self
+    }
+
+    /// Parses the first command of `unread` and removes it from there, `None` if the
+    /// command hasn't been read in full yet.
+    fn parse_unread(&mut self) -> io::Result<Option<Command>> {
+        let mut unread = self.unread.as_slice();
+        let command = match self.format {
+            Format::Json => {
+                if !unread.contains(&b'\n') {
+                    return Ok(None);
+                }
+                unread.read_command()?
+            }
+            Format::Binary => {
+                let mut payload = vec![];
+                match binary::read_frame(&mut unread, &mut payload) {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(err) => return Err(err),
+                }
+                binary::decode_command(&payload)?
+            }
+        };
+        let len = self.unread.len() - unread.len();
+        self.unread.drain(..len);
+        Ok(Some(command))
     }
 }
 
-impl<R: BufRead, W: Write> Endpoint for JsonEndpoint<R, W> {
+impl<R: BufRead, W: Write> Endpoint for StreamEndpoint<R, W> {
     fn send_message(&mut self, message: &Message) -> io::Result<()> {
-        self.writer.write_message(message)?;
+        match self.format {
+            Format::Json => self.json_writer.write_into(message, &mut self.buffer)?,
+            Format::Binary => {
+                self.buffer.clear();
+                self.format.encode_message(message, &mut self.buffer)?;
+            }
+        }
+
+        self.writer.write_all(&self.buffer)?;
+        self.stats.writes += 1;
+        self.stats.bytes_written += self.buffer.len() as u64;
         self.writer.flush()
     }
 
     fn get_command(&mut self) -> io::Result<Command> {
-        self.reader.read_command()
+        loop {
+            if let Some(command) = self.parse_unread()? {
+                return Ok(command);
+            }
+
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if available.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let len = available.len();
+            self.unread.extend_from_slice(available);
+            self.reader.consume(len);
+        }
+    }
+
+    fn write_stats(&self) -> WriteStats {
+        self.stats
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.set_read_timeout(timeout),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::{
+        traits::{MessageRead, MessageWrite},
+        Cell, Direction, Player, StatusLevel, World,
+    };
+
+    use std::{
+        collections::HashMap,
+        io::BufReader,
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    fn messages() -> Vec<Message> {
+        let player = Player {
+            score: 17,
+            territory: (0..300).map(|i| Cell(i % 31, i / 31)).collect(),
+            position: Cell(3, 4),
+            lines: vec![Cell(3, 5)],
+            direction: None,
+            has_lost: false,
+            position_hidden: false,
+        };
+        let big = World {
+            players: HashMap::from([("i".to_string(), player.clone()), ("2".to_string(), player)]),
+            tick_num: 12,
+            bonuses: vec![],
+            warmup: false,
+        };
+        vec![
+            Message::Tick(big),
+            Message::EndGame {},
+            Message::Status {
+                tick_num: 13,
+                text: "short".to_string(),
+                level: StatusLevel::Warning,
+            },
+            Message::Tick(World {
+                players: HashMap::new(),
+                tick_num: 14,
+                bonuses: vec![],
+                warmup: false,
+            }),
+        ]
+    }
+
+    /// Counts the calls of `write`, each of which is a syscall for sockets.
+    struct CountingWriter<W> {
+        inner: W,
+        writes: Arc<AtomicUsize>,
+    }
+
+    impl<W: Write> Write for CountingWriter<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn buffer_is_reused_without_residue() {
+        for format in [Format::Json, Format::Binary] {
+            let mut endpoint = StreamEndpoint::new(io::empty(), vec![], format);
+            let mut expected = vec![];
+            let mut capacity = 0;
+            for message in messages() {
+                endpoint.send_message(&message).unwrap();
+                match format {
+                    Format::Json => {
+                        let mut line = vec![];
+                        MessageWriter::new()
+                            .write_into(&message, &mut line)
+                            .unwrap();
+                        expected.extend_from_slice(&line);
+                    }
+                    Format::Binary => expected.write_message_as(format, &message).unwrap(),
+                }
+                assert_eq!(endpoint.writer, expected);
+
+                // The first message is the largest one, so nothing is reallocated after it.
+                if capacity == 0 {
+                    capacity = endpoint.buffer.capacity();
+                }
+                assert_eq!(endpoint.buffer.capacity(), capacity);
+            }
+
+            let mut reader = endpoint.writer.as_slice();
+            for message in messages() {
+                assert_eq!(reader.read_message_as(format).unwrap(), message);
+            }
+            assert!(reader.is_empty());
+
+            assert_eq!(
+                endpoint.write_stats(),
+                WriteStats {
+                    bytes_written: expected.len() as u64,
+                    writes: 4,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn single_write_per_message() {
+        for format in [Format::Json, Format::Binary] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let client = thread::spawn(move || {
+                let mut reader = BufReader::new(TcpStream::connect(address).unwrap());
+                (0..messages().len())
+                    .map(|_| reader.read_message_as(format).unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_nodelay(true).unwrap();
+            let writes = Arc::new(AtomicUsize::new(0));
+            let writer = CountingWriter {
+                inner: stream.try_clone().unwrap(),
+                writes: writes.clone(),
+            };
+            let mut endpoint = StreamEndpoint::new(BufReader::new(stream), writer, format);
+            for (i, message) in messages().iter().enumerate() {
+                endpoint.send_message(message).unwrap();
+                assert_eq!(writes.load(Ordering::Relaxed), i + 1);
+            }
+
+            assert_eq!(client.join().unwrap(), messages());
+            assert_eq!(endpoint.write_stats().writes, 4);
+        }
+    }
+
+    #[test]
+    fn socket_read_timeout() {
+        for format in [Format::Json, Format::Binary] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let writer = stream.try_clone().unwrap();
+            let mut endpoint = StreamEndpoint::new(reader, writer, format).with_socket(stream);
+
+            endpoint
+                .set_read_timeout(Some(Duration::from_millis(20)))
+                .unwrap();
+            let err = endpoint.get_command().unwrap_err();
+            assert!(crate::timeout::is_timeout(&err), "{err}");
+
+            let command = Command::ChangeDirection(Direction::Left);
+            client.write_command_as(format, &command).unwrap();
+            assert!(matches!(
+                endpoint.get_command().unwrap(),
+                Command::ChangeDirection(Direction::Left)
+            ));
+
+            // Endpoints without a socket just block.
+            let mut endpoint = StreamEndpoint::new(io::empty(), vec![], format);
+            endpoint
+                .set_read_timeout(Some(Duration::from_millis(20)))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn command_split_by_timeout() {
+        for format in [Format::Json, Format::Binary] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let writer = stream.try_clone().unwrap();
+            let mut endpoint = StreamEndpoint::new(reader, writer, format).with_socket(stream);
+            endpoint
+                .set_read_timeout(Some(Duration::from_millis(20)))
+                .unwrap();
+
+            let mut commands = vec![];
+            for command in [Command::ChangeDirection(Direction::Up), Command::NoOp] {
+                format.encode_command(&command, &mut commands).unwrap();
+            }
+            let (first, rest) = commands.split_at(2);
+            client.write_all(first).unwrap();
+            let err = endpoint.get_command().unwrap_err();
+            assert!(crate::timeout::is_timeout(&err), "{err}");
+
+            // The start of the command is kept, and the command which follows it in the
+            // same read as its end is read later.
+            client.write_all(rest).unwrap();
+            assert!(matches!(
+                endpoint.get_command().unwrap(),
+                Command::ChangeDirection(Direction::Up)
+            ));
+            assert!(matches!(endpoint.get_command().unwrap(), Command::NoOp));
+            let err = endpoint.get_command().unwrap_err();
+            assert!(crate::timeout::is_timeout(&err), "{err}");
+
+            drop(client);
+            let err = endpoint.get_command().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
     }
 }
@@ -0,0 +1,161 @@
+//! Fog of war: players only see the enemies near their territory and head.
+
+use std::collections::VecDeque;
+
+use paperio_proto::{Cell, Direction};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FogConfig {
+    /// Enemy cells are visible within this Manhattan distance of the player's territory
+    /// or head.
+    pub radius: u32,
+    /// Send enemy scores as 0, wherever the enemies are.
+    pub hide_scores: bool,
+}
+
+impl FogConfig {
+    pub fn new(radius: u32) -> Self {
+        Self {
+            radius,
+            hide_scores: false,
+        }
+    }
+
+    pub fn with_hidden_scores(mut self) -> Self {
+        self.hide_scores = true;
+        self
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Cells within the radius of any of the sources, found with a breadth-first search
+/// from all the sources at once, so that it takes a single pass over the map.
+pub struct Visibility {
+    width: i32,
+    height: i32,
+    is_visible: Vec<bool>,
+}
+
+impl Visibility {
+    pub fn new(
+        width: usize,
+        height: usize,
+        sources: impl IntoIterator<Item = Cell>,
+        radius: u32,
+    ) -> Self {
+        let mut visibility = Self {
+            width: width as i32,
+            height: height as i32,
+            is_visible: vec![false; width * height],
+        };
+
+        let mut queue = VecDeque::new();
+        for cell in sources {
+            if visibility.mark(cell) {
+                queue.push_back((cell, 0));
+            }
+        }
+        while let Some((cell, distance)) = queue.pop_front() {
+            if distance == radius {
+                continue;
+            }
+            for direction in [
+                Direction::Up,
+                Direction::Right,
+                Direction::Down,
+                Direction::Left,
+            ] {
+                let next = cell + direction;
+                if visibility.mark(next) {
+                    queue.push_back((next, distance + 1));
+                }
+            }
+        }
+
+        visibility
+    }
+
+    pub fn is_visible(&self, cell: Cell) -> bool {
+        self.index(cell).is_some_and(|index| self.is_visible[index])
+    }
+
+    fn index(&self, Cell(x, y): Cell) -> Option<usize> {
+        let in_bounds = (0..self.width).contains(&x) && (0..self.height).contains(&y);
+        in_bounds.then(|| (x + y * self.width) as usize)
+    }
+
+    /// Returns `true` if the cell is in bounds and wasn't visible yet.
+    fn mark(&mut self, cell: Cell) -> bool {
+        match self.index(cell) {
+            Some(index) if !self.is_visible[index] => {
+                self.is_visible[index] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, Instant};
+
+    fn visible_cells(visibility: &Visibility, size: i32) -> Vec<Cell> {
+        (0..size)
+            .flat_map(|y| (0..size).map(move |x| Cell(x, y)))
+            .filter(|&cell| visibility.is_visible(cell))
+            .collect()
+    }
+
+    #[test]
+    fn radius() {
+        let visibility = Visibility::new(5, 5, [Cell(2, 2)], 0);
+        assert_eq!(visible_cells(&visibility, 5), [Cell(2, 2)]);
+
+        let visibility = Visibility::new(5, 5, [Cell(2, 2)], 1);
+        assert_eq!(
+            visible_cells(&visibility, 5),
+            [Cell(2, 1), Cell(1, 2), Cell(2, 2), Cell(3, 2), Cell(2, 3)]
+        );
+
+        let visibility = Visibility::new(5, 5, [Cell(0, 0), Cell(4, 4)], 2);
+        for cell in visible_cells(&visibility, 5) {
+            let distance = cell
+                .distance_to(Cell(0, 0))
+                .min(cell.distance_to(Cell(4, 4)));
+            assert!(distance <= 2, "{cell:?}");
+        }
+        assert_eq!(visible_cells(&visibility, 5).len(), 2 * 6);
+
+        let visibility = Visibility::new(5, 5, [Cell(4, 0)], u32::MAX);
+        assert_eq!(visible_cells(&visibility, 5).len(), 25);
+        assert!(!visibility.is_visible(Cell(-1, 0)));
+        assert!(!visibility.is_visible(Cell::HIDDEN));
+    }
+
+    #[test]
+    fn no_sources() {
+        let visibility = Visibility::new(5, 5, [], 10);
+        assert!(visible_cells(&visibility, 5).is_empty());
+    }
+
+    #[test]
+    fn full_board_is_fast() {
+        let size = 31;
+        let cells = (0..size).flat_map(|x| (0..size).map(move |y| Cell(x, y)));
+
+        let started = Instant::now();
+        for radius in 0..100 {
+            let visibility = Visibility::new(size as usize, size as usize, cells.clone(), radius);
+            assert!(visibility.is_visible(Cell(30, 30)));
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -1,19 +1,48 @@
 use std::{cmp::Ordering, collections::HashMap, num::NonZero};
 
-use paperio_proto::{self, Cell, Direction, GameParams, World};
+use paperio_proto::{self, Bonus, BonusKind, Cell, Direction, GameParams, World};
 
-use crate::{game_field::GameField, player_vec::PlayerIndexedVector};
+use crate::{
+    bonus::{cells_per_tick, ActiveBonus, BonusConfig, BonusSpawner},
+    fog::{FogConfig, Visibility},
+    game_field::GameField,
+    player_vec::PlayerIndexedVector,
+    trace::{GameTrace, LossReason, TraceEvent, TraceRecord},
+};
 
-const INIT_POS: [Cell; 4] = [Cell(9, 21), Cell(21, 21), Cell(21, 9), Cell(9, 9)];
-const X_CELLS_COUNT: u32 = 31;
-const Y_CELLS_COUNT: u32 = 31;
+/// The smallest width and height of a map, so that the starting squares fit with a gap
+/// between them.
+pub const MIN_MAP_SIZE: u32 = 9;
 
 pub type PlayerId = NonZero<usize>;
 
+/// Whether a map of this width or height can be played. The size must be odd, so that
+/// the starting positions are symmetric about the center of the map.
+pub fn is_valid_map_size(cells: u32) -> bool {
+    cells >= MIN_MAP_SIZE && cells % 2 == 1
+}
+
+/// Players start at the corners of a square around the center of the map, in this
+/// order: top left, top right, bottom right and bottom left. On the default map these
+/// are `(9, 21)`, `(21, 21)`, `(21, 9)` and `(9, 9)`.
+pub fn starting_positions(params: GameParams) -> [Cell; 4] {
+    let near = |cells: u32| ((cells - 1) * 3 / 10) as i32;
+    let far = |cells: u32| cells as i32 - 1 - near(cells);
+    let (left, right) = (near(params.x_cells_count), far(params.x_cells_count));
+    let (bottom, top) = (near(params.y_cells_count), far(params.y_cells_count));
+    [
+        Cell(left, top),
+        Cell(right, top),
+        Cell(right, bottom),
+        Cell(left, bottom),
+    ]
+}
+
 struct Player {
     score: u32,
     position: Cell,
     direction: Direction,
+    bonuses: Vec<ActiveBonus>,
 }
 
 impl Player {
@@ -22,6 +51,7 @@ impl Player {
             score: 0,
             position,
             direction: Direction::Left,
+            bonuses: vec![],
         }
     }
 }
@@ -30,24 +60,41 @@ pub struct Game {
     tick: u32,
     players: PlayerIndexedVector<Player>,
     has_lost: PlayerIndexedVector<bool>,
+    loss_reasons: PlayerIndexedVector<Option<LossReason>>,
     params: GameParams,
     field: GameField,
+    bonuses: Vec<Bonus>,
+    bonus_spawner: Option<BonusSpawner>,
+    fog: Option<FogConfig>,
+    trace: Option<GameTrace>,
+    /// The first tick of the game after the warm-up, see `with_warmup`.
+    first_scored_tick: u32,
 }
 
 impl Game {
+    /// A game on the default map, see `GameParams::default`.
     pub fn new(player_count: usize) -> Self {
-        let params = GameParams {
-            x_cells_count: X_CELLS_COUNT,
-            y_cells_count: Y_CELLS_COUNT,
-        };
+        Self::with_map(GameParams::default(), player_count)
+    }
+
+    /// # Panics
+    ///
+    /// If the map size isn't valid, see `is_valid_map_size`.
+    pub fn with_map(params: GameParams, player_count: usize) -> Self {
+        assert!(
+            is_valid_map_size(params.x_cells_count) && is_valid_map_size(params.y_cells_count),
+            "invalid map size {}x{}",
+            params.x_cells_count,
+            params.y_cells_count
+        );
         let mut field = GameField::new(
             params.x_cells_count as usize,
             params.y_cells_count as usize,
             player_count,
         );
-        let players: PlayerIndexedVector<Player> = INIT_POS
-            .iter()
-            .map(|&pos| Player::new(pos))
+        let players: PlayerIndexedVector<Player> = starting_positions(params)
+            .into_iter()
+            .map(Player::new)
             .take(player_count)
             .collect::<Vec<_>>()
             .into();
@@ -56,13 +103,67 @@ impl Game {
             field.init_player(player_id, player.position);
         }
         let has_lost = PlayerIndexedVector::new(player_count);
+        let loss_reasons = PlayerIndexedVector::new(player_count);
 
         Game {
             tick: 1,
             players,
             has_lost,
+            loss_reasons,
             params,
             field,
+            bonuses: vec![],
+            bonus_spawner: None,
+            fog: None,
+            trace: None,
+            first_scored_tick: 1,
+        }
+    }
+
+    pub fn with_bonuses(mut self, config: BonusConfig) -> Self {
+        self.bonus_spawner = Some(BonusSpawner::new(config));
+        self
+    }
+
+    /// Limits what players see of their enemies, see `FogConfig`. Spectators still see
+    /// everything.
+    pub fn with_fog(mut self, config: FogConfig) -> Self {
+        self.fog = Some(config);
+        self
+    }
+
+    /// Plays `ticks` warm-up ticks first, so that players can get ready. They move as
+    /// usual, but can't capture any territory, and instead of losing they are put back
+    /// to their starting positions with their traces cleared. Bonuses don't appear.
+    ///
+    /// The ticks are numbered as usual and flagged with `World::warmup`. After them every
+    /// player starts the game from its starting position, keeping the direction.
+    pub fn with_warmup(mut self, ticks: u32) -> Self {
+        self.first_scored_tick = self.tick + ticks;
+        self
+    }
+
+    pub fn is_warmup(&self) -> bool {
+        self.tick < self.first_scored_tick
+    }
+
+    /// Enables recording of `TraceEvent`s, see `take_trace_records`.
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(GameTrace::new());
+        self
+    }
+
+    /// Returns the records made since the previous call, nothing if the trace is disabled.
+    pub fn take_trace_records(&mut self) -> Vec<TraceRecord> {
+        self.trace
+            .as_mut()
+            .map(GameTrace::take_records)
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, event: impl FnOnce() -> TraceEvent) {
+        if let Some(trace) = &mut self.trace {
+            trace.record(self.tick, event());
         }
     }
 
@@ -70,17 +171,42 @@ impl Game {
         self.has_lost[i]
     }
 
+    pub fn loss_reason(&self, i: PlayerId) -> Option<LossReason> {
+        self.loss_reasons[i]
+    }
+
+    /// Makes the player lose right away, before the next tick.
+    pub fn forfeit(&mut self, player_id: PlayerId) {
+        if self.has_lost[player_id] {
+            return;
+        }
+        self.field.remove_player(player_id);
+        self.has_lost[player_id] = true;
+        let reason = LossReason::Forfeited;
+        self.loss_reasons[player_id] = Some(reason);
+        self.record(|| TraceEvent::PlayerLost { player_id, reason });
+    }
+
     pub fn get_game_params(&self) -> GameParams {
         self.params
     }
 
     pub fn try_change_direction(&mut self, player_id: PlayerId, new_direction: Direction) -> bool {
         let direction = &mut self.players[player_id].direction;
-        if new_direction == direction.opposite() {
-            return false;
+        let accepted = new_direction != direction.opposite();
+        if accepted {
+            *direction = new_direction;
         }
-        *direction = new_direction;
-        true
+        self.record(|| TraceEvent::DirectionChange {
+            player_id,
+            direction: new_direction,
+            accepted,
+        });
+        accepted
+    }
+
+    pub fn active_bonuses(&self, player_id: PlayerId) -> &[ActiveBonus] {
+        &self.players[player_id].bonuses
     }
 
     pub fn get_player_scores(&self) -> PlayerIndexedVector<u32> {
@@ -88,23 +214,65 @@ impl Game {
     }
 
     pub fn tick(&mut self) {
-        let mut next_position = self
+        // Every player moves on the first step, unless slowed down, and players with nitro
+        // also on the second one.
+        let cells_to_move = self
             .players
-            .map(|player| player.position + player.direction);
+            .map(|player| cells_per_tick(&player.bonuses, self.tick));
+        let step_count = cells_to_move.iter().map(|(_, &cells)| cells).max();
+        let mut taken = vec![];
+        for step in 0..step_count.unwrap_or(0) {
+            self.step(&cells_to_move.map(|&cells| cells > step));
+            taken.extend(self.take_bonuses());
+        }
 
-        let mut loses_in_this_tick = PlayerIndexedVector::new(self.players.len());
+        if !self.is_warmup() {
+            self.update_bonuses(taken);
+        }
+
+        self.tick += 1;
+        if self.tick == self.first_scored_tick && self.tick > 1 {
+            for player_id in self.players.iter_player_ids().collect::<Vec<_>>() {
+                if !self.has_lost[player_id] {
+                    let direction = self.players[player_id].direction;
+                    self.reset_player(player_id);
+                    self.players[player_id].direction = direction;
+                }
+            }
+        }
+    }
+
+    /// Puts the player back to its starting position as a new one, with its trace cleared.
+    fn reset_player(&mut self, player_id: PlayerId) {
+        self.field.clear_trace(player_id);
+        let start = starting_positions(self.params)[player_id.get() - 1];
+        self.players[player_id] = Player::new(start);
+    }
+
+    /// Moves the players for which `moves` is set by a cell, the others stay.
+    fn step(&mut self, moves: &PlayerIndexedVector<bool>) {
+        let mut next_position = self.players.map(|player| player.position);
+        for (player_id, next_position) in next_position.iter_mut() {
+            if moves[player_id] {
+                *next_position = *next_position + self.players[player_id].direction;
+            }
+        }
+
+        // The first reason found in this tick is kept.
+        let mut loses_in_this_tick =
+            PlayerIndexedVector::<Option<LossReason>>::new(self.players.len());
 
         // This phase we sift all the players that are out of borders
         // and collect info about players that collide head to head.
         let mut cell_to_contenders = HashMap::<Cell, Vec<PlayerId>>::new();
         for (player_id, next_position) in next_position.iter_mut() {
-            if self.has_lost[player_id] {
+            if self.has_lost[player_id] || !moves[player_id] {
                 continue;
             }
 
-            if !next_position.in_bounds() {
+            if !self.params.contains(*next_position) {
                 *next_position = self.players[player_id].position;
-                loses_in_this_tick[player_id] = true;
+                loses_in_this_tick[player_id].get_or_insert(LossReason::OutOfBounds);
             } else {
                 cell_to_contenders
                     .entry(*next_position)
@@ -151,9 +319,24 @@ impl Game {
                 Some(player_with_shortest_path)
             });
 
+            if let Some(trace) = &mut self.trace {
+                let trace_lengths = players
+                    .iter()
+                    .map(|&player_id| self.field.traced_cells(player_id).len())
+                    .collect();
+                let event = TraceEvent::HeadToHead {
+                    cell: pos,
+                    contenders: players.clone(),
+                    trace_lengths,
+                    owner: cell_owner,
+                    winner,
+                };
+                trace.record(self.tick, event);
+            }
+
             for &player_id in players {
                 if winner != Some(player_id) {
-                    loses_in_this_tick[player_id] = true
+                    loses_in_this_tick[player_id].get_or_insert(LossReason::HeadToHead);
                 }
             }
         }
@@ -161,21 +344,39 @@ impl Game {
         // This phase we process players, that capture territory.
         // That is they step into their territory.
         // If player moves within his territory, nothing happens.
+        // During the warm-up they only drop their traces.
         let player_positions = self.players.map(|p| p.position);
+        let warmup = self.is_warmup();
         for (player_id, player) in self.players.iter_mut() {
-            if loses_in_this_tick[player_id] || self.has_lost[player_id] {
+            if loses_in_this_tick[player_id].is_some()
+                || self.has_lost[player_id]
+                || !moves[player_id]
+            {
                 continue;
             }
 
             let cell_state = &self.field[next_position[player_id]];
-            if cell_state.is_captured_by(player_id) {
+            if cell_state.is_captured_by(player_id) && warmup {
+                self.field.clear_trace(player_id);
+            } else if cell_state.is_captured_by(player_id) {
                 let (enemy_cells_captured, free_cells_captured, enemies_captured) =
                     self.field.capture_all(player_id, &player_positions);
 
                 player.score += enemy_cells_captured * 5 + free_cells_captured;
 
+                if let Some(trace) = &mut self.trace {
+                    if enemy_cells_captured + free_cells_captured > 0 {
+                        let event = TraceEvent::TerritoryCaptured {
+                            player_id,
+                            enemy_cells: enemy_cells_captured,
+                            free_cells: free_cells_captured,
+                        };
+                        trace.record(self.tick, event);
+                    }
+                }
+
                 for &enemy_id in &enemies_captured {
-                    loses_in_this_tick[enemy_id] = true;
+                    loses_in_this_tick[enemy_id].get_or_insert(LossReason::Encircled);
                 }
             }
         }
@@ -184,7 +385,7 @@ impl Game {
         // If two players cross each other at the same time, then the shortest trace wins.
         // If players have traces of the same length, then both of them lose.
         for (my_id, _) in self.players.iter_mut() {
-            if loses_in_this_tick[my_id] || self.has_lost[my_id] {
+            if loses_in_this_tick[my_id].is_some() || self.has_lost[my_id] || !moves[my_id] {
                 continue;
             }
 
@@ -192,7 +393,7 @@ impl Game {
             if let Some(other_id) = my_cell_state.is_traced() {
                 if other_id == my_id {
                     // Self cross.
-                    loses_in_this_tick[my_id] = true;
+                    loses_in_this_tick[my_id].get_or_insert(LossReason::TraceCrossed);
                 }
 
                 // We cross someones path, chech if he crosses our path.
@@ -211,14 +412,17 @@ impl Game {
                     &[other_id]
                 };
                 for &loser_id in losers {
-                    loses_in_this_tick[loser_id] = true;
+                    loses_in_this_tick[loser_id].get_or_insert(LossReason::TraceCrossed);
                 }
             }
         }
 
         // This phase we move players and set their traces.
         for (player_id, player) in self.players.iter_mut() {
-            if loses_in_this_tick[player_id] || self.has_lost[player_id] {
+            if loses_in_this_tick[player_id].is_some()
+                || self.has_lost[player_id]
+                || !moves[player_id]
+            {
                 continue;
             }
 
@@ -229,18 +433,103 @@ impl Game {
             player.position = next_position[player_id];
         }
 
+        // During the warm-up the players are reset instead.
+        if warmup {
+            for player_id in self.players.iter_player_ids().collect::<Vec<_>>() {
+                if loses_in_this_tick[player_id].is_some() {
+                    self.reset_player(player_id);
+                }
+            }
+            return;
+        }
+
         // This phase we marks player that have lost in this tick.
         for (player_id, has_lost) in self.has_lost.iter_mut() {
-            if loses_in_this_tick[player_id] {
+            if let Some(reason) = loses_in_this_tick[player_id] {
                 self.field.remove_player(player_id);
                 *has_lost = true;
+                self.loss_reasons[player_id] = Some(reason);
+                if let Some(trace) = &mut self.trace {
+                    trace.record(self.tick, TraceEvent::PlayerLost { player_id, reason });
+                }
             }
         }
+    }
 
-        self.tick += 1;
+    /// Removes the bonuses the heads of the players are on.
+    fn take_bonuses(&mut self) -> Vec<(PlayerId, BonusKind)> {
+        let mut taken = vec![];
+        for (player_id, player) in self.players.iter() {
+            if self.has_lost[player_id] {
+                continue;
+            }
+            if let Some(index) = self
+                .bonuses
+                .iter()
+                .position(|bonus| bonus.position == player.position)
+            {
+                taken.push((player_id, self.bonuses.remove(index).kind));
+            }
+        }
+        taken
+    }
+
+    fn update_bonuses(&mut self, taken: Vec<(PlayerId, BonusKind)>) {
+        let Some(spawner) = &mut self.bonus_spawner else {
+            return;
+        };
+
+        for (player_id, player) in self.players.iter_mut() {
+            if self.has_lost[player_id] {
+                player.bonuses.clear();
+                continue;
+            }
+
+            for bonus in player.bonuses.iter_mut() {
+                bonus.ticks_left -= 1;
+            }
+            player.bonuses.retain(|bonus| bonus.ticks_left > 0);
+        }
+        // Effects taken this tick start working from the next one.
+        for (player_id, kind) in taken {
+            if !self.has_lost[player_id] {
+                self.players[player_id].bonuses.push(ActiveBonus {
+                    kind,
+                    ticks_left: spawner.config().duration,
+                });
+            }
+        }
+
+        let (width, height) = (
+            self.params.x_cells_count as i32,
+            self.params.y_cells_count as i32,
+        );
+        let cells = (0..width).flat_map(|x| (0..height).map(move |y| Cell(x, y)));
+        let is_free = |cell| {
+            self.field[cell].is_free()
+                && self
+                    .players
+                    .iter()
+                    .all(|(_, player)| player.position != cell)
+        };
+        spawner.try_spawn(self.tick, &mut self.bonuses, cells, is_free);
     }
 
     pub fn get_player_world(&self, i: PlayerId) -> World {
+        let fog = self
+            .fog
+            .filter(|_| self.players.iter_player_ids().any(|id| id == i));
+        let visibility = fog.map(|config| {
+            let (territory, _) = self.field.get_for_player(i);
+            let sources = territory.iter().copied().chain([self.players[i].position]);
+            Visibility::new(
+                self.params.x_cells_count as usize,
+                self.params.y_cells_count as usize,
+                sources,
+                config.radius,
+            )
+        });
+
         let players = self
             .players
             .iter()
@@ -251,15 +540,27 @@ impl Game {
                     id.get().to_string()
                 };
 
+                // Sorted, so that the same cells are sent the same way on every tick,
+                // see `World::apply_delta`.
                 let (territory, lines) = self.field.get_for_player(id);
-                let proto_player = paperio_proto::Player {
+                let mut territory = territory.iter().copied().collect::<Vec<_>>();
+                territory.sort_unstable();
+                let mut lines = lines.iter().copied().collect::<Vec<_>>();
+                lines.sort_unstable();
+                let mut proto_player = paperio_proto::Player {
                     score: player.score,
-                    territory: territory.iter().copied().collect(),
+                    territory,
                     position: player.position,
-                    lines: lines.iter().copied().collect(),
+                    lines,
                     direction: Some(player.direction),
                     has_lost: self.has_lost(id),
+                    position_hidden: false,
                 };
+                if let (Some(config), Some(visibility)) = (fog, &visibility) {
+                    if id != i {
+                        hide_in_fog(&mut proto_player, config, visibility);
+                    }
+                }
 
                 (str_id, proto_player)
             })
@@ -267,6 +568,8 @@ impl Game {
         World {
             players,
             tick_num: self.tick,
+            bonuses: self.bonuses.clone(),
+            warmup: self.is_warmup(),
         }
     }
 
@@ -274,6 +577,40 @@ impl Game {
         self.get_player_world(NonZero::new(usize::MAX).unwrap())
     }
 
+    pub fn territory_share(&self, player_id: PlayerId) -> f64 {
+        let (territory, _) = self.field.get_for_player(player_id);
+        let area = self.params.x_cells_count * self.params.y_cells_count;
+        territory.len() as f64 / area as f64
+    }
+
+    pub fn max_territory_share(&self) -> f64 {
+        self.players
+            .iter_player_ids()
+            .map(|player_id| self.territory_share(player_id))
+            .fold(0., f64::max)
+    }
+
+    pub fn territory_leader_id(&self) -> Option<PlayerId> {
+        let territory_size = |player_id| self.field.get_for_player(player_id).0.len();
+        let player_id = self
+            .players
+            .iter_player_ids()
+            .max_by_key(|&player_id| territory_size(player_id))
+            .unwrap();
+        let leader_size = territory_size(player_id);
+        if self
+            .players
+            .iter_player_ids()
+            .filter(|&id| territory_size(id) == leader_size)
+            .count()
+            > 1
+        {
+            None
+        } else {
+            Some(player_id)
+        }
+    }
+
     pub fn leader_id(&self) -> Option<PlayerId> {
         let player_id = self
             .players
@@ -295,3 +632,552 @@ impl Game {
         }
     }
 }
+
+fn hide_in_fog(player: &mut paperio_proto::Player, config: FogConfig, visibility: &Visibility) {
+    player.territory.retain(|&cell| visibility.is_visible(cell));
+    player.lines.retain(|&cell| visibility.is_visible(cell));
+    if !visibility.is_visible(player.position) {
+        player.position = Cell::HIDDEN;
+        player.direction = None;
+        player.position_hidden = true;
+    }
+    if config.hide_scores {
+        player.score = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    fn first_player() -> PlayerId {
+        PlayerId::new(1).unwrap()
+    }
+
+    fn second_player() -> PlayerId {
+        PlayerId::new(2).unwrap()
+    }
+
+    /// Plays one tick per item, changing directions first.
+    fn play(game: &mut Game, script: &[Vec<(PlayerId, Direction)>]) {
+        for changes in script {
+            for &(player_id, direction) in changes {
+                game.try_change_direction(player_id, direction);
+            }
+            game.tick();
+        }
+    }
+
+    fn losses(records: &[TraceRecord]) -> Vec<TraceRecord> {
+        records
+            .iter()
+            .filter(|record| matches!(record.event, TraceEvent::PlayerLost { .. }))
+            .cloned()
+            .collect()
+    }
+
+    fn lost(tick: u32, player_id: PlayerId, reason: LossReason) -> TraceRecord {
+        TraceRecord {
+            tick,
+            event: TraceEvent::PlayerLost { player_id, reason },
+        }
+    }
+
+    // Player 1 turns towards player 2 on the top row of their territories,
+    // so that they meet at (15, 22) with traces of the same length.
+    fn head_to_head_script() -> Vec<Vec<(PlayerId, Direction)>> {
+        let (p1, p2) = (first_player(), second_player());
+        let mut script = vec![vec![]; 7];
+        script[0] = vec![
+            (p1, Direction::Right),
+            (p1, Direction::Up),
+            (p2, Direction::Up),
+        ];
+        script[1] = vec![(p1, Direction::Right), (p2, Direction::Left)];
+        script
+    }
+
+    fn with_bonus(kind: BonusKind, position: Cell, duration: u32) -> Game {
+        let mut config = BonusConfig::new(1000, 0);
+        config.duration = duration;
+        let mut game = Game::new(1).with_bonuses(config);
+        game.bonuses.push(Bonus { kind, position });
+        game
+    }
+
+    fn position(game: &Game) -> Cell {
+        game.players[first_player()].position
+    }
+
+    #[test]
+    fn nitro_moves_two_cells() {
+        // The player starts at (9, 21) heading left.
+        let mut game = with_bonus(BonusKind::Nitro, Cell(7, 21), 3);
+        game.tick();
+        assert_eq!(game.get_spectator_world().bonuses.len(), 1);
+        assert!(game.active_bonuses(first_player()).is_empty());
+
+        game.tick();
+        assert!(game.get_spectator_world().bonuses.is_empty());
+        assert_eq!(position(&game), Cell(7, 21));
+        assert_eq!(
+            game.active_bonuses(first_player()),
+            &[ActiveBonus {
+                kind: BonusKind::Nitro,
+                ticks_left: 3,
+            }]
+        );
+
+        for x in [5, 3, 1] {
+            game.tick();
+            assert_eq!(position(&game), Cell(x, 21));
+        }
+        assert!(game.active_bonuses(first_player()).is_empty());
+        // Both cells of a step are traced.
+        let world = game.get_spectator_world();
+        assert_eq!(world.players["1"].lines.len(), 7);
+
+        game.tick();
+        assert_eq!(position(&game), Cell(0, 21));
+        assert_eq!(game.get_player_scores()[first_player()], 0);
+    }
+
+    #[test]
+    fn slowdown_moves_every_other_tick() {
+        let mut game = with_bonus(BonusKind::Slowdown, Cell(8, 21), 4);
+        game.tick();
+        assert_eq!(game.active_bonuses(first_player()).len(), 1);
+
+        // Only on even ticks while slowed down.
+        for x in [7, 7, 6, 6] {
+            game.tick();
+            assert_eq!(position(&game), Cell(x, 21));
+        }
+        assert!(game.active_bonuses(first_player()).is_empty());
+
+        game.tick();
+        assert_eq!(position(&game), Cell(5, 21));
+    }
+
+    #[test]
+    fn nitro_closes_loop_sooner() {
+        // Up from the territory, left, down and right back into it.
+        let ticks_to_capture = |mut game: Game| {
+            for tick in 1..30 {
+                let Cell(x, y) = position(&game);
+                let direction = if x >= 9 && y < 25 {
+                    Direction::Up
+                } else if y >= 25 && x > 6 {
+                    Direction::Left
+                } else if y > 21 {
+                    Direction::Down
+                } else {
+                    Direction::Right
+                };
+                game.try_change_direction(first_player(), direction);
+                game.tick();
+                assert!(!game.has_lost(first_player()));
+                if game.get_player_scores()[first_player()] > 0 {
+                    return tick;
+                }
+            }
+            panic!("the loop is never closed");
+        };
+
+        let without_nitro = with_bonus(BonusKind::Nitro, Cell(0, 0), 10);
+        assert_eq!(ticks_to_capture(without_nitro), 13);
+        let with_nitro = with_bonus(BonusKind::Nitro, Cell(9, 23), 10);
+        assert_eq!(ticks_to_capture(with_nitro), 9);
+    }
+
+    #[test]
+    fn bonuses_spawn_on_free_cells() {
+        let directions = [
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+        ];
+
+        for seed in 0..20 {
+            let mut config = BonusConfig::new(1, seed);
+            config.max_count = 30;
+            let mut game = Game::new(4).with_bonuses(config);
+
+            for tick in 0..60 {
+                for player_id in game.players.iter_player_ids() {
+                    let turn = (tick / 3 + player_id.get()) % directions.len();
+                    game.try_change_direction(player_id, directions[turn]);
+                }
+                let old_bonuses = game.bonuses.clone();
+                game.tick();
+
+                // Territory may be captured around a bonus later, only check new ones.
+                let world = game.get_spectator_world();
+                let new_bonuses = world.bonuses.iter().filter(|b| !old_bonuses.contains(b));
+                for bonus in new_bonuses {
+                    for player in world.players.values().filter(|p| !p.has_lost) {
+                        assert_ne!(bonus.position, player.position);
+                        assert!(!player.territory.contains(&bonus.position));
+                        assert!(!player.lines.contains(&bonus.position));
+                    }
+                }
+            }
+
+            assert!(!game.get_spectator_world().bonuses.is_empty());
+        }
+    }
+
+    #[test]
+    fn trace_out_of_bounds() {
+        let mut game = Game::new(2).with_trace();
+        play(&mut game, &vec![vec![]; 10]);
+
+        let records = game.take_trace_records();
+        assert_eq!(
+            losses(&records),
+            [lost(10, first_player(), LossReason::OutOfBounds)]
+        );
+        assert_eq!(
+            game.loss_reason(first_player()),
+            Some(LossReason::OutOfBounds)
+        );
+        assert_eq!(game.loss_reason(second_player()), None);
+        assert!(game.take_trace_records().is_empty());
+    }
+
+    #[test]
+    fn trace_head_to_head() {
+        let mut game = Game::new(2).with_trace();
+        play(&mut game, &head_to_head_script());
+
+        let records = game.take_trace_records();
+        assert_eq!(
+            records[..2],
+            [
+                TraceRecord {
+                    tick: 1,
+                    event: TraceEvent::DirectionChange {
+                        player_id: first_player(),
+                        direction: Direction::Right,
+                        accepted: false,
+                    },
+                },
+                TraceRecord {
+                    tick: 1,
+                    event: TraceEvent::DirectionChange {
+                        player_id: first_player(),
+                        direction: Direction::Up,
+                        accepted: true,
+                    },
+                },
+            ]
+        );
+        assert!(records.contains(&TraceRecord {
+            tick: 7,
+            event: TraceEvent::HeadToHead {
+                cell: Cell(15, 22),
+                contenders: vec![first_player(), second_player()],
+                trace_lengths: vec![4, 4],
+                owner: None,
+                winner: None,
+            },
+        }));
+        assert_eq!(
+            losses(&records),
+            [
+                lost(7, first_player(), LossReason::HeadToHead),
+                lost(7, second_player(), LossReason::HeadToHead),
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_crossed() {
+        let mut game = Game::new(2).with_trace();
+        let p1 = first_player();
+        let mut script = vec![vec![]; 6];
+        script[3] = vec![(p1, Direction::Up)];
+        script[4] = vec![(p1, Direction::Right)];
+        script[5] = vec![(p1, Direction::Down)];
+        play(&mut game, &script);
+
+        let records = game.take_trace_records();
+        assert_eq!(losses(&records), [lost(6, p1, LossReason::TraceCrossed)]);
+        assert_eq!(game.loss_reason(p1), Some(LossReason::TraceCrossed));
+    }
+
+    #[test]
+    fn trace_encircled() {
+        let mut game = Game::new(2).with_trace();
+        let p1 = first_player();
+        let p2 = second_player();
+        // A loop over the top left corner of the territory around (8, 23).
+        let mut script = vec![vec![]; 7];
+        script[0] = vec![(p1, Direction::Up)];
+        script[3] = vec![(p1, Direction::Left)];
+        script[5] = vec![(p1, Direction::Down)];
+        play(&mut game, &script);
+
+        // Player 2 can't get inside without crossing the trace, so it is put there.
+        game.players[p2].position = Cell(8, 23);
+        play(&mut game, &[vec![(p1, Direction::Right)]]);
+
+        let records = game.take_trace_records();
+        assert!(records.contains(&TraceRecord {
+            tick: 8,
+            event: TraceEvent::TerritoryCaptured {
+                player_id: p1,
+                enemy_cells: 0,
+                free_cells: 7,
+            },
+        }));
+        assert_eq!(losses(&records), [lost(8, p2, LossReason::Encircled)]);
+        assert_eq!(game.loss_reason(p2), Some(LossReason::Encircled));
+    }
+
+    #[test]
+    fn disabled_trace_records_nothing() {
+        let mut game = Game::new(2);
+        play(&mut game, &head_to_head_script());
+
+        assert!(game.trace.is_none());
+        assert!(game.take_trace_records().is_empty());
+        assert_eq!(
+            game.loss_reason(first_player()),
+            Some(LossReason::HeadToHead)
+        );
+    }
+
+    #[test]
+    fn fog_hides_far_enemies() {
+        let enemy = |game: &mut Game, radius| {
+            game.fog = Some(FogConfig::new(radius));
+            game.get_player_world(first_player()).players["2"].clone()
+        };
+        let mut game = Game::new(2);
+        game.players[second_player()].score = 5;
+        // The territories are 3x3 squares around (9, 21) and (21, 21).
+        let full = game.get_player_world(first_player()).players["2"].clone();
+        assert_eq!(full.territory.len(), 9);
+
+        let hidden = enemy(&mut game, 0);
+        assert!(hidden.territory.is_empty());
+        assert_eq!(hidden.position, Cell::HIDDEN);
+        assert_eq!(hidden.direction, None);
+        assert!(hidden.position_hidden);
+        assert_eq!(hidden.score, 5);
+
+        let edge = enemy(&mut game, 10);
+        assert_eq!(edge.territory.len(), 3);
+        assert!(edge.territory.iter().all(|cell| cell.0 == 20));
+        assert!(edge.position_hidden);
+
+        let head = enemy(&mut game, 11);
+        assert_eq!(head.position, Cell(21, 21));
+        assert_eq!(head.direction, Some(Direction::Left));
+        assert!(!head.position_hidden);
+        assert_eq!(head.territory.len(), 6);
+
+        assert_eq!(enemy(&mut game, u32::MAX), full);
+    }
+
+    #[test]
+    fn fog_keeps_own_and_spectator_view() {
+        let mut game = Game::new(2).with_fog(FogConfig::new(0).with_hidden_scores());
+        game.players[first_player()].score = 3;
+        game.players[second_player()].score = 5;
+
+        let world = game.get_player_world(first_player());
+        assert_eq!(world.me().territory.len(), 9);
+        assert_eq!(world.me().score, 3);
+        assert!(!world.me().position_hidden);
+        assert_eq!(world.players["2"].score, 0);
+
+        let spectator_world = game.get_spectator_world();
+        for player in spectator_world.players.values() {
+            assert_eq!(player.territory.len(), 9);
+            assert!(!player.position_hidden);
+        }
+        assert_eq!(spectator_world.players["2"].score, 5);
+    }
+
+    fn map(width: u32, height: u32) -> GameParams {
+        GameParams {
+            x_cells_count: width,
+            y_cells_count: height,
+        }
+    }
+
+    #[test]
+    fn map_sizes() {
+        for cells in [9, 15, 31, 101] {
+            assert!(is_valid_map_size(cells), "{cells}");
+        }
+        for cells in [0, 1, 7, 10, 30] {
+            assert!(!is_valid_map_size(cells), "{cells}");
+        }
+    }
+
+    #[test]
+    #[should_panic = "invalid map size 16x15"]
+    fn even_map_size() {
+        Game::with_map(map(16, 15), 2);
+    }
+
+    #[test]
+    fn default_map_is_unchanged() {
+        assert_eq!(
+            starting_positions(GameParams::default()),
+            [Cell(9, 21), Cell(21, 21), Cell(21, 9), Cell(9, 9)]
+        );
+        let game = Game::new(4);
+        assert_eq!(game.get_game_params(), map(31, 31));
+        let world = game.get_spectator_world();
+        for (player_id, position) in [("1", Cell(9, 21)), ("4", Cell(9, 9))] {
+            let player = &world.players[player_id];
+            assert_eq!(player.position, position);
+            assert_eq!(player.territory.len(), 9);
+            assert!(player
+                .territory
+                .iter()
+                .all(|cell| cell.distance_to(position) <= 2));
+        }
+    }
+
+    #[test]
+    fn smaller_map() {
+        for (params, positions) in [
+            (
+                map(15, 15),
+                [Cell(4, 10), Cell(10, 10), Cell(10, 4), Cell(4, 4)],
+            ),
+            (
+                map(9, 21),
+                [Cell(2, 14), Cell(6, 14), Cell(6, 6), Cell(2, 6)],
+            ),
+        ] {
+            assert_eq!(starting_positions(params), positions);
+        }
+
+        // Player 1 starts at (4, 10) heading left, and leaves the map on the 5th tick.
+        let mut game = Game::with_map(map(15, 15), 2).with_trace();
+        assert_eq!(game.get_game_params(), map(15, 15));
+        play(&mut game, &vec![vec![]; 4]);
+        assert!(losses(&game.take_trace_records()).is_empty());
+        assert_eq!(
+            game.get_spectator_world().players["1"].position,
+            Cell(0, 10)
+        );
+
+        play(&mut game, &[vec![], vec![(second_player(), Direction::Up)]]);
+        assert_eq!(
+            losses(&game.take_trace_records()),
+            [lost(5, first_player(), LossReason::OutOfBounds)]
+        );
+        // Player 2 turns up at (5, 10) and leaves the top edge.
+        play(&mut game, &vec![vec![]; 4]);
+        assert_eq!(
+            losses(&game.take_trace_records()),
+            [lost(10, second_player(), LossReason::OutOfBounds)]
+        );
+    }
+
+    /// Player 1 goes up from its territory, left, down and right back into it, which
+    /// captures the loop on the 13th tick.
+    fn loop_script() -> Vec<Vec<(PlayerId, Direction)>> {
+        let p1 = first_player();
+        let mut script = vec![vec![]; 13];
+        script[0] = vec![(p1, Direction::Up)];
+        script[4] = vec![(p1, Direction::Left)];
+        script[7] = vec![(p1, Direction::Down)];
+        script[11] = vec![(p1, Direction::Right)];
+        script
+    }
+
+    fn territories(game: &Game) -> Vec<HashSet<Cell>> {
+        let world = game.get_spectator_world();
+        (1..=game.players.len())
+            .map(|id| {
+                world.players[&id.to_string()]
+                    .territory
+                    .iter()
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn warmup_captures_nothing() {
+        let mut game = Game::new(2).with_warmup(20).with_trace();
+        let initial_territories = territories(&game);
+        // Player 2 goes left along the same row, and meets player 1 on its territory when
+        // it closes the loop at (8, 21).
+        for changes in loop_script() {
+            play(&mut game, &[changes]);
+            let world = game.get_spectator_world();
+            assert!(world.warmup);
+            assert_eq!(territories(&game), initial_territories);
+            assert!(game
+                .get_player_scores()
+                .iter()
+                .all(|(_, &score)| score == 0));
+        }
+
+        let world = game.get_spectator_world();
+        assert_eq!(world.players["1"].position, Cell(8, 21));
+        assert!(world.players["1"].lines.is_empty());
+        assert_eq!(world.players["2"].position, Cell(21, 21));
+        assert!(world.players["2"].lines.is_empty());
+        assert!(losses(&game.take_trace_records()).is_empty());
+    }
+
+    #[test]
+    fn warmup_resets_instead_of_losing() {
+        let mut game = Game::new(1).with_warmup(20).with_trace();
+        // The player would leave the map on the 10th tick.
+        play(&mut game, &vec![vec![]; 9]);
+        assert_eq!(position(&game), Cell(0, 21));
+        assert_eq!(game.field.traced_cells(first_player()).len(), 8);
+
+        game.tick();
+        assert!(!game.has_lost(first_player()));
+        assert_eq!(position(&game), Cell(9, 21));
+        assert!(game.field.traced_cells(first_player()).is_empty());
+        assert!(losses(&game.take_trace_records()).is_empty());
+    }
+
+    #[test]
+    fn warmup_ends_at_starting_positions() {
+        let (p1, p2) = (first_player(), second_player());
+        let mut game = Game::new(2).with_warmup(5).with_trace();
+        let initial_territories = territories(&game);
+        play(&mut game, &[vec![(p1, Direction::Up), (p2, Direction::Up)]]);
+        play(&mut game, &vec![vec![]; 3]);
+        assert!(game.get_spectator_world().warmup);
+
+        game.tick();
+        let world = game.get_spectator_world();
+        assert!(!world.warmup);
+        assert_eq!(world.tick_num, 6);
+        assert_eq!(territories(&game), initial_territories);
+        for (player_id, start) in [p1, p2].into_iter().zip(starting_positions(game.params)) {
+            let player = &world.players[&player_id.get().to_string()];
+            assert_eq!(player.position, start);
+            assert!(player.lines.is_empty());
+            assert!(game.field.traced_cells(player_id).is_empty());
+        }
+
+        // The directions are kept, and the game goes on as usual: player 1 closes its
+        // loop, and player 2 leaves the top edge.
+        play(&mut game, &loop_script());
+        assert!(game.get_player_scores()[p1] > 0);
+        assert_eq!(
+            losses(&game.take_trace_records()),
+            [lost(15, p2, LossReason::OutOfBounds)]
+        );
+    }
+}
@@ -24,6 +24,10 @@ impl CellState {
     pub fn is_captured_by(&self, player_id: PlayerId) -> bool {
         self.captured.is_some_and(|id| id == player_id)
     }
+
+    pub fn is_free(&self) -> bool {
+        self.captured.is_none() && self.traced.is_none()
+    }
 }
 
 struct Array2D<T> {
@@ -42,6 +46,12 @@ impl<T: Default + Clone> Array2D<T> {
     }
 }
 
+impl<T> Array2D<T> {
+    fn contains(&self, Cell(x, y): Cell) -> bool {
+        (0..self.width as i32).contains(&x) && (0..self.height as i32).contains(&y)
+    }
+}
+
 impl<T> Index<Cell> for Array2D<T> {
     type Output = T;
 
@@ -60,6 +70,11 @@ pub struct GameField {
     field: Array2D<CellState>,
     captured_cells: PlayerIndexedVector<HashSet<Cell>>,
     traced_cells: PlayerIndexedVector<HashSet<Cell>>,
+    /// Scratch buffers of `capture_all`, kept between captures not to allocate them
+    /// on every one.
+    reached: Array2D<bool>,
+    queue: Vec<Cell>,
+    inner_cells: Vec<Cell>,
 }
 
 impl Index<Cell> for GameField {
@@ -80,6 +95,9 @@ impl GameField {
             field,
             captured_cells: players_territory,
             traced_cells: players_lines,
+            reached: Array2D::new(width, height),
+            queue: vec![],
+            inner_cells: vec![],
         }
     }
 
@@ -117,54 +135,50 @@ impl GameField {
         cell_state.captured = Some(player_id);
     }
 
-    fn find_inner_cells(&self, player_id: PlayerId) -> Vec<Cell> {
-        let mut visited = Array2D::<bool>::new(self.field.width, self.field.height);
-        for &c in &self.captured_cells[player_id] {
-            visited[c] = true
-        }
-        for &c in &self.traced_cells[player_id] {
-            visited[c] = true
+    /// Puts into `inner_cells` the cells enclosed by the territory and the traces of the
+    /// player, which are the cells not reachable from the border of the map without
+    /// stepping on them. The cells of the player itself aren't included.
+    fn find_inner_cells(&mut self, player_id: PlayerId, inner_cells: &mut Vec<Cell>) {
+        let field = &self.field;
+        let is_wall = |cell: Cell| {
+            let state = &field[cell];
+            state.is_captured_by(player_id) || state.is_traced_by(player_id)
+        };
+        let (width, height) = (field.width as i32, field.height as i32);
+
+        self.reached.data.fill(false);
+        self.queue.clear();
+        let border = (0..width)
+            .flat_map(|x| [Cell(x, 0), Cell(x, height - 1)])
+            .chain((0..height).flat_map(|y| [Cell(0, y), Cell(width - 1, y)]));
+        for cell in border {
+            if !is_wall(cell) && !self.reached[cell] {
+                self.reached[cell] = true;
+                self.queue.push(cell);
+            }
         }
 
-        let mut inner_cells = Vec::<Cell>::new();
-
-        let territory_bounds = self.captured_cells[player_id]
-            .iter()
-            .chain(self.traced_cells[player_id].iter())
-            .flat_map(|&c| c.iter_neighbors());
-        for c in territory_bounds {
-            if visited[c] {
-                continue;
-            }
-            visited[c] = true;
-
-            let mut border_reached = false;
-            let inner_cells_initial_len = inner_cells.len();
-            let mut queue_index = inner_cells_initial_len;
-            inner_cells.push(c);
-
-            while queue_index < inner_cells.len() {
-                let c = inner_cells[queue_index];
-                queue_index += 1;
-                for n in c.iter_neighbours_unchecked() {
-                    if n.in_bounds() {
-                        if !visited[n] {
-                            inner_cells.push(n);
-                            visited[n] = true;
-                        }
-                    } else {
-                        border_reached = true;
-                    }
+        let mut queue_index = 0;
+        while queue_index < self.queue.len() {
+            let cell = self.queue[queue_index];
+            queue_index += 1;
+            for n in cell.iter_neighbours_unchecked() {
+                if field.contains(n) && !self.reached[n] && !is_wall(n) {
+                    self.reached[n] = true;
+                    self.queue.push(n);
                 }
             }
+        }
 
-            if border_reached {
-                // We reached the border, hence this part is not inner and must be dropped
-                inner_cells.truncate(inner_cells_initial_len);
+        inner_cells.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let cell = Cell(x, y);
+                if !self.reached[cell] && !is_wall(cell) {
+                    inner_cells.push(cell);
+                }
             }
         }
-
-        inner_cells
     }
 
     pub fn capture_all(
@@ -176,7 +190,8 @@ impl GameField {
             return (0, 0, HashSet::new());
         }
 
-        let mut captured_cells = self.find_inner_cells(player_id);
+        let mut captured_cells = std::mem::take(&mut self.inner_cells);
+        self.find_inner_cells(player_id, &mut captured_cells);
         captured_cells.extend(self.traced_cells[player_id].drain());
 
         let mut enemy_cells_captured = 0;
@@ -206,10 +221,17 @@ impl GameField {
 
             self.set_captured(cell, player_id)
         }
+        self.inner_cells = captured_cells;
 
         (enemy_cells_captured, free_cells_captured, captured_enemies)
     }
 
+    pub fn clear_trace(&mut self, player_id: PlayerId) {
+        for traced_cell in self.traced_cells[player_id].drain() {
+            self.field[traced_cell].traced = None;
+        }
+    }
+
     pub fn remove_player(&mut self, player_id: PlayerId) {
         for traced_cell in self.traced_cells[player_id].drain() {
             self.field[traced_cell].traced = None;
@@ -235,3 +257,125 @@ impl GameField {
         }
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(id: usize) -> PlayerId {
+        PlayerId::new(id).unwrap()
+    }
+
+    /// Traces the border of the rectangle between the corners.
+    fn trace_rectangle(field: &mut GameField, player_id: PlayerId, from: Cell, to: Cell) {
+        for x in from.0..=to.0 {
+            for y in from.1..=to.1 {
+                if x == from.0 || x == to.0 || y == from.1 || y == to.1 {
+                    field.set_trace(Cell(x, y), player_id);
+                }
+            }
+        }
+    }
+
+    /// The heads of the four players, out of the way unless set.
+    fn positions(heads: &[(usize, Cell)]) -> PlayerIndexedVector<Cell> {
+        let mut positions = vec![Cell(0, 0); 4];
+        for &(id, head) in heads {
+            positions[id - 1] = head;
+        }
+        positions.into()
+    }
+
+    #[test]
+    fn open_to_border() {
+        // A trace from the territory in the corner down to the bottom edge: the cells
+        // between them touch the edge and stay free.
+        let mut field = GameField::new(9, 9, 4);
+        field.init_player(player(1), Cell(1, 7));
+        for cell in [Cell(3, 7), Cell(4, 7), Cell(4, 6), Cell(4, 5), Cell(4, 4)] {
+            field.set_trace(cell, player(1));
+        }
+        for y in 0..4 {
+            field.set_trace(Cell(4, y), player(1));
+        }
+
+        let (enemy_cells, free_cells, enemies) = field.capture_all(player(1), &positions(&[]));
+        assert_eq!((enemy_cells, free_cells), (0, 9));
+        assert!(enemies.is_empty());
+        assert!(field[Cell(3, 6)].is_free());
+        assert!(field[Cell(0, 0)].is_free());
+        assert_eq!(field.get_for_player(player(1)).0.len(), 18);
+        assert!(field.traced_cells(player(1)).is_empty());
+    }
+
+    #[test]
+    fn nested_loops() {
+        // The loop of the first player surrounds a ring of the second one with a free
+        // cell inside, and a hole in the own territory is filled too.
+        let mut field = GameField::new(15, 11, 4);
+        for x in 6..=8 {
+            for y in 4..=6 {
+                if (x, y) != (7, 5) {
+                    field.set_captured(Cell(x, y), player(2));
+                }
+            }
+        }
+        for x in 0..=2 {
+            for y in 8..=10 {
+                if (x, y) != (1, 9) {
+                    field.set_captured(Cell(x, y), player(1));
+                }
+            }
+        }
+        trace_rectangle(&mut field, player(1), Cell(4, 2), Cell(10, 8));
+
+        let heads = positions(&[(2, Cell(13, 1))]);
+        let (enemy_cells, free_cells, enemies) = field.capture_all(player(1), &heads);
+        // 5x5 cells inside, 8 of them are the ring, and 24 cells of the trace.
+        assert_eq!(enemy_cells, 8);
+        assert_eq!(free_cells, 17 + 24 + 1);
+        assert!(enemies.is_empty());
+        assert!(field[Cell(7, 5)].is_captured_by(player(1)));
+        assert!(field[Cell(1, 9)].is_captured_by(player(1)));
+        assert!(field[Cell(11, 5)].is_free());
+        assert!(field.get_for_player(player(2)).0.is_empty());
+    }
+
+    #[test]
+    fn enemies_inside() {
+        let mut field = GameField::new(11, 11, 4);
+        trace_rectangle(&mut field, player(1), Cell(1, 1), Cell(7, 7));
+        // The second player has a trace inside, the head of the third one is inside, and
+        // the head of the fourth one is on the trace of the first one.
+        field.set_trace(Cell(3, 3), player(2));
+        field.set_trace(Cell(3, 4), player(2));
+        field.set_trace(Cell(9, 9), player(2));
+        let heads = positions(&[(2, Cell(9, 9)), (3, Cell(5, 5)), (4, Cell(1, 4))]);
+
+        let (enemy_cells, free_cells, enemies) = field.capture_all(player(1), &heads);
+        assert_eq!((enemy_cells, free_cells), (0, 49));
+        assert_eq!(enemies, HashSet::from([player(2), player(3), player(4)]));
+        // Enemy traces are left to be removed with their players.
+        assert!(field[Cell(3, 3)].is_traced_by(player(2)));
+        assert!(field[Cell(3, 3)].is_captured_by(player(1)));
+        assert!(!field[Cell(9, 9)].is_captured_by(player(1)));
+
+        let heads = positions(&[(2, Cell(9, 9))]);
+        field.set_trace(Cell(8, 8), player(1));
+        let (_, _, enemies) = field.capture_all(player(1), &heads);
+        assert!(enemies.is_empty());
+    }
+
+    #[test]
+    fn no_trace_no_capture() {
+        let mut field = GameField::new(9, 9, 4);
+        field.init_player(player(1), Cell(4, 4));
+        assert_eq!(
+            field.capture_all(player(1), &positions(&[])),
+            (0, 0, HashSet::new())
+        );
+        assert_eq!(field.get_for_player(player(1)).0.len(), 9);
+    }
+}
@@ -1,5 +1,17 @@
+pub mod admin;
+pub mod bonus;
+pub mod bot;
+pub mod budget;
+pub mod delta;
 pub mod endpoint;
+pub mod fog;
 pub mod game;
 mod game_field;
 pub mod player_vec;
+pub mod replay;
+pub mod results;
 pub mod server;
+pub mod spectator;
+pub mod status;
+pub mod timeout;
+pub mod trace;
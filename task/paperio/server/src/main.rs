@@ -1,19 +1,34 @@
 use anyhow::{ensure, Context, Result};
 use clap::Parser;
 use log::info;
+use paperio_proto::{traits::Format, GameParams, PlayerInfo};
 use paperio_server::{
-    endpoint::{Endpoint, JsonEndpoint},
-    game::PlayerId,
+    admin,
+    bonus::BonusConfig,
+    bot::{BotEndpoint, BotKind},
+    budget::{BudgetConfig, BudgetPolicy},
+    delta::DeltaConfig,
+    endpoint::{Endpoint, StreamEndpoint},
+    fog::FogConfig,
+    game::{self, PlayerId},
     player_vec::PlayerIndexedVector,
+    replay::{self, Replayer},
+    results::GameResults,
     server::Server,
+    spectator::SpectatorHandle,
+    status,
+    timeout::TimeoutConfig,
 };
 
 use std::{
     collections::HashMap,
+    fs::File,
     io::{BufReader, BufWriter},
     iter,
     net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
     thread,
+    time::Duration,
 };
 
 #[derive(Parser)]
@@ -37,20 +52,174 @@ struct Arguments {
     #[arg(long = "p4")]
     player_four_port: Option<u16>,
 
+    /// The format of messages and commands: json (lines) or binary (length-prefixed
+    /// frames). The built-in bots only speak JSON.
+    #[arg(long, default_value_t = Format::Json)]
+    format: Format,
+
+    #[arg(long = "f1")]
+    player_one_format: Option<Format>,
+
+    #[arg(long = "f2")]
+    player_two_format: Option<Format>,
+
+    #[arg(long = "f3")]
+    player_three_format: Option<Format>,
+
+    #[arg(long = "f4")]
+    player_four_format: Option<Format>,
+
+    #[arg(long)]
+    spectator_format: Option<Format>,
+
     #[arg(short = 'n', long, default_value_t = 4)]
     player_count: usize,
 
+    /// Names of the players shown by spectators, comma-separated in the order of players.
+    #[arg(long, value_delimiter = ',')]
+    player_names: Vec<String>,
+
+    /// Play the last seats with built-in bots instead of waiting for connections,
+    /// comma-separated: `coward` or `random`. E.g. `--bots coward,random` with 4 players
+    /// only waits for players 1 and 2.
+    #[arg(long, value_delimiter = ',', conflicts_with = "replay")]
+    bots: Vec<BotKind>,
+
+    /// Seed of the random decisions of the bots, each gets its own from it.
+    #[arg(long, default_value_t = 0)]
+    bot_seed: u64,
+
     #[arg(short, long, default_value_t = 300)]
     tick_count: usize,
 
+    /// Width of the map in cells, odd and at least 9.
+    #[arg(long, default_value_t = GameParams::default().x_cells_count)]
+    width: u32,
+
+    /// Height of the map in cells, odd and at least 9.
+    #[arg(long, default_value_t = GameParams::default().y_cells_count)]
+    height: u32,
+
+    /// Also print the results as a JSON line after the human-readable ones, see
+    /// `results::GameResults`.
+    #[arg(long)]
+    json_results: bool,
+
     #[arg(long, default_value_t = 8001)]
     spectator_port: u16,
 
     #[arg(short, long, default_value_t = 0)]
     spectator_count: usize,
 
+    /// Messages queued for a spectator which falls behind the game. The oldest ticks are
+    /// dropped when the queue is full, so that a slow spectator doesn't hold the game.
+    #[arg(long, default_value_t = SpectatorHandle::DEFAULT_CAPACITY)]
+    spectator_queue: usize,
+
     #[arg(short, long, default_value_t = 2)]
     log_level: usize,
+
+    /// End the game as soon as some player captures this share of the map.
+    #[arg(long)]
+    territory_win: Option<f64>,
+
+    /// Play this many ticks before the game, in which players move, but neither capture
+    /// territory nor lose: they are put back to their starting positions instead.
+    #[arg(long, default_value_t = 0)]
+    warmup_ticks: u32,
+
+    /// Spawn random bonuses: nitro doubles the speed of the player who takes it and
+    /// slowdown halves it for a while, see `bonus::cells_per_tick`.
+    #[arg(long)]
+    bonuses: bool,
+
+    /// Spawn a bonus every this many ticks, implies `--bonuses`.
+    #[arg(long)]
+    bonus_rate: Option<u32>,
+
+    /// The same seed spawns the same bonuses for the same moves.
+    #[arg(long, default_value_t = 0)]
+    bonus_seed: u64,
+
+    /// Write the events of the game (direction changes, collisions, captures and losses)
+    /// to this file as JSON lines.
+    #[arg(long)]
+    trace_game: Option<PathBuf>,
+
+    /// Record the messages sent to spectators to this file, to be replayed with `--replay`.
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Don't run a game, play a file written with `--record` to spectators instead.
+    /// Players aren't waited for.
+    #[arg(long, conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    /// Play the replay this many times faster, e.g. 0.5 for twice as slow.
+    #[arg(long, default_value_t = 1., requires = "replay")]
+    replay_speed: f64,
+
+    /// Time a player may take to respond to a tick, in milliseconds. Unused time
+    /// accumulates for up to a few ticks. Response times aren't limited if not set.
+    #[arg(long)]
+    cpu_budget_ms: Option<u64>,
+
+    /// What to do with a player who has exceeded its CPU budget: warn, noop (ignore its
+    /// command on that tick) or forfeit.
+    #[arg(long, default_value_t = BudgetPolicy::Warn)]
+    budget_policy: BudgetPolicy,
+
+    /// Forfeit a player on this many violations of its CPU budget.
+    #[arg(long, default_value_t = 1)]
+    budget_violations: usize,
+
+    /// Time players have to respond to a tick, in milliseconds. A player who hasn't
+    /// responded in time does nothing on that tick. Players are waited for if not set.
+    #[arg(long)]
+    turn_timeout_ms: Option<u64>,
+
+    /// Drop a player on this many timeouts in a row, requires `--turn-timeout-ms`.
+    #[arg(long, default_value_t = TimeoutConfig::DEFAULT_MAX_CONSECUTIVE, requires = "turn_timeout_ms")]
+    max_timeouts: usize,
+
+    /// Read status messages for spectators from this file or FIFO, a line per message,
+    /// e.g. `[alert] player 2 is about to cut player 3!`. The level (info, warning or alert)
+    /// is optional.
+    #[arg(long)]
+    status_fifo: Option<PathBuf>,
+
+    /// Only show players the enemy cells within this distance of their territory or head,
+    /// and enemy heads only when they are that close. Players see everything if not set.
+    #[arg(long)]
+    fog_radius: Option<u32>,
+
+    /// Hide enemy scores from players too, requires `--fog-radius`.
+    #[arg(long, requires = "fog_radius")]
+    fog_hide_scores: bool,
+
+    /// Disable Nagle's algorithm on accepted sockets, which delays the small messages
+    /// of the lock-step exchange of ticks and commands.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    tcp_nodelay: bool,
+
+    /// Send these endpoints the changes since the previous tick instead of full ticks:
+    /// comma-separated player numbers and `spectators`. The built-in bots need full ticks.
+    #[arg(long, value_delimiter = ',')]
+    deltas: Vec<String>,
+
+    /// With `--deltas`, still send a full tick every this many ticks.
+    #[arg(long, default_value_t = DeltaConfig::DEFAULT_KEYFRAME_INTERVAL)]
+    keyframe_interval: usize,
+
+    /// Serve an admin console on this port: a command per line (`pause`, `resume`,
+    /// `status`, `dump-world`, `kick <player_id>`, `end` or `set tick-ms <n>`) and a JSON
+    /// line per response.
+    #[arg(long)]
+    admin_port: Option<u16>,
+
+    /// Require this token as the first line of admin sessions, requires `--admin-port`.
+    #[arg(long, requires = "admin_port")]
+    admin_token: Option<String>,
 }
 
 #[derive(Clone, Copy)]
@@ -59,7 +228,25 @@ enum EndpointTag {
     Spectator,
 }
 
-fn get_port_to_endpoint_tags(args: &Arguments) -> HashMap<u16, Vec<EndpointTag>> {
+impl EndpointTag {
+    fn format(&self, args: &Arguments) -> Format {
+        let format = match self {
+            Self::Player(player_id) => [
+                args.player_one_format,
+                args.player_two_format,
+                args.player_three_format,
+                args.player_four_format,
+            ][player_id.get() - 1],
+            Self::Spectator => args.spectator_format,
+        };
+        format.unwrap_or(args.format)
+    }
+}
+
+fn get_port_to_endpoint_tags(
+    args: &Arguments,
+    player_count: usize,
+) -> HashMap<u16, Vec<EndpointTag>> {
     let player_ports = [
         args.player_one_port,
         args.player_two_port,
@@ -69,7 +256,8 @@ fn get_port_to_endpoint_tags(args: &Arguments) -> HashMap<u16, Vec<EndpointTag>>
 
     let mut port_to_endpoint_tags = HashMap::<u16, Vec<EndpointTag>>::new();
 
-    for i in 0..args.player_count {
+    // The rest of the seats are taken by bots.
+    for i in 0..player_count.saturating_sub(args.bots.len()) {
         let tag = EndpointTag::Player(PlayerId::new(i + 1).unwrap());
 
         let port = player_ports
@@ -96,7 +284,8 @@ fn get_port_to_endpoint_tags(args: &Arguments) -> HashMap<u16, Vec<EndpointTag>>
 
 fn spawn_listener(
     socket_address: SocketAddr,
-    tags: Vec<EndpointTag>,
+    tags: Vec<(EndpointTag, Format)>,
+    nodelay: bool,
 ) -> thread::JoinHandle<Result<Vec<(EndpointTag, impl Endpoint)>>> {
     thread::spawn(move || {
         if tags.is_empty() {
@@ -110,14 +299,19 @@ fn spawn_listener(
 
         tags.into_iter()
             .zip(TcpListener::bind(socket_address)?.incoming())
-            .map(|(tag, mb_stream)| {
+            .map(|((tag, format), mb_stream)| {
                 let stream = mb_stream?;
                 let peer_addr = stream.peer_addr()?;
                 info!("incomming connection: {peer_addr} -> {socket_address}");
 
+                stream
+                    .set_nodelay(nodelay)
+                    .context("failed to set TCP_NODELAY")?;
+
+                // The endpoint buffers writes itself.
                 let reader = BufReader::new(stream.try_clone().context("failed to clone fd")?);
-                let writer = BufWriter::new(stream);
-                let endpoint = JsonEndpoint::new(reader, writer);
+                let writer = stream.try_clone().context("failed to clone fd")?;
+                let endpoint = StreamEndpoint::new(reader, writer, format).with_socket(stream);
 
                 Ok((tag, endpoint))
             })
@@ -125,27 +319,52 @@ fn spawn_listener(
     })
 }
 
-fn get_endpoints(
+fn get_bot_endpoints(
     args: &Arguments,
-) -> Result<(PlayerIndexedVector<impl Endpoint>, Vec<impl Endpoint>)> {
-    let port_to_endpoint_tags = get_port_to_endpoint_tags(args);
+    player_count: usize,
+) -> impl Iterator<Item = (PlayerId, BotEndpoint)> + '_ {
+    let first_seat = player_count.saturating_sub(args.bots.len());
+    (first_seat..player_count)
+        .zip(&args.bots)
+        .map(|(i, &kind)| {
+            let player_id = PlayerId::new(i + 1).unwrap();
+            let seed = args.bot_seed.wrapping_add(i as u64);
+            info!("Player #{player_id} is played by the {kind} bot");
+            (player_id, BotEndpoint::new(kind, seed))
+        })
+}
+
+/// The endpoints of the players and of the spectators.
+type Endpoints = (PlayerIndexedVector<Box<dyn Endpoint>>, Vec<SpectatorHandle>);
+
+fn get_endpoints(args: &Arguments, player_count: usize) -> Result<Endpoints> {
+    let port_to_endpoint_tags = get_port_to_endpoint_tags(args, player_count);
 
     let mut handles = vec![];
     for (port, endpoint_tags) in port_to_endpoint_tags {
         let socket_addr = format!("{}:{}", args.address, port)
             .parse()
             .with_context(|| format!("invalid socket address: {}:{}", args.address, port))?;
-        let handle = spawn_listener(socket_addr, endpoint_tags);
+        let endpoint_tags = endpoint_tags
+            .into_iter()
+            .map(|tag| (tag, tag.format(args)))
+            .collect();
+        let handle = spawn_listener(socket_addr, endpoint_tags, args.tcp_nodelay);
         handles.push(handle);
     }
 
-    let mut players = PlayerIndexedVector::new(args.player_count);
+    let mut players = PlayerIndexedVector::<Option<Box<dyn Endpoint>>>::new(player_count);
+    for (player_id, endpoint) in get_bot_endpoints(args, player_count) {
+        players[player_id] = Some(Box::new(endpoint));
+    }
     let mut spectators = vec![];
     for handle in handles {
         for (tag, endpoint) in handle.join().unwrap()? {
             match tag {
-                EndpointTag::Player(player_id) => players[player_id] = Some(endpoint),
-                EndpointTag::Spectator => spectators.push(endpoint),
+                EndpointTag::Player(player_id) => players[player_id] = Some(Box::new(endpoint)),
+                EndpointTag::Spectator => {
+                    spectators.push(SpectatorHandle::spawn(endpoint, args.spectator_queue))
+                }
             }
         }
     }
@@ -155,12 +374,92 @@ fn get_endpoints(
     Ok((players, spectators))
 }
 
+fn get_delta_config(args: &Arguments) -> Result<Option<DeltaConfig>> {
+    if args.deltas.is_empty() {
+        return Ok(None);
+    }
+
+    let mut players = vec![];
+    let mut spectators = false;
+    for endpoint in &args.deltas {
+        if endpoint == "spectators" {
+            spectators = true;
+            continue;
+        }
+        let player_id = endpoint
+            .parse::<usize>()
+            .ok()
+            .filter(|number| *number <= args.player_count)
+            .and_then(PlayerId::new)
+            .with_context(|| {
+                format!(
+                    "invalid delta endpoint '{endpoint}', expected a player number from 1 to {} \
+                    or 'spectators'",
+                    args.player_count
+                )
+            })?;
+        players.push(player_id);
+    }
+
+    let config =
+        DeltaConfig::new(players, spectators).with_keyframe_interval(args.keyframe_interval);
+    Ok(Some(config))
+}
+
 fn main() -> Result<()> {
     let args = Arguments::parse();
     ensure!(
         (1..=4).contains(&args.player_count),
         "player count should be from 1 to 4"
     );
+    for (name, cells) in [("width", args.width), ("height", args.height)] {
+        ensure!(
+            game::is_valid_map_size(cells),
+            "map {name} should be odd and at least {}, got {cells}",
+            game::MIN_MAP_SIZE
+        );
+    }
+    ensure!(
+        args.territory_win
+            .is_none_or(|threshold| threshold > 0. && threshold <= 1.),
+        "territory win threshold should be in (0, 1]"
+    );
+    ensure!(args.bonus_rate != Some(0), "bonus rate should be positive");
+    ensure!(
+        args.cpu_budget_ms != Some(0),
+        "CPU budget should be positive"
+    );
+    ensure!(
+        args.budget_violations > 0,
+        "budget violations amount should be positive"
+    );
+    ensure!(
+        args.turn_timeout_ms != Some(0),
+        "turn timeout should be positive"
+    );
+    ensure!(args.max_timeouts > 0, "max timeouts should be positive");
+    ensure!(
+        args.keyframe_interval > 0,
+        "keyframe interval should be positive"
+    );
+    ensure!(args.replay_speed > 0., "replay speed should be positive");
+    ensure!(
+        args.spectator_queue > 0,
+        "spectator queue should be positive"
+    );
+    ensure!(
+        args.bots.len() <= args.player_count,
+        "expected at most {} bots, got {}",
+        args.player_count,
+        args.bots.len()
+    );
+    ensure!(
+        args.player_names.is_empty() || args.player_names.len() == args.player_count,
+        "expected {} player names, got {}",
+        args.player_count,
+        args.player_names.len()
+    );
+    let delta_config = get_delta_config(&args)?;
 
     stderrlog::new()
         .verbosity(args.log_level)
@@ -168,8 +467,116 @@ fn main() -> Result<()> {
         .init()
         .unwrap();
 
-    let (player_endpoints, spectator_endpoints) = get_endpoints(&args)?;
-    Server::new(player_endpoints, spectator_endpoints).run(args.tick_count);
+    if let Some(path) = &args.replay {
+        return run_replay(&args, path);
+    }
+
+    // Before waiting for players, so that the console is there as soon as the server is.
+    let admin_requests = match args.admin_port {
+        Some(port) => {
+            let socket_addr = format!("{}:{}", args.address, port);
+            let listener = TcpListener::bind(&socket_addr)
+                .with_context(|| format!("failed to bind admin console to {socket_addr}"))?;
+            info!("admin console is listening on {socket_addr}");
+            Some(admin::spawn_admin_console(
+                listener,
+                args.admin_token.clone(),
+            ))
+        }
+        None => None,
+    };
+
+    let (player_endpoints, spectator_endpoints) = get_endpoints(&args, args.player_count)?;
+    let mut server = Server::new(player_endpoints, spectator_endpoints).with_map_size(GameParams {
+        x_cells_count: args.width,
+        y_cells_count: args.height,
+    });
+    if let Some(requests) = admin_requests {
+        server = server.with_admin(requests);
+    }
+    if let Some(threshold) = args.territory_win {
+        server = server.with_territory_win(threshold);
+    }
+    server = server.with_warmup(args.warmup_ticks);
+    let bonus_rate = args
+        .bonus_rate
+        .or(args.bonuses.then_some(BonusConfig::DEFAULT_PERIOD));
+    if let Some(rate) = bonus_rate {
+        server = server.with_bonuses(BonusConfig::new(rate, args.bonus_seed));
+    }
+    if let Some(path) = &args.trace_game {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create trace file {}", path.display()))?;
+        server = server.with_game_trace(BufWriter::new(file));
+    }
+    if let Some(path) = &args.record {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create recording {}", path.display()))?;
+        server = server.with_recording(BufWriter::new(file));
+    }
+    if let Some(millis) = args.cpu_budget_ms {
+        let config = BudgetConfig::new(Duration::from_millis(millis), args.budget_policy)
+            .with_max_violations(args.budget_violations);
+        server = server.with_cpu_budget(config);
+    }
+    if let Some(millis) = args.turn_timeout_ms {
+        let config = TimeoutConfig::new(Duration::from_millis(millis))
+            .with_max_consecutive(args.max_timeouts);
+        server = server.with_turn_timeout(config);
+    }
+    if let Some(radius) = args.fog_radius {
+        let mut config = FogConfig::new(radius);
+        if args.fog_hide_scores {
+            config = config.with_hidden_scores();
+        }
+        server = server.with_fog(config);
+    }
+    if let Some(path) = &args.status_fifo {
+        server = server.with_status_feed(status::spawn_status_reader(path.clone()));
+    }
+    if let Some(config) = delta_config {
+        server = server.with_deltas(config);
+    }
+    if !args.player_names.is_empty() {
+        let infos = args
+            .player_names
+            .iter()
+            .map(|name| PlayerInfo {
+                user_name: name.clone(),
+            })
+            .collect::<Vec<_>>();
+        server = server.with_player_infos(infos.into());
+    }
+    let outcome = server.run_game(args.tick_count);
+    for (player_id, result) in outcome.players.iter() {
+        print!("Player #{player_id}: score {}", result.score);
+        if let Some(reason) = result.loss_reason {
+            print!(", lost ({reason})");
+        }
+        if result.timeouts > 0 {
+            print!(", {} timeout(s)", result.timeouts);
+        }
+        if let Some(err) = &result.io_error {
+            print!(", io error ({err})");
+        }
+        println!();
+    }
+    if args.json_results {
+        let results = serde_json::to_string(&GameResults::new(&outcome))
+            .context("failed to serialize results")?;
+        println!("{results}");
+    }
+
+    Ok(())
+}
 
+fn run_replay(args: &Arguments, path: &Path) -> Result<()> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open recording {}", path.display()))?;
+    let (_, spectator_endpoints) = get_endpoints(args, 0)?;
+    let count = Replayer::new(spectator_endpoints)
+        .with_speed(args.replay_speed)
+        .run(replay::read_records(BufReader::new(file)))?;
+    info!("replayed {count} messages");
     Ok(())
 }
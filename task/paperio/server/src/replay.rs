@@ -0,0 +1,236 @@
+//! Recording games for spectators and playing them back, see `Recorder` and `Replayer`.
+
+use std::{
+    io::{BufRead, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use log::*;
+use paperio_proto::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::endpoint::Endpoint;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A line of a recording.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ReplayRecord {
+    /// Since the first message of the game.
+    pub elapsed_ms: u64,
+    pub message: Message,
+}
+
+/// Same as `ReplayRecord`, without cloning the message.
+#[derive(Serialize)]
+struct RecordRef<'a> {
+    elapsed_ms: u64,
+    message: &'a Message,
+}
+
+/// Writes the messages sent to spectators as JSON lines of `ReplayRecord`.
+pub struct Recorder<W> {
+    writer: W,
+    started: Option<Instant>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            started: None,
+        }
+    }
+
+    pub fn record(&mut self, message: &Message) -> anyhow::Result<()> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let record = RecordRef {
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            message,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Reads a recording written by `Recorder` line by line.
+pub fn read_records(reader: impl BufRead) -> impl Iterator<Item = anyhow::Result<ReplayRecord>> {
+    reader.lines().enumerate().map(|(i, line)| {
+        let line = line.context("failed to read the recording")?;
+        serde_json::from_str(&line)
+            .with_context(|| format!("malformed record on line {} of the recording", i + 1))
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Plays a recording back to spectators, at its pace, without running a game. The
+/// spectators can't tell it from a live game: they get the same messages and are waited
+/// for after every tick.
+pub struct Replayer<'a> {
+    spectator_endpoints: Vec<Box<dyn Endpoint + 'a>>,
+    speed: f64,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(spectator_endpoints: impl IntoIterator<Item = impl Endpoint + 'a>) -> Self {
+        Self {
+            spectator_endpoints: spectator_endpoints
+                .into_iter()
+                .map(|e| Box::new(e) as Box<dyn Endpoint>)
+                .collect(),
+            speed: 1.,
+        }
+    }
+
+    /// Plays the recording this many times faster, e.g. 0.5 for twice as slow.
+    ///
+    /// # Panics
+    ///
+    /// If the speed isn't positive.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0., "replay speed should be positive");
+        self.speed = speed;
+        self
+    }
+
+    /// Returns the amount of the messages played.
+    pub fn run(
+        mut self,
+        records: impl IntoIterator<Item = anyhow::Result<ReplayRecord>>,
+    ) -> anyhow::Result<usize> {
+        let started = Instant::now();
+        let mut count = 0;
+        for record in records {
+            let ReplayRecord {
+                elapsed_ms,
+                message,
+            } = record?;
+
+            let due = Duration::from_millis(elapsed_ms).div_f64(self.speed);
+            if let Some(delay) = due.checked_sub(started.elapsed()) {
+                thread::sleep(delay);
+            }
+
+            for endpoint in self.spectator_endpoints.iter_mut() {
+                if let Err(err) = endpoint.send_message(&message) {
+                    error!("failed to send message to spectator: {err}");
+                }
+            }
+            if matches!(message, Message::Tick(_) | Message::TickDelta(_)) {
+                for endpoint in self.spectator_endpoints.iter_mut() {
+                    if let Err(err) = endpoint.get_command() {
+                        error!("failed to sync with spectator: {err}");
+                    }
+                }
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::{Command, StatusLevel, World};
+
+    use std::{collections::HashMap, io};
+
+    #[derive(Default)]
+    struct CountingEndpoint {
+        messages: Vec<(Duration, Message)>,
+        syncs: usize,
+        started: Option<Instant>,
+    }
+
+    impl Endpoint for CountingEndpoint {
+        fn send_message(&mut self, message: &Message) -> io::Result<()> {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            self.messages.push((started.elapsed(), message.clone()));
+            Ok(())
+        }
+
+        fn get_command(&mut self) -> io::Result<Command> {
+            self.syncs += 1;
+            Ok(Command::NoOp)
+        }
+    }
+
+    fn tick(tick_num: u32) -> Message {
+        Message::Tick(World {
+            players: HashMap::new(),
+            tick_num,
+            bonuses: vec![],
+            warmup: false,
+        })
+    }
+
+    fn records() -> Vec<ReplayRecord> {
+        let status = Message::Status {
+            tick_num: 1,
+            text: "status".to_string(),
+            level: StatusLevel::Info,
+        };
+        [
+            (0, tick(1)),
+            (0, status),
+            (100, tick(2)),
+            (200, Message::EndGame {}),
+        ]
+        .into_iter()
+        .map(|(elapsed_ms, message)| ReplayRecord {
+            elapsed_ms,
+            message,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn replay_keeps_pace() {
+        for speed in [1., 4.] {
+            let mut first = CountingEndpoint::default();
+            let mut second = CountingEndpoint::default();
+            let count = Replayer::new([&mut first, &mut second])
+                .with_speed(speed)
+                .run(records().into_iter().map(Ok))
+                .unwrap();
+            assert_eq!(count, 4);
+
+            for endpoint in [&first, &second] {
+                let messages = endpoint.messages.iter().map(|(_, m)| m.clone());
+                let expected = records().into_iter().map(|record| record.message);
+                assert!(messages.eq(expected));
+                // Only ticks are waited for.
+                assert_eq!(endpoint.syncs, 2);
+
+                for ((elapsed, _), record) in endpoint.messages.iter().zip(records()) {
+                    let due = Duration::from_millis(record.elapsed_ms).div_f64(speed);
+                    assert!(*elapsed + Duration::from_millis(5) >= due, "{elapsed:?}");
+                    assert!(*elapsed < due + Duration::from_millis(50), "{elapsed:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn replay_stops_on_error() {
+        let mut endpoint = CountingEndpoint::default();
+        let mut records = records().into_iter().map(Ok).collect::<Vec<_>>();
+        records.insert(2, Err(anyhow::anyhow!("malformed record")));
+
+        let result = Replayer::new([&mut endpoint]).with_speed(100.).run(records);
+        assert_eq!(result.unwrap_err().to_string(), "malformed record");
+        assert_eq!(endpoint.messages.len(), 2);
+    }
+}
@@ -0,0 +1,154 @@
+//! The machine-readable summary of a game, see `GameResults`.
+
+use serde::Serialize;
+
+use crate::{
+    game::PlayerId,
+    server::{GameOutcome, PlayerResult, WinReason},
+    trace::LossReason,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct PlayerSummary {
+    pub player_id: PlayerId,
+    pub score: u32,
+    pub has_lost: bool,
+    pub loss_reason: Option<LossReason>,
+    pub timeouts: usize,
+    pub io_error: Option<String>,
+}
+
+impl PlayerSummary {
+    pub fn new(player_id: PlayerId, result: &PlayerResult) -> Self {
+        Self {
+            player_id,
+            score: result.score,
+            has_lost: result.loss_reason.is_some(),
+            loss_reason: result.loss_reason,
+            timeouts: result.timeouts,
+            io_error: result.io_error.as_ref().map(|err| err.to_string()),
+        }
+    }
+}
+
+/// Printed by the server as a JSON line at the end of the game, for scripts to read
+/// instead of the human-readable lines.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct GameResults {
+    /// Unset on a tie.
+    pub winner: Option<PlayerId>,
+    pub win_reason: Option<WinReason>,
+    pub ticks_played: usize,
+    /// In the order of the players.
+    pub players: Vec<PlayerSummary>,
+}
+
+impl GameResults {
+    pub fn new(outcome: &GameOutcome) -> Self {
+        let winner = outcome
+            .players
+            .iter()
+            .find_map(|(player_id, result)| result.win_reason.map(|reason| (player_id, reason)));
+        Self {
+            winner: winner.map(|(player_id, _)| player_id),
+            win_reason: winner.map(|(_, reason)| reason),
+            ticks_played: outcome.ticks_played,
+            players: outcome
+                .players
+                .iter()
+                .map(|(player_id, result)| PlayerSummary::new(player_id, result))
+                .collect(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::endpoint::WriteStats;
+
+    use serde_json::json;
+
+    use std::io;
+
+    fn result(score: u32) -> PlayerResult {
+        PlayerResult {
+            score,
+            io_error: None,
+            win_reason: None,
+            loss_reason: None,
+            budget: None,
+            timeouts: 0,
+            output: WriteStats::default(),
+        }
+    }
+
+    #[test]
+    fn serialize() {
+        let mut winner = result(40);
+        winner.win_reason = Some(WinReason::Territory);
+        let mut lost = result(12);
+        lost.loss_reason = Some(LossReason::TraceCrossed);
+        let mut crashed = result(3);
+        crashed.io_error = Some(io::ErrorKind::BrokenPipe.into());
+        crashed.timeouts = 2;
+        let outcome = GameOutcome {
+            players: vec![lost, winner, crashed].into(),
+            ticks_played: 120,
+        };
+
+        let results = GameResults::new(&outcome);
+        assert_eq!(
+            serde_json::to_value(&results).unwrap(),
+            json!({
+                "winner": 2,
+                "win_reason": "territory",
+                "ticks_played": 120,
+                "players": [
+                    {
+                        "player_id": 1,
+                        "score": 12,
+                        "has_lost": true,
+                        "loss_reason": "trace_crossed",
+                        "timeouts": 0,
+                        "io_error": null,
+                    },
+                    {
+                        "player_id": 2,
+                        "score": 40,
+                        "has_lost": false,
+                        "loss_reason": null,
+                        "timeouts": 0,
+                        "io_error": null,
+                    },
+                    {
+                        "player_id": 3,
+                        "score": 3,
+                        "has_lost": false,
+                        "loss_reason": null,
+                        "timeouts": 2,
+                        "io_error": "broken pipe",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn tie() {
+        let outcome = GameOutcome {
+            players: vec![result(5), result(5)].into(),
+            ticks_played: 300,
+        };
+        let results = GameResults::new(&outcome);
+        assert_eq!(results.winner, None);
+        assert_eq!(results.win_reason, None);
+        assert_eq!(results.players.len(), 2);
+        assert!(results.players.iter().all(|player| player.score == 5));
+    }
+}
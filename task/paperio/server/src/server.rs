@@ -1,23 +1,93 @@
-use std::io;
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::mpsc::Receiver,
+    thread,
+    time::{Duration, Instant},
+};
 
 use log::*;
-use paperio_proto::{Command, Message};
+use paperio_proto::{Command, GameParams, Message, PlayerInfo};
+use serde::Serialize;
 
 use crate::{
-    endpoint::Endpoint,
-    game::{Game, PlayerId},
+    admin::{AdminCommand, AdminRequest, AdminResponse, AdminState, GameStatus, PlayerStatus},
+    bonus::BonusConfig,
+    budget::{BudgetConfig, BudgetPolicy, BudgetStats, PlayerBudget},
+    delta::{DeltaConfig, DeltaEncoder},
+    endpoint::{Endpoint, WriteStats},
+    fog::FogConfig,
+    game::{self, Game, PlayerId},
     player_vec::PlayerIndexedVector,
+    replay::Recorder,
+    status::StatusUpdate,
+    timeout::{self, PlayerTimeouts, TimeoutConfig},
+    trace::{self, LossReason},
 };
 
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WinReason {
+    /// The game lasted all of its ticks and the winner has the highest score.
+    Score,
+    /// The winner's territory crossed the `territory_win` share of the map.
+    Territory,
+}
+
+impl fmt::Display for WinReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Score => write!(f, "score"),
+            Self::Territory => write!(f, "territory"),
+        }
+    }
+}
+
+/// Shortest read timeout: a zero one is an error, and the response may be already
+/// received anyway.
+const MIN_READ_TIMEOUT: Duration = Duration::from_millis(1);
+
 pub struct PlayerResult {
     pub score: u32,
     pub io_error: Option<io::Error>,
+    /// Set for the winner only.
+    pub win_reason: Option<WinReason>,
+    pub loss_reason: Option<LossReason>,
+    /// Set if the server runs with a CPU budget, see `Server::with_cpu_budget`.
+    pub budget: Option<BudgetStats>,
+    /// Ticks the player hasn't responded to in time, see `Server::with_turn_timeout`.
+    pub timeouts: usize,
+    pub output: WriteStats,
+}
+
+pub struct GameOutcome {
+    pub players: PlayerIndexedVector<PlayerResult>,
+    /// Fewer than asked for if the game has ended early. Warm-up ticks aren't counted.
+    pub ticks_played: usize,
 }
 
 pub struct Server<'a> {
     player_endpoints: PlayerIndexedVector<Box<dyn Endpoint + 'a>>,
     spectator_endpoints: Vec<Box<dyn Endpoint + 'a>>,
     player_io_errors: PlayerIndexedVector<Option<io::Error>>,
+    map: GameParams,
+    territory_win: Option<f64>,
+    warmup_ticks: u32,
+    bonuses: Option<BonusConfig>,
+    fog: Option<FogConfig>,
+    game_trace: Option<Box<dyn Write + 'a>>,
+    recorder: Option<Recorder<Box<dyn Write + 'a>>>,
+    cpu_budget: Option<BudgetConfig>,
+    player_budgets: PlayerIndexedVector<PlayerBudget>,
+    turn_timeout: Option<TimeoutConfig>,
+    player_timeouts: PlayerIndexedVector<PlayerTimeouts>,
+    status_feed: Option<Receiver<StatusUpdate>>,
+    deltas: Option<DeltaConfig>,
+    player_delta_encoders: PlayerIndexedVector<Option<DeltaEncoder>>,
+    spectator_delta_encoder: Option<DeltaEncoder>,
+    player_infos: Option<PlayerIndexedVector<PlayerInfo>>,
+    admin_requests: Option<Receiver<AdminRequest>>,
+    admin: AdminState,
 }
 
 impl<'a> Server<'a> {
@@ -33,29 +103,225 @@ impl<'a> Server<'a> {
                 .map(|e| Box::new(e) as Box<dyn Endpoint>)
                 .collect(),
             player_io_errors: PlayerIndexedVector::new(player_count),
+            map: GameParams::default(),
+            territory_win: None,
+            warmup_ticks: 0,
+            bonuses: None,
+            fog: None,
+            game_trace: None,
+            recorder: None,
+            cpu_budget: None,
+            player_budgets: PlayerIndexedVector::new(player_count),
+            turn_timeout: None,
+            player_timeouts: PlayerIndexedVector::new(player_count),
+            status_feed: None,
+            deltas: None,
+            player_delta_encoders: PlayerIndexedVector::new(player_count),
+            spectator_delta_encoder: None,
+            player_infos: None,
+            admin_requests: None,
+            admin: AdminState::default(),
         }
     }
 
-    pub fn run(mut self, ticks_amount: usize) -> PlayerIndexedVector<PlayerResult> {
-        let mut game = Game::new(self.player_endpoints.len());
+    /// Plays on a map of this size instead of the default one.
+    ///
+    /// # Panics
+    ///
+    /// If the size isn't valid, see `game::is_valid_map_size`.
+    pub fn with_map_size(mut self, params: GameParams) -> Self {
+        assert!(
+            game::is_valid_map_size(params.x_cells_count)
+                && game::is_valid_map_size(params.y_cells_count),
+            "invalid map size {}x{}",
+            params.x_cells_count,
+            params.y_cells_count
+        );
+        self.map = params;
+        self
+    }
+
+    /// Ends the game early as soon as some player captures at least
+    /// `threshold` of the map.
+    pub fn with_territory_win(mut self, threshold: f64) -> Self {
+        self.territory_win = Some(threshold);
+        self
+    }
+
+    /// Plays `ticks` warm-up ticks before the `ticks_amount` ones of `run`, see
+    /// `Game::with_warmup`.
+    pub fn with_warmup(mut self, ticks: u32) -> Self {
+        self.warmup_ticks = ticks;
+        self
+    }
+
+    pub fn with_bonuses(mut self, config: BonusConfig) -> Self {
+        self.bonuses = Some(config);
+        self
+    }
+
+    /// Writes the events of the game to `writer` as JSON lines, see `trace::TraceEvent`.
+    pub fn with_game_trace(mut self, writer: impl Write + 'a) -> Self {
+        self.game_trace = Some(Box::new(writer));
+        self
+    }
+
+    /// Records the messages sent to spectators to `writer` to be replayed later, see
+    /// `replay::Replayer`.
+    pub fn with_recording(mut self, writer: impl Write + 'a) -> Self {
+        self.recorder = Some(Recorder::new(Box::new(writer)));
+        self
+    }
+
+    /// Hides far enemies from players, see `FogConfig`.
+    pub fn with_fog(mut self, config: FogConfig) -> Self {
+        self.fog = Some(config);
+        self
+    }
+
+    /// Limits the time players take to respond to ticks, see `BudgetConfig`.
+    ///
+    /// Only reading the command is timed: the tick is already sent and flushed by then,
+    /// so the server's own serialization isn't charged to the player.
+    pub fn with_cpu_budget(mut self, config: BudgetConfig) -> Self {
+        self.cpu_budget = Some(config);
+        self
+    }
+
+    /// Gives players a deadline to respond to each tick, see `TimeoutConfig`. It's shared
+    /// by all the players, so a tick takes at most `per_turn` however many are slow.
+    ///
+    /// Timed-out responses aren't charged to the CPU budget.
+    pub fn with_turn_timeout(mut self, config: TimeoutConfig) -> Self {
+        self.turn_timeout = Some(config);
+        self
+    }
+
+    /// Sends the statuses received from `feed` to spectators before the next tick, see
+    /// `status::spawn_status_reader`.
+    pub fn with_status_feed(mut self, feed: Receiver<StatusUpdate>) -> Self {
+        self.status_feed = Some(feed);
+        self
+    }
+
+    /// Sends the endpoints of the config `Message::TickDelta` instead of full ticks,
+    /// except for keyframes.
+    ///
+    /// # Panics
+    ///
+    /// If a player of the config is not in the game.
+    pub fn with_deltas(mut self, config: DeltaConfig) -> Self {
+        for &player_id in &config.players {
+            self.player_delta_encoders[player_id] = Some(DeltaEncoder::default());
+        }
+        if config.spectators {
+            self.spectator_delta_encoder = Some(DeltaEncoder::default());
+        }
+        self.deltas = Some(config);
+        self
+    }
+
+    /// Announces the names of the players to spectators after `Message::StartGame`, see
+    /// `Message::PlayerAnnouncement`. Players don't get it, as the built-in bots don't
+    /// know the message.
+    ///
+    /// # Panics
+    ///
+    /// If there isn't an info per player.
+    pub fn with_player_infos(mut self, infos: PlayerIndexedVector<PlayerInfo>) -> Self {
+        assert_eq!(
+            infos.len(),
+            self.player_endpoints.len(),
+            "expected an info per player"
+        );
+        self.player_infos = Some(infos);
+        self
+    }
+
+    /// Answers the commands of `requests` before every tick, see
+    /// `admin::spawn_admin_console`.
+    pub fn with_admin(mut self, requests: Receiver<AdminRequest>) -> Self {
+        self.admin_requests = Some(requests);
+        self
+    }
+
+    pub fn run(self, ticks_amount: usize) -> PlayerIndexedVector<PlayerResult> {
+        self.run_game(ticks_amount).players
+    }
+
+    pub fn run_game(mut self, ticks_amount: usize) -> GameOutcome {
+        let mut game =
+            Game::with_map(self.map, self.player_endpoints.len()).with_warmup(self.warmup_ticks);
+        if let Some(config) = self.bonuses {
+            game = game.with_bonuses(config);
+        }
+        if let Some(config) = self.fog {
+            game = game.with_fog(config);
+        }
+        if self.game_trace.is_some() {
+            game = game.with_trace();
+        }
         let params = game.get_game_params();
 
         self.send_to_all(&Message::StartGame(params));
+        if let Some(infos) = &self.player_infos {
+            // The ids of the spectator world.
+            let infos = infos
+                .iter()
+                .map(|(player_id, info)| (player_id.get().to_string(), info.clone()))
+                .collect();
+            self.send_to_spectators(&Message::PlayerAnnouncement(infos));
+        }
 
-        for tick in 0..ticks_amount {
+        let mut win_reason = WinReason::Score;
+        let mut ticks_played = 0;
+        for tick in 0..self.warmup_ticks as usize + ticks_amount {
             debug!("tick #{tick}");
+            let warmup = game.is_warmup();
+            let tick_started = Instant::now();
 
+            self.handle_admin_requests(&game, tick);
+            if self.admin.end_requested {
+                info!("the game is ended by the admin on tick #{tick}");
+                break;
+            }
+
+            let keyframe = self
+                .deltas
+                .as_ref()
+                .is_some_and(|config| config.is_keyframe(tick));
             for player_id in self.player_endpoints.iter_player_ids() {
                 let world = game.get_player_world(player_id);
-                self.send_to_player(player_id, &Message::Tick(world));
+                let message = match &mut self.player_delta_encoders[player_id] {
+                    Some(encoder) => encoder.encode(world, keyframe),
+                    None => Message::Tick(world),
+                };
+                self.send_to_player(player_id, &message);
             }
 
             let spectator_world = game.get_spectator_world();
-            self.send_to_spectators(&Message::Tick(spectator_world));
+            self.send_statuses(spectator_world.tick_num);
+            let message = match &mut self.spectator_delta_encoder {
+                Some(encoder) => encoder.encode(spectator_world, keyframe),
+                None => Message::Tick(spectator_world),
+            };
+            self.send_to_spectators(&message);
 
+            let deadline = self
+                .turn_timeout
+                .map(|config| Instant::now() + config.per_turn);
             for player_id in self.player_endpoints.iter_player_ids() {
-                let mb_command = self.try_get_player_command(player_id);
-                if let Some(Command::ChangeDirection(dir)) = mb_command {
+                let started = Instant::now();
+                let mb_command = self.try_get_player_command(player_id, tick, deadline);
+                let elapsed = started.elapsed();
+
+                let Some(command) = mb_command else {
+                    continue;
+                };
+                if !self.charge_budget(&mut game, player_id, tick, elapsed) {
+                    continue;
+                }
+                if let Command::ChangeDirection(dir) = command {
                     game.try_change_direction(player_id, dir);
                 }
             }
@@ -63,25 +329,177 @@ impl<'a> Server<'a> {
             self.sync_with_spectators();
 
             game.tick();
+            if !warmup {
+                ticks_played += 1;
+            } else if !game.is_warmup() {
+                info!("the warm-up is over, starting the game");
+            }
+            self.write_trace(&mut game);
+
+            if self
+                .territory_win
+                .is_some_and(|threshold| game.max_territory_share() >= threshold)
+            {
+                info!("territory threshold is reached on tick #{tick}, ending the game");
+                win_reason = WinReason::Territory;
+                break;
+            }
+
+            if let Some(tick_duration) = self.admin.tick_duration {
+                if let Some(rest) = tick_duration.checked_sub(tick_started.elapsed()) {
+                    thread::sleep(rest);
+                }
+            }
         }
 
         self.send_to_all(&Message::EndGame {});
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.flush() {
+                error!("failed to write recording: {err}");
+            }
+        }
 
-        let mb_leader_id = game.leader_id();
+        let mb_leader_id = match win_reason {
+            WinReason::Score => game.leader_id(),
+            WinReason::Territory => game.territory_leader_id(),
+        };
         match mb_leader_id {
-            Some(player_id) => println!("Winner is Player #{player_id}!"),
+            Some(player_id) => println!("Winner is Player #{player_id}! (by {win_reason})"),
             None => println!("There is no winner (tie)"),
         }
 
-        game.get_player_scores()
-            .into_iter()
+        if self.cpu_budget.is_some() {
+            for (player_id, budget) in self.player_budgets.iter() {
+                info!("Player #{player_id} CPU budget: {}", budget.stats());
+            }
+        }
+
+        for (player_id, endpoint) in self.player_endpoints.iter() {
+            let stats = endpoint.write_stats();
+            info!(
+                "Player #{player_id} output: {} bytes in {} writes",
+                stats.bytes_written, stats.writes
+            );
+        }
+        for (i, endpoint) in self.spectator_endpoints.iter().enumerate() {
+            let stats = endpoint.write_stats();
+            info!(
+                "Spectator #{} output: {} bytes in {} writes",
+                i + 1,
+                stats.bytes_written,
+                stats.writes
+            );
+        }
+
+        let has_budget = self.cpu_budget.is_some();
+        let players = game
+            .get_player_scores()
+            .iter()
             .zip(self.player_io_errors)
-            .map(|(score, io_error)| PlayerResult { score, io_error })
+            .zip(self.player_budgets)
+            .map(|(((player_id, &score), io_error), budget)| PlayerResult {
+                score,
+                io_error,
+                win_reason: (mb_leader_id == Some(player_id)).then_some(win_reason),
+                loss_reason: game.loss_reason(player_id),
+                budget: has_budget.then(|| *budget.stats()),
+                timeouts: self.player_timeouts[player_id].total(),
+                output: self.player_endpoints[player_id].write_stats(),
+            })
             .collect::<Vec<_>>()
-            .into()
+            .into();
+        GameOutcome {
+            players,
+            ticks_played,
+        }
+    }
+
+    /// Charges the time the player took to respond on this tick to its budget, returns
+    /// whether the command should be applied.
+    fn charge_budget(
+        &mut self,
+        game: &mut Game,
+        player_id: PlayerId,
+        tick: usize,
+        elapsed: Duration,
+    ) -> bool {
+        let Some(config) = &self.cpu_budget else {
+            return true;
+        };
+        if game.has_lost(player_id) {
+            return true;
+        }
+
+        let budget = &mut self.player_budgets[player_id];
+        if !budget.charge(config, elapsed) {
+            return true;
+        }
+
+        let violations = budget.stats().violations;
+        match config.policy {
+            BudgetPolicy::Warn => {
+                warn!("Player #{player_id} exceeded its CPU budget on tick #{tick}: {elapsed:?}");
+                true
+            }
+            BudgetPolicy::NoOp => {
+                warn!(
+                    "Player #{player_id} exceeded its CPU budget on tick #{tick}: {elapsed:?}, \
+                    ignoring its command"
+                );
+                false
+            }
+            BudgetPolicy::Forfeit if violations >= config.max_violations => {
+                warn!(
+                    "Player #{player_id} exceeded its CPU budget on tick #{tick}: {elapsed:?}, \
+                    forfeiting it after {violations} violation(s)"
+                );
+                game.forfeit(player_id);
+                false
+            }
+            BudgetPolicy::Forfeit => {
+                warn!(
+                    "Player #{player_id} exceeded its CPU budget on tick #{tick}: {elapsed:?}, \
+                    violation {violations} of {}",
+                    config.max_violations
+                );
+                true
+            }
+        }
+    }
+
+    fn write_trace(&mut self, game: &mut Game) {
+        let Some(writer) = &mut self.game_trace else {
+            return;
+        };
+        let records = game.take_trace_records();
+        if let Err(err) = trace::write_records(&records, writer) {
+            error!("failed to write game trace, disabling it: {err}");
+            self.game_trace = None;
+        }
+    }
+
+    fn send_statuses(&mut self, tick_num: u32) {
+        let Some(feed) = &self.status_feed else {
+            return;
+        };
+        let updates = feed.try_iter().collect::<Vec<_>>();
+        for StatusUpdate { text, level } in updates {
+            debug!("sending status to spectators: {text}");
+            self.send_to_spectators(&Message::Status {
+                tick_num,
+                text,
+                level,
+            });
+        }
     }
 
     fn send_to_spectators(&mut self, message: &Message) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.record(message) {
+                error!("failed to write recording, disabling it: {err}");
+                self.recorder = None;
+            }
+        }
         for endpoint in self.spectator_endpoints.iter_mut() {
             if let Err(err) = endpoint.send_message(message) {
                 error!("failed to send message to spectator: {err}");
@@ -111,21 +529,151 @@ impl<'a> Server<'a> {
         self.send_to_spectators(message);
     }
 
-    fn try_get_player_command(&mut self, player_id: PlayerId) -> Option<Command> {
+    /// Reads the response of the player to the current tick, skipping the late ones to
+    /// the ticks it has timed out on, if any.
+    fn try_get_player_command(
+        &mut self,
+        player_id: PlayerId,
+        tick: usize,
+        deadline: Option<Instant>,
+    ) -> Option<Command> {
         if self.player_io_errors[player_id].is_some() {
             return None;
         }
         let endpoint = &mut self.player_endpoints[player_id];
-        match endpoint.get_command() {
-            Ok(cmd) => Some(cmd),
-            Err(err) => {
-                error!("failed to get command from Player #{player_id}: {err}");
-                self.player_io_errors[player_id] = Some(err);
-                None
+        let timeouts = &mut self.player_timeouts[player_id];
+        loop {
+            let mut result = Ok(());
+            if let Some(deadline) = deadline {
+                let timeout = deadline
+                    .saturating_duration_since(Instant::now())
+                    .max(MIN_READ_TIMEOUT);
+                result = endpoint.set_read_timeout(Some(timeout));
+            }
+
+            match result.and_then(|()| endpoint.get_command()) {
+                Ok(cmd) => {
+                    if timeouts.respond() {
+                        return Some(cmd);
+                    }
+                    debug!("skipping a late command of Player #{player_id}");
+                }
+                Err(err) => {
+                    let is_timeout = timeout::is_timeout(&err);
+                    match self.turn_timeout.filter(|_| is_timeout) {
+                        Some(config) if !timeouts.time_out(&config) => {
+                            warn!("Player #{player_id} timed out on tick #{tick}, ignoring it");
+                        }
+                        Some(config) => {
+                            error!(
+                                "Player #{player_id} timed out on tick #{tick}, dropping it after \
+                                {} timeout(s) in a row",
+                                config.max_consecutive
+                            );
+                            self.player_io_errors[player_id] = Some(err);
+                        }
+                        None => {
+                            error!("failed to get command from Player #{player_id}: {err}");
+                            self.player_io_errors[player_id] = Some(err);
+                        }
+                    }
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Answers the console, waiting for it to resume the game while it's paused.
+    fn handle_admin_requests(&mut self, game: &Game, tick: usize) {
+        let Some(requests) = self.admin_requests.take() else {
+            return;
+        };
+        loop {
+            let request = if self.admin.paused {
+                match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => {
+                        warn!("admin console is gone, resuming the game");
+                        self.admin.paused = false;
+                        break;
+                    }
+                }
+            } else {
+                match requests.try_recv() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                }
+            };
+            let response = self.handle_admin_command(game, tick, request.command);
+            // The session may be closed by now, which is its own business.
+            let _ = request.reply.send(response);
+        }
+        self.admin_requests = Some(requests);
+    }
+
+    fn handle_admin_command(
+        &mut self,
+        game: &Game,
+        tick: usize,
+        command: AdminCommand,
+    ) -> AdminResponse {
+        match command {
+            AdminCommand::Pause => {
+                info!("the game is paused by the admin on tick #{tick}");
+                self.admin.paused = true;
+            }
+            AdminCommand::Resume => {
+                info!("the game is resumed by the admin on tick #{tick}");
+                self.admin.paused = false;
+            }
+            AdminCommand::Status => return AdminResponse::Status(self.admin_status(game, tick)),
+            AdminCommand::DumpWorld => return AdminResponse::World(game.get_spectator_world()),
+            AdminCommand::Kick(player_id) => {
+                if player_id.get() > self.player_endpoints.len() {
+                    return AdminResponse::error(format!("there is no Player #{player_id}"));
+                }
+                if self.player_io_errors[player_id].is_some() {
+                    return AdminResponse::error(format!(
+                        "Player #{player_id} is already disconnected"
+                    ));
+                }
+                warn!("Player #{player_id} is kicked by the admin on tick #{tick}");
+                self.player_io_errors[player_id] = Some(io::Error::other("kicked by the admin"));
+            }
+            AdminCommand::End => {
+                self.admin.end_requested = true;
+                self.admin.paused = false;
+            }
+            AdminCommand::SetTickMs(millis) => {
+                self.admin.tick_duration = (millis > 0).then(|| Duration::from_millis(millis));
             }
         }
+        AdminResponse::Ok
     }
 
+    fn admin_status(&self, game: &Game, tick: usize) -> GameStatus {
+        GameStatus {
+            tick,
+            paused: self.admin.paused,
+            tick_ms: self
+                .admin
+                .tick_duration
+                .map(|duration| duration.as_millis() as u64),
+            players: game
+                .get_player_scores()
+                .iter()
+                .map(|(player_id, &score)| PlayerStatus {
+                    player_id,
+                    score,
+                    has_lost: game.has_lost(player_id),
+                    connected: self.player_io_errors[player_id].is_none(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Waits for every spectator to respond to the tick, which `SpectatorHandle`s do right
+    /// away.
     fn sync_with_spectators(&mut self) {
         for endpoint in self.spectator_endpoints.iter_mut() {
             if let Err(err) = endpoint.get_command() {
@@ -134,3 +682,818 @@ impl<'a> Server<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        admin,
+        bot::{BotEndpoint, BotKind},
+        replay::{self, Replayer},
+        spectator::SpectatorHandle,
+    };
+
+    use paperio_proto::{writer::MessageWriter, Direction, StatusLevel, World};
+
+    use std::{
+        collections::VecDeque,
+        io::{BufRead, BufReader},
+        iter,
+        net::{TcpListener, TcpStream},
+        sync::{
+            mpsc::{self, Sender},
+            Arc, Mutex,
+        },
+        thread,
+    };
+
+    #[derive(Default)]
+    struct ScriptedEndpoint {
+        commands: VecDeque<Command>,
+        delays: VecDeque<Duration>,
+        read_timeout: Option<Duration>,
+        messages: Vec<Message>,
+    }
+
+    impl ScriptedEndpoint {
+        fn new(commands: impl IntoIterator<Item = Command>) -> Self {
+            Self {
+                commands: commands.into_iter().collect(),
+                delays: VecDeque::new(),
+                read_timeout: None,
+                messages: vec![],
+            }
+        }
+
+        /// Delays the first responses, one delay per tick. Unless some are longer than the
+        /// read timeout: those time out and are read on the next tick, before the response
+        /// to it, which takes the next delay.
+        fn with_delays(mut self, delays: impl IntoIterator<Item = Duration>) -> Self {
+            self.delays = delays.into_iter().collect();
+            self
+        }
+
+        fn tick_count(&self) -> usize {
+            self.messages
+                .iter()
+                .filter(|m| matches!(m, Message::Tick(_)))
+                .count()
+        }
+    }
+
+    impl Endpoint for ScriptedEndpoint {
+        fn send_message(&mut self, message: &Message) -> io::Result<()> {
+            self.messages.push(message.clone());
+            Ok(())
+        }
+
+        fn get_command(&mut self) -> io::Result<Command> {
+            if let Some(delay) = self.delays.pop_front() {
+                if let Some(timeout) = self.read_timeout.filter(|timeout| delay > *timeout) {
+                    // The command stays queued, it's just late.
+                    thread::sleep(timeout);
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                thread::sleep(delay);
+            }
+            Ok(self.commands.pop_front().unwrap_or(Command::NoOp))
+        }
+
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+            self.read_timeout = timeout;
+            Ok(())
+        }
+    }
+
+    // Walks a loop around the left side of the initial territory, capturing 4 new cells
+    // (13 of 961 in total) on the 6th tick.
+    fn capturing_commands() -> Vec<Command> {
+        vec![
+            Command::NoOp,
+            Command::NoOp,
+            Command::ChangeDirection(Direction::Up),
+            Command::NoOp,
+            Command::ChangeDirection(Direction::Right),
+            Command::ChangeDirection(Direction::Down),
+        ]
+    }
+
+    fn run_with_threshold(threshold: Option<f64>) -> (ScriptedEndpoint, GameOutcome) {
+        let mut endpoint = ScriptedEndpoint::new(capturing_commands());
+        let players: PlayerIndexedVector<_> = vec![&mut endpoint].into();
+        let mut server = Server::new(players, Vec::<ScriptedEndpoint>::new());
+        if let Some(threshold) = threshold {
+            server = server.with_territory_win(threshold);
+        }
+        let outcome = server.run_game(20);
+        (endpoint, outcome)
+    }
+
+    #[test]
+    fn territory_win_ends_on_crossing_tick() {
+        let (endpoint, outcome) = run_with_threshold(Some(13. / 961.));
+
+        assert_eq!(endpoint.tick_count(), 6);
+        assert_eq!(outcome.ticks_played, 6);
+        assert_eq!(endpoint.messages.last(), Some(&Message::EndGame {}));
+
+        let result = &outcome.players[PlayerId::new(1).unwrap()];
+        assert_eq!(result.score, 4);
+        assert_eq!(result.win_reason, Some(WinReason::Territory));
+    }
+
+    #[test]
+    fn territory_win_below_threshold() {
+        let (endpoint, outcome) = run_with_threshold(Some(14. / 961.));
+
+        assert_eq!(endpoint.tick_count(), 20);
+        assert_eq!(outcome.ticks_played, 20);
+        assert_eq!(endpoint.messages.last(), Some(&Message::EndGame {}));
+
+        let result = &outcome.players[PlayerId::new(1).unwrap()];
+        assert_eq!(result.win_reason, Some(WinReason::Score));
+    }
+
+    #[test]
+    fn no_territory_win_by_default() {
+        let (endpoint, _) = run_with_threshold(None);
+        assert_eq!(endpoint.tick_count(), 20);
+    }
+
+    const FAST: Duration = Duration::ZERO;
+    const SLOW: Duration = Duration::from_millis(150);
+
+    fn budget_config(policy: BudgetPolicy) -> BudgetConfig {
+        BudgetConfig::new(Duration::from_millis(50), policy).with_cap(Duration::from_millis(50))
+    }
+
+    fn run_with_budget(
+        commands: Vec<Command>,
+        delays: Vec<Duration>,
+        config: Option<BudgetConfig>,
+    ) -> PlayerResult {
+        let mut endpoint = ScriptedEndpoint::new(commands).with_delays(delays);
+        let players: PlayerIndexedVector<_> = vec![&mut endpoint].into();
+        let mut server = Server::new(players, Vec::<ScriptedEndpoint>::new());
+        if let Some(config) = config {
+            server = server.with_cpu_budget(config);
+        }
+        let mut results = server.run(10).into_iter();
+        results.next().unwrap()
+    }
+
+    #[test]
+    fn budget_warn_keeps_commands() {
+        let delays = vec![FAST, FAST, SLOW];
+        let config = budget_config(BudgetPolicy::Warn);
+        let result = run_with_budget(capturing_commands(), delays, Some(config));
+        let expected = run_with_budget(capturing_commands(), vec![], None);
+
+        assert_eq!(result.score, expected.score);
+        assert_eq!(result.loss_reason, None);
+        assert!(expected.budget.is_none());
+
+        let stats = result.budget.unwrap();
+        assert_eq!(stats.responses, 10);
+        assert_eq!(stats.violations, 1);
+        assert!(stats.max >= SLOW);
+    }
+
+    #[test]
+    fn budget_noop_drops_slow_command() {
+        let delays = vec![FAST, FAST, SLOW];
+        let config = budget_config(BudgetPolicy::NoOp);
+        let result = run_with_budget(capturing_commands(), delays, Some(config));
+
+        let mut commands = capturing_commands();
+        commands[2] = Command::NoOp;
+        let expected = run_with_budget(commands, vec![], None);
+
+        assert_eq!(result.score, expected.score);
+        assert_ne!(result.score, 4);
+        assert_eq!(result.loss_reason, expected.loss_reason);
+        assert_eq!(result.budget.unwrap().violations, 1);
+    }
+
+    #[test]
+    fn budget_forfeit_after_violations() {
+        let config = budget_config(BudgetPolicy::Forfeit).with_max_violations(2);
+        let result = run_with_budget(capturing_commands(), vec![SLOW, FAST, SLOW], Some(config));
+        assert_eq!(result.loss_reason, Some(LossReason::Forfeited));
+        // Only the responses up to the forfeiting one are accounted.
+        assert_eq!(result.budget.unwrap().responses, 3);
+
+        let config = budget_config(BudgetPolicy::Forfeit).with_max_violations(3);
+        let result = run_with_budget(capturing_commands(), vec![SLOW, FAST, SLOW], Some(config));
+        assert_eq!(result.loss_reason, None);
+        assert_eq!(result.budget.unwrap().violations, 2);
+    }
+
+    #[test]
+    fn budget_accumulates_over_fast_ticks() {
+        let config = BudgetConfig::new(Duration::from_millis(40), BudgetPolicy::Warn)
+            .with_cap(Duration::from_millis(400));
+
+        let delays = vec![FAST, FAST, FAST, FAST, Duration::from_millis(100)];
+        let result = run_with_budget(vec![], delays, Some(config));
+        assert_eq!(result.budget.unwrap().violations, 0);
+
+        let result = run_with_budget(vec![], vec![Duration::from_millis(100)], Some(config));
+        assert_eq!(result.budget.unwrap().violations, 1);
+    }
+
+    fn run_with_timeout(delays: Vec<Duration>, config: TimeoutConfig) -> PlayerResult {
+        let mut endpoint = ScriptedEndpoint::new(capturing_commands()).with_delays(delays);
+        let players: PlayerIndexedVector<_> = vec![&mut endpoint].into();
+        let server = Server::new(players, Vec::<ScriptedEndpoint>::new()).with_turn_timeout(config);
+        let mut results = server.run(10).into_iter();
+        results.next().unwrap()
+    }
+
+    const TURN: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn timeout_is_noop() {
+        let result = run_with_timeout(vec![FAST, FAST, SLOW], TimeoutConfig::new(TURN));
+
+        // The late command isn't applied on the next tick either.
+        let mut commands = capturing_commands();
+        commands[2] = Command::NoOp;
+        let expected = run_with_budget(commands, vec![], None);
+
+        assert_eq!(result.score, expected.score);
+        assert_ne!(result.score, 4);
+        assert_eq!(result.loss_reason, expected.loss_reason);
+        assert!(result.io_error.is_none());
+        assert_eq!(result.timeouts, 1);
+    }
+
+    #[test]
+    fn consecutive_timeouts_drop_player() {
+        let config = TimeoutConfig::new(TURN).with_max_consecutive(2);
+
+        let result = run_with_timeout(vec![SLOW, SLOW], config);
+        let err = result.io_error.unwrap();
+        assert!(timeout::is_timeout(&err), "{err}");
+        assert_eq!(result.timeouts, 2);
+
+        // The late response of the first tick doesn't break the row, the next one does.
+        let result = run_with_timeout(vec![SLOW, FAST, FAST, SLOW], config);
+        assert!(result.io_error.is_none());
+        assert_eq!(result.timeouts, 2);
+    }
+
+    #[test]
+    fn timeouts_keep_tick_rate() {
+        const TICKS: u32 = 10;
+        let mut fast = ScriptedEndpoint::default().with_delays(vec![TURN * 3 / 5; 20]);
+        let mut slow = ScriptedEndpoint::default().with_delays(vec![Duration::from_secs(1); 20]);
+        let players: PlayerIndexedVector<_> = vec![&mut fast, &mut slow].into();
+        let config = TimeoutConfig::new(TURN).with_max_consecutive(TICKS as usize + 1);
+
+        let started = Instant::now();
+        let results = Server::new(players, Vec::<ScriptedEndpoint>::new())
+            .with_turn_timeout(config)
+            .run(TICKS as usize);
+        let elapsed = started.elapsed();
+
+        // The deadline is shared, so the slow player has got what the fast one has left.
+        assert!(elapsed >= TURN * TICKS, "{elapsed:?}");
+        assert!(elapsed < TURN * TICKS + TURN * 4, "{elapsed:?}");
+        let results = results.into_iter().collect::<Vec<_>>();
+        assert_eq!(results[0].timeouts, 0);
+        assert_eq!(results[1].timeouts, TICKS as usize);
+        assert!(results[1].io_error.is_none());
+        assert_eq!(slow.tick_count(), TICKS as usize);
+    }
+
+    fn admin_request(
+        requests: &Sender<AdminRequest>,
+        command: AdminCommand,
+    ) -> Receiver<AdminResponse> {
+        let (reply, response) = mpsc::channel();
+        requests.send(AdminRequest { command, reply }).unwrap();
+        response
+    }
+
+    fn admin_status(response: AdminResponse) -> GameStatus {
+        match response {
+            AdminResponse::Status(status) => status,
+            response => panic!("expected status, got {response:?}"),
+        }
+    }
+
+    #[test]
+    fn admin_kick_and_tick_ms() {
+        const TICKS: u32 = 5;
+        let (sender, receiver) = mpsc::channel();
+        let responses = [
+            AdminCommand::Kick(PlayerId::new(2).unwrap()),
+            AdminCommand::Kick(PlayerId::new(2).unwrap()),
+            AdminCommand::Kick(PlayerId::new(3).unwrap()),
+            AdminCommand::SetTickMs(20),
+            AdminCommand::Status,
+        ]
+        .map(|command| admin_request(&sender, command));
+
+        let mut first = ScriptedEndpoint::default();
+        let mut second = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut first, &mut second].into();
+        let started = Instant::now();
+        let results = Server::new(players, Vec::<ScriptedEndpoint>::new())
+            .with_admin(receiver)
+            .run(TICKS as usize);
+        let elapsed = started.elapsed();
+
+        let [kick, kick_again, kick_missing, set_tick_ms, status] =
+            responses.map(|response| response.recv().unwrap());
+        assert_eq!(kick, AdminResponse::Ok);
+        assert_eq!(
+            kick_again,
+            AdminResponse::error("Player #2 is already disconnected")
+        );
+        assert_eq!(kick_missing, AdminResponse::error("there is no Player #3"));
+        assert_eq!(set_tick_ms, AdminResponse::Ok);
+        let status = admin_status(status);
+        assert_eq!(
+            (status.tick, status.paused, status.tick_ms),
+            (0, false, Some(20))
+        );
+        let connected = status
+            .players
+            .iter()
+            .map(|p| p.connected)
+            .collect::<Vec<_>>();
+        assert_eq!(connected, [true, false]);
+
+        assert!(elapsed >= Duration::from_millis(20) * TICKS, "{elapsed:?}");
+        let results = results.into_iter().collect::<Vec<_>>();
+        assert!(results[0].io_error.is_none());
+        let err = results[1].io_error.as_ref().unwrap();
+        assert_eq!(err.to_string(), "kicked by the admin");
+        assert_eq!(first.tick_count(), TICKS as usize);
+        assert_eq!(second.tick_count(), 0);
+    }
+
+    #[test]
+    fn admin_pause_gates_ticks() {
+        const TICKS: usize = 1000;
+        let (sender, receiver) = mpsc::channel();
+        let mut endpoint = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut endpoint].into();
+        let server = Server::new(players, Vec::<ScriptedEndpoint>::new()).with_admin(receiver);
+
+        let results = thread::scope(|s| {
+            s.spawn(move || {
+                let request = |command| admin_request(&sender, command).recv().unwrap();
+                assert_eq!(request(AdminCommand::SetTickMs(5)), AdminResponse::Ok);
+                assert_eq!(request(AdminCommand::Pause), AdminResponse::Ok);
+                let paused = admin_status(request(AdminCommand::Status));
+                assert!(paused.paused);
+
+                thread::sleep(Duration::from_millis(50));
+                assert_eq!(admin_status(request(AdminCommand::Status)), paused);
+
+                assert_eq!(request(AdminCommand::Resume), AdminResponse::Ok);
+                thread::sleep(Duration::from_millis(50));
+                let resumed = admin_status(request(AdminCommand::Status));
+                assert!(!resumed.paused);
+                assert!(resumed.tick > paused.tick, "{resumed:?}");
+
+                assert_eq!(request(AdminCommand::End), AdminResponse::Ok);
+            });
+            server.run(TICKS)
+        });
+
+        assert!(results.into_iter().all(|result| result.io_error.is_none()));
+        assert!(endpoint.tick_count() < TICKS);
+    }
+
+    #[test]
+    fn admin_gone_resumes() {
+        let (sender, receiver) = mpsc::channel();
+        let response = admin_request(&sender, AdminCommand::Pause);
+        drop(sender);
+
+        let mut endpoint = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut endpoint].into();
+        Server::new(players, Vec::<ScriptedEndpoint>::new())
+            .with_admin(receiver)
+            .run(3);
+
+        assert_eq!(response.recv().unwrap(), AdminResponse::Ok);
+        assert_eq!(endpoint.tick_count(), 3);
+    }
+
+    #[test]
+    fn admin_console_over_socket() {
+        const TICKS: usize = 1000;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let console = admin::spawn_admin_console(listener, Some("secret".to_string()));
+
+        // Paused from the start, or the game would be over before the console connects.
+        let (sender, requests) = mpsc::channel();
+        let pause = admin_request(&sender, AdminCommand::Pause);
+        thread::spawn(move || {
+            for request in console {
+                if sender.send(request).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut endpoint = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut endpoint].into();
+        let server = Server::new(players, Vec::<ScriptedEndpoint>::new()).with_admin(requests);
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                let mut writer = TcpStream::connect(address).unwrap();
+                let mut lines = BufReader::new(writer.try_clone().unwrap()).lines();
+                let mut send = |line: &str| -> AdminResponse {
+                    writeln!(writer, "{line}").unwrap();
+                    serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap()
+                };
+
+                assert_eq!(send("secret"), AdminResponse::Ok);
+                assert_eq!(send("set tick-ms 5"), AdminResponse::Ok);
+                assert_eq!(send("pause"), AdminResponse::Ok);
+                let paused = admin_status(send("status")).tick;
+
+                thread::sleep(Duration::from_millis(50));
+                assert_eq!(admin_status(send("status")).tick, paused);
+
+                assert_eq!(send("resume"), AdminResponse::Ok);
+                thread::sleep(Duration::from_millis(50));
+                assert!(admin_status(send("status")).tick > paused);
+                assert_eq!(send("end"), AdminResponse::Ok);
+            });
+            server.run(TICKS)
+        });
+
+        assert_eq!(pause.recv().unwrap(), AdminResponse::Ok);
+        assert!(endpoint.tick_count() < TICKS);
+    }
+
+    #[test]
+    fn statuses_go_to_spectators_before_tick() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let update = |text: &str| StatusUpdate {
+            text: text.to_string(),
+            level: StatusLevel::Alert,
+        };
+        sender.send(update("first")).unwrap();
+        sender.send(update("second")).unwrap();
+
+        let mut player = ScriptedEndpoint::default();
+        let mut spectator = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut player].into();
+        Server::new(players, vec![&mut spectator])
+            .with_status_feed(receiver)
+            .run(3);
+
+        assert!(player
+            .messages
+            .iter()
+            .all(|m| !matches!(m, Message::Status { .. })));
+
+        let Message::Tick(world) = &spectator.messages[3] else {
+            panic!("expected a tick, got {:?}", spectator.messages[3]);
+        };
+        for (message, text) in spectator.messages[1..3].iter().zip(["first", "second"]) {
+            assert_eq!(
+                message,
+                &Message::Status {
+                    tick_num: world.tick_num,
+                    text: text.to_string(),
+                    level: StatusLevel::Alert,
+                }
+            );
+        }
+        assert_eq!(spectator.tick_count(), 3);
+        assert_eq!(spectator.messages.len(), 7);
+    }
+
+    #[test]
+    fn player_infos_go_to_spectators() {
+        let mut first = ScriptedEndpoint::default();
+        let mut second = ScriptedEndpoint::default();
+        let mut spectator = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut first, &mut second].into();
+        let infos = ["alice", "bob"].map(|name| PlayerInfo {
+            user_name: name.to_string(),
+        });
+        Server::new(players, vec![&mut spectator])
+            .with_player_infos(Vec::from(infos.clone()).into())
+            .run(2);
+
+        assert!(matches!(spectator.messages[0], Message::StartGame(_)));
+        let Message::PlayerAnnouncement(announced) = &spectator.messages[1] else {
+            panic!("expected an announcement, got {:?}", spectator.messages[1]);
+        };
+        assert_eq!(announced.len(), 2);
+        assert_eq!(announced["1"], infos[0]);
+        assert_eq!(announced["2"], infos[1]);
+        let Message::Tick(world) = &spectator.messages[2] else {
+            panic!("expected a tick, got {:?}", spectator.messages[2]);
+        };
+        assert!(world.players.keys().all(|id| announced.contains_key(id)));
+
+        for player in [&first, &second] {
+            assert!(player
+                .messages
+                .iter()
+                .all(|m| !matches!(m, Message::PlayerAnnouncement(_))));
+        }
+    }
+
+    #[test]
+    fn bots_play_a_full_game() {
+        const TICKS: usize = 300;
+        let mut external = ScriptedEndpoint::new(capturing_commands());
+        let mut spectator = ScriptedEndpoint::default();
+        let bots = [BotKind::Coward, BotKind::Coward, BotKind::Random]
+            .into_iter()
+            .enumerate()
+            .map(|(i, kind)| Box::new(BotEndpoint::new(kind, i as u64)) as Box<dyn Endpoint>);
+        let players = iter::once(Box::new(&mut external) as Box<dyn Endpoint>)
+            .chain(bots)
+            .collect::<Vec<_>>();
+
+        let results = Server::new(players.into(), [&mut spectator]).run(TICKS);
+        let results = results.into_iter().collect::<Vec<_>>();
+
+        assert_eq!(spectator.tick_count(), TICKS);
+        assert_eq!(external.tick_count(), TICKS);
+        assert!(results.iter().all(|result| result.io_error.is_none()));
+
+        let Some(Message::Tick(last_tick)) = spectator
+            .messages
+            .iter()
+            .rfind(|m| matches!(m, Message::Tick(_)))
+        else {
+            panic!("no ticks");
+        };
+        for (i, result) in results.iter().enumerate() {
+            let player = &last_tick.players[&(i + 1).to_string()];
+            assert!(result.score >= player.score);
+            if player.has_lost {
+                assert!(result.loss_reason.is_some());
+            }
+        }
+        // The scripted player runs into the border, the cowards keep capturing.
+        assert!(results[0].loss_reason.is_some());
+        for coward in &results[1..3] {
+            assert!(coward.loss_reason.is_none());
+            assert!(coward.score > results[0].score);
+        }
+
+        let max_score = results.iter().map(|result| result.score).max().unwrap();
+        let winners = results
+            .iter()
+            .filter(|result| result.win_reason.is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(winners.len(), 1);
+        assert_eq!(winners[0].score, max_score);
+        assert_eq!(winners[0].win_reason, Some(WinReason::Score));
+    }
+
+    /// Sleeps on every command, like a GUI which renders slowly or is paused.
+    struct SlowSpectator {
+        delay: Duration,
+        messages: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl Endpoint for SlowSpectator {
+        fn send_message(&mut self, message: &Message) -> io::Result<()> {
+            self.messages.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+
+        fn get_command(&mut self) -> io::Result<Command> {
+            thread::sleep(self.delay);
+            Ok(Command::NoOp)
+        }
+    }
+
+    #[test]
+    fn slow_spectator_doesnt_hold_the_game() {
+        const TICKS: usize = 100;
+        const DELAY: Duration = Duration::from_millis(10);
+        let mut fast = ScriptedEndpoint::default();
+        let messages = Arc::new(Mutex::new(vec![]));
+        let slow = SlowSpectator {
+            delay: DELAY,
+            messages: messages.clone(),
+        };
+        let players = (0..2)
+            .map(|i| BotEndpoint::new(BotKind::Coward, i))
+            .collect::<Vec<_>>();
+        let spectators = vec![
+            Box::new(&mut fast) as Box<dyn Endpoint>,
+            Box::new(SpectatorHandle::spawn(slow, 8)),
+        ];
+
+        let started = Instant::now();
+        let outcome = Server::new(players.into(), spectators).run_game(TICKS);
+        // Waiting for the spectator would take `TICKS * DELAY`.
+        assert!(started.elapsed() < DELAY * TICKS as u32 / 2);
+        assert_eq!(outcome.ticks_played, TICKS);
+        assert_eq!(fast.tick_count(), TICKS);
+
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages[0], fast.messages[0]);
+        assert_eq!(messages.last(), Some(&Message::EndGame {}));
+        let ticks = messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::Tick(world) => Some(world),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(!ticks.is_empty() && ticks.len() < TICKS);
+        assert!(ticks.windows(2).all(|w| w[0].tick_num < w[1].tick_num));
+        let last_tick = fast
+            .messages
+            .iter()
+            .rfind(|m| matches!(m, Message::Tick(_)));
+        assert_eq!(
+            Some(&Message::Tick(ticks[ticks.len() - 1].clone())),
+            last_tick
+        );
+    }
+
+    #[test]
+    fn small_map_game() {
+        const TICKS: usize = 300;
+        let params = GameParams {
+            x_cells_count: 15,
+            y_cells_count: 15,
+        };
+        let mut spectator = ScriptedEndpoint::default();
+        let players = [BotKind::Coward, BotKind::Random]
+            .into_iter()
+            .enumerate()
+            .map(|(i, kind)| BotEndpoint::new(kind, i as u64))
+            .collect::<Vec<_>>();
+
+        let outcome = Server::new(players.into(), [&mut spectator])
+            .with_map_size(params)
+            .run_game(TICKS);
+        assert_eq!(outcome.ticks_played, TICKS);
+        assert_eq!(spectator.tick_count(), TICKS);
+        assert_eq!(spectator.messages[0], Message::StartGame(params));
+
+        for message in &spectator.messages {
+            let Message::Tick(world) = message else {
+                continue;
+            };
+            for player in world.players.values() {
+                assert!(player.territory.iter().all(|&cell| params.contains(cell)));
+                assert!(player.lines.iter().all(|&cell| params.contains(cell)));
+            }
+        }
+        // The bots only lose to each other on a map they know the size of.
+        let results = outcome.players.into_iter().collect::<Vec<_>>();
+        assert!(results
+            .iter()
+            .all(|result| result.loss_reason != Some(LossReason::OutOfBounds)));
+        assert!(results[0].score > 0);
+    }
+
+    #[test]
+    #[should_panic = "invalid map size 31x8"]
+    fn invalid_map_size() {
+        let params = GameParams {
+            x_cells_count: 31,
+            y_cells_count: 8,
+        };
+        Server::new(
+            vec![ScriptedEndpoint::default()].into(),
+            [ScriptedEndpoint::default()],
+        )
+        .with_map_size(params);
+    }
+
+    #[test]
+    fn recording_replays_the_same() {
+        let mut players = (0..4)
+            .map(|i| {
+                let mut commands = vec![Command::NoOp; i];
+                commands.extend(capturing_commands());
+                ScriptedEndpoint::new(commands)
+            })
+            .collect::<Vec<_>>();
+        let mut spectator = ScriptedEndpoint::default();
+        let mut recording = vec![];
+        let (sender, receiver) = std::sync::mpsc::channel();
+        sender
+            .send(StatusUpdate {
+                text: "recorded".to_string(),
+                level: StatusLevel::Info,
+            })
+            .unwrap();
+        Server::new(
+            players.iter_mut().collect::<Vec<_>>().into(),
+            vec![&mut spectator],
+        )
+        .with_bonuses(BonusConfig::new(3, 7))
+        .with_status_feed(receiver)
+        .with_recording(&mut recording)
+        .run(15);
+
+        let mut replayed = ScriptedEndpoint::default();
+        let records = replay::read_records(recording.as_slice());
+        let count = Replayer::new(vec![&mut replayed])
+            .with_speed(100.)
+            .run(records)
+            .unwrap();
+
+        assert_eq!(count, spectator.messages.len());
+        assert_eq!(replayed.tick_count(), 15);
+        assert_eq!(replayed.messages, spectator.messages);
+        let mut writer = MessageWriter::new();
+        let mut encode = |message| {
+            let mut line = vec![];
+            writer.write_into(message, &mut line).unwrap();
+            line
+        };
+        for (message, expected) in replayed.messages.iter().zip(&spectator.messages) {
+            assert_eq!(encode(message), encode(expected));
+        }
+    }
+
+    fn run_with_deltas(
+        config: Option<DeltaConfig>,
+    ) -> (ScriptedEndpoint, ScriptedEndpoint, ScriptedEndpoint) {
+        let mut second_commands = vec![Command::ChangeDirection(Direction::Down); 3];
+        second_commands.extend(capturing_commands());
+
+        let mut first = ScriptedEndpoint::new(capturing_commands());
+        let mut second = ScriptedEndpoint::new(second_commands);
+        let mut spectator = ScriptedEndpoint::default();
+        let players: PlayerIndexedVector<_> = vec![&mut first, &mut second].into();
+        let mut server =
+            Server::new(players, vec![&mut spectator]).with_bonuses(BonusConfig::new(3, 7));
+        if let Some(config) = config {
+            server = server.with_deltas(config);
+        }
+        server.run(20);
+        (first, second, spectator)
+    }
+
+    /// The worlds of all the ticks, patched with the deltas.
+    fn reconstruct(endpoint: &ScriptedEndpoint) -> Vec<World> {
+        let mut worlds: Vec<World> = vec![];
+        for message in &endpoint.messages {
+            match message {
+                Message::Tick(world) => worlds.push(world.clone()),
+                Message::TickDelta(delta) => {
+                    let mut world = worlds.last().unwrap().clone();
+                    world.apply_delta(delta).unwrap();
+                    worlds.push(world);
+                }
+                _ => {}
+            }
+        }
+        worlds
+    }
+
+    #[test]
+    fn deltas_reproduce_full_ticks() {
+        let (first, second, spectator) = run_with_deltas(None);
+        let config = DeltaConfig::new([PlayerId::new(1).unwrap()], true).with_keyframe_interval(7);
+        let (first_deltas, second_deltas, spectator_deltas) = run_with_deltas(Some(config));
+
+        let full_ticks = |endpoint: &ScriptedEndpoint| {
+            endpoint
+                .messages
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| matches!(m, Message::Tick(_)))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        // The start game message goes first.
+        assert_eq!(full_ticks(&first_deltas), [1, 8, 15]);
+        assert_eq!(first_deltas.messages.len(), first.messages.len());
+        assert_eq!(second_deltas.messages, second.messages);
+
+        for (full, patched) in [(&first, &first_deltas), (&spectator, &spectator_deltas)] {
+            let expected = reconstruct(full);
+            assert_eq!(expected.len(), 20);
+            assert_eq!(reconstruct(patched), expected);
+        }
+
+        // The game has actually changed: captures, traces and bonuses.
+        let worlds = reconstruct(&spectator);
+        let territory = |world: &World, id: &str| world.players[id].territory.len();
+        assert!(territory(&worlds[19], "1") > territory(&worlds[0], "1"));
+        assert!(worlds.iter().any(|world| !world.bonuses.is_empty()));
+        assert!(worlds
+            .iter()
+            .any(|world| !world.players["2"].lines.is_empty()));
+    }
+}
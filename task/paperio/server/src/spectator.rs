@@ -0,0 +1,359 @@
+//! Serving spectators from their own threads, so that a slow one doesn't hold the game
+//! back, see `SpectatorHandle`.
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::*;
+use paperio_proto::{Command, Message};
+
+use crate::endpoint::{Endpoint, WriteStats};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How long dropping a handle waits for the spectator to receive the rest of its queue,
+/// e.g. `Message::EndGame`.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn is_frame(message: &Message) -> bool {
+    matches!(message, Message::Tick(_) | Message::TickDelta(_))
+}
+
+struct State {
+    queue: VecDeque<Message>,
+    capacity: usize,
+    /// A frame has been dropped, and the deltas up to the next full tick are useless.
+    skip_deltas: bool,
+    /// Frames have been dropped since the queue was empty last time.
+    lagging: bool,
+    dropped: usize,
+    stats: WriteStats,
+    error: Option<(io::ErrorKind, String)>,
+    closed: bool,
+    finished: bool,
+}
+
+impl State {
+    fn new(capacity: usize) -> Self {
+        Self {
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
+            skip_deltas: false,
+            lagging: false,
+            dropped: 0,
+            stats: WriteStats::default(),
+            error: None,
+            closed: false,
+            finished: false,
+        }
+    }
+
+    fn push(&mut self, message: Message) {
+        if self.queue.len() >= self.capacity {
+            if !self.lagging {
+                warn!("spectator is too slow, dropping the oldest frames");
+                self.lagging = true;
+            }
+            self.drop_oldest();
+        }
+        match message {
+            Message::TickDelta(_) if self.skip_deltas => {
+                self.dropped += 1;
+                return;
+            }
+            Message::Tick(_) => self.skip_deltas = false,
+            _ => {}
+        }
+        self.queue.push_back(message);
+    }
+
+    /// Drops the oldest frame, along with the deltas after it up to the next full tick,
+    /// which can't be applied without it. Other messages are only dropped if there are no
+    /// frames in the queue.
+    fn drop_oldest(&mut self) {
+        let Some(index) = self.queue.iter().position(is_frame) else {
+            self.queue.pop_front();
+            self.dropped += 1;
+            return;
+        };
+        self.queue.remove(index);
+        self.dropped += 1;
+
+        let mut i = index;
+        while let Some(message) = self.queue.get(i) {
+            match message {
+                Message::Tick(_) => return,
+                Message::TickDelta(_) => {
+                    self.queue.remove(i);
+                    self.dropped += 1;
+                }
+                _ => i += 1,
+            }
+        }
+        self.skip_deltas = true;
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Sends messages to a spectator from its own thread through a queue of up to `capacity`
+/// messages, and waits for its response to every frame there instead of the game.
+///
+/// If the spectator falls behind and the queue is full, the oldest frames are dropped,
+/// so that it gets thinned, but still valid, ticks. `get_command` returns right away.
+/// Dropping the handle waits a bit for the spectator to receive the rest of the queue.
+pub struct SpectatorHandle {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SpectatorHandle {
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    /// # Panics
+    ///
+    /// If the capacity is zero.
+    pub fn spawn(endpoint: impl Endpoint + Send + 'static, capacity: usize) -> Self {
+        assert!(capacity > 0, "spectator queue capacity should be positive");
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::new(capacity)),
+            changed: Condvar::new(),
+        });
+        let thread = thread::spawn({
+            let shared = shared.clone();
+            move || serve(endpoint, &shared)
+        });
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// The frames the spectator hasn't received because it was too slow.
+    pub fn dropped(&self) -> usize {
+        self.shared.state.lock().unwrap().dropped
+    }
+}
+
+fn serve(mut endpoint: impl Endpoint, shared: &Shared) {
+    loop {
+        let message = {
+            let mut state = shared
+                .changed
+                .wait_while(shared.state.lock().unwrap(), |state| {
+                    state.queue.is_empty() && !state.closed
+                })
+                .unwrap();
+            match state.queue.pop_front() {
+                Some(message) => message,
+                None => break,
+            }
+        };
+
+        let mut result = endpoint.send_message(&message);
+        if is_frame(&message) {
+            result = result.and_then(|()| endpoint.get_command().map(drop));
+        }
+
+        let mut state = shared.state.lock().unwrap();
+        state.stats = endpoint.write_stats();
+        if state.queue.is_empty() {
+            state.lagging = false;
+        }
+        if let Err(err) = result {
+            error!("failed to serve spectator, disconnecting it: {err}");
+            state.error = Some((err.kind(), err.to_string()));
+            state.queue.clear();
+            break;
+        }
+    }
+
+    shared.state.lock().unwrap().finished = true;
+    shared.changed.notify_all();
+}
+
+impl Endpoint for SpectatorHandle {
+    fn send_message(&mut self, message: &Message) -> io::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some((kind, text)) = &state.error {
+            return Err(io::Error::new(*kind, text.clone()));
+        }
+        state.push(message.clone());
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    fn get_command(&mut self) -> io::Result<Command> {
+        Ok(Command::NoOp)
+    }
+
+    fn write_stats(&self) -> WriteStats {
+        self.shared.state.lock().unwrap().stats
+    }
+}
+
+impl Drop for SpectatorHandle {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        self.shared.changed.notify_all();
+
+        let (state, result) = self
+            .shared
+            .changed
+            .wait_timeout_while(state, FLUSH_TIMEOUT, |state| !state.finished)
+            .unwrap();
+        if state.dropped > 0 {
+            info!("spectator has missed {} frame(s)", state.dropped);
+        }
+        if result.timed_out() {
+            warn!(
+                "spectator hasn't received the last {} message(s) in time, leaving it",
+                state.queue.len()
+            );
+            return;
+        }
+        drop(state);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::{World, WorldDelta};
+
+    use std::{collections::HashMap, time::Instant};
+
+    fn tick(tick_num: u32) -> Message {
+        Message::Tick(World {
+            players: HashMap::new(),
+            tick_num,
+            bonuses: vec![],
+            warmup: false,
+        })
+    }
+
+    fn delta(tick_num: u32) -> Message {
+        Message::TickDelta(WorldDelta {
+            base_tick_num: tick_num - 1,
+            tick_num,
+            players: HashMap::new(),
+            removed_players: vec![],
+            bonuses: vec![],
+            warmup: false,
+        })
+    }
+
+    fn tick_nums(messages: impl IntoIterator<Item = Message>) -> Vec<u32> {
+        messages
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::Tick(world) => Some(world.tick_num),
+                Message::TickDelta(delta) => Some(delta.tick_num),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_queue_drops_oldest_ticks() {
+        let mut state = State::new(3);
+        state.push(Message::StartGame(Default::default()));
+        for tick_num in 1..=4 {
+            state.push(tick(tick_num));
+        }
+        state.push(Message::EndGame {});
+
+        assert!(matches!(state.queue[0], Message::StartGame(_)));
+        assert!(matches!(state.queue[2], Message::EndGame {}));
+        assert_eq!(tick_nums(state.queue.clone()), [4]);
+        assert_eq!(state.dropped, 3);
+    }
+
+    #[test]
+    fn dropped_delta_skips_to_keyframe() {
+        let mut state = State::new(2);
+        state.push(tick(1));
+        state.push(delta(2));
+        state.push(delta(3));
+        assert!(state.queue.is_empty());
+        assert!(state.skip_deltas);
+        assert_eq!(state.dropped, 3);
+
+        // Nothing to apply the deltas to until a full tick comes.
+        state.push(delta(4));
+        state.push(tick(5));
+        state.push(delta(6));
+        assert_eq!(tick_nums(state.queue.clone()), [5, 6]);
+        assert_eq!(state.dropped, 4);
+
+        state.push(tick(7));
+        state.push(delta(8));
+        assert_eq!(tick_nums(state.queue.clone()), [7, 8]);
+        assert_eq!(state.dropped, 6);
+    }
+
+    struct SlowSpectator {
+        delay: Duration,
+        messages: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl Endpoint for SlowSpectator {
+        fn send_message(&mut self, message: &Message) -> io::Result<()> {
+            self.messages.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+
+        fn get_command(&mut self) -> io::Result<Command> {
+            thread::sleep(self.delay);
+            Ok(Command::NoOp)
+        }
+    }
+
+    #[test]
+    fn slow_spectator_gets_thinned_frames() {
+        const TICKS: u32 = 40;
+        let messages = Arc::new(Mutex::new(vec![]));
+        let spectator = SlowSpectator {
+            delay: Duration::from_millis(20),
+            messages: messages.clone(),
+        };
+
+        let started = Instant::now();
+        let mut handle = SpectatorHandle::spawn(spectator, 4);
+        handle
+            .send_message(&Message::StartGame(Default::default()))
+            .unwrap();
+        for tick_num in 0..TICKS {
+            handle.send_message(&tick(tick_num)).unwrap();
+            assert!(matches!(handle.get_command().unwrap(), Command::NoOp));
+        }
+        handle.send_message(&Message::EndGame {}).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+        let dropped = handle.dropped();
+        drop(handle);
+
+        let messages = messages.lock().unwrap().clone();
+        assert!(matches!(messages[0], Message::StartGame(_)));
+        assert!(matches!(messages.last(), Some(Message::EndGame {})));
+        let received = tick_nums(messages);
+        assert!(received.len() < TICKS as usize);
+        assert!(received.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(received.last(), Some(&(TICKS - 1)));
+        assert!(dropped > 0);
+    }
+}
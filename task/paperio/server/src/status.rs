@@ -0,0 +1,221 @@
+//! Status messages for spectators injected mid-game, see `Message::Status`.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use log::*;
+use paperio_proto::StatusLevel;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StatusUpdate {
+    pub text: String,
+    pub level: StatusLevel,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseStatusError {
+    UnclosedLevel,
+    UnknownLevel(String),
+    EmptyText,
+}
+
+impl fmt::Display for ParseStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnclosedLevel => write!(f, "level is not closed with ']'"),
+            Self::UnknownLevel(level) => write!(
+                f,
+                "unknown level '{level}', expected 'info', 'warning' or 'alert'"
+            ),
+            Self::EmptyText => write!(f, "text is empty"),
+        }
+    }
+}
+
+/// Parses a line like `[alert] player 2 is about to cut player 3!`. The level is
+/// optional and defaults to info. Blank lines are `Ok(None)`.
+pub fn parse_status_line(line: &str) -> Result<Option<StatusUpdate>, ParseStatusError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (level, text) = match line.strip_prefix('[') {
+        Some(rest) => {
+            let (level, text) = rest
+                .split_once(']')
+                .ok_or(ParseStatusError::UnclosedLevel)?;
+            let level = match level.trim() {
+                "info" => StatusLevel::Info,
+                "warning" => StatusLevel::Warning,
+                "alert" => StatusLevel::Alert,
+                level => return Err(ParseStatusError::UnknownLevel(level.to_string())),
+            };
+            (level, text.trim_start())
+        }
+        None => (StatusLevel::Info, line),
+    };
+    if text.is_empty() {
+        return Err(ParseStatusError::EmptyText);
+    }
+
+    Ok(Some(StatusUpdate {
+        text: text.to_string(),
+        level,
+    }))
+}
+
+/// Sends the statuses read from `reader` line by line, skipping malformed lines with a
+/// warning. Returns `false` if the receiver is gone.
+pub fn forward_status_lines(
+    reader: impl BufRead,
+    sender: &Sender<StatusUpdate>,
+) -> io::Result<bool> {
+    for line in reader.lines() {
+        let line = line?;
+        match parse_status_line(&line) {
+            Ok(Some(update)) => {
+                if sender.send(update).is_err() {
+                    return Ok(false);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => warn!("skipping malformed status line {line:?}: {err}"),
+        }
+    }
+    Ok(true)
+}
+
+/// Reads statuses from the file at `path` in a background thread. A FIFO is reopened
+/// every time its writer closes it, so that statuses can be written with e.g. `echo`.
+pub fn spawn_status_reader(path: PathBuf) -> Receiver<StatusUpdate> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let result = File::open(&path).and_then(|file| {
+            let is_fifo = is_fifo(&file)?;
+            let is_open = forward_status_lines(BufReader::new(file), &sender)?;
+            Ok(is_open && is_fifo)
+        });
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => {
+                error!("failed to read statuses from {}: {err}", path.display());
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(unix)]
+fn is_fifo(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    Ok(file.metadata()?.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(text: &str, level: StatusLevel) -> Option<StatusUpdate> {
+        Some(StatusUpdate {
+            text: text.to_string(),
+            level,
+        })
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_status_line("player 2 is about to cut player 3!"),
+            Ok(update(
+                "player 2 is about to cut player 3!",
+                StatusLevel::Info
+            ))
+        );
+        assert_eq!(
+            parse_status_line("[alert] player 2 is about to cut player 3!\n"),
+            Ok(update(
+                "player 2 is about to cut player 3!",
+                StatusLevel::Alert
+            ))
+        );
+        assert_eq!(
+            parse_status_line(" [ warning ]close call"),
+            Ok(update("close call", StatusLevel::Warning))
+        );
+        assert_eq!(
+            parse_status_line("[info] [brackets] stay"),
+            Ok(update("[brackets] stay", StatusLevel::Info))
+        );
+        assert_eq!(parse_status_line(""), Ok(None));
+        assert_eq!(parse_status_line("  \t"), Ok(None));
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert_eq!(
+            parse_status_line("[alert player 2"),
+            Err(ParseStatusError::UnclosedLevel)
+        );
+        assert_eq!(
+            parse_status_line("[loud] player 2"),
+            Err(ParseStatusError::UnknownLevel("loud".to_string()))
+        );
+        assert_eq!(
+            parse_status_line("[alert]  "),
+            Err(ParseStatusError::EmptyText)
+        );
+    }
+
+    #[test]
+    fn forward_skips_malformed() {
+        let (sender, receiver) = mpsc::channel();
+        let input = "first\n[loud] skipped\n\n[alert] second\n[unclosed\n";
+        assert!(forward_status_lines(input.as_bytes(), &sender).unwrap());
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [
+                update("first", StatusLevel::Info).unwrap(),
+                update("second", StatusLevel::Alert).unwrap(),
+            ]
+        );
+
+        drop(receiver);
+        assert!(!forward_status_lines(input.as_bytes(), &sender).unwrap());
+    }
+
+    #[test]
+    fn reader_stops_at_end_of_file() {
+        let dir = std::env::temp_dir().join(format!("paperio-status-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("statuses");
+        std::fs::write(&path, "one\n[warning] two\n").unwrap();
+
+        let receiver = spawn_status_reader(path);
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            [
+                update("one", StatusLevel::Info).unwrap(),
+                update("two", StatusLevel::Warning).unwrap(),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! Deadlines for the commands of players, see `TimeoutConfig`.
+
+use std::{io, time::Duration};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Players have `per_turn` from the moment the ticks are sent to respond, so a slow
+/// player doesn't hold the game back. A player who doesn't make it does nothing on that
+/// tick, its late response is skipped on the next one. Only `max_consecutive` timeouts
+/// in a row drop the player, as an IO error would.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeoutConfig {
+    pub per_turn: Duration,
+    pub max_consecutive: usize,
+}
+
+impl TimeoutConfig {
+    pub const DEFAULT_MAX_CONSECUTIVE: usize = 3;
+
+    pub fn new(per_turn: Duration) -> Self {
+        Self {
+            per_turn,
+            max_consecutive: Self::DEFAULT_MAX_CONSECUTIVE,
+        }
+    }
+
+    pub fn with_max_consecutive(mut self, max_consecutive: usize) -> Self {
+        self.max_consecutive = max_consecutive;
+        self
+    }
+}
+
+/// Whether a read has failed because of the read timeout: Unix reports it as
+/// `WouldBlock`, Windows as `TimedOut`.
+pub fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+pub struct PlayerTimeouts {
+    consecutive: usize,
+    total: usize,
+    /// Responses to the ticks the player has timed out on, which are still to come.
+    late_responses: usize,
+}
+
+impl PlayerTimeouts {
+    /// Accounts a timeout, returns whether the player should be dropped.
+    pub fn time_out(&mut self, config: &TimeoutConfig) -> bool {
+        self.consecutive += 1;
+        self.total += 1;
+        self.late_responses += 1;
+        self.consecutive >= config.max_consecutive
+    }
+
+    /// Accounts a response, returns whether it's the one to the current tick rather
+    /// than a late one.
+    pub fn respond(&mut self) -> bool {
+        if self.late_responses > 0 {
+            self.late_responses -= 1;
+            return false;
+        }
+        self.consecutive = 0;
+        true
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn late_responses_are_skipped() {
+        let config = TimeoutConfig::new(Duration::from_millis(10)).with_max_consecutive(3);
+        let mut timeouts = PlayerTimeouts::default();
+
+        assert!(timeouts.respond());
+        assert!(!timeouts.time_out(&config));
+        assert!(!timeouts.time_out(&config));
+        // The responses to both timed-out ticks go first.
+        assert!(!timeouts.respond());
+        assert!(!timeouts.respond());
+        assert!(timeouts.respond());
+
+        // The response has reset the row of timeouts, late ones don't.
+        assert!(!timeouts.time_out(&config));
+        assert!(!timeouts.respond());
+        assert!(!timeouts.time_out(&config));
+        assert!(timeouts.time_out(&config));
+        assert_eq!(timeouts.total(), 5);
+    }
+
+    #[test]
+    fn timeout_errors() {
+        assert!(is_timeout(&io::ErrorKind::WouldBlock.into()));
+        assert!(is_timeout(&io::ErrorKind::TimedOut.into()));
+        assert!(!is_timeout(&io::ErrorKind::UnexpectedEof.into()));
+    }
+}
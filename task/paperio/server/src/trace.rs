@@ -0,0 +1,100 @@
+use std::{fmt, io::Write};
+
+use paperio_proto::{Cell, Direction};
+use serde::Serialize;
+
+use crate::game::PlayerId;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LossReason {
+    OutOfBounds,
+    /// Lost a head-to-head collision.
+    HeadToHead,
+    /// Its trace was crossed, by itself or by another player.
+    TraceCrossed,
+    /// Was inside the territory captured by another player.
+    Encircled,
+    /// Was forfeited by the server, e.g. for exceeding its CPU budget.
+    Forfeited,
+}
+
+impl fmt::Display for LossReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfBounds => write!(f, "out of bounds"),
+            Self::HeadToHead => write!(f, "head to head"),
+            Self::TraceCrossed => write!(f, "trace crossed"),
+            Self::Encircled => write!(f, "encircled"),
+            Self::Forfeited => write!(f, "forfeited"),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    DirectionChange {
+        player_id: PlayerId,
+        direction: Direction,
+        accepted: bool,
+    },
+    HeadToHead {
+        cell: Cell,
+        contenders: Vec<PlayerId>,
+        /// Trace lengths of the contenders, in the same order.
+        trace_lengths: Vec<usize>,
+        owner: Option<PlayerId>,
+        winner: Option<PlayerId>,
+    },
+    TerritoryCaptured {
+        player_id: PlayerId,
+        enemy_cells: u32,
+        free_cells: u32,
+    },
+    PlayerLost {
+        player_id: PlayerId,
+        reason: LossReason,
+    },
+}
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct TraceRecord {
+    pub tick: u32,
+    #[serde(flatten)]
+    pub event: TraceEvent,
+}
+
+/// Collects records of a game until they are taken.
+#[derive(Default)]
+pub struct GameTrace {
+    records: Vec<TraceRecord>,
+}
+
+impl GameTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, tick: u32, event: TraceEvent) {
+        self.records.push(TraceRecord { tick, event });
+    }
+
+    pub fn take_records(&mut self) -> Vec<TraceRecord> {
+        std::mem::take(&mut self.records)
+    }
+}
+
+/// Writes one JSON line per record.
+pub fn write_records(records: &[TraceRecord], mut writer: impl Write) -> anyhow::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Marking the start of every tick on stderr when the launcher asks for it, see
+//! `paperio_proto::heartbeat`.
+
+use paperio_proto::heartbeat::{self, ENV_VAR};
+
+use std::{
+    env,
+    io::{self, Write},
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Disabled by default, so that nothing is written unless the launcher reads it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Heartbeat {
+    enabled: bool,
+}
+
+impl Heartbeat {
+    /// Enabled if `ENV_VAR` is set to `1`.
+    pub fn from_env() -> Self {
+        Self::from_value(env::var(ENV_VAR).ok().as_deref())
+    }
+
+    fn from_value(value: Option<&str>) -> Self {
+        Self {
+            enabled: value == Some("1"),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Writes the marker of the tick to stderr. It's only a hint for the launcher, so
+    /// errors are ignored.
+    pub fn beat(&self, tick_num: u32) {
+        self.beat_to(&mut io::stderr().lock(), tick_num);
+    }
+
+    fn beat_to(&self, writer: &mut impl Write, tick_num: u32) {
+        if self.enabled {
+            let _ = heartbeat::write_marker(writer, tick_num);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::heartbeat::parse_marker;
+
+    #[test]
+    fn gated_by_env_var() {
+        assert!(Heartbeat::from_value(Some("1")).is_enabled());
+        for value in [None, Some(""), Some("0"), Some("yes")] {
+            assert!(!Heartbeat::from_value(value).is_enabled(), "{value:?}");
+        }
+        assert!(!Heartbeat::default().is_enabled());
+
+        env::set_var(ENV_VAR, "1");
+        assert!(Heartbeat::from_env().is_enabled());
+        env::remove_var(ENV_VAR);
+        assert!(!Heartbeat::from_env().is_enabled());
+    }
+
+    #[test]
+    fn beats_only_when_enabled() {
+        let mut output = vec![];
+        Heartbeat::default().beat_to(&mut output, 3);
+        assert!(output.is_empty());
+
+        let heartbeat = Heartbeat::from_value(Some("1"));
+        heartbeat.beat_to(&mut output, 3);
+        heartbeat.beat_to(&mut output, 4);
+        let lines = String::from_utf8(output).unwrap();
+        let ticks = lines.lines().map(parse_marker).collect::<Vec<_>>();
+        assert_eq!(ticks, [Some(3), Some(4)]);
+    }
+}
@@ -1,3 +1,6 @@
 #![forbid(unsafe_code)]
 
+pub mod heartbeat;
+pub mod pathfinding;
+pub mod risk;
 pub mod strategy;
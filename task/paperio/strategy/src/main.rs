@@ -1,10 +1,10 @@
 #![forbid(unsafe_code)]
 
 use paperio_proto::{
-    traits::{JsonRead, JsonWrite},
-    Command, Message,
+    traits::{Format, MessageRead, MessageWrite},
+    Command, Message, World,
 };
-use paperio_strategy::strategy::Strategy;
+use paperio_strategy::{heartbeat::Heartbeat, strategy::Strategy};
 
 use std::{
     io::{stdin, stdout, BufReader, Read, Write},
@@ -12,17 +12,32 @@ use std::{
 };
 
 fn run(reader: impl Read, mut writer: impl Write) {
+    let format = Format::from_env().unwrap();
     let mut reader = BufReader::new(reader);
 
-    let Ok(Message::StartGame(_)) = reader.read_message() else {
+    let Ok(Message::StartGame(params)) = reader.read_message_as(format) else {
         panic!("expected the first message to be 'start_game'");
     };
 
-    let mut strategy = Strategy::new();
-    while let Ok(Message::Tick(tick_params)) = reader.read_message() {
-        let direction = strategy.on_tick(tick_params);
+    let mut strategy = Strategy::with_params(params).with_heartbeat(Heartbeat::from_env());
+    let mut last_world = None::<World>;
+    loop {
+        let world = match reader.read_message_as(format) {
+            Ok(Message::Tick(world)) => world,
+            Ok(Message::TickDelta(delta)) => {
+                let mut world = last_world.take().expect("got a tick delta before a tick");
+                world
+                    .apply_delta(&delta)
+                    .expect("failed to apply a tick delta");
+                world
+            }
+            _ => break,
+        };
+        last_world = Some(world.clone());
+
+        let direction = strategy.on_tick(world);
         let msg = Command::ChangeDirection(direction);
-        writer.write_command(&msg).unwrap();
+        writer.write_command_as(format, &msg).unwrap();
         writer.flush().unwrap();
     }
 }
@@ -0,0 +1,342 @@
+//! Distances over the map and the way back home, as pure functions of `World`, see
+//! `best_return_path`.
+
+use paperio_proto::{Cell, Direction, GameParams, World};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The distance to cells no source can reach.
+pub const UNREACHABLE: u32 = u32::MAX;
+
+/// Distances from the nearest of some cells, in moves, see `DistanceField::new`.
+pub struct DistanceField {
+    params: GameParams,
+    distances: Vec<u32>,
+}
+
+impl DistanceField {
+    /// A BFS from all the `sources` at once through the cells of the map `passable` holds
+    /// for. The sources themselves are always at zero, the cells out of the map are
+    /// skipped.
+    pub fn new(
+        params: &GameParams,
+        sources: impl IntoIterator<Item = Cell>,
+        passable: impl Fn(Cell) -> bool,
+    ) -> Self {
+        let mut field = Self {
+            params: *params,
+            distances: vec![UNREACHABLE; (params.x_cells_count * params.y_cells_count) as usize],
+        };
+        let mut queue = VecDeque::new();
+        for source in sources {
+            if let Some(index) = field.index(source) {
+                field.distances[index] = 0;
+                queue.push_back(source);
+            }
+        }
+
+        while let Some(cell) = queue.pop_front() {
+            let distance = field.get(cell) + 1;
+            for next in params.iter_neighbours(cell) {
+                let index = field.index(next).unwrap();
+                if field.distances[index] == UNREACHABLE && passable(next) {
+                    field.distances[index] = distance;
+                    queue.push_back(next);
+                }
+            }
+        }
+        field
+    }
+
+    /// `UNREACHABLE` for cells out of the map too.
+    pub fn get(&self, cell: Cell) -> u32 {
+        self.index(cell)
+            .map_or(UNREACHABLE, |index| self.distances[index])
+    }
+
+    /// In the order of `GameParams::iter_cells`.
+    fn index(&self, cell: Cell) -> Option<usize> {
+        self.params
+            .contains(cell)
+            .then(|| (cell.0 * self.params.y_cells_count as i32 + cell.1) as usize)
+    }
+}
+
+/// From my head, around my own trace, which I can't cross.
+pub fn my_distances(world: &World, params: &GameParams) -> DistanceField {
+    let me = world.me();
+    let lines = me.lines.iter().copied().collect::<HashSet<_>>();
+    DistanceField::new(params, [me.position], |cell| !lines.contains(&cell))
+}
+
+/// From the nearest head of the enemies still in the game. Enemies hidden by the fog are
+/// assumed to be far away, as in `Strategy`.
+pub fn enemy_distances(world: &World, params: &GameParams) -> DistanceField {
+    let heads = world
+        .iter_enemies()
+        .filter(|(_, enemy)| !enemy.has_lost && !enemy.position_hidden)
+        .map(|(_, enemy)| enemy.position);
+    DistanceField::new(params, heads, |_| true)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A way from my head into my territory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReturnPath {
+    /// Empty if I'm at home already.
+    pub directions: Vec<Direction>,
+    /// How many moves the enemies need to reach my trace, including the cells of the
+    /// path, minus the length of the path. The path is safe if it's positive, and it's
+    /// huge without enemies in sight.
+    pub margin: i64,
+}
+
+impl ReturnPath {
+    pub fn is_safe(&self) -> bool {
+        self.margin > 0
+    }
+}
+
+/// The shortest of the safe ways home, see `ReturnPath::margin`.
+pub fn safe_return_path(world: &World, params: &GameParams) -> Option<Vec<Direction>> {
+    best_return_path(world, params)
+        .filter(ReturnPath::is_safe)
+        .map(|path| path.directions)
+}
+
+/// The way home with the largest margin, even if it's not safe, and the shortest of
+/// those. The first move is never the reverse of `Player::direction`, and the path never
+/// crosses my trace. `None` if my trace cuts me off the territory.
+pub fn best_return_path(world: &World, params: &GameParams) -> Option<ReturnPath> {
+    let me = world.me();
+    let enemies = enemy_distances(world, params);
+    let trace_distance = me
+        .lines
+        .iter()
+        .chain([&me.position])
+        .map(|&cell| enemies.get(cell))
+        .min()
+        .unwrap_or(UNREACHABLE);
+    let territory = me.territory.iter().copied().collect::<HashSet<_>>();
+    if territory.contains(&me.position) {
+        return Some(ReturnPath {
+            directions: vec![],
+            margin: trace_distance as i64,
+        });
+    }
+
+    // Every distance an enemy may have to the cells of the path, from the safest.
+    let mut thresholds = params
+        .iter_cells()
+        .map(|cell| enemies.get(cell).min(trace_distance))
+        .collect::<Vec<_>>();
+    thresholds.sort_unstable_by(|a, b| b.cmp(a));
+    thresholds.dedup();
+
+    let mut best = None::<ReturnPath>;
+    for threshold in thresholds {
+        let Some(directions) = shortest_path_home(world, params, &territory, |cell| {
+            enemies.get(cell) >= threshold
+        }) else {
+            continue;
+        };
+        let margin = threshold as i64 - directions.len() as i64;
+        let better = best
+            .as_ref()
+            .is_none_or(|best| (margin, best.directions.len()) > (best.margin, directions.len()));
+        if better {
+            best = Some(ReturnPath { directions, margin });
+        }
+    }
+    best
+}
+
+/// A BFS from my head through the cells `passable` holds for, up to my territory.
+fn shortest_path_home(
+    world: &World,
+    params: &GameParams,
+    territory: &HashSet<Cell>,
+    passable: impl Fn(Cell) -> bool,
+) -> Option<Vec<Direction>> {
+    let me = world.me();
+    let lines = me.lines.iter().copied().collect::<HashSet<_>>();
+    let forbidden_first = me.direction.map(Direction::opposite);
+
+    let mut came_from = HashMap::<Cell, (Cell, Direction)>::new();
+    let mut queue = VecDeque::from([me.position]);
+    while let Some(cell) = queue.pop_front() {
+        for direction in [
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ] {
+            if cell == me.position && Some(direction) == forbidden_first {
+                continue;
+            }
+            let Some(next) = params.adjacent(cell, direction) else {
+                continue;
+            };
+            if next == me.position || lines.contains(&next) || came_from.contains_key(&next) {
+                continue;
+            }
+            let home = territory.contains(&next);
+            if !home && !passable(next) {
+                continue;
+            }
+            came_from.insert(next, (cell, direction));
+            if home {
+                return Some(unwind(&came_from, me.position, next));
+            }
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+fn unwind(
+    came_from: &HashMap<Cell, (Cell, Direction)>,
+    start: Cell,
+    mut cell: Cell,
+) -> Vec<Direction> {
+    let mut directions = vec![];
+    while cell != start {
+        let (previous, direction) = came_from[&cell];
+        directions.push(direction);
+        cell = previous;
+    }
+    directions.reverse();
+    directions
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::Player;
+
+    const PARAMS: GameParams = GameParams {
+        x_cells_count: 31,
+        y_cells_count: 31,
+    };
+
+    fn square(center: Cell) -> Vec<Cell> {
+        let Cell(x, y) = center;
+        (x - 1..=x + 1)
+            .flat_map(|x| (y - 1..=y + 1).map(move |y| Cell(x, y)))
+            .collect()
+    }
+
+    fn player(position: Cell) -> Player {
+        Player {
+            score: 0,
+            territory: square(position),
+            position,
+            lines: vec![],
+            direction: Some(Direction::Left),
+            has_lost: false,
+            position_hidden: false,
+        }
+    }
+
+    /// I've left my territory around (15, 15) upwards, and am at (15, 20) now.
+    fn world(enemies: impl IntoIterator<Item = Player>) -> World {
+        let me = Player {
+            territory: square(Cell(15, 15)),
+            position: Cell(15, 20),
+            lines: (17..=20).map(|y| Cell(15, y)).collect(),
+            direction: Some(Direction::Up),
+            ..player(Cell(15, 15))
+        };
+        let mut players = HashMap::from([("i".to_string(), me)]);
+        for (i, enemy) in enemies.into_iter().enumerate() {
+            players.insert((i + 2).to_string(), enemy);
+        }
+        World {
+            players,
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    #[test]
+    fn my_distances_go_around_the_trace() {
+        let distances = my_distances(&world([]), &PARAMS);
+        assert_eq!(distances.get(Cell(15, 20)), 0);
+        assert_eq!(distances.get(Cell(16, 19)), 2);
+        assert_eq!(distances.get(Cell(15, 16)), 6);
+        assert_eq!(distances.get(Cell(15, 19)), UNREACHABLE);
+        assert_eq!(distances.get(Cell(-1, 0)), UNREACHABLE);
+    }
+
+    #[test]
+    fn enemy_distances_from_the_nearest() {
+        let hidden = Player {
+            position: Cell::HIDDEN,
+            position_hidden: true,
+            ..player(Cell(15, 10))
+        };
+        let lost = Player {
+            has_lost: true,
+            ..player(Cell(15, 14))
+        };
+        let alone = enemy_distances(&world([]), &PARAMS);
+        assert_eq!(alone.get(Cell(15, 15)), UNREACHABLE);
+
+        let world = world([player(Cell(0, 0)), player(Cell(30, 30)), hidden, lost]);
+        let distances = enemy_distances(&world, &PARAMS);
+        assert_eq!(distances.get(Cell(0, 0)), 0);
+        assert_eq!(distances.get(Cell(1, 0)), 1);
+        assert_eq!(distances.get(Cell(15, 14)), 29);
+        assert_eq!(distances.get(Cell(29, 28)), 3);
+    }
+
+    #[test]
+    fn safe_way_home() {
+        // Either side of the trace, the reverse is not allowed.
+        let path = safe_return_path(&world([player(Cell(28, 2))]), &PARAMS).unwrap();
+        assert_eq!(path.len(), 5);
+        assert!([Direction::Left, Direction::Right].contains(&path[0]));
+
+        let path = best_return_path(&world([]), &PARAMS).unwrap();
+        assert_eq!(path.directions.len(), 5);
+        assert!(path.is_safe());
+    }
+
+    #[test]
+    fn lesser_evil() {
+        // The enemy is 3 moves away from the trace, so that no way home is safe, but it
+        // cuts the way on the left sooner.
+        let world = world([player(Cell(12, 19))]);
+        assert_eq!(safe_return_path(&world, &PARAMS), None);
+
+        use Direction::*;
+        let path = best_return_path(&world, &PARAMS).unwrap();
+        assert_eq!(path.directions, [Right, Down, Down, Down, Down]);
+        assert_eq!(path.margin, -2);
+    }
+
+    #[test]
+    fn at_home_or_cut_off() {
+        let mut world = world([player(Cell(28, 2))]);
+        let me = world.players.get_mut("i").unwrap();
+        me.position = Cell(15, 15);
+        me.lines.clear();
+        let path = best_return_path(&world, &PARAMS).unwrap();
+        assert!(path.directions.is_empty());
+        assert_eq!(path.margin, 13 + 13);
+
+        // In the corner, between the map edges and the trace.
+        let me = world.players.get_mut("i").unwrap();
+        me.position = Cell(0, 30);
+        me.lines = vec![Cell(0, 29), Cell(1, 29), Cell(1, 30), Cell(0, 30)];
+        me.direction = Some(Direction::Left);
+        assert_eq!(best_return_path(&world, &PARAMS), None);
+    }
+}
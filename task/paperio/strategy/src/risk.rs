@@ -0,0 +1,359 @@
+use paperio_proto::World;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Where the player stands among the players that haven't lost yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Standing {
+    /// Starts with 1 for the leader. Players with equal scores share the better rank.
+    pub rank: usize,
+    pub player_count: usize,
+    /// The score minus the best enemy score: minus the leader's one when behind, and minus
+    /// the second best one when leading. Zero without enemies.
+    pub gap: i64,
+}
+
+impl Standing {
+    pub fn of(world: &World) -> Self {
+        let my_score = world.me().score as i64;
+        let enemy_scores = world
+            .iter_enemies()
+            .filter(|(_, enemy)| !enemy.has_lost)
+            .map(|(_, enemy)| enemy.score as i64)
+            .collect::<Vec<_>>();
+
+        Self {
+            rank: 1 + enemy_scores.iter().filter(|&&s| s > my_score).count(),
+            player_count: 1 + enemy_scores.len(),
+            gap: enemy_scores
+                .iter()
+                .max()
+                .map_or(0, |&best_score| my_score - best_score),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Parameters of `risk_factor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RiskConfig {
+    /// The expected length of the game, the server's default one. Players aren't told it.
+    pub tick_count: u32,
+    /// The score gap that has the full effect on its own.
+    pub gap_scale: f64,
+    /// The effect of being the last compared to being behind by `gap_scale`.
+    pub rank_weight: f64,
+    /// The factor on the last tick when far behind, in `(0, 1]`.
+    pub min_factor: f64,
+    /// The factor on the last tick when far ahead, at least 1.
+    pub max_factor: f64,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            tick_count: 300,
+            gap_scale: 50.,
+            rank_weight: 0.5,
+            min_factor: 0.25,
+            max_factor: 4.,
+        }
+    }
+}
+
+impl RiskConfig {
+    /// The fraction of the game remaining, from 1 at the start down to 0.
+    pub fn time_left(&self, tick_num: u32) -> f64 {
+        if self.tick_count == 0 {
+            return 0.;
+        }
+        (1. - tick_num as f64 / self.tick_count as f64).clamp(0., 1.)
+    }
+}
+
+/// How much to scale the aversion to danger by.
+///
+/// The standing is first turned into a lead in `[-1, 1]`: the gap over `gap_scale`, minus
+/// `rank_weight` times the share of the players ahead. The factor is then
+/// `max_factor ^ (lead * elapsed)` for a non-negative lead and
+/// `min_factor ^ (-lead * elapsed)` for a negative one, where `elapsed` is
+/// `1 - time_left`. So it's 1 at the start of the game and for a tie for the lead, never
+/// decreases with the gap, never increases with the rank, and moves away from 1 as the
+/// game goes on: up when leading and down when behind.
+pub fn risk_factor(standing: Standing, time_left: f64, config: &RiskConfig) -> f64 {
+    let behind = match standing.player_count {
+        0 | 1 => 0.,
+        count => (standing.rank.clamp(1, count) - 1) as f64 / (count - 1) as f64,
+    };
+    let lead =
+        (standing.gap as f64 / config.gap_scale - config.rank_weight * behind).clamp(-1., 1.);
+    let elapsed = 1. - time_left.clamp(0., 1.);
+
+    let bound = if lead >= 0. {
+        config.max_factor
+    } else {
+        config.min_factor
+    };
+    bound
+        .powf(lead.abs() * elapsed)
+        .clamp(config.min_factor, config.max_factor)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::{Cell, Player};
+
+    use std::collections::HashMap;
+
+    const TIMES: [f64; 6] = [1., 0.8, 0.5, 0.2, 0.05, 0.];
+
+    /// All the standings possible with scores up to 120: the leader's gap is
+    /// non-negative, and everyone else's is negative.
+    fn standings(player_count: usize) -> Vec<Standing> {
+        let mut standings = vec![];
+        for rank in 1..=player_count {
+            let gaps = match (rank, player_count) {
+                (_, 1) => 0..=0,
+                (1, _) => 0..=120,
+                _ => -120..=-1,
+            };
+            for gap in gaps {
+                standings.push(Standing {
+                    rank,
+                    player_count,
+                    gap,
+                });
+            }
+        }
+        standings
+    }
+
+    fn factor(rank: usize, player_count: usize, gap: i64, time_left: f64) -> f64 {
+        let standing = Standing {
+            rank,
+            player_count,
+            gap,
+        };
+        risk_factor(standing, time_left, &RiskConfig::default())
+    }
+
+    fn player(score: u32, has_lost: bool) -> Player {
+        Player {
+            score,
+            territory: vec![],
+            position: Cell(0, 0),
+            lines: vec![],
+            direction: None,
+            has_lost,
+            position_hidden: false,
+        }
+    }
+
+    fn world(me: u32, enemies: &[(u32, bool)]) -> World {
+        let mut players = HashMap::from([("i".to_string(), player(me, false))]);
+        for (i, &(score, has_lost)) in enemies.iter().enumerate() {
+            players.insert((i + 2).to_string(), player(score, has_lost));
+        }
+        World {
+            players,
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    #[test]
+    fn standing_of_world() {
+        let standing = |me, enemies| Standing::of(&world(me, enemies));
+
+        assert_eq!(
+            standing(10, &[(3, false), (7, false)]),
+            Standing {
+                rank: 1,
+                player_count: 3,
+                gap: 3
+            }
+        );
+        assert_eq!(
+            standing(5, &[(3, false), (7, false), (9, false)]),
+            Standing {
+                rank: 3,
+                player_count: 4,
+                gap: -4
+            }
+        );
+        // A tie for the lead.
+        assert_eq!(
+            standing(7, &[(7, false), (2, false)]),
+            Standing {
+                rank: 1,
+                player_count: 3,
+                gap: 0
+            }
+        );
+        // A tie behind the leader.
+        assert_eq!(
+            standing(4, &[(4, false), (6, false)]),
+            Standing {
+                rank: 2,
+                player_count: 3,
+                gap: -2
+            }
+        );
+        // Players that have lost don't count.
+        assert_eq!(
+            standing(4, &[(40, true), (2, false)]),
+            Standing {
+                rank: 1,
+                player_count: 2,
+                gap: 2
+            }
+        );
+        assert_eq!(
+            standing(4, &[(40, true)]),
+            Standing {
+                rank: 1,
+                player_count: 1,
+                gap: 0
+            }
+        );
+    }
+
+    #[test]
+    fn time_left() {
+        let config = RiskConfig::default();
+        assert_eq!(config.time_left(0), 1.);
+        assert_eq!(config.time_left(150), 0.5);
+        assert_eq!(config.time_left(300), 0.);
+        assert_eq!(config.time_left(1000), 0.);
+
+        let config = RiskConfig {
+            tick_count: 0,
+            ..config
+        };
+        assert_eq!(config.time_left(0), 0.);
+    }
+
+    #[test]
+    fn neutral_at_start_and_for_ties() {
+        for player_count in 1..=4 {
+            for standing in standings(player_count) {
+                assert_eq!(
+                    risk_factor(standing, 1., &RiskConfig::default()),
+                    1.,
+                    "{standing:?}"
+                );
+            }
+        }
+        for player_count in 1..=4 {
+            for time_left in TIMES {
+                assert_eq!(factor(1, player_count, 0, time_left), 1.);
+            }
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let config = RiskConfig::default();
+        for player_count in 1..=4 {
+            for standing in standings(player_count) {
+                for time_left in TIMES {
+                    let factor = risk_factor(standing, time_left, &config);
+                    assert!(
+                        (config.min_factor..=config.max_factor).contains(&factor),
+                        "{standing:?}, {time_left}: {factor}"
+                    );
+                }
+            }
+        }
+
+        assert_eq!(factor(4, 4, -1000, 0.), config.min_factor);
+        assert_eq!(factor(2, 2, -1000, 0.), config.min_factor);
+        assert_eq!(factor(1, 4, 1000, 0.), config.max_factor);
+        assert_eq!(factor(1, 2, 1000, 0.), config.max_factor);
+        // Out of range arguments.
+        assert_eq!(factor(7, 4, -1000, -1.), config.min_factor);
+        assert_eq!(factor(1, 4, 1000, 2.), 1.);
+    }
+
+    #[test]
+    fn monotone_in_gap() {
+        for player_count in 1..=4 {
+            for rank in 1..=player_count {
+                for time_left in TIMES {
+                    let factors = (-120..=120)
+                        .map(|gap| factor(rank, player_count, gap, time_left))
+                        .collect::<Vec<_>>();
+                    assert!(
+                        factors.windows(2).all(|w| w[0] <= w[1]),
+                        "rank {rank} of {player_count}, {time_left}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn monotone_in_rank() {
+        for player_count in 2..=4 {
+            for gap in -120..=-1 {
+                for time_left in TIMES {
+                    let factors = (2..=player_count)
+                        .map(|rank| factor(rank, player_count, gap, time_left))
+                        .collect::<Vec<_>>();
+                    assert!(
+                        factors.windows(2).all(|w| w[0] >= w[1]),
+                        "gap {gap} of {player_count}, {time_left}"
+                    );
+                    assert!(factor(1, player_count, 0, time_left) >= factors[0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn monotone_in_time() {
+        for player_count in 1..=4 {
+            for standing in standings(player_count) {
+                let factors = TIMES
+                    .iter()
+                    .map(|&time_left| risk_factor(standing, time_left, &RiskConfig::default()))
+                    .collect::<Vec<_>>();
+                let (leading, behind) = (standing.gap > 0, standing.gap < 0);
+                assert!(
+                    factors.windows(2).all(|w| match (leading, behind) {
+                        (true, _) => w[0] <= w[1],
+                        (_, true) => w[0] >= w[1],
+                        _ => w[0] == w[1],
+                    }),
+                    "{standing:?}: {factors:?}"
+                );
+                if leading {
+                    assert!(factors[5] > 1., "{standing:?}");
+                }
+                if behind {
+                    assert!(factors[5] < 1., "{standing:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn two_players() {
+        // Behind by a single point, the rank still makes a difference.
+        assert!(factor(2, 2, -1, 0.) < factor(2, 4, -1, 0.));
+        assert!(factor(2, 2, -1, 0.) < 1.);
+        assert!(factor(1, 2, 1, 0.) > 1.);
+
+        // Being behind weighs more than being ahead as much, as the rank adds up.
+        let ahead = factor(1, 2, 10, 0.1);
+        let behind = factor(2, 2, -10, 0.1);
+        assert!(ahead > 1. && ahead < 2.);
+        assert!(behind < 1. / ahead);
+    }
+}
@@ -1,12 +1,108 @@
-use paperio_proto::{Cell, Direction, World};
-use std::cmp::{max, min};
+use crate::{
+    heartbeat::Heartbeat,
+    pathfinding::best_return_path,
+    risk::{risk_factor, RiskConfig, Standing},
+};
+
+use paperio_proto::{Cell, Direction, GameParams, World};
+use std::{
+    cmp::{max, min},
+    ops::Deref,
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
-pub struct Strategy {
+/// Directions a player may take from some cell: at most three, as it can't reverse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LegalDirections {
+    directions: [Direction; 3],
+    len: usize,
+}
+
+impl Deref for LegalDirections {
+    type Target = [Direction];
+
+    fn deref(&self) -> &[Direction] {
+        &self.directions[..self.len]
+    }
+}
+
+/// The only way to pick the next direction, so that it is never the reverse of the
+/// previous one and never leads out of the map: the server ignores a reverse and the
+/// player keeps going.
+pub struct LegalMove {
     previous_direction: Direction,
+}
+
+impl LegalMove {
+    pub fn new(previous_direction: Direction) -> Self {
+        Self { previous_direction }
+    }
+
+    pub fn previous_direction(&self) -> Direction {
+        self.previous_direction
+    }
+
+    pub fn is_legal(&self, position: Cell, params: &GameParams, direction: Direction) -> bool {
+        direction != self.previous_direction.opposite()
+            && params.contains(position.adjacent_unchecked(direction))
+    }
+
+    /// In the order: straight, clockwise, counterclockwise.
+    pub fn legal_directions(&self, position: Cell, params: &GameParams) -> LegalDirections {
+        let mut legal = LegalDirections {
+            directions: [self.previous_direction; 3],
+            len: 0,
+        };
+        let candidates = [
+            self.previous_direction,
+            self.previous_direction.next(true),
+            self.previous_direction.next(false),
+        ];
+        for direction in candidates {
+            if self.is_legal(position, params, direction) {
+                legal.directions[legal.len] = direction;
+                legal.len += 1;
+            }
+        }
+        legal
+    }
+
+    /// Returns the first legal direction of `preferred` and `fallback_order`, or of
+    /// `legal_directions` if there is none. It becomes the previous direction.
+    pub fn choose(
+        &mut self,
+        position: Cell,
+        params: &GameParams,
+        preferred: Direction,
+        fallback_order: &[Direction],
+    ) -> Direction {
+        let direction = [preferred]
+            .iter()
+            .chain(fallback_order)
+            .copied()
+            .find(|&direction| self.is_legal(position, params, direction))
+            .or_else(|| self.legal_directions(position, params).first().copied())
+            // Only possible on a map of a single cell.
+            .unwrap_or(self.previous_direction);
+        self.previous_direction = direction;
+        direction
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Out of the territory, heads home as soon as the enemies could cut the trace this
+/// many moves after getting there, see `ReturnPath::margin`.
+const RETURN_MARGIN: i64 = 2;
+
+pub struct Strategy {
+    params: GameParams,
+    legal_move: LegalMove,
     best_rectangle: Option<Rectangle>,
     continuous_useless_ticks: i32,
+    risk: RiskConfig,
+    heartbeat: Heartbeat,
 }
 
 impl Default for Strategy {
@@ -17,17 +113,42 @@ impl Default for Strategy {
 
 impl Strategy {
     pub fn new() -> Self {
+        Self::with_params(GameParams::default())
+    }
+
+    pub fn with_params(params: GameParams) -> Self {
         Self {
-            previous_direction: Direction::Left,
+            params,
+            legal_move: LegalMove::new(Direction::Left),
             best_rectangle: None,
             continuous_useless_ticks: 0,
+            risk: RiskConfig::default(),
+            heartbeat: Heartbeat::default(),
         }
     }
 
+    pub fn with_risk_config(mut self, risk: RiskConfig) -> Self {
+        self.risk = risk;
+        self
+    }
+
+    /// Marks the start of every tick on stderr, see `Heartbeat`.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
     pub fn on_tick(&mut self, world: World) -> Direction {
+        self.heartbeat.beat(world.tick_num);
         let me = world.me();
+        let previous_direction = self.legal_move.previous_direction();
 
-        let mut next_direction: Direction;
+        let standing = Standing::of(&world);
+        let risk_factor = risk_factor(standing, self.risk.time_left(world.tick_num), &self.risk);
+        log::debug!(
+            "tick {}: {standing:?}, risk factor {risk_factor:.3}",
+            world.tick_num
+        );
 
         let contains = me.territory.contains(&me.position);
         if contains {
@@ -36,6 +157,26 @@ impl Strategy {
             self.continuous_useless_ticks = 0
         }
 
+        // Gives up the rectangle when it gets dangerous. If no way home is safe any more,
+        // the one the enemies need the longest to cut is taken.
+        if !contains {
+            if let Some(path) = best_return_path(&world, &self.params) {
+                if path.margin <= RETURN_MARGIN {
+                    log::debug!(
+                        "tick {}: heading home, margin {}",
+                        world.tick_num,
+                        path.margin
+                    );
+                    return self.legal_move.choose(
+                        me.position,
+                        &self.params,
+                        path.directions[0],
+                        &[],
+                    );
+                }
+            }
+        }
+
         let new_best_rectangle = match &self.best_rectangle {
             Some(best_rectangle) => {
                 contains
@@ -46,9 +187,10 @@ impl Strategy {
         };
 
         if new_best_rectangle {
-            let best_cell = world
+            let best_cell = self
+                .params
                 .iter_cells()
-                .map(|cell| (cell, Self::get_score(&world, &cell)))
+                .map(|cell| (cell, self.get_score(&world, &cell, risk_factor)))
                 .max_by_key(|x| x.1)
                 .map(|x| x.0)
                 .unwrap_or(me.position);
@@ -56,63 +198,56 @@ impl Strategy {
             self.best_rectangle = Some(Rectangle::new(&me.position, &best_cell));
 
             let (dx, dy) = (best_cell.0 - me.position.0, best_cell.1 - me.position.1);
-            next_direction = Self::determine_direction(dx, dy, self.previous_direction);
-        } else {
-            match &self.best_rectangle {
-                Some(best_rectangle) => {
-                    let adjacent = me.position.adjacent(self.previous_direction);
-                    if let Some(adj) = adjacent {
-                        if best_rectangle.is_on_perimeter(&adj) {
-                            return self.previous_direction;
-                        }
-                    }
-
-                    next_direction = self.previous_direction.next(true);
-
-                    let adjacent = me.position.adjacent(next_direction);
-                    if let Some(adj) = adjacent {
-                        if best_rectangle.is_on_perimeter(&adj) {
-                            self.previous_direction = next_direction;
-                            return next_direction;
-                        }
-                    }
+            return self.determine_direction(me.position, dx, dy);
+        }
 
-                    next_direction = next_direction.opposite();
-                }
-                None => {
-                    next_direction = self.previous_direction.next(true);
-                }
+        match &self.best_rectangle {
+            // Follow the perimeter of the rectangle, clockwise if possible.
+            Some(best_rectangle) => {
+                let is_on_perimeter = |direction| {
+                    self.params
+                        .adjacent(me.position, direction)
+                        .is_some_and(|adj| best_rectangle.is_on_perimeter(&adj))
+                };
+                let preferences = [previous_direction, previous_direction.next(true)]
+                    .into_iter()
+                    .filter(|&direction| is_on_perimeter(direction))
+                    .chain([previous_direction.next(false)])
+                    .collect::<Vec<_>>();
+                self.legal_move
+                    .choose(me.position, &self.params, preferences[0], &preferences[1..])
             }
+            None => self.legal_move.choose(
+                me.position,
+                &self.params,
+                previous_direction.next(true),
+                &[],
+            ),
         }
-
-        self.previous_direction = next_direction;
-        next_direction
     }
 
-    fn determine_direction(dx: i32, dy: i32, previous_direction: Direction) -> Direction {
-        if dx < 0 && previous_direction != Direction::Right {
-            return Direction::Left;
-        }
-
-        if dy < 0 && previous_direction != Direction::Up {
-            return Direction::Down;
-        }
-
-        if dy > 0 && previous_direction != Direction::Down {
-            return Direction::Up;
-        }
-
-        if dx > 0 && previous_direction != Direction::Left {
-            return Direction::Right;
-        }
-
-        previous_direction
+    /// Heads towards the cell at `(dx, dy)`, trying left, down, up and right in this
+    /// order. Keeps the previous direction if the cell is reached.
+    fn determine_direction(&mut self, position: Cell, dx: i32, dy: i32) -> Direction {
+        let preferences = [
+            (dx < 0, Direction::Left),
+            (dy < 0, Direction::Down),
+            (dy > 0, Direction::Up),
+            (dx > 0, Direction::Right),
+            (true, self.legal_move.previous_direction()),
+        ]
+        .into_iter()
+        .filter_map(|(wanted, direction)| wanted.then_some(direction))
+        .collect::<Vec<_>>();
+        self.legal_move
+            .choose(position, &self.params, preferences[0], &preferences[1..])
     }
 
-    fn get_score(world: &World, cell: &Cell) -> i32 {
+    /// The danger is scaled by `risk_factor`, see `risk::risk_factor`.
+    fn get_score(&self, world: &World, cell: &Cell, risk_factor: f64) -> i32 {
         let rectangle = Rectangle::new(&world.me().position, cell);
 
-        let cells_score = Self::get_cells_score(world, &rectangle);
+        let cells_score = self.get_cells_score(world, &rectangle);
         let danger = Self::get_danger_punishment(world, &rectangle);
         let elimination_bonus = Self::get_elimination_bonus(world, &rectangle);
         let save_punishment = if rectangle.is_inside(&world.me().territory) {
@@ -122,13 +257,15 @@ impl Strategy {
         };
 
         let bonus = 3 * cells_score + elimination_bonus;
-        let punishment = i32::pow(danger, 2) + save_punishment;
+        let danger_punishment = (risk_factor * i32::pow(danger, 2) as f64).round() as i32;
+        let punishment = danger_punishment + save_punishment;
 
         bonus - punishment
     }
 
-    fn get_cells_score(world: &World, rectange: &Rectangle) -> i32 {
-        let enemy_area = world
+    fn get_cells_score(&self, world: &World, rectange: &Rectangle) -> i32 {
+        let enemy_area = self
+            .params
             .iter_cells()
             .filter(|cell| rectange.has_inside(cell))
             .fold(0, |acc, cell| {
@@ -149,11 +286,13 @@ impl Strategy {
     }
 
     fn get_danger_punishment(world: &World, rectange: &Rectangle) -> i32 {
+        // Enemies hidden by the fog are assumed to be far away.
         let min_enemy_distance = world
             .iter_enemies()
+            .filter(|enemy| !enemy.1.position_hidden)
             .map(|enemy| rectange.get_distance(&enemy.1.position))
             .min()
-            .unwrap();
+            .unwrap_or_else(|| rectange.get_perimeter());
 
         rectange.get_perimeter() - min_enemy_distance
     }
@@ -269,3 +408,269 @@ impl Rectangle {
         true
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::{Player, MAP_SIZE_CELLS};
+
+    use std::collections::HashMap;
+
+    const PARAMS: GameParams = GameParams {
+        x_cells_count: MAP_SIZE_CELLS as u32,
+        y_cells_count: MAP_SIZE_CELLS as u32,
+    };
+
+    fn square(center: Cell) -> Vec<Cell> {
+        let Cell(x, y) = center;
+        (x - 1..=x + 1)
+            .flat_map(|x| (y - 1..=y + 1).map(move |y| Cell(x, y)))
+            .collect()
+    }
+
+    fn make_world(position: Cell, territory: Vec<Cell>, lines: Vec<Cell>) -> World {
+        let me = Player {
+            score: 0,
+            territory,
+            position,
+            lines,
+            direction: None,
+            has_lost: false,
+            position_hidden: false,
+        };
+        let enemy = Player {
+            score: 3,
+            territory: square(Cell(21, 9)),
+            position: Cell(21, 9),
+            lines: vec![],
+            direction: Some(Direction::Left),
+            has_lost: false,
+            position_hidden: false,
+        };
+        World {
+            players: HashMap::from([("i".to_string(), me), ("2".to_string(), enemy)]),
+            tick_num: 1,
+            bonuses: vec![],
+            warmup: false,
+        }
+    }
+
+    fn without_enemies(mut world: World) -> World {
+        world.players.retain(|id, _| id == "i");
+        world
+    }
+
+    #[test]
+    fn legal_directions() {
+        use Direction::*;
+
+        let legal_move = LegalMove::new(Left);
+        assert_eq!(
+            *legal_move.legal_directions(Cell(5, 5), &PARAMS),
+            [Left, Up, Down]
+        );
+        assert_eq!(
+            *legal_move.legal_directions(Cell(0, 5), &PARAMS),
+            [Up, Down]
+        );
+        assert_eq!(*legal_move.legal_directions(Cell(0, 0), &PARAMS), [Up]);
+        assert_eq!(
+            *legal_move.legal_directions(Cell(5, 30), &PARAMS),
+            [Left, Down]
+        );
+
+        let legal_move = LegalMove::new(Up);
+        assert_eq!(*legal_move.legal_directions(Cell(30, 30), &PARAMS), [Left]);
+
+        let small = GameParams {
+            x_cells_count: 6,
+            y_cells_count: 31,
+        };
+        assert_eq!(*legal_move.legal_directions(Cell(5, 5), &small), [Up, Left]);
+    }
+
+    #[test]
+    fn choose_records_direction() {
+        use Direction::*;
+
+        let mut legal_move = LegalMove::new(Left);
+        assert_eq!(legal_move.choose(Cell(5, 5), &PARAMS, Right, &[Down]), Down);
+        assert_eq!(legal_move.previous_direction(), Down);
+        assert_eq!(legal_move.choose(Cell(5, 5), &PARAMS, Right, &[]), Right);
+        assert_eq!(
+            legal_move.choose(Cell(30, 5), &PARAMS, Right, &[Left]),
+            Down
+        );
+        assert_eq!(legal_move.choose(Cell(30, 0), &PARAMS, Down, &[]), Left);
+        assert_eq!(legal_move.previous_direction(), Left);
+    }
+
+    #[test]
+    fn determine_direction_preferences() {
+        use Direction::*;
+
+        let mut strategy = Strategy::new();
+        assert_eq!(strategy.determine_direction(Cell(5, 5), -1, -1), Left);
+        assert_eq!(strategy.determine_direction(Cell(5, 5), 1, -1), Down);
+        assert_eq!(strategy.determine_direction(Cell(5, 5), 1, 1), Right);
+        // Left is the reverse now.
+        assert_eq!(strategy.determine_direction(Cell(5, 5), -1, 1), Up);
+        assert_eq!(strategy.determine_direction(Cell(5, 5), 0, 0), Up);
+        // Straight is out of the map, clockwise is the first legal direction.
+        assert_eq!(strategy.determine_direction(Cell(5, 30), 0, 0), Right);
+    }
+
+    #[test]
+    fn perimeter_following() {
+        use Direction::*;
+
+        // Outside of the territory, so that the rectangle is kept, and without enemies, so
+        // that the way home is safe.
+        let territory = square(Cell(20, 20));
+        let mut strategy = Strategy::new();
+        strategy.best_rectangle = Some(Rectangle::new(&Cell(3, 3), &Cell(8, 8)));
+
+        let world = without_enemies(make_world(Cell(5, 3), territory.clone(), vec![Cell(5, 3)]));
+        assert_eq!(strategy.on_tick(world), Left);
+
+        let world = without_enemies(make_world(Cell(3, 3), territory.clone(), vec![Cell(3, 3)]));
+        assert_eq!(strategy.on_tick(world), Up);
+
+        let world = without_enemies(make_world(Cell(3, 8), territory.clone(), vec![Cell(3, 8)]));
+        assert_eq!(strategy.on_tick(world), Right);
+
+        // Neither straight nor clockwise is on the perimeter.
+        let world = without_enemies(make_world(Cell(10, 10), territory, vec![Cell(10, 10)]));
+        assert_eq!(strategy.on_tick(world), Up);
+    }
+
+    #[test]
+    fn perimeter_fallback_stays_in_bounds() {
+        use Direction::*;
+
+        // Used to turn counterclockwise, that is down and out of the map.
+        let mut strategy = Strategy::new();
+        strategy.best_rectangle = Some(Rectangle::new(&Cell(3, 3), &Cell(6, 6)));
+        let world = make_world(Cell(0, 0), square(Cell(5, 5)), vec![Cell(0, 0)]);
+        assert_eq!(strategy.on_tick(world), Up);
+        assert_eq!(strategy.legal_move.previous_direction(), Up);
+    }
+
+    #[test]
+    fn never_reverses_nor_leaves_the_map() {
+        let start = Cell(9, 21);
+        let territory = square(start);
+        let mut strategy = Strategy::new();
+        let (mut position, mut direction) = (start, Direction::Left);
+        let mut lines = vec![];
+
+        for tick in 0..60 {
+            let world = make_world(position, territory.clone(), lines.clone());
+            let next_direction = strategy.on_tick(world);
+            assert_ne!(next_direction, direction.opposite(), "tick {tick}");
+
+            position = position.adjacent_unchecked(next_direction);
+            assert!(PARAMS.contains(position), "tick {tick}");
+            direction = next_direction;
+
+            if territory.contains(&position) {
+                lines.clear();
+            } else {
+                lines.push(position);
+            }
+        }
+    }
+
+    #[test]
+    fn gambles_when_trailing_late() {
+        let choose = |tick_num, my_score, enemy_score| {
+            let position = Cell(15, 15);
+            let mut world = make_world(position, square(position), vec![]);
+            world.tick_num = tick_num;
+            world.players.get_mut("i").unwrap().score = my_score;
+            world.players.get_mut("2").unwrap().score = enemy_score;
+
+            let mut strategy = Strategy::new();
+            strategy.on_tick(world);
+            strategy.best_rectangle.unwrap()
+        };
+
+        let early = choose(1, 0, 3);
+        // The same board late in the game and far behind.
+        let trailing = choose(280, 0, 200);
+        assert!(trailing.get_area() > 2 * early.get_area());
+
+        let leading = choose(280, 200, 0);
+        assert!(leading.get_area() <= early.get_area());
+    }
+
+    #[test]
+    fn heads_home_when_threatened() {
+        use Direction::*;
+
+        // Up from the territory around (15, 15), with an enemy 3 moves away from the trace
+        // on the left: no way home is safe, the one on the right is the longest to cut.
+        let territory = square(Cell(15, 15));
+        let lines = (17..=20).map(|y| Cell(15, y)).collect::<Vec<_>>();
+        let mut world = make_world(Cell(15, 20), territory.clone(), lines.clone());
+        let enemy = world.players.get_mut("2").unwrap();
+        enemy.position = Cell(12, 19);
+        enemy.territory = square(Cell(11, 19));
+
+        let mut strategy = Strategy::new();
+        strategy.legal_move = LegalMove::new(Up);
+        assert_eq!(strategy.on_tick(world), Right);
+
+        // The same trace far from the enemy.
+        let world = make_world(Cell(15, 20), territory, lines);
+        let mut strategy = Strategy::new();
+        strategy.legal_move = LegalMove::new(Up);
+        strategy.best_rectangle = Some(Rectangle::new(&Cell(15, 16), &Cell(18, 22)));
+        assert_eq!(strategy.on_tick(world), Up);
+    }
+
+    #[test]
+    fn hidden_enemies() {
+        use paperio_proto::{traits::JsonRead, Message};
+
+        // Messages are lines, the tick is only spread out to be readable.
+        let tick = r#"{
+            "type": "tick",
+            "params": {
+                "players": {
+                    "i": {
+                        "score": 0,
+                        "territory": [[9, 21], [9, 22], [10, 21], [10, 22]],
+                        "position": [9, 21],
+                        "lines": [],
+                        "direction": "left",
+                        "has_lost": false
+                    },
+                    "2": {
+                        "score": 7,
+                        "territory": [[12, 21]],
+                        "position": [-1, -1],
+                        "lines": [],
+                        "direction": null,
+                        "has_lost": false,
+                        "position_hidden": true
+                    }
+                },
+                "tick_num": 12
+            }
+        }"#
+        .replace('\n', "");
+        let Ok(Message::Tick(world)) = tick.as_bytes().read_message() else {
+            panic!("expected a tick");
+        };
+        assert!(world.players["2"].position_hidden);
+        assert!(!world.me().position_hidden);
+
+        let mut strategy = Strategy::new();
+        let direction = strategy.on_tick(world);
+        assert!(PARAMS.contains(Cell(9, 21).adjacent_unchecked(direction)));
+    }
+}
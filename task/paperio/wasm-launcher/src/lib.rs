@@ -1,22 +1,45 @@
-use anyhow::Result;
+mod preopen;
+mod tick_budget;
+
+pub use tick_budget::{TickBudget, TickStats};
+
+use anyhow::{bail, Result};
 
 use std::{
     any::Any,
-    io::{Read, Write},
+    io::{self, BufReader, Read, Write},
     net::TcpStream,
     path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::UNIX_EPOCH,
 };
 
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
 
+use cap_rand::{rngs::StdRng, SeedableRng};
+use cap_std::time::{Duration, Instant, SystemTime};
+use paperio_proto::{heartbeat, traits::Format};
 use wasi_common::{
+    clocks::{WasiClocks, WasiMonotonicClock, WasiSystemClock},
     file::WasiFile,
     pipe::{ReadPipe, WritePipe},
+    snapshots::preview_1::wasi_snapshot_preview1,
+    table::Table,
     WasiCtx,
 };
-use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime::{
+    AsContextMut, Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits,
+    StoreLimitsBuilder, Trap,
+};
 use wasmtime_wasi::WasiCtxBuilder;
+use wiggle::wasmtime::WasmtimeGuestMemory;
+
+use preopen::Preopen;
+use tick_budget::{TickProxy, TickWatch, MAX_FUEL_BEFORE_TICKS};
 
 pub trait IntoWasiFile {
     fn into_wasi_file(self) -> impl WasiFile + 'static;
@@ -52,8 +75,192 @@ impl<R: Read + Any + Send + Sync> IntoWasiFile for ReadPipe<R> {
 pub struct RunStatus {
     pub fuel_consumed: u64,
     pub result: Result<()>,
+    pub deterministic: bool,
+    /// Whether the heartbeat fields are set, see `WasmStrategyRunner::heartbeat`.
+    pub heartbeat: bool,
+    /// The tick the strategy has started last.
+    pub last_heartbeat_tick: Option<u32>,
+    pub ticks_started: u32,
+    /// How long ago the strategy has started the last tick, as of the end of the run.
+    pub since_last_heartbeat: Option<std::time::Duration>,
+    /// Set with `WasmStrategyRunner::tick_budget`.
+    pub tick_stats: Option<TickStats>,
+}
+
+impl RunStatus {
+    /// `None` if the strategy has finished, or failed in a way `Failure` doesn't tell.
+    pub fn failure(&self) -> Option<Failure> {
+        self.result.as_ref().err().and_then(Failure::of)
+    }
+
+    /// Where the strategy has stopped according to its heartbeats, to tell a stuck
+    /// strategy from one which hasn't got to the game. `None` without heartbeats.
+    pub fn heartbeat_report(&self) -> Option<String> {
+        if !self.heartbeat {
+            return None;
+        }
+        let report = match (self.last_heartbeat_tick, self.since_last_heartbeat) {
+            (Some(tick_num), Some(elapsed)) => format!(
+                "hung after starting tick {tick_num} {elapsed:.1?} ago, {} tick(s) started",
+                self.ticks_started
+            ),
+            _ => "never started a tick, probably a handshake problem".to_string(),
+        };
+        Some(report)
+    }
 }
 
+/// Why a run has failed, for the exit code of the launcher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Any trap but the ones below, e.g. a panic of the strategy.
+    Trap,
+    /// Out of `cpu_fuel_limit`, or of the fuel of a tick.
+    OutOfFuel,
+    /// Stopped with `Interrupter`, or out of the time of a tick.
+    Interrupted,
+    /// The launcher has failed to talk to the server or to open a file.
+    Io,
+}
+
+impl Failure {
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        let failure = match err.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => Self::OutOfFuel,
+            Some(Trap::Interrupt) => Self::Interrupted,
+            Some(_) => Self::Trap,
+            None if err.chain().any(|cause| cause.is::<io::Error>()) => Self::Io,
+            None => return None,
+        };
+        Some(failure)
+    }
+
+    /// 1 is left for the other errors and 2 for the usage ones, as clap has it.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Trap => 3,
+            Self::OutOfFuel => 4,
+            Self::Interrupted => 5,
+            Self::Io => 6,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What the heartbeat markers have told so far.
+#[derive(Clone, Copy, Debug, Default)]
+struct HeartbeatLog {
+    last_tick: Option<u32>,
+    ticks_started: u32,
+    last_at: Option<std::time::Instant>,
+}
+
+/// Passes the stderr of the guest to `inner` as is, picking the heartbeat markers out
+/// of its lines, see `paperio_proto::heartbeat`.
+struct HeartbeatScanner<W> {
+    inner: W,
+    /// The unfinished line.
+    line: Vec<u8>,
+    log: Arc<Mutex<HeartbeatLog>>,
+}
+
+impl<W> HeartbeatScanner<W> {
+    /// Lines longer than this aren't scanned, so that a strategy printing without
+    /// newlines doesn't eat memory. Markers are much shorter.
+    const MAX_LINE: usize = 4096;
+
+    fn scan(&mut self, buf: &[u8]) {
+        for chunk in buf.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if self.line.len() > Self::MAX_LINE {
+                // Only the end of a line may be a marker.
+                self.line.drain(..self.line.len() - Self::MAX_LINE);
+            }
+            if !chunk.ends_with(b"\n") {
+                continue;
+            }
+            if let Some(tick_num) = heartbeat::parse_marker(&String::from_utf8_lossy(&self.line)) {
+                let mut log = self.log.lock().unwrap();
+                log.last_tick = Some(tick_num);
+                log.ticks_started += 1;
+                log.last_at = Some(std::time::Instant::now());
+            }
+            self.line.clear();
+        }
+    }
+}
+
+impl<W: Write> Write for HeartbeatScanner<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.scan(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The wall clock value seen by the guest in the deterministic mode.
+pub const DETERMINISTIC_EPOCH: std::time::Duration = std::time::Duration::from_secs(1_700_000_000);
+
+/// How much the monotonic clock advances per call in the deterministic mode.
+pub const DETERMINISTIC_CLOCK_STEP: std::time::Duration = std::time::Duration::from_millis(1);
+
+struct PinnedSystemClock;
+
+impl WasiSystemClock for PinnedSystemClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self, _precision: Duration) -> SystemTime {
+        SystemTime::from_std(UNIX_EPOCH + DETERMINISTIC_EPOCH)
+    }
+}
+
+/// WASI reports monotonic time relative to the first reading, so the real base
+/// instant does not leak to the guest.
+struct SteppingMonotonicClock {
+    base: Instant,
+    calls: AtomicU32,
+}
+
+impl WasiMonotonicClock for SteppingMonotonicClock {
+    fn resolution(&self) -> Duration {
+        DETERMINISTIC_CLOCK_STEP
+    }
+
+    fn now(&self, _precision: Duration) -> Instant {
+        let calls = self.calls.fetch_add(1, Ordering::Relaxed);
+        self.base + DETERMINISTIC_CLOCK_STEP * calls
+    }
+}
+
+// The sync `WasiCtxBuilder` always uses the real clocks and an entropy-seeded rng, so
+// the context is assembled by hand. `random_get` reads from the context's rng.
+fn deterministic_wasi_ctx(seed: u64) -> WasiCtx {
+    let clocks = WasiClocks::new()
+        .with_system(PinnedSystemClock)
+        .with_monotonic(SteppingMonotonicClock {
+            base: Instant::from_std(std::time::Instant::now()),
+            calls: AtomicU32::new(0),
+        });
+
+    WasiCtx::new(
+        Box::new(StdRng::seed_from_u64(seed)),
+        clocks,
+        wasmtime_wasi::sched_ctx(),
+        Table::new(),
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 pub struct WasmStrategyRunner {
     engine: Engine,
     path: PathBuf,
@@ -62,6 +269,12 @@ pub struct WasmStrategyRunner {
     stderr: Option<Box<dyn WasiFile>>,
     cpu_fuel_limit: u64,
     memory_size_limit: usize,
+    deterministic_seed: Option<u64>,
+    env: Vec<(String, String)>,
+    preopens: Vec<Preopen>,
+    heartbeat_log: Option<Arc<Mutex<HeartbeatLog>>>,
+    tick_proxy: Option<TickProxy>,
+    interrupted: Arc<AtomicBool>,
 }
 
 impl WasmStrategyRunner {
@@ -78,6 +291,12 @@ impl WasmStrategyRunner {
             stderr: None,
             cpu_fuel_limit: u64::MAX,
             memory_size_limit: usize::MAX,
+            deterministic_seed: None,
+            env: vec![],
+            preopens: vec![],
+            heartbeat_log: None,
+            tick_proxy: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -106,12 +325,102 @@ impl WasmStrategyRunner {
         self
     }
 
-    pub fn make_iterrupter(&self) -> Interrupter {
+    /// Replaces the clocks and the random source of the guest with deterministic
+    /// ones, so that runs with the same input and `seed` produce the same output.
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.deterministic_seed = Some(seed);
+        self
+    }
+
+    /// Sets an environment variable of the guest, e.g. `Format::ENV_VAR`.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn envs<K: Into<String>, V: Into<String>>(
+        mut self,
+        vars: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.env.extend(
+            vars.into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Lets the guest read the files under `host_path` as `guest_path`, e.g. a config
+    /// or weights. The guest can't change anything there, so that strategies sharing a
+    /// directory in a tournament don't affect each other, see `preopen_dir_read_write`.
+    /// Directories are preopened in order, from fd 3.
+    pub fn preopen_dir(self, host_path: impl Into<PathBuf>, guest_path: impl Into<String>) -> Self {
+        self.preopen(host_path.into(), guest_path.into(), false)
+    }
+
+    /// Like `preopen_dir`, but the guest may also create, change and remove files.
+    pub fn preopen_dir_read_write(
+        self,
+        host_path: impl Into<PathBuf>,
+        guest_path: impl Into<String>,
+    ) -> Self {
+        self.preopen(host_path.into(), guest_path.into(), true)
+    }
+
+    fn preopen(mut self, host_path: PathBuf, guest_path: String, read_write: bool) -> Self {
+        self.preopens.push(Preopen {
+            host_path,
+            guest_path,
+            read_write,
+        });
+        self
+    }
+
+    /// Asks the strategy to mark the start of every tick on stderr, see
+    /// `paperio_proto::heartbeat`, and reports the last one in `RunStatus`. Replaces
+    /// `stderr`: the output of the guest goes to `stderr` here, markers included.
+    pub fn heartbeat(mut self, stderr: impl Write + Any + Send + Sync) -> Self {
+        let log = Arc::new(Mutex::new(HeartbeatLog::default()));
+        let scanner = HeartbeatScanner {
+            inner: stderr,
+            line: vec![],
+            log: log.clone(),
+        };
+        self.heartbeat_log = Some(log);
+        self.env(heartbeat::ENV_VAR, "1")
+            .stderr(WritePipe::new(scanner))
+    }
+
+    /// Limits every tick instead of the whole run, see `TickBudget`. Replaces `stdin`:
+    /// the messages of the server in `format` are read from `input` by the launcher,
+    /// which passes them on to the strategy. `cpu_fuel_limit` only applies up to the
+    /// first tick then, and `RunStatus::tick_stats` is set.
+    pub fn tick_budget(
+        mut self,
+        input: impl Read + Send + Sync + 'static,
+        format: Format,
+        budget: TickBudget,
+    ) -> Self {
+        self.tick_proxy = Some(TickProxy {
+            input: Box::new(BufReader::new(input)),
+            format,
+            budget,
+        });
+        self
+    }
+
+    /// Stops the strategy from another thread, e.g. once it's out of time.
+    pub fn make_interrupter(&self) -> Interrupter {
         Interrupter {
             engine: self.engine.clone(),
+            interrupted: self.interrupted.clone(),
         }
     }
 
+    #[deprecated(note = "renamed to `make_interrupter`")]
+    pub fn make_iterrupter(&self) -> Interrupter {
+        self.make_interrupter()
+    }
+
     pub fn run(self) -> Result<RunStatus> {
         struct AppState {
             wasi_ctx: WasiCtx,
@@ -121,17 +430,30 @@ impl WasmStrategyRunner {
         let mut linker = Linker::new(&self.engine);
         wasmtime_wasi::add_to_linker(&mut linker, |s: &mut AppState| &mut s.wasi_ctx)?;
 
-        let mut wasi_ctx_builder = WasiCtxBuilder::new();
+        let mut wasi_ctx = match self.deterministic_seed {
+            Some(seed) => deterministic_wasi_ctx(seed),
+            None => WasiCtxBuilder::new().build(),
+        };
+        for (key, value) in &self.env {
+            wasi_ctx.push_env(key, value)?;
+        }
+        for preopen in &self.preopens {
+            wasi_ctx.push_preopened_dir(preopen.open()?, &preopen.guest_path)?;
+        }
         if let Some(stdin) = self.stdin {
-            wasi_ctx_builder = wasi_ctx_builder.stdin(stdin);
+            wasi_ctx.set_stdin(stdin);
         }
         if let Some(stdout) = self.stdout {
-            wasi_ctx_builder = wasi_ctx_builder.stdout(stdout);
+            wasi_ctx.set_stdout(stdout);
         }
         if let Some(stderr) = self.stderr {
-            wasi_ctx_builder = wasi_ctx_builder.stderr(stderr);
+            wasi_ctx.set_stderr(stderr);
         }
-        let wasi_ctx = wasi_ctx_builder.build();
+        let tick_watch = self.tick_proxy.map(|proxy| {
+            let (watch, reader) = TickWatch::start(proxy, &self.engine);
+            wasi_ctx.set_stdin(Box::new(ReadPipe::new(reader)));
+            watch
+        });
 
         let store_limits = StoreLimitsBuilder::new()
             .memory_size(self.memory_size_limit)
@@ -145,9 +467,48 @@ impl WasmStrategyRunner {
                 store_limits,
             },
         );
-        store.add_fuel(self.cpu_fuel_limit)?;
+        let fuel_limit = match tick_watch {
+            Some(_) => self.cpu_fuel_limit.min(MAX_FUEL_BEFORE_TICKS),
+            None => self.cpu_fuel_limit,
+        };
+        store.add_fuel(fuel_limit)?;
         store.limiter(|s| &mut s.store_limits);
         store.set_epoch_deadline(1);
+        if let Some(watch) = &tick_watch {
+            // Replaces `fd_read`, which starts the ticks, to set their fuel as it returns.
+            // The read itself is the one of `wasi_common`: calling the `Func` linked by
+            // `wasmtime_wasi` from here would lose the memory of the strategy.
+            let meter = watch.meter(self.interrupted.clone());
+            linker.allow_shadowing(true).func_wrap(
+                "wasi_snapshot_preview1",
+                "fd_read",
+                move |mut caller: Caller<'_, AppState>,
+                      fd: i32,
+                      iovs: i32,
+                      iovs_len: i32,
+                      nread: i32|
+                      -> Result<i32> {
+                    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                        bail!("missing required memory export");
+                    };
+                    let (memory, state) = memory.data_and_store_mut(&mut caller);
+                    let memory = WasmtimeGuestMemory::new(memory);
+                    let errno = wiggle::run_in_dummy_executor(wasi_snapshot_preview1::fd_read(
+                        &mut state.wasi_ctx,
+                        &memory,
+                        fd,
+                        iovs,
+                        iovs_len,
+                        nread,
+                    ))??;
+                    meter.on_read(caller.as_context_mut())?;
+                    Ok(errno)
+                },
+            )?;
+
+            let meter = watch.meter(self.interrupted.clone());
+            store.epoch_deadline_callback(move |_| meter.on_epoch());
+        }
 
         let module = Module::from_file(&self.engine, self.path)
             .map_err(|e| e.context("failed to load wasm file"))?;
@@ -158,19 +519,482 @@ impl WasmStrategyRunner {
             .typed::<(), ()>(&store)?
             .call(&mut store, ());
 
+        let mut fuel_consumed = store.fuel_consumed().unwrap();
+        let (result, tick_stats) = match tick_watch {
+            Some(watch) => {
+                fuel_consumed = watch.fuel_consumed(fuel_consumed);
+                let (result, stats) = watch.finish(fuel_consumed, result);
+                (result, Some(stats))
+            }
+            None => (result, None),
+        };
+        let heartbeats = self
+            .heartbeat_log
+            .as_ref()
+            .map(|log| *log.lock().unwrap())
+            .unwrap_or_default();
         Ok(RunStatus {
-            fuel_consumed: store.fuel_consumed().unwrap(),
+            fuel_consumed,
             result,
+            deterministic: self.deterministic_seed.is_some(),
+            heartbeat: self.heartbeat_log.is_some(),
+            last_heartbeat_tick: heartbeats.last_tick,
+            ticks_started: heartbeats.ticks_started,
+            since_last_heartbeat: heartbeats.last_at.map(|at| at.elapsed()),
+            tick_stats,
         })
     }
 }
 
 pub struct Interrupter {
     engine: Engine,
+    interrupted: Arc<AtomicBool>,
 }
 
 impl Interrupter {
     pub fn interrupt(self) {
+        self.interrupted.store(true, Ordering::Relaxed);
         self.engine.increment_epoch();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paperio_proto::{Message, World};
+
+    use std::{collections::HashMap, fs, io::Cursor, path::Path};
+
+    // Prints the wall clock, two monotonic clock readings and two 16-byte random chunks.
+    const CLOCKS_AND_RANDOM_WAT: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "clock_time_get"
+                (func $clock_time_get (param i32 i64 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "random_get"
+                (func $random_get (param i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "fd_write"
+                (func $fd_write (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (func (export "_start")
+                (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0)))
+                (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 8)))
+                (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 16)))
+                (drop (call $random_get (i32.const 24) (i32.const 16)))
+                (drop (call $random_get (i32.const 40) (i32.const 16)))
+                (i32.store (i32.const 64) (i32.const 0))
+                (i32.store (i32.const 68) (i32.const 56))
+                (drop (call $fd_write (i32.const 1) (i32.const 64) (i32.const 1) (i32.const 72)))))
+    "#;
+
+    fn run_module(name: &str, deterministic_seed: Option<u64>) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "paperio-wasm-launcher-{}-{name}.wat",
+            std::process::id()
+        ));
+        fs::write(&path, CLOCKS_AND_RANDOM_WAT).unwrap();
+
+        let stdout = WritePipe::new_in_memory();
+        let mut runner = WasmStrategyRunner::new(&path).stdout(stdout.clone());
+        if let Some(seed) = deterministic_seed {
+            runner = runner.deterministic(seed);
+        }
+        let status = runner.run().unwrap();
+        fs::remove_file(&path).unwrap();
+        status.result.unwrap();
+        assert_eq!(status.deterministic, deterministic_seed.is_some());
+
+        stdout
+            .try_into_inner()
+            .map(Cursor::into_inner)
+            .unwrap_or_else(|_| panic!("stdout is still in use"))
+    }
+
+    fn read_u64(output: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(output[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn deterministic_runs_are_identical() {
+        let first = run_module("deterministic", Some(42));
+        let second = run_module("deterministic", Some(42));
+        assert_eq!(first.len(), 56);
+        assert_eq!(first, second);
+
+        assert_eq!(read_u64(&first, 0), DETERMINISTIC_EPOCH.as_nanos() as u64);
+        assert_eq!(
+            read_u64(&first, 16) - read_u64(&first, 8),
+            DETERMINISTIC_CLOCK_STEP.as_nanos() as u64
+        );
+        assert_ne!(first[24..40], first[40..56]);
+
+        assert_ne!(run_module("deterministic", Some(43))[24..], first[24..]);
+    }
+
+    fn scan(chunks: &[&[u8]]) -> (HeartbeatLog, Vec<u8>) {
+        let log = Arc::new(Mutex::new(HeartbeatLog::default()));
+        let mut scanner = HeartbeatScanner {
+            inner: vec![],
+            line: vec![],
+            log: log.clone(),
+        };
+        for chunk in chunks {
+            scanner.write_all(chunk).unwrap();
+        }
+        let log = *log.lock().unwrap();
+        (log, scanner.inner)
+    }
+
+    fn marker(tick_num: u32) -> Vec<u8> {
+        let mut marker = vec![];
+        heartbeat::write_marker(&mut marker, tick_num).unwrap();
+        marker
+    }
+
+    #[test]
+    fn heartbeats_in_noisy_stderr() {
+        let noisy = [
+            b"starting up\n".to_vec(),
+            marker(1),
+            b"tick 1: thinking".to_vec(),
+            b" hard\n".to_vec(),
+            marker(2),
+            // A marker after an unfinished line.
+            b"tick 2: ".to_vec(),
+            marker(3),
+            b"fake: paperio-heartbeat 4\n".to_vec(),
+            format!("{}5x\n", heartbeat::MARKER).into_bytes(),
+            vec![0xff, 0xfe, b'\n'],
+            marker(6)[..10].to_vec(),
+            marker(6)[10..].to_vec(),
+            b"stuck reading stdin".to_vec(),
+        ];
+        let expected = noisy.concat();
+
+        // Whole, byte by byte and in the chunks as written.
+        let chunks = noisy.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let bytes = expected.chunks(1).collect::<Vec<_>>();
+        for chunks in [vec![expected.as_slice()], bytes, chunks] {
+            let (log, output) = scan(&chunks);
+            assert_eq!(output, expected);
+            assert_eq!(log.last_tick, Some(6));
+            assert_eq!(log.ticks_started, 4);
+            assert!(log.last_at.is_some());
+        }
+
+        let (log, _) = scan(&[b"no markers\n".as_slice(), b"at all".as_slice()]);
+        assert_eq!(log.last_tick, None);
+        assert_eq!(log.ticks_started, 0);
+
+        // A long line doesn't hide the marker at its end.
+        let long = [vec![b'.'; 10_000], marker(7)].concat();
+        assert_eq!(scan(&[long.as_slice()]).0.last_tick, Some(7));
+    }
+
+    #[test]
+    fn heartbeat_reports() {
+        let status = |heartbeat, last_heartbeat_tick: Option<u32>| RunStatus {
+            fuel_consumed: 0,
+            result: Ok(()),
+            deterministic: false,
+            heartbeat,
+            last_heartbeat_tick,
+            ticks_started: last_heartbeat_tick.unwrap_or(0),
+            since_last_heartbeat: last_heartbeat_tick.map(|_| std::time::Duration::from_secs(2)),
+            tick_stats: None,
+        };
+        assert_eq!(status(false, None).heartbeat_report(), None);
+        assert_eq!(
+            status(true, None).heartbeat_report().unwrap(),
+            "never started a tick, probably a handshake problem"
+        );
+        assert_eq!(
+            status(true, Some(57)).heartbeat_report().unwrap(),
+            "hung after starting tick 57 2.0s ago, 57 tick(s) started"
+        );
+    }
+
+    /// Writes `stderr` and then spins until interrupted, like a strategy stuck on a tick.
+    fn stalling_wat(stderr: &[u8]) -> String {
+        let data = stderr
+            .iter()
+            .map(|b| format!("\\{b:02x}"))
+            .collect::<String>();
+        format!(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 64) "{data}")
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 64))
+                    (i32.store (i32.const 4) (i32.const {len}))
+                    (drop (call $fd_write (i32.const 2) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (loop $spin (br $spin))))
+            "#,
+            len = stderr.len(),
+        )
+    }
+
+    #[test]
+    fn stalled_strategy_reports_last_heartbeat() {
+        let stderr = [
+            b"connected\n".to_vec(),
+            marker(1),
+            b"moving left\n".to_vec(),
+            marker(2),
+            b"waiting for the world".to_vec(),
+        ]
+        .concat();
+        let path = std::env::temp_dir().join(format!(
+            "paperio-wasm-launcher-{}-stalling.wat",
+            std::process::id()
+        ));
+        fs::write(&path, stalling_wat(&stderr)).unwrap();
+
+        let runner = WasmStrategyRunner::new(&path).heartbeat(io::sink());
+        let interrupter = runner.make_interrupter();
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            interrupter.interrupt();
+        });
+        let status = runner.run().unwrap();
+        stopper.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(status.failure(), Some(Failure::Interrupted));
+        assert!(status.heartbeat);
+        assert_eq!(status.last_heartbeat_tick, Some(2));
+        assert_eq!(status.ticks_started, 2);
+        assert!(status
+            .heartbeat_report()
+            .unwrap()
+            .starts_with("hung after starting tick 2 "));
+    }
+
+    /// Reads stdin message by message, and spins for a bit on every message but the
+    /// `slow_message`-th, which takes `slow` iterations.
+    fn spinning_wat(slow_message: u32, slow: u64) -> String {
+        format!(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func $spin (param $n i64)
+                    (loop $spin
+                        (if (i64.ne (local.get $n) (i64.const 0))
+                            (then
+                                (local.set $n (i64.sub (local.get $n) (i64.const 1)))
+                                (br $spin)))))
+                (func (export "_start") (local $messages i32)
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const 65000))
+                    (loop $read
+                        (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                        (if (i32.eqz (i32.load (i32.const 8))) (then (return)))
+                        (local.set $messages (i32.add (local.get $messages) (i32.const 1)))
+                        (call $spin
+                            (select
+                                (i64.const {slow})
+                                (i64.const 1000)
+                                (i32.eq (local.get $messages) (i32.const {slow_message}))))
+                        (br $read))))
+            "#,
+            slow = slow as i64,
+        )
+    }
+
+    /// A game of 5 ticks, in JSON.
+    fn game_messages() -> Vec<u8> {
+        let tick = |tick_num| {
+            Message::Tick(World {
+                players: HashMap::new(),
+                tick_num,
+                bonuses: vec![],
+                warmup: false,
+            })
+        };
+        let mut messages = vec![Message::StartGame(Default::default())];
+        messages.extend((1..=5).map(tick));
+        messages.push(Message::EndGame {});
+
+        let mut buffer = vec![];
+        for message in &messages {
+            Format::Json.encode_message(message, &mut buffer).unwrap();
+        }
+        buffer
+    }
+
+    fn run_ticks(name: &str, wat: String, budget: TickBudget) -> RunStatus {
+        let path = std::env::temp_dir().join(format!(
+            "paperio-wasm-launcher-{}-{name}.wat",
+            std::process::id()
+        ));
+        fs::write(&path, wat).unwrap();
+        let status = WasmStrategyRunner::new(&path)
+            .tick_budget(Cursor::new(game_messages()), Format::Json, budget)
+            .run()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        status
+    }
+
+    #[test]
+    fn slow_tick_runs_out_of_fuel() {
+        // Message 4 is tick 3, which takes about 70M fuel, and the rest take about 7K.
+        let wat = spinning_wat(4, 10_000_000);
+        let status = run_ticks("metered", wat.clone(), TickBudget::default());
+        status.result.unwrap();
+        let stats = status.tick_stats.unwrap();
+        assert_eq!(stats.ticks, 5);
+        assert_eq!(stats.max_fuel_tick, Some(3));
+        assert!(stats.max_fuel > 10_000_000);
+        assert_eq!(stats.ticks_over_budget, 0);
+
+        // Every tick fits into the fuel left from the others, but can't use it.
+        let budget = TickBudget {
+            fuel: Some(1_000_000),
+            time: None,
+        };
+        let status = run_ticks("fuel", wat, budget);
+        assert_eq!(status.failure(), Some(Failure::OutOfFuel));
+        let err = status.result.unwrap_err();
+        assert_eq!(err.to_string(), "tick 3 has taken more than 1000000 fuel");
+        let stats = status.tick_stats.unwrap();
+        assert_eq!(stats.ticks, 3);
+        assert_eq!(stats.ticks_over_budget, 1);
+        assert!(status.fuel_consumed < 4_000_000);
+    }
+
+    #[test]
+    fn stuck_tick_runs_out_of_time() {
+        let budget = TickBudget {
+            fuel: None,
+            time: Some(std::time::Duration::from_millis(200)),
+        };
+        let started = std::time::Instant::now();
+        let status = run_ticks("time", spinning_wat(4, u64::MAX), budget);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+        assert_eq!(status.failure(), Some(Failure::Interrupted));
+        let err = status.result.unwrap_err();
+        assert_eq!(err.to_string(), "tick 3 has taken longer than 200ms");
+        let stats = status.tick_stats.unwrap();
+        assert_eq!(stats.ticks, 3);
+        assert_eq!(stats.ticks_over_budget, 1);
+    }
+
+    const FD_READ: u64 = 1 << 1;
+    const FD_WRITE: u64 = 1 << 6;
+
+    /// Opens `config.txt` in the first preopened directory with `rights` and echoes it to
+    /// stdout, traps if any call fails.
+    fn echo_config_wat(rights: u64) -> String {
+        format!(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "path_open"
+                    (func $path_open
+                        (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 64) "config.txt")
+                (func $check (param $errno i32)
+                    (if (local.get $errno) (then unreachable)))
+                (func (export "_start")
+                    (call $check (call $path_open
+                        (i32.const 3) (i32.const 0) (i32.const 64) (i32.const 10) (i32.const 0)
+                        (i64.const {rights}) (i64.const 0) (i32.const 0) (i32.const 0)))
+                    (i32.store (i32.const 16) (i32.const 1024))
+                    (i32.store (i32.const 20) (i32.const 4096))
+                    (call $check (call $fd_read
+                        (i32.load (i32.const 0)) (i32.const 16) (i32.const 1) (i32.const 24)))
+                    (i32.store (i32.const 20) (i32.load (i32.const 24)))
+                    (call $check (call $fd_write
+                        (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))))
+            "#
+        )
+    }
+
+    const CONFIG: &str = "weights: 0.5 0.25\n";
+
+    fn echo_config(
+        name: &str,
+        rights: u64,
+        preopen: impl FnOnce(WasmStrategyRunner, &Path) -> WasmStrategyRunner,
+    ) -> (RunStatus, Vec<u8>) {
+        let dir = std::env::temp_dir().join(format!(
+            "paperio-wasm-launcher-{}-{name}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.txt"), CONFIG).unwrap();
+        let path = dir.with_extension("wat");
+        fs::write(&path, echo_config_wat(rights)).unwrap();
+
+        let stdout = WritePipe::new_in_memory();
+        let runner = WasmStrategyRunner::new(&path)
+            .stdin(ReadPipe::from(""))
+            .stdout(stdout.clone());
+        let status = preopen(runner, &dir).run().unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let stdout = stdout
+            .try_into_inner()
+            .map(Cursor::into_inner)
+            .unwrap_or_else(|_| panic!("stdout is still in use"));
+        (status, stdout)
+    }
+
+    #[test]
+    fn preopened_file_round_trips() {
+        let (status, stdout) = echo_config("preopen", FD_READ, |runner, dir| {
+            runner.preopen_dir(dir, "/config")
+        });
+        status.result.unwrap();
+        assert_eq!(stdout, CONFIG.as_bytes());
+
+        let (status, stdout) = echo_config("no-preopen", FD_READ, |runner, _| runner);
+        assert_eq!(status.failure(), Some(Failure::Trap));
+        assert!(stdout.is_empty());
+    }
+
+    #[test]
+    fn preopened_dirs_are_read_only() {
+        let rights = FD_READ | FD_WRITE;
+        let (status, _) = echo_config("read-only", rights, |runner, dir| {
+            runner.preopen_dir(dir, "/config")
+        });
+        assert_eq!(status.failure(), Some(Failure::Trap));
+
+        let (status, stdout) = echo_config("read-write", rights, |runner, dir| {
+            runner.preopen_dir_read_write(dir, "/config")
+        });
+        status.result.unwrap();
+        assert_eq!(stdout, CONFIG.as_bytes());
+    }
+
+    #[test]
+    fn failures() {
+        let refused = anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionRefused))
+            .context("failed to connect");
+        assert_eq!(Failure::of(&refused), Some(Failure::Io));
+        let panicked = anyhow::Error::new(Trap::UnreachableCodeReached).context("strategy failed");
+        assert_eq!(Failure::of(&panicked), Some(Failure::Trap));
+        assert_eq!(Failure::of(&anyhow::anyhow!("not a wasm module")), None);
+    }
+
+    #[test]
+    fn regular_runs_differ() {
+        let first = run_module("regular", None);
+        let second = run_module("regular", None);
+        assert_eq!(first.len(), 56);
+        assert_ne!(first[24..], second[24..]);
+    }
+}
@@ -1,8 +1,11 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use paperio_wasm_launcher::WasmStrategyRunner;
+use paperio_proto::traits::Format;
+use paperio_wasm_launcher::{Failure, TickBudget, WasmStrategyRunner};
 
-use std::net::TcpStream;
+use std::{fs::File, io, net::TcpStream, process::ExitCode, thread, time::Duration};
+
+use wasi_common::pipe::WritePipe;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -12,20 +15,130 @@ struct Arguments {
     address: String,
     #[arg(short, long, default_value_t = 8000)]
     port: u16,
+    /// The format the server uses for this endpoint, passed to the strategy in
+    /// the environment.
+    #[arg(long, default_value_t = Format::Json)]
+    format: Format,
+    /// Ask the strategy to mark the start of every tick on stderr, to tell where it has
+    /// got stuck if it fails.
+    #[arg(long)]
+    heartbeat: bool,
+    /// The fuel the strategy gets for every tick, fuel left from a tick isn't carried
+    /// over. Makes the launcher read the messages of the server itself.
+    #[arg(long)]
+    tick_fuel: Option<u64>,
+    /// How long the strategy may think on a tick before it's stopped. Makes the launcher
+    /// read the messages of the server itself.
+    #[arg(long)]
+    tick_time_ms: Option<u64>,
+    /// The fuel for the whole run, or up to the first tick with `--tick-fuel`.
+    #[arg(long)]
+    fuel_limit: Option<u64>,
+    /// How much memory the strategy may grow to.
+    #[arg(long)]
+    memory_limit_mb: Option<usize>,
+    /// Write the stderr of the strategy into this file instead of dropping it.
+    #[arg(long)]
+    stderr_file: Option<String>,
+    /// How long the whole run may take before the strategy is stopped.
+    #[arg(long)]
+    time_limit_secs: Option<u64>,
+    /// Let the strategy read the files of a host directory under a path of its own,
+    /// e.g. `--preopen weights=/weights`. Can be repeated.
+    #[arg(long, value_name = "HOST=GUEST", value_parser = parse_pair)]
+    preopen: Vec<(String, String)>,
+    /// Like `--preopen`, but the strategy may also change the files.
+    #[arg(long, value_name = "HOST=GUEST", value_parser = parse_pair)]
+    preopen_rw: Vec<(String, String)>,
+    /// An environment variable of the strategy. Can be repeated.
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_pair)]
+    env: Vec<(String, String)>,
 }
 
-pub fn main() -> Result<()> {
-    let args = Arguments::parse();
+fn parse_pair(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(left, right)| (left.to_string(), right.to_string()))
+        .ok_or_else(|| format!("'{s}' has no '='"))
+}
 
+fn run(args: Arguments) -> Result<()> {
     let address = format!("{}:{}", args.address, args.port);
     let stdin = TcpStream::connect(&address).with_context(|| format!("failed to {address}"))?;
     let stdout = stdin.try_clone().context("failed to clone tcp stream")?;
 
-    let status = WasmStrategyRunner::new(args.path)
-        .stdin(stdin)
+    let mut runner = WasmStrategyRunner::new(args.path)
         .stdout(stdout)
-        .run()
-        .context("failed to run strategy")?;
+        .env(Format::ENV_VAR, args.format.to_string())
+        .envs(args.env);
+    for (host_path, guest_path) in args.preopen {
+        runner = runner.preopen_dir(host_path, guest_path);
+    }
+    for (host_path, guest_path) in args.preopen_rw {
+        runner = runner.preopen_dir_read_write(host_path, guest_path);
+    }
+    let budget = TickBudget {
+        fuel: args.tick_fuel,
+        time: args.tick_time_ms.map(Duration::from_millis),
+    };
+    runner = if budget == TickBudget::default() {
+        runner.stdin(stdin)
+    } else {
+        runner.tick_budget(stdin, args.format, budget)
+    };
+    if let Some(limit) = args.fuel_limit {
+        runner = runner.cpu_fuel_limit(limit);
+    }
+    if let Some(limit) = args.memory_limit_mb {
+        runner = runner.memory_size_limit(limit * 1024 * 1024);
+    }
+
+    let stderr_file = args
+        .stderr_file
+        .map(|path| File::create(&path).with_context(|| format!("failed to create {path}")))
+        .transpose()?;
+    runner = match (stderr_file, args.heartbeat) {
+        (Some(file), true) => runner.heartbeat(file),
+        (None, true) => runner.heartbeat(io::stderr()),
+        (Some(file), false) => runner.stderr(WritePipe::new(file)),
+        (None, false) => runner,
+    };
+
+    if let Some(secs) = args.time_limit_secs {
+        let interrupter = runner.make_interrupter();
+        // Left running after the strategy finishes, the process exits anyway.
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(secs));
+            interrupter.interrupt();
+        });
+    }
+    let status = runner.run().context("failed to run strategy")?;
+
+    if let Some(stats) = status.tick_stats {
+        eprintln!(
+            "{} tick(s), at most {} fuel per tick (tick {}), {} over budget",
+            stats.ticks,
+            stats.max_fuel,
+            stats
+                .max_fuel_tick
+                .map_or("-".to_string(), |tick_num| tick_num.to_string()),
+            stats.ticks_over_budget
+        );
+    }
+
+    match status.heartbeat_report() {
+        Some(report) => status.result.context(format!("strategy failed: {report}")),
+        None => status.result.context("strategy failed"),
+    }
+}
 
-    status.result.context("strategy failed")
+/// The exit code tells why the strategy has failed, see `Failure::exit_code`.
+pub fn main() -> ExitCode {
+    match run(Arguments::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            // The way `main` returning the error would print it.
+            eprintln!("Error: {err:?}");
+            ExitCode::from(Failure::of(&err).map_or(1, Failure::exit_code))
+        }
+    }
 }
@@ -0,0 +1,92 @@
+//! Host directories the guest may see, read-only unless asked otherwise, see
+//! `WasmStrategyRunner::preopen_dir`.
+
+use anyhow::{Context, Result};
+
+use std::{any::Any, path::PathBuf};
+
+use wasi_common::{
+    dir::{OpenResult, ReaddirCursor, ReaddirEntity, WasiDir},
+    file::{FdFlags, Filestat, OFlags},
+    Error, ErrorExt,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub(crate) struct Preopen {
+    pub host_path: PathBuf,
+    pub guest_path: String,
+    pub read_write: bool,
+}
+
+impl Preopen {
+    pub fn open(&self) -> Result<Box<dyn WasiDir>> {
+        let dir = cap_std::fs::Dir::open_ambient_dir(&self.host_path, cap_std::ambient_authority())
+            .with_context(|| format!("failed to preopen {}", self.host_path.display()))?;
+        let dir = wasmtime_wasi::dir::Dir::from_cap_std(dir);
+        if self.read_write {
+            Ok(Box::new(dir))
+        } else {
+            Ok(Box::new(ReadOnlyDir(Box::new(dir))))
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Lets the files be opened for reading only, and the subdirectories as read-only as
+/// well. Whatever changes the directory itself isn't implemented, and fails with the
+/// default error of `WasiDir`.
+struct ReadOnlyDir(Box<dyn WasiDir>);
+
+#[async_trait::async_trait]
+impl WasiDir for ReadOnlyDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        if write || oflags.intersects(OFlags::CREATE | OFlags::TRUNCATE) {
+            return Err(Error::perm());
+        }
+        let opened = self
+            .0
+            .open_file(symlink_follow, path, oflags, read, write, fdflags)
+            .await?;
+        Ok(match opened {
+            OpenResult::Dir(dir) => OpenResult::Dir(Box::new(ReadOnlyDir(dir))),
+            file => file,
+        })
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        self.0.readdir(cursor).await
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        self.0.read_link(path).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.0.get_filestat().await
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.0.get_path_filestat(path, follow_symlinks).await
+    }
+}
@@ -0,0 +1,320 @@
+//! Budgets for every tick instead of the whole run: the launcher reads the messages of
+//! the server on behalf of the strategy to tell where ticks start, see
+//! `WasmStrategyRunner::tick_budget`.
+
+use anyhow::Result;
+
+use std::{
+    io::{self, BufRead, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use paperio_proto::{
+    binary,
+    traits::{Format, JsonRead},
+    Message,
+};
+use wasmtime::{Engine, StoreContextMut, Trap, UpdateDeadline};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The most fuel a run with tick budgets starts with. The store sums up the fuel it has
+/// been given in an `i64` and silently stops adding any once that overflows, which an
+/// unlimited run would do with the first tick, leaving the ticks without their fuel.
+pub(crate) const MAX_FUEL_BEFORE_TICKS: u64 = i64::MAX as u64 / 2;
+
+/// The limits of a tick, from the moment the strategy gets it till it asks for the next
+/// message. Fuel isn't carried over: every tick starts with `fuel`, however much the
+/// previous ones have left.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickBudget {
+    pub fuel: Option<u64>,
+    /// Wall clock time.
+    pub time: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickStats {
+    pub ticks: u32,
+    pub max_fuel: u64,
+    /// The tick which has taken `max_fuel`.
+    pub max_fuel_tick: Option<u32>,
+    pub ticks_over_budget: u32,
+}
+
+pub(crate) struct TickProxy {
+    pub input: Box<dyn BufRead + Send + Sync>,
+    pub format: Format,
+    pub budget: TickBudget,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+struct State {
+    /// The tick the strategy is on and when it has got it, `None` while it waits for a
+    /// message.
+    current: Option<(u32, Instant)>,
+    /// The tick which has started, but whose fuel hasn't been set yet, see
+    /// `TickMeter::on_read`.
+    fuel_pending: Option<u32>,
+    /// The tick being metered and the fuel consumed before it.
+    metered: Option<(u32, u64)>,
+    /// The fuel thrown away to start the first tick with the budget, which
+    /// `Store::fuel_consumed` counts.
+    discarded_fuel: u64,
+    /// The tick which has run out of time.
+    timed_out: Option<u32>,
+    closed: bool,
+    stats: TickStats,
+}
+
+impl State {
+    fn finish_metered(&mut self, fuel_consumed: u64) {
+        let Some((tick_num, start)) = self.metered.take() else {
+            return;
+        };
+        let fuel = fuel_consumed.saturating_sub(start);
+        if self.stats.max_fuel_tick.is_none() || fuel > self.stats.max_fuel {
+            self.stats.max_fuel = fuel;
+            self.stats.max_fuel_tick = Some(tick_num);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Keeps track of the ticks of one run: `reader` is the stdin of the strategy, and
+/// `meter` has to be called after every `fd_read` of the strategy and from the epoch
+/// deadline callback of its store.
+///
+/// The fuel of a tick is set once the read which has started it returns, while no wasm
+/// is running. A watchdog thread bumps the epoch once the tick is out of time, and the
+/// callback traps then.
+pub(crate) struct TickWatch {
+    shared: Arc<Shared>,
+    budget: TickBudget,
+    watchdog: Option<JoinHandle<()>>,
+}
+
+impl TickWatch {
+    pub fn start(proxy: TickProxy, engine: &Engine) -> (Self, TickReader) {
+        let shared = Arc::new(Shared::default());
+        let watchdog = proxy.budget.time.map(|time| {
+            let shared = shared.clone();
+            let engine = engine.clone();
+            thread::spawn(move || watch_deadlines(&shared, &engine, time))
+        });
+        let reader = TickReader {
+            input: proxy.input,
+            format: proxy.format,
+            message: vec![],
+            position: 0,
+            shared: shared.clone(),
+        };
+        let watch = Self {
+            shared,
+            budget: proxy.budget,
+            watchdog,
+        };
+        (watch, reader)
+    }
+
+    /// `interrupted` is set by `Interrupter`, whose epoch bump would be taken for a tick
+    /// otherwise.
+    pub fn meter(&self, interrupted: Arc<AtomicBool>) -> TickMeter {
+        TickMeter {
+            shared: self.shared.clone(),
+            budget: self.budget,
+            interrupted,
+        }
+    }
+
+    /// The fuel consumed by the strategy, of `Store::fuel_consumed`.
+    pub fn fuel_consumed(&self, store_fuel_consumed: u64) -> u64 {
+        store_fuel_consumed.saturating_sub(self.shared.state.lock().unwrap().discarded_fuel)
+    }
+
+    /// Meters the last tick, and tells which tick has run out of its budget, if any.
+    pub fn finish(mut self, fuel_consumed: u64, result: Result<()>) -> (Result<()>, TickStats) {
+        let mut state = self.shared.state.lock().unwrap();
+        let trap = result
+            .as_ref()
+            .err()
+            .and_then(|err| err.downcast_ref::<Trap>());
+        let over_budget = match (trap, state.metered) {
+            (Some(Trap::OutOfFuel), Some((tick_num, _))) => self
+                .budget
+                .fuel
+                .map(|fuel| format!("tick {tick_num} has taken more than {fuel} fuel")),
+            (Some(Trap::Interrupt), _) => state
+                .timed_out
+                .zip(self.budget.time)
+                .map(|(tick_num, time)| format!("tick {tick_num} has taken longer than {time:?}")),
+            _ => None,
+        };
+        let result = match over_budget {
+            Some(message) => {
+                state.stats.ticks_over_budget += 1;
+                result.map_err(|err| err.context(message))
+            }
+            None => result,
+        };
+        state.finish_metered(fuel_consumed);
+        state.closed = true;
+        let stats = state.stats;
+        drop(state);
+
+        self.shared.changed.notify_all();
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.join().unwrap();
+        }
+        (result, stats)
+    }
+}
+
+fn watch_deadlines(shared: &Shared, engine: &Engine, time: Duration) {
+    let mut state = shared.state.lock().unwrap();
+    while !state.closed {
+        let Some((tick_num, started)) = state.current else {
+            state = shared.changed.wait(state).unwrap();
+            continue;
+        };
+        let left = time.saturating_sub(started.elapsed());
+        if !left.is_zero() {
+            state = shared.changed.wait_timeout(state, left).unwrap().0;
+            continue;
+        }
+        if state.timed_out.is_none() {
+            state.timed_out = Some(tick_num);
+            engine.increment_epoch();
+        }
+        state = shared.changed.wait(state).unwrap();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub(crate) struct TickMeter {
+    shared: Arc<Shared>,
+    budget: TickBudget,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl TickMeter {
+    /// Traps if the strategy has been interrupted or is out of the time of a tick.
+    pub fn on_epoch(&self) -> Result<UpdateDeadline> {
+        let state = self.shared.state.lock().unwrap();
+        if self.interrupted.load(Ordering::Relaxed) || state.timed_out.is_some() {
+            return Err(Trap::Interrupt.into());
+        }
+        Ok(UpdateDeadline::Continue(1))
+    }
+
+    /// Sets the fuel of the tick the read has started, if any. Has to be called from the
+    /// host, when wasm isn't running: fuel changed from within the epoch callback isn't
+    /// seen by the code being run.
+    pub fn on_read<T>(&self, mut ctx: StoreContextMut<'_, T>) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        let Some(tick_num) = state.fuel_pending.take() else {
+            return Ok(());
+        };
+
+        let fuel_consumed = ctx
+            .fuel_consumed()
+            .unwrap_or(0)
+            .saturating_sub(state.discarded_fuel);
+        state.finish_metered(fuel_consumed);
+        if let Some(fuel) = self.budget.fuel {
+            // Only the first tick may have more left, from the limit of the whole run.
+            // The fuel thrown away counts as consumed for the store.
+            let remaining = ctx.consume_fuel(0)?;
+            if remaining > fuel {
+                ctx.consume_fuel(remaining - fuel)?;
+                state.discarded_fuel += remaining - fuel;
+            } else {
+                ctx.add_fuel(fuel - remaining)?;
+            }
+        }
+        state.metered = Some((tick_num, fuel_consumed));
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The stdin of the strategy: passes the messages of `input` as they are, but one at a
+/// time, to tell when the strategy is done with a tick and asks for the next message.
+pub(crate) struct TickReader {
+    input: Box<dyn BufRead + Send + Sync>,
+    format: Format,
+    message: Vec<u8>,
+    /// How much of `message` the strategy has read.
+    position: usize,
+    shared: Arc<Shared>,
+}
+
+impl TickReader {
+    /// Leaves `message` empty at the end of the input. Messages which fail to decode are
+    /// passed as well, for the strategy to deal with.
+    fn next_message(&mut self) -> io::Result<()> {
+        self.message.clear();
+        self.position = 0;
+        let message = match self.format {
+            Format::Json => {
+                self.input.read_until(b'\n', &mut self.message)?;
+                (&mut &self.message[..]).read_message().ok()
+            }
+            Format::Binary => {
+                let mut payload = vec![];
+                match binary::read_frame(&mut self.input, &mut payload) {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                    Err(err) => return Err(err),
+                }
+                binary::write_frame(&mut self.message, &payload)?;
+                binary::decode_message(&payload).ok()
+            }
+        };
+
+        let tick_num = match message {
+            Some(Message::Tick(world)) => world.tick_num,
+            Some(Message::TickDelta(delta)) => delta.tick_num,
+            _ => return Ok(()),
+        };
+        let mut state = self.shared.state.lock().unwrap();
+        state.current = Some((tick_num, Instant::now()));
+        state.fuel_pending = Some(tick_num);
+        state.stats.ticks += 1;
+        drop(state);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+impl Read for TickReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.position == self.message.len() {
+            self.shared.state.lock().unwrap().current = None;
+            self.next_message()?;
+        }
+        let len = buf.len().min(self.message.len() - self.position);
+        buf[..len].copy_from_slice(&self.message[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
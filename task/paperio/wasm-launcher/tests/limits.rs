@@ -0,0 +1,68 @@
+use paperio_wasm_launcher::Failure;
+
+use std::{
+    net::TcpListener,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+const SPIN_WAT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/spin.wat");
+const UNREACHABLE_WAT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/unreachable.wat");
+
+/// Runs the launcher against a server which never says anything, and returns its exit
+/// code and how long it has taken.
+fn launch(path: &str, args: &[&str]) -> (Option<i32>, Duration) {
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = server.local_addr().unwrap().port().to_string();
+    let started = Instant::now();
+    let status = Command::new(env!("CARGO_BIN_EXE_paperio-wasm-launcher"))
+        .args([path, "--address", "127.0.0.1", "--port", &port])
+        .args(args)
+        .status()
+        .unwrap();
+    drop(server);
+    (status.code(), started.elapsed())
+}
+
+fn exit_code(failure: Failure) -> Option<i32> {
+    Some(failure.exit_code().into())
+}
+
+#[test]
+fn time_limit() {
+    let (code, elapsed) = launch(SPIN_WAT, &["--time-limit-secs", "1"]);
+    assert_eq!(code, exit_code(Failure::Interrupted));
+    assert!(elapsed >= Duration::from_secs(1));
+    assert!(elapsed < Duration::from_secs(10));
+}
+
+#[test]
+fn fuel_limit() {
+    let (code, _) = launch(
+        SPIN_WAT,
+        &["--fuel-limit", "1000000", "--time-limit-secs", "10"],
+    );
+    assert_eq!(code, exit_code(Failure::OutOfFuel));
+}
+
+#[test]
+fn trap() {
+    let (code, _) = launch(UNREACHABLE_WAT, &[]);
+    assert_eq!(code, exit_code(Failure::Trap));
+}
+
+#[test]
+fn no_server() {
+    // Nothing listens on the port once it's freed.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+        .to_string();
+    let status = Command::new(env!("CARGO_BIN_EXE_paperio-wasm-launcher"))
+        .args([SPIN_WAT, "--address", "127.0.0.1", "--port", &port])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), exit_code(Failure::Io));
+}
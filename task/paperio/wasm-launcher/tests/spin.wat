@@ -0,0 +1,5 @@
+;; A strategy stuck on the first tick: never reads anything and spins forever.
+(module
+    (memory (export "memory") 1)
+    (func (export "_start")
+        (loop $spin (br $spin))))
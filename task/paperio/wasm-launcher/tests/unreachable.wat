@@ -0,0 +1,5 @@
+;; A strategy which panics right away.
+(module
+    (memory (export "memory") 1)
+    (func (export "_start")
+        unreachable))
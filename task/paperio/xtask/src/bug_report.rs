@@ -0,0 +1,625 @@
+//! `bug-report`: packs what the course staff asks for when the infrastructure misbehaves
+//! into a single tar under the task directory, with a `report.md` to fill in.
+//!
+//! Everything is collected defensively: a missing piece is noted in the report instead
+//! of failing the command. Only the names of changed files get into the archive, not
+//! their contents.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use xtask_util::{get_cwd_repo_path, get_cwd_task_path};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The packages whose versions are worth knowing, the rest can be read from the lock.
+pub const REPORTED_PACKAGES: &[&str] = &["wasmtime", "eframe", "egui", "serde", "serde_json"];
+
+/// Files the recipes leave in the task directory, taken if they are there.
+pub const LAST_RUN_FILES: &[&str] = &["tournament.json"];
+
+const BLOCK_SIZE: usize = 512;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A file of the archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// Writes an uncompressed ustar archive, which any `tar` extracts. Names must be at most
+/// 100 bytes long.
+pub fn write_tar(mut writer: impl Write, members: &[Member], mtime: u64) -> io::Result<()> {
+    for member in members {
+        let name = member.name.as_bytes();
+        if name.len() > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("archive member name is too long: {}", member.name),
+            ));
+        }
+
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name);
+        let mut put_octal = |offset: usize, width: usize, value: u64| {
+            let field = format!("{value:0digits$o}\0", digits = width - 1);
+            header[offset..offset + width].copy_from_slice(field.as_bytes());
+        };
+        put_octal(100, 8, 0o644);
+        put_octal(108, 8, 0);
+        put_octal(116, 8, 0);
+        put_octal(124, 12, member.content.len() as u64);
+        put_octal(136, 12, mtime);
+        header[156] = b'0';
+        header[257..265].copy_from_slice(b"ustar\x0000");
+
+        // Computed with the checksum field itself filled with spaces.
+        header[148..156].fill(b' ');
+        let checksum = header.iter().map(|&byte| u32::from(byte)).sum::<u32>();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+        writer.write_all(&header)?;
+        writer.write_all(&member.content)?;
+        let padding = member.content.len().next_multiple_of(BLOCK_SIZE) - member.content.len();
+        writer.write_all(&[0; BLOCK_SIZE][..padding])?;
+    }
+    writer.write_all(&[0; 2 * BLOCK_SIZE])?;
+    writer.flush()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The versions of a package in a lock file, several if dependencies disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageVersions {
+    pub name: String,
+    /// Empty if the package isn't in the lock.
+    pub versions: Vec<String>,
+}
+
+/// Finds the versions of `packages` among the `[[package]]` entries of a `Cargo.lock`.
+/// The result is in the order of `packages`, versions in the order of the lock.
+pub fn parse_lock_versions(lock: &str, packages: &[&str]) -> Vec<PackageVersions> {
+    let mut versions = BTreeMap::<&str, Vec<String>>::new();
+    let mut name = None;
+    for line in lock.lines().map(str::trim) {
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some(value) = toml_string(line, "name") {
+            name = Some(value);
+        } else if let (Some(value), Some(name)) = (toml_string(line, "version"), name) {
+            versions.entry(name).or_default().push(value.to_string());
+        }
+    }
+
+    packages
+        .iter()
+        .map(|&package| PackageVersions {
+            name: package.to_string(),
+            versions: versions.remove(package).unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// The value of a `key = "value"` line.
+fn toml_string<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = line
+        .strip_prefix(key)?
+        .trim_start()
+        .strip_prefix('=')?
+        .trim();
+    value.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Runs the command to completion and returns its stdout, or why it couldn't be run.
+pub fn command_output(program: &str, args: &[&str], dir: &Path) -> Result<String> {
+    let output = process::Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("failed to run {program}"))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{program} has failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The `host` line of `rustc -Vv`, or the OS and the architecture of this binary.
+pub fn host_triple(rustc_version: Option<&str>) -> String {
+    rustc_version
+        .and_then(|version| version.lines().find_map(|line| line.strip_prefix("host: ")))
+        .map(|host| host.trim().to_string())
+        .unwrap_or_else(|| format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The members of the archive, and the notes on what couldn't be collected.
+#[derive(Default, Debug)]
+pub struct Bundle {
+    pub members: Vec<Member>,
+    pub notes: Vec<String>,
+}
+
+impl Bundle {
+    pub fn add(&mut self, name: impl Into<String>, content: impl Into<Vec<u8>>) {
+        self.members.push(Member {
+            name: name.into(),
+            content: content.into(),
+        });
+    }
+
+    pub fn note(&mut self, note: impl Into<String>) {
+        self.notes.push(note.into());
+    }
+
+    /// Adds the files of `dir` as `<prefix>/<file name>` in the order of names, without
+    /// subdirectories.
+    pub fn add_dir(&mut self, dir: &Path, prefix: &str) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.note(format!(
+                    "there is no {prefix}/ directory, has a game been run?"
+                ));
+                return;
+            }
+            Err(err) => {
+                self.note(format!("failed to list {prefix}/: {err}"));
+                return;
+            }
+        };
+
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        if paths.is_empty() {
+            self.note(format!("{prefix}/ is empty"));
+        }
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy();
+            self.add_file(&path, &format!("{prefix}/{name}"));
+        }
+    }
+
+    /// Adds the file as `name`, or notes that it's missing.
+    pub fn add_file(&mut self, path: &Path, name: &str) {
+        match fs::read(path) {
+            Ok(content) => self.add(name, content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.note(format!("there is no {name}"))
+            }
+            Err(err) => self.note(format!("failed to read {name}: {err}")),
+        }
+    }
+
+    /// Adds the output of the command as `name`, or notes why there is none.
+    pub fn add_output(&mut self, name: &str, output: &Result<String>) {
+        match output {
+            Ok(output) => self.add(name, output.as_str()),
+            Err(err) => self.note(format!("no {name}: {err:#}")),
+        }
+    }
+}
+
+/// What `report.md` summarizes besides the bundle itself.
+pub struct ReportInfo<'a> {
+    pub timestamp: u64,
+    pub host: &'a str,
+    pub cargo_version: Option<&'a str>,
+    pub packages: &'a [PackageVersions],
+    /// `git status --porcelain` of the task, `None` if it has failed.
+    pub git_status: Option<&'a str>,
+}
+
+pub fn render_report(info: &ReportInfo, bundle: &Bundle) -> String {
+    let mut report = String::new();
+    writeln!(report, "# paperio bug report").unwrap();
+    writeln!(report).unwrap();
+    writeln!(
+        report,
+        "Collected by `cargo xtask bug-report` at {} (Unix time).",
+        info.timestamp
+    )
+    .unwrap();
+    writeln!(report).unwrap();
+
+    writeln!(report, "## What happened").unwrap();
+    writeln!(report).unwrap();
+    writeln!(
+        report,
+        "<!-- What you expected and what you got instead. -->"
+    )
+    .unwrap();
+    writeln!(report).unwrap();
+    writeln!(report, "## Steps to reproduce").unwrap();
+    writeln!(report).unwrap();
+    writeln!(
+        report,
+        "<!-- The commands you ran, starting from a clean checkout if possible. -->"
+    )
+    .unwrap();
+    writeln!(report, "1. ").unwrap();
+    writeln!(report).unwrap();
+
+    writeln!(report, "## Environment").unwrap();
+    writeln!(report).unwrap();
+    writeln!(report, "- host: `{}`", info.host).unwrap();
+    writeln!(
+        report,
+        "- cargo: `{}`",
+        info.cargo_version.map_or("unknown", str::trim)
+    )
+    .unwrap();
+    writeln!(report, "- rustc: see `env/rustc.txt`").unwrap();
+    writeln!(report).unwrap();
+
+    writeln!(report, "## Packages").unwrap();
+    writeln!(report).unwrap();
+    writeln!(report, "| package | versions |").unwrap();
+    writeln!(report, "|---|---|").unwrap();
+    for package in info.packages {
+        let versions = match package.versions.is_empty() {
+            true => "not in Cargo.lock".to_string(),
+            false => package.versions.join(", "),
+        };
+        writeln!(report, "| {} | {versions} |", package.name).unwrap();
+    }
+    writeln!(report).unwrap();
+
+    writeln!(report, "## Changed files").unwrap();
+    writeln!(report).unwrap();
+    match info.git_status {
+        Some(status) if status.trim().is_empty() => writeln!(report, "None.").unwrap(),
+        Some(status) => {
+            writeln!(report, "```").unwrap();
+            write!(report, "{}", status.trim_end()).unwrap();
+            writeln!(report, "\n```").unwrap();
+        }
+        None => writeln!(report, "Unknown, git status has failed.").unwrap(),
+    }
+    writeln!(report).unwrap();
+
+    writeln!(report, "## Files").unwrap();
+    writeln!(report).unwrap();
+    for member in &bundle.members {
+        writeln!(report, "- `{}`", member.name).unwrap();
+    }
+    if !bundle.notes.is_empty() {
+        writeln!(report).unwrap();
+        writeln!(report, "## Not collected").unwrap();
+        writeln!(report).unwrap();
+        for note in &bundle.notes {
+            writeln!(report, "- {note}").unwrap();
+        }
+    }
+    report
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Collects everything of the task at `task_dir` into a bundle, `report.md` first.
+pub fn collect(task_dir: &Path, lock_path: Option<&Path>, timestamp: u64) -> Bundle {
+    let mut bundle = Bundle::default();
+
+    bundle.add_dir(&task_dir.join("log"), "log");
+    for name in LAST_RUN_FILES {
+        let path = task_dir.join(name);
+        if path.exists() {
+            bundle.add_file(&path, name);
+        }
+    }
+
+    let rustc = command_output("rustc", &["-Vv"], task_dir);
+    let cargo = command_output("cargo", &["-V"], task_dir);
+    bundle.add_output("env/rustc.txt", &rustc);
+    bundle.add_output("env/cargo.txt", &cargo);
+
+    let git_status = command_output("git", &["status", "--porcelain", "--", "."], task_dir);
+    bundle.add_output("git-status.txt", &git_status);
+
+    let lock = match lock_path {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| bundle.note(format!("failed to read {}: {err}", path.display())))
+            .ok(),
+        None => {
+            bundle.note("there is no Cargo.lock, has the workspace been built?");
+            None
+        }
+    };
+    let packages = parse_lock_versions(lock.as_deref().unwrap_or_default(), REPORTED_PACKAGES);
+
+    let report = render_report(
+        &ReportInfo {
+            timestamp,
+            host: &host_triple(rustc.as_deref().ok()),
+            cargo_version: cargo.as_deref().ok(),
+            packages: &packages,
+            git_status: git_status.as_deref().ok(),
+        },
+        &bundle,
+    );
+    bundle.members.insert(
+        0,
+        Member {
+            name: "report.md".to_string(),
+            content: report.into_bytes(),
+        },
+    );
+    bundle
+}
+
+pub fn run() -> Result<()> {
+    let task_dir = get_cwd_task_path()?;
+    let lock_path = get_cwd_repo_path()
+        .map(|repo| repo.join("Cargo.lock"))
+        .ok()
+        .filter(|path| path.exists());
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("the clock is before the Unix epoch")?
+        .as_secs();
+
+    let bundle = collect(&task_dir, lock_path.as_deref(), timestamp);
+    for note in &bundle.notes {
+        eprintln!("note: {note}");
+    }
+
+    let path: PathBuf = task_dir.join(format!("bug-report-{timestamp}.tar"));
+    let file = fs::File::create(&path).with_context(|| format!("failed to create {path:?}"))?;
+    write_tar(io::BufWriter::new(file), &bundle.members, timestamp)
+        .with_context(|| format!("failed to write {path:?}"))?;
+    eprintln!(
+        "The report is written to {path:?}, fill in report.md and attach the archive to your \
+        bug report"
+    );
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names and contents of an archive written by `write_tar`.
+    fn read_tar(mut archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut members = vec![];
+        while archive.len() >= BLOCK_SIZE && archive[..BLOCK_SIZE].iter().any(|&b| b != 0) {
+            let (header, rest) = archive.split_at(BLOCK_SIZE);
+            let field = |range: std::ops::Range<usize>| {
+                String::from_utf8(header[range].to_vec())
+                    .unwrap()
+                    .trim_end_matches(['\0', ' '])
+                    .to_string()
+            };
+            assert_eq!(&header[257..263], b"ustar\0");
+            let size = usize::from_str_radix(&field(124..136), 8).unwrap();
+            let checksum = u32::from_str_radix(&field(148..156), 8).unwrap();
+            let sum = header
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| {
+                    if (148..156).contains(&i) {
+                        32
+                    } else {
+                        u32::from(b)
+                    }
+                })
+                .sum::<u32>();
+            assert_eq!(checksum, sum);
+
+            members.push((field(0..100), rest[..size].to_vec()));
+            archive = &rest[size.next_multiple_of(BLOCK_SIZE)..];
+        }
+        assert_eq!(archive, [0; 2 * BLOCK_SIZE]);
+        members
+    }
+
+    fn member(name: &str, content: &str) -> Member {
+        Member {
+            name: name.to_string(),
+            content: content.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_write_tar() {
+        let members = [
+            member("report.md", "# report\n"),
+            member("log/empty.log", ""),
+            Member {
+                name: "log/server.log".to_string(),
+                content: vec![b'x'; BLOCK_SIZE + 1],
+            },
+        ];
+        let mut archive = vec![];
+        write_tar(&mut archive, &members, 1_700_000_000).unwrap();
+        assert_eq!(archive.len() % BLOCK_SIZE, 0);
+
+        let read = read_tar(&archive);
+        let expected = members
+            .iter()
+            .map(|member| (member.name.clone(), member.content.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(read, expected);
+
+        let long = member(&"x".repeat(101), "");
+        let err = write_tar(io::sink(), &[long], 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_parse_lock_versions() {
+        let lock = r#"# This file is automatically @generated by Cargo.
+version = 3
+
+[[package]]
+name = "serde"
+version = "1.0.210"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = [
+ "serde_derive",
+]
+
+[[package]]
+name = "wasmtime"
+version = "24.0.0"
+
+[[package]]
+name = "serde_derive"
+version = "1.0.210"
+
+[[package]]
+name = "wasmtime"
+version = "17.0.2"
+"#;
+        let versions = parse_lock_versions(lock, &["wasmtime", "serde", "eframe"]);
+        let versions = versions
+            .iter()
+            .map(|package| (package.name.as_str(), package.versions.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            [
+                ("wasmtime", vec!["24.0.0".to_string(), "17.0.2".to_string()]),
+                ("serde", vec!["1.0.210".to_string()]),
+                ("eframe", vec![]),
+            ]
+        );
+
+        // The lock format version isn't a package.
+        assert!(parse_lock_versions(lock, &["version"])[0]
+            .versions
+            .is_empty());
+        assert!(parse_lock_versions("", &["serde"])[0].versions.is_empty());
+    }
+
+    #[test]
+    fn test_host_triple() {
+        let rustc = "rustc 1.81.0 (eeb90cda1 2024-09-04)\nbinary: rustc\n\
+            host: x86_64-unknown-linux-gnu\nrelease: 1.81.0\n";
+        assert_eq!(host_triple(Some(rustc)), "x86_64-unknown-linux-gnu");
+        assert!(host_triple(None).contains(std::env::consts::OS));
+        assert!(host_triple(Some("garbage")).contains(std::env::consts::ARCH));
+    }
+
+    #[test]
+    fn test_bundle_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_dir = dir.path().join("log");
+        fs::create_dir(&log_dir).unwrap();
+        fs::write(log_dir.join("server.log"), "server").unwrap();
+        fs::write(log_dir.join("bot_0.log"), "bot").unwrap();
+        fs::create_dir(log_dir.join("nested")).unwrap();
+
+        let mut bundle = Bundle::default();
+        bundle.add_dir(&log_dir, "log");
+        assert_eq!(
+            bundle.members,
+            [
+                member("log/bot_0.log", "bot"),
+                member("log/server.log", "server")
+            ]
+        );
+        assert!(bundle.notes.is_empty());
+
+        let mut bundle = Bundle::default();
+        bundle.add_dir(&dir.path().join("missing"), "log");
+        bundle.add_file(&dir.path().join("tournament.json"), "tournament.json");
+        bundle.add_output(
+            "env/bogus.txt",
+            &command_output("no-such-program-xtask", &[], dir.path()),
+        );
+        assert!(bundle.members.is_empty());
+        assert_eq!(bundle.notes.len(), 3);
+        assert!(bundle.notes[0].contains("no log/"));
+        assert!(bundle.notes[1].contains("tournament.json"));
+        assert!(bundle.notes[2].starts_with("no env/bogus.txt"));
+    }
+
+    #[test]
+    fn test_collect() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("log")).unwrap();
+        fs::write(dir.path().join("log/server.log"), "tick #1").unwrap();
+        fs::write(dir.path().join("tournament.json"), "{}").unwrap();
+        let lock = dir.path().join("Cargo.lock");
+        fs::write(
+            &lock,
+            "[[package]]\nname = \"eframe\"\nversion = \"0.28.1\"\n",
+        )
+        .unwrap();
+
+        let bundle = collect(dir.path(), Some(&lock), 42);
+        let names = bundle
+            .members
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>();
+        // The outputs of the commands depend on the machine, they may be missing.
+        assert_eq!(
+            names[..3],
+            ["report.md", "log/server.log", "tournament.json"]
+        );
+        for name in &names[3..] {
+            assert!(
+                ["env/rustc.txt", "env/cargo.txt", "git-status.txt"].contains(name),
+                "{name}"
+            );
+        }
+
+        let report = String::from_utf8(bundle.members[0].content.clone()).unwrap();
+        let headings = report
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headings[..7],
+            [
+                "# paperio bug report",
+                "## What happened",
+                "## Steps to reproduce",
+                "## Environment",
+                "## Packages",
+                "## Changed files",
+                "## Files",
+            ]
+        );
+        assert!(report.contains("at 42 (Unix time)"));
+        assert!(report.contains("| eframe | 0.28.1 |"));
+        assert!(report.contains("| wasmtime | not in Cargo.lock |"));
+        assert!(report.contains("- `log/server.log`"));
+    }
+
+    #[test]
+    fn test_collect_without_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = collect(dir.path(), None, 0);
+        assert_eq!(bundle.members[0].name, "report.md");
+        assert!(!bundle
+            .members
+            .iter()
+            .any(|member| member.name.starts_with("log/")));
+
+        let report = String::from_utf8(bundle.members[0].content.clone()).unwrap();
+        assert!(report.contains("## Not collected"));
+        assert!(report.contains("there is no log/ directory"));
+        assert!(report.contains("there is no Cargo.lock"));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Child, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use xshell::{cmd, Shell};
+use xtask_util::{get_cwd_repo_path, get_cwd_task_path};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How long to wait for the server to start listening before launching its clients.
+pub const SERVER_STARTUP: Duration = Duration::from_millis(500);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn build_binaries(packages: &[&str]) -> Result<()> {
+    let sh = Shell::new()?;
+    for package in packages {
+        cmd!(sh, "cargo build --package {package} --release").run()?;
+    }
+    Ok(())
+}
+
+/// `cargo run` of a package in the release profile, arguments follow.
+pub fn cargo_run(package: &str) -> process::Command {
+    let mut cmd = process::Command::new("cargo");
+    cmd.args(["run", "--package", package, "--release", "--"]);
+    cmd
+}
+
+/// Runs the release binary of a package directly, so that killing the command kills the
+/// binary itself rather than cargo. The package must be built by `build_binaries`.
+pub fn release_binary(package: &str) -> Result<process::Command> {
+    let target_dir = match env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => get_cwd_repo_path()?.join("target"),
+    };
+    let path = target_dir
+        .join("release")
+        .join(package)
+        .with_extension(env::consts::EXE_EXTENSION);
+    Ok(process::Command::new(path))
+}
+
+pub fn wasm_launcher_args(cmd: &mut process::Command, wasm_path: &Path, port: u16) {
+    cmd.arg(wasm_path).args(["--port", &port.to_string()]);
+}
+
+/// Redirects stderr of the command to `log/<log_name>.log` if the name is given, and prints
+/// the command line.
+fn prepare_cmd(cmd: &mut process::Command, log_name: Option<impl AsRef<str>>) -> Result<()> {
+    if let Some(log_name) = log_name {
+        let dir_path = get_cwd_task_path()?.join("log");
+        if !dir_path.exists() {
+            fs::create_dir(&dir_path).context("failed to create log dir")?;
+        }
+
+        let file_path = dir_path.join(format!("{}.log", log_name.as_ref()));
+        let log_file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&file_path)
+            .with_context(|| format!("failed to create {file_path:?}"))?;
+
+        cmd.stderr(log_file);
+    }
+
+    eprintln!(
+        "$ {} {}",
+        cmd.get_program().to_string_lossy(),
+        cmd.get_args()
+            .map(|a| a.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    Ok(())
+}
+
+/// Runs the command to completion, returns its stdout.
+pub fn run_cmd(mut cmd: process::Command, log_name: Option<impl AsRef<str>>) -> Result<Vec<u8>> {
+    prepare_cmd(&mut cmd, log_name)?;
+
+    let output = cmd
+        .output()
+        .with_context(|| format!("failed to run {cmd:?}"))?;
+
+    Ok(output.stdout)
+}
+
+/// Starts the command with a piped stdout, see `wait_until`.
+pub fn spawn_cmd(mut cmd: process::Command, log_name: Option<impl AsRef<str>>) -> Result<Child> {
+    prepare_cmd(&mut cmd, log_name)?;
+    cmd.stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to spawn {cmd:?}"))
+}
+
+/// Waits for the process to exit and returns its stdout, or kills it and returns `None`
+/// once the deadline passes.
+///
+/// The stdout is only read after the exit, so it should be small enough to fit into
+/// the pipe buffer.
+pub fn wait_until(mut child: Child, deadline: Instant) -> Result<Option<Vec<u8>>> {
+    loop {
+        if child.try_wait()?.is_some() {
+            return Ok(Some(child.wait_with_output()?.stdout));
+        }
+        if Instant::now() >= deadline {
+            // It may exit on its own right before the kill.
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The line the server prints with `--json-results`, as far as the recipes need it.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct GameResults {
+    /// Unset on a tie.
+    pub winner: Option<usize>,
+    pub ticks_played: usize,
+    pub players: Vec<PlayerSummary>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct PlayerSummary {
+    pub player_id: usize,
+    pub score: u32,
+    pub has_lost: bool,
+    pub io_error: Option<String>,
+}
+
+/// Finds the results among the lines the server has printed: the last JSON one.
+pub fn parse_game_results(stdout: &str) -> Result<GameResults> {
+    let line = stdout
+        .lines()
+        .rev()
+        .find(|line| line.starts_with('{'))
+        .context("the server hasn't printed the results")?;
+    serde_json::from_str(line).context("malformed results of the server")
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_game_results() {
+        let stdout = r#"Winner is Player #2! (by score)
+Player #1: score 12, lost (trace crossed)
+Player #2: score 40
+Player #3: score 3, io error (broken pipe)
+{"winner":2,"win_reason":"score","ticks_played":300,"players":[{"player_id":1,"score":12,"has_lost":true,"loss_reason":"trace_crossed","timeouts":0,"io_error":null},{"player_id":2,"score":40,"has_lost":false,"loss_reason":null,"timeouts":0,"io_error":null},{"player_id":3,"score":3,"has_lost":false,"loss_reason":null,"timeouts":1,"io_error":"broken pipe"}]}
+"#;
+        let results = parse_game_results(stdout).unwrap();
+        assert_eq!(results.winner, Some(2));
+        assert_eq!(results.ticks_played, 300);
+        assert_eq!(
+            results.players[2],
+            PlayerSummary {
+                player_id: 3,
+                score: 3,
+                has_lost: false,
+                io_error: Some("broken pipe".to_string()),
+            }
+        );
+        assert!(results.players[0].has_lost);
+
+        let tie = r#"There is no winner (tie)
+Player #1: score 0
+{"winner":null,"win_reason":null,"ticks_played":120,"players":[{"player_id":1,"score":0,"has_lost":false,"loss_reason":null,"timeouts":0,"io_error":null}]}"#;
+        assert_eq!(parse_game_results(tie).unwrap().winner, None);
+
+        assert!(parse_game_results("Winner is Player #4! (by score)\n").is_err());
+        assert!(parse_game_results("{\"winner\":\n").is_err());
+    }
+}
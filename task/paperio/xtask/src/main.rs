@@ -1,14 +1,20 @@
-use std::{
-    fs, process,
-    thread::{self, JoinHandle},
-    time::Duration,
-};
+mod bug_report;
+mod launch;
+mod tournament;
 
-use anyhow::{bail, Context, Result};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{bail, Result};
 use clap::{Parser, Subcommand};
-use xshell::{cmd, Shell};
 use xtask_util::get_cwd_task_path;
 
+/// The ports the server listens on by default.
+const DEFAULT_PLAYER_PORT: u16 = 8000;
+const SPECTATOR_PORT: u16 = 8001;
+/// The port of the fourth player, who is either you or your strategy.
+const STRATEGY_PORT: u16 = 8004;
+const STRATEGY_PLAYER_ID: usize = 4;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -36,6 +42,13 @@ enum Command {
 
     /// Run you strategy three times against bots (no gui).
     Challenge,
+
+    /// Run a tournament between wasm strategies and write the standings.
+    Tournament(tournament::TournamentArgs),
+
+    /// Pack the logs of the last run and the environment into an archive to attach to a
+    /// bug report.
+    BugReport,
 }
 
 #[derive(Clone, Copy)]
@@ -48,6 +61,7 @@ enum GuiMode {
 enum Outcome {
     Won,
     Lost,
+    Tie,
 }
 
 struct Recipe {
@@ -93,6 +107,10 @@ impl Recipe {
                 eprintln!("You lost :(");
                 bail!("strategy lost");
             }
+            Ok(Ok(Outcome::Tie)) => {
+                eprintln!("It's a tie, which isn't a win yet");
+                bail!("strategy tied");
+            }
             Ok(Err(err)) => {
                 bail!("server error: {err}")
             }
@@ -105,16 +123,12 @@ impl Recipe {
     }
 
     fn build_binaries() -> Result<()> {
-        let sh = Shell::new()?;
-        for package in [
+        launch::build_binaries(&[
             "paperio-wasm-launcher",
             "paperio-strategy",
             "paperio-gui",
             "paperio-server",
-        ] {
-            cmd!(sh, "cargo build --package {package} --release").run()?;
-        }
-        Ok(())
+        ])
     }
 
     fn launch_bots(capture_logs: bool) -> Result<Vec<JoinHandle<Result<()>>>> {
@@ -128,22 +142,15 @@ impl Recipe {
                 .join(format!("{bot_name}.wasm"));
 
             let handle = thread::spawn(move || -> Result<()> {
-                let mut cmd = process::Command::new("cargo");
-                cmd.args([
-                    "run",
-                    "--package",
-                    "paperio-wasm-launcher",
-                    "--release",
-                    "--",
-                ])
-                .arg(bot_path);
+                let mut cmd = launch::cargo_run("paperio-wasm-launcher");
+                launch::wasm_launcher_args(&mut cmd, &bot_path, DEFAULT_PLAYER_PORT);
 
                 let log_name = if capture_logs {
                     Some(format!("bot_{bot_id}"))
                 } else {
                     None
                 };
-                Self::run_cmd(cmd, log_name)?;
+                launch::run_cmd(cmd, log_name)?;
 
                 Ok(())
             });
@@ -155,18 +162,11 @@ impl Recipe {
 
     fn launch_strategy(capture_logs: bool) -> JoinHandle<Result<()>> {
         thread::spawn(move || -> Result<()> {
-            let mut cmd = process::Command::new("cargo");
-            cmd.args([
-                "run",
-                "--package",
-                "paperio-strategy",
-                "--release",
-                "--",
-                "8004",
-            ]);
+            let mut cmd = launch::cargo_run("paperio-strategy");
+            cmd.arg(STRATEGY_PORT.to_string());
 
             let log_name = if capture_logs { Some("strategy") } else { None };
-            Self::run_cmd(cmd, log_name)?;
+            launch::run_cmd(cmd, log_name)?;
 
             Ok(())
         })
@@ -175,25 +175,16 @@ impl Recipe {
     fn launch_gui(is_spectator: bool, capture_logs: bool) -> JoinHandle<Result<()>> {
         thread::spawn(move || -> Result<()> {
             let (port, spectator_arg) = if is_spectator {
-                ("8001", &["--spectator"] as &[_])
+                (SPECTATOR_PORT, &["--spectator"] as &[_])
             } else {
-                ("8004", &[] as &[_])
+                (STRATEGY_PORT, &[] as &[_])
             };
 
-            let mut cmd = process::Command::new("cargo");
-            cmd.args([
-                "run",
-                "--package",
-                "paperio-gui",
-                "--release",
-                "--",
-                "-p",
-                port,
-            ])
-            .args(spectator_arg);
+            let mut cmd = launch::cargo_run("paperio-gui");
+            cmd.args(["-p", &port.to_string()]).args(spectator_arg);
 
             let log_name = if capture_logs { Some("gui") } else { None };
-            Self::run_cmd(cmd, log_name)?;
+            launch::run_cmd(cmd, log_name)?;
 
             Ok(())
         })
@@ -201,69 +192,27 @@ impl Recipe {
 
     fn launch_server(with_spectator: bool, capture_logs: bool) -> JoinHandle<Result<Outcome>> {
         let handle = thread::spawn(move || -> Result<Outcome> {
-            let mut cmd = process::Command::new("cargo");
-            cmd.args([
-                "run",
-                "--package",
-                "paperio-server",
-                "--release",
-                "--",
-                "--p4",
-                "8004",
-            ]);
+            let mut cmd = launch::cargo_run("paperio-server");
+            cmd.args(["--p4", &STRATEGY_PORT.to_string(), "--json-results"]);
 
             if with_spectator {
                 cmd.args(["--spectator-count", "1"]);
             }
 
             let log_name = if capture_logs { Some("server") } else { None };
-            let stdout = Self::run_cmd(cmd, log_name)?;
-
-            if String::from_utf8_lossy(&stdout).contains("Winner is Player #4") {
-                Ok(Outcome::Won)
-            } else {
-                Ok(Outcome::Lost)
-            }
+            let stdout = launch::run_cmd(cmd, log_name)?;
+
+            let results = launch::parse_game_results(&String::from_utf8_lossy(&stdout))?;
+            Ok(match results.winner {
+                Some(STRATEGY_PLAYER_ID) => Outcome::Won,
+                Some(_) => Outcome::Lost,
+                None => Outcome::Tie,
+            })
         });
 
-        thread::sleep(Duration::from_millis(500));
+        thread::sleep(launch::SERVER_STARTUP);
         handle
     }
-
-    fn run_cmd(mut cmd: process::Command, log_name: Option<impl AsRef<str>>) -> Result<Vec<u8>> {
-        if let Some(log_name) = log_name {
-            let dir_path = get_cwd_task_path()?.join("log");
-            if !dir_path.exists() {
-                fs::create_dir(&dir_path).context("failed to create log dir")?;
-            }
-
-            let file_path = dir_path.join(format!("{}.log", log_name.as_ref()));
-            let log_file = fs::OpenOptions::new()
-                .create(true)
-                .truncate(true)
-                .write(true)
-                .open(&file_path)
-                .with_context(|| format!("failed to create {file_path:?}"))?;
-
-            cmd.stderr(log_file);
-        }
-
-        eprintln!(
-            "$ {} {}",
-            cmd.get_program().to_string_lossy(),
-            cmd.get_args()
-                .into_iter()
-                .map(|a| a.to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(" ")
-        );
-
-        let output = cmd
-            .output()
-            .with_context(|| format!("failed to run {cmd:?}"))?;
-
-        Ok(output.stdout)
-    }
 }
 
 fn play(no_logs: bool) -> Result<()> {
@@ -315,5 +264,7 @@ fn main() -> Result<()> {
         Command::Watch => watch(args.no_logs),
         Command::Debug => debug(args.no_logs),
         Command::Challenge => challenge(args.no_logs),
+        Command::Tournament(tournament_args) => tournament::run(tournament_args, !args.no_logs),
+        Command::BugReport => bug_report::run(),
     }
 }
@@ -0,0 +1,624 @@
+//! A tournament between wasm strategies, run locally: a seeded schedule of games of up to
+//! four players, played several at a time, and the standings.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    io::BufWriter,
+    net::TcpListener,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::Parser;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Serialize;
+
+use crate::launch;
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const MAX_PLAYERS: usize = 4;
+
+const PLAYER_PORT_FLAGS: [&str; MAX_PLAYERS] = ["--p1", "--p2", "--p3", "--p4"];
+
+/// How long the launchers may take to exit after the server has finished the game.
+const LAUNCHER_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Parser, Clone, Debug)]
+pub struct TournamentArgs {
+    /// A directory with the strategies, named `<login>.wasm`.
+    pub strategies_dir: PathBuf,
+
+    /// Games each strategy plays.
+    #[arg(long, default_value_t = 4)]
+    pub games: usize,
+
+    /// Seed of the schedule, the same seed gives the same groups.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Games played at the same time.
+    #[arg(long, default_value_t = 1)]
+    pub parallel: usize,
+
+    /// Ports of the games are allocated starting from this one.
+    #[arg(long, default_value_t = 9000)]
+    pub first_port: u16,
+
+    #[arg(long, default_value_t = 300)]
+    pub tick_count: usize,
+
+    /// Forfeit strategies taking longer than this to respond to a tick, in milliseconds.
+    #[arg(long)]
+    pub tick_budget_ms: Option<u64>,
+
+    /// Kill games lasting longer than this, in seconds. All of their strategies score zero.
+    #[arg(long, default_value_t = 300)]
+    pub game_timeout_secs: u64,
+
+    /// Where to write the JSON report.
+    #[arg(long, default_value = "tournament.json")]
+    pub report: PathBuf,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns the groups of strategy indices, a group per game.
+///
+/// Every strategy plays one game per round, so `rounds` games in total: each round the
+/// strategies are shuffled and split into as few groups of at most `MAX_PLAYERS` as
+/// possible, with sizes differing by one at most.
+pub fn schedule(strategy_count: usize, rounds: usize, seed: u64) -> Vec<Vec<usize>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let group_count = strategy_count.div_ceil(MAX_PLAYERS);
+
+    let mut groups = vec![];
+    for _ in 0..rounds {
+        let mut order = (0..strategy_count).collect::<Vec<_>>();
+        order.shuffle(&mut rng);
+
+        let mut rest = order.as_slice();
+        for i in 0..group_count {
+            let size = strategy_count / group_count + usize::from(i < strategy_count % group_count);
+            let (group, tail) = rest.split_at(size);
+            groups.push(group.to_vec());
+            rest = tail;
+        }
+    }
+    groups
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Hands out ports of a range to the games running at the same time.
+pub struct PortAllocator {
+    ports: Range<u16>,
+    in_use: BTreeSet<u16>,
+}
+
+impl PortAllocator {
+    pub fn new(ports: Range<u16>) -> Self {
+        Self {
+            ports,
+            in_use: BTreeSet::new(),
+        }
+    }
+
+    /// Takes the lowest `count` ports which are neither allocated yet nor rejected by
+    /// `is_available`, e.g. because another process listens on them. Allocates nothing
+    /// if there are not enough such ports.
+    pub fn allocate(
+        &mut self,
+        count: usize,
+        mut is_available: impl FnMut(u16) -> bool,
+    ) -> Option<Vec<u16>> {
+        let ports = self
+            .ports
+            .clone()
+            .filter(|port| !self.in_use.contains(port) && is_available(*port))
+            .take(count)
+            .collect::<Vec<_>>();
+        if ports.len() < count {
+            return None;
+        }
+        self.in_use.extend(&ports);
+        Some(ports)
+    }
+
+    pub fn release(&mut self, ports: &[u16]) {
+        for port in ports {
+            self.in_use.remove(port);
+        }
+    }
+}
+
+fn is_port_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    /// Strategy indices, by seat.
+    pub players: Vec<usize>,
+    /// Scores by seat, `None` if the strategy has crashed or timed out.
+    pub scores: Vec<Option<u32>>,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Standing {
+    pub name: String,
+    pub points: u32,
+    pub total_score: u64,
+    pub games: usize,
+    /// Games the strategy has crashed or timed out in.
+    pub failures: usize,
+}
+
+/// Placement points are a point per opponent outscored in a game: 3 for the winner of
+/// a four player game down to 0 for the last one, tied players get the same points.
+/// Failed strategies score zero.
+///
+/// Standings are ordered by points, then by total score, then by name.
+pub fn standings(names: &[String], games: &[GameRecord]) -> Vec<Standing> {
+    let mut standings = names
+        .iter()
+        .map(|name| Standing {
+            name: name.clone(),
+            ..Standing::default()
+        })
+        .collect::<Vec<_>>();
+
+    for game in games {
+        let scores = game
+            .scores
+            .iter()
+            .map(|score| score.unwrap_or(0))
+            .collect::<Vec<_>>();
+        for (seat, &player) in game.players.iter().enumerate() {
+            let standing = &mut standings[player];
+            standing.games += 1;
+            standing.total_score += u64::from(scores[seat]);
+            standing.points += scores.iter().filter(|&&score| score < scores[seat]).count() as u32;
+            if game.scores[seat].is_none() {
+                standing.failures += 1;
+            }
+        }
+    }
+
+    standings.sort_by(|lhs, rhs| {
+        rhs.points
+            .cmp(&lhs.points)
+            .then(rhs.total_score.cmp(&lhs.total_score))
+            .then_with(|| lhs.name.cmp(&rhs.name))
+    });
+    standings
+}
+
+pub fn render_standings(standings: &[Standing]) -> String {
+    let name_width = standings
+        .iter()
+        .map(|standing| standing.name.len())
+        .chain(["strategy".len()])
+        .max()
+        .unwrap();
+
+    let mut table = format!(
+        "{:>3}  {:<name_width$}  {:>6}  {:>7}  {:>5}  {:>8}\n",
+        "#", "strategy", "points", "score", "games", "failures"
+    );
+    for (place, standing) in standings.iter().enumerate() {
+        writeln!(
+            table,
+            "{:>3}  {:<name_width$}  {:>6}  {:>7}  {:>5}  {:>8}",
+            place + 1,
+            standing.name,
+            standing.points,
+            standing.total_score,
+            standing.games,
+            standing.failures,
+        )
+        .unwrap();
+    }
+    table
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Parses the `Player #<id>: score <score>[, ...]` lines printed by the server at the end
+/// of a game. Players that were forfeited or got an IO error, i.e. have crashed or timed
+/// out, get `None`. Returns `None` if some player is missing.
+pub fn parse_scores(stdout: &str, player_count: usize) -> Option<Vec<Option<u32>>> {
+    let mut scores = vec![None; player_count];
+    for line in stdout.lines() {
+        let Some((id, rest)) = line
+            .strip_prefix("Player #")
+            .and_then(|line| line.split_once(": score "))
+        else {
+            continue;
+        };
+        let (score, details) = rest.split_once(',').unwrap_or((rest, ""));
+        let (Ok(id), Ok(score)) = (id.parse::<usize>(), score.parse::<u32>()) else {
+            continue;
+        };
+        if !(1..=player_count).contains(&id) {
+            continue;
+        }
+
+        let has_failed = details.contains("io error") || details.contains("lost (forfeited)");
+        scores[id - 1] = Some((!has_failed).then_some(score));
+    }
+    scores.into_iter().collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize)]
+struct Report<'a> {
+    seed: u64,
+    games_per_strategy: usize,
+    standings: &'a [Standing],
+    games: Vec<GameReport<'a>>,
+}
+
+#[derive(Serialize)]
+struct GameReport<'a> {
+    players: Vec<&'a str>,
+    scores: &'a [Option<u32>],
+}
+
+struct Strategy {
+    name: String,
+    path: PathBuf,
+}
+
+fn find_strategies(dir: &Path) -> Result<Vec<Strategy>> {
+    let mut strategies = vec![];
+    for mb_entry in fs::read_dir(dir).with_context(|| format!("failed to read {dir:?}"))? {
+        let path = mb_entry?.path();
+        if path.extension().is_none_or(|extension| extension != "wasm") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            bail!("strategy name is not valid UTF-8: {path:?}");
+        };
+        strategies.push(Strategy {
+            name: name.to_string(),
+            path,
+        });
+    }
+    strategies.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    Ok(strategies)
+}
+
+struct Tournament<'a> {
+    args: &'a TournamentArgs,
+    strategies: Vec<Strategy>,
+    ports: Mutex<PortAllocator>,
+    capture_logs: bool,
+}
+
+impl Tournament<'_> {
+    /// Plays the game, the failures of the strategies are not errors.
+    fn play(&self, game_index: usize, players: &[usize]) -> Result<Vec<Option<u32>>> {
+        let ports = self
+            .ports
+            .lock()
+            .unwrap()
+            .allocate(players.len(), is_port_free)
+            .context("no free ports left")?;
+        let result = self.play_on(game_index, players, &ports);
+        self.ports.lock().unwrap().release(&ports);
+        result
+    }
+
+    fn play_on(
+        &self,
+        game_index: usize,
+        players: &[usize],
+        ports: &[u16],
+    ) -> Result<Vec<Option<u32>>> {
+        let deadline = Instant::now() + Duration::from_secs(self.args.game_timeout_secs);
+        let log_name = |name: &str| {
+            self.capture_logs
+                .then(|| format!("tournament_game_{game_index}_{name}"))
+        };
+
+        let mut cmd = launch::release_binary("paperio-server")?;
+        cmd.args(["--player-count", &players.len().to_string()])
+            .args(["--tick-count", &self.args.tick_count.to_string()]);
+        for (flag, port) in PLAYER_PORT_FLAGS.iter().zip(ports) {
+            cmd.arg(flag).arg(port.to_string());
+        }
+        if let Some(millis) = self.args.tick_budget_ms {
+            cmd.args(["--cpu-budget-ms", &millis.to_string()])
+                .args(["--budget-policy", "forfeit"]);
+        }
+        let mut server = launch::spawn_cmd(cmd, log_name("server"))?;
+        thread::sleep(launch::SERVER_STARTUP);
+
+        let mut launchers = vec![];
+        for (&player, &port) in players.iter().zip(ports) {
+            let strategy = &self.strategies[player];
+            let mb_launcher =
+                launch::release_binary("paperio-wasm-launcher").and_then(|mut cmd| {
+                    launch::wasm_launcher_args(&mut cmd, &strategy.path, port);
+                    launch::spawn_cmd(cmd, log_name(&strategy.name))
+                });
+            match mb_launcher {
+                Ok(launcher) => launchers.push(launcher),
+                Err(err) => {
+                    for child in launchers.iter_mut().chain([&mut server]) {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let mb_stdout = launch::wait_until(server, deadline)?;
+        let launchers_deadline = Instant::now() + LAUNCHER_GRACE;
+        for launcher in launchers {
+            launch::wait_until(launcher, launchers_deadline)?;
+        }
+
+        let Some(stdout) = mb_stdout else {
+            eprintln!("game #{game_index} has timed out");
+            return Ok(vec![None; players.len()]);
+        };
+        parse_scores(&String::from_utf8_lossy(&stdout), players.len())
+            .context("failed to parse the results printed by the server")
+    }
+}
+
+pub fn run(args: TournamentArgs, capture_logs: bool) -> Result<()> {
+    ensure!(
+        args.parallel > 0,
+        "parallel games amount should be positive"
+    );
+
+    let strategies = find_strategies(&args.strategies_dir)?;
+    ensure!(
+        strategies.len() >= 2,
+        "a tournament needs at least two strategies, found {}",
+        strategies.len()
+    );
+    launch::build_binaries(&["paperio-wasm-launcher", "paperio-server"])?;
+
+    let groups = schedule(strategies.len(), args.games, args.seed);
+    let tournament = Tournament {
+        args: &args,
+        strategies,
+        ports: Mutex::new(PortAllocator::new(args.first_port..u16::MAX)),
+        capture_logs,
+    };
+
+    let next_game = AtomicUsize::new(0);
+    let records = Mutex::new(vec![None; groups.len()]);
+    thread::scope(|scope| {
+        for _ in 0..args.parallel.min(groups.len()) {
+            scope.spawn(|| loop {
+                let game_index = next_game.fetch_add(1, Ordering::Relaxed);
+                let Some(players) = groups.get(game_index) else {
+                    break;
+                };
+                eprintln!("Running game #{game_index} of {}...", groups.len());
+
+                let scores = tournament.play(game_index, players).unwrap_or_else(|err| {
+                    eprintln!("game #{game_index} has failed: {err:#}");
+                    vec![None; players.len()]
+                });
+                records.lock().unwrap()[game_index] = Some(GameRecord {
+                    players: players.clone(),
+                    scores,
+                });
+            });
+        }
+    });
+    let records = records
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(Option::unwrap)
+        .collect::<Vec<_>>();
+
+    let names = tournament
+        .strategies
+        .iter()
+        .map(|strategy| strategy.name.clone())
+        .collect::<Vec<_>>();
+    let standings = standings(&names, &records);
+    print!("{}", render_standings(&standings));
+
+    let report = Report {
+        seed: args.seed,
+        games_per_strategy: args.games,
+        standings: &standings,
+        games: records
+            .iter()
+            .map(|record| GameReport {
+                players: record.players.iter().map(|&i| names[i].as_str()).collect(),
+                scores: &record.scores,
+            })
+            .collect(),
+    };
+    let file = fs::File::create(&args.report)
+        .with_context(|| format!("failed to create {:?}", args.report))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &report)
+        .context("failed to write the report")?;
+    eprintln!("The report is written to {:?}", args.report);
+
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xtask_util::get_cwd_task_path;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_schedule() {
+        for strategy_count in [2, 3, 4, 5, 8, 9, 20, 21] {
+            let rounds = 3;
+            let groups = schedule(strategy_count, rounds, 42);
+
+            let mut games = vec![0; strategy_count];
+            for group in &groups {
+                assert!((2..=MAX_PLAYERS).contains(&group.len()), "{groups:?}");
+                assert_eq!(
+                    group.iter().collect::<BTreeSet<_>>().len(),
+                    group.len(),
+                    "{groups:?}"
+                );
+                for &player in group {
+                    games[player] += 1;
+                }
+            }
+            assert!(games.iter().all(|&count| count == rounds), "{groups:?}");
+            assert_eq!(groups.len(), rounds * strategy_count.div_ceil(MAX_PLAYERS));
+        }
+
+        let sizes = |groups: Vec<Vec<usize>>| groups.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes(schedule(21, 1, 0)), [4, 4, 4, 3, 3, 3]);
+        assert_eq!(sizes(schedule(5, 1, 0)), [3, 2]);
+        assert!(schedule(0, 3, 0).is_empty());
+        assert!(schedule(10, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_schedule_is_seeded() {
+        assert_eq!(schedule(20, 5, 1), schedule(20, 5, 1));
+        assert_ne!(schedule(20, 5, 1), schedule(20, 5, 2));
+    }
+
+    #[test]
+    fn test_port_allocator() {
+        let mut allocator = PortAllocator::new(9000..9010);
+        let first = allocator.allocate(4, |_| true).unwrap();
+        assert_eq!(first, [9000, 9001, 9002, 9003]);
+        let second = allocator.allocate(4, |port| port != 9005).unwrap();
+        assert_eq!(second, [9004, 9006, 9007, 9008]);
+
+        // Rejected ports are not remembered.
+        assert_eq!(allocator.allocate(3, |_| true), None);
+        assert_eq!(allocator.allocate(2, |port| port != 9005), None);
+        assert_eq!(allocator.allocate(1, |port| port != 9005), Some(vec![9009]));
+
+        allocator.release(&first);
+        assert_eq!(
+            allocator.allocate(3, |_| true),
+            Some(vec![9000, 9001, 9002])
+        );
+        assert_eq!(allocator.allocate(2, |_| true), Some(vec![9003, 9005]));
+        assert_eq!(allocator.allocate(1, |_| true), None);
+    }
+
+    #[test]
+    fn test_standings() {
+        let names = names(&["alice", "bob", "carol", "dave", "eve"]);
+        let games = [
+            GameRecord {
+                players: vec![0, 1, 2, 3],
+                scores: vec![Some(10), Some(30), Some(20), Some(20)],
+            },
+            GameRecord {
+                players: vec![4, 0, 1],
+                scores: vec![None, Some(5), Some(7)],
+            },
+        ];
+        let standings = standings(&names, &games);
+
+        let summary = standings
+            .iter()
+            .map(|s| {
+                (
+                    s.name.as_str(),
+                    s.points,
+                    s.total_score,
+                    s.games,
+                    s.failures,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("bob", 5, 37, 2, 0),
+                ("carol", 1, 20, 1, 0),
+                ("dave", 1, 20, 1, 0),
+                ("alice", 1, 15, 2, 0),
+                ("eve", 0, 0, 1, 1),
+            ]
+        );
+
+        let table = render_standings(&standings);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "  #  strategy  points    score  games  failures");
+        assert_eq!(lines[1], "  1  bob            5       37      2         0");
+    }
+
+    #[test]
+    fn test_parse_scores() {
+        let stdout = "\
+            Winner is Player #2! (by score)\n\
+            Player #1: score 12, lost (trace crossed)\n\
+            Player #2: score 40\n\
+            Player #3: score 7, lost (forfeited)\n\
+            Player #4: score 3, io error (broken pipe)\n";
+        assert_eq!(
+            parse_scores(stdout, 4),
+            Some(vec![Some(12), Some(40), None, None])
+        );
+        assert_eq!(parse_scores(stdout, 2), Some(vec![Some(12), Some(40)]));
+        assert_eq!(parse_scores("There is no winner (tie)\n", 1), None);
+        assert_eq!(parse_scores("Player #1: score 5\n", 2), None);
+    }
+
+    #[test]
+    #[ignore = "builds and runs the server and the wasm launcher"]
+    fn test_bots_tournament() {
+        let report_dir = tempfile::tempdir().unwrap();
+        let report = report_dir.path().join("report.json");
+        let args = TournamentArgs {
+            strategies_dir: get_cwd_task_path().unwrap().join("bots"),
+            games: 2,
+            seed: 0,
+            parallel: 2,
+            first_port: 9100,
+            tick_count: 100,
+            tick_budget_ms: None,
+            game_timeout_secs: 120,
+            report: report.clone(),
+        };
+        run(args, false).unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_reader(fs::File::open(report).unwrap()).unwrap();
+        let standings = report["standings"].as_array().unwrap();
+        assert_eq!(standings.len(), 3);
+        for standing in standings {
+            assert_eq!(standing["games"], 2);
+            assert_eq!(standing["failures"], 0);
+        }
+        assert_eq!(report["games"].as_array().unwrap().len(), 2);
+    }
+}
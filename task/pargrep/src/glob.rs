@@ -0,0 +1,78 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`, any character but `/`.
+    AnyChar,
+    /// `*`, any characters but `/`.
+    Star,
+    /// `**`, any characters.
+    AnyPath,
+    /// `**/`, nothing or any characters ending with `/`, i.e. any directories.
+    AnyDirectories,
+}
+
+/// A glob over paths relative to the searched directory, with `/` as the separator.
+/// A glob without `/` matches the file name at any depth instead.
+pub(crate) struct Glob {
+    tokens: Vec<Token>,
+    matches_name: bool,
+}
+
+impl Glob {
+    pub(crate) fn new(glob: &str) -> Self {
+        let glob = glob.strip_prefix("./").unwrap_or(glob);
+        let mut tokens = vec![];
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::AnyChar,
+                '*' => match chars.next_if_eq(&'*') {
+                    None => Token::Star,
+                    Some(_) if chars.next_if_eq(&'/').is_some() => Token::AnyDirectories,
+                    Some(_) => Token::AnyPath,
+                },
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        Self {
+            matches_name: !glob.contains('/'),
+            tokens,
+        }
+    }
+
+    pub(crate) fn matches_any(globs: &[Glob], relative_path: &str) -> bool {
+        globs.iter().any(|glob| glob.is_match(relative_path))
+    }
+
+    pub(crate) fn is_match(&self, relative_path: &str) -> bool {
+        let text = match self.matches_name {
+            true => relative_path.rsplit('/').next().unwrap_or(relative_path),
+            false => relative_path,
+        };
+        let text = text.chars().collect::<Vec<_>>();
+
+        // is_match[j] tells whether the tokens matched so far can end right before
+        // text[j], a row of the usual dynamic programming table at a time.
+        let mut is_match = vec![false; text.len() + 1];
+        is_match[0] = true;
+        for token in &self.tokens {
+            let mut next = vec![false; text.len() + 1];
+            for j in 0..=text.len() {
+                next[j] = match token {
+                    Token::Char(c) => j > 0 && is_match[j - 1] && text[j - 1] == *c,
+                    Token::AnyChar => j > 0 && is_match[j - 1] && text[j - 1] != '/',
+                    Token::Star => is_match[j] || (j > 0 && next[j - 1] && text[j - 1] != '/'),
+                    Token::AnyPath => is_match[j] || (j > 0 && next[j - 1]),
+                    Token::AnyDirectories => {
+                        is_match[j]
+                            || (j > 0 && text[j - 1] == '/' && is_match[..j].contains(&true))
+                    }
+                };
+            }
+            is_match = next;
+        }
+        is_match[text.len()]
+    }
+}
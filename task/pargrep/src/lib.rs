@@ -1,10 +1,23 @@
 #![forbid(unsafe_code)]
 
+mod glob;
+mod pattern;
+
+pub use pattern::{PatternError, PatternErrorKind};
+
+use glob::Glob;
+use pattern::Matcher;
 use rayon::prelude::*;
 use std::{
-    fs::{read_dir, File},
+    collections::HashSet,
+    fs::{read_dir, DirEntry, File},
     io::{BufRead, BufReader},
+    ops::Range,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -14,6 +27,10 @@ pub struct Match {
     pub path: PathBuf,
     pub line: String,
     pub line_number: usize,
+    /// 1-based byte column of the first match in `line`.
+    pub column: usize,
+    /// Byte ranges of all the non-overlapping matches in `line`, left to right.
+    pub spans: Vec<Range<usize>>,
 }
 
 #[derive(Debug)]
@@ -25,56 +42,286 @@ pub struct Error {
 pub enum Event {
     Match(Match),
     Error(Error),
+    /// A binary file skipped with `BinaryMode::Skip`.
+    Binary(PathBuf),
+}
+
+/// Files with a NUL byte in their first chunk are binary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryMode {
+    #[default]
+    Skip,
+    /// Search binary files as text, with invalid UTF-8 replaced by `U+FFFD`.
+    Lossy,
+}
+
+/// Globs match paths relative to the searched directory, with `/` as the separator:
+/// `*` and `?` don't match `/`, `**` matches anything and `**/` any directories. A glob
+/// without `/` matches file names at any depth, e.g. `target` or `*.rs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Treat the pattern as a regular expression rather than a substring. Supported are
+    /// literals, `.`, classes like `[a-z_]` and `[^0-9]`, `\d`, `\w`, `\s` and their
+    /// negations, `\b`, anchors `^` and `$`, and quantifiers `*`, `+` and `?`.
+    pub regex: bool,
+    pub ignore_case: bool,
+    /// Only match whole words, i.e. not preceded nor followed by alphanumerics or `_`.
+    pub whole_word: bool,
+    pub binary: BinaryMode,
+    /// Stop searching, not just reporting, once this many matches are found.
+    pub max_matches: Option<usize>,
+    /// Only search the files matching any of these globs, all files if empty.
+    pub include_globs: Vec<String>,
+    /// Skip the files and directories matching any of these globs. Excluded directories
+    /// aren't even listed.
+    pub exclude_globs: Vec<String>,
+    /// Only search this many levels below the searched directory, e.g. 1 for its own
+    /// files only.
+    pub max_depth: Option<usize>,
+    /// Follow symbolic links below the searched directory, which is always followed.
+    /// A directory reachable by several paths, e.g. through a loop, is searched once.
+    pub follow_symlinks: bool,
 }
 
+/// A search shared by the workers.
+struct Search<F> {
+    matcher: Matcher,
+    binary: BinaryMode,
+    max_matches: Option<usize>,
+    include_globs: Vec<Glob>,
+    exclude_globs: Vec<Glob>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    /// Canonical paths of the directories listed already, only if following symlinks.
+    visited: Mutex<HashSet<PathBuf>>,
+    match_count: AtomicUsize,
+    is_stopped: AtomicBool,
+    on_event: F,
+}
+
+impl<F: Fn(Event) + Sync> Search<F> {
+    fn new(matcher: Matcher, options: &SearchOptions, on_event: F) -> Self {
+        Self {
+            matcher,
+            binary: options.binary,
+            max_matches: options.max_matches,
+            include_globs: options.include_globs.iter().map(|g| Glob::new(g)).collect(),
+            exclude_globs: options.exclude_globs.iter().map(|g| Glob::new(g)).collect(),
+            max_depth: options.max_depth,
+            follow_symlinks: options.follow_symlinks,
+            visited: Mutex::new(HashSet::new()),
+            match_count: AtomicUsize::new(0),
+            is_stopped: AtomicBool::new(options.max_matches == Some(0)),
+            on_event,
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.is_stopped.load(Ordering::Relaxed)
+    }
+
+    fn emit(&self, event: Event) {
+        if let (Event::Match(_), Some(max_matches)) = (&event, self.max_matches) {
+            // Other workers may find matches concurrently, only the first ones count.
+            let count = self.match_count.fetch_add(1, Ordering::Relaxed) + 1;
+            if count >= max_matches {
+                self.is_stopped.store(true, Ordering::Relaxed);
+            }
+            if count > max_matches {
+                return;
+            }
+        }
+        (self.on_event)(event);
+    }
+
+    fn emit_error(&self, path: &Path, error: std::io::Error) {
+        self.emit(Event::Error(Error {
+            path: path.to_path_buf(),
+            error,
+        }))
+    }
+
+    /// Returns `false` if the directory is listed already.
+    fn visit(&self, path: &Path) -> bool {
+        if !self.follow_symlinks {
+            return true;
+        }
+        match path.canonicalize() {
+            Ok(path) => self.visited.lock().unwrap().insert(path),
+            Err(err) => {
+                self.emit_error(path, err);
+                false
+            }
+        }
+    }
+}
+
+/// Follows symlinks, see `SearchOptions::follow_symlinks`.
 pub fn run<P: AsRef<Path>>(path: P, pattern: &str) -> Vec<Event> {
-    let path = path.as_ref();
-    process(path, pattern)
+    collect(|on_event| {
+        let options = SearchOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        process(
+            path.as_ref(),
+            &Search::new(Matcher::substring(pattern), &options, on_event),
+        )
+    })
 }
 
-fn process(path: &Path, pattern: &str) -> Vec<Event> {
+/// Fails if the pattern is not a valid regular expression, before searching anything.
+pub fn run_with_options<P: AsRef<Path>>(
+    path: P,
+    pattern: &str,
+    options: &SearchOptions,
+) -> Result<Vec<Event>, PatternError> {
+    let matcher = Matcher::new(pattern, options)?;
+    Ok(collect(|on_event| {
+        process(path.as_ref(), &Search::new(matcher, options, on_event))
+    }))
+}
+
+/// Calls `on_event` as soon as an event happens, from the worker threads, and returns
+/// once the search is over. Events come in no particular order, not even the matches
+/// of a single file: only `line_number` tells which line is which.
+///
+/// Fails if the pattern is not a valid regular expression, before searching anything.
+pub fn run_streaming<P, F>(
+    path: P,
+    pattern: &str,
+    options: &SearchOptions,
+    on_event: F,
+) -> Result<(), PatternError>
+where
+    P: AsRef<Path>,
+    F: Fn(Event) + Sync,
+{
+    let matcher = Matcher::new(pattern, options)?;
+    process(path.as_ref(), &Search::new(matcher, options, on_event));
+    Ok(())
+}
+
+fn collect(search: impl FnOnce(&(dyn Fn(Event) + Sync))) -> Vec<Event> {
+    let events = Mutex::new(vec![]);
+    search(&|event| events.lock().unwrap().push(event));
+    events.into_inner().unwrap()
+}
+
+fn process<F: Fn(Event) + Sync>(path: &Path, search: &Search<F>) {
+    if search.is_stopped() {
+        return;
+    }
     if path.is_file() {
-        process_file(path, pattern)
-    } else {
-        process_directory(path, pattern)
+        process_file(path, search)
+    } else if search.visit(path) {
+        process_directory(path, "", 0, search)
     }
 }
 
-fn process_file(path: &Path, pattern: &str) -> Vec<Event> {
-    match File::open(path) {
-        Ok(file) => BufReader::new(file)
-            .lines()
-            .enumerate()
-            .flat_map(|(line_number, line)| match line {
-                Ok(line) if line.contains(pattern) => Some(Event::Match(Match {
-                    path: path.to_path_buf(),
-                    line,
-                    line_number: line_number + 1,
-                })),
-                Ok(_) => None,
-                Err(err) => Some(Event::Error(Error {
-                    path: path.to_path_buf(),
-                    error: err,
-                })),
-            })
-            .collect(),
-        Err(err) => vec![Event::Error(Error {
-            path: path.to_path_buf(),
-            error: err,
-        })],
+fn process_file<F: Fn(Event) + Sync>(path: &Path, search: &Search<F>) {
+    let mut reader = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(err) => return search.emit_error(path, err),
+    };
+    match reader.fill_buf() {
+        Ok(chunk) if search.binary == BinaryMode::Skip && chunk.contains(&0) => {
+            return search.emit(Event::Binary(path.to_path_buf()));
+        }
+        Ok(_) => {}
+        Err(err) => return search.emit_error(path, err),
+    }
+
+    let mut buffer = vec![];
+    for line_number in 1.. {
+        if search.is_stopped() {
+            return;
+        }
+
+        buffer.clear();
+        match reader.read_until(b'\n', &mut buffer) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(err) => return search.emit_error(path, err),
+        }
+
+        // Only invalid lines are copied.
+        let line = String::from_utf8_lossy(strip_line_ending(&buffer));
+        let spans = search.matcher.find_iter(&line);
+        if let Some(first) = spans.first() {
+            search.emit(Event::Match(Match {
+                path: path.to_path_buf(),
+                column: first.start + 1,
+                line: line.into_owned(),
+                line_number,
+                spans,
+            }));
+        }
     }
 }
 
-fn process_directory(path: &Path, pattern: &str) -> Vec<Event> {
+/// Strips `\n` or `\r\n`, as `BufRead::lines` does.
+fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// `relative_path` and `depth` are relative to the searched directory.
+fn process_directory<F: Fn(Event) + Sync>(
+    path: &Path,
+    relative_path: &str,
+    depth: usize,
+    search: &Search<F>,
+) {
     match read_dir(path) {
         Ok(read_dir) => read_dir
             .filter_map(Result::ok)
+            // Stops listing the directory too.
+            .take_while(|_| !search.is_stopped())
             .par_bridge()
-            .flat_map(|dir_entry| process(&dir_entry.path(), pattern))
-            .collect(),
-        Err(err) => vec![Event::Error(Error {
-            path: path.to_path_buf(),
-            error: err,
-        })],
+            .for_each(|dir_entry| process_entry(&dir_entry, relative_path, depth + 1, search)),
+        Err(err) => search.emit_error(path, err),
+    }
+}
+
+fn process_entry<F: Fn(Event) + Sync>(
+    dir_entry: &DirEntry,
+    parent_path: &str,
+    depth: usize,
+    search: &Search<F>,
+) {
+    let path = dir_entry.path();
+    let name = dir_entry.file_name();
+    let relative_path = match parent_path {
+        "" => name.to_string_lossy().into_owned(),
+        parent_path => format!("{parent_path}/{}", name.to_string_lossy()),
+    };
+    if Glob::matches_any(&search.exclude_globs, &relative_path) {
+        return;
+    }
+
+    // Unlike `Path::metadata`, doesn't follow symlinks and mostly needs no syscall.
+    let file_type = match dir_entry.file_type() {
+        Ok(file_type) if !file_type.is_symlink() => file_type,
+        Ok(_) if !search.follow_symlinks => return,
+        Ok(_) => match path.metadata() {
+            Ok(metadata) => metadata.file_type(),
+            Err(err) => return search.emit_error(&path, err),
+        },
+        Err(err) => return search.emit_error(&path, err),
+    };
+
+    if file_type.is_dir() {
+        // Its entries would be too deep.
+        if search.max_depth.is_some_and(|max_depth| depth >= max_depth) || !search.visit(&path) {
+            return;
+        }
+        process_directory(&path, &relative_path, depth, search)
+    } else if file_type.is_file()
+        && search.max_depth.is_none_or(|max_depth| depth <= max_depth)
+        && (search.include_globs.is_empty()
+            || Glob::matches_any(&search.include_globs, &relative_path))
+    {
+        process_file(&path, search)
     }
 }
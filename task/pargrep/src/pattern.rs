@@ -0,0 +1,521 @@
+use crate::SearchOptions;
+
+use std::{error, fmt, iter::Peekable, ops::Range, str::CharIndices};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    /// Byte offset in the pattern.
+    pub position: usize,
+    pub kind: PatternErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternErrorKind {
+    UnclosedClass,
+    EmptyClass,
+    InvalidRange,
+    NothingToRepeat,
+    TrailingBackslash,
+    /// Groups and alternation aren't supported, escape the character to match it.
+    Unsupported(char),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            PatternErrorKind::UnclosedClass => write!(f, "unclosed character class")?,
+            PatternErrorKind::EmptyClass => write!(f, "empty character class")?,
+            PatternErrorKind::InvalidRange => write!(f, "invalid range in character class")?,
+            PatternErrorKind::NothingToRepeat => write!(f, "quantifier follows nothing")?,
+            PatternErrorKind::TrailingBackslash => write!(f, "trailing backslash")?,
+            PatternErrorKind::Unsupported(c) => write!(f, "unsupported '{c}', escape it")?,
+        }
+        write!(f, " at offset {}", self.position)
+    }
+}
+
+impl error::Error for PatternError {}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A pattern compiled once and shared between the workers.
+pub(crate) enum Matcher {
+    Substring {
+        pattern: String,
+        ignore_case: bool,
+        whole_word: bool,
+    },
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub(crate) fn substring(pattern: &str) -> Self {
+        Self::Substring {
+            pattern: pattern.to_owned(),
+            ignore_case: false,
+            whole_word: false,
+        }
+    }
+
+    pub(crate) fn new(pattern: &str, options: &SearchOptions) -> Result<Self, PatternError> {
+        if options.regex {
+            return Regex::new(pattern, options).map(Self::Regex);
+        }
+        Ok(Self::Substring {
+            pattern: pattern.to_owned(),
+            ignore_case: options.ignore_case,
+            whole_word: options.whole_word,
+        })
+    }
+
+    /// Finds the first match starting at `from` or later.
+    pub(crate) fn find_at(&self, line: &str, from: usize) -> Option<Range<usize>> {
+        match self {
+            Self::Substring {
+                pattern,
+                ignore_case: false,
+                whole_word: false,
+            } => line[from..]
+                .find(pattern.as_str())
+                .map(|start| from + start..from + start + pattern.len()),
+            Self::Substring {
+                pattern,
+                ignore_case,
+                whole_word,
+            } => line[from..]
+                .char_indices()
+                .map(|(start, _)| from + start)
+                .chain([line.len()])
+                .find_map(|start| {
+                    let end = if *ignore_case {
+                        start + match_ignore_case(&line[start..], pattern)?
+                    } else {
+                        line[start..]
+                            .starts_with(pattern.as_str())
+                            .then_some(start + pattern.len())?
+                    };
+                    (!*whole_word || is_whole_word(line, start, end)).then_some(start..end)
+                }),
+            Self::Regex(regex) => regex.find_at(line, from),
+        }
+    }
+
+    /// All the non-overlapping matches, left to right. Empty matches are included, as
+    /// for the empty pattern, except right after another match, as in the `regex` crate.
+    pub(crate) fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        let mut spans = Vec::<Range<usize>>::new();
+        let mut from = 0;
+        while from <= line.len() {
+            let Some(span) = self.find_at(line, from) else {
+                break;
+            };
+            if !span.is_empty() {
+                from = span.end;
+            } else {
+                from = span.end + line[span.end..].chars().next().map_or(1, char::len_utf8);
+                if spans.last().is_some_and(|last| last.end == span.start) {
+                    continue;
+                }
+            }
+            spans.push(span);
+        }
+        spans
+    }
+}
+
+/// Returns the length of the prefix of `haystack` equal to `needle` up to case.
+fn match_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    let mut haystack_chars = haystack.char_indices();
+    for needle_char in needle.chars() {
+        let (_, c) = haystack_chars.next()?;
+        if !eq_ignore_case(c, needle_char) {
+            return None;
+        }
+    }
+    Some(haystack_chars.next().map_or(haystack.len(), |(i, _)| i))
+}
+
+/// Simple char-by-char case folding, never allocates.
+fn eq_ignore_case(lhs: char, rhs: char) -> bool {
+    lhs == rhs || lhs.to_lowercase().eq(rhs.to_lowercase())
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_whole_word(line: &str, start: usize, end: usize) -> bool {
+    let before = line[..start].chars().next_back();
+    let after = line[end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A small regular expression: literals, `.`, classes like `[a-z_]` and `[^0-9]`,
+/// escapes `\d`, `\w`, `\s` (and their negations `\D`, `\W`, `\S`), the word boundary
+/// `\b`, anchors `^` and `$`, and quantifiers `*`, `+` and `?`.
+///
+/// Lines are matched by simulating all the possible positions in the pattern at once,
+/// so matching takes O(line * pattern) time whatever the pattern is.
+pub(crate) struct Regex {
+    items: Vec<Item>,
+    ignore_case: bool,
+}
+
+struct Item {
+    kind: ItemKind,
+    repeat: Repeat,
+}
+
+enum ItemKind {
+    Char(CharMatcher),
+    Assertion(Assertion),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Repeat {
+    One,
+    Optional,
+    Star,
+}
+
+#[derive(Clone)]
+enum CharMatcher {
+    Literal(char),
+    Any,
+    Class {
+        negated: bool,
+        ranges: Vec<ClassRange>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum ClassRange {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+#[derive(Clone, Copy)]
+enum Assertion {
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NoWordBefore,
+    NoWordAfter,
+}
+
+impl ClassRange {
+    fn contains(&self, c: char) -> bool {
+        match *self {
+            Self::Range(from, to) => (from..=to).contains(&c),
+            Self::Digit(negated) => c.is_ascii_digit() != negated,
+            Self::Word(negated) => is_word_char(c) != negated,
+            Self::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+impl CharMatcher {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        match self {
+            Self::Literal(literal) if ignore_case => eq_ignore_case(*literal, c),
+            Self::Literal(literal) => *literal == c,
+            Self::Any => true,
+            Self::Class { negated, ranges } => {
+                let contains = |c: char| ranges.iter().any(|range| range.contains(c));
+                let found = contains(c)
+                    || ignore_case
+                        && (c.to_lowercase().any(contains) || c.to_uppercase().any(contains));
+                found != *negated
+            }
+        }
+    }
+}
+
+impl Assertion {
+    fn holds(self, before: Option<char>, after: Option<char>) -> bool {
+        let word_before = before.is_some_and(is_word_char);
+        let word_after = after.is_some_and(is_word_char);
+        match self {
+            Self::LineStart => before.is_none(),
+            Self::LineEnd => after.is_none(),
+            Self::WordBoundary => word_before != word_after,
+            Self::NoWordBefore => !word_before,
+            Self::NoWordAfter => !word_after,
+        }
+    }
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str, options: &SearchOptions) -> Result<Self, PatternError> {
+        let mut items = Parser::new(pattern).parse()?;
+        if options.whole_word {
+            let assertion = |assertion| Item {
+                kind: ItemKind::Assertion(assertion),
+                repeat: Repeat::One,
+            };
+            items.insert(0, assertion(Assertion::NoWordBefore));
+            items.push(assertion(Assertion::NoWordAfter));
+        }
+        Ok(Self {
+            items,
+            ignore_case: options.ignore_case,
+        })
+    }
+
+    /// Finds the leftmost match starting at `from` or later, the longest of them.
+    ///
+    /// Every state remembers where its match started. States are ordered by that, so
+    /// a state reached by several paths keeps the leftmost start.
+    pub(crate) fn find_at(&self, line: &str, from: usize) -> Option<Range<usize>> {
+        let accept = self.items.len();
+        let mut current = StateSet::new(accept + 1);
+        let mut next = StateSet::new(accept + 1);
+        let mut best: Option<Range<usize>> = None;
+
+        let mut before = line[..from].chars().next_back();
+        let mut chars = line[from..]
+            .char_indices()
+            .map(|(i, c)| (from + i, c))
+            .peekable();
+        loop {
+            let position = chars.peek().map_or(line.len(), |&(i, _)| i);
+            // Until something is found, a match may start anywhere.
+            if best.is_none() {
+                let after = chars.peek().map(|&(_, c)| c);
+                self.add_state(&mut current, 0, position, before, after);
+            }
+            if let Some(start) = current.start_of(accept) {
+                best = Some(start..position);
+            }
+
+            let Some((_, c)) = chars.next() else {
+                return best;
+            };
+            let after = chars.peek().map(|&(_, c)| c);
+            for &(state, start) in &current.states {
+                // Matches starting later than the found one don't matter.
+                if best.as_ref().is_some_and(|best| start > best.start) {
+                    continue;
+                }
+                let Some(Item {
+                    kind: ItemKind::Char(matcher),
+                    repeat,
+                }) = self.items.get(state)
+                else {
+                    continue;
+                };
+                if matcher.matches(c, self.ignore_case) {
+                    let next_state = if *repeat == Repeat::Star {
+                        state
+                    } else {
+                        state + 1
+                    };
+                    self.add_state(&mut next, next_state, start, Some(c), after);
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            before = Some(c);
+            if best.is_some() && current.states.is_empty() {
+                return best;
+            }
+        }
+    }
+
+    /// Adds the state and all the states reachable from it without consuming a char.
+    fn add_state(
+        &self,
+        set: &mut StateSet,
+        state: usize,
+        start: usize,
+        before: Option<char>,
+        after: Option<char>,
+    ) {
+        if !set.insert(state, start) {
+            return;
+        }
+        let Some(item) = self.items.get(state) else {
+            return;
+        };
+        let skips = match item.kind {
+            ItemKind::Assertion(assertion) => assertion.holds(before, after),
+            ItemKind::Char(_) => item.repeat != Repeat::One,
+        };
+        if skips {
+            self.add_state(set, state + 1, start, before, after);
+        }
+    }
+}
+
+/// States with the starts of their matches, in the order of insertion.
+struct StateSet {
+    states: Vec<(usize, usize)>,
+    starts: Vec<Option<usize>>,
+}
+
+impl StateSet {
+    fn new(len: usize) -> Self {
+        Self {
+            states: Vec::with_capacity(len),
+            starts: vec![None; len],
+        }
+    }
+
+    fn insert(&mut self, state: usize, start: usize) -> bool {
+        if self.starts[state].is_some() {
+            return false;
+        }
+        self.starts[state] = Some(start);
+        self.states.push((state, start));
+        true
+    }
+
+    fn start_of(&self, state: usize) -> Option<usize> {
+        self.starts[state]
+    }
+
+    fn clear(&mut self) {
+        for &(state, _) in &self.states {
+            self.starts[state] = None;
+        }
+        self.states.clear();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(pattern: &'a str) -> Self {
+        Self {
+            chars: pattern.char_indices().peekable(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<Item>, PatternError> {
+        let mut items = Vec::<Item>::new();
+        while let Some((position, c)) = self.chars.next() {
+            let kind = match c {
+                '*' | '+' | '?' => {
+                    let Some(Item {
+                        kind: ItemKind::Char(matcher),
+                        repeat: Repeat::One,
+                    }) = items.last()
+                    else {
+                        return Err(error(position, PatternErrorKind::NothingToRepeat));
+                    };
+                    match c {
+                        '*' => items.last_mut().unwrap().repeat = Repeat::Star,
+                        '?' => items.last_mut().unwrap().repeat = Repeat::Optional,
+                        _ => {
+                            // `x+` is `xx*`.
+                            let kind = ItemKind::Char(matcher.clone());
+                            items.push(Item {
+                                kind,
+                                repeat: Repeat::Star,
+                            });
+                        }
+                    }
+                    continue;
+                }
+                '^' if position == 0 => ItemKind::Assertion(Assertion::LineStart),
+                '$' if self.chars.peek().is_none() => ItemKind::Assertion(Assertion::LineEnd),
+                '.' => ItemKind::Char(CharMatcher::Any),
+                '[' => ItemKind::Char(self.parse_class(position)?),
+                '\\' => self.parse_escape(position)?,
+                '(' | ')' | '|' => {
+                    return Err(error(position, PatternErrorKind::Unsupported(c)));
+                }
+                c => ItemKind::Char(CharMatcher::Literal(c)),
+            };
+            items.push(Item {
+                kind,
+                repeat: Repeat::One,
+            });
+        }
+        Ok(items)
+    }
+
+    fn parse_escape(&mut self, position: usize) -> Result<ItemKind, PatternError> {
+        let Some((_, c)) = self.chars.next() else {
+            return Err(error(position, PatternErrorKind::TrailingBackslash));
+        };
+        let kind = match c {
+            'b' => ItemKind::Assertion(Assertion::WordBoundary),
+            c => match class_escape(c) {
+                Some(range) => ItemKind::Char(CharMatcher::Class {
+                    negated: false,
+                    ranges: vec![range],
+                }),
+                None => ItemKind::Char(CharMatcher::Literal(c)),
+            },
+        };
+        Ok(kind)
+    }
+
+    fn parse_class(&mut self, start: usize) -> Result<CharMatcher, PatternError> {
+        let negated = self.chars.next_if(|&(_, c)| c == '^').is_some();
+        let mut ranges = vec![];
+        loop {
+            let Some((position, c)) = self.chars.next() else {
+                return Err(error(start, PatternErrorKind::UnclosedClass));
+            };
+            let from = match c {
+                ']' if ranges.is_empty() => {
+                    return Err(error(start, PatternErrorKind::EmptyClass));
+                }
+                ']' => break,
+                '\\' => {
+                    let Some((_, c)) = self.chars.next() else {
+                        return Err(error(position, PatternErrorKind::TrailingBackslash));
+                    };
+                    if let Some(range) = class_escape(c) {
+                        ranges.push(range);
+                        continue;
+                    }
+                    c
+                }
+                c => c,
+            };
+
+            // A trailing `-` is a literal, as in `[a-]`.
+            let mut lookahead = self.chars.clone().map(|(_, c)| c);
+            let is_range =
+                lookahead.next() == Some('-') && lookahead.next().is_some_and(|c| c != ']');
+            if !is_range {
+                ranges.push(ClassRange::Range(from, from));
+                continue;
+            }
+            self.chars.next();
+            let (to_position, to) = self.chars.next().unwrap();
+            if to < from {
+                return Err(error(to_position, PatternErrorKind::InvalidRange));
+            }
+            ranges.push(ClassRange::Range(from, to));
+        }
+        Ok(CharMatcher::Class { negated, ranges })
+    }
+}
+
+fn class_escape(c: char) -> Option<ClassRange> {
+    match c {
+        'd' | 'D' => Some(ClassRange::Digit(c == 'D')),
+        'w' | 'W' => Some(ClassRange::Word(c == 'W')),
+        's' | 'S' => Some(ClassRange::Space(c == 'S')),
+        _ => None,
+    }
+}
+
+fn error(position: usize, kind: PatternErrorKind) -> PatternError {
+    PatternError { position, kind }
+}
@@ -1,9 +1,14 @@
+// Single-range `vec![(a..b)]` spans are meant literally, not as a collected range.
+#![allow(clippy::single_range_in_vec_init)]
+
 use tempdir::TempDir;
 
 use std::{
     fs,
     io::{self, BufRead, BufReader, BufWriter, Write},
+    ops::Range,
     path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
     time::{Duration, Instant},
 };
 
@@ -58,6 +63,7 @@ fn test_file() {
         .map(|ev| match ev {
             pargrep::Event::Match(m) => m,
             pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+            pargrep::Event::Binary(path) => panic!("unexpected binary file: {:?}", path),
         })
         .collect::<Vec<_>>();
 
@@ -71,16 +77,22 @@ fn test_file() {
                 path: path.to_path_buf(),
                 line: "Feed'st thy light'st flame with self-substantial fuel,".into(),
                 line_number: 6,
+                column: 9,
+                spans: vec![(8..11)],
             },
             pargrep::Match {
                 path: path.to_path_buf(),
                 line: "Thyself thy foe, to thy sweet self too cruel.".into(),
                 line_number: 8,
+                column: 9,
+                spans: vec![8..11, 20..23],
             },
             pargrep::Match {
                 path: path.to_path_buf(),
                 line: "Within thine own bud buriest thy content".into(),
                 line_number: 11,
+                column: 30,
+                spans: vec![(29..32)],
             },
         ]
     );
@@ -118,6 +130,7 @@ fn test_tree() {
         .map(|ev| match ev {
             pargrep::Event::Match(m) => m,
             pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+            pargrep::Event::Binary(path) => panic!("unexpected binary file: {:?}", path),
         })
         .collect::<Vec<_>>();
 
@@ -148,9 +161,545 @@ fn test_error() {
         pargrep::Event::Error(error) => {
             assert_eq!(error.path.to_str().unwrap(), path);
         }
+        pargrep::Event::Binary(path) => panic!("unexpected binary file: {:?}", path),
     }
 }
 
+fn find_matches(
+    text: impl AsRef<[u8]>,
+    pattern: &str,
+    options: &pargrep::SearchOptions,
+) -> Vec<pargrep::Match> {
+    let tmp_dir = TempDir::new("pargrep").unwrap();
+    let path = tmp_dir.path().join("text");
+    fs::write(&path, text).unwrap();
+
+    let events = pargrep::run_with_options(&path, pattern, options).unwrap();
+    let mut matches = events
+        .into_iter()
+        .map(|ev| match ev {
+            pargrep::Event::Match(m) => m,
+            pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+            pargrep::Event::Binary(path) => panic!("unexpected binary file: {:?}", path),
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|m| m.line_number);
+    matches
+}
+
+fn matching_lines(text: &str, pattern: &str, options: &pargrep::SearchOptions) -> Vec<String> {
+    find_matches(text, pattern, options)
+        .into_iter()
+        .map(|m| m.line)
+        .collect()
+}
+
+fn matching_spans(
+    text: &str,
+    pattern: &str,
+    options: &pargrep::SearchOptions,
+) -> Vec<(usize, Vec<Range<usize>>)> {
+    find_matches(text, pattern, options)
+        .into_iter()
+        .map(|m| {
+            assert_eq!(m.column, m.spans[0].start + 1);
+            (m.line_number, m.spans)
+        })
+        .collect()
+}
+
+const TEXT: &str = "Rust is fast\n\
+    rusty nails\n\
+    TRUST me\n\
+    Ärger und ärgern\n\
+    snake_case rust_lang\n\
+    version 1.75, edition 2021\n";
+
+#[test]
+fn test_ignore_case() {
+    let options = pargrep::SearchOptions {
+        ignore_case: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_lines(TEXT, "rust", &options),
+        [
+            "Rust is fast",
+            "rusty nails",
+            "TRUST me",
+            "snake_case rust_lang"
+        ]
+    );
+    assert_eq!(
+        matching_lines(TEXT, "ÄRGERN", &options),
+        ["Ärger und ärgern"]
+    );
+
+    let options = pargrep::SearchOptions::default();
+    assert_eq!(
+        matching_lines(TEXT, "rust", &options),
+        ["rusty nails", "snake_case rust_lang"]
+    );
+}
+
+#[test]
+fn test_whole_word() {
+    let options = pargrep::SearchOptions {
+        whole_word: true,
+        ..Default::default()
+    };
+    assert_eq!(matching_lines(TEXT, "rust", &options), Vec::<String>::new());
+    assert_eq!(
+        matching_lines(TEXT, "rust_lang", &options),
+        ["snake_case rust_lang"]
+    );
+    assert_eq!(
+        matching_lines(TEXT, "1.75", &options),
+        ["version 1.75, edition 2021"]
+    );
+    assert_eq!(matching_lines(TEXT, "1.7", &options), Vec::<String>::new());
+    // The second occurrence is a whole word even though the first one is not.
+    assert_eq!(
+        matching_lines("ärgern ärger", "ärger", &options),
+        ["ärgern ärger"]
+    );
+
+    let options = pargrep::SearchOptions {
+        whole_word: true,
+        ignore_case: true,
+        ..Default::default()
+    };
+    assert_eq!(matching_lines(TEXT, "rust", &options), ["Rust is fast"]);
+
+    let options = pargrep::SearchOptions {
+        regex: true,
+        whole_word: true,
+        ..Default::default()
+    };
+    assert_eq!(matching_lines(TEXT, "r[a-z]+", &options), ["rusty nails"]);
+    assert_eq!(
+        matching_lines(TEXT, r"\d+", &options),
+        ["version 1.75, edition 2021"]
+    );
+    assert!(matching_lines(TEXT, r"st\b", &options).is_empty());
+
+    let options = pargrep::SearchOptions {
+        regex: true,
+        ..Default::default()
+    };
+    assert_eq!(matching_lines(TEXT, r"st\b", &options), ["Rust is fast"]);
+    assert_eq!(
+        matching_lines(TEXT, r"\bru", &options),
+        ["rusty nails", "snake_case rust_lang"]
+    );
+}
+
+#[test]
+fn test_regex() {
+    let options = pargrep::SearchOptions {
+        regex: true,
+        ..Default::default()
+    };
+    assert_eq!(matching_lines(TEXT, "^[A-Z]+ ", &options), ["TRUST me"]);
+    assert_eq!(
+        matching_lines(TEXT, r"\d\.\d+,", &options),
+        ["version 1.75, edition 2021"]
+    );
+    assert_eq!(matching_lines(TEXT, "s$", &options), ["rusty nails"]);
+    assert_eq!(
+        matching_lines(TEXT, "ru?st.*_", &options),
+        ["snake_case rust_lang"]
+    );
+    assert_eq!(matching_lines(TEXT, "[^a-z ]S", &options), ["TRUST me"]);
+    // Metacharacters are literals in substring mode.
+    assert_eq!(
+        matching_lines(TEXT, "1.75", &Default::default()),
+        ["version 1.75, edition 2021"]
+    );
+    assert_eq!(
+        matching_lines(TEXT, "1.7.", &Default::default()),
+        Vec::<String>::new()
+    );
+
+    let options = pargrep::SearchOptions {
+        regex: true,
+        ignore_case: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_lines(TEXT, "^[r]ust", &options),
+        ["Rust is fast", "rusty nails"]
+    );
+
+    // Pathological patterns stay linear in the line length.
+    let line = "a".repeat(10_000);
+    assert_eq!(
+        matching_lines(
+            &line,
+            "a*a*a*a*a*a*a*a*b",
+            &pargrep::SearchOptions {
+                regex: true,
+                ..Default::default()
+            }
+        ),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_invalid_regex() {
+    let options = pargrep::SearchOptions {
+        regex: true,
+        ..Default::default()
+    };
+    for (pattern, position, kind) in [
+        ("[abc", 0, pargrep::PatternErrorKind::UnclosedClass),
+        ("a[]", 1, pargrep::PatternErrorKind::EmptyClass),
+        ("[z-a]", 3, pargrep::PatternErrorKind::InvalidRange),
+        ("*a", 0, pargrep::PatternErrorKind::NothingToRepeat),
+        ("a**", 2, pargrep::PatternErrorKind::NothingToRepeat),
+        ("^?", 1, pargrep::PatternErrorKind::NothingToRepeat),
+        ("abc\\", 3, pargrep::PatternErrorKind::TrailingBackslash),
+        ("a|b", 1, pargrep::PatternErrorKind::Unsupported('|')),
+    ] {
+        // The path does not even exist: the pattern is checked before searching.
+        let error = pargrep::run_with_options("/sad/sdg/sdg", pattern, &options)
+            .err()
+            .unwrap();
+        assert_eq!(error, pargrep::PatternError { position, kind }, "{pattern}");
+    }
+
+    // Any pattern is a valid substring.
+    let events = pargrep::run_with_options("/sad/sdg/sdg", "[abc", &Default::default());
+    assert_eq!(events.unwrap().len(), 1);
+}
+
+#[test]
+fn test_spans() {
+    let text = "abab xab\nno match\nAB ab\n";
+    assert_eq!(
+        matching_spans(text, "ab", &Default::default()),
+        [(1, vec![0..2, 2..4, 6..8]), (3, vec![(3..5)])]
+    );
+    // Matches don't overlap.
+    assert_eq!(
+        matching_spans("aaaa", "aa", &Default::default()),
+        [(1, vec![0..2, 2..4])]
+    );
+
+    let ignore_case = pargrep::SearchOptions {
+        ignore_case: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_spans(text, "ab", &ignore_case),
+        [(1, vec![0..2, 2..4, 6..8]), (3, vec![0..2, 3..5])]
+    );
+    // Offsets are in bytes.
+    assert_eq!(
+        matching_spans("привет, ПРИВЕТ", "привет", &ignore_case),
+        [(1, vec![0..12, 14..26])]
+    );
+
+    let whole_word = pargrep::SearchOptions {
+        whole_word: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_spans("ab abab ab", "ab", &whole_word),
+        [(1, vec![0..2, 8..10])]
+    );
+
+    let regex = pargrep::SearchOptions {
+        regex: true,
+        ..Default::default()
+    };
+    // Leftmost, then longest.
+    assert_eq!(
+        matching_spans("caaab aa a", "a+", &regex),
+        [(1, vec![1..4, 6..8, 9..10])]
+    );
+    assert_eq!(
+        matching_spans("xaab ab b", "a*b", &regex),
+        [(1, vec![1..4, 5..7, 8..9])]
+    );
+    assert_eq!(
+        matching_spans("one 22 333", r"\b\d+\b", &regex),
+        [(1, vec![4..6, 7..10])]
+    );
+    assert_eq!(matching_spans("abc", "^.", &regex), [(1, vec![(0..1)])]);
+    // Empty matches are reported too, as for the empty pattern.
+    assert_eq!(
+        matching_spans("ab", "", &Default::default()),
+        [(1, vec![0..0, 1..1, 2..2])]
+    );
+    assert_eq!(matching_spans("ba", "a*", &regex), [(1, vec![0..0, 1..2])]);
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let text = b"broken \xff\xfe needle\r\nneedle\nnothing\n";
+    let matches = find_matches(text, "needle", &Default::default());
+    assert_eq!(
+        matches
+            .iter()
+            .map(|m| (m.line.as_str(), m.line_number, m.column))
+            .collect::<Vec<_>>(),
+        [("broken \u{fffd}\u{fffd} needle", 1, 15), ("needle", 2, 1)]
+    );
+    assert_eq!(matches[0].spans, [(14..20)]);
+}
+
+#[test]
+fn test_binary_lossy() {
+    let text = b"\x7fELF\x00\x00\x01\nheader \xc3\x28 needle needle\x00\nnothing\n";
+    let lossy = pargrep::SearchOptions {
+        binary: pargrep::BinaryMode::Lossy,
+        ..Default::default()
+    };
+    let matches = find_matches(text, "needle", &lossy);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].line, "header \u{fffd}( needle needle\0");
+    assert_eq!(matches[0].line_number, 2);
+    assert_eq!(matches[0].spans, [12..18, 19..25]);
+}
+
+#[test]
+fn test_binary_skipped() {
+    let tree_desc: TreeDesc = &[
+        ("bin/tool", b"\x7fELF\x00\x00 needle"),
+        ("text", b"a needle"),
+    ];
+    let tmp_dir = make_tree(tree_desc).unwrap();
+    let late = [&b"needle\n"[..], &[b'x'; 64 * 1024], b"\x00"].concat();
+    fs::write(tmp_dir.path().join("late"), late).unwrap();
+
+    let mut binaries = vec![];
+    let mut matches = vec![];
+    for event in pargrep::run(tmp_dir.path(), "needle") {
+        match event {
+            pargrep::Event::Match(m) => matches.push(m),
+            pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+            pargrep::Event::Binary(path) => binaries.push(path),
+        }
+    }
+
+    assert_eq!(binaries, [tmp_dir.path().join("bin/tool")]);
+    // Files are only checked for NUL bytes at the start.
+    matches.sort_by(|m1, m2| m1.path.cmp(&m2.path));
+    assert_eq!(matches.len(), 2);
+    assert!(matches[0].path.ends_with("late"));
+    assert!(matches[1].path.ends_with("text"));
+}
+
+fn matching_paths(root: &Path, options: &pargrep::SearchOptions) -> Vec<String> {
+    let mut paths = pargrep::run_with_options(root, "needle", options)
+        .unwrap()
+        .into_iter()
+        .map(|ev| match ev {
+            pargrep::Event::Match(m) => {
+                let path = m.path.strip_prefix(root).unwrap();
+                path.to_str().unwrap().replace('\\', "/")
+            }
+            pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+            pargrep::Event::Binary(path) => panic!("unexpected binary file: {:?}", path),
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+const LAYOUT: TreeDesc = &[
+    ("notes.txt", b"needle"),
+    ("src/lib.rs", b"needle"),
+    ("src/nested/deep.rs", b"needle"),
+    ("src/nested/target", b"needle"),
+    ("target/debug/out", b"needle"),
+    (".git/HEAD", b"needle"),
+];
+
+#[test]
+fn test_exclude() {
+    let tmp_dir = make_tree(LAYOUT).unwrap();
+    let options = pargrep::SearchOptions {
+        exclude_globs: vec!["target".to_string(), ".git".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &options),
+        ["notes.txt", "src/lib.rs", "src/nested/deep.rs"]
+    );
+
+    let options = pargrep::SearchOptions {
+        exclude_globs: vec!["src/*".to_string(), "./target/**".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &options),
+        [".git/HEAD", "notes.txt"]
+    );
+}
+
+#[test]
+fn test_include() {
+    let tmp_dir = make_tree(LAYOUT).unwrap();
+    let options = pargrep::SearchOptions {
+        include_globs: vec!["*.rs".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &options),
+        ["src/lib.rs", "src/nested/deep.rs"]
+    );
+
+    let options = pargrep::SearchOptions {
+        include_globs: vec!["**/nested/*".to_string(), ".git/*".to_string()],
+        exclude_globs: vec!["deep.rs".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &options),
+        [".git/HEAD", "src/nested/target"]
+    );
+}
+
+#[test]
+fn test_max_depth() {
+    let tmp_dir = make_tree(LAYOUT).unwrap();
+    let with_depth = |max_depth| pargrep::SearchOptions {
+        max_depth: Some(max_depth),
+        ..Default::default()
+    };
+
+    assert!(matching_paths(tmp_dir.path(), &with_depth(0)).is_empty());
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &with_depth(1)),
+        ["notes.txt"]
+    );
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &with_depth(2)),
+        [".git/HEAD", "notes.txt", "src/lib.rs"]
+    );
+    assert_eq!(matching_paths(tmp_dir.path(), &with_depth(100)).len(), 6);
+
+    // The searched file itself is always searched.
+    let path = tmp_dir.path().join("notes.txt");
+    let events = pargrep::run_with_options(path, "needle", &with_depth(0)).unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let tmp_dir = make_tree(&[("dir/file", b"needle")]).unwrap();
+    symlink(tmp_dir.path(), tmp_dir.path().join("dir/loop")).unwrap();
+    symlink(tmp_dir.path().join("dir/file"), tmp_dir.path().join("link")).unwrap();
+
+    let options = pargrep::SearchOptions::default();
+    assert_eq!(matching_paths(tmp_dir.path(), &options), ["dir/file"]);
+
+    let options = pargrep::SearchOptions {
+        follow_symlinks: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_paths(tmp_dir.path(), &options),
+        ["dir/file", "link"]
+    );
+
+    // `run` follows them, as it always has.
+    let mut paths = pargrep::run(tmp_dir.path(), "needle")
+        .into_iter()
+        .map(|event| match event {
+            pargrep::Event::Match(m) => m.path,
+            pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+            pargrep::Event::Binary(path) => panic!("unexpected binary file: {:?}", path),
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [tmp_dir.path().join("dir/file"), tmp_dir.path().join("link")]
+    );
+}
+
+fn make_large_tree() -> TempDir {
+    let tmp_dir = TempDir::new("pargrep").unwrap();
+    for i in 0..64 {
+        let dir_path = tmp_dir.path().join(i.to_string());
+        fs::create_dir(&dir_path).unwrap();
+        for j in 0..64 {
+            fs::write(dir_path.join(j.to_string()), "hay\nneedle\nhay\n").unwrap();
+        }
+    }
+    tmp_dir
+}
+
+#[test]
+fn test_streaming() {
+    let tmp_dir = make_large_tree();
+
+    let start = Instant::now();
+    let first_event = std::sync::OnceLock::new();
+    let match_count = AtomicUsize::new(0);
+    pargrep::run_streaming(
+        tmp_dir.path(),
+        "needle",
+        &pargrep::SearchOptions::default(),
+        |event| {
+            first_event.get_or_init(|| start.elapsed());
+            match event {
+                pargrep::Event::Match(m) => {
+                    assert_eq!(m.line_number, 2);
+                    match_count.fetch_add(1, Ordering::Relaxed);
+                }
+                pargrep::Event::Error(err) => panic!("unexpected error: {:?}", err),
+                pargrep::Event::Binary(path) => panic!("unexpected binary: {:?}", path),
+            }
+        },
+    )
+    .unwrap();
+    let total = start.elapsed();
+
+    assert_eq!(match_count.into_inner(), 64 * 64);
+    assert!(*first_event.get().unwrap() < total / 2);
+}
+
+#[test]
+fn test_max_matches() {
+    let tmp_dir = make_large_tree();
+    let options = pargrep::SearchOptions {
+        max_matches: Some(1),
+        ..Default::default()
+    };
+
+    let full = time(|| pargrep::run(tmp_dir.path(), "needle"));
+    let start = Instant::now();
+    let events = pargrep::run_with_options(tmp_dir.path(), "needle", &options).unwrap();
+    let capped = start.elapsed();
+
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], pargrep::Event::Match(_)));
+    assert!(capped < full / 4);
+
+    let text = "needle 1\nhay\nneedle 2\nneedle 3\nneedle 4\n";
+    let options = pargrep::SearchOptions {
+        max_matches: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(
+        matching_lines(text, "needle", &options),
+        ["needle 1", "needle 2", "needle 3"]
+    );
+    let options = pargrep::SearchOptions {
+        max_matches: Some(0),
+        ..Default::default()
+    };
+    assert!(matching_lines(text, "needle", &options).is_empty());
+}
+
 #[test]
 #[cfg(not(debug_assertions))]
 fn test_performance() {
@@ -173,7 +722,6 @@ fn test_performance() {
     assert!(par_durations[6] < single_durations[6]);
 }
 
-#[allow(unused)]
 fn time<R, F: FnOnce() -> R>(func: F) -> Duration {
     let now = Instant::now();
     func();
@@ -208,11 +756,13 @@ fn single_run(path: &Path, pattern: &str) -> Vec<pargrep::Event> {
         let reader = BufReader::new(fs::File::open(&path).unwrap());
         for (i, mb_line) in reader.lines().enumerate() {
             let line = mb_line.unwrap();
-            if line.contains(pattern) {
+            if let Some(start) = line.find(pattern) {
                 events.push(pargrep::Event::Match(pargrep::Match {
                     path: path.clone(),
                     line,
                     line_number: i + 1,
+                    column: start + 1,
+                    spans: vec![(start..start + pattern.len())],
                 }));
             }
         }
@@ -1,6 +1,10 @@
-use std::vec;
+use std::{
+    collections::{HashSet, VecDeque},
+    vec,
+};
 
-use rand::Rng;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rayon::prelude::*;
 
 /// Represents a grid of boolean values.
 pub struct BoolGrid {
@@ -33,9 +37,13 @@ impl BoolGrid {
     /// * `vacancy` - probability of any given value being equal
     ///   to `false`.
     pub fn random(width: usize, height: usize, vacancy: f64) -> Self {
-        let mut grid = BoolGrid::new(width, height);
+        Self::random_with_rng(width, height, vacancy, &mut rand::thread_rng())
+    }
 
-        let mut rng = rand::thread_rng();
+    /// Same as `random`, but takes the values from the given generator,
+    /// so that a seeded one gives the same grid every time.
+    pub fn random_with_rng(width: usize, height: usize, vacancy: f64, rng: &mut impl Rng) -> Self {
+        let mut grid = BoolGrid::new(width, height);
 
         for x in 0..width {
             for y in 0..height {
@@ -96,60 +104,685 @@ impl BoolGrid {
 /// Returns `true` if the given grid percolates. That is, if there is a path
 /// from any cell with `y` == 0 to any cell with `y` == `height` - 1.
 /// If the grid is empty (`width` == 0 or `height` == 0), it percolates.
+///
+/// Joins adjacent free cells into sets, together with two virtual cells: one above
+/// the top row and one below the bottom row. The grid percolates if they end up in
+/// the same set.
 pub fn percolates(grid: &BoolGrid) -> bool {
-    if grid.width() == 0 || grid.height() == 0 {
+    let (width, height) = (grid.width(), grid.height());
+    if width == 0 || height == 0 {
         return true;
     }
 
-    for x in 0..grid.width() {
-        let mut visited = vec![vec![false; grid.width()]; grid.height()];
-
-        if dfs(grid, &mut visited, x, 0) {
-            return true;
+    let index = |x: usize, y: usize| y * width + x;
+    let (top, bottom) = (width * height, width * height + 1);
+    let mut sets = DisjointSets::new(width * height + 2);
+    for y in 0..height {
+        for x in 0..width {
+            if grid.get(x, y) {
+                continue;
+            }
+            if y == 0 {
+                sets.union(index(x, y), top);
+            }
+            if y == height - 1 {
+                sets.union(index(x, y), bottom);
+            }
+            if x + 1 < width && !grid.get(x + 1, y) {
+                sets.union(index(x, y), index(x + 1, y));
+            }
+            if y + 1 < height && !grid.get(x, y + 1) {
+                sets.union(index(x, y), index(x, y + 1));
+            }
         }
     }
 
-    false
+    sets.find(top) == sets.find(bottom)
 }
 
-pub fn dfs(grid: &BoolGrid, visited: &mut Vec<Vec<bool>>, x: usize, y: usize) -> bool {
-    if grid.get(x, y) {
-        return false;
-    } else if y == grid.height() - 1 {
-        return true;
-    }
-
-    visited[y][x] = true;
+/// Weighted union-find with path halving.
+struct DisjointSets {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
 
-    let moves = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
 
-    for (dy, dx) in moves.iter() {
-        let y = y.wrapping_add(*dy as usize);
-        let x = x.wrapping_add(*dx as usize);
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
 
-        if y < grid.height() && x < grid.width() && !visited[y][x] && dfs(grid, visited, x, y) {
-            return true;
+    /// Returns `false` if `i` and `j` are in the same set already.
+    fn union(&mut self, i: usize, j: usize) -> bool {
+        let (mut i, mut j) = (self.find(i), self.find(j));
+        if i == j {
+            return false;
+        }
+        if self.sizes[i] < self.sizes[j] {
+            (i, j) = (j, i);
         }
+        self.parents[j] = i;
+        self.sizes[i] += self.sizes[j];
+        true
     }
 
-    false
+    /// Adds a new set of a single element, returns the element.
+    fn add(&mut self) -> usize {
+        self.parents.push(self.parents.len());
+        self.sizes.push(1);
+        self.parents.len() - 1
+    }
+
+    fn len(&self) -> usize {
+        self.parents.len()
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
 const N_TRIALS: u64 = 10000;
 
+/// Trials of a chunk share a generator seeded by the chunk index. Chunks
+/// don't depend on the thread they run on, so the estimate only depends
+/// on the seed.
+const CHUNK_TRIALS: u64 = 256;
+
+/// Standard normal quantile for a 95% confidence interval.
+const Z_95: f64 = 1.959964;
+
+/// Result of a Monte-Carlo estimation of the percolation probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbabilityEstimate {
+    /// Share of the trials that percolated.
+    pub probability: f64,
+    pub standard_error: f64,
+    /// Bounds of the 95% confidence interval, clamped to [0, 1].
+    pub confidence_interval: (f64, f64),
+    pub trials: u64,
+}
+
+impl ProbabilityEstimate {
+    fn new(percolated: u64, trials: u64) -> Self {
+        let probability = percolated as f64 / trials as f64;
+        let standard_error = (probability * (1. - probability) / trials as f64).sqrt();
+        let margin = Z_95 * standard_error;
+        Self {
+            probability,
+            standard_error,
+            confidence_interval: (
+                (probability - margin).max(0.),
+                (probability + margin).min(1.),
+            ),
+            trials,
+        }
+    }
+
+    /// Returns `true` if `probability` lies within the confidence interval.
+    pub fn contains(&self, probability: f64) -> bool {
+        let (low, high) = self.confidence_interval;
+        (low..=high).contains(&probability)
+    }
+}
+
 /// Returns an estimate of the probability that a random grid with given
 /// `width, `height` and `vacancy` probability percolates.
 /// To compute an estimate, it runs `N_TRIALS` of random experiments,
 /// in each creating a random grid and checking if it percolates.
 pub fn evaluate_probability(width: usize, height: usize, vacancy: f64) -> f64 {
-    let mut perc_count = 0;
-    for _ in 0..N_TRIALS {
-        let grid = BoolGrid::random(width, height, vacancy);
-        if percolates(&grid) {
-            perc_count += 1;
+    evaluate_probability_with(width, height, vacancy, N_TRIALS, rand::random()).probability
+}
+
+/// Same as `evaluate_probability`, but runs the given number of `trials`
+/// in parallel and also estimates the error. The same `seed` gives the same
+/// estimate, whatever the number of threads.
+///
+/// # Panics
+///
+/// If `trials` is 0.
+pub fn evaluate_probability_with(
+    width: usize,
+    height: usize,
+    vacancy: f64,
+    trials: u64,
+    seed: u64,
+) -> ProbabilityEstimate {
+    assert!(trials > 0, "at least one trial is required");
+
+    let percolated = (0..trials.div_ceil(CHUNK_TRIALS))
+        .into_par_iter()
+        .map(|chunk| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(chunk));
+            let chunk_trials = CHUNK_TRIALS.min(trials - chunk * CHUNK_TRIALS);
+            (0..chunk_trials)
+                .filter(|_| {
+                    percolates(&BoolGrid::random_with_rng(width, height, vacancy, &mut rng))
+                })
+                .count() as u64
+        })
+        .sum();
+    ProbabilityEstimate::new(percolated, trials)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A grid where all sites start blocked and are opened one at a time,
+/// keeping track of whether the grid percolates after every opened site.
+/// Unlike `BoolGrid`, it takes nearly constant time per site instead of
+/// a whole pass over the grid per check.
+pub struct PercolationSim {
+    width: usize,
+    height: usize,
+    is_open: Vec<bool>,
+    open_count: usize,
+    /// Sites, followed by the virtual top and bottom ones.
+    sets: DisjointSets,
+}
+
+impl PercolationSim {
+    /// Creates a new simulation with all sites blocked.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - grid width.
+    /// * `height` - grid height.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            is_open: vec![false; width * height],
+            open_count: 0,
+            sets: DisjointSets::new(width * height + 2),
+        }
+    }
+
+    /// Returns grid width.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns grid height.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Opens a given site, does nothing if it is open already.
+    ///
+    /// # Panics
+    ///
+    /// If `x` or `y` is out of bounds.
+    pub fn open(&mut self, x: usize, y: usize) {
+        let site = self.index(x, y);
+        if self.is_open[site] {
+            return;
+        }
+        self.is_open[site] = true;
+        self.open_count += 1;
+
+        if y == 0 {
+            self.sets.union(site, self.top());
+        }
+        if y == self.height - 1 {
+            self.sets.union(site, self.bottom());
+        }
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (x, y) in neighbours {
+            if x < self.width && y < self.height && self.is_open(x, y) {
+                let neighbour = self.index(x, y);
+                self.sets.union(site, neighbour);
+            }
+        }
+    }
+
+    /// Returns `true` if a given site is open.
+    ///
+    /// # Panics
+    ///
+    /// If `x` or `y` is out of bounds.
+    pub fn is_open(&self, x: usize, y: usize) -> bool {
+        self.is_open[self.index(x, y)]
+    }
+
+    /// Returns `true` if the open sites connect the top row to the bottom one.
+    /// Just as with `percolates`, an empty grid percolates.
+    pub fn percolates(&mut self) -> bool {
+        if self.width == 0 || self.height == 0 {
+            return true;
+        }
+        let (top, bottom) = (self.top(), self.bottom());
+        self.sets.find(top) == self.sets.find(bottom)
+    }
+
+    pub fn number_of_open_sites(&self) -> usize {
+        self.open_count
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "site ({x}, {y}) is out of bounds",
+        );
+        y * self.width + x
+    }
+
+    fn top(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn bottom(&self) -> usize {
+        self.width * self.height + 1
+    }
+}
+
+/// Returns an estimate of the percolation threshold: the mean share of
+/// sites that are open when a grid first percolates, opening the sites
+/// one by one in a random order. Trials run in parallel, the same `seed`
+/// gives the same estimate.
+///
+/// # Panics
+///
+/// If `trials` is 0 or the grid is empty.
+pub fn estimate_threshold(width: usize, height: usize, trials: u64, seed: u64) -> f64 {
+    assert!(trials > 0, "at least one trial is required");
+    assert!(width > 0 && height > 0, "the grid must not be empty");
+
+    let sites = (0..width)
+        .flat_map(|x| (0..height).map(move |y| (x, y)))
+        .collect::<Vec<_>>();
+    // Counts rather than shares are summed, so that the order of the
+    // parallel sum doesn't change the result.
+    let open_sites: u64 = (0..trials)
+        .into_par_iter()
+        .map(|trial| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(trial));
+            let mut order = sites.clone();
+            order.shuffle(&mut rng);
+
+            let mut sim = PercolationSim::new(width, height);
+            for (x, y) in order {
+                sim.open(x, y);
+                if sim.percolates() {
+                    break;
+                }
+            }
+            sim.number_of_open_sites() as u64
+        })
+        .sum();
+    open_sites as f64 / (trials as f64 * sites.len() as f64)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Keeps track of whether a `BoolGrid` percolates while its cells are opened
+/// (set to `false`) and closed (set to `true`) one at a time.
+///
+/// Open cells are joined in a union-find together with the virtual top and
+/// bottom cells, just as in `percolates`, so opening a cell takes nearly
+/// constant amortized time. Union-find can't split sets, so closing a cell
+/// only gives it a fresh element, leaving the old one joined with whatever it
+/// was joined with. The sets may then join cells which aren't connected any
+/// more, but never the other way around. When asked, the closed cells are
+/// checked: every group of adjacent closed cells must have its open neighbours
+/// (and the top or the bottom, if it touches them) still connected, which is
+/// looked for by a BFS visiting at most `PATCH_LIMIT` cells around it. If so,
+/// the sets are right again. If not, or if more than `close_threshold` cells
+/// have been closed since the last rebuild, the sets are rebuilt from
+/// scratch.
+///
+/// So opening costs O(α(n)), closing O(1), and a query after `k` closes either
+/// O(k * PATCH_LIMIT) or O(width * height * α(n)) if the rebuild is needed.
+/// The latter is what it takes for a closed cell which cuts a big cluster in
+/// two, or one far from any detour. `cluster_count` recounts the clusters in
+/// O(width * height) after closes, as it can't tell how many of them a close
+/// has split.
+pub struct IncrementalPercolation {
+    grid: BoolGrid,
+    /// The element of every cell in the sets, a closed cell gets a fresh one.
+    elements: Vec<usize>,
+    /// Cells, the virtual top and bottom ones, and the left behind elements of
+    /// the closed cells.
+    sets: DisjointSets,
+    /// Same without joining the top and the bottom rows, to count clusters.
+    clusters: DisjointSets,
+    cluster_count: usize,
+    /// `cluster_count` is unknown since a cell has been closed.
+    recount: bool,
+    /// Cells closed since the sets were last checked.
+    unchecked: Vec<usize>,
+    closes_since_rebuild: usize,
+    close_threshold: usize,
+    rebuilds: usize,
+}
+
+impl IncrementalPercolation {
+    pub const DEFAULT_CLOSE_THRESHOLD: usize = 64;
+
+    /// Cells visited around a group of closed cells looking for a detour.
+    pub const PATCH_LIMIT: usize = 4096;
+
+    /// Builds the sets for a given grid, where `false` cells are open.
+    pub fn new(grid: BoolGrid) -> Self {
+        let mut this = Self {
+            grid,
+            elements: vec![],
+            sets: DisjointSets::new(0),
+            clusters: DisjointSets::new(0),
+            cluster_count: 0,
+            recount: false,
+            unchecked: vec![],
+            closes_since_rebuild: 0,
+            close_threshold: Self::DEFAULT_CLOSE_THRESHOLD,
+            rebuilds: 0,
+        };
+        this.rebuild();
+        this
+    }
+
+    /// Rebuilds the sets once more than `threshold` cells have been closed
+    /// since the last rebuild, however they are checked. This bounds the
+    /// elements left behind by the closed cells.
+    pub fn with_close_threshold(mut self, threshold: usize) -> Self {
+        self.close_threshold = threshold;
+        self
+    }
+
+    /// Returns grid width.
+    pub fn width(&self) -> usize {
+        self.grid.width()
+    }
+
+    /// Returns grid height.
+    pub fn height(&self) -> usize {
+        self.grid.height()
+    }
+
+    pub fn grid(&self) -> &BoolGrid {
+        &self.grid
+    }
+
+    /// Returns `true` if a given cell is open.
+    ///
+    /// # Panics
+    ///
+    /// If `x` or `y` is out of bounds.
+    pub fn is_open(&self, x: usize, y: usize) -> bool {
+        !self.grid.get(x, y)
+    }
+
+    /// Opens a given cell, does nothing if it is open already. Returns whether
+    /// the grid percolates now.
+    ///
+    /// # Panics
+    ///
+    /// If `x` or `y` is out of bounds.
+    pub fn open(&mut self, x: usize, y: usize) -> bool {
+        let cell = self.index(x, y);
+        if !self.grid.get(x, y) {
+            return self.percolates();
+        }
+        self.grid.set(x, y, false);
+        self.cluster_count += 1;
+
+        let element = self.elements[cell];
+        if y == 0 {
+            self.sets.union(element, self.top());
+        }
+        if y == self.height() - 1 {
+            self.sets.union(element, self.bottom());
+        }
+        for neighbour in self.neighbours(cell) {
+            let (nx, ny) = self.coordinates(neighbour);
+            if self.grid.get(nx, ny) {
+                continue;
+            }
+            let other = self.elements[neighbour];
+            self.sets.union(element, other);
+            if self.clusters.union(element, other) {
+                self.cluster_count -= 1;
+            }
+        }
+        self.percolates()
+    }
+
+    /// Closes a given cell, does nothing if it is closed already. The sets are
+    /// checked on the next query.
+    ///
+    /// # Panics
+    ///
+    /// If `x` or `y` is out of bounds.
+    pub fn close(&mut self, x: usize, y: usize) {
+        let cell = self.index(x, y);
+        if self.grid.get(x, y) {
+            return;
+        }
+        self.grid.set(x, y, true);
+        self.recount = true;
+
+        self.closes_since_rebuild += 1;
+        if self.closes_since_rebuild > self.close_threshold {
+            // The sets will be rebuilt anyway.
+            return;
+        }
+        let element = self.sets.add();
+        self.clusters.add();
+        self.elements[cell] = element;
+        self.unchecked.push(cell);
+    }
+
+    /// Returns `true` if the open cells connect the top row to the bottom one.
+    /// Just as with `percolates`, an empty grid percolates.
+    pub fn percolates(&mut self) -> bool {
+        if self.width() == 0 || self.height() == 0 {
+            return true;
+        }
+        let (top, bottom) = (self.top(), self.bottom());
+        // The sets may only join too much, so they are only checked if joined.
+        if self.sets.find(top) != self.sets.find(bottom) {
+            return false;
+        }
+        self.settle();
+        self.sets.find(top) == self.sets.find(bottom)
+    }
+
+    /// Returns the number of clusters of adjacent open cells.
+    pub fn cluster_count(&mut self) -> usize {
+        self.settle();
+        if self.recount {
+            let mut seen = vec![false; self.clusters.len()];
+            self.cluster_count = 0;
+            for cell in 0..self.elements.len() {
+                let (x, y) = self.coordinates(cell);
+                if self.grid.get(x, y) {
+                    continue;
+                }
+                let root = self.clusters.find(self.elements[cell]);
+                if !seen[root] {
+                    seen[root] = true;
+                    self.cluster_count += 1;
+                }
+            }
+            self.recount = false;
+        }
+        self.cluster_count
+    }
+
+    /// Returns how many times the sets have been built from scratch, including
+    /// the construction.
+    pub fn rebuild_count(&self) -> usize {
+        self.rebuilds
+    }
+
+    /// Makes the sets right after closes, see the struct docs.
+    fn settle(&mut self) {
+        if self.closes_since_rebuild > self.close_threshold || !self.patches_hold() {
+            self.rebuild();
+        }
+        self.unchecked.clear();
+    }
+
+    fn rebuild(&mut self) {
+        let (width, height) = (self.width(), self.height());
+        let cells = width * height;
+        self.elements = (0..cells).collect();
+        self.sets = DisjointSets::new(cells + 2);
+        // Same elements, the top and the bottom are left alone.
+        self.clusters = DisjointSets::new(cells + 2);
+        self.cluster_count = 0;
+        for y in 0..height {
+            for x in 0..width {
+                if self.grid.get(x, y) {
+                    continue;
+                }
+                let cell = y * width + x;
+                self.cluster_count += 1;
+                if y == 0 {
+                    self.sets.union(cell, self.top());
+                }
+                if y == height - 1 {
+                    self.sets.union(cell, self.bottom());
+                }
+                // Only the neighbours visited before, which are counted already.
+                let left = (x > 0 && !self.grid.get(x - 1, y)).then(|| cell - 1);
+                let up = (y > 0 && !self.grid.get(x, y - 1)).then(|| cell - width);
+                for neighbour in left.into_iter().chain(up) {
+                    self.sets.union(cell, neighbour);
+                    if self.clusters.union(cell, neighbour) {
+                        self.cluster_count -= 1;
+                    }
+                }
+            }
+        }
+
+        self.recount = false;
+        self.unchecked.clear();
+        self.closes_since_rebuild = 0;
+        self.rebuilds += 1;
+    }
+
+    /// Returns `true` if every group of adjacent cells closed since the last
+    /// check has its open neighbours connected around it.
+    fn patches_hold(&self) -> bool {
+        let mut closed = self
+            .unchecked
+            .iter()
+            .copied()
+            .filter(|&cell| {
+                let (x, y) = self.coordinates(cell);
+                self.grid.get(x, y)
+            })
+            .collect::<HashSet<_>>();
+
+        while let Some(&start) = closed.iter().next() {
+            closed.remove(&start);
+            let mut group = vec![start];
+            let mut targets = HashSet::new();
+            let (mut touches_top, mut touches_bottom) = (false, false);
+            while let Some(cell) = group.pop() {
+                let (_, y) = self.coordinates(cell);
+                touches_top |= y == 0;
+                touches_bottom |= y == self.height() - 1;
+                for neighbour in self.neighbours(cell) {
+                    let (nx, ny) = self.coordinates(neighbour);
+                    if !self.grid.get(nx, ny) {
+                        targets.insert(neighbour);
+                    } else if closed.remove(&neighbour) {
+                        group.push(neighbour);
+                    }
+                }
+            }
+            if !self.is_connected(&targets, touches_top, touches_bottom) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Looks for the open `targets` connected to each other, and to the top
+    /// and the bottom rows if asked, visiting at most `PATCH_LIMIT` cells.
+    fn is_connected(&self, targets: &HashSet<usize>, mut top: bool, mut bottom: bool) -> bool {
+        let Some(&start) = targets.iter().next() else {
+            // Nothing but maybe the top and the bottom, which are as connected
+            // as the grid percolates.
+            return !(top && bottom);
+        };
+        let mut left = targets.len();
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            let (_, y) = self.coordinates(cell);
+            top &= y != 0;
+            bottom &= y != self.height() - 1;
+            if targets.contains(&cell) {
+                left -= 1;
+            }
+            if left == 0 && !top && !bottom {
+                return true;
+            }
+            if visited.len() >= Self::PATCH_LIMIT {
+                continue;
+            }
+            for neighbour in self.open_neighbours(cell) {
+                if visited.insert(neighbour) {
+                    queue.push_back(neighbour);
+                }
+            }
         }
+        false
+    }
+
+    fn neighbours(&self, cell: usize) -> impl Iterator<Item = usize> {
+        let (width, height) = (self.width(), self.height());
+        let (x, y) = (cell % width, cell / width);
+        [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ]
+        .into_iter()
+        .filter(move |&(x, y)| x < width && y < height)
+        .map(move |(x, y)| y * width + x)
+    }
+
+    fn open_neighbours(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        self.neighbours(cell).filter(|&neighbour| {
+            let (x, y) = self.coordinates(neighbour);
+            !self.grid.get(x, y)
+        })
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width() && y < self.height(),
+            "cell ({x}, {y}) is out of bounds",
+        );
+        y * self.width() + x
+    }
+
+    fn coordinates(&self, cell: usize) -> (usize, usize) {
+        (cell % self.width(), cell / self.width())
+    }
+
+    /// The virtual top cell, the left behind elements go after the bottom one.
+    fn top(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    fn bottom(&self) -> usize {
+        self.width() * self.height() + 1
     }
-    perc_count as f64 / N_TRIALS as f64
 }
@@ -1,4 +1,10 @@
-use perc::{evaluate_probability, percolates, BoolGrid};
+use perc::{
+    estimate_threshold, evaluate_probability, evaluate_probability_with, percolates, BoolGrid,
+    IncrementalPercolation, PercolationSim,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use std::collections::VecDeque;
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -85,6 +91,103 @@ fn test_percolates() {
     assert!(percolates(&BoolGrid::random(50, 50, 0.9)));
 }
 
+/// A plain BFS from all the free cells of the top row.
+fn reference_percolates(grid: &BoolGrid) -> bool {
+    let (width, height) = (grid.width(), grid.height());
+    if width == 0 || height == 0 {
+        return true;
+    }
+
+    let mut visited = vec![vec![false; height]; width];
+    let mut queue = (0..width)
+        .filter(|&x| !grid.get(x, 0))
+        .map(|x| (x, 0))
+        .collect::<VecDeque<_>>();
+    for &(x, y) in &queue {
+        visited[x][y] = true;
+    }
+    while let Some((x, y)) = queue.pop_front() {
+        if y == height - 1 {
+            return true;
+        }
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (x, y) in neighbours {
+            if x < width && y < height && !grid.get(x, y) && !visited[x][y] {
+                visited[x][y] = true;
+                queue.push_back((x, y));
+            }
+        }
+    }
+    false
+}
+
+#[test]
+fn test_non_square() {
+    let mut narrow = BoolGrid::new(3, 50);
+    for y in 0..50 {
+        narrow.set(0, y, true);
+        narrow.set(2, y, true);
+    }
+    assert!(percolates(&narrow));
+    narrow.set(1, 49, true);
+    assert!(!percolates(&narrow));
+
+    let mut wide = BoolGrid::new(50, 3);
+    for x in 0..50 {
+        wide.set(x, 1, true);
+    }
+    assert!(!percolates(&wide));
+    wide.set(49, 1, false);
+    assert!(percolates(&wide));
+
+    assert!(percolates(&BoolGrid::new(1, 50)));
+    assert!(percolates(&BoolGrid::new(50, 1)));
+}
+
+#[test]
+fn test_large_grid() {
+    let size = 1000;
+    assert!(percolates(&BoolGrid::new(size, size)));
+
+    // A snake through every other row, so that the path is as long as possible.
+    let mut snake = BoolGrid::new(size, size);
+    for y in (1..size).step_by(2) {
+        for x in 0..size {
+            snake.set(x, y, true);
+        }
+        let gap = if y % 4 == 1 { size - 1 } else { 0 };
+        snake.set(gap, y, false);
+    }
+    assert!(percolates(&snake));
+    snake.set(0, size - 1, true);
+    assert!(!percolates(&snake));
+}
+
+#[test]
+fn test_against_reference() {
+    for width in 0..8 {
+        for height in 0..8 {
+            for vacancy in [0.3, 0.5, 0.6, 0.8] {
+                for _ in 0..20 {
+                    let grid = BoolGrid::random(width, height, vacancy);
+                    assert_eq!(
+                        percolates(&grid),
+                        reference_percolates(&grid),
+                        "width: {}, height: {}",
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+    }
+}
+
 #[test]
 fn test_probability() {
     for (width, height, vacancy, expected) in
@@ -102,3 +205,254 @@ fn test_probability() {
         );
     }
 }
+
+#[test]
+fn test_random_with_rng() {
+    let make = |seed| BoolGrid::random_with_rng(20, 30, 0.5, &mut StdRng::seed_from_u64(seed));
+    let (first, second) = (make(7), make(7));
+    for x in 0..20 {
+        for y in 0..30 {
+            assert_eq!(first.get(x, y), second.get(x, y));
+        }
+    }
+}
+
+#[test]
+fn test_probability_is_seeded() {
+    let first = evaluate_probability_with(10, 10, 0.57, 5000, 42);
+    let second = evaluate_probability_with(10, 10, 0.57, 5000, 42);
+    assert_eq!(first, second);
+    assert_eq!(first.trials, 5000);
+}
+
+#[test]
+fn test_probability_extremes() {
+    for trials in [1, 255, 256, 1000] {
+        let estimate = evaluate_probability_with(10, 10, 1., trials, 0);
+        assert_eq!(estimate.probability, 1.);
+        assert_eq!(estimate.standard_error, 0.);
+        assert_eq!(estimate.confidence_interval, (1., 1.));
+
+        let estimate = evaluate_probability_with(10, 10, 0., trials, 0);
+        assert_eq!(estimate.probability, 0.);
+        assert_eq!(estimate.confidence_interval, (0., 0.));
+    }
+}
+
+#[test]
+fn test_probability_confidence_interval() {
+    let (width, height, vacancy) = (10, 10, 0.6);
+    let estimate = evaluate_probability_with(width, height, vacancy, 10000, 1);
+    let (low, high) = estimate.confidence_interval;
+    assert!(low < estimate.probability && estimate.probability < high);
+    assert!(high - low < 0.03, "{estimate:?}");
+
+    // Many more serial trials, so that their own error is negligible.
+    let mut rng = StdRng::seed_from_u64(2);
+    let trials = 100000;
+    let percolated = (0..trials)
+        .filter(|_| percolates(&BoolGrid::random_with_rng(width, height, vacancy, &mut rng)))
+        .count();
+    let serial = percolated as f64 / trials as f64;
+    assert!(estimate.contains(serial), "{serial} is not in {estimate:?}");
+}
+
+#[test]
+fn test_sim_single_column() {
+    let mut sim = PercolationSim::new(1, 10);
+    assert_eq!(sim.number_of_open_sites(), 0);
+    assert!(!sim.percolates());
+
+    for y in (0..10).rev() {
+        assert!(!sim.is_open(0, y));
+        sim.open(0, y);
+        assert!(sim.is_open(0, y));
+        assert_eq!(sim.percolates(), y == 0);
+    }
+    assert_eq!(sim.number_of_open_sites(), 10);
+
+    sim.open(0, 5);
+    assert_eq!(sim.number_of_open_sites(), 10);
+    assert!(percolates(&BoolGrid::new(1, 10)));
+}
+
+#[test]
+fn test_sim_flips_once() {
+    let (width, height) = (15, 12);
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..20 {
+        let mut sites = (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .collect::<Vec<_>>();
+        sites.shuffle(&mut rng);
+
+        let mut sim = PercolationSim::new(width, height);
+        let mut grid = BoolGrid::new(width, height);
+        for x in 0..width {
+            for y in 0..height {
+                grid.set(x, y, true);
+            }
+        }
+
+        let mut flips = 0;
+        let mut was_percolating = false;
+        for (x, y) in sites {
+            sim.open(x, y);
+            grid.set(x, y, false);
+            let is_percolating = sim.percolates();
+            assert_eq!(is_percolating, percolates(&grid));
+            if is_percolating != was_percolating {
+                flips += 1;
+                was_percolating = is_percolating;
+            }
+        }
+        assert_eq!(flips, 1);
+        assert_eq!(sim.number_of_open_sites(), width * height);
+    }
+}
+
+#[test]
+fn test_sim_empty() {
+    assert!(PercolationSim::new(0, 5).percolates());
+    assert!(PercolationSim::new(5, 0).percolates());
+}
+
+#[test]
+fn test_threshold() {
+    let threshold = estimate_threshold(20, 20, 500, 0);
+    assert!((threshold - 0.593).abs() < 0.05, "threshold: {threshold}");
+    assert_eq!(threshold, estimate_threshold(20, 20, 500, 0));
+
+    assert_eq!(estimate_threshold(1, 7, 10, 0), 1.);
+    assert_eq!(estimate_threshold(7, 1, 10, 0), 1. / 7.);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Counts the clusters of adjacent free cells with a plain BFS.
+fn reference_cluster_count(grid: &BoolGrid) -> usize {
+    let (width, height) = (grid.width(), grid.height());
+    let mut visited = vec![vec![false; height]; width];
+    let mut count = 0;
+    for x in 0..width {
+        for y in 0..height {
+            if grid.get(x, y) || visited[x][y] {
+                continue;
+            }
+            count += 1;
+            visited[x][y] = true;
+            let mut queue = VecDeque::from([(x, y)]);
+            while let Some((x, y)) = queue.pop_front() {
+                let neighbours = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                for (x, y) in neighbours {
+                    if x < width && y < height && !grid.get(x, y) && !visited[x][y] {
+                        visited[x][y] = true;
+                        queue.push_back((x, y));
+                    }
+                }
+            }
+        }
+    }
+    count
+}
+
+fn blocked_grid(width: usize, height: usize) -> BoolGrid {
+    let mut grid = BoolGrid::new(width, height);
+    for x in 0..width {
+        for y in 0..height {
+            grid.set(x, y, true);
+        }
+    }
+    grid
+}
+
+#[test]
+fn test_incremental_against_reference() {
+    let mut rng = StdRng::seed_from_u64(5);
+    for width in 1..7 {
+        for height in 1..7 {
+            for threshold in [0, 3, IncrementalPercolation::DEFAULT_CLOSE_THRESHOLD] {
+                let grid = BoolGrid::random_with_rng(width, height, 0.6, &mut rng);
+                let mut inc = IncrementalPercolation::new(grid).with_close_threshold(threshold);
+                for _ in 0..200 {
+                    let (x, y) = (rng.gen_range(0..width), rng.gen_range(0..height));
+                    if rng.gen_bool(0.5) {
+                        let percolating = inc.open(x, y);
+                        assert_eq!(percolating, reference_percolates(inc.grid()));
+                    } else {
+                        inc.close(x, y);
+                    }
+                    assert_eq!(inc.is_open(x, y), !inc.grid().get(x, y));
+
+                    // Skips queries now and then, so that closes pile up.
+                    if rng.gen_bool(0.3) {
+                        assert_eq!(inc.percolates(), reference_percolates(inc.grid()));
+                        assert_eq!(inc.percolates(), percolates(inc.grid()));
+                    }
+                    if rng.gen_bool(0.3) {
+                        assert_eq!(inc.cluster_count(), reference_cluster_count(inc.grid()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_incremental_burst_of_opens() {
+    let size = 300;
+    let mut inc = IncrementalPercolation::new(blocked_grid(size, size));
+    assert!(!inc.percolates());
+    assert_eq!(inc.cluster_count(), 0);
+
+    let mut cells = (0..size)
+        .flat_map(|x| (0..size).map(move |y| (x, y)))
+        .collect::<Vec<_>>();
+    cells.shuffle(&mut StdRng::seed_from_u64(7));
+    let mut was_percolating = false;
+    for &(x, y) in &cells[..cells.len() * 3 / 4] {
+        let percolating = inc.open(x, y);
+        assert!(percolating || !was_percolating);
+        was_percolating = percolating;
+        inc.cluster_count();
+    }
+    assert_eq!(inc.rebuild_count(), 1);
+
+    assert_eq!(inc.percolates(), reference_percolates(inc.grid()));
+    assert_eq!(inc.cluster_count(), reference_cluster_count(inc.grid()));
+    assert_eq!(inc.rebuild_count(), 1);
+}
+
+#[test]
+fn test_incremental_close() {
+    let mut inc = IncrementalPercolation::new(BoolGrid::new(5, 5));
+    assert!(inc.percolates());
+    assert_eq!(inc.cluster_count(), 1);
+
+    // There is a way around.
+    inc.close(2, 2);
+    assert!(inc.percolates());
+    assert_eq!(inc.cluster_count(), 1);
+    assert_eq!(inc.rebuild_count(), 1);
+
+    // There isn't any more.
+    for x in [0, 1, 3, 4] {
+        inc.close(x, 2);
+    }
+    assert!(!inc.percolates());
+    assert_eq!(inc.cluster_count(), 2);
+    assert_eq!(inc.rebuild_count(), 2);
+
+    assert!(inc.open(4, 2));
+    inc.close(0, 0);
+    assert!(inc.percolates());
+    assert_eq!(inc.cluster_count(), 1);
+    assert_eq!(inc.rebuild_count(), 2);
+
+    assert!(IncrementalPercolation::new(BoolGrid::new(0, 5)).percolates());
+}
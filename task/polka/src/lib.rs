@@ -1,5 +1,7 @@
 #![forbid(unsafe_code)]
 
+pub mod repl;
+
 use std::{collections::HashMap, fmt::Display};
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -8,6 +10,8 @@ use std::{collections::HashMap, fmt::Display};
 pub enum Value {
     Number(f64),
     Symbol(String),
+    /// A quotation: tokens between `[` and `]`, evaluated by `if` and `times`.
+    Block(Vec<String>),
 }
 
 impl Display for Value {
@@ -15,15 +19,64 @@ impl Display for Value {
         match self {
             Self::Number(num) => write!(f, "{}", num),
             Self::Symbol(sym) => write!(f, "'{}", sym),
+            Self::Block(tokens) => {
+                write!(f, "[")?;
+                for token in tokens {
+                    write!(f, " {}", token)?;
+                }
+                write!(f, " ]")
+            }
         }
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The default of `Interpreter::with_frame_limit`.
+pub const DEFAULT_FRAME_LIMIT: usize = 100_000;
+
+/// Tokens being evaluated: the expression itself or a block run by `if` or `times`.
+struct Frame {
+    tokens: Vec<String>,
+    index: usize,
+    /// How many more times to evaluate the tokens after this pass, for `times`.
+    repeats: usize,
+}
+
+impl Frame {
+    fn new(tokens: Vec<String>, repeats: usize) -> Self {
+        Self {
+            tokens,
+            index: 0,
+            repeats,
+        }
+    }
+
+    fn next_token(&mut self) -> Option<String> {
+        if self.index == self.tokens.len() {
+            if self.repeats == 0 || self.tokens.is_empty() {
+                return None;
+            }
+            self.repeats -= 1;
+            self.index = 0;
+        }
+        self.index += 1;
+        Some(self.tokens[self.index - 1].clone())
+    }
+
+    /// Returns `true` if the frame has no tokens left, so a word evaluated last may
+    /// replace it rather than stack a frame on top of it.
+    fn is_finished(&self) -> bool {
+        self.index == self.tokens.len() && self.repeats == 0
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 pub struct Interpreter {
     stack: Vec<Value>,
     variables: HashMap<String, Value>,
+    frame_limit: usize,
 }
 impl Default for Interpreter {
     fn default() -> Self {
@@ -36,43 +89,163 @@ impl Interpreter {
         Self {
             stack: Vec::new(),
             variables: HashMap::new(),
+            frame_limit: DEFAULT_FRAME_LIMIT,
         }
     }
 
+    /// Limits how deeply blocks run by `if` and `times` may nest, counting the expression
+    /// itself. A block run by the last word of another block replaces it instead of
+    /// nesting, so tail recursion like `[ ... $loop $n 0 < if ] 'loop set` runs in
+    /// a single frame however long it loops.
+    pub fn with_frame_limit(mut self, frame_limit: usize) -> Self {
+        self.frame_limit = frame_limit;
+        self
+    }
+
     pub fn stack(&self) -> &[Value] {
         &self.stack[..]
     }
 
+    pub fn variables(&self) -> &HashMap<String, Value> {
+        &self.variables
+    }
+
+    /// Evaluates whitespace-separated tokens of `expr` against the current stack.
+    ///
+    /// Binary operators take the top of the stack as their left operand, so `1 2 -`
+    /// evaluates to `1` and `1 2 <` to `0`. Comparisons `=`, `<` and `>` push `1` for
+    /// true and `0` for false. `=` compares any two values, with symbols compared by
+    /// name, while `<` and `>` accept numbers only.
+    ///
+    /// `[ ... ]` pushes the enclosed tokens as a block without evaluating them; blocks
+    /// may be nested. `[ ... ] cond if` evaluates the block when `cond` is non-zero and
+    /// `[ ... ] n times` evaluates it `n` times.
+    ///
+    /// Panics on an unknown token, a type error, a stack underflow, an unbalanced
+    /// bracket or exceeding the frame limit, see `with_frame_limit`.
     pub fn eval(&mut self, expr: &str) {
-        let tokens: Vec<&str> = expr.split_whitespace().collect();
+        let tokens = expr.split_whitespace().map(str::to_string).collect();
+        self.eval_tokens(tokens);
+    }
+
+    /// Runs frames from a work stack rather than recursively, so that deep recursion
+    /// of blocks hits the frame limit instead of overflowing the call stack.
+    fn eval_tokens(&mut self, tokens: Vec<String>) {
+        let mut frames = vec![Frame::new(tokens, 0)];
+        while let Some(frame) = frames.last_mut() {
+            let Some(token) = frame.next_token() else {
+                frames.pop();
+                continue;
+            };
+
+            if token == "[" {
+                let (block, block_end) = Self::parse_block(&frame.tokens, frame.index);
+                self.stack.push(Value::Block(block));
+                frame.index = block_end;
+                continue;
+            }
 
-        for token in tokens {
             if let Ok(number) = token.parse::<f64>() {
                 self.stack.push(Value::Number(number));
                 continue;
             }
 
-            match token {
-                "+" => self.handle_arithmetic_operation(Self::sum),
-                "-" => self.handle_arithmetic_operation(Self::subtract),
-                "*" => self.handle_arithmetic_operation(Self::multiply),
-                "/" => self.handle_arithmetic_operation(Self::divide),
-                "set" => self.set_variable(),
-                number if number.parse::<f64>().is_ok() => {
-                    self.handle_number(number.parse::<f64>().unwrap())
-                }
-                apostrophe_variable_name
-                    if apostrophe_variable_name.strip_prefix('\'').is_some() =>
-                {
-                    self.push_variable_name(apostrophe_variable_name.strip_prefix('\'').unwrap())
+            let call = match token.as_str() {
+                "if" => self.handle_if(),
+                "times" => self.handle_times(),
+                word => {
+                    self.eval_word(word);
+                    None
                 }
-                dollar_variable_name if dollar_variable_name.strip_prefix('$').is_some() => self
-                    .lookup_and_push_variable_value(
-                        dollar_variable_name.strip_prefix('$').unwrap(),
-                    ),
-                something => panic!("invalid token: {something}"),
+            };
+            if let Some(call) = call {
+                self.push_frame(&mut frames, call);
+            }
+        }
+    }
+
+    fn push_frame(&self, frames: &mut Vec<Frame>, frame: Frame) {
+        if frames.last().is_some_and(Frame::is_finished) {
+            frames.pop();
+        }
+        if frames.len() == self.frame_limit {
+            panic!(
+                "frame limit of {} exceeded, is there an infinite recursion?",
+                self.frame_limit
+            );
+        }
+        frames.push(frame);
+    }
+
+    fn eval_word(&mut self, word: &str) {
+        match word {
+            "+" => self.handle_arithmetic_operation(Self::sum),
+            "-" => self.handle_arithmetic_operation(Self::subtract),
+            "*" => self.handle_arithmetic_operation(Self::multiply),
+            "/" => self.handle_arithmetic_operation(Self::divide),
+            "=" => self.handle_equality(),
+            "<" => self.handle_comparison(Self::less),
+            ">" => self.handle_comparison(Self::greater),
+            "dup" => self.dup(),
+            "drop" => self.drop(),
+            "swap" => self.swap(),
+            "over" => self.over(),
+            "set" => self.set_variable(),
+            "]" => panic!("unexpected ']' without matching '['"),
+            number if number.parse::<f64>().is_ok() => {
+                self.handle_number(number.parse::<f64>().unwrap())
+            }
+            apostrophe_variable_name if apostrophe_variable_name.strip_prefix('\'').is_some() => {
+                self.push_variable_name(apostrophe_variable_name.strip_prefix('\'').unwrap())
+            }
+            dollar_variable_name if dollar_variable_name.strip_prefix('$').is_some() => {
+                self.lookup_and_push_variable_value(dollar_variable_name.strip_prefix('$').unwrap())
+            }
+            something => panic!("invalid token: {something}"),
+        }
+    }
+
+    /// Returns tokens of the block starting at `start` (right after its `[`) and the index
+    /// right after the matching `]`.
+    fn parse_block(tokens: &[String], start: usize) -> (Vec<String>, usize) {
+        let mut depth = 1;
+        for (index, token) in tokens.iter().enumerate().skip(start) {
+            match token.as_str() {
+                "[" => depth += 1,
+                "]" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return (tokens[start..index].to_vec(), index + 1);
             }
         }
+        panic!("unclosed '['")
+    }
+
+    fn pop_block(&mut self, word: &str) -> Vec<String> {
+        match self.pop(word) {
+            Value::Block(block) => block,
+            value => panic!("expected a block in '{word}', but found {value}"),
+        }
+    }
+
+    fn handle_if(&mut self) -> Option<Frame> {
+        let condition = self.pop("if");
+        let condition = self.get_operand_value(Some(condition));
+        let block = self.pop_block("if");
+
+        (condition != 0.).then(|| Frame::new(block, 0))
+    }
+
+    fn handle_times(&mut self) -> Option<Frame> {
+        let count = self.pop("times");
+        let count = self.get_operand_value(Some(count));
+        let block = self.pop_block("times");
+
+        if count < 0. || count.fract() != 0. {
+            panic!("expected a non-negative integer count in 'times', but found {count}");
+        }
+        (count >= 1.).then(|| Frame::new(block, count as usize - 1))
     }
 
     fn handle_arithmetic_operation(&mut self, operation: fn(a: f64, b: f64) -> f64) {
@@ -86,6 +259,62 @@ impl Interpreter {
             .push(Value::Number(operation(operand_1, operand_2)))
     }
 
+    fn handle_equality(&mut self) {
+        let value_1 = self.pop("=");
+        let value_2 = self.pop("=");
+
+        self.push_bool(value_1 == value_2)
+    }
+
+    fn handle_comparison(&mut self, comparison: fn(a: f64, b: f64) -> bool) {
+        let value_1 = self.pop("comparison");
+        let value_2 = self.pop("comparison");
+
+        match (value_1, value_2) {
+            (Value::Number(a), Value::Number(b)) => self.push_bool(comparison(a, b)),
+            (a, b) => panic!("cannot compare {a} and {b}, expected numbers"),
+        }
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.stack.push(Value::Number(if value { 1. } else { 0. }))
+    }
+
+    fn pop(&mut self, word: &str) -> Value {
+        match self.stack.pop() {
+            Some(value) => value,
+            None => panic!("stack underflow in '{word}'"),
+        }
+    }
+
+    fn peek(&self, depth: usize, word: &str) -> &Value {
+        match self.stack.len().checked_sub(depth + 1) {
+            Some(index) => &self.stack[index],
+            None => panic!("stack underflow in '{word}'"),
+        }
+    }
+
+    fn dup(&mut self) {
+        let value = self.peek(0, "dup").clone();
+        self.stack.push(value)
+    }
+
+    fn drop(&mut self) {
+        self.pop("drop");
+    }
+
+    fn swap(&mut self) {
+        self.peek(1, "swap");
+
+        let len = self.stack.len();
+        self.stack.swap(len - 1, len - 2)
+    }
+
+    fn over(&mut self) {
+        let value = self.peek(1, "over").clone();
+        self.stack.push(value)
+    }
+
     fn get_operand_value(&self, operand: Option<Value>) -> f64 {
         match operand {
             Some(Value::Number(number)) => number,
@@ -141,4 +370,12 @@ impl Interpreter {
     fn divide(a: f64, b: f64) -> f64 {
         a / b
     }
+
+    fn less(a: f64, b: f64) -> bool {
+        a < b
+    }
+
+    fn greater(a: f64, b: f64) -> bool {
+        a > b
+    }
 }
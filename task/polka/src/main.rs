@@ -1,27 +1,19 @@
 #![forbid(unsafe_code)]
 
-use std::io::{stdin, stdout, BufRead, Write};
-
-fn print_values(values: &[polka::Value]) {
-    print!("[");
-    if let Some(value) = values.first() {
-        print!("{}", value);
-        for value in &values[1..] {
-            print!(", {}", value);
-        }
-    }
-    println!("]");
-}
+use std::{
+    io::{stdin, stdout},
+    panic,
+};
 
 fn main() {
-    print!("> ");
-    stdout().flush().unwrap();
+    // Evaluation errors are reported by the REPL itself, other panics as usual.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !polka::repl::is_evaluating() {
+            default_hook(info)
+        }
+    }));
 
     let mut inter = polka::Interpreter::new();
-    for line in stdin().lock().lines() {
-        inter.eval(&line.unwrap());
-        print_values(inter.stack());
-        print!("> ");
-        stdout().flush().unwrap();
-    }
+    polka::repl::run(&mut inter, stdin().lock(), stdout()).unwrap();
 }
@@ -0,0 +1,85 @@
+//! Line-oriented read-eval-print loop over an [`Interpreter`].
+
+use std::{
+    cell::Cell,
+    io::{self, BufRead, Write},
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+use crate::{Interpreter, Value};
+
+const PROMPT: &str = "> ";
+
+/// Evaluates `input` line by line, printing the stack after every line to `output`.
+///
+/// Evaluation errors are printed and do not stop the loop; the stack keeps whatever
+/// the failed line managed to do before the error. Besides expressions, two commands
+/// are supported: `:vars` lists defined variables and `:quit` exits.
+pub fn run(inter: &mut Interpreter, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    write!(output, "{PROMPT}")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        match line.trim() {
+            ":quit" => break,
+            ":vars" => print_variables(inter, &mut output)?,
+            expr => match eval_caught(inter, expr) {
+                Ok(()) => print_values(inter.stack(), &mut output)?,
+                Err(payload) => {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown error".to_string());
+                    writeln!(output, "error: {message}")?;
+                }
+            },
+        }
+
+        write!(output, "{PROMPT}")?;
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+thread_local! {
+    static EVALUATING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether `run` is evaluating a line on this thread. Its panics are evaluation errors,
+/// which `run` catches and prints itself, so a panic hook can keep quiet about them.
+pub fn is_evaluating() -> bool {
+    EVALUATING.get()
+}
+
+/// Evaluates `expr`, catching the panic on an error, see `is_evaluating`.
+fn eval_caught(inter: &mut Interpreter, expr: &str) -> thread::Result<()> {
+    EVALUATING.set(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| inter.eval(expr)));
+    EVALUATING.set(false);
+    result
+}
+
+fn print_values(values: &[Value], output: &mut impl Write) -> io::Result<()> {
+    write!(output, "[")?;
+    if let Some(value) = values.first() {
+        write!(output, "{}", value)?;
+        for value in &values[1..] {
+            write!(output, ", {}", value)?;
+        }
+    }
+    writeln!(output, "]")
+}
+
+fn print_variables(inter: &Interpreter, output: &mut impl Write) -> io::Result<()> {
+    let mut variables = inter.variables().iter().collect::<Vec<_>>();
+    variables.sort_unstable_by_key(|(name, _)| *name);
+
+    for (name, value) in variables {
+        writeln!(output, "{name} = {value}")?;
+    }
+    Ok(())
+}
@@ -1,7 +1,9 @@
-use polka::{Interpreter, Value};
+use polka::{repl, Interpreter, Value};
 
 use pretty_assertions::assert_eq;
 
+use std::{collections::HashMap, io::Cursor};
+
 fn test(inter: &mut Interpreter, expr: &str, stack: &[Value]) {
     inter.eval(expr);
     assert_eq!(inter.stack(), stack);
@@ -93,3 +95,334 @@ fn test_empty_stack() {
     let mut inter = Interpreter::new();
     inter.eval("1 +");
 }
+
+#[test]
+fn test_dup() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "3 dup", &[Value::Number(3.), Value::Number(3.)]);
+    test(&mut inter, "* dup +", &[Value::Number(18.)]);
+    test(
+        &mut inter,
+        "'x dup",
+        &[
+            Value::Number(18.),
+            Value::Symbol("x".to_string()),
+            Value::Symbol("x".to_string()),
+        ],
+    );
+}
+
+#[test]
+fn test_drop() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "1 2 drop", &[Value::Number(1.)]);
+    test(&mut inter, "'x drop drop", &[]);
+}
+
+#[test]
+fn test_swap() {
+    let mut inter = Interpreter::new();
+    test(
+        &mut inter,
+        "1 2 swap",
+        &[Value::Number(2.), Value::Number(1.)],
+    );
+    test(&mut inter, "swap -", &[Value::Number(1.)]);
+    test(
+        &mut inter,
+        "'x swap",
+        &[Value::Symbol("x".to_string()), Value::Number(1.)],
+    );
+}
+
+#[test]
+fn test_over() {
+    let mut inter = Interpreter::new();
+    test(
+        &mut inter,
+        "1 2 over",
+        &[Value::Number(1.), Value::Number(2.), Value::Number(1.)],
+    );
+    test(&mut inter, "+ +", &[Value::Number(4.)]);
+    test(
+        &mut inter,
+        "'x over",
+        &[
+            Value::Number(4.),
+            Value::Symbol("x".to_string()),
+            Value::Number(4.),
+        ],
+    );
+}
+
+#[test]
+fn test_comparison() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "1 2 <", &[Value::Number(0.)]);
+    test(&mut inter, "drop 2 1 <", &[Value::Number(1.)]);
+    test(&mut inter, "drop 1 2 >", &[Value::Number(1.)]);
+    test(&mut inter, "drop 2 2 >", &[Value::Number(0.)]);
+    test(&mut inter, "drop 2 2 <", &[Value::Number(0.)]);
+    test(&mut inter, "drop 2 2 =", &[Value::Number(1.)]);
+    test(&mut inter, "drop 2 3 =", &[Value::Number(0.)]);
+}
+
+#[test]
+fn test_symbol_equality() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "'x 'x =", &[Value::Number(1.)]);
+    test(
+        &mut inter,
+        "'x 'y =",
+        &[Value::Number(1.), Value::Number(0.)],
+    );
+    test(
+        &mut inter,
+        "'x 1 =",
+        &[Value::Number(1.), Value::Number(0.), Value::Number(0.)],
+    );
+}
+
+#[test]
+fn test_stack_words_combined() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "5 dup * 'x set", &[]);
+    test(
+        &mut inter,
+        "$x 10 over over <",
+        &[Value::Number(25.), Value::Number(10.), Value::Number(1.)],
+    );
+    test(&mut inter, "drop swap drop 10 =", &[Value::Number(1.)]);
+}
+
+#[test]
+#[should_panic]
+fn test_symbol_comparison_error() {
+    let mut inter = Interpreter::new();
+    inter.eval("'x 'y <");
+}
+
+#[test]
+#[should_panic]
+fn test_dup_underflow() {
+    let mut inter = Interpreter::new();
+    inter.eval("dup");
+}
+
+#[test]
+#[should_panic]
+fn test_drop_underflow() {
+    let mut inter = Interpreter::new();
+    inter.eval("drop");
+}
+
+#[test]
+#[should_panic]
+fn test_swap_underflow() {
+    let mut inter = Interpreter::new();
+    inter.eval("1 swap");
+}
+
+#[test]
+#[should_panic]
+fn test_over_underflow() {
+    let mut inter = Interpreter::new();
+    inter.eval("1 over");
+}
+
+#[test]
+#[should_panic]
+fn test_comparison_underflow() {
+    let mut inter = Interpreter::new();
+    inter.eval("1 <");
+}
+
+#[test]
+#[should_panic]
+fn test_equality_underflow() {
+    let mut inter = Interpreter::new();
+    inter.eval("=");
+}
+
+fn block(tokens: &[&str]) -> Value {
+    Value::Block(tokens.iter().map(|t| t.to_string()).collect())
+}
+
+#[test]
+fn test_block() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "[ 1 2 + ]", &[block(&["1", "2", "+"])]);
+    test(&mut inter, "drop [ ]", &[block(&[])]);
+    assert_eq!(block(&["1", "dup"]).to_string(), "[ 1 dup ]");
+}
+
+#[test]
+fn test_nested_blocks() {
+    let mut inter = Interpreter::new();
+    test(
+        &mut inter,
+        "[ 1 [ 2 [ 3 ] ] 4 ]",
+        &[block(&["1", "[", "2", "[", "3", "]", "]", "4"])],
+    );
+    test(
+        &mut inter,
+        "1 if",
+        &[
+            Value::Number(1.),
+            block(&["2", "[", "3", "]"]),
+            Value::Number(4.),
+        ],
+    );
+    test(
+        &mut inter,
+        "drop 1 if",
+        &[Value::Number(1.), Value::Number(2.), block(&["3"])],
+    );
+}
+
+#[test]
+fn test_if() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "[ 10 ] 1 if", &[Value::Number(10.)]);
+    test(&mut inter, "[ 20 ] 0 if", &[Value::Number(10.)]);
+    test(&mut inter, "[ 2 * ] 3 2 > if", &[Value::Number(10.)]);
+    test(&mut inter, "[ 2 * ] 2 3 > if", &[Value::Number(20.)]);
+    test(&mut inter, "5 'c set [ 1 + ] 'c if", &[Value::Number(21.)]);
+}
+
+#[test]
+fn test_times() {
+    let mut inter = Interpreter::new();
+    test(&mut inter, "1 [ 2 * ] 10 times", &[Value::Number(1024.)]);
+    test(&mut inter, "[ 2 * ] 0 times", &[Value::Number(1024.)]);
+    test(
+        &mut inter,
+        "[ dup ] 2 times",
+        &[
+            Value::Number(1024.),
+            Value::Number(1024.),
+            Value::Number(1024.),
+        ],
+    );
+}
+
+#[test]
+fn test_nested_control_flow() {
+    let mut inter = Interpreter::new();
+    test(
+        &mut inter,
+        "0 [ [ 1 + ] 3 times ] 4 times",
+        &[Value::Number(12.)],
+    );
+    test(
+        &mut inter,
+        "[ [ 100 + ] 1 if ] 2 times",
+        &[Value::Number(212.)],
+    );
+}
+
+/// Counts `n` down to 0, the recursive call is the last word of the block.
+const COUNTDOWN: &str = "[ 1 $n - 'n set $loop $n 0 < if ] 'loop set";
+
+#[test]
+fn test_tail_recursion() {
+    let mut inter = Interpreter::new().with_frame_limit(3);
+    inter.eval(COUNTDOWN);
+    test(
+        &mut inter,
+        "100000 'n set $loop 1 if $n",
+        &[Value::Number(0.)],
+    );
+
+    // Tail calls from a block run by `times` don't accumulate frames either.
+    test(
+        &mut inter,
+        "drop 0 'k set [ 1000 'n set $loop 1 if 1 $k + 'k set ] 100 times $k",
+        &[Value::Number(100.)],
+    );
+}
+
+#[test]
+fn test_deep_recursion() {
+    let mut inter = Interpreter::new();
+    inter.eval("[ 1 $n - 'n set $loop $n 0 < if 1 + ] 'loop set 0 50000 'n set");
+    test(&mut inter, "$loop 1 if", &[Value::Number(50000.)]);
+}
+
+#[test]
+#[should_panic(expected = "frame limit of 1000 exceeded")]
+fn test_frame_limit() {
+    let mut inter = Interpreter::new().with_frame_limit(1000);
+    inter.eval("[ 1 $n - 'n set $loop $n 0 < if 0 drop ] 'loop set 2000 'n set");
+    inter.eval("$loop 1 if");
+}
+
+#[test]
+fn test_frame_limit_is_not_reached() {
+    let mut inter = Interpreter::new().with_frame_limit(1000);
+    inter.eval("[ 1 $n - 'n set $loop $n 0 < if 0 drop ] 'loop set 998 'n set");
+    test(&mut inter, "$loop 1 if $n", &[Value::Number(0.)]);
+}
+
+#[test]
+#[should_panic]
+fn test_unclosed_block() {
+    let mut inter = Interpreter::new();
+    inter.eval("[ 1 [ 2 ]");
+}
+
+#[test]
+#[should_panic]
+fn test_unexpected_block_end() {
+    let mut inter = Interpreter::new();
+    inter.eval("1 ]");
+}
+
+#[test]
+#[should_panic]
+fn test_if_without_block() {
+    let mut inter = Interpreter::new();
+    inter.eval("2 1 if");
+}
+
+#[test]
+#[should_panic]
+fn test_times_negative_count() {
+    let mut inter = Interpreter::new();
+    inter.eval("[ 1 ] -1 times");
+}
+
+#[test]
+fn test_variables_accessor() {
+    let mut inter = Interpreter::new();
+    assert!(inter.variables().is_empty());
+
+    inter.eval("4 'x set 'x 'y set");
+    assert_eq!(
+        inter.variables(),
+        &HashMap::from([
+            ("x".to_string(), Value::Number(4.)),
+            ("y".to_string(), Value::Symbol("x".to_string())),
+        ])
+    );
+}
+
+#[test]
+fn test_repl() {
+    let input = "1 2 +\n'x\nset :vars\n:vars\n1 +\n$x dup\n:quit\n10\n";
+    let mut output = Vec::new();
+    let mut inter = Interpreter::new();
+    repl::run(&mut inter, Cursor::new(input), &mut output).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "> [3]\n\
+         > [3, 'x]\n\
+         > error: invalid token: :vars\n\
+         > x = 3\n\
+         > error: incorrect operand\n\
+         > [3, 3]\n\
+         > "
+    );
+    assert_eq!(inter.stack(), &[Value::Number(3.), Value::Number(3.)]);
+}
@@ -1,12 +1,26 @@
 #![forbid(unsafe_code)]
 
-use std::rc::Rc;
+use std::{fmt, rc::Rc};
 
 struct Node<T> {
     data: Rc<T>,
     next: Option<Rc<Node<T>>>,
 }
 
+/// Unlinks the following nodes one by one, as dropping them recursively overflows the
+/// call stack on long stacks. Stops at the first node still shared with another stack.
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        let mut next = self.next.take();
+        while let Some(node) = next {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => next = node.next.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
 pub struct PStack<T> {
     head: Option<Rc<Node<T>>>,
     size: usize,
@@ -45,6 +59,13 @@ impl<T> PStack<T> {
         }
     }
 
+    /// Pushes the values in the order of `values`, so the last one ends up on top.
+    pub fn push_many(&self, values: impl IntoIterator<Item = T>) -> Self {
+        let mut stack = self.clone();
+        stack.extend(values);
+        stack
+    }
+
     pub fn pop(&self) -> Option<(Rc<T>, Self)> {
         self.head.as_ref().map(|head| {
             (
@@ -57,6 +78,27 @@ impl<T> PStack<T> {
         })
     }
 
+    /// Returns the top value, like `pop` does, without the rest of the stack.
+    pub fn peek(&self) -> Option<Rc<T>> {
+        self.head.as_ref().map(|head| Rc::clone(&head.data))
+    }
+
+    /// Returns a stack of the same values in the reversed order, in O(n). The nodes are
+    /// new, so the stacks don't share any structure, while the values themselves are shared.
+    pub fn reverse(&self) -> Self {
+        let mut reversed = Self::new();
+        for data in self.iter() {
+            reversed = PStack {
+                head: Some(Rc::new(Node {
+                    data,
+                    next: reversed.head,
+                })),
+                size: reversed.size + 1,
+            };
+        }
+        reversed
+    }
+
     pub fn len(&self) -> usize {
         self.size
     }
@@ -89,3 +131,53 @@ impl<T> Iterator for PStackIterator<T> {
         }
     }
 }
+
+/// Values are pushed in the order of the iterator, so the last one ends up on top and
+/// `iter` yields them in the reversed order.
+impl<T> FromIterator<T> for PStack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut stack = Self::new();
+        stack.extend(values);
+        stack
+    }
+}
+
+/// Pushes the values in the order of the iterator, so the last one ends up on top.
+impl<T> Extend<T> for PStack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            *self = self.push(value);
+        }
+    }
+}
+
+/// Lists the values from the top.
+impl<T: fmt::Debug> fmt::Debug for PStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Stacks are equal if they have equal values in the same order. Once both stacks
+/// reach a shared node, the rest of them is equal without comparing the values.
+impl<T: PartialEq> PartialEq for PStack<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.size != other.size {
+            return false;
+        }
+
+        let (mut lhs, mut rhs) = (&self.head, &other.head);
+        while let (Some(lhs_node), Some(rhs_node)) = (lhs, rhs) {
+            if Rc::ptr_eq(lhs_node, rhs_node) {
+                return true;
+            }
+            if lhs_node.data != rhs_node.data {
+                return false;
+            }
+            (lhs, rhs) = (&lhs_node.next, &rhs_node.next);
+        }
+        true
+    }
+}
+
+impl<T: Eq> Eq for PStack<T> {}
@@ -1,5 +1,7 @@
 use pstack::PStack;
 
+use std::{cell::Cell, rc::Rc};
+
 #[test]
 fn test_simple() {
     let mut stack = PStack::new();
@@ -101,3 +103,161 @@ fn test_iter_parallel() {
         assert_eq!(iter_two.next().as_deref().copied(), Some(200 - i - 1));
     }
 }
+
+#[test]
+fn test_peek() {
+    let stack = PStack::new();
+    assert_eq!(stack.peek(), None);
+
+    let stack = stack.push(1).push(2);
+    assert_eq!(stack.peek().as_deref(), Some(&2));
+    assert_eq!(stack.len(), 2);
+
+    let (top, tail) = stack.pop().unwrap();
+    assert!(Rc::ptr_eq(&top, &stack.peek().unwrap()));
+    assert_eq!(tail.peek().as_deref(), Some(&1));
+}
+
+#[test]
+fn test_from_iter() {
+    let stack = (0..5).collect::<PStack<_>>();
+    assert_eq!(stack.len(), 5);
+    assert_eq!(stack.peek().as_deref(), Some(&4));
+    assert_eq!(
+        stack.iter().map(|value| *value).collect::<Vec<_>>(),
+        [4, 3, 2, 1, 0]
+    );
+
+    let mut extended = stack.clone();
+    extended.extend([5, 6]);
+    assert_eq!(extended.len(), 7);
+    assert_eq!(extended, (0..7).collect());
+    assert_eq!(stack.push_many(5..7), extended);
+
+    // The original stack is untouched.
+    assert_eq!(stack, (0..5).collect());
+    assert_eq!(stack.push_many([]), stack);
+}
+
+#[test]
+fn test_reverse() {
+    let stack = (0..100).collect::<PStack<_>>();
+    let reversed = stack.reverse();
+    assert_eq!(reversed.len(), 100);
+    assert_eq!(
+        reversed.iter().map(|value| *value).collect::<Vec<_>>(),
+        (0..100).collect::<Vec<_>>()
+    );
+    assert_eq!(reversed.reverse(), stack);
+    assert!(Rc::ptr_eq(
+        &stack.peek().unwrap(),
+        &reversed.iter().last().unwrap()
+    ));
+
+    assert!(PStack::<i32>::new().reverse().is_empty());
+}
+
+#[test]
+fn test_clone_persistence() {
+    let stack = (0..10).collect::<PStack<_>>();
+    let copy = stack.clone();
+
+    let pushed = copy.push(10);
+    let (_, popped) = copy.pop().unwrap();
+    assert_eq!(pushed.len(), 11);
+    assert_eq!(popped.len(), 9);
+
+    assert_eq!(stack.len(), 10);
+    assert_eq!(stack, copy);
+    assert_eq!(
+        copy.iter().map(|value| *value).collect::<Vec<_>>(),
+        (0..10).rev().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_debug() {
+    let stack = PStack::new().push("a").push("b");
+    assert_eq!(format!("{stack:?}"), r#"["b", "a"]"#);
+    assert_eq!(format!("{:?}", PStack::<i32>::new()), "[]");
+}
+
+#[test]
+fn test_equality() {
+    struct Tracked<'a> {
+        value: i32,
+        comparisons: &'a Cell<usize>,
+    }
+
+    impl PartialEq for Tracked<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            self.comparisons.set(self.comparisons.get() + 1);
+            self.value == other.value
+        }
+    }
+
+    let comparisons = Cell::new(0);
+    let tracked = |value| Tracked {
+        value,
+        comparisons: &comparisons,
+    };
+
+    let base = (0..1000).map(tracked).collect::<PStack<_>>();
+    assert!(base == base.clone());
+    assert_eq!(comparisons.get(), 0);
+
+    // Only the values above the shared tail are compared.
+    assert!(base.push(tracked(1)).push(tracked(2)) == base.push(tracked(1)).push(tracked(2)));
+    assert_eq!(comparisons.get(), 2);
+
+    assert!(base.push(tracked(1)) != base.push(tracked(2)));
+    assert_eq!(comparisons.get(), 3);
+
+    // Stacks of different sizes are never equal.
+    assert!(base != base.push(tracked(1)));
+    assert_eq!(comparisons.get(), 3);
+
+    let copy = (0..1000).map(tracked).collect::<PStack<_>>();
+    assert!(base == copy);
+    assert_eq!(comparisons.get(), 1003);
+}
+
+#[test]
+fn test_drop_long_stack() {
+    let stack = (0..1_000_000).collect::<PStack<_>>();
+    assert_eq!(stack.len(), 1_000_000);
+    drop(stack);
+
+    // The iterator owns the nodes once the stack is gone.
+    let mut iter = (0..1_000_000).collect::<PStack<_>>().iter();
+    assert_eq!(iter.next().map(|top| *top), Some(999_999));
+    drop(iter);
+}
+
+#[test]
+fn test_drop_shared_tail() {
+    let make = || {
+        let tail = (0..500_000).map(Rc::new).collect::<PStack<_>>();
+        let first = tail.push_many((0..10).map(Rc::new));
+        let second = tail.push(Rc::new(-1));
+        let value = tail.peek().unwrap();
+        drop(tail);
+        (first, second, value)
+    };
+
+    for first_dropped in [false, true] {
+        let (first, second, value) = make();
+        let (dropped, kept) = match first_dropped {
+            true => (first, second),
+            false => (second, first),
+        };
+        let kept_len = kept.len();
+        drop(dropped);
+
+        assert_eq!(Rc::strong_count(&value), 2);
+        assert_eq!(kept.iter().count(), kept_len);
+        assert!(kept.iter().skip(kept_len - 1).all(|last| **last == 0));
+        drop(kept);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
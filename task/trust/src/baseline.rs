@@ -0,0 +1,251 @@
+//! Hall of fame: recording how an agent does against a set of opponents, saving it, and
+//! comparing a later version of the agent against the record.
+//!
+//! Agents are created by factories from seeds derived from the seed of the record, so
+//! recording the same agent twice gives the same report. Deltas only reflect changes of
+//! the agent as long as the opponents are deterministic or take their randomness from
+//! the seed they are given.
+
+use crate::{
+    cli::{self, Payoff},
+    seed::SeedHierarchy,
+    Agent,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A factory of an agent from its seed, see `crate::sweep::AgentFactory`.
+pub type AgentFn<'a> = &'a dyn Fn(u64) -> Box<dyn Agent>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OpponentScore {
+    pub opponent: String,
+    /// Per round, so that records of different lengths are comparable.
+    pub average_score: f64,
+    pub opponent_average_score: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BaselineReport {
+    pub name: String,
+    pub rounds: usize,
+    pub seed: u64,
+    /// In the order the opponents were given.
+    pub opponents: Vec<OpponentScore>,
+}
+
+impl BaselineReport {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+pub struct Baseline;
+
+impl Baseline {
+    /// Plays a match of `rounds` rounds against every opponent with the default payoff.
+    /// The agent and the opponent of a match get the seeds of
+    /// `baseline/{opponent}/agent` and `baseline/{opponent}/opponent` under `seed`.
+    pub fn record(
+        name: &str,
+        agent: AgentFn,
+        opponents: &[(&str, AgentFn)],
+        rounds: usize,
+        seed: u64,
+    ) -> BaselineReport {
+        let seeds = SeedHierarchy::new(seed).child("baseline");
+        let opponents = opponents
+            .iter()
+            .map(|&(opponent, make_opponent)| {
+                let seeds = seeds.child(opponent);
+                let result = cli::play_agents(
+                    agent(seeds.derive("agent")),
+                    make_opponent(seeds.derive("opponent")),
+                    rounds,
+                    Payoff::default(),
+                );
+                let average = |score: i32| match rounds {
+                    0 => 0.,
+                    _ => f64::from(score) / rounds as f64,
+                };
+                OpponentScore {
+                    opponent: opponent.to_string(),
+                    average_score: average(result.left_score),
+                    opponent_average_score: average(result.right_score),
+                }
+            })
+            .collect();
+        BaselineReport {
+            name: name.to_string(),
+            rounds,
+            seed,
+            opponents,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Improved,
+    Unchanged,
+    Regressed,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Improved => write!(f, "improved"),
+            Self::Unchanged => write!(f, "unchanged"),
+            Self::Regressed => write!(f, "regressed"),
+        }
+    }
+}
+
+/// An opponent of either report. Scores are unset if the opponent is missing from the
+/// report, and the delta then too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matchup {
+    pub opponent: String,
+    pub baseline_score: Option<f64>,
+    pub current_score: Option<f64>,
+    pub delta: Option<f64>,
+}
+
+impl Matchup {
+    pub fn has_changed(&self) -> bool {
+        self.delta.is_some_and(|delta| delta != 0.)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComparisonReport {
+    pub baseline: String,
+    pub current: String,
+    /// The opponents of the baseline first, then the new ones.
+    pub matchups: Vec<Matchup>,
+    /// The mean delta over the opponents of both reports.
+    pub overall_delta: f64,
+    pub min_improvement: f64,
+    pub verdict: Verdict,
+}
+
+/// The current agent has improved if its scores are higher by more than
+/// `min_improvement` points per round on average, and has regressed if they are lower
+/// by more than that.
+pub fn compare_against_baseline(
+    current: &BaselineReport,
+    baseline: &BaselineReport,
+    min_improvement: f64,
+) -> ComparisonReport {
+    let score = |report: &BaselineReport, opponent: &str| {
+        report
+            .opponents
+            .iter()
+            .find(|score| score.opponent == opponent)
+            .map(|score| score.average_score)
+    };
+
+    let new_opponents = current
+        .opponents
+        .iter()
+        .filter(|new| score(baseline, &new.opponent).is_none());
+    let matchups = baseline
+        .opponents
+        .iter()
+        .chain(new_opponents)
+        .map(|OpponentScore { opponent, .. }| {
+            let baseline_score = score(baseline, opponent);
+            let current_score = score(current, opponent);
+            Matchup {
+                opponent: opponent.clone(),
+                baseline_score,
+                current_score,
+                delta: current_score.zip(baseline_score).map(|(c, b)| c - b),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let deltas = matchups
+        .iter()
+        .filter_map(|matchup| matchup.delta)
+        .collect::<Vec<_>>();
+    let overall_delta = match deltas.len() {
+        0 => 0.,
+        count => deltas.iter().sum::<f64>() / count as f64,
+    };
+    let verdict = if overall_delta > min_improvement {
+        Verdict::Improved
+    } else if overall_delta < -min_improvement {
+        Verdict::Regressed
+    } else {
+        Verdict::Unchanged
+    };
+
+    ComparisonReport {
+        baseline: baseline.name.clone(),
+        current: current.name.clone(),
+        matchups,
+        overall_delta,
+        min_improvement,
+        verdict,
+    }
+}
+
+/// A row per opponent, marked with `+` or `-` if the delta is positive or negative.
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cell = |value: Option<f64>| match value {
+            Some(value) => format!("{value:.2}"),
+            None => "-".to_string(),
+        };
+
+        writeln!(
+            f,
+            "  {:<12}  {:>10}  {:>10}  {:>7}",
+            "opponent", self.baseline, self.current, "delta"
+        )?;
+        for matchup in &self.matchups {
+            let marker = match matchup.delta {
+                Some(delta) if delta > 0. => '+',
+                Some(delta) if delta < 0. => '-',
+                _ => ' ',
+            };
+            let delta = match matchup.delta {
+                Some(delta) => format!("{delta:+.2}"),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{marker} {:<12}  {:>10}  {:>10}  {:>7}",
+                matchup.opponent,
+                cell(matchup.baseline_score),
+                cell(matchup.current_score),
+                delta
+            )?;
+        }
+        writeln!(
+            f,
+            "overall {:+.2} per round: {} (min improvement {:.2})",
+            self.overall_delta, self.verdict, self.min_improvement
+        )
+    }
+}
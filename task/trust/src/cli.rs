@@ -0,0 +1,220 @@
+use crate::{
+    Agent, CheatingAgent, CooperatingAgent, CopycatAgent, DetectiveAgent, Game, GrudgerAgent,
+    RoundOutcome,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::{error::Error, fmt, fmt::Write, str::FromStr};
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const AGENT_NAMES: [&str; 5] = ["cheater", "cooperator", "grudger", "copycat", "detective"];
+
+pub fn make_agent(name: &str) -> Result<Box<dyn Agent>, CliError> {
+    let agent: Box<dyn Agent> = match name {
+        "cheater" => Box::new(CheatingAgent::new()),
+        "cooperator" => Box::new(CooperatingAgent::new()),
+        "grudger" => Box::new(GrudgerAgent::new()),
+        "copycat" => Box::new(CopycatAgent::new()),
+        "detective" => Box::new(DetectiveAgent::new()),
+        _ => return Err(CliError::UnknownAgent(name.to_string())),
+    };
+    Ok(agent)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CliError {
+    UnknownAgent(String),
+    InvalidPayoff(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownAgent(name) => write!(
+                f,
+                "unknown agent '{name}', valid agents are: {}",
+                AGENT_NAMES.join(", ")
+            ),
+            Self::InvalidPayoff(payoff) => write!(
+                f,
+                "invalid payoff '{payoff}', expected four integers 'r,t,s,p'"
+            ),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Score deltas in the classic notation: reward for mutual cooperation, temptation
+/// to cheat, sucker's payoff and punishment for mutual cheating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payoff {
+    pub reward: i32,
+    pub temptation: i32,
+    pub sucker: i32,
+    pub punishment: i32,
+}
+
+impl Payoff {
+    /// Returns score deltas of the left and the right agent.
+    pub fn deltas(&self, outcome: RoundOutcome) -> (i32, i32) {
+        match outcome {
+            RoundOutcome::BothCooperated => (self.reward, self.reward),
+            RoundOutcome::LeftCheated => (self.temptation, self.sucker),
+            RoundOutcome::RightCheated => (self.sucker, self.temptation),
+            RoundOutcome::BothCheated => (self.punishment, self.punishment),
+        }
+    }
+}
+
+/// The payoff used by `Game` itself.
+impl Default for Payoff {
+    fn default() -> Self {
+        Self {
+            reward: 2,
+            temptation: 3,
+            sucker: -1,
+            punishment: 0,
+        }
+    }
+}
+
+impl FromStr for Payoff {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CliError::InvalidPayoff(s.to_string());
+
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match values[..] {
+            [reward, temptation, sucker, punishment] => Ok(Self {
+                reward,
+                temptation,
+                sucker,
+                punishment,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub(crate) struct MatchResult {
+    pub(crate) outcomes: Vec<RoundOutcome>,
+    pub(crate) left_score: i32,
+    pub(crate) right_score: i32,
+}
+
+fn play_match(
+    left: &str,
+    right: &str,
+    rounds: usize,
+    payoff: Payoff,
+) -> Result<MatchResult, CliError> {
+    Ok(play_agents(
+        make_agent(left)?,
+        make_agent(right)?,
+        rounds,
+        payoff,
+    ))
+}
+
+// Scores are recomputed from the outcomes, so that a custom payoff works without
+// touching `Game`.
+pub(crate) fn play_agents(
+    left: Box<dyn Agent>,
+    right: Box<dyn Agent>,
+    rounds: usize,
+    payoff: Payoff,
+) -> MatchResult {
+    let mut game = Game::new(left, right);
+
+    let outcomes = (0..rounds).map(|_| game.play_round()).collect::<Vec<_>>();
+    let (left_score, right_score) = outcomes
+        .iter()
+        .map(|&outcome| payoff.deltas(outcome))
+        .fold((0, 0), |(left, right), (dl, dr)| (left + dl, right + dr));
+
+    MatchResult {
+        outcomes,
+        left_score,
+        right_score,
+    }
+}
+
+fn moves(outcome: RoundOutcome) -> (&'static str, &'static str) {
+    match outcome {
+        RoundOutcome::BothCooperated => ("cooperate", "cooperate"),
+        RoundOutcome::LeftCheated => ("cheat", "cooperate"),
+        RoundOutcome::RightCheated => ("cooperate", "cheat"),
+        RoundOutcome::BothCheated => ("cheat", "cheat"),
+    }
+}
+
+pub fn run_match(
+    left: &str,
+    right: &str,
+    rounds: usize,
+    payoff: Payoff,
+) -> Result<String, CliError> {
+    let result = play_match(left, right, rounds, payoff)?;
+
+    let mut output = String::new();
+    writeln!(output, "{:>5}  {:<10}  {}", "round", left, right).unwrap();
+    for (i, &outcome) in result.outcomes.iter().enumerate() {
+        let (left_move, right_move) = moves(outcome);
+        writeln!(output, "{:>5}  {:<10}  {}", i + 1, left_move, right_move).unwrap();
+    }
+    writeln!(
+        output,
+        "final score: {left} {}, {right} {}",
+        result.left_score, result.right_score
+    )
+    .unwrap();
+
+    Ok(output)
+}
+
+/// Every agent plays every other agent once, standings are sorted by the total score.
+pub fn run_tournament(agents: &[&str], rounds: usize, payoff: Payoff) -> Result<String, CliError> {
+    // Validate the names even if there are no matches.
+    for agent in agents {
+        make_agent(agent)?;
+    }
+
+    let mut scores = vec![0; agents.len()];
+    for i in 0..agents.len() {
+        for j in i + 1..agents.len() {
+            let result = play_match(agents[i], agents[j], rounds, payoff)?;
+            scores[i] += result.left_score;
+            scores[j] += result.right_score;
+        }
+    }
+
+    let mut standings = agents.iter().zip(scores).collect::<Vec<_>>();
+    standings.sort_by(|(lhs_name, lhs_score), (rhs_name, rhs_score)| {
+        rhs_score.cmp(lhs_score).then(lhs_name.cmp(rhs_name))
+    });
+
+    let mut output = String::new();
+    for (place, (name, score)) in standings.iter().enumerate() {
+        writeln!(output, "{:>2}. {:<10}  {:>5}", place + 1, name, score).unwrap();
+    }
+
+    Ok(output)
+}
+
+pub fn list_agents() -> String {
+    AGENT_NAMES.iter().map(|name| format!("{name}\n")).collect()
+}
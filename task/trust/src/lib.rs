@@ -1,5 +1,13 @@
 #![forbid(unsafe_code)]
 
+use serde::{Deserialize, Serialize};
+
+pub mod baseline;
+pub mod cli;
+pub mod net;
+pub mod seed;
+pub mod sweep;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -75,7 +83,8 @@ pub trait Agent {
     fn set_score(&mut self, score: i32);
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Move {
     Cooperate,
     Cheat,
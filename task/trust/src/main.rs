@@ -0,0 +1,79 @@
+use clap::{Parser, Subcommand};
+use trust::cli::{self, Payoff, AGENT_NAMES};
+
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Arguments {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a match between two agents, printing every round.
+    Match {
+        left: String,
+        right: String,
+
+        #[arg(short, long, default_value_t = 10)]
+        rounds: usize,
+
+        /// Score deltas as 'reward,temptation,sucker,punishment'.
+        #[arg(long, default_value = "2,3,-1,0")]
+        payoff: Payoff,
+    },
+    /// Play all pairs among the agents and print the standings.
+    Tournament {
+        #[arg(short, long, default_value_t = 10)]
+        rounds: usize,
+
+        /// Comma-separated agent names, all agents by default.
+        #[arg(long, value_delimiter = ',')]
+        agents: Vec<String>,
+
+        /// Score deltas as 'reward,temptation,sucker,punishment'.
+        #[arg(long, default_value = "2,3,-1,0")]
+        payoff: Payoff,
+    },
+    /// Print names of the available agents.
+    ListAgents,
+}
+
+fn main() -> ExitCode {
+    let args = Arguments::parse();
+
+    let result = match args.command {
+        Command::Match {
+            left,
+            right,
+            rounds,
+            payoff,
+        } => cli::run_match(&left, &right, rounds, payoff),
+        Command::Tournament {
+            rounds,
+            agents,
+            payoff,
+        } => {
+            let agents = if agents.is_empty() {
+                AGENT_NAMES.to_vec()
+            } else {
+                agents.iter().map(String::as_str).collect()
+            };
+            cli::run_tournament(&agents, rounds, payoff)
+        }
+        Command::ListAgents => Ok(cli::list_agents()),
+    };
+
+    match result {
+        Ok(output) => {
+            print!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Matches over TCP in the manner of paperio: JSON messages tagged with their `type`, one
+//! per line. The server plays the left agent and drives the match, the client plays the
+//! right one, see `MatchServer` and `connect_and_play`.
+//!
+//! The server starts with `StartMatch`, then sends a `RoundRequest` every round and waits
+//! for a `RoundReply`, and ends with `EndMatch`. The client is trusted to play by the
+//! rules, but not to keep the connection in order:
+//!
+//! * A reply which isn't a `RoundReply`, e.g. not even JSON, counts as `Move::Cheat` for
+//!   the round.
+//! * If the reply doesn't come within the round timeout, or the connection fails, the
+//!   client forfeits: it's taken to cheat in that round and all the remaining ones, and
+//!   isn't asked anymore. It still gets `EndMatch` if the connection allows.
+
+use crate::{cli::Payoff, Agent, Move, RoundOutcome};
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "params", rename_all = "snake_case")]
+pub enum TrustMessage {
+    StartMatch {
+        rounds: usize,
+        payoff: Payoff,
+    },
+    /// Rounds are numbered from 1. The opponent's move is the one of the previous round.
+    RoundRequest {
+        round: usize,
+        opponent_previous: Option<Move>,
+    },
+    RoundReply {
+        mv: Move,
+    },
+    EndMatch {
+        your_score: i32,
+        opponent_score: i32,
+    },
+}
+
+/// Reads a line. The end of the stream is `io::ErrorKind::UnexpectedEof`, and a line
+/// which isn't a message is `io::ErrorKind::InvalidData`.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<TrustMessage> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes the message as a line and flushes it, so that the peer can answer.
+pub fn write_message(writer: &mut impl Write, message: &TrustMessage) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The server's account of a match, the remote agent being the right one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteMatchResult {
+    pub outcomes: Vec<RoundOutcome>,
+    pub left_score: i32,
+    pub right_score: i32,
+    /// The round the remote agent has forfeited the match at, see the module docs.
+    pub forfeited_at: Option<usize>,
+}
+
+/// Serves a single match to the first client which connects, see the module docs.
+pub struct MatchServer {
+    listener: TcpListener,
+    rounds: usize,
+    payoff: Payoff,
+    round_timeout: Duration,
+}
+
+impl MatchServer {
+    pub const DEFAULT_ROUND_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// 10 rounds with the default payoff, as `SweepSpec` has it.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            rounds: 10,
+            payoff: Payoff::default(),
+            round_timeout: Self::DEFAULT_ROUND_TIMEOUT,
+        })
+    }
+
+    pub fn with_rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    pub fn with_payoff(mut self, payoff: Payoff) -> Self {
+        self.payoff = payoff;
+        self
+    }
+
+    /// How long the client may think over a round before it forfeits.
+    pub fn with_round_timeout(mut self, timeout: Duration) -> Self {
+        self.round_timeout = timeout;
+        self
+    }
+
+    /// E.g. the port picked for port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for a client and plays the match against it with the agent `left_factory`
+    /// makes once the client has connected. Fails only if the client fails to connect
+    /// or to receive `StartMatch`.
+    pub fn serve_match(
+        self,
+        left_factory: impl FnOnce() -> Box<dyn Agent>,
+    ) -> io::Result<RemoteMatchResult> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_read_timeout(Some(self.round_timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let mut left = left_factory();
+
+        let start = TrustMessage::StartMatch {
+            rounds: self.rounds,
+            payoff: self.payoff,
+        };
+        write_message(&mut writer, &start)?;
+
+        let mut result = RemoteMatchResult {
+            outcomes: Vec::with_capacity(self.rounds),
+            left_score: 0,
+            right_score: 0,
+            forfeited_at: None,
+        };
+        let mut left_previous = None;
+        for round in 1..=self.rounds {
+            let left_move = left.play_round();
+            let right_move = match result.forfeited_at {
+                Some(_) => Move::Cheat,
+                None => {
+                    let request = TrustMessage::RoundRequest {
+                        round,
+                        opponent_previous: left_previous,
+                    };
+                    match remote_move(&mut reader, &mut writer, &request) {
+                        Ok(mv) => mv,
+                        Err(_) => {
+                            result.forfeited_at = Some(round);
+                            Move::Cheat
+                        }
+                    }
+                }
+            };
+            left.update(right_move);
+            left_previous = Some(left_move);
+
+            let outcome = outcome(left_move, right_move);
+            let (left_delta, right_delta) = self.payoff.deltas(outcome);
+            result.left_score += left_delta;
+            result.right_score += right_delta;
+            result.outcomes.push(outcome);
+        }
+        left.set_score(result.left_score);
+
+        let end = TrustMessage::EndMatch {
+            your_score: result.right_score,
+            opponent_score: result.left_score,
+        };
+        // A client which has forfeited may be gone already.
+        write_message(&mut writer, &end).ok();
+        Ok(result)
+    }
+}
+
+/// An error only if the client has to forfeit.
+fn remote_move(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    request: &TrustMessage,
+) -> io::Result<Move> {
+    write_message(writer, request)?;
+    match read_message(reader) {
+        Ok(TrustMessage::RoundReply { mv }) => Ok(mv),
+        Ok(_) => Ok(Move::Cheat),
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => Ok(Move::Cheat),
+        Err(err) => Err(err),
+    }
+}
+
+fn outcome(left: Move, right: Move) -> RoundOutcome {
+    match (left, right) {
+        (Move::Cooperate, Move::Cooperate) => RoundOutcome::BothCooperated,
+        (Move::Cheat, Move::Cooperate) => RoundOutcome::LeftCheated,
+        (Move::Cooperate, Move::Cheat) => RoundOutcome::RightCheated,
+        (Move::Cheat, Move::Cheat) => RoundOutcome::BothCheated,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The client's account of a match, as the server has told it in `EndMatch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientMatchResult {
+    pub rounds: usize,
+    pub payoff: Payoff,
+    pub your_score: i32,
+    pub opponent_score: i32,
+}
+
+/// Plays the right agent of a match served by `MatchServer`. Unexpected messages are
+/// `io::ErrorKind::InvalidData`. The agent gets the score the server reports.
+pub fn connect_and_play(
+    addr: impl ToSocketAddrs,
+    mut agent: Box<dyn Agent>,
+) -> io::Result<ClientMatchResult> {
+    let stream = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let unexpected = |message: TrustMessage| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected message {message:?}"),
+        )
+    };
+
+    let (rounds, payoff) = match read_message(&mut reader)? {
+        TrustMessage::StartMatch { rounds, payoff } => (rounds, payoff),
+        message => return Err(unexpected(message)),
+    };
+    loop {
+        match read_message(&mut reader)? {
+            TrustMessage::RoundRequest {
+                opponent_previous, ..
+            } => {
+                if let Some(opponent_move) = opponent_previous {
+                    agent.update(opponent_move);
+                }
+                let reply = TrustMessage::RoundReply {
+                    mv: agent.play_round(),
+                };
+                write_message(&mut writer, &reply)?;
+            }
+            TrustMessage::EndMatch {
+                your_score,
+                opponent_score,
+            } => {
+                agent.set_score(your_score);
+                return Ok(ClientMatchResult {
+                    rounds,
+                    payoff,
+                    your_score,
+                    opponent_score,
+                });
+            }
+            message => return Err(unexpected(message)),
+        }
+    }
+}
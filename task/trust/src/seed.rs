@@ -0,0 +1,57 @@
+//! One master seed for a whole simulation: every seeded component derives its own seed
+//! from the master one by a path, e.g. `tournament/round3/cheater_vs_copycat/noise`.
+//!
+//! Derived values are part of the reproducibility contract: renaming a path, or
+//! changing the hash, changes the results of every experiment which used it.
+
+////////////////////////////////////////////////////////////////////////////////
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The finalizer of splitmix64.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Absorbs the length of the segment and then its bytes in 8-byte little-endian
+/// chunks, the last one zero-padded, each step being `mix((state + GOLDEN_GAMMA) ^ word)`.
+fn absorb(state: u64, segment: &str) -> u64 {
+    let step = |state: u64, word: u64| mix(state.wrapping_add(GOLDEN_GAMMA) ^ word);
+
+    let bytes = segment.as_bytes();
+    let state = step(state, bytes.len() as u64);
+    bytes.chunks(8).fold(state, |state, chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        step(state, u64::from_le_bytes(word))
+    })
+}
+
+/// Derives child seeds from a master seed by `/`-separated paths.
+///
+/// Segments are absorbed one by one, so `child("a").derive("b") == derive("a/b")`,
+/// and a component may be handed a child hierarchy instead of the master seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeedHierarchy {
+    root: u64,
+}
+
+impl SeedHierarchy {
+    pub fn new(master_seed: u64) -> Self {
+        Self { root: master_seed }
+    }
+
+    /// The seed of the path. Empty segments count, so `a//b` differs from `a/b`.
+    pub fn derive(&self, path: &str) -> u64 {
+        path.split('/').fold(self.root, absorb)
+    }
+
+    /// The hierarchy rooted at the path.
+    pub fn child(&self, path: &str) -> Self {
+        Self {
+            root: self.derive(path),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Parameter sweeps: many matches over the cartesian product of agent pairings, payoffs,
+//! round counts and seeds, played on several threads.
+
+use crate::{
+    cli::{self, CliError, Payoff},
+    seed::SeedHierarchy,
+    Agent, RoundOutcome,
+};
+
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Creates a fresh agent for every match from the agent seed, see `SweepSpec::play`.
+pub type AgentFactory = Box<dyn Fn(u64) -> Box<dyn Agent> + Send + Sync>;
+
+/// The minimal interval between two progress reports, except for the final one.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+struct NamedFactory {
+    name: String,
+    factory: AgentFactory,
+}
+
+/// Matches are enumerated pairing-major, then by payoff, round count and seed.
+pub struct SweepSpec {
+    agents: Vec<NamedFactory>,
+    pairings: Vec<(usize, usize)>,
+    payoffs: Vec<Payoff>,
+    rounds: Vec<usize>,
+    seeds: Vec<u64>,
+    seed_hierarchy: Option<SeedHierarchy>,
+}
+
+impl SweepSpec {
+    /// A single payoff, round count and seed: the default payoff, 10 rounds and seed 0.
+    pub fn new() -> Self {
+        Self {
+            agents: vec![],
+            pairings: vec![],
+            payoffs: vec![Payoff::default()],
+            rounds: vec![10],
+            seeds: vec![0],
+            seed_hierarchy: None,
+        }
+    }
+
+    pub fn with_agent(
+        mut self,
+        name: &str,
+        factory: impl Fn(u64) -> Box<dyn Agent> + Send + Sync + 'static,
+    ) -> Self {
+        self.agents.push(NamedFactory {
+            name: name.to_string(),
+            factory: Box::new(factory),
+        });
+        self
+    }
+
+    /// Adds one of `cli::AGENT_NAMES`, which ignore the seed.
+    pub fn with_builtin_agent(self, name: &str) -> Result<Self, CliError> {
+        cli::make_agent(name)?;
+        let owned_name = name.to_string();
+        Ok(self.with_agent(name, move |_| cli::make_agent(&owned_name).unwrap()))
+    }
+
+    /// Without explicit pairings every agent plays every other agent once, as in
+    /// a tournament.
+    ///
+    /// # Panics
+    ///
+    /// If any of the agents is not added yet.
+    pub fn with_pairing(mut self, left: &str, right: &str) -> Self {
+        let pairing = (self.agent_index(left), self.agent_index(right));
+        self.pairings.push(pairing);
+        self
+    }
+
+    pub fn with_payoffs(mut self, payoffs: impl IntoIterator<Item = Payoff>) -> Self {
+        self.payoffs = payoffs.into_iter().collect();
+        self
+    }
+
+    pub fn with_rounds(mut self, rounds: impl IntoIterator<Item = usize>) -> Self {
+        self.rounds = rounds.into_iter().collect();
+        self
+    }
+
+    pub fn with_seeds(mut self, seeds: impl IntoIterator<Item = u64>) -> Self {
+        self.seeds = seeds.into_iter().collect();
+        self
+    }
+
+    /// Makes agents take seeds derived from the match seed under `path` rather than
+    /// the match seed itself: `{path}/seed{seed}/{left}_vs_{right}/left` and
+    /// `.../right`, so that the two agents of a match are independent.
+    pub fn with_seed_hierarchy(mut self, seeds: &SeedHierarchy, path: &str) -> Self {
+        self.seed_hierarchy = Some(seeds.child(path));
+        self
+    }
+
+    /// The number of matches.
+    pub fn len(&self) -> usize {
+        self.pairings().len() * self.payoffs.len() * self.rounds.len() * self.seeds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn agent_index(&self, name: &str) -> usize {
+        self.agents
+            .iter()
+            .position(|agent| agent.name == name)
+            .unwrap_or_else(|| panic!("agent '{name}' is not added to the sweep"))
+    }
+
+    fn pairings(&self) -> Vec<(usize, usize)> {
+        if !self.pairings.is_empty() {
+            return self.pairings.clone();
+        }
+        let count = self.agents.len();
+        (0..count)
+            .flat_map(|i| (i + 1..count).map(move |j| (i, j)))
+            .collect()
+    }
+
+    fn play(&self, pairings: &[(usize, usize)], index: usize) -> SweepRecord {
+        let (payoffs, rounds, seeds) = (&self.payoffs, &self.rounds, &self.seeds);
+
+        let seed = seeds[index % seeds.len()];
+        let index = index / seeds.len();
+        let round_count = rounds[index % rounds.len()];
+        let index = index / rounds.len();
+        let payoff = payoffs[index % payoffs.len()];
+        let (left, right) = pairings[index / payoffs.len()];
+        let (left, right) = (&self.agents[left], &self.agents[right]);
+
+        let (left_seed, right_seed) = match &self.seed_hierarchy {
+            Some(seeds) => {
+                let seeds = seeds.child(&format!("seed{seed}/{}_vs_{}", left.name, right.name));
+                (seeds.derive("left"), seeds.derive("right"))
+            }
+            None => (seed, seed),
+        };
+
+        let result = cli::play_agents(
+            (left.factory)(left_seed),
+            (right.factory)(right_seed),
+            round_count,
+            payoff,
+        );
+        SweepRecord {
+            left: left.name.clone(),
+            right: right.name.clone(),
+            payoff,
+            rounds: round_count,
+            seed,
+            left_score: result.left_score,
+            right_score: result.right_score,
+            tally: OutcomeTally::new(&result.outcomes),
+        }
+    }
+}
+
+impl Default for SweepSpec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutcomeTally {
+    pub both_cooperated: usize,
+    pub left_cheated: usize,
+    pub right_cheated: usize,
+    pub both_cheated: usize,
+}
+
+impl OutcomeTally {
+    fn new(outcomes: &[RoundOutcome]) -> Self {
+        let mut tally = Self::default();
+        for outcome in outcomes {
+            match outcome {
+                RoundOutcome::BothCooperated => tally.both_cooperated += 1,
+                RoundOutcome::LeftCheated => tally.left_cheated += 1,
+                RoundOutcome::RightCheated => tally.right_cheated += 1,
+                RoundOutcome::BothCheated => tally.both_cheated += 1,
+            }
+        }
+        tally
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SweepRecord {
+    pub left: String,
+    pub right: String,
+    pub payoff: Payoff,
+    pub rounds: usize,
+    pub seed: u64,
+    pub left_score: i32,
+    pub right_score: i32,
+    pub tally: OutcomeTally,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SweepProgress {
+    pub completed: usize,
+    pub total: usize,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Plays all the matches of `spec` on `threads` threads. Records are in the order of
+/// the spec enumeration, whatever the scheduling, and each one only depends on its
+/// parameters, so that a seed reproduces a match.
+///
+/// `progress` is called from the worker threads at most once per `PROGRESS_INTERVAL`,
+/// and then once more when all the matches are played.
+pub fn run_sweep(
+    spec: &SweepSpec,
+    threads: usize,
+    progress: impl Fn(SweepProgress) + Sync,
+) -> Vec<SweepRecord> {
+    let pairings = spec.pairings();
+    let total = spec.len();
+    let next_index = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let last_report = Mutex::new(Instant::now());
+
+    let worker = || {
+        let mut records = vec![];
+        loop {
+            let index = next_index.fetch_add(1, Ordering::Relaxed);
+            if index >= total {
+                break records;
+            }
+            records.push((index, spec.play(&pairings, index)));
+
+            let completed = completed.fetch_add(1, Ordering::Relaxed) + 1;
+            // Never wait for another worker to report.
+            if let Ok(mut last_report) = last_report.try_lock() {
+                if last_report.elapsed() >= PROGRESS_INTERVAL {
+                    progress(SweepProgress { completed, total });
+                    *last_report = Instant::now();
+                }
+            }
+        }
+    };
+
+    let mut records = vec![None; total];
+    thread::scope(|scope| {
+        let handles = (0..threads.max(1))
+            .map(|_| scope.spawn(worker))
+            .collect::<Vec<_>>();
+        for handle in handles {
+            for (index, record) in handle.join().unwrap() {
+                records[index] = Some(record);
+            }
+        }
+    });
+    progress(SweepProgress {
+        completed: total,
+        total,
+    });
+
+    records.into_iter().map(Option::unwrap).collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const CSV_HEADER: &str = "left,right,reward,temptation,sucker,punishment,rounds,seed,\
+    left_score,right_score,both_cooperated,left_cheated,right_cheated,both_cheated";
+
+/// Writes the records with a `CSV_HEADER` line, quoting agent names if needed.
+pub fn write_csv(records: &[SweepRecord], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "{CSV_HEADER}")?;
+    for record in records {
+        let SweepRecord {
+            payoff, tally: t, ..
+        } = record;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&record.left),
+            csv_field(&record.right),
+            payoff.reward,
+            payoff.temptation,
+            payoff.sucker,
+            payoff.punishment,
+            record.rounds,
+            record.seed,
+            record.left_score,
+            record.right_score,
+            t.both_cooperated,
+            t.left_cheated,
+            t.right_cheated,
+            t.both_cheated,
+        )?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -1,5 +1,20 @@
 use trust::{
-    CheatingAgent, CooperatingAgent, CopycatAgent, DetectiveAgent, Game, GrudgerAgent, RoundOutcome,
+    baseline::{self, AgentFn, Baseline, BaselineReport, OpponentScore, Verdict},
+    cli::{self, CliError, Payoff},
+    net::{self, MatchServer, TrustMessage},
+    seed::SeedHierarchy,
+    sweep::{self, SweepProgress, SweepRecord, SweepSpec},
+    Agent, CheatingAgent, CooperatingAgent, CopycatAgent, DetectiveAgent, Game, GrudgerAgent, Move,
+    RoundOutcome,
+};
+
+use std::{
+    collections::HashMap,
+    io::{BufReader, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
 };
 
 fn test_game<'a>(mut game: Game, expected_outcomes: impl IntoIterator<Item = &'a RoundOutcome>) {
@@ -208,3 +223,548 @@ fn test_copycat_detective() {
             .chain([RoundOutcome::BothCooperated; 11].iter()),
     );
 }
+
+#[test]
+fn test_cli_match() {
+    let output = cli::run_match("copycat", "detective", 6, Payoff::default()).unwrap();
+    assert_eq!(
+        output,
+        "\
+round  copycat     detective
+    1  cooperate   cooperate
+    2  cooperate   cheat
+    3  cheat       cooperate
+    4  cooperate   cooperate
+    5  cooperate   cooperate
+    6  cooperate   cooperate
+final score: copycat 10, detective 10
+"
+    );
+
+    let payoff = "1,5,0,-1".parse().unwrap();
+    let output = cli::run_match("grudger", "cheater", 3, payoff).unwrap();
+    assert_eq!(
+        output,
+        "\
+round  grudger     cheater
+    1  cooperate   cheat
+    2  cheat       cheat
+    3  cheat       cheat
+final score: grudger -2, cheater 3
+"
+    );
+}
+
+#[test]
+fn test_cli_tournament() {
+    let output = cli::run_tournament(&["copycat", "cheater", "cooperator"], 5, Payoff::default());
+    assert_eq!(
+        output.unwrap(),
+        concat!(
+            " 1. cheater        18\n",
+            " 2. copycat         9\n",
+            " 3. cooperator      5\n",
+        )
+    );
+
+    let output = cli::run_tournament(&cli::AGENT_NAMES, 10, Payoff::default()).unwrap();
+    assert_eq!(output.lines().count(), cli::AGENT_NAMES.len());
+    assert!(output.starts_with(" 1. copycat        57\n"));
+}
+
+#[test]
+fn test_cli_errors() {
+    let err = cli::run_match("copycat", "tit-for-tat", 1, Payoff::default()).unwrap_err();
+    assert_eq!(err, CliError::UnknownAgent("tit-for-tat".to_string()));
+    assert_eq!(
+        err.to_string(),
+        "unknown agent 'tit-for-tat', valid agents are: \
+         cheater, cooperator, grudger, copycat, detective"
+    );
+
+    let err = cli::run_tournament(&["nobody"], 1, Payoff::default()).unwrap_err();
+    assert_eq!(err, CliError::UnknownAgent("nobody".to_string()));
+
+    assert!("1,2,3".parse::<Payoff>().is_err());
+    assert!("1,2,x,4".parse::<Payoff>().is_err());
+    assert_eq!(
+        cli::list_agents().lines().collect::<Vec<_>>(),
+        cli::AGENT_NAMES
+    );
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+struct RandomAgent {
+    score: i32,
+    state: u64,
+}
+
+impl Agent for RandomAgent {
+    fn play_round(&mut self) -> Move {
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        match self.state % 2 {
+            0 => Move::Cooperate,
+            _ => Move::Cheat,
+        }
+    }
+
+    fn update(&mut self, _opponent_move: Move) {}
+
+    fn get_score(&self) -> i32 {
+        self.score
+    }
+
+    fn set_score(&mut self, score: i32) {
+        self.score = score
+    }
+}
+
+fn sweep_spec() -> SweepSpec {
+    let mut spec = SweepSpec::new().with_agent("random", |seed| {
+        Box::new(RandomAgent {
+            score: 0,
+            state: seed + 1,
+        })
+    });
+    for name in cli::AGENT_NAMES {
+        spec = spec.with_builtin_agent(name).unwrap();
+    }
+    spec.with_payoffs([Payoff::default(), "1,5,0,-1".parse().unwrap()])
+        .with_rounds([1, 10, 25])
+        .with_seeds(0..4)
+}
+
+#[test]
+fn test_sweep_threads() {
+    let spec = sweep_spec();
+    assert_eq!(spec.len(), 15 * 2 * 3 * 4);
+
+    let single = sweep::run_sweep(&spec, 1, |_| {});
+    assert_eq!(single.len(), spec.len());
+    assert_eq!(sweep::run_sweep(&spec, 4, |_| {}), single);
+
+    assert_eq!(
+        (single[0].left.as_str(), single[0].right.as_str()),
+        ("random", "cheater")
+    );
+    assert_eq!((single[0].rounds, single[0].seed), (1, 0));
+    assert_eq!((single[1].rounds, single[1].seed), (1, 1));
+    assert_eq!((single[4].rounds, single[4].seed), (10, 0));
+    assert_eq!(single[12].payoff, "1,5,0,-1".parse().unwrap());
+    let last = single.last().unwrap();
+    assert_eq!(
+        (last.left.as_str(), last.right.as_str()),
+        ("copycat", "detective")
+    );
+    for record in &single {
+        let tally = record.tally;
+        let total = tally.both_cooperated + tally.left_cheated + tally.right_cheated;
+        assert_eq!(total + tally.both_cheated, record.rounds);
+    }
+}
+
+#[test]
+fn test_sweep_progress() {
+    let spec = sweep_spec();
+    let reports = Mutex::new(vec![]);
+    sweep::run_sweep(&spec, 4, |progress| reports.lock().unwrap().push(progress));
+
+    let reports = reports.into_inner().unwrap();
+    assert_eq!(
+        reports.last(),
+        Some(&SweepProgress {
+            completed: spec.len(),
+            total: spec.len(),
+        })
+    );
+    assert!(reports.windows(2).all(|w| w[0].completed <= w[1].completed));
+}
+
+#[test]
+fn test_sweep_seeds() {
+    let spec = sweep_spec();
+    let records = sweep::run_sweep(&spec, 3, |_| {});
+
+    let single = SweepSpec::new()
+        .with_agent("random", |seed| {
+            Box::new(RandomAgent {
+                score: 0,
+                state: seed + 1,
+            })
+        })
+        .with_builtin_agent("copycat")
+        .unwrap()
+        .with_pairing("random", "copycat")
+        .with_rounds([25])
+        .with_seeds([3]);
+    let rerun = sweep::run_sweep(&single, 1, |_| {});
+    assert_eq!(rerun.len(), 1);
+    assert!(records.contains(&rerun[0]));
+
+    let by_seed = records
+        .iter()
+        .filter(|r| {
+            r.left == "random"
+                && r.right == "copycat"
+                && r.rounds == 25
+                && r.payoff == Payoff::default()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(by_seed.len(), 4);
+    assert!(by_seed.iter().any(|r| r.tally != by_seed[0].tally));
+}
+
+#[test]
+fn test_sweep_csv() {
+    let spec = SweepSpec::new()
+        .with_builtin_agent("grudger")
+        .unwrap()
+        .with_agent("tit, for \"tat\"", |_| cli::make_agent("copycat").unwrap())
+        .with_rounds([3]);
+    let records = sweep::run_sweep(&spec, 2, |_| {});
+
+    let mut csv = vec![];
+    sweep::write_csv(&records, &mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        format!(
+            "{}\n{}\n",
+            sweep::CSV_HEADER,
+            "grudger,\"tit, for \"\"tat\"\"\",2,3,-1,0,3,0,6,6,3,0,0,0"
+        )
+    );
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_seed_paths() {
+    // These values pin the hash: if they change, old experiments can't be reproduced.
+    let seeds = SeedHierarchy::new(42);
+    for (path, expected) in [
+        ("", 0xbdd732262feb6e95),
+        ("tournament", 0x901e3cd9a993bb7e),
+        (
+            "tournament/round3/match_cheater_vs_copycat/noise",
+            0x7d14e328e39ab7cb,
+        ),
+        ("sweep/seed0/random_vs_copycat/left", 0x4848d7af95ab81a3),
+        ("a/b", 0x1e100648acf7da66),
+        ("a//b", 0x150f217005cc4310),
+    ] {
+        assert_eq!(seeds.derive(path), expected, "{path}");
+    }
+    assert_eq!(
+        SeedHierarchy::new(0).derive("tournament"),
+        0x08f99d207b61ab57
+    );
+
+    assert_eq!(seeds.child("a").derive("b"), seeds.derive("a/b"));
+    assert_eq!(
+        seeds
+            .child("tournament/round3")
+            .child("x_vs_y")
+            .derive("noise"),
+        seeds.derive("tournament/round3/x_vs_y/noise")
+    );
+    assert_ne!(seeds.derive("ab"), seeds.derive("a/b"));
+    assert_ne!(seeds.derive("round1"), seeds.derive("round10"));
+}
+
+#[test]
+fn test_sweep_seed_paths() {
+    let agent_seeds = Arc::new(Mutex::new(vec![]));
+    let recording_agent = |name: &'static str| {
+        let agent_seeds = agent_seeds.clone();
+        move |seed| {
+            agent_seeds.lock().unwrap().push((name, seed));
+            cli::make_agent("cheater").unwrap()
+        }
+    };
+    let spec = SweepSpec::new()
+        .with_agent("a", recording_agent("a"))
+        .with_agent("b", recording_agent("b"))
+        .with_seeds([3])
+        .with_seed_hierarchy(&SeedHierarchy::new(42), "sweep");
+    let records = sweep::run_sweep(&spec, 1, |_| {});
+    assert_eq!(records[0].seed, 3);
+
+    let seeds = SeedHierarchy::new(42);
+    assert_eq!(
+        *agent_seeds.lock().unwrap(),
+        [
+            ("a", seeds.derive("sweep/seed3/a_vs_b/left")),
+            ("b", seeds.derive("sweep/seed3/a_vs_b/right")),
+        ]
+    );
+    assert_eq!(agent_seeds.lock().unwrap()[0].1, 0xee5149b2956af37e);
+}
+
+fn random_agent(seed: u64) -> Box<dyn Agent> {
+    Box::new(RandomAgent {
+        score: 0,
+        state: seed | 1,
+    })
+}
+
+/// Total scores of the agents, best first.
+fn standings(records: &[SweepRecord]) -> Vec<(String, i32)> {
+    let mut scores = HashMap::<String, i32>::new();
+    for record in records {
+        *scores.entry(record.left.clone()).or_default() += record.left_score;
+        *scores.entry(record.right.clone()).or_default() += record.right_score;
+    }
+    let mut standings = scores.into_iter().collect::<Vec<_>>();
+    standings.sort_by(|(lhs_name, lhs), (rhs_name, rhs)| rhs.cmp(lhs).then(lhs_name.cmp(rhs_name)));
+    standings
+}
+
+fn noisy_tournament(master_seed: u64) -> Vec<(String, i32)> {
+    let mut spec = SweepSpec::new()
+        .with_agent("random", random_agent)
+        .with_agent("coin", random_agent);
+    for name in ["copycat", "grudger", "detective"] {
+        spec = spec.with_builtin_agent(name).unwrap();
+    }
+    let spec = spec
+        .with_rounds([20])
+        .with_seeds(0..3)
+        .with_seed_hierarchy(&SeedHierarchy::new(master_seed), "tournament");
+    standings(&sweep::run_sweep(&spec, 3, |_| {}))
+}
+
+#[test]
+fn test_seeded_tournament() {
+    let standings = noisy_tournament(2024);
+    assert_eq!(standings.len(), 5);
+    assert_eq!(noisy_tournament(2024), standings);
+    assert_ne!(noisy_tournament(2025), standings);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn builtin(name: &'static str) -> impl Fn(u64) -> Box<dyn Agent> {
+    move |_| cli::make_agent(name).unwrap()
+}
+
+fn record_baseline(name: &'static str) -> BaselineReport {
+    let cheater = builtin("cheater");
+    let cooperator = builtin("cooperator");
+    let copycat = builtin("copycat");
+    let grudger = builtin("grudger");
+    let detective = builtin("detective");
+    let opponents: [(&str, AgentFn); 6] = [
+        ("cheater", &cheater),
+        ("cooperator", &cooperator),
+        ("copycat", &copycat),
+        ("grudger", &grudger),
+        ("detective", &detective),
+        ("random", &random_agent),
+    ];
+    Baseline::record(name, &builtin(name), &opponents, 20, 2024)
+}
+
+#[test]
+fn test_baseline_is_reproducible() {
+    let report = record_baseline("copycat");
+    assert_eq!(record_baseline("copycat"), report);
+    assert_eq!(report.rounds, 20);
+    assert_eq!(report.opponents.len(), 6);
+
+    let cooperator = &report.opponents[1];
+    assert_eq!(cooperator.opponent, "cooperator");
+    assert_eq!(cooperator.average_score, 2.);
+    assert_eq!(cooperator.opponent_average_score, 2.);
+    let cheater = &report.opponents[0];
+    assert_eq!(cheater.average_score, -1. / 20.);
+    assert_eq!(cheater.opponent_average_score, 3. / 20.);
+}
+
+#[test]
+fn test_baseline_comparison() {
+    let baseline = record_baseline("copycat");
+    let current = record_baseline("grudger");
+    let comparison = baseline::compare_against_baseline(&current, &baseline, 0.);
+
+    let changed = comparison
+        .matchups
+        .iter()
+        .filter(|matchup| matchup.has_changed())
+        .map(|matchup| matchup.opponent.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(changed, ["detective", "random"]);
+    assert!(comparison
+        .matchups
+        .iter()
+        .all(|matchup| matchup.delta.is_some()));
+
+    let table = comparison.to_string();
+    let lines = table.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 8);
+    assert!(lines[0].contains("copycat") && lines[0].contains("grudger"));
+    assert!(lines[1].starts_with("  cheater"));
+    let marker = if comparison.matchups[4].delta.unwrap() > 0. {
+        "+ detective"
+    } else {
+        "- detective"
+    };
+    assert!(lines[5].starts_with(marker), "{table}");
+    assert!(lines[7].contains(&comparison.verdict.to_string()));
+}
+
+#[test]
+fn test_baseline_json() {
+    let report = record_baseline("detective");
+    let path = std::env::temp_dir().join(format!("trust-baseline-{}.json", std::process::id()));
+    report.save(&path).unwrap();
+    let loaded = BaselineReport::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.unwrap(), report);
+
+    let err = BaselineReport::load(std::env::temp_dir().join("trust-no-such-baseline.json"));
+    assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_baseline_threshold() {
+    let report = |name: &str, scores: &[(&str, f64)]| BaselineReport {
+        name: name.to_string(),
+        rounds: 10,
+        seed: 0,
+        opponents: scores
+            .iter()
+            .map(|&(opponent, average_score)| OpponentScore {
+                opponent: opponent.to_string(),
+                average_score,
+                opponent_average_score: 0.,
+            })
+            .collect(),
+    };
+    let baseline = report("old", &[("cheater", -0.1), ("copycat", 2.), ("gone", 1.)]);
+    let current = report("new", &[("cheater", 0.), ("copycat", 2.), ("fresh", 3.)]);
+
+    let comparison = baseline::compare_against_baseline(&current, &baseline, 0.);
+    assert_eq!(comparison.verdict, Verdict::Improved);
+    assert!((comparison.overall_delta - 0.05).abs() < 1e-9);
+    let opponents = comparison
+        .matchups
+        .iter()
+        .map(|matchup| (matchup.opponent.as_str(), matchup.delta.is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        opponents,
+        [
+            ("cheater", true),
+            ("copycat", true),
+            ("gone", false),
+            ("fresh", false)
+        ]
+    );
+
+    let comparison = baseline::compare_against_baseline(&current, &baseline, 0.1);
+    assert_eq!(comparison.verdict, Verdict::Unchanged);
+    let comparison = baseline::compare_against_baseline(&baseline, &current, 0.1);
+    assert_eq!(comparison.verdict, Verdict::Unchanged);
+    let comparison = baseline::compare_against_baseline(&baseline, &current, 0.);
+    assert_eq!(comparison.verdict, Verdict::Regressed);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_net_match() {
+    let server = MatchServer::bind("127.0.0.1:0").unwrap().with_rounds(20);
+    let addr = server.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        server
+            .serve_match(|| Box::new(DetectiveAgent::new()))
+            .unwrap()
+    });
+    let client = net::connect_and_play(addr, Box::new(CopycatAgent::new())).unwrap();
+    let server = server.join().unwrap();
+
+    let mut game = Game::new(
+        Box::new(DetectiveAgent::new()),
+        Box::new(CopycatAgent::new()),
+    );
+    let outcomes = (0..20).map(|_| game.play_round()).collect::<Vec<_>>();
+    assert_eq!(server.outcomes, outcomes);
+    assert_eq!(server.forfeited_at, None);
+    assert_eq!(
+        (server.left_score, server.right_score),
+        (game.left_score(), game.right_score())
+    );
+
+    assert_eq!(client.rounds, 20);
+    assert_eq!(client.payoff, Payoff::default());
+    assert_eq!(
+        (client.your_score, client.opponent_score),
+        (server.right_score, server.left_score)
+    );
+}
+
+#[test]
+fn test_net_misbehaving_client() {
+    let server = MatchServer::bind("127.0.0.1:0")
+        .unwrap()
+        .with_rounds(4)
+        .with_round_timeout(Duration::from_millis(200));
+    let addr = server.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        server
+            .serve_match(|| Box::new(CooperatingAgent::new()))
+            .unwrap()
+    });
+
+    let mut writer = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(writer.try_clone().unwrap());
+    let mut expect = |expected: TrustMessage| {
+        assert_eq!(net::read_message(&mut reader).unwrap(), expected);
+    };
+    expect(TrustMessage::StartMatch {
+        rounds: 4,
+        payoff: Payoff::default(),
+    });
+
+    expect(TrustMessage::RoundRequest {
+        round: 1,
+        opponent_previous: None,
+    });
+    let reply = TrustMessage::RoundReply {
+        mv: Move::Cooperate,
+    };
+    net::write_message(&mut writer, &reply).unwrap();
+
+    // Not even JSON: cheating.
+    expect(TrustMessage::RoundRequest {
+        round: 2,
+        opponent_previous: Some(Move::Cooperate),
+    });
+    writer.write_all(b"cooperate\n").unwrap();
+
+    // No reply in time: cheating till the end.
+    expect(TrustMessage::RoundRequest {
+        round: 3,
+        opponent_previous: Some(Move::Cooperate),
+    });
+    expect(TrustMessage::EndMatch {
+        your_score: 2 + 3 * 3,
+        opponent_score: 2 - 3,
+    });
+
+    let server = server.join().unwrap();
+    assert_eq!(
+        server.outcomes,
+        [
+            RoundOutcome::BothCooperated,
+            RoundOutcome::RightCheated,
+            RoundOutcome::RightCheated,
+            RoundOutcome::RightCheated,
+        ]
+    );
+    assert_eq!(server.forfeited_at, Some(3));
+}
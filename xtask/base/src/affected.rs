@@ -0,0 +1,472 @@
+use crate::util::create_shell;
+
+use anyhow::{bail, Context, Result};
+use gix::{bstr::ByteSlice, progress::prodash::progress, status::UntrackedFiles, Repository};
+use walkdir::WalkDir;
+use xshell::cmd;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Component, Path, PathBuf},
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const COURSE_REMOTE_NAME: &str = "origin";
+const TASKS_DIR: &str = "task";
+const CHECKER_CONFIG_FILE_NAME: &str = ".check.toml";
+
+/// Changes of these paths may affect every task: the checker itself and the workspace.
+const GLOBAL_PATHS: &[&str] = &["xtask", "Cargo.toml", "Cargo.lock", ".cargo"];
+
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns the name of the task owning `path`, given relative to the repository root.
+pub fn owning_task(path: &Path) -> Option<String> {
+    let mut components = path.components();
+    if components.next()? != Component::Normal(TASKS_DIR.as_ref()) {
+        return None;
+    }
+    let name = components.next()?.as_os_str().to_str()?;
+    // Files right in `task/` belong to no task.
+    components.next()?;
+    Some(name.to_owned())
+}
+
+/// Resolves `.` and `..` without touching the file system. Returns `None` if the path
+/// escapes its root.
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    Some(normalized)
+}
+
+/// Returns the paths of the `path` dependencies of a manifest, relative to the repository
+/// root. `manifest_dir` is relative to the root as well.
+///
+/// Target-specific dependencies are included, workspace-inherited ones are not.
+pub fn path_dependencies(manifest_dir: &Path, manifest: &str) -> Result<Vec<PathBuf>> {
+    let manifest = manifest
+        .parse::<toml::Table>()
+        .with_context(|| format!("failed to parse manifest in {manifest_dir:?}"))?;
+
+    let mut tables = vec![&manifest];
+    if let Some(toml::Value::Table(targets)) = manifest.get("target") {
+        tables.extend(targets.values().filter_map(toml::Value::as_table));
+    }
+
+    let mut dependencies = vec![];
+    for table in tables {
+        for &name in DEPENDENCY_TABLES {
+            let Some(toml::Value::Table(deps)) = table.get(name) else {
+                continue;
+            };
+            for dep in deps.values() {
+                let Some(path) = dep.get("path").and_then(toml::Value::as_str) else {
+                    continue;
+                };
+                let Some(path) = normalize(&manifest_dir.join(path)) else {
+                    bail!("dependency path {path:?} in {manifest_dir:?} is outside of the repo");
+                };
+                dependencies.push(path);
+            }
+        }
+    }
+    Ok(dependencies)
+}
+
+/// Resolves the set of tasks affected by `changed_paths`.
+///
+/// A task is affected if it owns a changed path, if a changed path is under one of its
+/// `shared_dependencies` (path dependencies outside of the task directory), or if
+/// a global path like `xtask/` is changed.
+pub fn affected_tasks(
+    changed_paths: &[PathBuf],
+    shared_dependencies: &BTreeMap<String, Vec<PathBuf>>,
+) -> BTreeSet<String> {
+    let all_tasks = || shared_dependencies.keys().cloned();
+    let mut affected = BTreeSet::new();
+
+    for path in changed_paths {
+        if GLOBAL_PATHS.iter().any(|global| path.starts_with(global)) {
+            return all_tasks().collect();
+        }
+
+        if let Some(task) = owning_task(path) {
+            if shared_dependencies.contains_key(&task) {
+                affected.insert(task);
+            }
+        }
+
+        for (task, dependencies) in shared_dependencies {
+            if dependencies
+                .iter()
+                .any(|dependency| path.starts_with(dependency))
+            {
+                affected.insert(task.clone());
+            }
+        }
+    }
+
+    affected
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Finds all tasks of the repository and their path dependencies outside of their
+/// directories.
+pub fn read_shared_dependencies(repo_path: &Path) -> Result<BTreeMap<String, Vec<PathBuf>>> {
+    let mut tasks = BTreeMap::new();
+
+    let tasks_path = repo_path.join(TASKS_DIR);
+    let entries = std::fs::read_dir(&tasks_path)
+        .with_context(|| format!("failed to read tasks dir {tasks_path:?}"))?;
+    for mb_entry in entries {
+        let task_path = mb_entry?.path();
+        if !task_path.join(CHECKER_CONFIG_FILE_NAME).exists() {
+            continue;
+        }
+        let task_dir = task_path.strip_prefix(repo_path)?.to_owned();
+        let task_name = task_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("invalid task path: {task_path:?}"))?
+            .to_owned();
+
+        let mut dependencies = vec![];
+        let manifests = WalkDir::new(&task_path)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != "target");
+        for mb_manifest in manifests {
+            let manifest =
+                mb_manifest.with_context(|| format!("failed to traverse {task_path:?}"))?;
+            if manifest.file_name() != "Cargo.toml" {
+                continue;
+            }
+            let manifest_path = manifest.path();
+            let manifest_dir = manifest_path.parent().unwrap().strip_prefix(repo_path)?;
+            let content = std::fs::read_to_string(manifest_path)
+                .with_context(|| format!("failed to read {manifest_path:?}"))?;
+            dependencies.extend(
+                path_dependencies(manifest_dir, &content)?
+                    .into_iter()
+                    .filter(|dependency| !dependency.starts_with(&task_dir)),
+            );
+        }
+
+        dependencies.sort();
+        dependencies.dedup();
+        tasks.insert(task_name, dependencies);
+    }
+
+    Ok(tasks)
+}
+
+/// Default base of `--affected`: the merge-base of `HEAD` with the default branch of
+/// the course remote.
+pub fn default_base(repo: &Repository) -> Result<String> {
+    let candidates = [
+        format!("refs/remotes/{COURSE_REMOTE_NAME}/HEAD"),
+        format!("refs/remotes/{COURSE_REMOTE_NAME}/main"),
+        format!("refs/remotes/{COURSE_REMOTE_NAME}/master"),
+    ];
+    let Some(upstream) = candidates
+        .iter()
+        .find_map(|name| repo.rev_parse_single(name.as_str()).ok())
+    else {
+        bail!("failed to find the default branch of remote '{COURSE_REMOTE_NAME}', use --since");
+    };
+
+    // NB: gix 0.66 can't compute merge-bases yet, so we use git cli.
+    let sh = create_shell(repo.git_dir())?;
+    let upstream = upstream.to_string();
+    let merge_base = cmd!(sh, "git merge-base HEAD {upstream}")
+        .quiet()
+        .read()
+        .context("failed to find merge-base with the course remote")?;
+    Ok(merge_base)
+}
+
+/// Returns the paths that differ between the tree of `base` and the working tree,
+/// including staged, unstaged and untracked changes, relative to the repository root.
+pub fn changed_paths(repo: &Repository, base: &str) -> Result<Vec<PathBuf>> {
+    let base_tree = repo
+        .rev_parse_single(base)
+        .with_context(|| format!("failed to resolve {base:?}"))?
+        .object()?
+        .peel_to_tree()
+        .with_context(|| format!("{base:?} does not point to a tree"))?;
+
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    base_tree
+        .traverse()
+        .breadthfirst(&mut recorder)
+        .context("failed to traverse the base tree")?;
+    let mut base_files = recorder
+        .records
+        .into_iter()
+        .filter(|entry| !entry.mode.is_tree())
+        .map(|entry| (entry.filepath, entry.oid))
+        .collect::<HashMap<_, _>>();
+
+    // Base against the index: committed and staged changes.
+    let mut changed = BTreeSet::new();
+    let index = repo.index_or_empty().context("failed to read the index")?;
+    for entry in index.entries() {
+        let path = entry.path(&index);
+        if base_files.remove(path) != Some(entry.id) {
+            changed.insert(path.to_owned());
+        }
+    }
+    changed.extend(base_files.into_keys());
+
+    // Index against the working tree: unstaged and untracked changes.
+    // Untracked directories are listed file by file, as they may contain a new task.
+    let status = repo
+        .status(progress::Discard)
+        .context("failed to get repository status")?
+        .untracked_files(UntrackedFiles::Files);
+    for mb_entry in status.into_index_worktree_iter(None)? {
+        changed.insert(mb_entry?.rela_path().to_owned());
+    }
+
+    changed
+        .into_iter()
+        .map(|path| {
+            path.to_path()
+                .map(Path::to_owned)
+                .with_context(|| format!("path is not a valid utf-8: {path:?}"))
+        })
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xshell::{cmd, Shell};
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn synthetic_tasks() -> BTreeMap<String, Vec<PathBuf>> {
+        BTreeMap::from([
+            ("add".to_string(), vec![]),
+            ("paperio".to_string(), paths(&["xtask/base", "xtask/util"])),
+            ("chip8".to_string(), paths(&["shared/emu"])),
+            ("ini".to_string(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_owning_task() {
+        let owner = |path: &str| owning_task(Path::new(path));
+        assert_eq!(owner("task/add/src/lib.rs").as_deref(), Some("add"));
+        assert_eq!(
+            owner("task/paperio/proto/src/lib.rs").as_deref(),
+            Some("paperio")
+        );
+        assert_eq!(owner("task/add/.check.toml").as_deref(), Some("add"));
+        assert_eq!(owner("task/README.md"), None);
+        assert_eq!(owner("task"), None);
+        assert_eq!(owner("xtask/base/src/check.rs"), None);
+        assert_eq!(owner("README.md"), None);
+        assert_eq!(owner("tasks/add/src/lib.rs"), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalized = |path: &str| normalize(Path::new(path));
+        assert_eq!(
+            normalized("task/comm/xtask/../../../xtask/base"),
+            Some(PathBuf::from("xtask/base"))
+        );
+        assert_eq!(
+            normalized("task/gc/./gc-derive"),
+            Some("task/gc/gc-derive".into())
+        );
+        assert_eq!(normalized("task/../.."), None);
+    }
+
+    #[test]
+    fn test_path_dependencies() {
+        let manifest = r#"
+            [package]
+            name = "xtask-comm"
+
+            [dependencies]
+            anyhow = { version = "1.0.87" }
+            xtask-base = { path = "../../../xtask/base" }
+            local = { path = "./local" }
+
+            [dev-dependencies]
+            helper = { path = "../../../shared/helper", version = "0.1" }
+
+            [target.'cfg(unix)'.dependencies]
+            unix-only = { path = "../../../shared/unix" }
+        "#;
+        let mut dependencies = path_dependencies(Path::new("task/comm/xtask"), manifest).unwrap();
+        dependencies.sort();
+        assert_eq!(
+            dependencies,
+            paths(&[
+                "shared/helper",
+                "shared/unix",
+                "task/comm/xtask/local",
+                "xtask/base",
+            ])
+        );
+
+        let escaping = r#"
+            [dependencies]
+            outside = { path = "../../../../somewhere" }
+        "#;
+        assert!(path_dependencies(Path::new("task/comm/xtask"), escaping).is_err());
+        assert!(path_dependencies(Path::new("task/add"), "not a [manifest").is_err());
+    }
+
+    #[test]
+    fn test_affected_tasks() {
+        let tasks = synthetic_tasks();
+        let affected = |changed: &[&str]| affected_tasks(&paths(changed), &tasks);
+
+        assert_eq!(affected(&[]), names(&[]));
+        assert_eq!(affected(&["README.md", "task/README.md"]), names(&[]));
+        assert_eq!(affected(&["task/add/src/lib.rs"]), names(&["add"]));
+        assert_eq!(
+            affected(&["task/add/src/lib.rs", "task/ini/tests/tests.rs"]),
+            names(&["add", "ini"])
+        );
+        assert_eq!(affected(&["shared/emu/src/cpu.rs"]), names(&["chip8"]));
+        assert_eq!(affected(&["shared/emulator/src/cpu.rs"]), names(&[]));
+        // A task that is gone or has no checker config.
+        assert_eq!(affected(&["task/removed/src/lib.rs"]), names(&[]));
+
+        let all = names(&["add", "chip8", "ini", "paperio"]);
+        assert_eq!(affected(&["xtask/util/src/lib.rs"]), all);
+        assert_eq!(affected(&["Cargo.toml"]), all);
+        assert_eq!(
+            affected(&[".cargo/config.toml", "task/add/src/lib.rs"]),
+            all
+        );
+    }
+
+    #[test]
+    fn test_read_shared_dependencies() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let root = repo_dir.path();
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+
+        write("task/calc/.check.toml", "");
+        write("task/calc/Cargo.toml", "[dependencies]\nlog = \"0.4\"\n");
+        write("task/game/.check.toml", "");
+        write(
+            "task/game/server/Cargo.toml",
+            "[dependencies]\nproto = { path = \"../proto\" }\n",
+        );
+        write(
+            "task/game/xtask/Cargo.toml",
+            "[dependencies]\nxtask-base = { path = \"../../../xtask/base\" }\n",
+        );
+        write("task/game/target/debug/Cargo.toml", "not a [manifest");
+        write("task/notes/README.md", "");
+
+        let tasks = read_shared_dependencies(root).unwrap();
+        assert_eq!(
+            tasks,
+            BTreeMap::from([
+                ("calc".to_string(), vec![]),
+                ("game".to_string(), paths(&["xtask/base"])),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_changed_paths() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let sh = Shell::new().unwrap();
+        sh.change_dir(repo_dir.path());
+
+        let git = |args: &[&str]| {
+            cmd!(
+                sh,
+                "git -c user.name=test -c user.email=test@test {args...}"
+            )
+            .quiet()
+            .run()
+            .unwrap();
+        };
+
+        git(&["init", "--quiet"]);
+        sh.write_file("task/add/src/lib.rs", "fn add() {}").unwrap();
+        sh.write_file("task/ini/src/lib.rs", "fn parse() {}")
+            .unwrap();
+        sh.write_file("README.md", "readme").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "first"]);
+        let base = cmd!(sh, "git rev-parse HEAD").read().unwrap();
+
+        sh.write_file("task/add/src/lib.rs", "fn add(a: i32) {}")
+            .unwrap();
+        sh.remove_path("README.md").unwrap();
+        git(&["commit", "--quiet", "-am", "second"]);
+
+        let repo = gix::open(repo_dir.path()).unwrap();
+        assert_eq!(
+            changed_paths(&repo, &base).unwrap(),
+            paths(&["README.md", "task/add/src/lib.rs"])
+        );
+        assert_eq!(changed_paths(&repo, "HEAD").unwrap(), Vec::<PathBuf>::new());
+
+        // Staged, unstaged and untracked changes.
+        sh.write_file("task/ini/src/lib.rs", "fn parse(s: &str) {}")
+            .unwrap();
+        sh.write_file("task/comm/src/main.rs", "fn main() {}")
+            .unwrap();
+        sh.write_file("xtask/base/src/lib.rs", "").unwrap();
+        git(&["add", "xtask"]);
+
+        let repo = gix::open(repo_dir.path()).unwrap();
+        assert_eq!(
+            changed_paths(&repo, "HEAD").unwrap(),
+            paths(&[
+                "task/comm/src/main.rs",
+                "task/ini/src/lib.rs",
+                "xtask/base/src/lib.rs",
+            ])
+        );
+        assert_eq!(
+            changed_paths(&repo, &base).unwrap(),
+            paths(&[
+                "README.md",
+                "task/add/src/lib.rs",
+                "task/comm/src/main.rs",
+                "task/ini/src/lib.rs",
+                "xtask/base/src/lib.rs",
+            ])
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    affected,
     checker_config::{read_checker_config, BuildConfig, LintConfig, TestConfig},
     util::create_shell,
 };
@@ -8,7 +9,7 @@ use clap::Parser;
 use proc_macro2::{Ident, Span, TokenStream, TokenTree};
 use walkdir::WalkDir;
 use xshell::cmd;
-use xtask_util::canonicalize;
+use xtask_util::{canonicalize, get_cwd_repo_path};
 
 use std::{
     collections::HashSet,
@@ -23,8 +24,18 @@ use std::{
 
 #[derive(Parser, Clone, Debug)]
 pub struct CheckArgs {
+    #[clap(conflicts_with = "affected")]
     pub task_path: Vec<PathBuf>,
 
+    #[clap(long, action)]
+    /// Check only the tasks affected by changes since the merge-base
+    /// with the course remote.
+    pub affected: bool,
+
+    #[clap(long, requires = "affected")]
+    /// Base revision for --affected.
+    pub since: Option<String>,
+
     #[clap(long, action)]
     /// Disable default features in Cargo.
     pub no_default_features: bool,
@@ -199,10 +210,37 @@ fn collect_cargo_args(args: &CheckArgs) -> Vec<String> {
     cargo_args.into_iter().map(|s| s.to_string()).collect()
 }
 
+fn affected_task_paths(since: Option<&str>) -> Result<Vec<PathBuf>> {
+    let repo_path = get_cwd_repo_path()?;
+    let repo = gix::open(&repo_path).context("failed to open git repository")?;
+
+    let base = match since {
+        Some(since) => since.to_owned(),
+        None => affected::default_base(&repo)?,
+    };
+    let changed_paths = affected::changed_paths(&repo, &base)?;
+    let shared_dependencies = affected::read_shared_dependencies(&repo_path)?;
+    let tasks = affected::affected_tasks(&changed_paths, &shared_dependencies);
+
+    if tasks.is_empty() {
+        eprintln!("No tasks affected since {base}");
+    } else {
+        let list = tasks.iter().map(String::as_str).collect::<Vec<_>>();
+        eprintln!("Tasks affected since {base}: {}", list.join(", "));
+    }
+
+    Ok(tasks
+        .into_iter()
+        .map(|task| repo_path.join("task").join(task))
+        .collect())
+}
+
 pub fn check(args: CheckArgs) -> Result<()> {
     let cargo_args = collect_cargo_args(&args);
 
-    let task_paths = if args.task_path.is_empty() {
+    let task_paths = if args.affected {
+        affected_task_paths(args.since.as_deref())?
+    } else if args.task_path.is_empty() {
         vec![env::current_dir().context("failed to get cwd")?]
     } else {
         args.task_path
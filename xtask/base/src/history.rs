@@ -0,0 +1,311 @@
+use anyhow::{anyhow, bail, Context, Result};
+use gix::{bstr::ByteSlice, object::Kind, refs::transaction::PreviousValue, ObjectId, Repository};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const TAG_PREFIX: &str = "xtask-submit";
+const MESSAGE_TITLE: &str = "xtask submit";
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A submission recorded by a `xtask-submit/<task>/<index>` tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submission {
+    pub index: usize,
+    pub tag: String,
+    /// `None` if the tag points to a commit which is missing from the repository.
+    pub commit: Option<ObjectId>,
+    pub subject: String,
+    /// Recorded in the tag message, empty for tags created by other means.
+    pub date: String,
+    pub branches: Vec<String>,
+}
+
+impl Submission {
+    /// The commit to resubmit.
+    pub fn reachable_commit(&self) -> Result<ObjectId> {
+        self.commit.with_context(|| {
+            format!(
+                "the commit of submission #{} is unreachable, tag {} points to a missing object",
+                self.index, self.tag
+            )
+        })
+    }
+}
+
+pub fn tag_name(task_name: &str, index: usize) -> String {
+    format!("{TAG_PREFIX}/{task_name}/{index}")
+}
+
+/// The index of a tag of the task, e.g. 3 for `xtask-submit/add/3` and task `add`.
+pub fn parse_tag_index(task_name: &str, tag: &str) -> Option<usize> {
+    let index = tag
+        .strip_prefix(TAG_PREFIX)?
+        .strip_prefix('/')?
+        .strip_prefix(task_name)?
+        .strip_prefix('/')?;
+    if !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    index.parse().ok()
+}
+
+fn format_message(date: &str, branches: &[&str]) -> String {
+    format!(
+        "{MESSAGE_TITLE}\n\ndate: {date}\nbranches: {}\n",
+        branches.join(" ")
+    )
+}
+
+/// Returns the date and the branches.
+fn parse_message(message: &str) -> (String, Vec<String>) {
+    let mut date = String::new();
+    let mut branches = vec![];
+    for line in message.lines() {
+        if let Some(value) = line.strip_prefix("date: ") {
+            date = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("branches: ") {
+            branches = value.split_whitespace().map(str::to_string).collect();
+        }
+    }
+    (date, branches)
+}
+
+/// Prior submissions of the task, ordered by their indices.
+pub fn list_submissions(repo: &Repository, task_name: &str) -> Result<Vec<Submission>> {
+    let mut submissions = vec![];
+    let references = repo.references().context("failed to read references")?;
+    for mb_reference in references.tags().context("failed to read tags")? {
+        let mut reference = mb_reference.map_err(|err| anyhow!("failed to read a tag: {err}"))?;
+        let tag = reference.name().shorten().to_string();
+        let Some(index) = parse_tag_index(task_name, &tag) else {
+            continue;
+        };
+
+        let (target, message) = match repo.find_object(reference.id()) {
+            Ok(object) if object.kind == Kind::Tag => {
+                let tag_object = object.into_tag();
+                let decoded = tag_object
+                    .decode()
+                    .with_context(|| format!("failed to decode tag {tag}"))?;
+                (
+                    Some(decoded.target()),
+                    decoded.message.to_str_lossy().into_owned(),
+                )
+            }
+            Ok(_) => (
+                Some(reference.peel_to_id_in_place()?.detach()),
+                String::new(),
+            ),
+            Err(_) => (None, String::new()),
+        };
+        let commit = target.and_then(|id| repo.find_commit(id).ok());
+
+        let (date, branches) = parse_message(&message);
+        submissions.push(Submission {
+            index,
+            tag,
+            commit: commit.as_ref().map(|commit| commit.id),
+            subject: match &commit {
+                Some(commit) => commit.message()?.summary().to_string(),
+                None => String::new(),
+            },
+            date,
+            branches,
+        });
+    }
+
+    submissions.sort_by_key(|submission| submission.index);
+    Ok(submissions)
+}
+
+pub fn find_submission(submissions: &[Submission], index: usize) -> Result<&Submission> {
+    match submissions.iter().find(|s| s.index == index) {
+        Some(submission) => Ok(submission),
+        None if submissions.is_empty() => bail!("there are no submissions yet"),
+        None => bail!(
+            "there is no submission #{index}, the known ones are: {}",
+            submissions
+                .iter()
+                .map(|s| format!("#{}", s.index))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Tags the commit as the next submission of the task, returns the tag name.
+pub fn record_submission(
+    repo: &Repository,
+    task_name: &str,
+    commit: ObjectId,
+    branches: &[&str],
+) -> Result<String> {
+    let index = list_submissions(repo, task_name)?
+        .last()
+        .map_or(1, |submission| submission.index + 1);
+    let tag = tag_name(task_name, index);
+
+    let date = gix::date::Time::now_local_or_utc().format(gix::date::time::format::ISO8601);
+    let tagger = repo
+        .committer()
+        .transpose()
+        .context("failed to read the committer")?;
+    repo.tag(
+        &tag,
+        commit,
+        Kind::Commit,
+        tagger,
+        format_message(&date, branches),
+        PreviousValue::MustNotExist,
+    )
+    .with_context(|| format!("failed to create tag {tag}"))?;
+
+    Ok(tag)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xshell::{cmd, Shell};
+
+    #[test]
+    fn test_tag_names() {
+        assert_eq!(tag_name("add", 3), "xtask-submit/add/3");
+        assert_eq!(parse_tag_index("add", "xtask-submit/add/3"), Some(3));
+        assert_eq!(parse_tag_index("add", &tag_name("add", 12)), Some(12));
+
+        assert_eq!(parse_tag_index("add", "xtask-submit/addition/3"), None);
+        assert_eq!(parse_tag_index("addition", "xtask-submit/add/3"), None);
+        assert_eq!(parse_tag_index("add", "xtask-submit/add/sub/3"), None);
+        assert_eq!(parse_tag_index("add", "xtask-submit/add/"), None);
+        assert_eq!(parse_tag_index("add", "xtask-submit/add/+3"), None);
+        assert_eq!(parse_tag_index("add", "xtask-submit/add/x"), None);
+        assert_eq!(parse_tag_index("add", "other/add/3"), None);
+    }
+
+    #[test]
+    fn test_message() {
+        let message = format_message("2024-10-01", &["main", "submit/add"]);
+        assert_eq!(
+            parse_message(&message),
+            (
+                "2024-10-01".to_string(),
+                vec!["main".to_string(), "submit/add".to_string()]
+            )
+        );
+        assert_eq!(parse_message(""), (String::new(), vec![]));
+    }
+
+    #[test]
+    fn test_find_submission() {
+        let submission = |index| Submission {
+            index,
+            tag: tag_name("add", index),
+            commit: None,
+            subject: String::new(),
+            date: String::new(),
+            branches: vec![],
+        };
+        let submissions = [submission(1), submission(3)];
+        assert_eq!(find_submission(&submissions, 3).unwrap().index, 3);
+
+        let err = find_submission(&submissions, 2).unwrap_err().to_string();
+        assert!(err.contains("#1, #3"), "{err}");
+        assert!(find_submission(&[], 1).is_err());
+        assert!(submissions[0].reachable_commit().is_err());
+    }
+
+    #[test]
+    fn test_submissions_in_repo() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let sh = Shell::new().unwrap();
+        sh.change_dir(repo_dir.path());
+
+        let git = |args: &[&str]| {
+            cmd!(sh, "git {args...}").quiet().read().unwrap();
+        };
+        git(&["init", "--quiet"]);
+        git(&["config", "user.name", "test"]);
+        git(&["config", "user.email", "test@test"]);
+
+        let commit = |message: &str| {
+            git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+            let id = cmd!(sh, "git rev-parse HEAD").read().unwrap();
+            ObjectId::from_hex(id.as_bytes()).unwrap()
+        };
+        let first = commit("first");
+        let second = commit("second\n\nbody");
+
+        let repo = gix::open(repo_dir.path()).unwrap();
+        assert!(list_submissions(&repo, "add").unwrap().is_empty());
+
+        let branches = ["main", "submit/add"];
+        assert_eq!(
+            record_submission(&repo, "add", first, &branches).unwrap(),
+            "xtask-submit/add/1"
+        );
+        assert_eq!(
+            record_submission(&repo, "add", second, &branches[1..]).unwrap(),
+            "xtask-submit/add/2"
+        );
+
+        // Synthetic tags: a lightweight one with a gap in the indices, ones of another
+        // task or with a wrong name, and one pointing to a missing commit.
+        git(&["tag", "xtask-submit/add/7", &first.to_string()]);
+        git(&["tag", "xtask-submit/ini/1"]);
+        git(&["tag", "xtask-submit/add/x"]);
+        sh.write_file(
+            repo_dir.path().join(".git/refs/tags/xtask-submit/add/9"),
+            format!("{}\n", "1".repeat(40)),
+        )
+        .unwrap();
+
+        let repo = gix::open(repo_dir.path()).unwrap();
+        let submissions = list_submissions(&repo, "add").unwrap();
+        assert_eq!(
+            submissions.iter().map(|s| s.index).collect::<Vec<_>>(),
+            [1, 2, 7, 9]
+        );
+
+        assert_eq!(submissions[0].tag, "xtask-submit/add/1");
+        assert_eq!(submissions[0].commit, Some(first));
+        assert_eq!(submissions[0].subject, "first");
+        assert_eq!(submissions[0].branches, ["main", "submit/add"]);
+        assert!(!submissions[0].date.is_empty());
+
+        assert_eq!(submissions[1].commit, Some(second));
+        assert_eq!(submissions[1].subject, "second");
+        assert_eq!(submissions[1].branches, ["submit/add"]);
+
+        assert_eq!(submissions[2].commit, Some(first));
+        assert_eq!(submissions[2].date, "");
+        assert!(submissions[2].branches.is_empty());
+
+        assert_eq!(submissions[3].commit, None);
+        let err = find_submission(&submissions, 9)
+            .unwrap()
+            .reachable_commit()
+            .unwrap_err();
+        assert!(err.to_string().contains("unreachable"), "{err}");
+        assert_eq!(
+            find_submission(&submissions, 7)
+                .unwrap()
+                .reachable_commit()
+                .unwrap(),
+            first
+        );
+
+        assert_eq!(
+            record_submission(&repo, "add", second, &branches).unwrap(),
+            "xtask-submit/add/10"
+        );
+        assert_eq!(
+            record_submission(&repo, "ini", second, &branches).unwrap(),
+            "xtask-submit/ini/2"
+        );
+    }
+}
@@ -1,5 +1,7 @@
+mod affected;
 mod check;
 mod checker_config;
+mod history;
 mod submit;
 mod util;
 
@@ -15,11 +17,19 @@ pub enum Command {
 
     /// Submit task.
     Submit(submit::SubmitArgs),
+
+    /// List prior submissions of task.
+    Submissions(submit::SubmissionsArgs),
+
+    /// Submit an earlier submission of task again.
+    Resubmit(submit::ResubmitArgs),
 }
 
 pub fn run_command(cmd: Command) -> Result<()> {
     match cmd {
         Command::Check(args) => check::check(args),
         Command::Submit(args) => submit::submit(args),
+        Command::Submissions(args) => submit::submissions(args),
+        Command::Resubmit(args) => submit::resubmit(args),
     }
 }
@@ -1,13 +1,16 @@
+use crate::history::{find_submission, list_submissions, record_submission};
+
 use xtask_util::canonicalize;
 
 use anyhow::{bail, ensure, Context, Result};
 use clap::Parser;
-use gix::{progress::prodash::progress, remote::Direction, Repository};
+use gix::{progress::prodash::progress, remote::Direction, ObjectId, Repository};
 use xshell::{cmd, Shell};
 
 use std::{
     env,
     ffi::OsStr,
+    io::{self, BufRead, Write},
     path::{Path, PathBuf},
 };
 
@@ -29,6 +32,27 @@ pub struct SubmitArgs {
     pub verbose: bool,
 }
 
+#[derive(Parser, Clone, Debug)]
+pub struct SubmissionsArgs {
+    pub task_path: Option<PathBuf>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct ResubmitArgs {
+    pub task_path: Option<PathBuf>,
+
+    /// Index of the submission, see `submissions`.
+    #[arg(short, long)]
+    pub index: usize,
+
+    /// Push the submission to main as well, after a confirmation.
+    #[arg(long, action)]
+    pub main: bool,
+
+    #[arg(short, long, action)]
+    pub verbose: bool,
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 fn uncommitted_changes(repo: &Repository, task_name: &str) -> Result<Vec<PathBuf>> {
@@ -69,14 +93,19 @@ fn get_student_login(repo: &Repository, remote: &str) -> Result<String> {
     Ok(tail.trim_end_matches(".git").to_string())
 }
 
-fn push_task(path: &Path, branch: &str, verbose: bool) -> Result<()> {
+/// Force-pushes `revision`, e.g. `HEAD` or a commit id, to the branch.
+fn push_task(path: &Path, revision: &str, branch: &str, verbose: bool) -> Result<()> {
     // NB: pushing using gix would require dealing with user authentication,
     // which is very difficult to get right.
     // So we give up and use git cli.
     let sh = Shell::new().context("failed to create shell")?;
     sh.change_dir(path);
 
-    let cmd = cmd!(sh, "git push --force {STUDENT_REMOTE_NAME} HEAD:{branch}");
+    // The full destination name, as git can't guess it for a commit id.
+    let cmd = cmd!(
+        sh,
+        "git push --force {STUDENT_REMOTE_NAME} {revision}:refs/heads/{branch}"
+    );
 
     if verbose {
         return cmd
@@ -101,11 +130,10 @@ fn get_submit_branch(task_name: &str, subtask: &Option<String>) -> String {
     }
 }
 
-pub fn submit(args: SubmitArgs) -> Result<()> {
-    let task_path = canonicalize(
-        args.task_path
-            .unwrap_or(env::current_dir().context("failed to get cwd")?),
-    )?;
+/// Returns the task path and the task name.
+fn resolve_task(task_path: Option<PathBuf>) -> Result<(PathBuf, String)> {
+    let task_path =
+        canonicalize(task_path.unwrap_or(env::current_dir().context("failed to get cwd")?))?;
 
     ensure!(
         task_path.join(".check.toml").exists(),
@@ -118,6 +146,31 @@ pub fn submit(args: SubmitArgs) -> Result<()> {
         .with_context(|| format!("invalid task path: {task_path:?}"))?
         .to_owned();
 
+    Ok((task_path, task_name))
+}
+
+/// Tagging is a convenience, so a failure doesn't fail the submission.
+fn tag_submission(repo: &Repository, task_name: &str, commit: ObjectId, branches: &[&str]) {
+    match record_submission(repo, task_name, commit, branches) {
+        Ok(tag) => eprintln!("Recorded the submission as tag \"{tag}\"."),
+        Err(err) => eprintln!("WARNING: failed to record the submission: {err:#}"),
+    }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read the answer")?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+pub fn submit(args: SubmitArgs) -> Result<()> {
+    let (task_path, task_name) = resolve_task(args.task_path)?;
+
     let repo = gix::discover(&task_path).context("failed to discover git repository")?;
 
     let uncommitted_files = uncommitted_changes(&repo, &task_name)
@@ -136,11 +189,77 @@ pub fn submit(args: SubmitArgs) -> Result<()> {
     let student_login = get_student_login(&repo, STUDENT_REMOTE_NAME)?;
     let submit_branch = get_submit_branch(&task_name, &args.subtask);
 
+    let head = repo.head_id().context("failed to resolve HEAD")?.detach();
+
     eprintln!("Submitting \"{task_name}\" ...");
-    push_task(&task_path, "main", args.verbose)?;
-    push_task(&task_path, &submit_branch, args.verbose)?;
+    push_task(&task_path, "HEAD", "main", args.verbose)?;
+    push_task(&task_path, "HEAD", &submit_branch, args.verbose)?;
+    tag_submission(&repo, &task_name, head, &["main", &submit_branch]);
 
     eprintln!("OK: task is successfully submitted.");
     eprintln!("-> {STUDENT_GROUP_URL}/{student_login}/pipelines");
     Ok(())
 }
+
+pub fn submissions(args: SubmissionsArgs) -> Result<()> {
+    let (task_path, task_name) = resolve_task(args.task_path)?;
+    let repo = gix::discover(&task_path).context("failed to discover git repository")?;
+
+    let submissions = list_submissions(&repo, &task_name)?;
+    if submissions.is_empty() {
+        eprintln!("\"{task_name}\" has not been submitted yet.");
+    }
+    for submission in submissions {
+        let commit = match submission.commit {
+            Some(commit) => commit.to_hex_with_len(10).to_string(),
+            None => "unreachable".to_string(),
+        };
+        println!(
+            "#{:<3} {:<30} {:<11} {:<25} {}",
+            submission.index, submission.tag, commit, submission.date, submission.subject
+        );
+    }
+    Ok(())
+}
+
+pub fn resubmit(args: ResubmitArgs) -> Result<()> {
+    let (task_path, task_name) = resolve_task(args.task_path)?;
+    let repo = gix::discover(&task_path).context("failed to discover git repository")?;
+
+    let submissions = list_submissions(&repo, &task_name)?;
+    let submission = find_submission(&submissions, args.index)?;
+    let commit = submission.reachable_commit()?;
+
+    // Submissions of subtasks record their own submit branches.
+    let submit_branch = submission
+        .branches
+        .iter()
+        .find(|branch| branch.starts_with("submit/"))
+        .cloned()
+        .unwrap_or_else(|| get_submit_branch(&task_name, &None));
+
+    let student_login = get_student_login(&repo, STUDENT_REMOTE_NAME)?;
+    let revision = commit.to_string();
+    let mut branches = vec![submit_branch.as_str()];
+    if args.main
+        && confirm(&format!(
+            "Push {} to main as well, overwriting it?",
+            commit.to_hex_with_len(10)
+        ))?
+    {
+        branches.insert(0, "main");
+    }
+
+    eprintln!(
+        "Resubmitting \"{task_name}\" #{} ({}) ...",
+        submission.index, submission.subject
+    );
+    for branch in &branches {
+        push_task(&task_path, &revision, branch, args.verbose)?;
+    }
+    tag_submission(&repo, &task_name, commit, &branches);
+
+    eprintln!("OK: task is successfully resubmitted.");
+    eprintln!("-> {STUDENT_GROUP_URL}/{student_login}/pipelines");
+    Ok(())
+}